@@ -1,4 +1,7 @@
-use akaze::Akaze;
+use akaze::{
+    image::{gaussian_blur, GrayFloatImage},
+    Akaze,
+};
 use bitarray::BitArray;
 use criterion::{criterion_group, criterion_main, Criterion};
 use std::path::Path;
@@ -11,9 +14,14 @@ fn extract(c: &mut Criterion) {
     c.bench_function("extract", |b| b.iter(|| image_to_kps("res/0000000000.png")));
 }
 
+fn blur(c: &mut Criterion) {
+    let image = GrayFloatImage::from_dynamic(&image::open("res/0000000000.png").unwrap());
+    c.bench_function("gaussian_blur", |b| b.iter(|| gaussian_blur(&image, 1.6)));
+}
+
 criterion_group!(
     name = benches;
     config = Criterion::default().sample_size(10);
-    targets = extract
+    targets = extract, blur
 );
 criterion_main!(benches);
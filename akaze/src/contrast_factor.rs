@@ -1,4 +1,4 @@
-use crate::image::{gaussian_blur, GrayFloatImage};
+use crate::image::{gaussian_blur, BorderMode, GrayFloatImage};
 use log::*;
 
 /// This function computes a good empirical value for the k contrast factor
@@ -19,32 +19,24 @@ pub fn compute_contrast_factor(
     gradient_histogram_scale: f64,
     num_bins: usize,
 ) -> f64 {
-    let mut num_points: f64 = 0.0;
-    let mut histogram = vec![0; num_bins];
     let gaussian = gaussian_blur(image, gradient_histogram_scale as f32);
-    let Lx = crate::derivatives::scharr_horizontal(&gaussian, 1);
-    let Ly = crate::derivatives::scharr_vertical(&gaussian, 1);
-    let hmax = (1..gaussian.height() - 1)
-        .flat_map(|y| (1..gaussian.width() - 1).map(move |x| (x, y)))
-        .map(|(x, y)| Lx.get(x, y).powi(2) as f64 + Ly.get(x, y).powi(2) as f64)
-        .map(float_ord::FloatOrd)
-        .max()
-        .unwrap()
-        .0
-        .sqrt();
-    for y in 1..(gaussian.height() - 1) {
-        for x in 1..(gaussian.width() - 1) {
-            let modg = (Lx.get(x, y).powi(2) as f64 + Ly.get(x, y).powi(2) as f64).sqrt();
-            if modg != 0.0 {
-                let mut bin_number = f64::floor((num_bins as f64) * (modg / hmax)) as usize;
-                if bin_number == num_bins {
-                    bin_number -= 1;
-                }
-                histogram[bin_number] += 1;
-                num_points += 1f64;
+    let Lx = crate::derivatives::scharr_horizontal(&gaussian, 1, BorderMode::Extend);
+    let Ly = crate::derivatives::scharr_vertical(&gaussian, 1, BorderMode::Extend);
+    let mut magnitude = GrayFloatImage::gradient_magnitude(&Lx, &Ly);
+    // The border pixels don't have complete derivatives, so they are zeroed like flat pixels.
+    let (width, height) = (magnitude.width(), magnitude.height());
+    for y in 0..height {
+        for x in 0..width {
+            if x == 0 || y == 0 || x + 1 == width || y + 1 == height {
+                magnitude.put(x, y, 0.0);
             }
         }
     }
+    let hmax = f64::from(magnitude.max().unwrap_or(0.0));
+    let mut histogram = magnitude.histogram(num_bins, 0.0, hmax as f32);
+    // Flat pixels have no gradient and aren't counted.
+    histogram[0] -= magnitude.iter().filter(|&&modg| modg == 0.0).count();
+    let num_points = histogram.iter().sum::<usize>() as f64;
     let threshold: usize = (num_points * percentile) as usize;
     let mut k: usize = 0;
     let mut num_elements: usize = 0;
@@ -1,5 +1,8 @@
-use crate::image::{fill_border, GrayFloatImage};
-use ndarray::{s, Array2, ArrayView2, ArrayViewMut2};
+use crate::image::{
+    border::filter_border,
+    convolution::{filter_columns, filter_rows},
+    BorderMode, GrayFloatImage,
+};
 
 /// Compute the Scharr derivative horizontally
 ///
@@ -8,21 +11,28 @@ use ndarray::{s, Array2, ArrayView2, ArrayViewMut2};
 /// # Arguments
 /// * `image` - the input image.
 /// * `sigma_size` - the scale of the derivative.
+/// * `border` - how the pixels beyond the border are handled.
 ///
 /// # Return value
 /// Output image derivative (an image.)
-pub fn scharr_horizontal(image: &GrayFloatImage, sigma_size: u32) -> GrayFloatImage {
+pub fn scharr_horizontal(
+    image: &GrayFloatImage,
+    sigma_size: u32,
+    border: BorderMode,
+) -> GrayFloatImage {
     let img_horizontal = scharr_axis(
         image,
         sigma_size,
         FilterDirection::Horizontal,
         FilterOrder::Main,
+        border,
     );
     scharr_axis(
         &img_horizontal,
         sigma_size,
         FilterDirection::Vertical,
         FilterOrder::Off,
+        border,
     )
 }
 
@@ -33,46 +43,31 @@ pub fn scharr_horizontal(image: &GrayFloatImage, sigma_size: u32) -> GrayFloatIm
 /// # Arguments
 /// * `image` - the input image.
 /// * `sigma_size` - the scale of the derivative.
+/// * `border` - how the pixels beyond the border are handled.
 ///
 /// # Return value
 /// Output image derivative (an image.)
-pub fn scharr_vertical(image: &GrayFloatImage, sigma_size: u32) -> GrayFloatImage {
+pub fn scharr_vertical(
+    image: &GrayFloatImage,
+    sigma_size: u32,
+    border: BorderMode,
+) -> GrayFloatImage {
     let img_horizontal = scharr_axis(
         image,
         sigma_size,
         FilterDirection::Horizontal,
         FilterOrder::Off,
+        border,
     );
     scharr_axis(
         &img_horizontal,
         sigma_size,
         FilterDirection::Vertical,
         FilterOrder::Main,
+        border,
     )
 }
 
-/// Multiplies and accumulates
-fn accumulate_mul_offset(
-    mut accumulator: ArrayViewMut2<f32>,
-    source: ArrayView2<f32>,
-    val: f32,
-    border: usize,
-    xoff: usize,
-    yoff: usize,
-) {
-    assert_eq!(source.dim(), accumulator.dim());
-    let dims = source.dim();
-    let mut accumulator =
-        accumulator.slice_mut(s![border..dims.0 - border, border..dims.1 - border]);
-    accumulator.scaled_add(
-        val,
-        &source.slice(s![
-            yoff..dims.0 + yoff - 2 * border,
-            xoff..dims.1 + xoff - 2 * border
-        ]),
-    );
-}
-
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum FilterDirection {
     Horizontal,
@@ -90,8 +85,8 @@ fn scharr_axis(
     sigma_size: u32,
     dir: FilterDirection,
     order: FilterOrder,
+    border_mode: BorderMode,
 ) -> GrayFloatImage {
-    let mut output = Array2::<f32>::zeros([image.height(), image.width()]);
     // Get the border size (we wont fill in this border width of the output).
     let border = sigma_size as usize;
     // Difference between middle and sides of main axis filter.
@@ -101,27 +96,23 @@ fn scharr_axis(
     // Middle intensity of filter.
     let middle = norm * w as f32;
 
-    let mut offsets = match order {
-        FilterOrder::Main => vec![
-            (norm, [border, 0]),
-            (middle, [border, border]),
-            (norm, [border, 2 * border]),
-        ],
-        FilterOrder::Off => vec![(-1.0, [border, 0]), (1.0, [border, 2 * border])],
+    let step = border as isize;
+    let taps = match order {
+        FilterOrder::Main => vec![(-step, norm), (0, middle), (step, norm)],
+        FilterOrder::Off => vec![(-step, -1.0), (step, 1.0)],
     };
 
-    if dir == FilterDirection::Horizontal {
-        // Swap the offsets if the filter is a horizontal filter.
-        for (_, [x, y]) in &mut offsets {
-            std::mem::swap(x, y);
-        }
-    }
-
-    // Accumulate the three components.
-    for (val, [x, y]) in offsets {
-        accumulate_mul_offset(output.view_mut(), image.ref_array2(), val, border, x, y);
-    }
-    let mut output = GrayFloatImage::from_array2(output);
-    fill_border(&mut output, border);
+    // Accumulate the components along the rows or the columns.
+    let mut output = match dir {
+        FilterDirection::Horizontal => filter_rows(image, &taps),
+        FilterDirection::Vertical => filter_columns(image, &taps),
+    };
+    filter_border(
+        image,
+        &mut output,
+        &taps,
+        dir == FilterDirection::Horizontal,
+        border_mode,
+    );
     output
 }
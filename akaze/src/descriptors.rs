@@ -1,5 +1,7 @@
 use crate::{Akaze, EvolutionStep, KeyPoint};
 use bitarray::BitArray;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 impl Akaze {
     /// Extract descriptors from keypoints/an evolution
@@ -10,13 +12,22 @@ impl Akaze {
     /// * `options` - The options of the nonlinear scale space.
     /// # Return value
     /// A vector of descriptors.
+    ///
+    /// With the `rayon` feature enabled the descriptors are extracted in parallel.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "akaze::descriptors", skip_all, fields(keypoints = keypoints.len()))
+    )]
     pub fn extract_descriptors(
         &self,
         evolutions: &[EvolutionStep],
         keypoints: &[KeyPoint],
     ) -> Vec<BitArray<64>> {
+        #[cfg(feature = "rayon")]
+        let keypoints = keypoints.par_iter();
+        #[cfg(not(feature = "rayon"))]
+        let keypoints = keypoints.iter();
         keypoints
-            .iter()
             .map(|keypoint| self.get_mldb_descriptor(keypoint, evolutions))
             .collect()
     }
@@ -1,15 +1,26 @@
-use crate::{derivatives, evolution::EvolutionStep, image::GrayFloatImage, Akaze};
+use crate::{
+    derivatives,
+    evolution::EvolutionStep,
+    image::{BorderMode, GrayFloatImage},
+    Akaze,
+};
 use ndarray::azip;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 impl Akaze {
     fn compute_multiscale_derivatives(&self, evolutions: &mut Vec<EvolutionStep>) {
-        for evolution in evolutions.iter_mut() {
+        let compute = |evolution: &mut EvolutionStep| {
             // The image decreases in size by a factor which is 2^octave.
             let ratio = 2.0f64.powi(evolution.octave as i32);
             // The scale of the edge filter.
             let sigma_size = f64::round(evolution.esigma * self.derivative_factor / ratio) as u32;
-            compute_multiscale_derivatives_for_evolution(evolution, sigma_size);
-        }
+            compute_multiscale_derivatives_for_evolution(evolution, sigma_size, self.border_mode);
+        };
+        #[cfg(feature = "rayon")]
+        evolutions.par_iter_mut().for_each(compute);
+        #[cfg(not(feature = "rayon"))]
+        evolutions.iter_mut().for_each(compute);
     }
 
     /// Compute the detector response - the determinant of the Hessian - and save the result
@@ -18,10 +29,12 @@ impl Akaze {
     /// # Arguments
     /// * `evolutions` - The computed evolutions.
     /// * `options` - The options
+    ///
+    /// With the `rayon` feature enabled the evolutions are processed in parallel.
     #[allow(non_snake_case, clippy::suspicious_operation_groupings)]
     pub fn detector_response(&self, evolutions: &mut Vec<EvolutionStep>) {
         self.compute_multiscale_derivatives(evolutions);
-        for evolution in evolutions.iter_mut() {
+        let respond = |evolution: &mut EvolutionStep| {
             let ratio = f64::powi(2.0, evolution.octave as i32);
             let sigma_size = f64::round(evolution.esigma * self.derivative_factor / ratio);
             let sigma_size_quat = sigma_size.powi(4) as f32;
@@ -34,14 +47,22 @@ impl Akaze {
             ) {
                 *Ldet = (Lxx * Lyy - Lxy * Lxy) * sigma_size_quat;
             });
-        }
+        };
+        #[cfg(feature = "rayon")]
+        evolutions.par_iter_mut().for_each(respond);
+        #[cfg(not(feature = "rayon"))]
+        evolutions.iter_mut().for_each(respond);
     }
 }
 
-fn compute_multiscale_derivatives_for_evolution(evolution: &mut EvolutionStep, sigma_size: u32) {
-    evolution.Lx = derivatives::scharr_horizontal(&evolution.Lsmooth, sigma_size);
-    evolution.Ly = derivatives::scharr_vertical(&evolution.Lsmooth, sigma_size);
-    evolution.Lxx = derivatives::scharr_horizontal(&evolution.Lx, sigma_size);
-    evolution.Lyy = derivatives::scharr_vertical(&evolution.Ly, sigma_size);
-    evolution.Lxy = derivatives::scharr_vertical(&evolution.Lx, sigma_size);
+fn compute_multiscale_derivatives_for_evolution(
+    evolution: &mut EvolutionStep,
+    sigma_size: u32,
+    border: BorderMode,
+) {
+    evolution.Lx = derivatives::scharr_horizontal(&evolution.Lsmooth, sigma_size, border);
+    evolution.Ly = derivatives::scharr_vertical(&evolution.Lsmooth, sigma_size, border);
+    evolution.Lxx = derivatives::scharr_horizontal(&evolution.Lx, sigma_size, border);
+    evolution.Lyy = derivatives::scharr_vertical(&evolution.Ly, sigma_size, border);
+    evolution.Lxy = derivatives::scharr_vertical(&evolution.Lx, sigma_size, border);
 }
@@ -1,5 +1,25 @@
+mod bayer;
+pub(crate) mod border;
+pub(crate) mod convolution;
+mod integral;
+mod io;
+mod photometric;
+mod pyramid;
+mod resize;
+mod statistics;
+
+pub use bayer::*;
+pub use border::*;
+pub use integral::*;
+pub use photometric::*;
+pub use pyramid::*;
+pub use resize::*;
+
+use border::filter_border;
+use convolution::{filter_columns, filter_rows, kernel_taps};
+use cv_core::nalgebra::{DMatrix, DMatrixSlice, DMatrixSliceMut, Dynamic};
 use derive_more::{Deref, DerefMut};
-use image::{imageops, DynamicImage, GenericImageView, ImageBuffer, Luma};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Luma};
 use log::*;
 use ndarray::{Array2, ArrayView2, ArrayViewMut2};
 use nshare::{MutNdarray2, RefNdarray2};
@@ -65,21 +85,212 @@ impl GrayFloatImage {
         })
     }
 
+    /// Create a unit float image from the image crate's DynamicImage type with a configurable conversion to gray.
+    ///
+    /// Images which are already gray are loaded as they are, and 16-bit images keep their precision.
+    ///
+    /// # Arguments
+    /// * `input_image` - the input image.
+    /// * `conversion` - how the color channels are combined.
+    /// # Return value
+    /// An image with pixel values between 0 and 1.
+    pub fn from_dynamic_with(input_image: &DynamicImage, conversion: GrayConversion) -> Self {
+        let convert = |rgb: [f32; 3]| conversion.convert(rgb).max(0.0).min(1.0);
+        match input_image {
+            _ if matches!(conversion, GrayConversion::Luma) => Self::from_dynamic(input_image),
+            DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_) => Self::from_dynamic(input_image),
+            DynamicImage::ImageRgb16(image) => Self(ImageBuffer::from_fn(
+                image.width(),
+                image.height(),
+                |x, y| {
+                    let [r, g, b] = image[(x, y)].0;
+                    Luma([convert([
+                        f32::from(r) / 65535f32,
+                        f32::from(g) / 65535f32,
+                        f32::from(b) / 65535f32,
+                    ])])
+                },
+            )),
+            DynamicImage::ImageRgba16(image) => Self(ImageBuffer::from_fn(
+                image.width(),
+                image.height(),
+                |x, y| {
+                    let [r, g, b, _] = image[(x, y)].0;
+                    Luma([convert([
+                        f32::from(r) / 65535f32,
+                        f32::from(g) / 65535f32,
+                        f32::from(b) / 65535f32,
+                    ])])
+                },
+            )),
+            _ => {
+                let image = input_image.to_rgb8();
+                Self(ImageBuffer::from_fn(
+                    image.width(),
+                    image.height(),
+                    |x, y| {
+                        let [r, g, b] = image[(x, y)].0;
+                        Luma([convert([
+                            f32::from(r) / 255f32,
+                            f32::from(g) / 255f32,
+                            f32::from(b) / 255f32,
+                        ])])
+                    },
+                ))
+            }
+        }
+    }
+
+    /// Create a unit float image from a raw single channel sensor buffer of up to 16 bits per pixel.
+    ///
+    /// # Arguments
+    /// * `pixels` - the buffer, where row `y` starts at `y * stride`.
+    /// * `width` - the width of the image.
+    /// * `height` - the height of the image.
+    /// * `stride` - the number of pixels between the starts of two rows, at least `width`.
+    /// * `bit_depth` - the number of significant bits per pixel, such as `10`, `12` or `16`.
+    /// # Return value
+    /// An image with pixel values between 0 and 1, or `None` if the stride is smaller than the
+    /// width or the buffer is too short.
+    pub fn from_raw_u16(
+        pixels: &[u16],
+        width: usize,
+        height: usize,
+        stride: usize,
+        bit_depth: u32,
+    ) -> Option<Self> {
+        if stride < width || (height > 0 && pixels.len() < (height - 1) * stride + width) {
+            return None;
+        }
+        let maximum = ((1u32 << bit_depth.max(1).min(16)) - 1) as f32;
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            data.extend(
+                pixels[y * stride..y * stride + width]
+                    .iter()
+                    .map(|&pixel| (f32::from(pixel) / maximum).min(1.0)),
+            );
+        }
+        Self::from_raw(width, height, data)
+    }
+
     pub fn from_array2(arr: Array2<f32>) -> Self {
-        Self(
-            ImageBuffer::from_raw(arr.dim().1 as u32, arr.dim().0 as u32, arr.into_raw_vec())
-                .expect("raw vector didn't have enough pixels for the image"),
-        )
+        Self::from_ndarray(arr)
     }
 
-    pub fn ref_array2(&self) -> ArrayView2<f32> {
+    /// Create an image from a row-major `Vec` of `width * height` pixels without copying.
+    ///
+    /// Returns `None` if the length of the buffer doesn't match the size of the image.
+    pub fn from_raw(width: usize, height: usize, pixels: Vec<f32>) -> Option<Self> {
+        ImageBuffer::from_raw(width as u32, height as u32, pixels).map(Self)
+    }
+
+    /// Create an image from an external buffer with a row stride, such as a camera driver's frame buffer.
+    ///
+    /// The rows are copied as whole slices rather than pixel by pixel.
+    ///
+    /// # Arguments
+    /// * `pixels` - the buffer, where row `y` starts at `y * stride`.
+    /// * `width` - the width of the image.
+    /// * `height` - the height of the image.
+    /// * `stride` - the number of pixels between the starts of two rows, at least `width`.
+    /// # Return value
+    /// The image, or `None` if the stride is smaller than the width or the buffer is too short.
+    pub fn from_strided(
+        pixels: &[f32],
+        width: usize,
+        height: usize,
+        stride: usize,
+    ) -> Option<Self> {
+        if stride < width || (height > 0 && pixels.len() < (height - 1) * stride + width) {
+            return None;
+        }
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            data.extend_from_slice(&pixels[y * stride..y * stride + width]);
+        }
+        Self::from_raw(width, height, data)
+    }
+
+    /// Create an image from an owned array with the shape `(height, width)`.
+    ///
+    /// The buffer of the array is reused without copying if the array is in standard (row-major) layout and spans
+    /// its whole buffer, otherwise the pixels are copied.
+    pub fn from_ndarray(array: Array2<f32>) -> Self {
+        let (height, width) = array.dim();
+        let len = width * height;
+        let contiguous = array.is_standard_layout();
+        let start = array.as_ptr();
+        let data = if len == 0 {
+            vec![]
+        } else if contiguous {
+            let mut data = array.into_raw_vec();
+            // The array may be a slice of its buffer, in which case it starts at an offset inside of it.
+            let offset = (start as usize - data.as_ptr() as usize) / core::mem::size_of::<f32>();
+            if offset != 0 || data.len() != len {
+                data.drain(..offset);
+                data.truncate(len);
+            }
+            data
+        } else {
+            array.iter().copied().collect()
+        };
+        Self::from_raw(width, height, data).expect("array didn't have enough pixels for the image")
+    }
+
+    /// Create an image by copying a view of an array with the shape `(height, width)`.
+    pub fn from_ndarray_view(array: ArrayView2<f32>) -> Self {
+        let (height, width) = array.dim();
+        let data = match array.as_slice() {
+            Some(pixels) => pixels.to_vec(),
+            None => array.iter().copied().collect(),
+        };
+        Self::from_raw(width, height, data).expect("array didn't have enough pixels for the image")
+    }
+
+    /// A view of the image as an array with the shape `(height, width)`, without copying.
+    pub fn as_ndarray_view(&self) -> ArrayView2<f32> {
         self.0.ref_ndarray2()
     }
 
-    pub fn mut_array2(&mut self) -> ArrayViewMut2<f32> {
+    /// A mutable view of the image as an array with the shape `(height, width)`, without copying.
+    pub fn as_ndarray_view_mut(&mut self) -> ArrayViewMut2<f32> {
         self.0.mut_ndarray2()
     }
 
+    pub fn ref_array2(&self) -> ArrayView2<f32> {
+        self.as_ndarray_view()
+    }
+
+    pub fn mut_array2(&mut self) -> ArrayViewMut2<f32> {
+        self.as_ndarray_view_mut()
+    }
+
+    /// A view of the image as a `height` x `width` matrix, without copying.
+    ///
+    /// The image is stored row-major while nalgebra matrices are column-major, so the view is strided.
+    pub fn as_dmatrix_view(&self) -> DMatrixSlice<f32, Dynamic, Dynamic> {
+        let (width, height) = (self.width(), self.height());
+        DMatrixSlice::from_slice_with_strides(self, height, width, width.max(1), 1)
+    }
+
+    /// A mutable view of the image as a `height` x `width` matrix, without copying.
+    pub fn as_dmatrix_view_mut(&mut self) -> DMatrixSliceMut<f32, Dynamic, Dynamic> {
+        let (width, height) = (self.width(), self.height());
+        DMatrixSliceMut::from_slice_with_strides_mut(self, height, width, width.max(1), 1)
+    }
+
+    /// Create an image by copying a `height` x `width` matrix.
+    pub fn from_dmatrix(matrix: &DMatrix<f32>) -> Self {
+        // The transpose of a column-major matrix has the row-major layout of the image.
+        let (height, width) = matrix.shape();
+        Self::from_raw(width, height, matrix.transpose().data.into())
+            .expect("matrix didn't have enough pixels for the image")
+    }
+
     pub fn zero_array(&self) -> Array2<f32> {
         Array2::zeros((self.height(), self.width()))
     }
@@ -108,15 +319,45 @@ impl GrayFloatImage {
         self.put_pixel(x as u32, y as u32, Luma([pixel_value]));
     }
 
+    /// Downscale the image by two, averaging each 2x2 block of pixels (see [`half_sample_into`]).
     pub fn half_size(&self) -> Self {
-        let width = self.width() / 2;
-        let height = self.height() / 2;
-        Self(imageops::resize(
-            &self.0,
-            width as u32,
-            height as u32,
-            imageops::FilterType::Nearest,
-        ))
+        let mut half = Self::new(self.width() / 2, self.height() / 2);
+        half_sample_into(self, &mut half);
+        half
+    }
+}
+
+/// How the color channels of an image are combined into gray.
+#[derive(Debug, Clone, Copy)]
+pub enum GrayConversion {
+    /// The luma conversion of the image crate, which is the default.
+    Luma,
+    /// A weighted sum of the red, green and blue channels.
+    Weights([f32; 3]),
+    /// A single channel, where `0` is red, `1` is green and `2` is blue.
+    ///
+    /// This is useful for cameras which only put meaningful data in one channel, such as NIR cameras.
+    Channel(usize),
+    /// A custom function of the red, green and blue channels between 0 and 1.
+    Custom(fn([f32; 3]) -> f32),
+}
+
+impl Default for GrayConversion {
+    fn default() -> Self {
+        Self::Luma
+    }
+}
+
+impl GrayConversion {
+    /// Convert a color with channels between 0 and 1 to gray.
+    pub fn convert(self, rgb: [f32; 3]) -> f32 {
+        match self {
+            // The Rec. 709 weights the image crate uses.
+            Self::Luma => Self::Weights([0.2126, 0.7152, 0.0722]).convert(rgb),
+            Self::Weights(weights) => weights.iter().zip(&rgb).map(|(w, c)| w * c).sum(),
+            Self::Channel(channel) => rgb[channel.min(2)],
+            Self::Custom(convert) => convert(rgb),
+        }
     }
 }
 
@@ -154,29 +395,20 @@ pub fn fill_border(output: &mut GrayFloatImage, half_width: usize) {
 /// # Arguments
 /// * `image` - the input image.
 /// * `kernel` the kernel to apply.
+/// * `border` - how the pixels beyond the border are handled.
 /// # Return value
 /// The filter result.
 #[inline(always)]
-pub fn horizontal_filter(image: &GrayFloatImage, kernel: &[f32]) -> GrayFloatImage {
+pub fn horizontal_filter(
+    image: &GrayFloatImage,
+    kernel: &[f32],
+    border: BorderMode,
+) -> GrayFloatImage {
     // Cannot have an even-sized kernel
     debug_assert!(kernel.len() % 2 == 1);
-    let half_width = (kernel.len() / 2) as i32;
-    let w = image.width() as i32;
-    let h = image.height() as i32;
-    let mut output = GrayFloatImage::new(image.width(), image.height());
-    for k in -half_width..=half_width {
-        let mut out_itr = output.iter_mut();
-        let mut image_itr = image.iter();
-        let mut out_ptr = out_itr.nth(half_width as usize).unwrap();
-        let mut image_val = image_itr.nth((half_width + k) as usize).unwrap();
-        let kernel_value = kernel[(k + half_width) as usize];
-        for _ in half_width..(w * h - half_width - 1) {
-            *out_ptr += kernel_value * image_val;
-            out_ptr = out_itr.next().unwrap();
-            image_val = image_itr.next().unwrap();
-        }
-    }
-    fill_border(&mut output, half_width as usize);
+    let taps = kernel_taps(kernel);
+    let mut output = filter_rows(image, &taps);
+    filter_border(image, &mut output, &taps, true, border);
     output
 }
 
@@ -185,31 +417,20 @@ pub fn horizontal_filter(image: &GrayFloatImage, kernel: &[f32]) -> GrayFloatIma
 /// # Arguments
 /// * `image` - the input image.
 /// * `kernel` the kernel to apply.
+/// * `border` - how the pixels beyond the border are handled.
 /// # Return value
 /// The filter result.
 #[inline(always)]
-pub fn vertical_filter(image: &GrayFloatImage, kernel: &[f32]) -> GrayFloatImage {
+pub fn vertical_filter(
+    image: &GrayFloatImage,
+    kernel: &[f32],
+    border: BorderMode,
+) -> GrayFloatImage {
     // Cannot have an even-sized kernel
     debug_assert!(kernel.len() % 2 == 1);
-    let half_width = (kernel.len() / 2) as i32;
-    let w = image.width() as i32;
-    let h = image.height() as i32;
-    let mut output = GrayFloatImage::new(image.width(), image.height());
-    for k in -half_width..=half_width {
-        let mut out_itr = output.iter_mut();
-        let mut image_itr = image.iter();
-        let mut out_ptr = out_itr.nth((half_width * w) as usize).unwrap();
-        let mut image_val = image_itr
-            .nth(((half_width * w) + (k * w)) as usize)
-            .unwrap();
-        let kernel_value = kernel[(k + half_width) as usize];
-        for _ in (half_width * w)..(w * h - (half_width * w) - 1) {
-            *out_ptr += kernel_value * image_val;
-            out_ptr = out_itr.next().unwrap();
-            image_val = image_itr.next().unwrap();
-        }
-    }
-    fill_border(&mut output, half_width as usize);
+    let taps = kernel_taps(kernel);
+    let mut output = filter_columns(image, &taps);
+    filter_border(image, &mut output, &taps, false, border);
     output
 }
 
@@ -254,16 +475,32 @@ fn gaussian_kernel(r: f32, kernel_size: usize) -> Vec<f32> {
 /// # Return value
 /// The resulting image after the filter was applied.
 pub fn gaussian_blur(image: &GrayFloatImage, r: f32) -> GrayFloatImage {
+    gaussian_blur_with_border(image, r, BorderMode::Extend)
+}
+
+/// Perform Gaussian blur on an image with the given border handling.
+///
+/// # Arguments
+/// * `r` - sigma.
+/// * `border` - how the pixels beyond the border are handled.
+/// # Return value
+/// The resulting image after the filter was applied.
+pub fn gaussian_blur_with_border(
+    image: &GrayFloatImage,
+    r: f32,
+    border: BorderMode,
+) -> GrayFloatImage {
     // a separable Gaussian kernel
     let kernel_size = (f32::ceil(r) as usize) * 2 + 1usize;
     let kernel = gaussian_kernel(r, kernel_size);
-    let img_horizontal = horizontal_filter(image, &kernel);
-    vertical_filter(&img_horizontal, &kernel)
+    let img_horizontal = horizontal_filter(image, &kernel, border);
+    vertical_filter(&img_horizontal, &kernel, border)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::gaussian_kernel;
+    use super::{gaussian_kernel, GrayFloatImage};
+    use ndarray::{s, Array2};
     #[test]
     fn gaussian_kernel_correct() {
         // test against known correct kernel
@@ -282,4 +519,22 @@ mod tests {
             assert!(f32::abs(*i - *j) < 0.0001);
         }
     }
+
+    #[test]
+    fn ndarray_and_nalgebra_views_agree() {
+        let array = Array2::from_shape_fn((7, 5), |(y, x)| (y * 5 + x) as f32);
+        // A slice of the rows starts at an offset inside of the buffer of the array.
+        let image = GrayFloatImage::from_ndarray(array.slice_move(s![2.., ..]));
+        assert_eq!((image.width(), image.height()), (5, 5));
+        let matrix = image.as_dmatrix_view();
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                assert_eq!(image.get(x, y), ((y + 2) * 5 + x) as f32);
+                assert_eq!(image.as_ndarray_view()[(y, x)], image.get(x, y));
+                assert_eq!(matrix[(y, x)], image.get(x, y));
+            }
+        }
+        let copy = GrayFloatImage::from_dmatrix(&matrix.into_owned());
+        assert_eq!(copy.as_raw(), image.as_raw());
+    }
 }
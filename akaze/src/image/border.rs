@@ -0,0 +1,157 @@
+use super::{fill_border, GrayFloatImage};
+
+/// How filters handle the pixels beyond the border of an image.
+///
+/// The border handling changes the filter responses near the edges of the image, and with them the keypoints which
+/// are detected there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BorderMode {
+    /// Only compute the pixels whose neighborhood lies inside of the image and copy the nearest computed pixel
+    /// outwards (see [`fill_border`]). This is the default.
+    Extend,
+    /// Repeat the edge pixel beyond the border, so `abcd` is read as `aaa|abcd|ddd`.
+    Replicate,
+    /// Mirror the image about the edge pixel, so `abcd` is read as `dcb|abcd|cba`.
+    Reflect,
+    /// Read a constant value beyond the border.
+    Constant(f32),
+}
+
+impl Default for BorderMode {
+    fn default() -> Self {
+        Self::Extend
+    }
+}
+
+impl BorderMode {
+    /// The index which index `ix` of a row or column of `len` pixels reads, or `None` if it reads the constant.
+    ///
+    /// [`BorderMode::Extend`] reads like [`BorderMode::Replicate`].
+    pub fn resolve(self, ix: isize, len: usize) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+        let last = len as isize - 1;
+        if (0..=last).contains(&ix) {
+            return Some(ix as usize);
+        }
+        match self {
+            Self::Extend | Self::Replicate => Some(ix.max(0).min(last) as usize),
+            Self::Reflect => {
+                if last == 0 {
+                    return Some(0);
+                }
+                // Reflection is periodic with a period of two passes over the row.
+                let period = 2 * last;
+                let ix = ix.rem_euclid(period);
+                Some(if ix > last { period - ix } else { ix } as usize)
+            }
+            Self::Constant(_) => None,
+        }
+    }
+
+    /// The pixel `(x, y)` of an image, which may lie beyond the border.
+    pub fn pixel(self, image: &GrayFloatImage, x: isize, y: isize) -> f32 {
+        match (
+            self.resolve(x, image.width()),
+            self.resolve(y, image.height()),
+        ) {
+            (Some(x), Some(y)) => image.get(x, y),
+            _ => match self {
+                Self::Constant(value) => value,
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+/// Complete the output of filtering an image with sparse taps along one axis by handling its borders.
+///
+/// The filters only compute the pixels for which every tap lies inside of the image, which leaves the pixels within
+/// the largest absolute offset of the edges along the filtered axis. These are either filled with [`fill_border`] or
+/// computed by reading beyond the border with the border mode.
+pub(crate) fn filter_border(
+    image: &GrayFloatImage,
+    output: &mut GrayFloatImage,
+    taps: &[(isize, f32)],
+    horizontal: bool,
+    mode: BorderMode,
+) {
+    let border = taps
+        .iter()
+        .map(|&(offset, _)| offset.unsigned_abs())
+        .max()
+        .unwrap_or(0);
+    if mode == BorderMode::Extend {
+        fill_border(output, border);
+        return;
+    }
+    let (along, across) = if horizontal {
+        (image.width(), image.height())
+    } else {
+        (image.height(), image.width())
+    };
+    let positions: Vec<usize> = if along <= 2 * border {
+        (0..along).collect()
+    } else {
+        (0..border).chain(along - border..along).collect()
+    };
+    for other in 0..across {
+        for &position in &positions {
+            let value = taps
+                .iter()
+                .map(|&(offset, weight)| {
+                    let ix = position as isize + offset;
+                    let pixel = if horizontal {
+                        mode.pixel(image, ix, other as isize)
+                    } else {
+                        mode.pixel(image, other as isize, ix)
+                    };
+                    weight * pixel
+                })
+                .sum();
+            if horizontal {
+                output.put(position, other, value);
+            } else {
+                output.put(other, position, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_border_modes() {
+        let read = |mode: BorderMode| -> Vec<Option<usize>> {
+            (-3..7).map(|ix| mode.resolve(ix, 4)).collect()
+        };
+        let some =
+            |ixs: &[usize]| -> Vec<Option<usize>> { ixs.iter().map(|&ix| Some(ix)).collect() };
+        assert_eq!(
+            read(BorderMode::Replicate),
+            some(&[0, 0, 0, 0, 1, 2, 3, 3, 3, 3])
+        );
+        assert_eq!(
+            read(BorderMode::Reflect),
+            some(&[3, 2, 1, 0, 1, 2, 3, 2, 1, 0])
+        );
+        assert_eq!(
+            read(BorderMode::Constant(0.5)),
+            vec![
+                None,
+                None,
+                None,
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(3),
+                None,
+                None,
+                None
+            ]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use super::GrayFloatImage;
+
+/// Filter every row of an image with sparse taps.
+///
+/// Each tap is an `(offset, weight)` pair, and output pixel `x` of a row receives
+/// `weight * input[x + offset]` from every tap. Only the columns for which every tap lies
+/// inside of the row are written, which are the columns `border..width - border` for the
+/// largest absolute offset `border`, and the rest of the output stays zero.
+///
+/// The taps are applied one at a time to whole rows, so the inner loop is a contiguous
+/// multiply-add which is vectorized (see [`axpy`]).
+pub(crate) fn filter_rows(image: &GrayFloatImage, taps: &[(isize, f32)]) -> GrayFloatImage {
+    let width = image.width();
+    let border = taps
+        .iter()
+        .map(|&(offset, _)| offset.unsigned_abs())
+        .max()
+        .unwrap_or(0);
+    let mut output = GrayFloatImage::new(width, image.height());
+    if width <= 2 * border {
+        return output;
+    }
+    let span = width - 2 * border;
+    for (out_row, in_row) in output
+        .chunks_exact_mut(width)
+        .zip(image.chunks_exact(width))
+    {
+        let out_row = &mut out_row[border..border + span];
+        for &(offset, weight) in taps {
+            let start = (border as isize + offset) as usize;
+            axpy(out_row, &in_row[start..start + span], weight);
+        }
+    }
+    output
+}
+
+/// Filter every column of an image with sparse taps.
+///
+/// This is the vertical counterpart of [`filter_rows`], where output row `y` receives
+/// `weight * input[y + offset]` from every tap. Only the rows `border..height - border`
+/// are written. Rather than striding down the columns, every output row accumulates the
+/// whole input rows of its taps, so the rows stay in cache and the multiply-add stays
+/// contiguous.
+pub(crate) fn filter_columns(image: &GrayFloatImage, taps: &[(isize, f32)]) -> GrayFloatImage {
+    let width = image.width();
+    let height = image.height();
+    let border = taps
+        .iter()
+        .map(|&(offset, _)| offset.unsigned_abs())
+        .max()
+        .unwrap_or(0);
+    let mut output = GrayFloatImage::new(width, height);
+    if width == 0 || height <= 2 * border {
+        return output;
+    }
+    let input: &[f32] = image;
+    for (y, out_row) in output
+        .chunks_exact_mut(width)
+        .enumerate()
+        .take(height - border)
+        .skip(border)
+    {
+        for &(offset, weight) in taps {
+            let start = (y as isize + offset) as usize * width;
+            axpy(out_row, &input[start..start + width], weight);
+        }
+    }
+    output
+}
+
+/// Converts a dense odd-sized kernel into taps centered on the kernel.
+pub(crate) fn kernel_taps(kernel: &[f32]) -> Vec<(isize, f32)> {
+    let half_width = (kernel.len() / 2) as isize;
+    kernel
+        .iter()
+        .enumerate()
+        .map(|(ix, &weight)| (ix as isize - half_width, weight))
+        .collect()
+}
+
+/// Computes `output += weight * input` elementwise.
+///
+/// On x86_64 this uses AVX with fused multiply-adds when the CPU supports them, which is
+/// detected at runtime, and otherwise falls back to a loop the compiler vectorizes.
+#[inline]
+pub(crate) fn axpy(output: &mut [f32], input: &[f32], weight: f32) {
+    debug_assert_eq!(output.len(), input.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") && is_x86_feature_detected!("fma") {
+            // Safety: the required CPU features were just detected.
+            unsafe { axpy_avx_fma(output, input, weight) };
+            return;
+        }
+    }
+    axpy_fallback(output, input, weight);
+}
+
+#[inline]
+fn axpy_fallback(output: &mut [f32], input: &[f32], weight: f32) {
+    for (out, &value) in output.iter_mut().zip(input) {
+        *out += weight * value;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,fma")]
+unsafe fn axpy_avx_fma(output: &mut [f32], input: &[f32], weight: f32) {
+    use std::arch::x86_64::{_mm256_fmadd_ps, _mm256_loadu_ps, _mm256_set1_ps, _mm256_storeu_ps};
+    let len = output.len().min(input.len());
+    let lanes = len / 8 * 8;
+    let weights = _mm256_set1_ps(weight);
+    for offset in (0..lanes).step_by(8) {
+        let out = output.as_mut_ptr().add(offset);
+        let value = _mm256_loadu_ps(input.as_ptr().add(offset));
+        _mm256_storeu_ps(out, _mm256_fmadd_ps(weights, value, _mm256_loadu_ps(out)));
+    }
+    axpy_fallback(&mut output[lanes..len], &input[lanes..len], weight);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axpy_matches_scalar() {
+        let input: Vec<f32> = (0..37).map(|ix| ix as f32 * 0.25).collect();
+        let mut output: Vec<f32> = (0..37).map(|ix| 1.0 - ix as f32).collect();
+        let mut expected = output.clone();
+        axpy(&mut output, &input, 0.5);
+        axpy_fallback(&mut expected, &input, 0.5);
+        for (a, b) in output.iter().zip(&expected) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+}
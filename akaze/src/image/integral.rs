@@ -0,0 +1,260 @@
+use super::GrayFloatImage;
+
+/// A summed area table of an image.
+///
+/// The sum of the pixels in any axis-aligned rectangle is computed in constant time
+/// from four lookups, which makes this the building block for box filters, SURF-style
+/// descriptors and fast approximations of filter responses. The sums are accumulated
+/// in `f64` so that large images don't lose precision.
+#[derive(Debug, Clone)]
+pub struct IntegralImage {
+    width: usize,
+    height: usize,
+    /// `(width + 1) * (height + 1)` sums where `sums[y * (width + 1) + x]` is the
+    /// sum of all pixels above and to the left of `(x, y)`, exclusive.
+    sums: Vec<f64>,
+}
+
+impl IntegralImage {
+    /// Compute the integral image of an image.
+    pub fn new(image: &GrayFloatImage) -> Self {
+        let width = image.width();
+        let height = image.height();
+        let stride = width + 1;
+        let mut sums = vec![0f64; stride * (height + 1)];
+        for (y, row) in image.chunks_exact(width.max(1)).enumerate().take(height) {
+            let mut row_sum = 0f64;
+            for (x, &pixel) in row.iter().enumerate() {
+                row_sum += f64::from(pixel);
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row_sum;
+            }
+        }
+        Self {
+            width,
+            height,
+            sums,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The sum of all pixels above and to the left of `(x, y)`, exclusive.
+    #[inline(always)]
+    fn at(&self, x: usize, y: usize) -> f64 {
+        self.sums[y * (self.width + 1) + x]
+    }
+
+    /// The sum of the pixels in the rectangle with the top left corner `(x, y)`.
+    ///
+    /// The rectangle is clipped to the image.
+    ///
+    /// # Arguments
+    /// * `x` - the left column of the rectangle.
+    /// * `y` - the top row of the rectangle.
+    /// * `width` - the number of columns in the rectangle.
+    /// * `height` - the number of rows in the rectangle.
+    pub fn sum(&self, x: usize, y: usize, width: usize, height: usize) -> f64 {
+        let x0 = x.min(self.width);
+        let y0 = y.min(self.height);
+        let x1 = x.saturating_add(width).min(self.width);
+        let y1 = y.saturating_add(height).min(self.height);
+        self.at(x1, y1) - self.at(x0, y1) - self.at(x1, y0) + self.at(x0, y0)
+    }
+
+    /// The mean of the pixels in the rectangle with the top left corner `(x, y)`.
+    ///
+    /// The rectangle is clipped to the image, and the mean of an empty rectangle is `0.0`.
+    pub fn mean(&self, x: usize, y: usize, width: usize, height: usize) -> f64 {
+        let columns = x.saturating_add(width).min(self.width).saturating_sub(x);
+        let rows = y.saturating_add(height).min(self.height).saturating_sub(y);
+        if columns == 0 || rows == 0 {
+            0.0
+        } else {
+            self.sum(x, y, width, height) / (columns * rows) as f64
+        }
+    }
+
+    /// The sum of the pixels in the square of `2 * radius + 1` pixels centered on `(x, y)`.
+    ///
+    /// The square is clipped to the image.
+    pub fn box_sum(&self, x: usize, y: usize, radius: usize) -> f64 {
+        let size = 2 * radius + 1;
+        self.sum(
+            x.saturating_sub(radius),
+            y.saturating_sub(radius),
+            size - radius.saturating_sub(x),
+            size - radius.saturating_sub(y),
+        )
+    }
+
+    /// Filter an image with a box filter of `2 * radius + 1` pixels on each side.
+    ///
+    /// The box is clipped at the borders, and each output pixel is the mean of the
+    /// pixels inside of its clipped box.
+    pub fn box_filter(&self, radius: usize) -> GrayFloatImage {
+        let mut output = GrayFloatImage::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let left = x.saturating_sub(radius);
+                let top = y.saturating_sub(radius);
+                let right = (x + radius + 1).min(self.width);
+                let bottom = (y + radius + 1).min(self.height);
+                let mean = self.mean(left, top, right - left, bottom - top);
+                output.put(x, y, mean as f32);
+            }
+        }
+        output
+    }
+}
+
+/// A summed area table of an image rotated by 45 degrees.
+///
+/// Entry `(x, y)` holds the sum of the pixels in the triangle with its bottom corner at
+/// `(x, y)` that widens by one pixel to each side for every row upwards. This makes the
+/// sum of any rectangle rotated by 45 degrees computable in constant time, as used by
+/// rotated Haar-like features.
+#[derive(Debug, Clone)]
+pub struct TiltedIntegralImage {
+    width: usize,
+    height: usize,
+    /// The triangles extend beyond the image to the left and right, so every row
+    /// holds `width + 2 * height` sums starting at the column `-height`.
+    sums: Vec<f64>,
+}
+
+impl TiltedIntegralImage {
+    /// Compute the tilted integral image of an image.
+    pub fn new(image: &GrayFloatImage) -> Self {
+        let width = image.width();
+        let height = image.height();
+        let stride = width + 2 * height;
+        let mut sums = vec![0f64; stride * height];
+        let pixel = |x: isize, y: isize| -> f64 {
+            if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                f64::from(image.get(x as usize, y as usize))
+            } else {
+                0.0
+            }
+        };
+        for y in 0..height {
+            for column in 0..stride {
+                let x = column as isize - height as isize;
+                let y = y as isize;
+                // The triangle is the union of the two triangles one row up and one column
+                // to either side, which overlap in the triangle two rows up, plus the two
+                // pixels in the middle column which neither of them contains.
+                let mut sum = pixel(x, y) + pixel(x, y - 1);
+                if y >= 1 {
+                    let above = (y as usize - 1) * stride;
+                    if column >= 1 {
+                        sum += sums[above + column - 1];
+                    }
+                    if column + 1 < stride {
+                        sum += sums[above + column + 1];
+                    }
+                }
+                if y >= 2 {
+                    sum -= sums[(y as usize - 2) * stride + column];
+                }
+                sums[y as usize * stride + column] = sum;
+            }
+        }
+        Self {
+            width,
+            height,
+            sums,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The sum of the triangle with its bottom corner at `(x, y)`.
+    fn at(&self, x: isize, y: isize) -> f64 {
+        // The triangle is empty if it is above the image or if it doesn't reach it.
+        if y < 0 || x + y < 0 || x - y >= self.width as isize {
+            return 0.0;
+        }
+        let column = x + self.height as isize;
+        self.sums[y as usize * (self.width + 2 * self.height) + column as usize]
+    }
+
+    /// The sum of the pixels in a rectangle rotated by 45 degrees.
+    ///
+    /// The rectangle has its bottom corner at the pixel `(x, y)` and extends `width`
+    /// diagonal steps up and to the left and `height` diagonal steps up and to the right,
+    /// so it covers `2 * width * height` pixels. The bottom corner must lie inside of the
+    /// image, while the rest of the rectangle is clipped to it.
+    pub fn tilted_sum(&self, x: usize, y: usize, width: usize, height: usize) -> f64 {
+        assert!(
+            x < self.width && y < self.height,
+            "the bottom corner must lie inside of the image"
+        );
+        let (x, y) = (x as isize, y as isize);
+        let (w, h) = (width as isize, height as isize);
+        self.at(x, y) - self.at(x + h, y - h) - self.at(x - w, y - w)
+            + self.at(x - w + h, y - w - h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image() -> GrayFloatImage {
+        let mut image = GrayFloatImage::new(13, 9);
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                image.put(x, y, ((x * 7 + y * 3) % 11) as f32);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn integral_sum_matches_brute_force() {
+        let image = test_image();
+        let integral = IntegralImage::new(&image);
+        for &(x, y, w, h) in &[(0, 0, 13, 9), (2, 3, 4, 5), (10, 7, 6, 6), (5, 5, 0, 3)] {
+            let mut expected = 0.0;
+            for py in y..(y + h).min(image.height()) {
+                for px in x..(x + w).min(image.width()) {
+                    expected += f64::from(image.get(px, py));
+                }
+            }
+            assert!((integral.sum(x, y, w, h) - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn tilted_sum_matches_brute_force() {
+        let image = test_image();
+        let tilted = TiltedIntegralImage::new(&image);
+        for &(x, y, w, h) in &[(6, 8, 2, 3), (1, 4, 3, 1), (12, 8, 4, 4), (0, 0, 1, 1)] {
+            // The rotated rectangle in the coordinates u = x + y and v = y - x.
+            let (u1, v1) = (x as isize + y as isize, y as isize - x as isize);
+            let (u0, v0) = (u1 - 2 * w as isize, v1 - 2 * h as isize);
+            let mut expected = 0.0;
+            for py in 0..image.height() as isize {
+                for px in 0..image.width() as isize {
+                    let (u, v) = (px + py, py - px);
+                    if u > u0 && u <= u1 && v > v0 && v <= v1 {
+                        expected += f64::from(image.get(px as usize, py as usize));
+                    }
+                }
+            }
+            assert!((tilted.tilted_sum(x, y, w, h) - expected).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use super::GrayFloatImage;
+use std::{
+    convert::TryInto,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Splits the first `count` whitespace separated tokens off of a Netpbm style header, skipping `#` comments.
+///
+/// Returns the tokens and the offset of the data, which starts after the single whitespace character that follows
+/// the last token.
+fn header_tokens(data: &[u8], count: usize) -> io::Result<(Vec<&str>, usize)> {
+    let mut tokens = Vec::with_capacity(count);
+    let mut ix = 0;
+    while tokens.len() < count {
+        match data.get(ix) {
+            Some(b'#') => {
+                while data.get(ix).map_or(false, |&c| c != b'\n') {
+                    ix += 1;
+                }
+            }
+            Some(c) if c.is_ascii_whitespace() => ix += 1,
+            Some(_) => {
+                let start = ix;
+                while data.get(ix).map_or(false, |c| !c.is_ascii_whitespace()) {
+                    ix += 1;
+                }
+                let token = std::str::from_utf8(&data[start..ix])
+                    .map_err(|_| invalid("the header is not valid text"))?;
+                tokens.push(token);
+            }
+            None => return Err(invalid("the header ended early")),
+        }
+    }
+    Ok((tokens, ix + 1))
+}
+
+fn parse<T: std::str::FromStr>(token: &str) -> io::Result<T> {
+    token
+        .parse()
+        .map_err(|_| invalid("the header contains an invalid number"))
+}
+
+/// Reads all bytes of a reader.
+fn read_all(mut reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// The byte order of the binary formats.
+#[derive(Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = bytes.try_into().unwrap();
+        match self {
+            Self::Little => u16::from_le_bytes(bytes),
+            Self::Big => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = bytes.try_into().unwrap();
+        match self {
+            Self::Little => u32::from_le_bytes(bytes),
+            Self::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn f32(self, bytes: &[u8]) -> f32 {
+        f32::from_bits(self.u32(bytes))
+    }
+}
+
+/// TIFF tags which are read and written.
+const TIFF_WIDTH: u16 = 256;
+const TIFF_HEIGHT: u16 = 257;
+const TIFF_BITS_PER_SAMPLE: u16 = 258;
+const TIFF_COMPRESSION: u16 = 259;
+const TIFF_PHOTOMETRIC: u16 = 262;
+const TIFF_STRIP_OFFSETS: u16 = 273;
+const TIFF_SAMPLES_PER_PIXEL: u16 = 277;
+const TIFF_ROWS_PER_STRIP: u16 = 278;
+const TIFF_STRIP_BYTE_COUNTS: u16 = 279;
+const TIFF_SAMPLE_FORMAT: u16 = 339;
+
+/// TIFF field types.
+const TIFF_SHORT: u16 = 3;
+const TIFF_LONG: u16 = 4;
+
+/// The values of a TIFF field, which are either inline or stored at an offset.
+fn tiff_values(data: &[u8], endian: Endian, entry: &[u8]) -> io::Result<Vec<u32>> {
+    let kind = endian.u16(&entry[2..4]);
+    let count = endian.u32(&entry[4..8]) as usize;
+    let size = match kind {
+        TIFF_SHORT => 2,
+        TIFF_LONG => 4,
+        _ => return Ok(vec![]),
+    };
+    let bytes = if count * size <= 4 {
+        &entry[8..8 + count * size]
+    } else {
+        let offset = endian.u32(&entry[8..12]) as usize;
+        data.get(offset..offset + count * size)
+            .ok_or_else(|| invalid("a TIFF field lies outside of the file"))?
+    };
+    Ok(bytes
+        .chunks_exact(size)
+        .map(|value| match kind {
+            TIFF_SHORT => u32::from(endian.u16(value)),
+            _ => endian.u32(value),
+        })
+        .collect())
+}
+
+impl GrayFloatImage {
+    /// Read a gray image from the Portable Float Map format.
+    ///
+    /// Only gray (`Pf`) images are supported. Color (`PF`) images are rejected.
+    ///
+    /// # Arguments
+    /// * `reader` - the source of the file.
+    /// # Return value
+    /// The image with its pixel values exactly as stored.
+    pub fn read_pfm(reader: impl Read) -> io::Result<Self> {
+        let data = read_all(reader)?;
+        let (tokens, offset) = header_tokens(&data, 4)?;
+        if tokens[0] != "Pf" {
+            return Err(invalid("only gray PFM images are supported"));
+        }
+        let width: usize = parse(tokens[1])?;
+        let height: usize = parse(tokens[2])?;
+        let scale: f32 = parse(tokens[3])?;
+        // A negative scale marks little endian data.
+        let endian = if scale < 0.0 {
+            Endian::Little
+        } else {
+            Endian::Big
+        };
+        let pixels = data
+            .get(offset..offset + width * height * 4)
+            .ok_or_else(|| invalid("the PFM data is too short"))?;
+        let mut image = Self::new(width, height);
+        // Rows are stored from the bottom to the top.
+        for (y, row) in pixels.chunks_exact(width.max(1) * 4).enumerate() {
+            for (x, pixel) in row.chunks_exact(4).enumerate() {
+                image.put(x, height - 1 - y, endian.f32(pixel));
+            }
+        }
+        Ok(image)
+    }
+
+    /// Write the image in the Portable Float Map format, which stores the pixels losslessly.
+    pub fn write_pfm(&self, mut writer: impl Write) -> io::Result<()> {
+        write!(writer, "Pf\n{} {}\n-1.0\n", self.width(), self.height())?;
+        let mut data = Vec::with_capacity(self.width() * self.height() * 4);
+        for row in self.chunks_exact(self.width().max(1)).rev() {
+            for pixel in row {
+                data.extend_from_slice(&pixel.to_le_bytes());
+            }
+        }
+        writer.write_all(&data)
+    }
+
+    /// Read a gray image from an uncompressed TIFF file with one 32-bit float sample per pixel.
+    ///
+    /// # Arguments
+    /// * `reader` - the source of the file.
+    /// # Return value
+    /// The image with its pixel values exactly as stored.
+    pub fn read_tiff(reader: impl Read) -> io::Result<Self> {
+        let data = read_all(reader)?;
+        let endian = match data.get(..4) {
+            Some(b"II*\0") => Endian::Little,
+            Some(b"MM\0*") => Endian::Big,
+            _ => return Err(invalid("the file is not a TIFF file")),
+        };
+        let ifd = endian.u32(&data[4..8]) as usize;
+        let entries = data
+            .get(ifd..ifd + 2)
+            .map(|count| endian.u16(count) as usize)
+            .ok_or_else(|| invalid("the TIFF directory lies outside of the file"))?;
+        let directory = data
+            .get(ifd + 2..ifd + 2 + 12 * entries)
+            .ok_or_else(|| invalid("the TIFF directory lies outside of the file"))?;
+        let (mut width, mut height) = (None, None);
+        let (mut offsets, mut counts) = (vec![], vec![]);
+        for entry in directory.chunks_exact(12) {
+            let values = tiff_values(&data, endian, entry)?;
+            let first = values.first().copied();
+            match endian.u16(&entry[0..2]) {
+                TIFF_WIDTH => width = first,
+                TIFF_HEIGHT => height = first,
+                TIFF_BITS_PER_SAMPLE if values.iter().any(|&bits| bits != 32) => {
+                    return Err(invalid("only 32-bit TIFF samples are supported"))
+                }
+                TIFF_COMPRESSION if first != Some(1) => {
+                    return Err(invalid("only uncompressed TIFF images are supported"))
+                }
+                TIFF_SAMPLES_PER_PIXEL if first != Some(1) => {
+                    return Err(invalid("only gray TIFF images are supported"))
+                }
+                TIFF_SAMPLE_FORMAT if first != Some(3) => {
+                    return Err(invalid("only float TIFF samples are supported"))
+                }
+                TIFF_STRIP_OFFSETS => offsets = values,
+                TIFF_STRIP_BYTE_COUNTS => counts = values,
+                _ => {}
+            }
+        }
+        let (width, height) = width
+            .zip(height)
+            .ok_or_else(|| invalid("the TIFF image has no size"))?;
+        let (width, height) = (width as usize, height as usize);
+        let mut pixels = Vec::with_capacity(width * height);
+        for (&offset, &count) in offsets.iter().zip(&counts) {
+            let strip = data
+                .get(offset as usize..offset as usize + count as usize)
+                .ok_or_else(|| invalid("a TIFF strip lies outside of the file"))?;
+            pixels.extend(strip.chunks_exact(4).map(|pixel| endian.f32(pixel)));
+        }
+        pixels.truncate(width * height);
+        Self::from_raw(width, height, pixels).ok_or_else(|| invalid("the TIFF data is too short"))
+    }
+
+    /// Write the image as an uncompressed TIFF file with one 32-bit float sample per pixel, which stores the
+    /// pixels losslessly.
+    pub fn write_tiff(&self, mut writer: impl Write) -> io::Result<()> {
+        let (width, height) = (self.width() as u32, self.height() as u32);
+        let entries: [(u16, u16, u32); 10] = [
+            (TIFF_WIDTH, TIFF_LONG, width),
+            (TIFF_HEIGHT, TIFF_LONG, height),
+            (TIFF_BITS_PER_SAMPLE, TIFF_SHORT, 32),
+            (TIFF_COMPRESSION, TIFF_SHORT, 1),
+            // Black is zero.
+            (TIFF_PHOTOMETRIC, TIFF_SHORT, 1),
+            // The only strip starts right after the header and the directory.
+            (TIFF_STRIP_OFFSETS, TIFF_LONG, 8 + 2 + 12 * 10 + 4),
+            (TIFF_SAMPLES_PER_PIXEL, TIFF_SHORT, 1),
+            (TIFF_ROWS_PER_STRIP, TIFF_LONG, height),
+            (TIFF_STRIP_BYTE_COUNTS, TIFF_LONG, width * height * 4),
+            // IEEE floating point.
+            (TIFF_SAMPLE_FORMAT, TIFF_SHORT, 3),
+        ];
+        let mut data = Vec::with_capacity(134 + self.len() * 4);
+        data.extend_from_slice(b"II*\0");
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for &(tag, kind, value) in &entries {
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&kind.to_le_bytes());
+            data.extend_from_slice(&1u32.to_le_bytes());
+            if kind == TIFF_SHORT {
+                data.extend_from_slice(&(value as u16).to_le_bytes());
+                data.extend_from_slice(&[0; 2]);
+            } else {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        // There is no next directory.
+        data.extend_from_slice(&0u32.to_le_bytes());
+        for pixel in self.iter() {
+            data.extend_from_slice(&pixel.to_le_bytes());
+        }
+        writer.write_all(&data)
+    }
+
+    /// Read a gray image from the binary (`P5`) Portable Gray Map format with 8 or 16 bits per pixel.
+    ///
+    /// # Arguments
+    /// * `reader` - the source of the file.
+    /// # Return value
+    /// An image with pixel values between 0 and 1.
+    pub fn read_pgm(reader: impl Read) -> io::Result<Self> {
+        let data = read_all(reader)?;
+        let (tokens, offset) = header_tokens(&data, 4)?;
+        if tokens[0] != "P5" {
+            return Err(invalid("only binary PGM images are supported"));
+        }
+        let width: usize = parse(tokens[1])?;
+        let height: usize = parse(tokens[2])?;
+        let max: u16 = parse(tokens[3])?;
+        if max == 0 {
+            return Err(invalid("the PGM maximum value is zero"));
+        }
+        let bytes = if max < 256 { 1 } else { 2 };
+        let pixels = data
+            .get(offset..offset + width * height * bytes)
+            .ok_or_else(|| invalid("the PGM data is too short"))?;
+        let pixels = pixels
+            .chunks_exact(bytes)
+            .map(|pixel| {
+                let value = if bytes == 1 {
+                    u16::from(pixel[0])
+                } else {
+                    Endian::Big.u16(pixel)
+                };
+                f32::from(value) / f32::from(max)
+            })
+            .collect();
+        Self::from_raw(width, height, pixels).ok_or_else(|| invalid("the PGM data is too short"))
+    }
+
+    /// Write the image in the binary Portable Gray Map format with 16 bits per pixel.
+    ///
+    /// Pixel values are clamped between 0 and 1 and quantized, so the format is only lossless for images which came
+    /// from 16-bit or 8-bit sources. Use [`GrayFloatImage::write_pfm`] or [`GrayFloatImage::write_tiff`] for
+    /// arbitrary values.
+    pub fn write_pgm(&self, mut writer: impl Write) -> io::Result<()> {
+        write!(writer, "P5\n{} {}\n65535\n", self.width(), self.height())?;
+        let mut data = Vec::with_capacity(self.len() * 2);
+        for pixel in self.iter() {
+            let value = (pixel.max(0.0).min(1.0) * 65535.0).round() as u16;
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        writer.write_all(&data)
+    }
+
+    /// Load an image from a PFM, TIFF or PGM file, which is chosen by the extension of the path.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        match extension(path).as_deref() {
+            Some("pfm") => Self::read_pfm(reader),
+            Some("tif") | Some("tiff") => Self::read_tiff(reader),
+            Some("pgm") => Self::read_pgm(reader),
+            _ => Err(unsupported()),
+        }
+    }
+
+    /// Save the image to a PFM, TIFF or PGM file, which is chosen by the extension of the path.
+    ///
+    /// This is meant for saving response maps and evolutions for debugging, so they can be reloaded with
+    /// [`GrayFloatImage::load`].
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = extension(path).ok_or_else(unsupported)?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format.as_str() {
+            "pfm" => self.write_pfm(&mut writer),
+            "tif" | "tiff" => self.write_tiff(&mut writer),
+            "pgm" => self.write_pgm(&mut writer),
+            _ => Err(unsupported()),
+        }?;
+        writer.flush()
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase())
+}
+
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "only .pfm, .tif, .tiff and .pgm files are supported",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> GrayFloatImage {
+        let mut image = GrayFloatImage::new(5, 3);
+        for y in 0..3 {
+            for x in 0..5 {
+                image.put(x, y, (x as f32 - 1.5) * 0.37 + y as f32 * 1e-3);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn float_formats_round_trip() {
+        let image = image();
+        let mut pfm = vec![];
+        image.write_pfm(&mut pfm).unwrap();
+        let mut tiff = vec![];
+        image.write_tiff(&mut tiff).unwrap();
+        for loaded in &[
+            GrayFloatImage::read_pfm(&pfm[..]).unwrap(),
+            GrayFloatImage::read_tiff(&tiff[..]).unwrap(),
+        ] {
+            assert_eq!((loaded.width(), loaded.height()), (5, 3));
+            assert_eq!(loaded.as_raw(), image.as_raw());
+        }
+    }
+
+    #[test]
+    fn pgm_round_trip() {
+        let mut image = image();
+        for pixel in image.iter_mut() {
+            *pixel = (pixel.max(0.0).min(1.0) * 65535.0).round() / 65535.0;
+        }
+        let mut pgm = vec![];
+        image.write_pgm(&mut pgm).unwrap();
+        let loaded = GrayFloatImage::read_pgm(&pgm[..]).unwrap();
+        assert_eq!(loaded.as_raw(), image.as_raw());
+        let commented = GrayFloatImage::read_pgm(&b"P5 # comment\n2 1\n255\n\x00\xff"[..]).unwrap();
+        assert_eq!(commented.as_raw(), &vec![0.0, 1.0]);
+    }
+}
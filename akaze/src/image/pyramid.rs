@@ -0,0 +1,151 @@
+use super::{resize_into, GrayFloatImage, Interpolation};
+
+/// An image pyramid, where each level is the level below it downscaled by a constant factor.
+///
+/// The levels are allocated once for a given image size and [`Pyramid::rebuild`] writes
+/// each new image into them in place, so a pyramid can be kept across the frames of a
+/// video without allocating. Level `0` is a copy of the image.
+#[derive(Debug, Clone)]
+pub struct Pyramid {
+    factor: f32,
+    interpolation: Interpolation,
+    levels: Vec<GrayFloatImage>,
+}
+
+impl Pyramid {
+    /// Allocate a pyramid for images of the given size.
+    ///
+    /// Levels are added until `levels` levels exist or the next level would be empty.
+    ///
+    /// # Arguments
+    /// * `width` - the width of the images.
+    /// * `height` - the height of the images.
+    /// * `levels` - the maximum number of levels, including the image itself.
+    /// * `factor` - the factor each level is downscaled by, which must exceed `1.0`.
+    /// * `interpolation` - how the levels are sampled.
+    pub fn new(
+        width: usize,
+        height: usize,
+        levels: usize,
+        factor: f32,
+        interpolation: Interpolation,
+    ) -> Self {
+        assert!(factor > 1.0, "the pyramid must downscale each level");
+        let mut sizes = vec![];
+        let (mut level_width, mut level_height) = (width, height);
+        while sizes.len() < levels && level_width > 0 && level_height > 0 {
+            sizes.push((level_width, level_height));
+            level_width = (level_width as f32 / factor) as usize;
+            level_height = (level_height as f32 / factor) as usize;
+        }
+        Self {
+            factor,
+            interpolation,
+            levels: sizes
+                .into_iter()
+                .map(|(width, height)| GrayFloatImage::new(width, height))
+                .collect(),
+        }
+    }
+
+    /// Build a pyramid from an image.
+    pub fn from_image(
+        image: &GrayFloatImage,
+        levels: usize,
+        factor: f32,
+        interpolation: Interpolation,
+    ) -> Self {
+        let mut pyramid = Self::new(image.width(), image.height(), levels, factor, interpolation);
+        pyramid.rebuild(image);
+        pyramid
+    }
+
+    /// Rebuild the pyramid from a new image in place.
+    ///
+    /// The levels are only reallocated if the image has a different size than the last
+    /// one, in which case the number of levels is kept where possible.
+    pub fn rebuild(&mut self, image: &GrayFloatImage) {
+        let same_size = self.levels.first().map_or(false, |base| {
+            base.width() == image.width() && base.height() == image.height()
+        });
+        if !same_size {
+            *self = Self::new(
+                image.width(),
+                image.height(),
+                self.levels.len().max(1),
+                self.factor,
+                self.interpolation,
+            );
+        }
+        if let Some(base) = self.levels.first_mut() {
+            base.copy_from_slice(image);
+        }
+        for level in 1..self.levels.len() {
+            let (below, above) = self.levels.split_at_mut(level);
+            resize_into(&below[level - 1], &mut above[0], self.interpolation);
+        }
+    }
+
+    /// The factor each level is downscaled by.
+    pub fn factor(&self) -> f32 {
+        self.factor
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// The levels from the image itself to the coarsest level.
+    pub fn levels(&self) -> &[GrayFloatImage] {
+        &self.levels
+    }
+
+    pub fn level(&self, level: usize) -> &GrayFloatImage {
+        &self.levels[level]
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// The factor which converts pixel coordinates of the image into pixel coordinates of
+    /// a level.
+    pub fn scale(&self, level: usize) -> f32 {
+        let base = &self.levels[0];
+        let level = &self.levels[level];
+        level.width() as f32 / base.width() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebuild_keeps_levels_and_preserves_constant_images() {
+        let mut image = GrayFloatImage::new(64, 48);
+        for pixel in image.iter_mut() {
+            *pixel = 0.25;
+        }
+        for &interpolation in &[
+            Interpolation::Nearest,
+            Interpolation::Bilinear,
+            Interpolation::Area,
+        ] {
+            let mut pyramid = Pyramid::new(64, 48, 4, 1.5, interpolation);
+            pyramid.rebuild(&image);
+            assert_eq!(pyramid.len(), 4);
+            assert_eq!(
+                (pyramid.level(1).width(), pyramid.level(1).height()),
+                (42, 32)
+            );
+            for level in pyramid.levels() {
+                assert!(level.iter().all(|&pixel| (pixel - 0.25).abs() < 1e-5));
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use super::{convolution::axpy, GrayFloatImage};
+
+/// How an image is resampled to another size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Take the source pixel closest to the center of each output pixel.
+    Nearest,
+    /// Interpolate linearly between the four source pixels around the center of each
+    /// output pixel.
+    Bilinear,
+    /// Average the source pixels covered by each output pixel, weighted by the area
+    /// they cover. This doesn't alias when downscaling.
+    Area,
+}
+
+/// Downscale an image by two into a preallocated target by averaging each 2x2 block of pixels.
+///
+/// This is the fast path of [`Interpolation::Area`] for a factor of two, which is how the
+/// octaves of the nonlinear scale space are built. A trailing odd row or column of the
+/// source is dropped.
+///
+/// # Arguments
+/// * `source` - the image to downscale.
+/// * `target` - the output image, which must be `source.width() / 2` by `source.height() / 2`.
+pub fn half_sample_into(source: &GrayFloatImage, target: &mut GrayFloatImage) {
+    let width = target.width();
+    assert!(
+        width == source.width() / 2 && target.height() == source.height() / 2,
+        "the target must be half the size of the source"
+    );
+    if width == 0 {
+        return;
+    }
+    let source_width = source.width();
+    let input: &[f32] = source;
+    // The sum of the two source rows of an output row.
+    let mut pairs = vec![0f32; 2 * width];
+    for (y, out_row) in target.chunks_exact_mut(width).enumerate() {
+        let top = 2 * y * source_width;
+        let bottom = top + source_width;
+        pairs.copy_from_slice(&input[top..top + 2 * width]);
+        axpy(&mut pairs, &input[bottom..bottom + 2 * width], 1.0);
+        sum_pairs(out_row, &pairs, 0.25);
+    }
+}
+
+/// Computes `output[x] = scale * (input[2 * x] + input[2 * x + 1])`.
+///
+/// On x86_64 this uses AVX2 when the CPU supports it, which is detected at runtime.
+#[inline]
+fn sum_pairs(output: &mut [f32], input: &[f32], scale: f32) {
+    debug_assert_eq!(2 * output.len(), input.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safety: the required CPU features were just detected.
+            unsafe { sum_pairs_avx2(output, input, scale) };
+            return;
+        }
+    }
+    sum_pairs_fallback(output, input, scale);
+}
+
+#[inline]
+fn sum_pairs_fallback(output: &mut [f32], input: &[f32], scale: f32) {
+    for (out, pair) in output.iter_mut().zip(input.chunks_exact(2)) {
+        *out = scale * (pair[0] + pair[1]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn sum_pairs_avx2(output: &mut [f32], input: &[f32], scale: f32) {
+    use std::arch::x86_64::{
+        _mm256_castpd_ps, _mm256_castps_pd, _mm256_hadd_ps, _mm256_loadu_ps, _mm256_mul_ps,
+        _mm256_permute4x64_pd, _mm256_set1_ps, _mm256_storeu_ps,
+    };
+    let len = output.len().min(input.len() / 2);
+    let lanes = len / 8 * 8;
+    let scales = _mm256_set1_ps(scale);
+    for offset in (0..lanes).step_by(8) {
+        let low = _mm256_loadu_ps(input.as_ptr().add(2 * offset));
+        let high = _mm256_loadu_ps(input.as_ptr().add(2 * offset + 8));
+        // The horizontal add interleaves the 128-bit lanes of both inputs, so the 64-bit
+        // quarters are put back in order.
+        let sums = _mm256_castpd_ps(_mm256_permute4x64_pd(
+            _mm256_castps_pd(_mm256_hadd_ps(low, high)),
+            0b11_01_10_00,
+        ));
+        _mm256_storeu_ps(output.as_mut_ptr().add(offset), _mm256_mul_ps(sums, scales));
+    }
+    sum_pairs_fallback(&mut output[lanes..len], &input[2 * lanes..2 * len], scale);
+}
+
+/// Resample an image into the size of a preallocated target image.
+///
+/// Pixel centers are aligned, so the center of the source maps onto the center of the target.
+/// Bilinear and area resampling are separable, and the vertical pass accumulates whole rows,
+/// which is vectorized.
+///
+/// # Arguments
+/// * `source` - the image to resample.
+/// * `target` - the output image, which determines the output size.
+/// * `interpolation` - how the output pixels are sampled.
+pub fn resize_into(
+    source: &GrayFloatImage,
+    target: &mut GrayFloatImage,
+    interpolation: Interpolation,
+) {
+    let (source_width, source_height) = (source.width(), source.height());
+    let (target_width, target_height) = (target.width(), target.height());
+    if source_width == 0 || source_height == 0 || target_width == 0 || target_height == 0 {
+        return;
+    }
+    if interpolation == Interpolation::Area
+        && source_width == 2 * target_width
+        && source_height == 2 * target_height
+    {
+        half_sample_into(source, target);
+        return;
+    }
+    let x_ratio = source_width as f32 / target_width as f32;
+    let y_ratio = source_height as f32 / target_height as f32;
+    match interpolation {
+        Interpolation::Nearest => {
+            let columns: Vec<usize> = (0..target_width)
+                .map(|x| (((x as f32 + 0.5) * x_ratio) as usize).min(source_width - 1))
+                .collect();
+            for (y, out_row) in target.chunks_exact_mut(target_width).enumerate() {
+                let source_y = (((y as f32 + 0.5) * y_ratio) as usize).min(source_height - 1);
+                let in_row = &source[source_y * source_width..(source_y + 1) * source_width];
+                for (out, &source_x) in out_row.iter_mut().zip(&columns) {
+                    *out = in_row[source_x];
+                }
+            }
+        }
+        Interpolation::Bilinear => {
+            let columns: Vec<(usize, usize, f32)> = (0..target_width)
+                .map(|x| linear_weights(x, x_ratio, source_width))
+                .collect();
+            let resample_row = |source_y: usize, out_row: &mut [f32]| {
+                let in_row = &source[source_y * source_width..(source_y + 1) * source_width];
+                for (out, &(left, right, weight)) in out_row.iter_mut().zip(&columns) {
+                    *out = in_row[left] * (1.0 - weight) + in_row[right] * weight;
+                }
+            };
+            // The two horizontally resampled source rows around the current output row.
+            let mut upper = (usize::MAX, vec![0f32; target_width]);
+            let mut lower = (usize::MAX, vec![0f32; target_width]);
+            for (y, out_row) in target.chunks_exact_mut(target_width).enumerate() {
+                let (top, bottom, weight) = linear_weights(y, y_ratio, source_height);
+                if upper.0 != top {
+                    if lower.0 == top {
+                        std::mem::swap(&mut upper, &mut lower);
+                    } else {
+                        resample_row(top, &mut upper.1);
+                        upper.0 = top;
+                    }
+                }
+                if lower.0 != bottom {
+                    resample_row(bottom, &mut lower.1);
+                    lower.0 = bottom;
+                }
+                for out in out_row.iter_mut() {
+                    *out = 0.0;
+                }
+                axpy(out_row, &upper.1, 1.0 - weight);
+                axpy(out_row, &lower.1, weight);
+            }
+        }
+        Interpolation::Area => {
+            let columns: Vec<Vec<(usize, f32)>> = (0..target_width)
+                .map(|x| area_weights(x, x_ratio, source_width))
+                .collect();
+            // Resample every source row horizontally first.
+            let mut rows = vec![0f32; target_width * source_height];
+            for (out_row, in_row) in rows
+                .chunks_exact_mut(target_width)
+                .zip(source.chunks_exact(source_width))
+            {
+                for (out, weights) in out_row.iter_mut().zip(&columns) {
+                    *out = weights
+                        .iter()
+                        .map(|&(source_x, weight)| in_row[source_x] * weight)
+                        .sum();
+                }
+            }
+            for (y, out_row) in target.chunks_exact_mut(target_width).enumerate() {
+                for out in out_row.iter_mut() {
+                    *out = 0.0;
+                }
+                for (source_y, weight) in area_weights(y, y_ratio, source_height) {
+                    let start = source_y * target_width;
+                    axpy(out_row, &rows[start..start + target_width], weight);
+                }
+            }
+        }
+    }
+}
+
+/// The two source pixels around the center of output pixel `ix` and the weight of the second.
+fn linear_weights(ix: usize, ratio: f32, len: usize) -> (usize, usize, f32) {
+    let center = ((ix as f32 + 0.5) * ratio - 0.5).max(0.0);
+    let low = (center as usize).min(len - 1);
+    let high = (low + 1).min(len - 1);
+    (low, high, center - low as f32)
+}
+
+/// The source pixels covered by output pixel `ix` and the normalized areas they cover.
+fn area_weights(ix: usize, ratio: f32, len: usize) -> Vec<(usize, f32)> {
+    let start = ix as f32 * ratio;
+    let end = ((ix + 1) as f32 * ratio).min(len as f32);
+    let first = (start as usize).min(len - 1);
+    let last = (end.ceil() as usize).min(len).max(first + 1);
+    let mut weights: Vec<(usize, f32)> = (first..last)
+        .map(|source| {
+            let covered = (end.min((source + 1) as f32) - start.max(source as f32)).max(0.0);
+            (source, covered)
+        })
+        .collect();
+    let total: f32 = weights.iter().map(|&(_, weight)| weight).sum();
+    for (_, weight) in &mut weights {
+        *weight /= total;
+    }
+    weights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_sample_averages_blocks() {
+        let mut image = GrayFloatImage::new(37, 22);
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                image.put(x, y, ((x * 7 + y * 3) % 11) as f32);
+            }
+        }
+        let mut half = GrayFloatImage::new(18, 11);
+        half_sample_into(&image, &mut half);
+        for y in 0..half.height() {
+            for x in 0..half.width() {
+                let expected = 0.25
+                    * (image.get(2 * x, 2 * y)
+                        + image.get(2 * x + 1, 2 * y)
+                        + image.get(2 * x, 2 * y + 1)
+                        + image.get(2 * x + 1, 2 * y + 1));
+                assert!((half.get(x, y) - expected).abs() < 1e-5);
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use super::{convolution::axpy, GrayFloatImage};
+
+/// Elementwise arithmetic and reductions.
+///
+/// The elementwise operations work on the whole buffer at once, so the compiler vectorizes them, and the gradient
+/// magnitude uses AVX when the CPU supports it.
+impl GrayFloatImage {
+    /// Add another image of the same size pixel by pixel.
+    pub fn add(&mut self, other: &Self) {
+        self.add_scaled(other, 1.0);
+    }
+
+    /// Add another image of the same size multiplied by `factor` pixel by pixel.
+    pub fn add_scaled(&mut self, other: &Self, factor: f32) {
+        assert!(
+            self.width() == other.width() && self.height() == other.height(),
+            "the images must have the same size"
+        );
+        axpy(self, other, factor);
+    }
+
+    /// Add `value` to every pixel.
+    pub fn add_scalar(&mut self, value: f32) {
+        for pixel in self.iter_mut() {
+            *pixel += value;
+        }
+    }
+
+    /// Multiply every pixel by `factor`.
+    pub fn scale(&mut self, factor: f32) {
+        for pixel in self.iter_mut() {
+            *pixel *= factor;
+        }
+    }
+
+    /// Clamp every pixel between `min` and `max`.
+    pub fn clamp(&mut self, min: f32, max: f32) {
+        for pixel in self.iter_mut() {
+            *pixel = pixel.max(min).min(max);
+        }
+    }
+
+    /// The smallest pixel value, or `None` if the image is empty. `NaN` pixels are ignored.
+    pub fn min(&self) -> Option<f32> {
+        self.min_max().map(|(min, _)| min)
+    }
+
+    /// The largest pixel value, or `None` if the image is empty. `NaN` pixels are ignored.
+    pub fn max(&self) -> Option<f32> {
+        self.min_max().map(|(_, max)| max)
+    }
+
+    /// The smallest and largest pixel values, or `None` if the image is empty. `NaN` pixels are ignored.
+    pub fn min_max(&self) -> Option<(f32, f32)> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(
+            self.iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &pixel| {
+                    (min.min(pixel), max.max(pixel))
+                }),
+        )
+    }
+
+    /// The mean pixel value, or `None` if the image is empty.
+    ///
+    /// The sum is accumulated in double precision.
+    pub fn mean(&self) -> Option<f32> {
+        self.mean_f64().map(|mean| mean as f32)
+    }
+
+    /// The standard deviation of the pixel values, or `None` if the image is empty.
+    pub fn std_dev(&self) -> Option<f32> {
+        let mean = self.mean_f64()?;
+        let variance = self
+            .iter()
+            .map(|&pixel| (f64::from(pixel) - mean).powi(2))
+            .sum::<f64>()
+            / self.len() as f64;
+        Some(variance.sqrt() as f32)
+    }
+
+    fn mean_f64(&self) -> Option<f64> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.iter().map(|&pixel| f64::from(pixel)).sum::<f64>() / self.len() as f64)
+    }
+
+    /// Count the pixels in `bins` equally wide bins between `min` and `max`.
+    ///
+    /// Pixels equal to `max` are counted in the last bin, and pixels outside of the range are not counted. If `max`
+    /// isn't larger than `min`, every pixel in the range is counted in the first bin.
+    pub fn histogram(&self, bins: usize, min: f32, max: f32) -> Vec<usize> {
+        let bins = bins.max(1);
+        let mut histogram = vec![0; bins];
+        let scale = if max > min {
+            bins as f32 / (max - min)
+        } else {
+            0.0
+        };
+        for &pixel in self.iter() {
+            if (min..=max).contains(&pixel) {
+                let bin = (((pixel - min) * scale) as usize).min(bins - 1);
+                histogram[bin] += 1;
+            }
+        }
+        histogram
+    }
+
+    /// Estimate a percentile (between 0 and 1) of the pixel values with a histogram of `bins` bins.
+    ///
+    /// The result is the upper edge of the bin which contains the percentile, so it overestimates by at most the
+    /// width of a bin. Returns `None` if the image is empty.
+    pub fn percentile(&self, percentile: f32, bins: usize) -> Option<f32> {
+        let (min, max) = self.min_max()?;
+        let bins = bins.max(1);
+        let histogram = self.histogram(bins, min, max);
+        let threshold = ((percentile.max(0.0).min(1.0) * self.len() as f32).ceil() as usize).max(1);
+        let mut count = 0;
+        for (bin, &bin_count) in histogram.iter().enumerate() {
+            count += bin_count;
+            if count >= threshold {
+                return Some(min + (max - min) * (bin + 1) as f32 / bins as f32);
+            }
+        }
+        Some(max)
+    }
+
+    /// Compute the gradient magnitude `sqrt(lx^2 + ly^2)` of the horizontal and vertical derivatives of an image.
+    pub fn gradient_magnitude(lx: &Self, ly: &Self) -> Self {
+        assert!(
+            lx.width() == ly.width() && lx.height() == ly.height(),
+            "the derivatives must have the same size"
+        );
+        let mut output = Self::new(lx.width(), lx.height());
+        magnitude(&mut output, lx, ly);
+        output
+    }
+}
+
+/// Computes `output[i] = sqrt(x[i]^2 + y[i]^2)`.
+///
+/// On x86_64 this uses AVX when the CPU supports it, which is detected at runtime.
+#[inline]
+fn magnitude(output: &mut [f32], x: &[f32], y: &[f32]) {
+    debug_assert!(output.len() == x.len() && output.len() == y.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            // Safety: the required CPU features were just detected.
+            unsafe { magnitude_avx(output, x, y) };
+            return;
+        }
+    }
+    magnitude_fallback(output, x, y);
+}
+
+#[inline]
+fn magnitude_fallback(output: &mut [f32], x: &[f32], y: &[f32]) {
+    for ((out, &x), &y) in output.iter_mut().zip(x).zip(y) {
+        *out = (x * x + y * y).sqrt();
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn magnitude_avx(output: &mut [f32], x: &[f32], y: &[f32]) {
+    use std::arch::x86_64::{
+        _mm256_add_ps, _mm256_loadu_ps, _mm256_mul_ps, _mm256_sqrt_ps, _mm256_storeu_ps,
+    };
+    let len = output.len().min(x.len()).min(y.len());
+    let lanes = len / 8 * 8;
+    for offset in (0..lanes).step_by(8) {
+        let vx = _mm256_loadu_ps(x.as_ptr().add(offset));
+        let vy = _mm256_loadu_ps(y.as_ptr().add(offset));
+        let squares = _mm256_add_ps(_mm256_mul_ps(vx, vx), _mm256_mul_ps(vy, vy));
+        _mm256_storeu_ps(output.as_mut_ptr().add(offset), _mm256_sqrt_ps(squares));
+    }
+    magnitude_fallback(&mut output[lanes..len], &x[lanes..len], &y[lanes..len]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reductions() {
+        let mut image = GrayFloatImage::new(4, 5);
+        for (ix, pixel) in image.iter_mut().enumerate() {
+            *pixel = ix as f32;
+        }
+        assert_eq!(image.min_max(), Some((0.0, 19.0)));
+        assert_eq!(image.mean(), Some(9.5));
+        assert_eq!(image.histogram(4, 0.0, 20.0), vec![5; 4]);
+        assert_eq!(image.percentile(0.5, 19), Some(10.0));
+        image.scale(0.5);
+        image.add_scalar(-1.0);
+        image.clamp(0.0, 5.0);
+        assert_eq!(image.min_max(), Some((0.0, 5.0)));
+        assert_eq!(GrayFloatImage::new(0, 0).mean(), None);
+    }
+
+    #[test]
+    fn gradient_magnitude_matches_scalar() {
+        let mut lx = GrayFloatImage::new(7, 3);
+        let mut ly = GrayFloatImage::new(7, 3);
+        for (ix, (x, y)) in lx.iter_mut().zip(ly.iter_mut()).enumerate() {
+            *x = ix as f32 * 0.3 - 2.0;
+            *y = 1.5 - ix as f32 * 0.1;
+        }
+        let magnitude = GrayFloatImage::gradient_magnitude(&lx, &ly);
+        let mut expected = vec![0.0; lx.len()];
+        magnitude_fallback(&mut expected, &lx, &ly);
+        assert_eq!(magnitude.as_raw(), &expected);
+    }
+}
@@ -4,12 +4,15 @@ mod descriptors;
 mod detector_response;
 mod evolution;
 mod fed_tau;
-mod image;
+pub mod image;
 mod nonlinear_diffusion;
 mod scale_space_extrema;
 
-use crate::image::{gaussian_blur, GrayFloatImage};
-use ::image::{DynamicImage, GenericImageView, ImageResult};
+use crate::image::{
+    gaussian_blur_with_border, BorderMode, Clahe, GrayConversion, GrayFloatImage,
+    PhotometricNormalization,
+};
+use ::image::{DynamicImage, ImageResult};
 use bitarray::BitArray;
 use cv_core::{nalgebra::Point2, ImagePoint};
 use evolution::*;
@@ -17,9 +20,13 @@ use log::*;
 use nonlinear_diffusion::pm_g2;
 use std::path::Path;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 /// A point of interest in an image.
 /// This pretty much follows from OpenCV conventions.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct KeyPoint {
     /// The horizontal coordinate in a coordinate system is
     /// defined s.t. +x faces right and starts from the top
@@ -88,6 +95,18 @@ pub struct Akaze {
 
     /// Actual patch size is 2*pattern_size*point.scale
     pub descriptor_pattern_size: usize,
+
+    /// How the filters and the diffusion handle the pixels beyond the image border
+    pub border_mode: BorderMode,
+
+    /// How color images are converted to gray
+    pub gray_conversion: GrayConversion,
+
+    /// Global photometric normalization applied to the image before extraction
+    pub photometric_normalization: Option<PhotometricNormalization>,
+
+    /// Adaptive histogram equalization applied to the image before extraction, after the normalization
+    pub clahe: Option<Clahe>,
 }
 
 impl Akaze {
@@ -128,6 +147,10 @@ impl Default for Akaze {
             detector_threshold: 0.001f64,
             descriptor_channels: 3usize,
             descriptor_pattern_size: 10usize,
+            border_mode: BorderMode::Extend,
+            gray_conversion: GrayConversion::Luma,
+            photometric_normalization: None,
+            clahe: None,
         }
     }
 }
@@ -138,13 +161,18 @@ impl Akaze {
     /// # Arguments
     /// * `evolutions` - The output scale space.
     /// * `image` - The input image.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "akaze::scale_space", skip_all, fields(evolutions = evolutions.len()))
+    )]
     fn create_nonlinear_scale_space(
         &self,
         evolutions: &mut Vec<EvolutionStep>,
         image: &GrayFloatImage,
     ) {
         trace!("Creating first evolution.");
-        evolutions[0].Lt = gaussian_blur(image, self.base_scale_offset as f32);
+        evolutions[0].Lt =
+            gaussian_blur_with_border(image, self.base_scale_offset as f32, self.border_mode);
         trace!("Gaussian blur finished.");
         evolutions[0].Lsmooth = evolutions[0].Lt.clone();
         debug!(
@@ -177,18 +205,25 @@ impl Akaze {
             } else {
                 evolutions[i].Lt = evolutions[i - 1].Lt.clone();
             }
-            evolutions[i].Lsmooth = gaussian_blur(&evolutions[i].Lt, 1.0f32);
+            evolutions[i].Lsmooth =
+                gaussian_blur_with_border(&evolutions[i].Lt, 1.0f32, self.border_mode);
             trace!("Gaussian blur finished.");
-            evolutions[i].Lx = derivatives::scharr_horizontal(&evolutions[i].Lsmooth, 1);
+            evolutions[i].Lx =
+                derivatives::scharr_horizontal(&evolutions[i].Lsmooth, 1, self.border_mode);
             trace!("Computing derivative Lx done.");
-            evolutions[i].Ly = derivatives::scharr_vertical(&evolutions[i].Lsmooth, 1);
+            evolutions[i].Ly =
+                derivatives::scharr_vertical(&evolutions[i].Lsmooth, 1, self.border_mode);
             trace!("Computing derivative Ly done.");
             evolutions[i].Lflow = pm_g2(&evolutions[i].Lx, &evolutions[i].Ly, contrast_factor);
             trace!("Lflow finished.");
             for j in 0..evolutions[i].fed_tau_steps.len() {
                 trace!("Starting diffusion step.");
                 let step_size = evolutions[i].fed_tau_steps[j];
-                nonlinear_diffusion::calculate_step(&mut evolutions[i], step_size as f32);
+                nonlinear_diffusion::calculate_step(
+                    &mut evolutions[i],
+                    step_size as f32,
+                    self.border_mode,
+                );
                 trace!("Diffusion step finished with step size {}", step_size);
             }
         }
@@ -202,10 +237,17 @@ impl Akaze {
     /// # Return Value
     /// The resulting keypoints.
     ///
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "akaze::detect", skip_all, fields(keypoints))
+    )]
     fn find_image_keypoints(&self, evolutions: &mut Vec<EvolutionStep>) -> Vec<KeyPoint> {
         self.detector_response(evolutions);
         trace!("Computing detector response finished.");
-        self.detect_keypoints(evolutions)
+        let keypoints = self.detect_keypoints(evolutions);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("keypoints", &keypoints.len());
+        keypoints
     }
 
     /// Extract features using the Akaze feature extractor.
@@ -229,8 +271,26 @@ impl Akaze {
     /// ```
     ///
     pub fn extract(&self, image: &DynamicImage) -> (Vec<KeyPoint>, Vec<BitArray<64>>) {
-        let float_image = GrayFloatImage::from_dynamic(image);
-        let mut evolutions = self.allocate_evolutions(image.width(), image.height());
+        self.extract_gray(GrayFloatImage::from_dynamic_with(
+            image,
+            self.gray_conversion,
+        ))
+    }
+
+    /// Extract features from a gray image with pixels between 0 and 1.
+    ///
+    /// This skips the conversion to gray, so images from other sources, such as raw 16-bit sensor data loaded
+    /// with [`GrayFloatImage::from_raw_u16`], can be used directly. The preprocessing is still applied.
+    ///
+    /// Returns the keypoints and the descriptors.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "akaze::extract", skip_all, fields(width = image.width(), height = image.height(), keypoints))
+    )]
+    pub fn extract_gray(&self, image: GrayFloatImage) -> (Vec<KeyPoint>, Vec<BitArray<64>>) {
+        let float_image = self.preprocess(image);
+        let mut evolutions =
+            self.allocate_evolutions(float_image.width() as u32, float_image.height() as u32);
         self.create_nonlinear_scale_space(&mut evolutions, &float_image);
         trace!("Finding image keypoints.");
         let keypoints = self.find_image_keypoints(&mut evolutions);
@@ -238,9 +298,28 @@ impl Akaze {
         let descriptors = self.extract_descriptors(&evolutions, &keypoints);
         trace!("Computing descriptors finished.");
         info!("Extracted {} features", keypoints.len());
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("keypoints", &keypoints.len());
         (keypoints, descriptors)
     }
 
+    /// Apply the optional preprocessing to an image before extraction.
+    ///
+    /// This applies the [`Akaze::photometric_normalization`] followed by the [`Akaze::clahe`], if they are set.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "akaze::preprocess", skip_all)
+    )]
+    pub fn preprocess(&self, mut image: GrayFloatImage) -> GrayFloatImage {
+        if let Some(normalization) = self.photometric_normalization {
+            normalization.apply(&mut image);
+        }
+        if let Some(clahe) = self.clahe {
+            image = clahe.apply(&image);
+        }
+        image
+    }
+
     /// Extract features using the Akaze feature extractor from an image on disk.
     ///
     /// This performs all operations end-to-end. The client might be only interested
@@ -1,4 +1,4 @@
-use crate::{EvolutionStep, GrayFloatImage};
+use crate::{image::BorderMode, EvolutionStep, GrayFloatImage};
 use ndarray::{azip, s, Array2};
 
 /// This function performs a scalar non-linear diffusion step.
@@ -8,14 +8,42 @@ use ndarray::{azip, s, Array2};
 /// * `c` - Conductivity image. The function c is a scalar value that depends on the gradient norm
 /// * `Lstep` - Previous image in the evolution
 /// * `step_size` - The step size in time units
+/// * `border` - How the pixels beyond the border are handled. Replicating the edge pixels means no flow
+///   crosses the border, while reflected and constant pixels beyond the border exchange flow with the edge pixels.
 /// Forward Euler Scheme 3x3 stencil
 /// dL_by_ds = d(c dL_by_dx)_by_dx + d(c dL_by_dy)_by_dy
 #[allow(non_snake_case)]
-pub fn calculate_step(evolution_step: &mut EvolutionStep, step_size: f32) {
+pub fn calculate_step(evolution_step: &mut EvolutionStep, step_size: f32, border: BorderMode) {
     // Get the ndarray types.
     let mut input = evolution_step.Lt.mut_array2();
     let conductivities = evolution_step.Lflow.ref_array2();
     let dim = input.dim();
+    // The flow from the pixels beyond the border into the edge pixels, with the value and conductivity of the pixel
+    // beyond the border taken from the pixel `inner` for reflection.
+    let mut border_flows: Vec<((usize, usize), f32)> = vec![];
+    let (height, width) = dim;
+    let mut add_border_flow = |edge: (usize, usize), inner: (usize, usize)| {
+        let (value, conductivity) = (input[edge], conductivities[edge]);
+        let (outer_value, outer_conductivity) = match border {
+            BorderMode::Extend | BorderMode::Replicate => return,
+            BorderMode::Reflect => (input[inner], conductivities[inner]),
+            BorderMode::Constant(outer_value) => (outer_value, conductivity),
+        };
+        border_flows.push((
+            edge,
+            step_size * conductivity * outer_conductivity * (outer_value - value),
+        ));
+    };
+    if width > 1 && height > 1 {
+        for y in 0..height {
+            add_border_flow((y, 0), (y, 1));
+            add_border_flow((y, width - 1), (y, width - 2));
+        }
+        for x in 0..width {
+            add_border_flow((0, x), (1, x));
+            add_border_flow((height - 1, x), (height - 2, x));
+        }
+    }
     // Horizontal flow.
     let mut horizontal_flow = Array2::<f32>::zeros((dim.0, dim.1 - 1));
     azip!((
@@ -55,6 +83,10 @@ pub fn calculate_step(evolution_step: &mut EvolutionStep, step_size: f32) {
     input
         .slice_mut(s![1.., ..])
         .zip_mut_with(&vertical_flow, |acc, &i| *acc -= i);
+    // Across the border
+    for (pixel, flow) in border_flows {
+        input[pixel] += flow;
+    }
 }
 
 /// This function computes the Perona and Malik conductivity coefficient g2
@@ -0,0 +1,122 @@
+/*
+ * C API of Rust CV.
+ *
+ * This header is maintained by hand and only changes in backwards compatible
+ * ways. Link against the `cv_capi` static or shared library.
+ *
+ * Handles returned by this API must be freed with their `_free` function.
+ * Poses map points from the first frame into the second, and their rotation
+ * is stored row by row.
+ */
+
+#ifndef CV_H
+#define CV_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct CvPoint2 {
+    double x;
+    double y;
+} CvPoint2;
+
+typedef struct CvPoint3 {
+    double x;
+    double y;
+    double z;
+} CvPoint3;
+
+/* The intrinsics of a pinhole camera in pixels. */
+typedef struct CvIntrinsics {
+    double fx;
+    double fy;
+    double cx;
+    double cy;
+    double skew;
+} CvIntrinsics;
+
+typedef struct CvPose {
+    double rotation[9];
+    double translation[3];
+} CvPose;
+
+typedef struct CvKeypoint {
+    float x;
+    float y;
+    float size;
+    float angle;
+    float response;
+    uint32_t octave;
+} CvKeypoint;
+
+typedef struct CvMatch {
+    uint32_t first;
+    uint32_t second;
+} CvMatch;
+
+typedef struct CvFeatures CvFeatures;
+typedef struct CvMap CvMap;
+
+/* Feature extraction and matching */
+
+/* Extracts AKAZE features from an 8-bit gray image. Returns NULL on failure. */
+CvFeatures *cv_extract_gray8(const uint8_t *pixels, size_t width, size_t height,
+                             size_t stride, double threshold);
+size_t cv_features_len(const CvFeatures *features);
+/* Valid until the features are freed. */
+const CvKeypoint *cv_features_keypoints(const CvFeatures *features);
+/* 64 bytes per feature, valid until the features are freed. */
+const uint8_t *cv_features_descriptors(const CvFeatures *features);
+void cv_features_free(CvFeatures *features);
+
+/* Writes at most `capacity` matches which pass the ratio test and returns how
+ * many were written. */
+size_t cv_match_descriptors(const CvFeatures *first, const CvFeatures *second,
+                            float ratio, CvMatch *out_matches, size_t capacity);
+
+/* Pose estimation
+ *
+ * `out_inliers` may be NULL. Otherwise it receives `len` bytes which are 1 for
+ * inliers and 0 for outliers. */
+
+/* Estimates the pose of the second camera relative to the first, with a unit
+ * translation. */
+bool cv_estimate_relative_pose(CvIntrinsics intrinsics, const CvPoint2 *first,
+                               const CvPoint2 *second, size_t len,
+                               double threshold, CvPose *out_pose,
+                               uint8_t *out_inliers);
+
+/* Estimates the pose which maps world points into the camera. */
+bool cv_estimate_absolute_pose(CvIntrinsics intrinsics, const CvPoint3 *world,
+                               const CvPoint2 *image, size_t len,
+                               double threshold, CvPose *out_pose,
+                               uint8_t *out_inliers);
+
+/* Maps */
+
+/* Returns NULL if the file can't be read. */
+CvMap *cv_map_load(const char *path);
+bool cv_map_save(const CvMap *map, const char *path);
+void cv_map_free(CvMap *map);
+
+size_t cv_map_reconstruction_count(const CvMap *map);
+size_t cv_map_view_count(const CvMap *map, size_t reconstruction);
+size_t cv_map_landmark_count(const CvMap *map, size_t reconstruction);
+/* The pose which maps world points into the camera of the view. */
+bool cv_map_view_pose(const CvMap *map, size_t reconstruction, size_t view,
+                      CvPose *out_pose);
+/* Writes at most `capacity` triangulated landmarks, NaN where triangulation
+ * fails, and returns how many were written. */
+size_t cv_map_landmarks(const CvMap *map, size_t reconstruction,
+                        CvPoint3 *out_points, size_t capacity);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CV_H */
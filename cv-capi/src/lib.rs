@@ -0,0 +1,527 @@
+//! # `cv-capi`
+//!
+//! A C API for feature extraction, descriptor matching, relative and absolute pose estimation, and saving and
+//! loading maps, so that existing C and C++ systems can adopt Rust CV one component at a time.
+//!
+//! The API is declared in `include/cv.h`, which is maintained by hand so that it stays stable. Objects which own
+//! memory, such as [`CvFeatures`] and [`CvMap`], are opaque and must be freed with their `_free` function. Plain data,
+//! such as points and poses, is passed in `#[repr(C)]` structs.
+//!
+//! ## Safety
+//!
+//! Every pointer must either be null where that is documented as allowed, or point to valid memory of the documented
+//! length. Handles must come from this library and must not be used after they are freed. Panics are caught at the
+//! boundary and reported like any other failure, so they never unwind into C.
+
+#![allow(clippy::missing_safety_doc)]
+
+use akaze::{image::GrayFloatImage, Akaze, KeyPoint};
+use arrsac::Arrsac;
+use bitarray::{BitArray, Hamming};
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point2, Point3, Vector2},
+    sample_consensus::{Consensus, Estimator},
+    CameraModel, FeatureMatch, FeatureWorldMatch, Pose, Projective, TriangulatorObservations,
+    WorldPoint,
+};
+use cv_geom::triangulation::LinearEigenTriangulator;
+use cv_pinhole::CameraIntrinsics;
+use cv_sfm::VSlamData;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use space::Knn;
+use std::{
+    ffi::CStr,
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr, slice,
+};
+
+/// A pixel position.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CvPoint2 {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A point in space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CvPoint3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// The intrinsics of a pinhole camera in pixels.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CvIntrinsics {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    pub skew: f64,
+}
+
+/// A rigid transformation with a row-major rotation matrix.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CvPose {
+    pub rotation: [f64; 9],
+    pub translation: [f64; 3],
+}
+
+/// A keypoint in pixels.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CvKeypoint {
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+    pub angle: f32,
+    pub response: f32,
+    pub octave: u32,
+}
+
+/// A match between the features at index `first` and `second` of two sets of features.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CvMatch {
+    pub first: u32,
+    pub second: u32,
+}
+
+/// The keypoints and descriptors extracted from an image.
+pub struct CvFeatures {
+    keypoints: Vec<CvKeypoint>,
+    descriptors: Vec<BitArray<64>>,
+}
+
+/// A saved map of [`cv_sfm`].
+pub struct CvMap(VSlamData);
+
+/// Runs `f`, returning `default` if it panics.
+///
+/// Nothing is observed after a panic except the output buffers, which the caller must treat as unspecified.
+fn guard<T>(default: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(default)
+}
+
+impl From<CvIntrinsics> for CameraIntrinsics {
+    fn from(intrinsics: CvIntrinsics) -> Self {
+        Self {
+            focals: Vector2::new(intrinsics.fx, intrinsics.fy),
+            principal_point: Point2::new(intrinsics.cx, intrinsics.cy),
+            skew: intrinsics.skew,
+        }
+    }
+}
+
+impl From<IsometryMatrix3<f64>> for CvPose {
+    fn from(isometry: IsometryMatrix3<f64>) -> Self {
+        let rotation = isometry.rotation.matrix();
+        let translation = isometry.translation.vector;
+        let mut pose = Self {
+            rotation: [0.0; 9],
+            translation: [translation.x, translation.y, translation.z],
+        };
+        for row in 0..3 {
+            for column in 0..3 {
+                pose.rotation[3 * row + column] = rotation[(row, column)];
+            }
+        }
+        pose
+    }
+}
+
+fn keypoint(point: CvPoint2) -> cv_core::KeyPoint {
+    cv_core::KeyPoint(Point2::new(point.x, point.y))
+}
+
+/// Writes whether each of `len` data points is an inlier to `out_inliers`, unless it is null.
+unsafe fn write_inliers(
+    out_inliers: *mut u8,
+    len: usize,
+    inliers: impl IntoIterator<Item = usize>,
+) {
+    if out_inliers.is_null() {
+        return;
+    }
+    let out = slice::from_raw_parts_mut(out_inliers, len);
+    out.iter_mut().for_each(|inlier| *inlier = 0);
+    for ix in inliers {
+        out[ix] = 1;
+    }
+}
+
+/// Runs ARRSAC with a fixed seed, so the results are reproducible.
+fn consensus<E, Data>(
+    estimator: &E,
+    threshold: f64,
+    data: &[Data],
+) -> Option<(E::Model, Vec<usize>)>
+where
+    E: Estimator<Data>,
+    Data: Clone,
+{
+    Arrsac::new(threshold, Pcg64::from_seed([1; 32]))
+        .model_inliers(estimator, data.iter().cloned())
+        .map(|(model, inliers)| (model, inliers.into_iter().collect()))
+}
+
+/// Extracts AKAZE features from an 8-bit gray image whose rows start `stride` bytes apart.
+///
+/// Returns null if the stride is smaller than the width or extraction fails.
+#[no_mangle]
+pub unsafe extern "C" fn cv_extract_gray8(
+    pixels: *const u8,
+    width: usize,
+    height: usize,
+    stride: usize,
+    threshold: f64,
+) -> *mut CvFeatures {
+    if pixels.is_null() || stride < width {
+        return ptr::null_mut();
+    }
+    let len = if height == 0 {
+        0
+    } else {
+        (height - 1) * stride + width
+    };
+    let pixels = slice::from_raw_parts(pixels, len);
+    guard(ptr::null_mut(), move || {
+        let mut image = GrayFloatImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.put(x, y, f32::from(pixels[y * stride + x]) / 255.0);
+            }
+        }
+        let (keypoints, descriptors) = Akaze::new(threshold).extract_gray(image);
+        let keypoints = keypoints
+            .iter()
+            .map(|keypoint: &KeyPoint| CvKeypoint {
+                x: keypoint.point.0,
+                y: keypoint.point.1,
+                size: keypoint.size,
+                angle: keypoint.angle,
+                response: keypoint.response,
+                octave: keypoint.octave as u32,
+            })
+            .collect();
+        Box::into_raw(Box::new(CvFeatures {
+            keypoints,
+            descriptors,
+        }))
+    })
+}
+
+/// The number of features.
+#[no_mangle]
+pub unsafe extern "C" fn cv_features_len(features: *const CvFeatures) -> usize {
+    features
+        .as_ref()
+        .map_or(0, |features| features.keypoints.len())
+}
+
+/// The keypoints of the features, which stay valid until the features are freed.
+#[no_mangle]
+pub unsafe extern "C" fn cv_features_keypoints(features: *const CvFeatures) -> *const CvKeypoint {
+    features
+        .as_ref()
+        .map_or(ptr::null(), |features| features.keypoints.as_ptr())
+}
+
+/// The 64-byte descriptors of the features one after another, which stay valid until the features are freed.
+#[no_mangle]
+pub unsafe extern "C" fn cv_features_descriptors(features: *const CvFeatures) -> *const u8 {
+    features.as_ref().map_or(ptr::null(), |features| {
+        features.descriptors.as_ptr() as *const u8
+    })
+}
+
+/// Frees features returned by [`cv_extract_gray8`]. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn cv_features_free(features: *mut CvFeatures) {
+    if !features.is_null() {
+        drop(Box::from_raw(features));
+    }
+}
+
+/// Matches every feature of `first` to its nearest neighbor in `second` by Hamming distance, if the distance is
+/// below `ratio` times the distance to the second nearest neighbor.
+///
+/// At most `capacity` matches are written to `out_matches`, and a capacity of `cv_features_len(first)` is always
+/// enough. Returns the number of matches written.
+#[no_mangle]
+pub unsafe extern "C" fn cv_match_descriptors(
+    first: *const CvFeatures,
+    second: *const CvFeatures,
+    ratio: f32,
+    out_matches: *mut CvMatch,
+    capacity: usize,
+) -> usize {
+    let (first, second) = match (first.as_ref(), second.as_ref()) {
+        (Some(first), Some(second)) if !out_matches.is_null() => (first, second),
+        _ => return 0,
+    };
+    let out = slice::from_raw_parts_mut(out_matches, capacity);
+    guard(0, move || {
+        let matches = first
+            .descriptors
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, descriptor)| {
+                let neighbors = space::LinearKnn {
+                    metric: Hamming,
+                    iter: second.descriptors.iter(),
+                }
+                .knn(descriptor, 2);
+                match neighbors.as_slice() {
+                    [best, next] if (best.distance as f32) < next.distance as f32 * ratio => {
+                        Some(CvMatch {
+                            first: ix as u32,
+                            second: best.index as u32,
+                        })
+                    }
+                    _ => None,
+                }
+            });
+        let mut written = 0;
+        for (out, found) in out.iter_mut().zip(matches) {
+            *out = found;
+            written += 1;
+        }
+        written
+    })
+}
+
+/// Estimates the pose of the second camera relative to the first from `len` matching pixel positions with the
+/// eight-point algorithm and ARRSAC. The translation has unit length.
+///
+/// If `out_inliers` isn't null, it receives `len` bytes which are 1 for inliers and 0 for outliers. Returns whether a
+/// pose was found.
+#[no_mangle]
+pub unsafe extern "C" fn cv_estimate_relative_pose(
+    intrinsics: CvIntrinsics,
+    first: *const CvPoint2,
+    second: *const CvPoint2,
+    len: usize,
+    threshold: f64,
+    out_pose: *mut CvPose,
+    out_inliers: *mut u8,
+) -> bool {
+    if first.is_null() || second.is_null() || out_pose.is_null() {
+        return false;
+    }
+    let first = slice::from_raw_parts(first, len);
+    let second = slice::from_raw_parts(second, len);
+    let intrinsics = CameraIntrinsics::from(intrinsics);
+    let estimate = guard(None, move || {
+        let matches: Vec<FeatureMatch> = first
+            .iter()
+            .zip(second)
+            .map(|(&a, &b)| {
+                FeatureMatch(
+                    intrinsics.calibrate(keypoint(a)),
+                    intrinsics.calibrate(keypoint(b)),
+                )
+            })
+            .collect();
+        consensus(&eight_point::EightPoint::new(), threshold, &matches)
+    });
+    match estimate {
+        Some((pose, inliers)) => {
+            let mut isometry = pose.isometry();
+            isometry.translation.vector = isometry.translation.vector.normalize();
+            *out_pose = isometry.into();
+            write_inliers(out_inliers, len, inliers);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Estimates the pose which maps world points into a camera from `len` world points and their pixel positions with
+/// Lambda Twist and ARRSAC.
+///
+/// If `out_inliers` isn't null, it receives `len` bytes which are 1 for inliers and 0 for outliers. Returns whether a
+/// pose was found.
+#[no_mangle]
+pub unsafe extern "C" fn cv_estimate_absolute_pose(
+    intrinsics: CvIntrinsics,
+    world: *const CvPoint3,
+    image: *const CvPoint2,
+    len: usize,
+    threshold: f64,
+    out_pose: *mut CvPose,
+    out_inliers: *mut u8,
+) -> bool {
+    if world.is_null() || image.is_null() || out_pose.is_null() {
+        return false;
+    }
+    let world = slice::from_raw_parts(world, len);
+    let image = slice::from_raw_parts(image, len);
+    let intrinsics = CameraIntrinsics::from(intrinsics);
+    let estimate = guard(None, move || {
+        let matches: Vec<FeatureWorldMatch> = world
+            .iter()
+            .zip(image)
+            .map(|(point, &pixel)| {
+                FeatureWorldMatch(
+                    intrinsics.calibrate(keypoint(pixel)),
+                    WorldPoint::from_point(Point3::new(point.x, point.y, point.z)),
+                )
+            })
+            .collect();
+        consensus(&lambda_twist::LambdaTwist::new(), threshold, &matches)
+    });
+    match estimate {
+        Some((pose, inliers)) => {
+            *out_pose = pose.isometry().into();
+            write_inliers(out_inliers, len, inliers);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Loads a map saved by `cv-sfm` or [`cv_map_save`] from a file.
+///
+/// Returns null if the file can't be read.
+#[no_mangle]
+pub unsafe extern "C" fn cv_map_load(path: *const c_char) -> *mut CvMap {
+    if path.is_null() {
+        return ptr::null_mut();
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(_) => return ptr::null_mut(),
+    };
+    guard(ptr::null_mut(), move || match VSlamData::load_path(path) {
+        Ok(data) => Box::into_raw(Box::new(CvMap(data))),
+        Err(_) => ptr::null_mut(),
+    })
+}
+
+/// Saves a map to a file. Returns whether it was saved.
+#[no_mangle]
+pub unsafe extern "C" fn cv_map_save(map: *const CvMap, path: *const c_char) -> bool {
+    let map = match map.as_ref() {
+        Some(map) if !path.is_null() => map,
+        _ => return false,
+    };
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    guard(false, move || map.0.save_path(path).is_ok())
+}
+
+/// Frees a map returned by [`cv_map_load`]. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn cv_map_free(map: *mut CvMap) {
+    if !map.is_null() {
+        drop(Box::from_raw(map));
+    }
+}
+
+/// The number of reconstructions in a map.
+#[no_mangle]
+pub unsafe extern "C" fn cv_map_reconstruction_count(map: *const CvMap) -> usize {
+    map.as_ref()
+        .map_or(0, |map| map.0.reconstructions().count())
+}
+
+/// The number of views in a reconstruction of a map, or 0 if there is no such reconstruction.
+#[no_mangle]
+pub unsafe extern "C" fn cv_map_view_count(map: *const CvMap, reconstruction: usize) -> usize {
+    map.as_ref()
+        .and_then(|map| {
+            let key = map.0.reconstructions().nth(reconstruction)?;
+            Some(map.0.reconstruction(key).views.len())
+        })
+        .unwrap_or(0)
+}
+
+/// Writes the pose which maps world points into the camera of a view of a reconstruction to `out_pose`.
+///
+/// Returns whether there is such a view.
+#[no_mangle]
+pub unsafe extern "C" fn cv_map_view_pose(
+    map: *const CvMap,
+    reconstruction: usize,
+    view: usize,
+    out_pose: *mut CvPose,
+) -> bool {
+    let pose = map.as_ref().and_then(|map| {
+        let key = map.0.reconstructions().nth(reconstruction)?;
+        map.0
+            .reconstruction(key)
+            .views
+            .values()
+            .nth(view)
+            .map(|view| view.pose)
+    });
+    match pose {
+        Some(pose) if !out_pose.is_null() => {
+            *out_pose = pose.isometry().into();
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The number of landmarks in a reconstruction of a map, or 0 if there is no such reconstruction.
+#[no_mangle]
+pub unsafe extern "C" fn cv_map_landmark_count(map: *const CvMap, reconstruction: usize) -> usize {
+    map.as_ref()
+        .and_then(|map| {
+            let key = map.0.reconstructions().nth(reconstruction)?;
+            Some(map.0.reconstruction(key).landmarks.len())
+        })
+        .unwrap_or(0)
+}
+
+/// Triangulates the landmarks of a reconstruction of a map from their observations and writes at most `capacity`
+/// of them to `out_points`. Landmarks which can't be triangulated are written as `NaN`.
+///
+/// Returns the number of landmarks written.
+#[no_mangle]
+pub unsafe extern "C" fn cv_map_landmarks(
+    map: *const CvMap,
+    reconstruction: usize,
+    out_points: *mut CvPoint3,
+    capacity: usize,
+) -> usize {
+    let map = match map.as_ref() {
+        Some(map) if !out_points.is_null() => map,
+        _ => return 0,
+    };
+    let out = slice::from_raw_parts_mut(out_points, capacity);
+    guard(0, move || {
+        let key = match map.0.reconstructions().nth(reconstruction) {
+            Some(key) => key,
+            None => return 0,
+        };
+        let triangulator = LinearEigenTriangulator::new();
+        let mut written = 0;
+        for (out, landmark) in out
+            .iter_mut()
+            .zip(map.0.reconstruction(key).landmarks.keys())
+        {
+            let point = triangulator
+                .triangulate_observations(map.0.landmark_pose_bearings(key, landmark))
+                .and_then(|point| point.point())
+                .unwrap_or_else(|| Point3::new(f64::NAN, f64::NAN, f64::NAN));
+            *out = CvPoint3 {
+                x: point.x,
+                y: point.y,
+                z: point.z,
+            };
+            written += 1;
+        }
+        written
+    })
+}
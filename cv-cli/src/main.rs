@@ -0,0 +1,446 @@
+use arrsac::Arrsac;
+use bitarray::Hamming;
+use cv_core::{
+    nalgebra::{Point2, Vector2},
+    sample_consensus::Consensus,
+    CameraModel, FeatureMatch, Pose,
+};
+use cv_geom::triangulation::LinearEigenTriangulator;
+use cv_pinhole::CameraIntrinsics;
+use cv_sfm::{
+    matching::{match_all_pairs, BruteForceMatcher, DescriptorMatch, MatchMatrix, Matcher},
+    ImageFeatures, IncrementalSfm, PointCloudEncoding,
+};
+use eight_point::EightPoint;
+use lambda_twist::LambdaTwist;
+use log::*;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use rayon::prelude::*;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// The extensions of the files in an image folder which are read as images.
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+/// The extension of saved [`ImageFeatures`].
+const FEATURES_EXTENSION: &str = "cvft";
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "cv",
+    about = "Feature extraction, matching, two-view estimation and reconstruction with Rust CV"
+)]
+struct Opt {
+    /// The seed of the random number generators of the consensus algorithms.
+    ///
+    /// The results are the same on every run with the same seed.
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Extracts AKAZE features from every image in a folder.
+    ///
+    /// The features of `image.jpg` are saved as `image.jpg.cvft` in the output folder.
+    Extract {
+        /// The folder of images.
+        #[structopt(parse(from_os_str))]
+        images: PathBuf,
+        /// The folder where the features are saved.
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+        #[structopt(flatten)]
+        extraction: ExtractionOpt,
+    },
+    /// Matches the features of every pair of images in a folder of features saved by `extract`.
+    ///
+    /// The images are numbered in the order of the names of their feature files.
+    Match {
+        /// The folder of features.
+        #[structopt(parse(from_os_str))]
+        features: PathBuf,
+        /// The file where the matches are saved.
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+        #[structopt(flatten)]
+        matching: MatchingOpt,
+    },
+    /// Estimates the relative pose between two images and prints it.
+    ///
+    /// The translation has unit length, since the scale can't be recovered from two images.
+    TwoView {
+        #[structopt(parse(from_os_str))]
+        first: PathBuf,
+        #[structopt(parse(from_os_str))]
+        second: PathBuf,
+        /// The file where the inlier matches are saved, with the first image as image 0.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// The inlier threshold of the essential matrix in sine distance.
+        #[structopt(long, default_value = "5e-4")]
+        essential_threshold: f64,
+        #[structopt(flatten)]
+        camera: CameraOpt,
+        #[structopt(flatten)]
+        extraction: ExtractionOpt,
+        #[structopt(flatten)]
+        matching: MatchingOpt,
+    },
+    /// Reconstructs the poses and a sparse point cloud of a folder of images taken with the same camera.
+    ///
+    /// The output folder receives the COLMAP binary model (`cameras.bin`, `images.bin` and `points3D.bin`) and the
+    /// point cloud as `points.ply`.
+    Reconstruct {
+        /// The folder of images.
+        #[structopt(parse(from_os_str))]
+        images: PathBuf,
+        /// The folder where the reconstruction is saved.
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+        /// The inlier threshold of the essential matrix in sine distance.
+        #[structopt(long, default_value = "5e-4")]
+        essential_threshold: f64,
+        /// The inlier threshold of PnP in cosine distance.
+        #[structopt(long, default_value = "5e-7")]
+        pnp_threshold: f64,
+        #[structopt(flatten)]
+        camera: CameraOpt,
+        #[structopt(flatten)]
+        extraction: ExtractionOpt,
+        #[structopt(flatten)]
+        matching: MatchingOpt,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct ExtractionOpt {
+    /// The AKAZE threshold to use.
+    ///
+    /// 0.01 will be very sparse and 0.0001 will be very dense.
+    #[structopt(short, long, default_value = "0.001")]
+    threshold: f64,
+    /// A folder of features saved by `extract`, which are used instead of extracting the features of an image again
+    /// when they exist.
+    #[structopt(long, parse(from_os_str))]
+    features: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct MatchingOpt {
+    /// The ratio of the distances of the best and second best match above which a match is rejected.
+    #[structopt(long, default_value = "0.8")]
+    ratio: f32,
+}
+
+#[derive(Debug, StructOpt)]
+struct CameraOpt {
+    /// The focal length in pixels.
+    ///
+    /// Defaults to the larger side of the image, which is a horizontal field of view of about 53 degrees for
+    /// landscape images.
+    #[structopt(long)]
+    focal: Option<f64>,
+    /// The x coordinate of the principal point in pixels, which defaults to the center of the image.
+    #[structopt(long)]
+    x_center: Option<f64>,
+    /// The y coordinate of the principal point in pixels, which defaults to the center of the image.
+    #[structopt(long)]
+    y_center: Option<f64>,
+}
+
+impl CameraOpt {
+    fn intrinsics(&self, (width, height): (u32, u32)) -> CameraIntrinsics {
+        let focal = self.focal.unwrap_or_else(|| f64::from(width.max(height)));
+        CameraIntrinsics {
+            focals: Vector2::new(focal, focal),
+            principal_point: Point2::new(
+                self.x_center.unwrap_or(f64::from(width) / 2.0),
+                self.y_center.unwrap_or(f64::from(height) / 2.0),
+            ),
+            skew: 0.0,
+        }
+    }
+}
+
+impl ExtractionOpt {
+    /// Loads the cached features of an image if there are any, and extracts them otherwise.
+    fn features(&self, image: &Path) -> Result<ImageFeatures> {
+        if let Some(cached) = self
+            .features
+            .as_ref()
+            .map(|dir| features_path(dir, image))
+            .filter(|path| path.is_file())
+        {
+            info!("loading cached features {}", cached.display());
+            return Ok(ImageFeatures::load_path(cached)?);
+        }
+        self.extract(image)
+    }
+
+    fn extract(&self, image: &Path) -> Result<ImageFeatures> {
+        let features: ImageFeatures = akaze::Akaze::new(self.threshold)
+            .extract_path(image)?
+            .into();
+        info!(
+            "extracted {} features from {}",
+            features.len(),
+            image.display()
+        );
+        Ok(features)
+    }
+}
+
+impl MatchingOpt {
+    fn matcher(&self) -> BruteForceMatcher<Hamming> {
+        BruteForceMatcher::new(Hamming).ratio(self.ratio)
+    }
+}
+
+/// The files in a folder with one of the extensions, sorted by name.
+fn files_with_extension(dir: &Path, extensions: &[&str]) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let matches = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extensions.contains(&extension.to_ascii_lowercase().as_str()))
+            .unwrap_or(false);
+        if matches && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// The path of the saved features of an image in a folder of features.
+fn features_path(dir: &Path, image: &Path) -> PathBuf {
+    let mut name = image.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(FEATURES_EXTENSION);
+    dir.join(name)
+}
+
+fn extract(images: &Path, output: &Path, extraction: &ExtractionOpt) -> Result<()> {
+    let images = files_with_extension(images, IMAGE_EXTENSIONS)?;
+    fs::create_dir_all(output)?;
+    images.par_iter().try_for_each(|image| -> Result<()> {
+        extraction
+            .features(image)?
+            .save_path(features_path(output, image))?;
+        Ok(())
+    })
+}
+
+fn match_features(features: &Path, output: &Path, matching: &MatchingOpt) -> Result<()> {
+    let paths = files_with_extension(features, &[FEATURES_EXTENSION])?;
+    let features = paths
+        .par_iter()
+        .map(|path| -> Result<ImageFeatures> { Ok(ImageFeatures::load_path(path)?) })
+        .collect::<Result<Vec<_>>>()?;
+    for (ix, path) in paths.iter().enumerate() {
+        info!("image {} is {}", ix, path.display());
+    }
+    let descriptors: Vec<_> = features
+        .iter()
+        .map(|features| features.descriptors.as_slice())
+        .collect();
+    let matches = match_all_pairs(&matching.matcher(), &descriptors);
+    info!(
+        "matched {} pairs of {} images",
+        matches.pairs.len(),
+        features.len()
+    );
+    matches.save_path(output)?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn two_view(
+    seed: u64,
+    first: &Path,
+    second: &Path,
+    output: Option<&Path>,
+    essential_threshold: f64,
+    camera: &CameraOpt,
+    extraction: &ExtractionOpt,
+    matching: &MatchingOpt,
+) -> Result<()> {
+    let first_intrinsics = camera.intrinsics(image::image_dimensions(first)?);
+    let second_intrinsics = camera.intrinsics(image::image_dimensions(second)?);
+    let first_features = extraction.features(first)?;
+    let second_features = extraction.features(second)?;
+    let matches = matching
+        .matcher()
+        .match_descriptors(&first_features.descriptors, &second_features.descriptors);
+    let (pose, inliers) = Arrsac::new(essential_threshold, Pcg64::seed_from_u64(seed))
+        .model_inliers(
+            &EightPoint::new(),
+            matches.iter().map(|m| {
+                FeatureMatch(
+                    first_intrinsics.calibrate(first_features.keypoints[m.query]),
+                    second_intrinsics.calibrate(second_features.keypoints[m.target]),
+                )
+            }),
+        )
+        .ok_or("failed to estimate the relative pose")?;
+    let inliers: Vec<DescriptorMatch> = inliers.into_iter().map(|ix| matches[ix]).collect();
+    let isometry = pose.isometry();
+    let rotation = isometry.rotation.matrix();
+    let translation = isometry.translation.vector.normalize();
+    println!("matches: {}", matches.len());
+    println!("inliers: {}", inliers.len());
+    println!(
+        "rotation: [[{}, {}, {}], [{}, {}, {}], [{}, {}, {}]]",
+        rotation[(0, 0)],
+        rotation[(0, 1)],
+        rotation[(0, 2)],
+        rotation[(1, 0)],
+        rotation[(1, 1)],
+        rotation[(1, 2)],
+        rotation[(2, 0)],
+        rotation[(2, 1)],
+        rotation[(2, 2)]
+    );
+    println!(
+        "translation: [{}, {}, {}]",
+        translation.x, translation.y, translation.z
+    );
+    if let Some(output) = output {
+        MatchMatrix {
+            num_images: 2,
+            pairs: std::iter::once(((0, 1), inliers)).collect(),
+        }
+        .save_path(output)?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn reconstruct(
+    seed: u64,
+    images: &Path,
+    output: &Path,
+    essential_threshold: f64,
+    pnp_threshold: f64,
+    camera: &CameraOpt,
+    extraction: &ExtractionOpt,
+    matching: &MatchingOpt,
+) -> Result<()> {
+    let paths = files_with_extension(images, IMAGE_EXTENSIONS)?;
+    let first = paths
+        .first()
+        .ok_or("the image folder does not contain any images")?;
+    let (width, height) = image::image_dimensions(first)?;
+    let intrinsics = camera.intrinsics((width, height));
+    let images = paths
+        .par_iter()
+        .map(|path| -> Result<_> {
+            let features = extraction.features(path)?;
+            Ok(features.colored_sfm_image(&intrinsics, &image::open(path)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut sfm = IncrementalSfm::new(
+        matching.matcher(),
+        Arrsac::new(essential_threshold, Pcg64::seed_from_u64(seed)),
+        EightPoint::new(),
+        Arrsac::new(pnp_threshold, Pcg64::seed_from_u64(seed.wrapping_add(1))),
+        LambdaTwist::new(),
+        LinearEigenTriangulator::new(),
+    );
+    let reconstruction = sfm
+        .reconstruct(&images)
+        .ok_or("no pair of images could initialize the reconstruction")?;
+    info!(
+        "registered {} of {} images with {} points",
+        reconstruction.num_registered(),
+        images.len(),
+        reconstruction.points.len()
+    );
+
+    fs::create_dir_all(output)?;
+    reconstruction
+        .colmap_model(
+            &images,
+            &intrinsics,
+            u64::from(width),
+            u64::from(height),
+            |image| {
+                paths[image]
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            },
+        )
+        .save_binary(output)?;
+    reconstruction
+        .point_cloud(None)
+        .save_ply(output.join("points.ply"), PointCloudEncoding::Binary)?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    pretty_env_logger::init_timed();
+    cv_core::set_deterministic(true);
+    let Opt { seed, command } = Opt::from_args();
+    match command {
+        Command::Extract {
+            images,
+            output,
+            extraction,
+        } => extract(&images, &output, &extraction),
+        Command::Match {
+            features,
+            output,
+            matching,
+        } => match_features(&features, &output, &matching),
+        Command::TwoView {
+            first,
+            second,
+            output,
+            essential_threshold,
+            camera,
+            extraction,
+            matching,
+        } => two_view(
+            seed,
+            &first,
+            &second,
+            output.as_deref(),
+            essential_threshold,
+            &camera,
+            &extraction,
+            &matching,
+        ),
+        Command::Reconstruct {
+            images,
+            output,
+            essential_threshold,
+            pnp_threshold,
+            camera,
+            extraction,
+            matching,
+        } => reconstruct(
+            seed,
+            &images,
+            &output,
+            essential_threshold,
+            pnp_threshold,
+            &camera,
+            &extraction,
+            &matching,
+        ),
+    }
+}
@@ -0,0 +1,513 @@
+//! # `cv-consensus`
+//!
+//! Configurable sample consensus for estimating models from noisy data.
+//!
+//! [`Ransac`] implements the [`Consensus`] trait from `sample-consensus`, so it can be used anywhere
+//! `arrsac::Arrsac` is used. Unlike ARRSAC, every part of the consensus process can be configured,
+//! such as the [`Sampling`] strategy used to draw minimal samples, the [`Scoring`] of models,
+//! and [`LocalOptimization`]. [`PreemptiveRansac`] bounds the work done per estimate for hard real-time applications.
+
+mod preemptive;
+mod report;
+mod sampling;
+mod scoring;
+
+pub use preemptive::PreemptiveRansac;
+pub use report::{ConsensusReport, ReportingConsensus, ResidualStatistics, ScoreUpdate};
+pub use sampling::Sampling;
+pub use scoring::{Score, Scoring};
+
+use cv_core::sample_consensus::{Consensus, Estimator, Model};
+use log::*;
+use rand::Rng;
+use sampling::Sampler;
+
+/// Locally optimized RANSAC (LO-RANSAC) configuration.
+///
+/// Whenever a new best model is found, an inner consensus is run which only samples from the inliers of that model
+/// before the outer consensus continues. Samples drawn from inliers are far more likely to produce an accurate model
+/// than samples from all of the data, so this dramatically improves accuracy (particularly for essential matrices)
+/// at the same iteration budget.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LocalOptimization {
+    /// The number of inner samples drawn from the inliers each time a new best model is found.
+    pub iterations: usize,
+    /// The size of each inner sample as a multiple of the minimal sample size.
+    ///
+    /// Estimators which can make use of more than the minimal number of samples will fit a least-squares model.
+    /// Estimators which only use the minimal number of samples still benefit from sampling only from inliers.
+    pub sample_size_multiplier: usize,
+    /// The inlier threshold used in the inner consensus as a multiple of the regular inlier threshold.
+    ///
+    /// A slightly larger threshold lets the inner consensus pull in inliers that a noisy minimal model missed.
+    pub threshold_multiplier: f64,
+}
+
+impl Default for LocalOptimization {
+    fn default() -> Self {
+        Self {
+            iterations: 10,
+            sample_size_multiplier: 2,
+            threshold_multiplier: 2.0,
+        }
+    }
+}
+
+/// A configurable random sample consensus.
+///
+/// By default this is classic RANSAC: uniform sampling with models scored by their number of inliers,
+/// terminating adaptively once the desired `confidence` that a good model was found is reached.
+#[derive(Clone, Debug)]
+pub struct Ransac<R> {
+    /// The residual below which a data point is considered an inlier.
+    pub inlier_threshold: f64,
+    /// The maximum number of samples to draw.
+    pub max_iterations: usize,
+    /// The desired probability of having drawn at least one all-inlier sample before terminating.
+    pub confidence: f64,
+    /// The strategy used to draw minimal samples.
+    pub sampling: Sampling,
+    /// The method used to score models.
+    pub scoring: Scoring,
+    /// Enables LO-RANSAC when set.
+    pub local_optimization: Option<LocalOptimization>,
+    /// The random number generator used for sampling.
+    pub rng: R,
+}
+
+impl<R> Ransac<R>
+where
+    R: Rng,
+{
+    pub fn new(inlier_threshold: f64, rng: R) -> Self {
+        Self {
+            inlier_threshold,
+            max_iterations: 1000,
+            confidence: 0.999,
+            sampling: Sampling::Uniform,
+            scoring: Scoring::InlierCount,
+            local_optimization: None,
+            rng,
+        }
+    }
+
+    #[must_use]
+    pub fn inlier_threshold(self, inlier_threshold: f64) -> Self {
+        Self {
+            inlier_threshold,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self { confidence, ..self }
+    }
+
+    /// Uses PROSAC sampling, which requires the data to be sorted by decreasing quality.
+    ///
+    /// See [`Sampling::Prosac`].
+    #[must_use]
+    pub fn prosac(self) -> Self {
+        Self {
+            sampling: Sampling::Prosac,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn sampling(self, sampling: Sampling) -> Self {
+        Self { sampling, ..self }
+    }
+
+    #[must_use]
+    pub fn scoring(self, scoring: Scoring) -> Self {
+        Self { scoring, ..self }
+    }
+
+    /// Uses MSAC truncated quadratic scoring (see [`Scoring::Msac`]).
+    #[must_use]
+    pub fn msac(self) -> Self {
+        Self {
+            scoring: Scoring::Msac,
+            ..self
+        }
+    }
+
+    /// Uses MAGSAC-style marginalized scoring (see [`Scoring::Magsac`]).
+    #[must_use]
+    pub fn magsac(self) -> Self {
+        Self {
+            scoring: Scoring::Magsac,
+            ..self
+        }
+    }
+
+    /// Enables LO-RANSAC with the given configuration (see [`LocalOptimization`]).
+    #[must_use]
+    pub fn local_optimization(self, local_optimization: LocalOptimization) -> Self {
+        Self {
+            local_optimization: Some(local_optimization),
+            ..self
+        }
+    }
+
+    /// The number of iterations required to reach the desired confidence given the fraction of inliers found.
+    fn required_iterations(&self, inliers: usize, len: usize, sample_size: usize) -> usize {
+        let inlier_ratio = inliers as f64 / len as f64;
+        let all_inlier_probability = inlier_ratio.powi(sample_size as i32);
+        if all_inlier_probability >= 1.0 {
+            return 0;
+        }
+        if all_inlier_probability <= 0.0 {
+            return self.max_iterations;
+        }
+        let iterations = (1.0 - self.confidence).ln() / (1.0 - all_inlier_probability).ln();
+        if iterations.is_finite() {
+            (iterations.ceil() as usize).min(self.max_iterations)
+        } else {
+            self.max_iterations
+        }
+    }
+
+    fn score<M, Data>(&self, model: &M, data: &[Data], weights: Option<&[f64]>) -> Score
+    where
+        M: Model<Data>,
+    {
+        score_residuals(
+            self.scoring,
+            self.inlier_threshold,
+            data.iter().map(|point| model.residual(point)),
+            weights,
+        )
+    }
+
+    /// Runs consensus and returns the model along with a [`ConsensusReport`] for failure analysis.
+    ///
+    /// This is the same as [`Consensus::model_inliers`], but with a more detailed result.
+    pub fn model_report<E, Data, I>(
+        &mut self,
+        estimator: &E,
+        data: I,
+    ) -> Option<(E::Model, ConsensusReport)>
+    where
+        E: Estimator<Data>,
+        Data: Clone,
+        I: Iterator<Item = Data>,
+    {
+        let data: Vec<Data> = data.collect();
+        self.run(estimator, &data, None, 1, |ransac, samples| {
+            ransac.evaluate(estimator, &data, None, samples)
+        })
+    }
+
+    /// Runs consensus with hypotheses generated and scored in parallel with `rayon`.
+    ///
+    /// Each round draws one sample per thread, and then the models from every sample are estimated concurrently while
+    /// their residuals are evaluated over the data in parallel. This is much faster on large data sets (10k+ matches),
+    /// where evaluating residuals dominates. Local optimization and termination are still checked in sample order,
+    /// so this may draw up to a round of extra samples compared to [`Ransac::model_report`].
+    #[cfg(feature = "rayon")]
+    pub fn model_parallel<E, Data, I>(
+        &mut self,
+        estimator: &E,
+        data: I,
+    ) -> Option<(E::Model, ConsensusReport)>
+    where
+        E: Estimator<Data> + Sync,
+        E::Model: Send + Sync,
+        Data: Clone + Send + Sync,
+        I: Iterator<Item = Data>,
+    {
+        use rayon::prelude::*;
+
+        let data: Vec<Data> = data.collect();
+        let (scoring, inlier_threshold) = (self.scoring, self.inlier_threshold);
+        let batch_size = rayon::current_num_threads();
+        self.run(estimator, &data, None, batch_size, |_, samples| {
+            samples
+                .par_iter()
+                .map(|sample| {
+                    estimator
+                        .estimate(sample.iter().map(|&ix| data[ix].clone()))
+                        .into_iter()
+                        .map(|model| {
+                            let residuals: Vec<f64> =
+                                data.par_iter().map(|point| model.residual(point)).collect();
+                            let score = score_residuals(
+                                scoring,
+                                inlier_threshold,
+                                residuals.into_iter(),
+                                None,
+                            );
+                            (model, score)
+                        })
+                        .collect()
+                })
+                .collect()
+        })
+    }
+
+    /// Runs consensus where each data point is paired with a confidence, such as one derived from its descriptor
+    /// distance or a learned match score.
+    ///
+    /// Higher weights make a data point more likely to be sampled (see [`Sampling::Uniform`]) and make it
+    /// contribute more to the score of models it is an inlier to, so high-quality matches dominate model selection.
+    /// Weights must be non-negative, and at least a minimal sample of them must be positive.
+    pub fn model_weighted<E, Data, I>(
+        &mut self,
+        estimator: &E,
+        data: I,
+    ) -> Option<(E::Model, ConsensusReport)>
+    where
+        E: Estimator<Data>,
+        Data: Clone,
+        I: Iterator<Item = (Data, f64)>,
+    {
+        let (data, weights): (Vec<Data>, Vec<f64>) = data.unzip();
+        self.run(estimator, &data, Some(&weights), 1, |ransac, samples| {
+            ransac.evaluate(estimator, &data, Some(&weights), samples)
+        })
+    }
+
+    fn inliers<M, Data>(model: &M, data: &[Data], threshold: f64) -> Vec<usize>
+    where
+        M: Model<Data>,
+    {
+        data.iter()
+            .enumerate()
+            .filter(|(_, point)| model.residual(point).abs() < threshold)
+            .map(|(ix, _)| ix)
+            .collect()
+    }
+
+    /// Estimates and scores the models from each sample on the current thread.
+    fn evaluate<E, Data>(
+        &self,
+        estimator: &E,
+        data: &[Data],
+        weights: Option<&[f64]>,
+        samples: &[Vec<usize>],
+    ) -> Vec<Vec<(E::Model, Score)>>
+    where
+        E: Estimator<Data>,
+        Data: Clone,
+    {
+        samples
+            .iter()
+            .map(|sample| {
+                estimator
+                    .estimate(sample.iter().map(|&ix| data[ix].clone()))
+                    .into_iter()
+                    .map(|model| {
+                        let score = self.score(&model, data, weights);
+                        (model, score)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Runs the inner consensus of LO-RANSAC, returning a better model and its inlier count if one is found.
+    fn locally_optimize<E, Data>(
+        &mut self,
+        local_optimization: LocalOptimization,
+        estimator: &E,
+        data: &[Data],
+        weights: Option<&[f64]>,
+        model: &E::Model,
+        score: Score,
+    ) -> Option<(E::Model, Score)>
+    where
+        E: Estimator<Data>,
+        Data: Clone,
+    {
+        let inner_threshold = self.inlier_threshold * local_optimization.threshold_multiplier;
+        let inner_inliers = Self::inliers(model, data, inner_threshold);
+        let sample_size =
+            (E::MIN_SAMPLES * local_optimization.sample_size_multiplier).min(inner_inliers.len());
+        if sample_size < E::MIN_SAMPLES {
+            return None;
+        }
+        let mut best: Option<(E::Model, Score)> = None;
+        let mut best_score = score;
+        for _ in 0..local_optimization.iterations {
+            let sample = rand::seq::index::sample(&mut self.rng, inner_inliers.len(), sample_size);
+            let sample = sample.iter().map(|ix| data[inner_inliers[ix]].clone());
+            for model in estimator.estimate(sample) {
+                let score = self.score(&model, data, weights);
+                if score.is_better_than(&best_score) {
+                    best_score = score;
+                    best = Some((model, score));
+                }
+            }
+        }
+        best
+    }
+
+    /// Runs the consensus process over the data.
+    ///
+    /// Samples are drawn in batches of `batch_size` and handed to `evaluate`, which returns the scored models from
+    /// each sample in the same order.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "consensus", skip_all, fields(data = data.len(), iterations, inliers))
+    )]
+    fn run<E, Data, F>(
+        &mut self,
+        estimator: &E,
+        data: &[Data],
+        weights: Option<&[f64]>,
+        batch_size: usize,
+        evaluate: F,
+    ) -> Option<(E::Model, ConsensusReport)>
+    where
+        E: Estimator<Data>,
+        Data: Clone,
+        F: Fn(&Self, &[Vec<usize>]) -> Vec<Vec<(E::Model, Score)>>,
+    {
+        let sample_size = E::MIN_SAMPLES;
+        if data.len() < sample_size {
+            return None;
+        }
+        let mut sampler = Sampler::new(self.sampling, data.len(), sample_size, self.max_iterations);
+        if let Some(weights) = weights {
+            sampler = sampler.weighted(weights)?;
+        }
+        let mut samples: Vec<Vec<usize>> = vec![];
+        let mut best: Option<(E::Model, Score)> = None;
+        let mut score_history = vec![];
+        let mut required_iterations = self.max_iterations;
+        let mut iteration = 0;
+        while iteration < required_iterations {
+            let batch = (required_iterations - iteration).min(batch_size.max(1));
+            samples.resize_with(batch, || Vec::with_capacity(sample_size));
+            for sample in &mut samples {
+                sampler.sample(&mut self.rng, sample);
+            }
+            for models in evaluate(self, &samples) {
+                iteration += 1;
+                for (model, score) in models {
+                    if best
+                        .as_ref()
+                        .map(|(_, best_score)| score.is_better_than(best_score))
+                        .unwrap_or(true)
+                    {
+                        let (model, score) = self
+                            .local_optimization
+                            .and_then(|local_optimization| {
+                                self.locally_optimize(
+                                    local_optimization,
+                                    estimator,
+                                    data,
+                                    weights,
+                                    &model,
+                                    score,
+                                )
+                            })
+                            .unwrap_or((model, score));
+                        required_iterations = self
+                            .required_iterations(score.inliers, data.len(), sample_size)
+                            .max(iteration);
+                        score_history.push(ScoreUpdate { iteration, score });
+                        best = Some((model, score));
+                    }
+                }
+            }
+        }
+        let (model, _) = best?;
+        let residuals: Vec<f64> = data
+            .iter()
+            .map(|point| model.residual(point).abs())
+            .collect();
+        let inlier_mask: Vec<bool> = residuals
+            .iter()
+            .map(|&residual| residual < self.inlier_threshold)
+            .collect();
+        let inliers: Vec<usize> = inlier_mask
+            .iter()
+            .enumerate()
+            .filter(|&(_, &inlier)| inlier)
+            .map(|(ix, _)| ix)
+            .collect();
+        info!(
+            "consensus found model with {} inliers out of {} after {} iterations",
+            inliers.len(),
+            data.len(),
+            iteration
+        );
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("iterations", &iteration);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("inliers", &inliers.len());
+        let report = ConsensusReport {
+            inliers,
+            inlier_mask,
+            iterations: iteration,
+            score_history,
+            residuals: ResidualStatistics::new(&residuals, self.inlier_threshold),
+        };
+        Some((model, report))
+    }
+}
+
+/// Scores residuals, optionally weighting each data point.
+fn score_residuals(
+    scoring: Scoring,
+    inlier_threshold: f64,
+    residuals: impl Iterator<Item = f64>,
+    weights: Option<&[f64]>,
+) -> Score {
+    match weights {
+        Some(weights) => {
+            scoring.score_weighted(residuals.zip(weights.iter().copied()), inlier_threshold)
+        }
+        None => scoring.score(residuals, inlier_threshold),
+    }
+}
+
+impl<E, R, Data> Consensus<E, Data> for Ransac<R>
+where
+    E: Estimator<Data>,
+    R: Rng,
+    Data: Clone,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.model_inliers(estimator, data).map(|(model, _)| model)
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.model_report(estimator, data)
+            .map(|(model, report)| (model, report.inliers))
+    }
+}
+
+impl<E, R, Data> ReportingConsensus<E, Data> for Ransac<R>
+where
+    E: Estimator<Data>,
+    R: Rng,
+    Data: Clone,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, ConsensusReport)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        Ransac::model_report(self, estimator, data)
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{Score, Scoring};
+use cv_core::sample_consensus::{Consensus, Estimator, Model};
+use log::*;
+use rand::{seq::SliceRandom, Rng};
+use std::time::{Duration, Instant};
+
+/// Preemptive RANSAC by David Nistér for hard real-time applications such as visual odometry.
+///
+/// Instead of drawing samples until the desired confidence is reached, a fixed number of hypotheses are generated
+/// up front and scored breadth-first: every remaining hypothesis is scored on the next block of data, and then the
+/// worse half of the hypotheses are discarded. The amount of work is bounded regardless of the data, so a
+/// degenerate frame can't blow the frame budget. If a `time_budget` is set, scoring stops once it is exceeded and
+/// the best hypothesis so far is returned, unless [`cv_core::set_deterministic`] is on.
+#[derive(Clone, Debug)]
+pub struct PreemptiveRansac<R> {
+    /// The residual below which a data point is considered an inlier.
+    pub inlier_threshold: f64,
+    /// The number of hypotheses generated up front.
+    pub hypotheses: usize,
+    /// The number of data points each hypothesis is scored on before the worst half are discarded.
+    pub block_size: usize,
+    /// The maximum time spent generating and scoring hypotheses.
+    ///
+    /// There is no clock on `wasm32-unknown-unknown`, so the budget is ignored there. It is also ignored in
+    /// deterministic mode (see [`cv_core::set_deterministic`]), since the hypotheses scored in time depend on the
+    /// speed of the machine.
+    pub time_budget: Option<Duration>,
+    /// The method used to score models.
+    pub scoring: Scoring,
+    /// The random number generator used for sampling.
+    pub rng: R,
+}
+
+impl<R> PreemptiveRansac<R>
+where
+    R: Rng,
+{
+    pub fn new(inlier_threshold: f64, rng: R) -> Self {
+        Self {
+            inlier_threshold,
+            hypotheses: 500,
+            block_size: 100,
+            time_budget: None,
+            scoring: Scoring::InlierCount,
+            rng,
+        }
+    }
+
+    #[must_use]
+    pub fn inlier_threshold(self, inlier_threshold: f64) -> Self {
+        Self {
+            inlier_threshold,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn hypotheses(self, hypotheses: usize) -> Self {
+        Self { hypotheses, ..self }
+    }
+
+    #[must_use]
+    pub fn block_size(self, block_size: usize) -> Self {
+        Self { block_size, ..self }
+    }
+
+    #[must_use]
+    pub fn time_budget(self, time_budget: Duration) -> Self {
+        Self {
+            time_budget: Some(time_budget),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn scoring(self, scoring: Scoring) -> Self {
+        Self { scoring, ..self }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "preemptive_consensus", skip_all, fields(data = data.len(), samples, inliers))
+    )]
+    fn run<E, Data>(&mut self, estimator: &E, data: &[Data]) -> Option<(E::Model, Vec<usize>)>
+    where
+        E: Estimator<Data>,
+        Data: Clone,
+    {
+        // The clock is only read when there is a budget, since reading it panics on targets without one.
+        let deadline = self.time_budget.and_then(|budget| {
+            if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+                warn!("time budgets are not supported on this target and are ignored");
+                None
+            } else if cv_core::is_deterministic() {
+                debug!("ignoring the time budget in deterministic mode");
+                None
+            } else {
+                Some((Instant::now(), budget))
+            }
+        });
+        let out_of_time = || {
+            deadline
+                .map(|(start, budget)| start.elapsed() >= budget)
+                .unwrap_or(false)
+        };
+        if data.len() < E::MIN_SAMPLES {
+            return None;
+        }
+
+        // Generate all of the hypotheses up front.
+        let mut hypotheses: Vec<(E::Model, Score)> = Vec::with_capacity(self.hypotheses);
+        let mut samples = 0;
+        while samples < self.hypotheses && !out_of_time() {
+            samples += 1;
+            let sample = rand::seq::index::sample(&mut self.rng, data.len(), E::MIN_SAMPLES);
+            hypotheses.extend(
+                estimator
+                    .estimate(sample.iter().map(|ix| data[ix].clone()))
+                    .into_iter()
+                    .map(|model| {
+                        (
+                            model,
+                            Score {
+                                value: 0.0,
+                                inliers: 0,
+                            },
+                        )
+                    }),
+            );
+        }
+
+        // Score the hypotheses breadth-first on the data in a random order.
+        let mut order: Vec<usize> = (0..data.len()).collect();
+        order.shuffle(&mut self.rng);
+        let block_size = self.block_size.max(1);
+        let mut scored = 0;
+        for block in order.chunks(block_size) {
+            if hypotheses.len() <= 1 || out_of_time() {
+                break;
+            }
+            for (model, score) in &mut hypotheses {
+                let block_score = self.scoring.score(
+                    block.iter().map(|&ix| model.residual(&data[ix])),
+                    self.inlier_threshold,
+                );
+                score.value += block_score.value;
+                score.inliers += block_score.inliers;
+            }
+            scored += block.len();
+            // Keep the better half of the hypotheses.
+            hypotheses.sort_unstable_by(|(_, a), (_, b)| {
+                b.value
+                    .partial_cmp(&a.value)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            hypotheses.truncate((hypotheses.len() + 1) / 2);
+        }
+
+        let (model, _) = hypotheses.into_iter().max_by(|(_, a), (_, b)| {
+            a.value
+                .partial_cmp(&b.value)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+        let inliers: Vec<usize> = data
+            .iter()
+            .enumerate()
+            .filter(|(_, point)| model.residual(point).abs() < self.inlier_threshold)
+            .map(|(ix, _)| ix)
+            .collect();
+        info!(
+            "preemptive consensus found model with {} inliers out of {} from {} samples after scoring {} points",
+            inliers.len(),
+            data.len(),
+            samples,
+            scored
+        );
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("samples", &samples);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("inliers", &inliers.len());
+        if let Some((start, _)) = deadline {
+            info!("preemptive consensus took {:?}", start.elapsed());
+        }
+        Some((model, inliers))
+    }
+}
+
+impl<E, R, Data> Consensus<E, Data> for PreemptiveRansac<R>
+where
+    E: Estimator<Data>,
+    R: Rng,
+    Data: Clone,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.model_inliers(estimator, data).map(|(model, _)| model)
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let data: Vec<Data> = data.collect();
+        self.run(estimator, &data)
+    }
+}
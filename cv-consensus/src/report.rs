@@ -0,0 +1,104 @@
+use crate::Score;
+use cv_core::sample_consensus::{Consensus, Estimator};
+
+/// Summary statistics of the residuals of every data point to a model.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResidualStatistics {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// The upper edge of each histogram bin (the lower edge of the first bin is `0.0`).
+    ///
+    /// The bins are evenly spaced up to a multiple of the inlier threshold, and the last bin collects everything above.
+    pub bin_edges: Vec<f64>,
+    /// The number of residuals in each bin.
+    pub histogram: Vec<usize>,
+}
+
+impl ResidualStatistics {
+    /// The number of histogram bins below the inlier threshold.
+    const INLIER_BINS: usize = 8;
+    /// The histogram extends to this multiple of the inlier threshold before the overflow bin.
+    const THRESHOLD_MULTIPLE: usize = 4;
+
+    /// Computes statistics from the absolute residuals of each data point.
+    pub fn new(residuals: &[f64], inlier_threshold: f64) -> Self {
+        if residuals.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = residuals.to_vec();
+        sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let bin_width = inlier_threshold / Self::INLIER_BINS as f64;
+        let bins = Self::INLIER_BINS * Self::THRESHOLD_MULTIPLE;
+        let mut bin_edges: Vec<f64> = (1..=bins).map(|bin| bin as f64 * bin_width).collect();
+        bin_edges.push(f64::INFINITY);
+        let mut histogram = vec![0; bin_edges.len()];
+        for &residual in residuals {
+            let bin = ((residual / bin_width) as usize).min(bins);
+            histogram[bin] += 1;
+        }
+        Self {
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            median: sorted[sorted.len() / 2],
+            bin_edges,
+            histogram,
+        }
+    }
+}
+
+/// A new best model found during consensus.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScoreUpdate {
+    /// The iteration the model was found on (starting from `1`).
+    pub iteration: usize,
+    /// The score of the model.
+    pub score: Score,
+}
+
+/// A structured report of a consensus run, which helps with analyzing why consensus succeeded or failed.
+///
+/// Retrieve it with [`Ransac::model_report`](crate::Ransac::model_report), or through [`ReportingConsensus`] from code
+/// which is generic over the consensus algorithm.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConsensusReport {
+    /// The indices of the inliers of the final model.
+    pub inliers: Vec<usize>,
+    /// Whether each data point is an inlier to the final model, aligned to the input data.
+    pub inlier_mask: Vec<bool>,
+    /// The number of samples drawn.
+    pub iterations: usize,
+    /// Every time a better model was found, in the order they were found.
+    pub score_history: Vec<ScoreUpdate>,
+    /// The distribution of the residuals of every data point to the final model.
+    pub residuals: ResidualStatistics,
+}
+
+impl ConsensusReport {
+    /// The fraction of the data which are inliers to the final model.
+    pub fn inlier_ratio(&self) -> f64 {
+        if self.inlier_mask.is_empty() {
+            0.0
+        } else {
+            self.inliers.len() as f64 / self.inlier_mask.len() as f64
+        }
+    }
+}
+
+/// A [`Consensus`] which can also report how it found its model.
+///
+/// This lets code which is generic over the consensus algorithm, such as the localization and two-view initialization
+/// of `cv-sfm`, pass the [`ConsensusReport`] on to its callers.
+pub trait ReportingConsensus<E, Data>: Consensus<E, Data>
+where
+    E: Estimator<Data>,
+{
+    /// Runs consensus and returns the model along with a [`ConsensusReport`].
+    ///
+    /// This is the same as [`Consensus::model_inliers`], but with a more detailed result.
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, ConsensusReport)>
+    where
+        I: Iterator<Item = Data> + Clone;
+}
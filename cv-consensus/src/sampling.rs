@@ -0,0 +1,130 @@
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    seq::index,
+    Rng,
+};
+
+/// The strategy used to draw minimal samples from the data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sampling {
+    /// Every sample is drawn uniformly from all of the data.
+    ///
+    /// When consensus is weighted (see [`Ransac::model_weighted`](crate::Ransac::model_weighted)), each data point is
+    /// instead drawn with probability proportional to its weight.
+    Uniform,
+    /// Progressive sample consensus (PROSAC) by Chum and Matas.
+    ///
+    /// The data must be sorted by decreasing quality (for instance, increasing descriptor distance or
+    /// decreasing ratio test score). Samples are initially drawn only from the best few data points and the
+    /// set being sampled from grows progressively until it is the whole data set, at which point PROSAC is
+    /// equivalent to uniform sampling. When the quality ordering is informative, a good model is found in
+    /// far fewer iterations than with uniform sampling.
+    ///
+    /// Since the ordering already encodes the quality of the data, weights only affect scoring with PROSAC.
+    Prosac,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self::Uniform
+    }
+}
+
+/// Draws minimal samples according to a [`Sampling`] strategy.
+pub(crate) struct Sampler {
+    sampling: Sampling,
+    /// The number of data points.
+    len: usize,
+    /// The number of data points in a minimal sample.
+    sample_size: usize,
+    /// The number of samples drawn so far.
+    iteration: usize,
+    /// Draws data points with probability proportional to their weight instead of uniformly.
+    weights: Option<WeightedIndex<f64>>,
+    /// PROSAC: The number of top quality data points being sampled from (`n`).
+    subset_size: usize,
+    /// PROSAC: The expected number of samples drawn from the top `n` data points out of `max_iterations` (`T_n`).
+    expected_samples: f64,
+    /// PROSAC: The iteration at which the subset grows (`T'_n`).
+    growth_iteration: usize,
+}
+
+impl Sampler {
+    pub(crate) fn new(
+        sampling: Sampling,
+        len: usize,
+        sample_size: usize,
+        max_iterations: usize,
+    ) -> Self {
+        // T_m = max_iterations * binomial(m, m) / binomial(N, m)
+        let expected_samples = (0..sample_size).fold(max_iterations as f64, |t, i| {
+            t * (sample_size - i) as f64 / (len - i) as f64
+        });
+        Self {
+            sampling,
+            len,
+            sample_size,
+            iteration: 0,
+            weights: None,
+            subset_size: sample_size,
+            expected_samples,
+            growth_iteration: 1,
+        }
+    }
+
+    /// Draws uniform samples with probability proportional to the weight of each data point.
+    ///
+    /// Returns `None` if fewer than `sample_size` data points have a positive weight or any weight is invalid.
+    pub(crate) fn weighted(self, weights: &[f64]) -> Option<Self> {
+        if weights.iter().filter(|&&weight| weight > 0.0).count() < self.sample_size {
+            return None;
+        }
+        Some(Self {
+            weights: Some(WeightedIndex::new(weights).ok()?),
+            ..self
+        })
+    }
+
+    /// Replaces the contents of `sample` with the indices of a new minimal sample.
+    pub(crate) fn sample<R: Rng + ?Sized>(&mut self, rng: &mut R, sample: &mut Vec<usize>) {
+        sample.clear();
+        self.iteration += 1;
+        match self.sampling {
+            Sampling::Uniform => match &self.weights {
+                Some(weights) => {
+                    // Rejecting duplicates is fast since the sample is tiny compared to the data.
+                    while sample.len() < self.sample_size {
+                        let ix = weights.sample(rng);
+                        if !sample.contains(&ix) {
+                            sample.push(ix);
+                        }
+                    }
+                }
+                None => sample.extend(index::sample(rng, self.len, self.sample_size).iter()),
+            },
+            Sampling::Prosac => {
+                if self.iteration >= self.growth_iteration && self.subset_size < self.len {
+                    // T_{n+1} = T_n * (n + 1) / (n + 1 - m)
+                    let n = self.subset_size as f64;
+                    let next_expected_samples =
+                        self.expected_samples * (n + 1.0) / (n + 1.0 - self.sample_size as f64);
+                    // T'_{n+1} = T'_n + ceil(T_{n+1} - T_n)
+                    self.growth_iteration +=
+                        ((next_expected_samples - self.expected_samples).ceil() as usize).max(1);
+                    self.expected_samples = next_expected_samples;
+                    self.subset_size += 1;
+                }
+                if self.growth_iteration < self.iteration {
+                    // The growth function has run out, so sample from the whole subset.
+                    sample.extend(index::sample(rng, self.subset_size, self.sample_size).iter());
+                } else {
+                    // Sample the newest member of the subset with the remainder of the sample from the rest of the subset.
+                    sample.extend(
+                        index::sample(rng, self.subset_size - 1, self.sample_size - 1).iter(),
+                    );
+                    sample.push(self.subset_size - 1);
+                }
+            }
+        }
+    }
+}
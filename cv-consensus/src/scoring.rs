@@ -0,0 +1,81 @@
+/// The method used to score how well a model fits the data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scoring {
+    /// The score is the number of data points with a residual below the inlier threshold.
+    InlierCount,
+    /// M-estimator sample consensus (MSAC) by Torr and Zisserman, which scores models by a truncated quadratic loss.
+    ///
+    /// Each data point costs `min(r², T²)`, so outliers all cost the same as in RANSAC, but inliers cost less the
+    /// closer they are to the model. The score is the cost saved relative to every point being an outlier, which is
+    /// `1 - r²/T²` per inlier. This breaks ties between models with the same number of inliers in favor of the more
+    /// accurate one, at no additional cost.
+    Msac,
+    /// MAGSAC-style scoring which marginalizes over the inlier threshold instead of using a hard cutoff.
+    ///
+    /// The inlier threshold `T` is instead treated as the maximum threshold. The truncated quadratic quality
+    /// `1 - r²/τ²` is integrated over a uniform prior on the threshold `τ` in `(0, T]`, which gives each data point
+    /// a weight of `(1 - r/T)²` for `r < T`. Points close to the model dominate the score, and points near the
+    /// threshold barely affect it, so the result is much less sensitive to the exact threshold chosen.
+    Magsac,
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Self::InlierCount
+    }
+}
+
+/// The score of a model.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Score {
+    /// The quality of the model (higher is better).
+    pub value: f64,
+    /// The number of data points with residuals below the inlier threshold.
+    pub inliers: usize,
+}
+
+impl Score {
+    /// Returns `true` if this score is strictly better than `other`.
+    pub fn is_better_than(&self, other: &Self) -> bool {
+        self.value > other.value || (self.value == other.value && self.inliers > other.inliers)
+    }
+}
+
+impl Scoring {
+    /// Scores a model from its residuals on every data point.
+    pub fn score(self, residuals: impl Iterator<Item = f64>, threshold: f64) -> Score {
+        self.score_weighted(residuals.map(|residual| (residual, 1.0)), threshold)
+    }
+
+    /// Scores a model from its residuals on every data point paired with the confidence of each data point.
+    ///
+    /// The contribution of each inlier to the score is multiplied by its weight, so that high-confidence data
+    /// dominates model selection. The number of inliers is not weighted.
+    pub fn score_weighted(
+        self,
+        residuals: impl Iterator<Item = (f64, f64)>,
+        threshold: f64,
+    ) -> Score {
+        let mut value = 0.0;
+        let mut inliers = 0;
+        for (residual, weight) in residuals
+            .map(|(residual, weight)| (residual.abs(), weight))
+            .filter(|&(r, _)| r < threshold)
+        {
+            inliers += 1;
+            value += weight
+                * match self {
+                    Scoring::InlierCount => 1.0,
+                    Scoring::Msac => {
+                        let ratio = residual / threshold;
+                        1.0 - ratio * ratio
+                    }
+                    Scoring::Magsac => {
+                        let quality = 1.0 - residual / threshold;
+                        quality * quality
+                    }
+                };
+        }
+        Score { value, inliers }
+    }
+}
@@ -0,0 +1,145 @@
+use cv_consensus::{LocalOptimization, PreemptiveRansac, Ransac};
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point3, Rotation3, UnitVector3, Vector3},
+    sample_consensus::Consensus,
+    CameraPoint, CameraToCamera, FeatureMatch, Pose, Projective,
+};
+use eight_point::EightPoint;
+use rand::{rngs::SmallRng, SeedableRng};
+
+const INLIERS: usize = 64;
+const OUTLIERS: usize = 32;
+const INLIER_THRESHOLD: f64 = 1e-5;
+
+const ROT_MAGNITUDE: f64 = 0.2;
+const POINT_BOX_SIZE: f64 = 2.0;
+const POINT_DISTANCE: f64 = 3.0;
+
+/// Generates matches where the first `INLIERS` are correct and the remaining `OUTLIERS` are random.
+fn some_test_data() -> Vec<FeatureMatch> {
+    let relative_pose = CameraToCamera(IsometryMatrix3::from_parts(
+        Vector3::new_random().into(),
+        Rotation3::new(Vector3::new_random() * std::f64::consts::PI * 2.0 * ROT_MAGNITUDE),
+    ));
+    let inliers = (0..INLIERS).map(|_| {
+        let mut a = Point3::from(Vector3::new_random() * POINT_BOX_SIZE);
+        a.x -= 0.5 * POINT_BOX_SIZE;
+        a.y -= 0.5 * POINT_BOX_SIZE;
+        a.z += POINT_DISTANCE;
+        let a = CameraPoint::from_point(a);
+        let b = relative_pose.transform(a);
+        FeatureMatch(a.bearing(), b.bearing())
+    });
+    let outliers = (0..OUTLIERS).map(|_| {
+        let random_bearing =
+            || UnitVector3::new_normalize(Vector3::new_random() - Vector3::new(0.5, 0.5, -1.0));
+        FeatureMatch(random_bearing(), random_bearing())
+    });
+    inliers.chain(outliers).collect()
+}
+
+fn check(mut ransac: Ransac<SmallRng>) {
+    let data = some_test_data();
+    let (_, inliers) = ransac
+        .model_inliers(&EightPoint::new(), data.iter().copied())
+        .expect("failed to find a model");
+    let correct = inliers.iter().filter(|&&ix| ix < INLIERS).count();
+    eprintln!("found {} inliers, {} correct", inliers.len(), correct);
+    assert!(correct >= INLIERS * 9 / 10);
+    assert!(inliers.len() - correct <= OUTLIERS / 10);
+}
+
+#[test]
+fn uniform() {
+    check(Ransac::new(INLIER_THRESHOLD, SmallRng::seed_from_u64(0)));
+}
+
+#[test]
+fn prosac() {
+    check(Ransac::new(INLIER_THRESHOLD, SmallRng::seed_from_u64(0)).prosac());
+}
+
+#[test]
+fn lo_ransac() {
+    check(
+        Ransac::new(INLIER_THRESHOLD, SmallRng::seed_from_u64(0))
+            .local_optimization(LocalOptimization::default()),
+    );
+}
+
+#[test]
+fn msac() {
+    check(Ransac::new(INLIER_THRESHOLD, SmallRng::seed_from_u64(0)).msac());
+}
+
+#[test]
+fn magsac() {
+    check(Ransac::new(INLIER_THRESHOLD, SmallRng::seed_from_u64(0)).magsac());
+}
+
+#[test]
+fn preemptive() {
+    let data = some_test_data();
+    let (_, inliers) = PreemptiveRansac::new(INLIER_THRESHOLD, SmallRng::seed_from_u64(0))
+        .hypotheses(200)
+        .block_size(16)
+        .model_inliers(&EightPoint::new(), data.iter().copied())
+        .expect("failed to find a model");
+    let correct = inliers.iter().filter(|&&ix| ix < INLIERS).count();
+    eprintln!("found {} inliers, {} correct", inliers.len(), correct);
+    assert!(correct >= INLIERS * 9 / 10);
+    assert!(inliers.len() - correct <= OUTLIERS / 10);
+}
+
+#[test]
+fn weighted() {
+    let data = some_test_data();
+    let weights = (0..data.len()).map(|ix| if ix < INLIERS { 1.0 } else { 0.1 });
+    let (_, report) = Ransac::new(INLIER_THRESHOLD, SmallRng::seed_from_u64(0))
+        .model_weighted(&EightPoint::new(), data.iter().copied().zip(weights))
+        .expect("failed to find a model");
+    let correct = report.inliers.iter().filter(|&&ix| ix < INLIERS).count();
+    eprintln!(
+        "found {} inliers, {} correct",
+        report.inliers.len(),
+        correct
+    );
+    assert!(correct >= INLIERS * 9 / 10);
+    assert!(report.inliers.len() - correct <= OUTLIERS / 10);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn parallel() {
+    let data = some_test_data();
+    let (_, report) = Ransac::new(INLIER_THRESHOLD, SmallRng::seed_from_u64(0))
+        .model_parallel(&EightPoint::new(), data.iter().copied())
+        .expect("failed to find a model");
+    let correct = report.inliers.iter().filter(|&&ix| ix < INLIERS).count();
+    eprintln!(
+        "found {} inliers, {} correct",
+        report.inliers.len(),
+        correct
+    );
+    assert!(correct >= INLIERS * 9 / 10);
+    assert!(report.inliers.len() - correct <= OUTLIERS / 10);
+}
+
+#[test]
+fn report() {
+    let data = some_test_data();
+    let (_, report) = Ransac::new(INLIER_THRESHOLD, SmallRng::seed_from_u64(0))
+        .model_report(&EightPoint::new(), data.iter().copied())
+        .expect("failed to find a model");
+    assert_eq!(report.inlier_mask.len(), data.len());
+    assert_eq!(
+        report.inlier_mask.iter().filter(|&&inlier| inlier).count(),
+        report.inliers.len()
+    );
+    assert!(report.iterations > 0);
+    assert!(report
+        .score_history
+        .windows(2)
+        .all(|pair| pair[1].score.is_better_than(&pair[0].score)));
+    assert_eq!(report.residuals.histogram.iter().sum::<usize>(), data.len());
+}
@@ -1,5 +1,5 @@
 use crate::{ImagePoint, KeyPoint};
-use nalgebra::UnitVector3;
+use nalgebra::{Matrix2x3, Point2, UnitVector3, Vector2, Vector3};
 
 /// Allows conversion between the point on an image and the internal projection
 /// which can describe the bearing of the projection out of the camera.
@@ -21,3 +21,28 @@ pub trait CameraModel {
     /// this operation is fallible.
     fn uncalibrate(&self, bearing: UnitVector3<f64>) -> Option<KeyPoint>;
 }
+
+/// A [`CameraModel`] with parameters that can be optimized, such as its focal lengths, principal point, and
+/// distortion coefficients, along with the analytic Jacobians of its projection.
+pub trait ParametricCameraModel: CameraModel {
+    /// The number of parameters of the model.
+    const PARAMETERS: usize;
+
+    /// Writes the parameters of the model into `parameters`, which has a length of [`Self::PARAMETERS`].
+    fn parameters(&self, parameters: &mut [f64]);
+
+    /// Sets the parameters of the model from `parameters`, which has a length of [`Self::PARAMETERS`].
+    fn set_parameters(&mut self, parameters: &[f64]);
+
+    /// Projects a point in the camera frame to pixel coordinates.
+    ///
+    /// Returns the pixel and its Jacobian in respect to the point, and writes the Jacobian of the pixel in respect to
+    /// each parameter into `parameter_jacobian`, which has a length of [`Self::PARAMETERS`].
+    ///
+    /// Returns `None` if the point can't be projected, such as when it is behind a pinhole camera.
+    fn project_jacobians(
+        &self,
+        point: Vector3<f64>,
+        parameter_jacobian: &mut [Vector2<f64>],
+    ) -> Option<(Point2<f64>, Matrix2x3<f64>)>;
+}
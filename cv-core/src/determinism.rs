@@ -0,0 +1,27 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether Rust CV should produce the same results on every run.
+///
+/// Every stochastic component of Rust CV takes a seedable random number generator or a seed, such as the sample
+/// consensus algorithms, visual SLAM and multi-view stereo, so they are reproducible once they are seeded the same
+/// way. The switch covers the remaining sources of variation between runs: components which would otherwise stop
+/// after a time budget, such as preemptive RANSAC, ignore their budget and always run to completion, since how much
+/// work they finish depends on the speed of the machine.
+///
+/// This is off by default and applies to the whole process.
+///
+/// ```
+/// cv_core::set_deterministic(true);
+/// assert!(cv_core::is_deterministic());
+/// cv_core::set_deterministic(false);
+/// ```
+pub fn set_deterministic(deterministic: bool) {
+    DETERMINISTIC.store(deterministic, Ordering::Relaxed);
+}
+
+/// Whether Rust CV should produce the same results on every run (see [`set_deterministic`]).
+pub fn is_deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Relaxed)
+}
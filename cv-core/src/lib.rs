@@ -14,6 +14,14 @@
 //! If there is a good reason to put code that some crates may need into `cv-core`, it should be
 //! gated behind a feature.
 //!
+//! ## Features
+//!
+//! * `std` (default) - let the dependencies use the standard library. Disable the default features to build
+//!   without it.
+//! * `alloc` - enable the heap allocated matrices of `nalgebra` without the standard library, for targets which
+//!   have an allocator, such as embedded targets and kernels.
+//! * `serde-serialize` - implement `Serialize` and `Deserialize` for the types of the crate.
+//!
 //! ## Triangulation
 //!
 //! Several of the traits with in `cv-core`, such as [`TriangulatorObservances`], must perform a process
@@ -66,19 +74,23 @@
 #![no_std]
 
 mod camera;
+mod determinism;
 mod keypoint;
 mod matches;
 mod point;
 mod pose;
+mod rig;
 mod so3;
 mod triangulation;
 
 pub use camera::*;
+pub use determinism::*;
 pub use keypoint::*;
 pub use matches::*;
 pub use nalgebra;
 pub use point::*;
 pub use pose::*;
+pub use rig::*;
 pub use sample_consensus;
 pub use so3::*;
 pub use triangulation::*;
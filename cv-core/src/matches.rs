@@ -1,4 +1,4 @@
-use crate::WorldPoint;
+use crate::{KeyPoint, WorldPoint};
 use nalgebra::UnitVector3;
 
 /// Two keypoint bearings matched together from two separate images
@@ -8,3 +8,10 @@ pub struct FeatureMatch(pub UnitVector3<f64>, pub UnitVector3<f64>);
 /// A keypoint bearing matched to a [`WorldPoint`].
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct FeatureWorldMatch(pub UnitVector3<f64>, pub WorldPoint);
+
+/// Two keypoints matched together from two separate images in pixel coordinates.
+///
+/// This is used for uncalibrated estimation, such as of a fundamental matrix, where the
+/// keypoints can't be converted into bearings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyPointMatch(pub KeyPoint, pub KeyPoint);
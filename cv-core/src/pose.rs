@@ -1,8 +1,11 @@
 use crate::{CameraPoint, FeatureMatch, FeatureWorldMatch, Projective, Skew3, WorldPoint};
+use core::ops::Mul;
 use derive_more::{AsMut, AsRef, From, Into};
 use nalgebra::{
-    IsometryMatrix3, Matrix4, Matrix4x6, Matrix6x4, Rotation3, Vector3, Vector4, Vector6,
+    IsometryMatrix3, Matrix4, Matrix4x6, Matrix6x4, Rotation3, SVector, SimilarityMatrix3, Vector3,
+    Vector4, Vector6,
 };
+use num_traits::Float;
 use sample_consensus::Model;
 
 #[cfg(feature = "serde-serialize")]
@@ -315,3 +318,109 @@ impl WorldToWorld {
         (b_pose.isometry().inverse() * a_pose.isometry()).into()
     }
 }
+
+/// A similarity transformation, which is a pose with an additional uniform scale factor.
+///
+/// Monocular reconstructions are only known up to scale and accumulate scale drift, so aligning two of them (such
+/// as when closing a loop or merging maps) needs a similarity instead of an isometry. A point is transformed by
+/// scaling and rotating it before translating it.
+///
+/// ```
+/// use cv_core::{nalgebra::{Point3, Rotation3, Vector3}, Projective, Sim3, WorldPoint};
+///
+/// let sim3 = Sim3::from_parts(Vector3::new(1.0, 2.0, 3.0), Rotation3::from_euler_angles(0.1, 0.2, 0.3), 2.0);
+/// let point = WorldPoint::from_point(Point3::new(0.5, -0.5, 1.0));
+/// let transformed = sim3.transform(point);
+/// let expected = sim3.rotation() * Vector3::new(0.5, -0.5, 1.0) * 2.0 + sim3.translation();
+/// assert!((transformed.point().unwrap().coords - expected).norm() < 1e-12);
+/// assert!((sim3.inverse().transform(transformed).point().unwrap() - point.point().unwrap()).norm() < 1e-12);
+/// assert!((Sim3::from_sim3(sim3.sim3()).homogeneous() - sim3.homogeneous()).norm() < 1e-12);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, AsMut, AsRef, From, Into)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct Sim3(pub SimilarityMatrix3<f64>);
+
+impl Sim3 {
+    /// Creates a similarity with no change in position, orientation, or scale.
+    pub fn identity() -> Self {
+        Self(SimilarityMatrix3::identity())
+    }
+
+    /// Create the similarity from translation, rotation, and scale.
+    pub fn from_parts(translation: Vector3<f64>, rotation: Rotation3<f64>, scale: f64) -> Self {
+        Self(SimilarityMatrix3::from_parts(
+            translation.into(),
+            rotation,
+            scale,
+        ))
+    }
+
+    /// Create a similarity with a scale of `1.0` from any pose.
+    pub fn from_pose<P: Pose>(pose: P) -> Self {
+        let isometry = pose.isometry();
+        Self::from_parts(isometry.translation.vector, isometry.rotation, 1.0)
+    }
+
+    /// Retrieve the translation, which is applied after scaling and rotating.
+    pub fn translation(self) -> Vector3<f64> {
+        self.0.isometry.translation.vector
+    }
+
+    /// Retrieve the rotation.
+    pub fn rotation(self) -> Rotation3<f64> {
+        self.0.isometry.rotation
+    }
+
+    /// Retrieve the scale factor.
+    pub fn scale(self) -> f64 {
+        self.0.scaling()
+    }
+
+    /// Retrieve the pose with the scale removed, which keeps the rotation and divides the translation by the scale.
+    ///
+    /// A similarity from the world to a camera with a scale `s` maps points to `s` times the points of this pose,
+    /// which have the same bearings, so this recovers the rigid pose of a camera after correcting its scale.
+    pub fn isometry(self) -> IsometryMatrix3<f64> {
+        IsometryMatrix3::from_parts((self.translation() / self.scale()).into(), self.rotation())
+    }
+
+    /// Takes the inverse of the similarity.
+    #[must_use]
+    pub fn inverse(self) -> Self {
+        Self(self.0.inverse())
+    }
+
+    /// Retrieve the homogeneous matrix.
+    pub fn homogeneous(self) -> Matrix4<f64> {
+        self.0.to_homogeneous()
+    }
+
+    /// Transform the given point.
+    pub fn transform<P: Projective>(self, point: P) -> P {
+        P::from_homogeneous(self.homogeneous() * point.homogeneous())
+    }
+
+    /// Retrieve the sim(3) representation of the similarity, which is the se(3) representation of the pose
+    /// (see [`Pose::se3`]) followed by the logarithm of the scale.
+    pub fn sim3(self) -> SVector<f64, 7> {
+        let t = self.translation();
+        let r: Skew3 = self.rotation().into();
+        SVector::<f64, 7>::from_column_slice(&[t.x, t.y, t.z, r.x, r.y, r.z, self.scale().ln()])
+    }
+
+    /// Set the sim(3) representation of the similarity.
+    pub fn from_sim3(sim3: SVector<f64, 7>) -> Self {
+        let translation = sim3.xyz();
+        let rotation = Skew3(Vector3::new(sim3[3], sim3[4], sim3[5])).into();
+        Self::from_parts(translation, rotation, sim3[6].exp())
+    }
+}
+
+impl Mul for Sim3 {
+    type Output = Sim3;
+
+    /// Composes two similarities, applying `rhs` first.
+    fn mul(self, rhs: Sim3) -> Sim3 {
+        Sim3(self.0 * rhs.0)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{CameraToCamera, FeatureMatch, Pose, WorldToCamera};
+use nalgebra::{IsometryMatrix3, UnitVector3, Vector3};
+use sample_consensus::Model;
+
+/// A rig of `N` cameras which are rigidly attached to each other, such as a stereo pair or a surround-view setup.
+///
+/// The rig has its own coordinate system (often the coordinate system of one of the cameras or of the vehicle), and
+/// the extrinsics of each camera transform points from the rig's coordinate system into the camera's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraRig<const N: usize> {
+    /// The transformation from rig coordinates into the coordinates of each camera.
+    pub extrinsics: [IsometryMatrix3<f64>; N],
+}
+
+impl<const N: usize> CameraRig<N> {
+    pub fn new(extrinsics: [IsometryMatrix3<f64>; N]) -> Self {
+        Self { extrinsics }
+    }
+
+    /// The number of cameras in the rig.
+    pub fn len(&self) -> usize {
+        N
+    }
+
+    /// Returns `true` if the rig has no cameras.
+    pub fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    /// The optical center of a camera in rig coordinates.
+    pub fn camera_center(&self, camera: usize) -> Vector3<f64> {
+        self.extrinsics[camera].inverse().translation.vector
+    }
+
+    /// Transforms a bearing from a camera into rig coordinates.
+    pub fn rig_bearing(&self, camera: usize, bearing: UnitVector3<f64>) -> UnitVector3<f64> {
+        self.extrinsics[camera].rotation.inverse() * bearing
+    }
+
+    /// Computes the pose of one camera in the rig given the [`WorldToCamera`] pose of the rig itself.
+    pub fn camera_pose(&self, rig_pose: WorldToCamera, camera: usize) -> WorldToCamera {
+        WorldToCamera(self.extrinsics[camera] * rig_pose.isometry())
+    }
+
+    /// Computes the relative pose from camera `a` to camera `b` after the rig moved by `motion`.
+    ///
+    /// `motion` transforms points from the rig coordinates before the motion into the rig coordinates after it.
+    pub fn relative_pose(&self, motion: CameraToCamera, a: usize, b: usize) -> CameraToCamera {
+        CameraToCamera(self.extrinsics[b] * motion.isometry() * self.extrinsics[a].inverse())
+    }
+}
+
+/// A feature bearing observed by a particular camera in a [`CameraRig`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RigBearing {
+    /// The index of the camera in the rig.
+    pub camera: usize,
+    /// The bearing in the coordinates of the camera.
+    pub bearing: UnitVector3<f64>,
+}
+
+/// Two [`RigBearing`] matched together from two separate positions of the rig.
+///
+/// The cameras may differ, which happens when a feature leaves the view of one camera and enters another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RigFeatureMatch(pub RigBearing, pub RigBearing);
+
+/// The motion of a [`CameraRig`] between two positions.
+///
+/// Unlike the relative pose of a single camera, the translation of a rig motion has a metric scale when
+/// the cameras in the rig don't share an optical center.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RigMotion<const N: usize> {
+    pub rig: CameraRig<N>,
+    /// Transforms points from the rig coordinates before the motion into the rig coordinates after it.
+    pub motion: CameraToCamera,
+}
+
+impl<const N: usize> Model<RigFeatureMatch> for RigMotion<N> {
+    fn residual(&self, data: &RigFeatureMatch) -> f64 {
+        let &RigFeatureMatch(a, b) = data;
+        self.rig
+            .relative_pose(self.motion, a.camera, b.camera)
+            .residual(&FeatureMatch(a.bearing, b.bearing))
+    }
+}
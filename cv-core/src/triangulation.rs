@@ -1,5 +1,30 @@
 use crate::{CameraPoint, CameraToCamera, Pose, Projective, WorldPoint, WorldToCamera};
-use nalgebra::UnitVector3;
+use nalgebra::{Matrix2, Matrix3, Matrix3x2, Matrix3x6, Matrix6, UnitVector3, Vector3};
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The uncertainty of an observation used to propagate the covariance of a triangulated point.
+///
+/// See [`TriangulatorObservations::triangulate_observations_covariance`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct ObservationCovariance {
+    /// The variance of the bearing in squared radians, which is assumed to be isotropic.
+    pub bearing: f64,
+    /// The covariance of the [`WorldToCamera`] pose in se(3) (with translation components before so(3) components).
+    pub pose: Matrix6<f64>,
+}
+
+impl ObservationCovariance {
+    /// Creates the uncertainty of an observation from a perfectly known pose.
+    pub fn bearing(bearing: f64) -> Self {
+        Self {
+            bearing,
+            pose: Matrix6::zeros(),
+        }
+    }
+}
 
 /// This trait is for algorithms which allow you to triangulate a point from two or more observances.
 /// Each observance is a [`WorldToCamera`] and a bearing.
@@ -34,6 +59,86 @@ pub trait TriangulatorObservations {
         )
         .map(|p| CameraPoint::from_homogeneous(p.0))
     }
+
+    /// This function triangulates the point in the same way as [`TriangulatorObservations::triangulate_observations`],
+    /// but additionally returns the 3x3 covariance of the point, which is propagated to first order from the
+    /// uncertainty of the bearings and poses of the observations.
+    ///
+    /// This fails if the point is at infinity or the observations do not constrain the point in every direction.
+    fn triangulate_observations_covariance(
+        &self,
+        pairs: impl Iterator<Item = (WorldToCamera, UnitVector3<f64>, ObservationCovariance)> + Clone,
+    ) -> Option<(WorldPoint, Matrix3<f64>)> {
+        let point =
+            self.triangulate_observations(pairs.clone().map(|(pose, bearing, _)| (pose, bearing)))?;
+        let covariance =
+            point_covariance(point, pairs.map(|(pose, _, covariance)| (pose, covariance)))?;
+        Some((point, covariance))
+    }
+}
+
+/// Computes the first order covariance of a triangulated point from the uncertainty of its observations.
+///
+/// Each observation constrains the point in the two directions orthogonal to its bearing, so the information of
+/// each observation is accumulated in that tangent space and the total information is inverted.
+///
+/// ```
+/// use cv_core::nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3};
+/// use cv_core::{point_covariance, ObservationCovariance, Pose, Projective, WorldPoint, WorldToCamera};
+///
+/// let point = WorldPoint::from_point(Point3::new(0.0, 0.0, 5.0));
+/// let poses = [
+///     WorldToCamera::identity(),
+///     WorldToCamera(IsometryMatrix3::from_parts(Vector3::new(-0.5, 0.0, 0.0).into(), Rotation3::identity())),
+/// ];
+/// let noise = ObservationCovariance::bearing(1e-6);
+/// let covariance = point_covariance(point, poses.iter().map(|&pose| (pose, noise))).unwrap();
+/// // With a small baseline the depth is much less certain than the lateral position.
+/// assert!(covariance[(2, 2)] > 10.0 * covariance[(0, 0)]);
+/// ```
+pub fn point_covariance(
+    point: WorldPoint,
+    observations: impl Iterator<Item = (WorldToCamera, ObservationCovariance)>,
+) -> Option<Matrix3<f64>> {
+    let point = point.point()?;
+    let mut information = Matrix3::zeros();
+    for (pose, covariance) in observations {
+        let isometry = pose.isometry();
+        let rotated = isometry.rotation * point.coords;
+        let camera = rotated + isometry.translation.vector;
+        let distance = camera.norm();
+        let direction = camera / distance;
+        let tangent = tangent_basis(direction) / distance;
+        // The Jacobians of the projection of the bearing onto the tangent space in respect to the point and pose.
+        let jacobian_point = tangent.transpose() * isometry.rotation.matrix();
+        let mut jacobian_pose = Matrix3x6::zeros();
+        jacobian_pose
+            .fixed_columns_mut::<3>(0)
+            .copy_from(&Matrix3::identity());
+        jacobian_pose
+            .fixed_columns_mut::<3>(3)
+            .copy_from(&-rotated.cross_matrix());
+        let jacobian_pose = tangent.transpose() * jacobian_pose;
+        // The bearing error is already measured in radians in the tangent space.
+        let noise = Matrix2::identity() * covariance.bearing
+            + jacobian_pose * covariance.pose * jacobian_pose.transpose();
+        information += jacobian_point.transpose() * noise.try_inverse()? * jacobian_point;
+    }
+    information
+        .try_inverse()
+        .filter(|covariance| covariance.iter().all(|n| n.is_finite()))
+}
+
+/// Creates an orthonormal basis of the plane orthogonal to `direction`.
+fn tangent_basis(direction: Vector3<f64>) -> Matrix3x2<f64> {
+    let axis = if direction.x.abs() < 0.5 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    let u = direction.cross(&axis).normalize();
+    let v = direction.cross(&u);
+    Matrix3x2::from_columns(&[u, v])
 }
 
 /// This trait allows you to take one relative pose from camera `A` to camera `B` and two bearings `a` and `b` from
@@ -0,0 +1,226 @@
+//! # `cv-geom-gpu`
+//!
+//! Bulk triangulation on the GPU with [`wgpu`].
+//!
+//! Quasi-dense reconstruction can require triangulating hundreds of thousands of correspondences at once, which is
+//! embarrassingly parallel. [`GpuTriangulator`] triangulates each track of observations in its own invocation of a
+//! compute shader with the same linear eigen method as `cv_geom::triangulation::LinearEigenTriangulator` and
+//! returns the same [`WorldPoint`] and [`CameraPoint`] outputs as the CPU triangulators, including the same checks.
+//!
+//! GPUs generally compute in single precision, so the points are less accurate than on the CPU. Keep the
+//! coordinates of the poses close to the origin, and refine the points afterwards if they need to be precise.
+
+use bytemuck::cast_slice;
+use cv_core::{
+    nalgebra::{UnitVector3, Vector4},
+    CameraPoint, CameraToCamera, FeatureMatch, Pose, Projective, WorldPoint, WorldToCamera,
+};
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// Invocations run per workgroup, which must match the shader.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Triangulates many tracks of observations at once on the GPU.
+pub struct GpuTriangulator {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuTriangulator {
+    /// Creates a triangulator on the default adapter.
+    ///
+    /// Returns `None` if there is no adapter or device available.
+    pub async fn new() -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .await
+            .ok()?;
+        Some(Self::from_device(device, queue))
+    }
+
+    /// Creates a triangulator which shares an existing device, such as the device of a renderer.
+    pub fn from_device(device: wgpu::Device, queue: wgpu::Queue) -> Self {
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("triangulate"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("triangulate.wgsl"))),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("triangulate"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+        });
+        Self {
+            device,
+            queue,
+            pipeline,
+        }
+    }
+
+    /// Triangulates each track of observations, returning `None` for the tracks which fail.
+    ///
+    /// Tracks with fewer than two observations always fail.
+    pub async fn triangulate_tracks<O>(&self, tracks: &[O]) -> Vec<Option<WorldPoint>>
+    where
+        O: AsRef<[(WorldToCamera, UnitVector3<f64>)]>,
+    {
+        let mut observations: Vec<f32> = vec![];
+        let mut ranges: Vec<u32> = vec![];
+        for track in tracks {
+            ranges.push((observations.len() / 16) as u32);
+            ranges.push(track.as_ref().len() as u32);
+            for (pose, bearing) in track.as_ref() {
+                let matrix = pose.homogeneous();
+                observations.extend(matrix.fixed_rows::<3>(0).iter().map(|&n| n as f32));
+                observations.extend(bearing.iter().map(|&n| n as f32));
+                observations.push(0.0);
+            }
+        }
+
+        let points = self.run(&observations, &ranges).await;
+        tracks
+            .iter()
+            .zip(points)
+            .map(|(track, point)| {
+                let track = track.as_ref();
+                Some(WorldPoint::from_homogeneous(point))
+                    .filter(|_| track.len() >= 2)
+                    .filter(|point| {
+                        // Ensure the point contains no NaN or infinity.
+                        point.homogeneous().iter().all(|n| n.is_finite())
+                    })
+                    .filter(|&point| {
+                        // Ensure the cheirality constraint.
+                        track.iter().all(|&(pose, bearing)| {
+                            pose.transform(point)
+                                .bearing()
+                                .dot(&bearing)
+                                .is_sign_positive()
+                        })
+                    })
+            })
+            .collect()
+    }
+
+    /// Triangulates the matches between two cameras in the reference frame of the first camera.
+    pub async fn triangulate_relative(
+        &self,
+        relative_pose: CameraToCamera,
+        matches: &[FeatureMatch],
+    ) -> Vec<Option<CameraPoint>> {
+        let tracks: Vec<[(WorldToCamera, UnitVector3<f64>); 2]> = matches
+            .iter()
+            .map(|&FeatureMatch(a, b)| {
+                [
+                    (WorldToCamera::identity(), a),
+                    (WorldToCamera(relative_pose.0), b),
+                ]
+            })
+            .collect();
+        self.triangulate_tracks(&tracks)
+            .await
+            .into_iter()
+            .map(|point| point.map(|point| CameraPoint::from_homogeneous(point.0)))
+            .collect()
+    }
+
+    /// Runs the shader and reads back the homogeneous point of every track.
+    async fn run(&self, observations: &[f32], ranges: &[u32]) -> Vec<Vector4<f64>> {
+        let tracks = ranges.len() / 2;
+        if tracks == 0 {
+            return vec![];
+        }
+        // Buffers may not be empty, even if every track is.
+        let observations = if observations.is_empty() {
+            &[0.0f32; 16][..]
+        } else {
+            observations
+        };
+        let observations = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("observations"),
+                contents: cast_slice(observations),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let ranges = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("ranges"),
+                contents: cast_slice(ranges),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let size = (tracks * 4 * std::mem::size_of::<f32>()) as wgpu::BufferAddress;
+        let points = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("points"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("triangulate"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: observations.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: ranges.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: points.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch((tracks as u32 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&points, 0, &staging, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        if mapping.await.is_err() {
+            return vec![Vector4::repeat(core::f64::NAN); tracks];
+        }
+        let points = cast_slice::<u8, f32>(&slice.get_mapped_range())
+            .chunks_exact(4)
+            .map(|p| Vector4::new(p[0], p[1], p[2], p[3]).cast::<f64>())
+            .collect();
+        staging.unmap();
+        points
+    }
+}
+
+/// Blocks on the asynchronous methods of [`GpuTriangulator`] for use outside of an async runtime.
+///
+/// ```no_run
+/// use cv_geom_gpu::{block_on, GpuTriangulator};
+/// let triangulator = block_on(GpuTriangulator::new()).expect("no GPU available");
+/// ```
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    pollster::block_on(future)
+}
@@ -0,0 +1,93 @@
+// Triangulates one track per invocation with the linear eigen method.
+//
+// Each observation is 16 floats: the columns of the 3x4 `WorldToCamera` matrix, followed by the bearing and padding.
+// Each track is a `(start, count)` range of observations.
+
+[[block]]
+struct Observations {
+    data: array<f32>;
+};
+
+[[block]]
+struct Ranges {
+    data: array<u32>;
+};
+
+[[block]]
+struct Points {
+    data: array<vec4<f32>>;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> observations: Observations;
+
+[[group(0), binding(1)]]
+var<storage, read> ranges: Ranges;
+
+[[group(0), binding(2)]]
+var<storage, read_write> points: Points;
+
+fn identity() -> mat4x4<f32> {
+    return mat4x4<f32>(
+        vec4<f32>(1.0, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, 1.0, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0)
+    );
+}
+
+fn column(o: u32) -> vec3<f32> {
+    return vec3<f32>(observations.data[o], observations.data[o + 1u], observations.data[o + 2u]);
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let track = id.x;
+    if (track >= arrayLength(&points.data)) {
+        return;
+    }
+    let start = ranges.data[2u * track];
+    let count = ranges.data[2u * track + 1u];
+
+    // Accumulate the normal equations, which project each camera point onto the plane orthogonal to its bearing.
+    var a = mat4x4<f32>(vec4<f32>(0.0), vec4<f32>(0.0), vec4<f32>(0.0), vec4<f32>(0.0));
+    for (var i = 0u; i < count; i = i + 1u) {
+        let o = 16u * (start + i);
+        let pose = mat4x3<f32>(column(o), column(o + 3u), column(o + 6u), column(o + 9u));
+        let b = column(o + 12u);
+        let term = pose - mat3x3<f32>(b * b.x, b * b.y, b * b.z) * pose;
+        a = a + transpose(term) * term;
+    }
+
+    // Find the eigenvectors of the symmetric matrix with cyclic Jacobi rotations.
+    var v = identity();
+    for (var sweep = 0u; sweep < 16u; sweep = sweep + 1u) {
+        for (var p = 0u; p < 3u; p = p + 1u) {
+            for (var q = p + 1u; q < 4u; q = q + 1u) {
+                let apq = a[q][p];
+                if (abs(apq) > 1e-30) {
+                    let theta = (a[q][q] - a[p][p]) / (2.0 * apq);
+                    let t = select(-1.0, 1.0, theta >= 0.0) / (abs(theta) + sqrt(theta * theta + 1.0));
+                    let c = 1.0 / sqrt(t * t + 1.0);
+                    let s = t * c;
+                    var j = identity();
+                    j[p][p] = c;
+                    j[q][q] = c;
+                    j[q][p] = s;
+                    j[p][q] = -s;
+                    a = transpose(j) * a * j;
+                    v = v * j;
+                }
+            }
+        }
+    }
+
+    // The point is the eigenvector with the smallest eigenvalue.
+    var smallest = 0u;
+    for (var i = 1u; i < 4u; i = i + 1u) {
+        if (a[i][i] < a[smallest][smallest]) {
+            smallest = i;
+        }
+    }
+    points.data[track] = v[smallest];
+}
@@ -0,0 +1,74 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point3, Rotation3, UnitVector3, Vector3},
+    CameraPoint, CameraToCamera, FeatureMatch, Pose, Projective, TriangulatorRelative,
+};
+use cv_geom::triangulation::LinearEigenTriangulator;
+use cv_geom_gpu::{block_on, GpuTriangulator};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+const SAMPLE_POINTS: usize = 1000;
+
+const ROT_MAGNITUDE: f64 = 0.2;
+const POINT_BOX_SIZE: f64 = 2.0;
+const POINT_DISTANCE: f64 = 3.0;
+
+#[test]
+fn randomized() {
+    let triangulator = match block_on(GpuTriangulator::new()) {
+        Some(triangulator) => triangulator,
+        None => {
+            eprintln!("skipping GPU triangulation test since no adapter is available");
+            return;
+        }
+    };
+    let mut rng = SmallRng::seed_from_u64(0);
+    let pose = CameraToCamera(IsometryMatrix3::from_parts(
+        Vector3::new(rng.gen(), rng.gen(), rng.gen()).into(),
+        Rotation3::new(
+            Vector3::new(rng.gen(), rng.gen(), rng.gen())
+                * std::f64::consts::PI
+                * 2.0
+                * ROT_MAGNITUDE,
+        ),
+    ));
+    let points: Vec<CameraPoint> = (0..SAMPLE_POINTS)
+        .map(|_| {
+            let mut point = Point3::new(rng.gen(), rng.gen(), rng.gen()) * POINT_BOX_SIZE;
+            point.x -= 0.5 * POINT_BOX_SIZE;
+            point.y -= 0.5 * POINT_BOX_SIZE;
+            point.z += POINT_DISTANCE;
+            CameraPoint::from_point(point)
+        })
+        .collect();
+    let matches: Vec<FeatureMatch> = points
+        .iter()
+        .map(|&point| FeatureMatch(point.bearing(), pose.transform(point).bearing()))
+        .collect();
+
+    let gpu = block_on(triangulator.triangulate_relative(pose, &matches));
+    let successes = gpu
+        .iter()
+        .zip(&matches)
+        .filter(|&(gpu, &FeatureMatch(a, b))| {
+            let cpu = LinearEigenTriangulator::new().triangulate_relative(pose, a, b);
+            match (gpu, cpu) {
+                (Some(gpu), Some(cpu)) => {
+                    (gpu.point().unwrap() - cpu.point().unwrap()).norm() < 1e-2
+                }
+                _ => false,
+            }
+        })
+        .count();
+    eprintln!("successes: {}", successes);
+    assert!(successes > 950);
+
+    // A track with a single observation cannot be triangulated.
+    let single = [(
+        cv_core::WorldToCamera::identity(),
+        UnitVector3::new_normalize(Vector3::z()),
+    )];
+    assert_eq!(
+        block_on(triangulator.triangulate_tracks(&[single])),
+        vec![None]
+    );
+}
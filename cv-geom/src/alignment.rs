@@ -0,0 +1,158 @@
+//! Alignment of two sets of corresponding 3d points.
+//!
+//! The same points reconstructed in two coordinate frames, such as the two sides of a loop in a monocular map or two
+//! separately built maps, differ by a similarity. The [`Umeyama`] estimator recovers it in closed form from three or
+//! more correspondences, and can be used with sample consensus to reject wrong correspondences.
+
+use cv_core::{
+    nalgebra::{Matrix3, Point3, Rotation3, Vector3},
+    sample_consensus::{Estimator, Model},
+    Sim3,
+};
+
+/// A correspondence between a point in the source frame and the same point in the target frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PointMatch(pub Point3<f64>, pub Point3<f64>);
+
+/// The residual is the distance from the transformed source point to the target point.
+impl Model<PointMatch> for Sim3 {
+    fn residual(&self, data: &PointMatch) -> f64 {
+        let &PointMatch(source, target) = data;
+        (self.0 * source - target).norm()
+    }
+}
+
+/// Estimates the [`Sim3`] from the source frame to the target frame of [`PointMatch`]es with the method of Umeyama.
+///
+/// This minimizes the sum of squared distances between the transformed source points and the target points. With
+/// [`Umeyama::scale`] disabled, the scale is held at `1.0` and a rigid transformation is estimated instead, such as
+/// when both frames have a metric scale.
+///
+/// ```
+/// use cv_core::{nalgebra::{Point3, Rotation3, Vector3}, sample_consensus::Estimator, Sim3};
+/// use cv_geom::alignment::{PointMatch, Umeyama};
+///
+/// let sim3 = Sim3::from_parts(Vector3::new(0.5, -1.0, 2.0), Rotation3::from_euler_angles(0.1, -0.3, 0.7), 2.5);
+/// let matches: Vec<PointMatch> = [
+///     Point3::new(0.0, 0.0, 0.0),
+///     Point3::new(1.0, 0.2, -0.4),
+///     Point3::new(-0.3, 1.1, 0.6),
+///     Point3::new(0.4, -0.8, 1.5),
+/// ]
+/// .iter()
+/// .map(|&point| PointMatch(point, sim3.0 * point))
+/// .collect();
+///
+/// let estimate = Umeyama::new().estimate(matches.iter().copied()).unwrap();
+/// assert!((estimate.scale() - 2.5).abs() < 1e-9);
+/// assert!((estimate.translation() - sim3.translation()).norm() < 1e-9);
+/// assert!(estimate.rotation().rotation_to(&sim3.rotation()).angle() < 1e-9);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Umeyama {
+    scale: bool,
+    epsilon: f64,
+    max_iterations: usize,
+}
+
+impl Umeyama {
+    /// Creates an `Umeyama` estimator with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set whether the scale is estimated.
+    ///
+    /// Default is `true`.
+    #[must_use]
+    pub fn scale(self, scale: bool) -> Self {
+        Self { scale, ..self }
+    }
+
+    /// Set the epsilon used in the SVD solver.
+    ///
+    /// Default is `1e-12`.
+    #[must_use]
+    pub fn epsilon(self, epsilon: f64) -> Self {
+        Self { epsilon, ..self }
+    }
+
+    /// Set the maximum number of iterations for the SVD solver.
+    ///
+    /// Default is `1000`.
+    #[must_use]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+}
+
+impl Default for Umeyama {
+    fn default() -> Self {
+        Self {
+            scale: true,
+            epsilon: 1e-12,
+            max_iterations: 1000,
+        }
+    }
+}
+
+impl Estimator<PointMatch> for Umeyama {
+    type Model = Sim3;
+    type ModelIter = Option<Sim3>;
+    const MIN_SAMPLES: usize = 3;
+
+    fn estimate<I>(&self, data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = PointMatch> + Clone,
+    {
+        let count = data.clone().count();
+        if count < Self::MIN_SAMPLES {
+            return None;
+        }
+        let n = count as f64;
+        let (source_sum, target_sum) = data.clone().fold(
+            (Vector3::zeros(), Vector3::zeros()),
+            |(source_sum, target_sum), PointMatch(source, target)| {
+                (source_sum + source.coords, target_sum + target.coords)
+            },
+        );
+        let source_mean = source_sum / n;
+        let target_mean = target_sum / n;
+
+        let mut covariance = Matrix3::zeros();
+        let mut source_variance = 0.0;
+        for PointMatch(source, target) in data {
+            let source = source.coords - source_mean;
+            let target = target.coords - target_mean;
+            covariance += target * source.transpose();
+            source_variance += source.norm_squared();
+        }
+        covariance /= n;
+        source_variance /= n;
+        if source_variance <= 0.0 {
+            return None;
+        }
+
+        let svd = covariance.try_svd(true, true, self.epsilon, self.max_iterations)?;
+        let (u, v_t) = (svd.u?, svd.v_t?);
+        // Flip the axis of the smallest singular value if needed, so that the result is a rotation, not a reflection.
+        let mut signs = Vector3::new(1.0, 1.0, 1.0);
+        if u.determinant() * v_t.determinant() < 0.0 {
+            let smallest = svd.singular_values.imin();
+            signs[smallest] = -1.0;
+        }
+        let rotation = Rotation3::from_matrix_unchecked(u * Matrix3::from_diagonal(&signs) * v_t);
+        let scale = if self.scale {
+            svd.singular_values.dot(&signs) / source_variance
+        } else {
+            1.0
+        };
+        let translation = target_mean - scale * (rotation * source_mean);
+        (scale.is_normal() && scale > 0.0).then(|| Sim3::from_parts(translation, rotation, scale))
+    }
+}
@@ -0,0 +1,152 @@
+//! Incremental estimation of the depth of a feature from successive observations.
+//!
+//! When the camera moves forwards or barely moves between frames, the parallax of a feature accrues slowly and any
+//! one-shot triangulation is dominated by noise. Instead, as in SVO and DSO, an [`InverseDepthFilter`] is created
+//! when a feature is first detected in a reference frame and every later observation of the feature refines a
+//! Gaussian estimate of its inverse depth along the reference bearing. Inverse depth is used because it is well
+//! behaved for distant points, which have an inverse depth close to zero instead of an unbounded depth.
+
+use cv_core::{
+    nalgebra::{Rotation3, UnitVector3, Vector3},
+    CameraPoint, CameraToCamera, Pose, Projective,
+};
+
+/// A Gaussian estimate of the inverse depth of a feature along its bearing in a reference camera.
+///
+/// ```
+/// use cv_core::nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3};
+/// use cv_core::{CameraPoint, CameraToCamera, Pose, Projective};
+/// use cv_geom::depth_filter::InverseDepthFilter;
+///
+/// let point = CameraPoint::from_point(Point3::new(0.2, -0.1, 4.0));
+/// // Start with a guess of the average scene depth and a large variance.
+/// let mut filter = InverseDepthFilter::new(point.bearing(), 0.5, 1.0);
+/// // The camera slowly moves forwards.
+/// for i in 1..=20 {
+///     let pose = CameraToCamera(IsometryMatrix3::from_parts(
+///         Vector3::new(0.002 * i as f64, 0.0, -0.05 * i as f64).into(),
+///         Rotation3::identity(),
+///     ));
+///     filter.update(pose, pose.transform(point).bearing(), 1e-3);
+/// }
+/// assert!((filter.inverse_depth - 0.25).abs() < 1e-2);
+/// assert!(filter.converged(1e-2));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InverseDepthFilter {
+    /// The bearing of the feature in the reference camera.
+    pub bearing: UnitVector3<f64>,
+    /// The mean of the inverse of the distance to the feature along the bearing.
+    pub inverse_depth: f64,
+    /// The variance of the inverse depth.
+    pub variance: f64,
+    /// The number of observations which were fused into the estimate.
+    pub observations: usize,
+    /// Observations whose inverse depth is further than this many standard deviations from the estimate are
+    /// rejected as outliers.
+    pub gate: f64,
+}
+
+impl InverseDepthFilter {
+    /// Creates a filter for the `bearing` in the reference camera with an initial guess of the inverse depth.
+    ///
+    /// The initial guess is normally the inverse of the average depth of the scene with a variance which covers
+    /// the whole range of depths in the scene.
+    pub fn new(bearing: UnitVector3<f64>, inverse_depth: f64, variance: f64) -> Self {
+        Self {
+            bearing,
+            inverse_depth,
+            variance,
+            observations: 0,
+            gate: 3.0,
+        }
+    }
+
+    /// Set the number of standard deviations beyond which observations are rejected.
+    ///
+    /// Default is `3.0`.
+    #[must_use]
+    pub fn gate(self, gate: f64) -> Self {
+        Self { gate, ..self }
+    }
+
+    /// The standard deviation of the inverse depth.
+    pub fn standard_deviation(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    /// Checks if the standard deviation of the inverse depth is below `max_standard_deviation`, at which point the
+    /// feature may be inserted into the map.
+    pub fn converged(&self, max_standard_deviation: f64) -> bool {
+        self.variance < max_standard_deviation * max_standard_deviation
+    }
+
+    /// The point in the reference camera at the current estimate of the inverse depth.
+    pub fn point(&self) -> CameraPoint {
+        CameraPoint::from_homogeneous(self.bearing.into_inner().push(self.inverse_depth))
+    }
+
+    /// Fuses a new observation of the feature into the estimate.
+    ///
+    /// The `relative_pose` transforms the reference camera into the camera which observed the feature along
+    /// `bearing`, and `noise` is the standard deviation of the angle of the bearing in radians.
+    ///
+    /// Returns `false` if the observation was rejected, either because there is no parallax to triangulate the
+    /// feature or because it is an outlier compared to the current estimate.
+    pub fn update(
+        &mut self,
+        relative_pose: CameraToCamera,
+        bearing: UnitVector3<f64>,
+        noise: f64,
+    ) -> bool {
+        let (inverse_depth, variance) = match self.measure(relative_pose, bearing, noise) {
+            Some(measurement) => measurement,
+            None => return false,
+        };
+        let difference = inverse_depth - self.inverse_depth;
+        let total_variance = self.variance + variance;
+        if difference * difference > self.gate * self.gate * total_variance {
+            return false;
+        }
+        // Fuse the two Gaussians.
+        self.inverse_depth += difference * self.variance / total_variance;
+        self.variance = self.variance * variance / total_variance;
+        self.observations += 1;
+        true
+    }
+
+    /// Triangulates the inverse depth from a single observation along with its variance from the bearing noise.
+    fn measure(
+        &self,
+        relative_pose: CameraToCamera,
+        bearing: UnitVector3<f64>,
+        noise: f64,
+    ) -> Option<(f64, f64)> {
+        // Work in the reference camera, where the observation was made from `center` along `bearing`.
+        let isometry = relative_pose.inverse().isometry();
+        let center = isometry.translation.vector;
+        let bearing = isometry.rotation * bearing.into_inner();
+        let inverse_depth = self.triangulate(center, bearing)?;
+
+        // Rotate the bearing by the noise within the epipolar plane in both directions to find how far the
+        // inverse depth moves.
+        let axis = UnitVector3::try_new(self.bearing.cross(&center), 1e-12)?;
+        let deviation = [noise, -noise]
+            .iter()
+            .map(|&angle| {
+                self.triangulate(center, Rotation3::from_axis_angle(&axis, angle) * bearing)
+                    .map(|perturbed| (perturbed - inverse_depth).abs())
+                    .unwrap_or(inverse_depth)
+            })
+            .fold(0.0, f64::max);
+        Some((inverse_depth, deviation * deviation)).filter(|&(_, variance)| variance > 0.0)
+    }
+
+    /// Finds the inverse depth along the reference bearing that intersects the ray from `center` along `bearing`.
+    fn triangulate(&self, center: Vector3<f64>, bearing: Vector3<f64>) -> Option<f64> {
+        // Solve `depth * f = center + s * bearing` by crossing both sides with `bearing`.
+        let z = self.bearing.cross(&bearing);
+        Some(z.norm_squared() / center.cross(&bearing).dot(&z))
+            .filter(|inverse_depth| inverse_depth.is_finite() && inverse_depth.is_sign_positive())
+    }
+}
@@ -28,5 +28,7 @@
 
 #![no_std]
 
+pub mod alignment;
+pub mod depth_filter;
 pub mod epipolar;
 pub mod triangulation;
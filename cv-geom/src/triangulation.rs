@@ -4,6 +4,8 @@ use cv_core::{
     CameraPoint, CameraToCamera, Pose, Projective, TriangulatorObservations, TriangulatorRelative,
     WorldPoint, WorldToCamera,
 };
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 /// This is a very quick triangulator to execute, but it is not particularly suitable for optimization.
 /// It can be used for optimization when you have very low error to begin with.
@@ -127,6 +129,156 @@ impl TriangulatorObservations for LinearEigenTriangulator {
     }
 }
 
+/// Triangulates a point from any number of observations by solving the homogeneous direct linear transform (DLT)
+/// system with SVD.
+///
+/// Each observation contributes the equations `[b]× [R | t] X = 0`, which state that the
+/// transformed point must be parallel to the observed bearing `b`. The normal equations of all observations are
+/// accumulated into a 4x4 matrix, so long feature tracks are as cheap to solve as two-view problems, and the
+/// triangulated homogeneous point is the right singular vector corresponding to the smallest singular value.
+///
+/// Unlike [`LinearEigenTriangulator`], this rejects degenerate systems. A point is only returned if there are at
+/// least [`DltTriangulator::min_observations`] observations and the ratio of the largest singular value to the
+/// second smallest singular value is no more than [`DltTriangulator::max_condition`]. A large ratio means that the
+/// null space is more than one dimensional, such as when all of the cameras lie on the observed ray.
+///
+/// ```
+/// use cv_core::nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3};
+/// use cv_core::{TriangulatorObservations, WorldPoint, WorldToCamera, Pose, Projective};
+/// use cv_geom::triangulation::DltTriangulator;
+///
+/// let point = WorldPoint::from_point(Point3::new(0.3, 0.1, 4.0));
+/// let poses = [
+///     WorldToCamera::identity(),
+///     WorldToCamera(IsometryMatrix3::from_parts(Vector3::new(0.2, 0.0, 0.0).into(), Rotation3::new(Vector3::new(0.0, 0.05, 0.0)))),
+///     WorldToCamera(IsometryMatrix3::from_parts(Vector3::new(0.4, 0.1, 0.0).into(), Rotation3::new(Vector3::new(0.02, 0.1, 0.0)))),
+///     WorldToCamera(IsometryMatrix3::from_parts(Vector3::new(0.6, -0.1, 0.1).into(), Rotation3::new(Vector3::new(-0.02, 0.15, 0.01)))),
+/// ];
+/// let observations = poses.iter().map(|&pose| (pose, pose.transform(point).bearing()));
+/// let triangulated = DltTriangulator::new().triangulate_observations(observations).unwrap();
+/// let distance = (point.point().unwrap() - triangulated.point().unwrap()).norm();
+/// assert!(distance < 1e-6);
+///
+/// // A single observation does not constrain the depth of the point.
+/// let observations = poses.iter().take(1).map(|&pose| (pose, pose.transform(point).bearing()));
+/// assert!(DltTriangulator::new().triangulate_observations(observations).is_none());
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct DltTriangulator {
+    epsilon: f64,
+    max_iterations: usize,
+    min_observations: usize,
+    max_condition: f64,
+}
+
+impl DltTriangulator {
+    /// Creates a `DltTriangulator` with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the epsilon used in the SVD solver.
+    ///
+    /// Default is `1e-12`.
+    #[must_use]
+    pub fn epsilon(self, epsilon: f64) -> Self {
+        Self { epsilon, ..self }
+    }
+
+    /// Set the maximum number of iterations for the SVD solver.
+    ///
+    /// Default is `1000`.
+    #[must_use]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    /// Set the minimum number of observations required to triangulate a point.
+    ///
+    /// This cannot be less than `2`, since one observation does not constrain the depth of the point.
+    ///
+    /// Default is `2`.
+    #[must_use]
+    pub fn min_observations(self, min_observations: usize) -> Self {
+        Self {
+            min_observations: min_observations.max(2),
+            ..self
+        }
+    }
+
+    /// Set the maximum ratio of the largest singular value to the second smallest singular value of the DLT system.
+    ///
+    /// Default is `1e8`.
+    #[must_use]
+    pub fn max_condition(self, max_condition: f64) -> Self {
+        Self {
+            max_condition,
+            ..self
+        }
+    }
+}
+
+impl Default for DltTriangulator {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-12,
+            max_iterations: 1000,
+            min_observations: 2,
+            max_condition: 1e8,
+        }
+    }
+}
+
+impl TriangulatorObservations for DltTriangulator {
+    fn triangulate_observations(
+        &self,
+        mut pairs: impl Iterator<Item = (WorldToCamera, UnitVector3<f64>)> + Clone,
+    ) -> Option<WorldPoint> {
+        if pairs.clone().count() < self.min_observations {
+            return None;
+        }
+
+        let mut normal: Matrix4<f64> = zero();
+        for (pose, bearing) in pairs.clone() {
+            // The rows of `[b]× [R | t]`.
+            let term = bearing.into_inner().cross_matrix() * pose.homogeneous().fixed_rows::<3>(0);
+            normal += term.transpose() * term;
+        }
+
+        let svd = normal.try_svd(false, true, self.epsilon, self.max_iterations)?;
+        let mut order = [0, 1, 2, 3];
+        order.sort_unstable_by_key(|&ix| float_ord::FloatOrd(svd.singular_values[ix]));
+
+        // The singular values of the normal matrix are the squares of those of the DLT system.
+        let condition = (svd.singular_values[order[3]] / svd.singular_values[order[1]]).sqrt();
+        if !(condition <= self.max_condition) {
+            return None;
+        }
+
+        Some(WorldPoint::from_homogeneous(
+            svd.v_t?.row(order[0]).transpose().into_owned(),
+        ))
+        .filter(|point| {
+            // Ensure the point contains no NaN or infinity.
+            point.homogeneous().iter().all(|n| n.is_finite())
+        })
+        .filter(|&point| {
+            // Ensure the cheirality constraint.
+            pairs.all(|(pose, bearing)| {
+                pose.transform(point)
+                    .bearing()
+                    .dot(&bearing)
+                    .is_sign_positive()
+            })
+        })
+    }
+}
+
 /// This is a very quick triangulator to execute, but it is not particularly suitable for optimization.
 /// It can be used for optimization when you have very low error to begin with.
 /// It is suitable for quickly generating 3d point outputs, such as for display purposes.
@@ -604,3 +756,602 @@ impl TriangulatorRelative for AngularLInfinityTriangulator {
         })
     }
 }
+
+/// From the paper "Triangulation Made Easy" by Peter Lindstrom, which computes the same optimal two-view
+/// triangulation as Hartley and Sturm in the paper
+/// ["Triangulation"](https://users.cecs.anu.edu.au/~hartley/Papers/triangulation/triangulation.pdf).
+///
+/// It triangulates by minimizing the L2 reprojection error on the normalized image plane. Both keypoints are
+/// corrected by the smallest squared distance so that they exactly satisfy the epipolar constraint, and the
+/// corrected bearings, which then intersect, are intersected exactly. This uses the two iteration `niter2` variant,
+/// which converges to the optimum for all practical purposes and does not need a polynomial solver.
+///
+/// Since the error is measured on the image plane, this is not suitable for camera models with very high FoV,
+/// and bearings pointing backwards are rejected.
+///
+/// # Example
+/// ```
+/// use cv_geom::triangulation::OptimalL2Triangulator;
+/// use cv_core::{nalgebra::{Vector3, Rotation3}, CameraToCamera, Pose, Projective, CameraPoint, TriangulatorRelative};
+/// // Create a pose.
+/// let pose = CameraToCamera::from_parts(Vector3::new(0.1, 0.1, 0.1), Rotation3::from_scaled_axis(Vector3::new(0.1, 0.1, 0.1)));
+/// // Create a point in front of both cameras and between both cameras.
+/// let real_point = CameraPoint::from_point(Vector3::new(0.3, 0.1, 2.0).into());
+/// // Turn the points into bearings in each camera and try to triangulate the point again.
+/// let triangulated_point = OptimalL2Triangulator.triangulate_relative(
+///     pose,
+///     real_point.bearing(),
+///     pose.transform(real_point).bearing()
+/// ).unwrap().point().unwrap();
+/// // Verify that the point is approximately equal.
+/// let real_point = real_point.point().unwrap();
+/// assert!((real_point - triangulated_point).norm() < 1e-6, "real_point: {}, triangulated_point: {}", real_point, triangulated_point);
+/// ```
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OptimalL2Triangulator;
+
+impl TriangulatorRelative for OptimalL2Triangulator {
+    fn triangulate_relative(
+        &self,
+        relative_pose: CameraToCamera,
+        a: UnitVector3<f64>,
+        b: UnitVector3<f64>,
+    ) -> Option<CameraPoint> {
+        if a.z <= 0.0 || b.z <= 0.0 {
+            return None;
+        }
+        let rotation = *relative_pose.isometry().rotation.matrix();
+        let translation = relative_pose.isometry().translation.vector;
+        // The essential matrix satisfying `b^T E a = 0` on the normalized image plane.
+        let essential = translation.cross_matrix() * rotation;
+        let essential_2x2 = essential.fixed_slice::<2, 2>(0, 0).into_owned();
+        let xa = a.into_inner() / a.z;
+        let xb = b.into_inner() / b.z;
+
+        // Algorithm `niter2` from the paper, where `x` is `xb` and `x'` is `xa`.
+        let mut nb = (essential * xa).xy();
+        let mut na = (essential.transpose() * xb).xy();
+        let c = xb.dot(&(essential * xa));
+        let p = nb.dot(&(essential_2x2 * na));
+        let q = 0.5 * (nb.norm_squared() + na.norm_squared());
+        let d = (q * q - p * c).sqrt();
+        let mut lambda = c / (q + d);
+        let delta_b = lambda * nb;
+        let delta_a = lambda * na;
+        nb -= essential_2x2 * delta_a;
+        na -= essential_2x2.transpose() * delta_b;
+        lambda *= 2.0 * d / (nb.norm_squared() + na.norm_squared());
+        let xa = xa - (lambda * na).push(0.0);
+        let xb = xb - (lambda * nb).push(0.0);
+
+        // The corrected bearings now intersect, so solve `depth * R a + t = s b` for the depth in camera A.
+        let rotated = rotation * xa;
+        let z = rotated.cross(&xb);
+        Some(CameraPoint::from_homogeneous(
+            xa.push(z.norm_squared() / z.dot(&xb.cross(&translation))),
+        ))
+        .filter(|point| {
+            // Ensure the point contains no NaN or infinity.
+            point.homogeneous().iter().all(|n| n.is_finite())
+        })
+        .filter(|&point| {
+            // Ensure the cheirality constraint.
+            point.bearing().dot(&a).is_sign_positive()
+                && relative_pose
+                    .transform(point)
+                    .bearing()
+                    .dot(&b)
+                    .is_sign_positive()
+        })
+    }
+}
+
+/// Triangulates many points at once, such as all of the new landmarks of a keyframe.
+///
+/// Each element of `observations` is the set of observations of one point, and the triangulated point (or `None`
+/// on failure) is written to the same index of `output`, which must have the same length. The output is passed in
+/// so that it can be reused between batches. With the `rayon` feature enabled the points are triangulated in
+/// parallel.
+///
+/// ```
+/// use cv_core::nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3};
+/// use cv_core::{Pose, Projective, WorldPoint, WorldToCamera};
+/// use cv_geom::triangulation::{triangulate_batch, DltTriangulator};
+///
+/// let poses = [
+///     WorldToCamera::identity(),
+///     WorldToCamera(IsometryMatrix3::from_parts(Vector3::new(0.3, 0.0, 0.0).into(), Rotation3::identity())),
+/// ];
+/// let points: Vec<WorldPoint> = (0..8)
+///     .map(|i| WorldPoint::from_point(Point3::new(0.1 * i as f64, -0.2, 3.0 + i as f64)))
+///     .collect();
+/// let observations: Vec<Vec<_>> = points
+///     .iter()
+///     .map(|&point| poses.iter().map(|&pose| (pose, pose.transform(point).bearing())).collect())
+///     .collect();
+/// let mut output = vec![None; points.len()];
+/// triangulate_batch(&DltTriangulator::new(), &observations, &mut output);
+/// for (point, triangulated) in points.iter().zip(&output) {
+///     let distance = (point.point().unwrap() - triangulated.unwrap().point().unwrap()).norm();
+///     assert!(distance < 1e-6);
+/// }
+/// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", name = "triangulate_batch", skip_all, fields(points = observations.len(), triangulated))
+)]
+pub fn triangulate_batch<T, O>(
+    triangulator: &T,
+    observations: &[O],
+    output: &mut [Option<WorldPoint>],
+) where
+    T: TriangulatorObservations + Sync,
+    O: AsRef<[(WorldToCamera, UnitVector3<f64>)]> + Sync,
+{
+    assert_eq!(
+        observations.len(),
+        output.len(),
+        "the output must have one element per set of observations"
+    );
+    let triangulate = |(observations, output): (&O, &mut Option<WorldPoint>)| {
+        *output = triangulator.triangulate_observations(observations.as_ref().iter().copied());
+    };
+    #[cfg(feature = "rayon")]
+    observations
+        .par_iter()
+        .zip(output.par_iter_mut())
+        .for_each(triangulate);
+    #[cfg(not(feature = "rayon"))]
+    observations
+        .iter()
+        .zip(output.iter_mut())
+        .for_each(triangulate);
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record(
+        "triangulated",
+        &output.iter().filter(|point| point.is_some()).count(),
+    );
+}
+
+/// The reason that a point was rejected by a [`TriangulationFilter`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TriangulationRejection {
+    /// The triangulator failed to produce a point.
+    ///
+    /// Triangulators already enforce cheirality and reject degenerate systems, so this includes those failures.
+    Failed,
+    /// The point is behind one of the cameras.
+    Cheirality,
+    /// There is too little parallax between the observations to determine the depth of the point.
+    Parallax,
+    /// The reprojection error of one of the observations is too large.
+    Reprojection,
+    /// The point is too far away from one of the cameras, or is at infinity.
+    Distance,
+}
+
+impl core::fmt::Display for TriangulationRejection {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Failed => "triangulation failed",
+            Self::Cheirality => "point is behind a camera",
+            Self::Parallax => "insufficient parallax",
+            Self::Reprojection => "reprojection error too large",
+            Self::Distance => "point too far from a camera",
+        })
+    }
+}
+
+/// Configurable validity checks of a triangulated point, which report the [`TriangulationRejection`] of the first
+/// check that fails.
+///
+/// Like the settings of `cv-sfm`, the angular checks are measured in cosine distance (`1 - cos(θ)`) between bearings:
+///
+/// * The incidence cosine distance is the largest cosine distance between the rays from any two of the camera
+///   centers to the point, which is small when there is too little parallax to determine the depth.
+/// * The reprojection cosine distance of an observation is the cosine distance between the observed bearing and
+///   the bearing of the triangulated point in that camera.
+///
+/// The checks are performed in the order of the variants of [`TriangulationRejection`].
+///
+/// ```
+/// use cv_core::nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3};
+/// use cv_core::{WorldPoint, WorldToCamera, Pose, Projective};
+/// use cv_geom::triangulation::{TriangulationFilter, TriangulationRejection};
+///
+/// let point = WorldPoint::from_point(Point3::new(0.0, 0.0, 100.0));
+/// let poses = [
+///     WorldToCamera::identity(),
+///     WorldToCamera(IsometryMatrix3::from_parts(Vector3::new(0.01, 0.0, 0.0).into(), Rotation3::identity())),
+/// ];
+/// let observations = poses.iter().map(|&pose| (pose, pose.transform(point).bearing()));
+/// let filter = TriangulationFilter::new().max_distance(50.0);
+/// assert_eq!(filter.check(point, observations.clone()), Err(TriangulationRejection::Distance));
+/// let filter = TriangulationFilter::new().min_incidence_cosine_distance(1e-6);
+/// assert_eq!(filter.check(point, observations.clone()), Err(TriangulationRejection::Parallax));
+/// assert_eq!(TriangulationFilter::new().check(point, observations), Ok(()));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct TriangulationFilter {
+    cheirality: bool,
+    min_incidence_cosine_distance: f64,
+    max_cosine_distance: f64,
+    max_distance: f64,
+}
+
+impl TriangulationFilter {
+    /// Creates a `TriangulationFilter` with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set whether points behind any of the cameras are rejected.
+    ///
+    /// Default is `true`.
+    #[must_use]
+    pub fn cheirality(self, cheirality: bool) -> Self {
+        Self { cheirality, ..self }
+    }
+
+    /// Set the minimum incidence cosine distance between the rays of two observations.
+    ///
+    /// Default is `0.0`.
+    #[must_use]
+    pub fn min_incidence_cosine_distance(self, min_incidence_cosine_distance: f64) -> Self {
+        Self {
+            min_incidence_cosine_distance,
+            ..self
+        }
+    }
+
+    /// Set the maximum reprojection cosine distance of any observation.
+    ///
+    /// Default is infinity.
+    #[must_use]
+    pub fn max_cosine_distance(self, max_cosine_distance: f64) -> Self {
+        Self {
+            max_cosine_distance,
+            ..self
+        }
+    }
+
+    /// Set the maximum distance from any of the cameras to the point.
+    ///
+    /// Points at infinity are only accepted when this is infinite.
+    ///
+    /// Default is infinity.
+    #[must_use]
+    pub fn max_distance(self, max_distance: f64) -> Self {
+        Self {
+            max_distance,
+            ..self
+        }
+    }
+
+    /// Checks a triangulated point against the observations it was triangulated from.
+    pub fn check(
+        &self,
+        point: WorldPoint,
+        mut pairs: impl Iterator<Item = (WorldToCamera, UnitVector3<f64>)> + Clone,
+    ) -> Result<(), TriangulationRejection> {
+        if self.cheirality
+            && !pairs.clone().all(|(pose, bearing)| {
+                pose.transform(point)
+                    .bearing()
+                    .dot(&bearing)
+                    .is_sign_positive()
+            })
+        {
+            return Err(TriangulationRejection::Cheirality);
+        }
+        if incidence_cosine_distance(point, pairs.clone().map(|(pose, _)| pose))
+            < self.min_incidence_cosine_distance
+        {
+            return Err(TriangulationRejection::Parallax);
+        }
+        if !pairs.clone().all(|(pose, bearing)| {
+            1.0 - pose.transform(point).bearing().dot(&bearing) <= self.max_cosine_distance
+        }) {
+            return Err(TriangulationRejection::Reprojection);
+        }
+        if self.max_distance.is_finite() {
+            let point = point.point().ok_or(TriangulationRejection::Distance)?;
+            if !pairs.all(|(pose, _)| {
+                let center = pose.inverse().isometry().translation.vector;
+                (point.coords - center).norm() <= self.max_distance
+            }) {
+                return Err(TriangulationRejection::Distance);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for TriangulationFilter {
+    fn default() -> Self {
+        Self {
+            cheirality: true,
+            min_incidence_cosine_distance: 0.0,
+            max_cosine_distance: core::f64::INFINITY,
+            max_distance: core::f64::INFINITY,
+        }
+    }
+}
+
+/// Wraps any [`TriangulatorObservations`] to reject points with a [`TriangulationFilter`], so the same checks can
+/// be shared by every triangulation strategy.
+///
+/// ```
+/// use cv_core::nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3};
+/// use cv_core::{TriangulatorObservations, WorldPoint, WorldToCamera, Pose, Projective};
+/// use cv_geom::triangulation::{CheckedTriangulator, DltTriangulator, TriangulationRejection};
+///
+/// let point = WorldPoint::from_point(Point3::new(0.0, 0.0, 100.0));
+/// let poses = [
+///     WorldToCamera::identity(),
+///     WorldToCamera(IsometryMatrix3::from_parts(Vector3::new(0.01, 0.0, 0.0).into(), Rotation3::identity())),
+/// ];
+/// let observations = poses.iter().map(|&pose| (pose, pose.transform(point).bearing()));
+/// // The point is triangulated, but the baseline is far too small compared to its distance.
+/// assert!(DltTriangulator::new().triangulate_observations(observations.clone()).is_some());
+/// let checked = CheckedTriangulator::new(DltTriangulator::new()).min_incidence_cosine_distance(1e-6);
+/// assert!(checked.triangulate_observations(observations.clone()).is_none());
+/// assert_eq!(checked.triangulate_checked(observations), Err(TriangulationRejection::Parallax));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct CheckedTriangulator<T> {
+    triangulator: T,
+    filter: TriangulationFilter,
+}
+
+impl<T> CheckedTriangulator<T> {
+    /// Wraps a triangulator with the default [`TriangulationFilter`], which only checks cheirality.
+    pub fn new(triangulator: T) -> Self {
+        Self {
+            triangulator,
+            filter: TriangulationFilter::new(),
+        }
+    }
+
+    /// Set the filter used to check the triangulated points.
+    #[must_use]
+    pub fn filter(self, filter: TriangulationFilter) -> Self {
+        Self { filter, ..self }
+    }
+
+    /// Set the minimum incidence cosine distance of the filter.
+    ///
+    /// See [`TriangulationFilter::min_incidence_cosine_distance`].
+    #[must_use]
+    pub fn min_incidence_cosine_distance(self, min_incidence_cosine_distance: f64) -> Self {
+        Self {
+            filter: self
+                .filter
+                .min_incidence_cosine_distance(min_incidence_cosine_distance),
+            ..self
+        }
+    }
+
+    /// Set the maximum reprojection cosine distance of the filter.
+    ///
+    /// See [`TriangulationFilter::max_cosine_distance`].
+    #[must_use]
+    pub fn max_cosine_distance(self, max_cosine_distance: f64) -> Self {
+        Self {
+            filter: self.filter.max_cosine_distance(max_cosine_distance),
+            ..self
+        }
+    }
+
+    /// Retrieve the wrapped triangulator.
+    pub fn into_inner(self) -> T {
+        self.triangulator
+    }
+}
+
+impl<T> CheckedTriangulator<T>
+where
+    T: TriangulatorObservations,
+{
+    /// Triangulates the point like [`TriangulatorObservations::triangulate_observations`], but reports why the
+    /// point was rejected.
+    pub fn triangulate_checked(
+        &self,
+        pairs: impl Iterator<Item = (WorldToCamera, UnitVector3<f64>)> + Clone,
+    ) -> Result<WorldPoint, TriangulationRejection> {
+        let point = self
+            .triangulator
+            .triangulate_observations(pairs.clone())
+            .ok_or(TriangulationRejection::Failed)?;
+        self.filter.check(point, pairs)?;
+        Ok(point)
+    }
+}
+
+/// The largest cosine distance between the rays from the camera centers to the point of any two observations.
+pub fn incidence_cosine_distance(
+    point: WorldPoint,
+    poses: impl Iterator<Item = WorldToCamera> + Clone,
+) -> f64 {
+    let homogeneous = point.homogeneous();
+    // The ray from the camera center to the point, which also works for points at infinity.
+    let ray = |pose: WorldToCamera| {
+        let center = pose.inverse().isometry().translation.vector;
+        (homogeneous.xyz() - center * homogeneous.w).normalize()
+    };
+    poses
+        .clone()
+        .enumerate()
+        .flat_map(|(ix, a)| {
+            poses
+                .clone()
+                .skip(ix + 1)
+                .map(move |b| 1.0 - ray(a).dot(&ray(b)))
+        })
+        .fold(0.0, f64::max)
+}
+
+impl<T> TriangulatorObservations for CheckedTriangulator<T>
+where
+    T: TriangulatorObservations,
+{
+    fn triangulate_observations(
+        &self,
+        pairs: impl Iterator<Item = (WorldToCamera, UnitVector3<f64>)> + Clone,
+    ) -> Option<WorldPoint> {
+        self.triangulate_checked(pairs).ok()
+    }
+}
+
+/// Triangulates a multi-frame feature track while rejecting observations from wrong matches.
+///
+/// Every pair of observations is triangulated with the wrapped triangulator as a hypothesis, like an exhaustive
+/// RANSAC, and each hypothesis is scored by the number of observations with a reprojection cosine distance below
+/// [`RobustTrackTriangulator::max_cosine_distance`]. The final point is triangulated from the inliers of the best
+/// hypothesis using all of them. Since there are few observations in a track, trying every pair is cheap and
+/// deterministic.
+///
+/// ```
+/// use cv_core::nalgebra::{IsometryMatrix3, Point3, Rotation3, UnitVector3, Vector3};
+/// use cv_core::{TriangulatorObservations, WorldPoint, WorldToCamera, Pose, Projective};
+/// use cv_geom::triangulation::{DltTriangulator, RobustTrackTriangulator};
+///
+/// let point = WorldPoint::from_point(Point3::new(0.3, 0.1, 4.0));
+/// let mut track: Vec<_> = (0..6)
+///     .map(|i| {
+///         let pose = WorldToCamera(IsometryMatrix3::from_parts(
+///             Vector3::new(0.2 * i as f64, 0.0, 0.0).into(),
+///             Rotation3::identity(),
+///         ));
+///         (pose, pose.transform(point).bearing())
+///     })
+///     .collect();
+/// // One of the observations is a wrong match.
+/// track[2].1 = UnitVector3::new_normalize(Vector3::new(-0.3, 0.2, 1.0));
+///
+/// let triangulator = RobustTrackTriangulator::new(DltTriangulator::new());
+/// let mut inliers = [false; 6];
+/// let triangulated = triangulator.triangulate_track(&track, &mut inliers).unwrap();
+/// assert!((point.point().unwrap() - triangulated.point().unwrap()).norm() < 1e-6);
+/// assert_eq!(inliers, [true, true, false, true, true, true]);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct RobustTrackTriangulator<T> {
+    triangulator: T,
+    max_cosine_distance: f64,
+    min_inliers: usize,
+}
+
+impl<T> RobustTrackTriangulator<T> {
+    /// Wraps a triangulator which is used for both the hypotheses and the final point.
+    pub fn new(triangulator: T) -> Self {
+        Self {
+            triangulator,
+            max_cosine_distance: 1e-5,
+            min_inliers: 2,
+        }
+    }
+
+    /// Set the maximum reprojection cosine distance of an inlier observation.
+    ///
+    /// Default is `1e-5`.
+    #[must_use]
+    pub fn max_cosine_distance(self, max_cosine_distance: f64) -> Self {
+        Self {
+            max_cosine_distance,
+            ..self
+        }
+    }
+
+    /// Set the minimum number of inlier observations required to triangulate a point.
+    ///
+    /// Default is `2`.
+    #[must_use]
+    pub fn min_inliers(self, min_inliers: usize) -> Self {
+        Self {
+            min_inliers: min_inliers.max(2),
+            ..self
+        }
+    }
+}
+
+impl<T> RobustTrackTriangulator<T>
+where
+    T: TriangulatorObservations,
+{
+    fn is_inlier(
+        &self,
+        point: WorldPoint,
+        (pose, bearing): (WorldToCamera, UnitVector3<f64>),
+    ) -> bool {
+        1.0 - pose.transform(point).bearing().dot(&bearing) <= self.max_cosine_distance
+    }
+
+    /// Triangulates the track and sets `inliers` to indicate which observations are consistent with the point.
+    ///
+    /// The `inliers` must have the same length as the `track`.
+    pub fn triangulate_track(
+        &self,
+        track: &[(WorldToCamera, UnitVector3<f64>)],
+        inliers: &mut [bool],
+    ) -> Option<WorldPoint> {
+        assert_eq!(
+            track.len(),
+            inliers.len(),
+            "the inliers must have one element per observation"
+        );
+        let point = self.triangulate_observations(track.iter().copied());
+        for (inlier, &observation) in inliers.iter_mut().zip(track) {
+            *inlier = point.map_or(false, |point| self.is_inlier(point, observation));
+        }
+        point
+    }
+}
+
+impl<T> TriangulatorObservations for RobustTrackTriangulator<T>
+where
+    T: TriangulatorObservations,
+{
+    fn triangulate_observations(
+        &self,
+        pairs: impl Iterator<Item = (WorldToCamera, UnitVector3<f64>)> + Clone,
+    ) -> Option<WorldPoint> {
+        let count_inliers = |point: WorldPoint| {
+            pairs
+                .clone()
+                .filter(|&observation| self.is_inlier(point, observation))
+                .count()
+        };
+
+        // Find the hypothesis from a pair of observations with the most inliers.
+        let (best, best_inliers) = pairs
+            .clone()
+            .enumerate()
+            .flat_map(|(ix, a)| pairs.clone().skip(ix + 1).map(move |b| (a, b)))
+            .filter_map(|(a, b)| {
+                self.triangulator
+                    .triangulate_observations(core::iter::once(a).chain(core::iter::once(b)))
+            })
+            .map(|point| (point, count_inliers(point)))
+            .fold(
+                None,
+                |best: Option<(WorldPoint, usize)>, (point, inliers)| match best {
+                    Some((_, best_inliers)) if best_inliers >= inliers => best,
+                    _ => Some((point, inliers)),
+                },
+            )?;
+        if best_inliers < self.min_inliers {
+            return None;
+        }
+
+        // Triangulate the final point from all of the inliers.
+        let point = self.triangulator.triangulate_observations(
+            pairs
+                .clone()
+                .filter(|&observation| self.is_inlier(best, observation)),
+        )?;
+        Some(point).filter(|&point| count_inliers(point) >= self.min_inliers)
+    }
+}
@@ -0,0 +1,421 @@
+//! # `cv-image-gpu`
+//!
+//! Image preprocessing on the GPU with [`wgpu`].
+//!
+//! Real-time applications often receive high resolution raw frames from their cameras, while feature extraction
+//! runs on much smaller gray images. [`GpuPreprocessor`] uploads each raw frame once and runs the whole
+//! preprocessing chain in a single compute pass: debayering, undistortion or any other remapping, conversion to
+//! gray, downscaling and photometric normalization. Only the final gray image is read back, directly into the
+//! [`GrayFloatImage`] that [`akaze::Akaze::extract_gray`] consumes, so the high resolution frames never cross the
+//! CPU memory bus after the upload.
+//!
+//! The buffers are allocated once for a fixed input and output size, so processing a frame doesn't allocate.
+
+use akaze::image::{BayerPattern, GrayFloatImage};
+use bytemuck::{cast_slice, Pod, Zeroable};
+use cv_core::{
+    nalgebra::{Point2, Vector2},
+    CameraModel, KeyPoint,
+};
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// Invocations run per workgroup along each axis, which must match the shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The pixel format of the raw frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// One gray byte per pixel.
+    Gray8,
+    /// Four bytes per pixel in the order red, green, blue and alpha.
+    Rgba8,
+    /// One byte per pixel of a Bayer mosaic, which is debayered bilinearly.
+    Bayer8(BayerPattern),
+}
+
+impl InputFormat {
+    /// The number of bytes per pixel.
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Gray8 | Self::Bayer8(_) => 1,
+            Self::Rgba8 => 4,
+        }
+    }
+}
+
+/// The preprocessing chain of a [`GpuPreprocessor`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preprocessing {
+    input_size: (u32, u32),
+    format: InputFormat,
+    output_size: (u32, u32),
+    remap: Option<Vec<Option<Point2<f32>>>>,
+    weights: [f32; 3],
+    gain: f32,
+    bias: f32,
+    gamma: f32,
+}
+
+impl Preprocessing {
+    /// Creates a chain which only converts frames of the given size and format to gray.
+    pub fn new(width: u32, height: u32, format: InputFormat) -> Self {
+        Self {
+            input_size: (width, height),
+            format,
+            output_size: (width, height),
+            remap: None,
+            weights: [0.2126, 0.7152, 0.0722],
+            gain: 1.0,
+            bias: 0.0,
+            gamma: 1.0,
+        }
+    }
+
+    /// The size of the output images, which are downscaled by averaging the pixels of the input they cover.
+    ///
+    /// Default is the size of the input.
+    #[must_use]
+    pub fn output_size(self, width: u32, height: u32) -> Self {
+        Self {
+            output_size: (width, height),
+            ..self
+        }
+    }
+
+    /// Remaps the frames, where the table holds the position in the input of every output pixel, row by row.
+    ///
+    /// This replaces the downscaling, and output pixels without a position are black. Use [`remap_table`] to
+    /// undistort images or to convert them between camera models.
+    ///
+    /// Default is no remapping.
+    #[must_use]
+    pub fn remap(self, table: Vec<Option<Point2<f32>>>) -> Self {
+        Self {
+            remap: Some(table),
+            ..self
+        }
+    }
+
+    /// The weights of the red, green and blue channels in the conversion to gray.
+    ///
+    /// Default is the Rec. 709 luma weights `[0.2126, 0.7152, 0.0722]`.
+    #[must_use]
+    pub fn weights(self, weights: [f32; 3]) -> Self {
+        Self { weights, ..self }
+    }
+
+    /// Maps the gray values to `gain * value + bias`, clamped between `0` and `1`.
+    ///
+    /// Default is a gain of `1.0` and a bias of `0.0`.
+    #[must_use]
+    pub fn normalization(self, gain: f32, bias: f32) -> Self {
+        Self { gain, bias, ..self }
+    }
+
+    /// Raises the normalized gray values to the power of `gamma`.
+    ///
+    /// Default is `1.0`.
+    #[must_use]
+    pub fn gamma(self, gamma: f32) -> Self {
+        Self { gamma, ..self }
+    }
+}
+
+/// Computes the remap table which maps images from the `source` camera into the `target` camera, for a target
+/// image of `width` by `height` pixels.
+///
+/// The source and target could for instance be a fisheye camera and a virtual pinhole camera, or a distorted camera
+/// and its intrinsics without distortion.
+pub fn remap_table(
+    source: &impl CameraModel,
+    target: &impl CameraModel,
+    width: u32,
+    height: u32,
+) -> Vec<Option<Point2<f32>>> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let bearing = target.calibrate(KeyPoint(Point2::new(f64::from(x), f64::from(y))));
+            source
+                .uncalibrate(bearing)
+                .map(|KeyPoint(point)| point.cast::<f32>())
+        })
+        .collect()
+}
+
+/// The uniform parameters of the shader, which must match its layout.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct Params {
+    input_width: u32,
+    input_height: u32,
+    output_width: u32,
+    output_height: u32,
+    format: u32,
+    pattern: u32,
+    remap: u32,
+    samples: u32,
+    weights: [f32; 4],
+    gain: f32,
+    bias: f32,
+    gamma: f32,
+    padding: f32,
+}
+
+/// Preprocesses raw frames of a fixed size and format on the GPU.
+pub struct GpuPreprocessor {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    input: wgpu::Buffer,
+    output: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    input_size: (u32, u32),
+    format: InputFormat,
+    output_size: (u32, u32),
+}
+
+impl GpuPreprocessor {
+    /// Creates a preprocessor on the default adapter.
+    ///
+    /// Returns `None` if there is no adapter or device available.
+    pub async fn new(preprocessing: Preprocessing) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .await
+            .ok()?;
+        Some(Self::from_device(device, queue, preprocessing))
+    }
+
+    /// Creates a preprocessor which shares an existing device, such as the device which captures the frames.
+    pub fn from_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        preprocessing: Preprocessing,
+    ) -> Self {
+        let Preprocessing {
+            input_size,
+            format,
+            output_size,
+            remap,
+            weights,
+            gain,
+            bias,
+            gamma,
+        } = preprocessing;
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("preprocess"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("preprocess.wgsl"))),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("preprocess"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+        });
+
+        // Downscaling averages enough samples per axis to cover every input pixel.
+        let samples = ((input_size.0 as f32 / output_size.0.max(1) as f32)
+            .max(input_size.1 as f32 / output_size.1.max(1) as f32))
+        .ceil()
+        .max(1.0) as u32;
+        let pattern = match format {
+            InputFormat::Bayer8(pattern) => {
+                let block = match pattern {
+                    BayerPattern::Rggb => [0, 1, 1, 2],
+                    BayerPattern::Bggr => [2, 1, 1, 0],
+                    BayerPattern::Grbg => [1, 0, 2, 1],
+                    BayerPattern::Gbrg => [1, 2, 0, 1],
+                };
+                block
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, &channel)| channel << (2 * ix))
+                    .sum()
+            }
+            _ => 0,
+        };
+        let params = Params {
+            input_width: input_size.0,
+            input_height: input_size.1,
+            output_width: output_size.0,
+            output_height: output_size.1,
+            format: match format {
+                InputFormat::Gray8 => 0,
+                InputFormat::Rgba8 => 1,
+                InputFormat::Bayer8(_) => 2,
+            },
+            pattern,
+            remap: remap.is_some() as u32,
+            samples,
+            weights: [weights[0], weights[1], weights[2], 0.0],
+            gain,
+            bias,
+            gamma,
+            padding: 0.0,
+        };
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        // Pixels without a position are marked with negative coordinates, and buffers may not be empty.
+        let mut positions: Vec<f32> = remap
+            .iter()
+            .flatten()
+            .flat_map(|position| {
+                let position = position.unwrap_or_else(|| Point2::new(-1.0, -1.0));
+                vec![position.x, position.y]
+            })
+            .collect();
+        if positions.is_empty() {
+            positions = vec![-1.0; 2];
+        }
+        let positions = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("positions"),
+            contents: cast_slice(&positions),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let input_bytes =
+            (input_size.0 as usize * input_size.1 as usize * format.bytes_per_pixel()).max(1);
+        let input = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("input"),
+            size: align(input_bytes) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let output_bytes =
+            (output_size.0 as usize * output_size.1 as usize).max(1) * std::mem::size_of::<f32>();
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output"),
+            size: output_bytes as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size: output_bytes as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("preprocess"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: input.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        });
+        Self {
+            device,
+            queue,
+            pipeline,
+            bind_group,
+            input,
+            output,
+            staging,
+            input_size,
+            format,
+            output_size,
+        }
+    }
+
+    /// The size of the output images.
+    pub fn output_size(&self) -> Vector2<u32> {
+        Vector2::new(self.output_size.0, self.output_size.1)
+    }
+
+    /// Preprocesses a raw frame into `output`, which is reallocated only if it doesn't have the output size.
+    ///
+    /// Returns `false` if the frame doesn't have the size of the input or the output could not be read back.
+    pub async fn process_into(&self, frame: &[u8], output: &mut GrayFloatImage) -> bool {
+        let (width, height) = self.input_size;
+        let bytes = width as usize * height as usize * self.format.bytes_per_pixel();
+        if frame.len() != bytes {
+            return false;
+        }
+        // Buffer writes must be aligned to four bytes.
+        if bytes % 4 == 0 {
+            self.queue.write_buffer(&self.input, 0, frame);
+        } else {
+            let mut padded = frame.to_vec();
+            padded.resize(align(bytes), 0);
+            self.queue.write_buffer(&self.input, 0, &padded);
+        }
+
+        let (output_width, output_height) = self.output_size;
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.dispatch(
+                (output_width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                (output_height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&self.output, 0, &self.staging, 0, self.staging.size());
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = self.staging.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        if mapping.await.is_err() {
+            return false;
+        }
+        if output.width() != output_width as usize || output.height() != output_height as usize {
+            *output = GrayFloatImage::new(output_width as usize, output_height as usize);
+        }
+        let pixels = output_width as usize * output_height as usize;
+        output.copy_from_slice(&cast_slice::<u8, f32>(&slice.get_mapped_range())[..pixels]);
+        self.staging.unmap();
+        true
+    }
+
+    /// Preprocesses a raw frame into a new image.
+    ///
+    /// Returns `None` if the frame doesn't have the size of the input or the output could not be read back.
+    pub async fn process(&self, frame: &[u8]) -> Option<GrayFloatImage> {
+        let mut output = GrayFloatImage::new(0, 0);
+        self.process_into(frame, &mut output).await.then(|| output)
+    }
+}
+
+/// Rounds a number of bytes up to the alignment of buffer copies.
+fn align(bytes: usize) -> usize {
+    (bytes + 3) / 4 * 4
+}
+
+/// Blocks on the asynchronous methods of [`GpuPreprocessor`] for use outside of an async runtime.
+///
+/// ```no_run
+/// use cv_image_gpu::{block_on, GpuPreprocessor, InputFormat, Preprocessing};
+/// let preprocessing = Preprocessing::new(1920, 1080, InputFormat::Gray8).output_size(960, 540);
+/// let preprocessor = block_on(GpuPreprocessor::new(preprocessing)).expect("no GPU available");
+/// ```
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    pollster::block_on(future)
+}
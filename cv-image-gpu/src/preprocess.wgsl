@@ -0,0 +1,166 @@
+// Preprocesses one output pixel per invocation.
+//
+// The input frame is packed into 32-bit words: one byte per pixel for gray and Bayer frames and one RGBA pixel per
+// word for color frames. Every output pixel samples the gray input bilinearly at its position in the input, which
+// comes from the remap table or from scaling, averaged over a grid of samples to downscale without aliasing.
+
+[[block]]
+struct Params {
+    input_width: u32;
+    input_height: u32;
+    output_width: u32;
+    output_height: u32;
+    // 0 is gray, 1 is RGBA and 2 is Bayer.
+    format: u32;
+    // The channels of the top left 2x2 block of the Bayer mosaic, two bits each, row by row.
+    pattern: u32;
+    // Whether the remap table is used.
+    remap: u32;
+    // The number of samples per axis for every output pixel.
+    samples: u32;
+    // The weights of the red, green and blue channels.
+    weights: vec4<f32>;
+    gain: f32;
+    bias: f32;
+    gamma: f32;
+    padding: f32;
+};
+
+[[block]]
+struct Words {
+    data: array<u32>;
+};
+
+[[block]]
+struct Positions {
+    data: array<vec2<f32>>;
+};
+
+[[block]]
+struct Pixels {
+    data: array<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+
+[[group(0), binding(1)]]
+var<storage, read> input: Words;
+
+[[group(0), binding(2)]]
+var<storage, read> positions: Positions;
+
+[[group(0), binding(3)]]
+var<storage, read_write> output: Pixels;
+
+fn byte(ix: u32) -> f32 {
+    let word = input.data[ix / 4u];
+    return f32((word >> (8u * (ix % 4u))) & 255u) / 255.0;
+}
+
+fn clamped(x: i32, y: i32) -> vec2<u32> {
+    return vec2<u32>(
+        u32(clamp(x, 0, i32(params.input_width) - 1)),
+        u32(clamp(y, 0, i32(params.input_height) - 1))
+    );
+}
+
+// The raw Bayer value at a pixel, where pixels beyond the border move by two pixels to keep their color.
+fn raw(x: i32, y: i32) -> f32 {
+    var px = x;
+    var py = y;
+    if (px < 0) { px = px + 2; }
+    if (py < 0) { py = py + 2; }
+    if (px >= i32(params.input_width)) { px = px - 2; }
+    if (py >= i32(params.input_height)) { py = py - 2; }
+    let p = clamped(px, py);
+    return byte(p.y * params.input_width + p.x);
+}
+
+fn bayer_channel(x: i32, y: i32) -> u32 {
+    let block = u32(y % 2) * 2u + u32(x % 2);
+    return (params.pattern >> (2u * block)) & 3u;
+}
+
+// Bilinear demosaicing of one pixel of the Bayer mosaic.
+fn demosaic(x: i32, y: i32) -> vec3<f32> {
+    let own = bayer_channel(x, y);
+    let center = raw(x, y);
+    let cross = 0.25 * (raw(x - 1, y) + raw(x + 1, y) + raw(x, y - 1) + raw(x, y + 1));
+    let diagonal = 0.25 * (raw(x - 1, y - 1) + raw(x + 1, y - 1) + raw(x - 1, y + 1) + raw(x + 1, y + 1));
+    let horizontal = 0.5 * (raw(x - 1, y) + raw(x + 1, y));
+    let vertical = 0.5 * (raw(x, y - 1) + raw(x, y + 1));
+    if (own == 1u) {
+        if (bayer_channel(x + 1, y) == 0u) {
+            return vec3<f32>(horizontal, center, vertical);
+        }
+        return vec3<f32>(vertical, center, horizontal);
+    }
+    if (own == 0u) {
+        return vec3<f32>(center, cross, diagonal);
+    }
+    return vec3<f32>(diagonal, cross, center);
+}
+
+fn gray(x: i32, y: i32) -> f32 {
+    let p = clamped(x, y);
+    let ix = p.y * params.input_width + p.x;
+    if (params.format == 0u) {
+        return byte(ix);
+    }
+    var rgb: vec3<f32>;
+    if (params.format == 1u) {
+        let word = input.data[ix];
+        rgb = vec3<f32>(
+            f32(word & 255u),
+            f32((word >> 8u) & 255u),
+            f32((word >> 16u) & 255u)
+        ) / 255.0;
+    } else {
+        rgb = demosaic(i32(p.x), i32(p.y));
+    }
+    return dot(rgb, params.weights.xyz);
+}
+
+fn sample(position: vec2<f32>) -> f32 {
+    let corner = floor(position);
+    let w = position - corner;
+    let x = i32(corner.x);
+    let y = i32(corner.y);
+    let top = mix(gray(x, y), gray(x + 1, y), w.x);
+    let bottom = mix(gray(x, y + 1), gray(x + 1, y + 1), w.x);
+    return mix(top, bottom, w.y);
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (id.x >= params.output_width || id.y >= params.output_height) {
+        return;
+    }
+    let ix = id.y * params.output_width + id.x;
+    var value = 0.0;
+    if (params.remap != 0u) {
+        // Positions outside of the input are negative.
+        let position = positions.data[ix];
+        if (position.x >= 0.0 && position.y >= 0.0) {
+            value = sample(position);
+        }
+    } else {
+        // Average a grid of samples inside of the footprint of the output pixel.
+        let scale = vec2<f32>(
+            f32(params.input_width) / f32(params.output_width),
+            f32(params.input_height) / f32(params.output_height)
+        );
+        let samples = max(params.samples, 1u);
+        for (var sy = 0u; sy < samples; sy = sy + 1u) {
+            for (var sx = 0u; sx < samples; sx = sx + 1u) {
+                let offset = (vec2<f32>(f32(sx), f32(sy)) + 0.5) / f32(samples);
+                let position = (vec2<f32>(f32(id.x), f32(id.y)) + offset) * scale - 0.5;
+                value = value + sample(position);
+            }
+        }
+        value = value / f32(samples * samples);
+    }
+    value = clamp(params.gain * value + params.bias, 0.0, 1.0);
+    output.data[ix] = pow(value, params.gamma);
+}
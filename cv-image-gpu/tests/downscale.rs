@@ -0,0 +1,35 @@
+use cv_image_gpu::{block_on, GpuPreprocessor, InputFormat, Preprocessing};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+#[test]
+fn downscale_averages_pixels() {
+    let frame: Vec<u8> = (0..WIDTH * HEIGHT)
+        .map(|ix| ((ix % WIDTH) * 3 + (ix / WIDTH) * 5) as u8)
+        .collect();
+    let preprocessing =
+        Preprocessing::new(WIDTH, HEIGHT, InputFormat::Gray8).output_size(WIDTH / 2, HEIGHT / 2);
+    let preprocessor = match block_on(GpuPreprocessor::new(preprocessing)) {
+        Some(preprocessor) => preprocessor,
+        None => {
+            eprintln!("skipping GPU preprocessing test since no adapter is available");
+            return;
+        }
+    };
+    let output = block_on(preprocessor.process(&frame)).unwrap();
+    assert_eq!(output.width(), (WIDTH / 2) as usize);
+    assert_eq!(output.height(), (HEIGHT / 2) as usize);
+    for y in 0..output.height() {
+        for x in 0..output.width() {
+            let expected: f32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .iter()
+                .map(|&(dx, dy)| {
+                    f32::from(frame[(2 * y + dy) * WIDTH as usize + 2 * x + dx]) / 255.0
+                })
+                .sum::<f32>()
+                / 4.0;
+            assert!((output.get(x, y) - expected).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,1540 @@
+use crate::{
+    lines::linearize_line_observation, BlockSparseMatrix, CameraAbovePlane, DepthObservation,
+    GnssPrior, LeastSquaresProblem, LevenbergMarquardt, LineObservation, Linearization,
+    MarginalPrior, OptimizationReport, Plane, PluckerLine, PointOnPlane, PoseGraphEdge,
+    ReducedSolvePrecision, RobustLoss, SolverBackend, TrustRegion,
+};
+use cv_core::{
+    nalgebra::{
+        DVector, IsometryMatrix3, Matrix3, Matrix3x6, Matrix4, Matrix6, Matrix6x3, Matrix6x4,
+        Point3, Rotation3, SMatrix, UnitVector3, Vector3, Vector4, Vector6,
+    },
+    CameraModel, CameraToCamera, ImagePoint, Pose, Projective, Skew3, WorldPoint, WorldToCamera,
+};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// An observation of a landmark from one of the poses of a [`BundleAdjustment`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct Observation {
+    /// The index of the pose the landmark was observed from.
+    pub pose: usize,
+    /// The index of the landmark.
+    pub landmark: usize,
+    /// The observed bearing in the camera.
+    pub bearing: UnitVector3<f64>,
+    /// The robust loss of this observation, which overrides the loss of the [`BundleAdjuster`].
+    pub loss: Option<RobustLoss>,
+}
+
+impl Observation {
+    pub fn new(pose: usize, landmark: usize, bearing: UnitVector3<f64>) -> Self {
+        Self {
+            pose,
+            landmark,
+            bearing,
+            loss: None,
+        }
+    }
+
+    /// Set the robust loss of this observation, such as to use a larger scale for noisier features.
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self {
+            loss: Some(loss),
+            ..self
+        }
+    }
+
+    /// Creates an observation from a keypoint by calibrating it with the camera model of the image.
+    pub fn from_keypoint<C, P>(pose: usize, landmark: usize, camera: &C, keypoint: P) -> Self
+    where
+        C: CameraModel,
+        P: ImagePoint,
+    {
+        Self::new(pose, landmark, camera.calibrate(keypoint))
+    }
+}
+
+/// A prior on one of the poses of a [`BundleAdjustment`], such as from a previous optimization or another sensor.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct PosePrior {
+    /// The index of the pose.
+    pub pose: usize,
+    /// The expected value of the pose.
+    pub prior: WorldToCamera,
+    /// The inverse covariance of the prior in se(3) (with translation components before so(3) components).
+    pub information: Matrix6<f64>,
+    /// The robust loss applied to the Mahalanobis norm of the error.
+    pub loss: RobustLoss,
+}
+
+impl PosePrior {
+    pub fn new(pose: usize, prior: WorldToCamera, information: Matrix6<f64>) -> Self {
+        Self {
+            pose,
+            prior,
+            information,
+            loss: RobustLoss::Squared,
+        }
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    /// The prior is the same as a pose graph edge from the prior to the pose with an identity measurement.
+    fn edge(&self) -> (PoseGraphEdge, [WorldToCamera; 1]) {
+        (
+            PoseGraphEdge::new(0, 1, CameraToCamera::identity(), self.information).loss(self.loss),
+            [self.prior],
+        )
+    }
+
+    /// The error, its Mahalanobis norm, and the Jacobian of the error in respect to the pose.
+    pub(crate) fn linearize(&self, pose: WorldToCamera) -> (Vector6<f64>, f64, Matrix6<f64>) {
+        let (edge, [prior]) = self.edge();
+        let (error, _, jacobian) = edge.linearize(&[prior, pose]);
+        (error, edge.mahalanobis(error), jacobian)
+    }
+
+    fn cost(&self, pose: WorldToCamera) -> f64 {
+        let (edge, [prior]) = self.edge();
+        edge.loss.loss(edge.mahalanobis(edge.error(&[prior, pose])))
+    }
+}
+
+/// How the gauge freedom of a [`BundleAdjustment`] is removed.
+///
+/// Bearing residuals do not change when every pose and landmark is moved by the same rigid motion, or when they
+/// are all scaled about the origin in the monocular case, so the Hessian is singular unless seven degrees of
+/// freedom are constrained. Problems with enough [`PosePrior`]s or [`GnssPrior`]s are already constrained.
+///
+/// ```
+/// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3}, *};
+/// use cv_optimize::{BundleAdjuster, BundleAdjustment, Gauge, Observation};
+///
+/// let poses: Vec<WorldToCamera> = (0..3)
+///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+///         Vector3::new(-0.3 * i as f64, 0.0, 0.0).into(),
+///         Rotation3::identity(),
+///     )))
+///     .collect();
+/// let landmarks: Vec<WorldPoint> = (0..20)
+///     .map(|i| WorldPoint::from_point(Point3::new((i % 5) as f64 * 0.4 - 0.8, (i / 5) as f64 * 0.3 - 0.5, 4.0 + (i % 3) as f64)))
+///     .collect();
+///
+/// let mut problem = BundleAdjustment::new(poses.clone(), landmarks.clone());
+/// problem.poses[2].0.translation.vector.x += 0.02;
+/// problem.set_gauge(Gauge::FixPosePair(0, 1));
+/// for (p, pose) in poses.iter().enumerate() {
+///     for (l, &landmark) in landmarks.iter().enumerate() {
+///         problem.observe(Observation::new(p, l, pose.transform(landmark).bearing()));
+///     }
+/// }
+///
+/// BundleAdjuster::new().optimize(&mut problem);
+/// assert_eq!(problem.poses[..2], poses[..2]);
+/// assert!((problem.poses[2].0.translation.vector - poses[2].0.translation.vector).norm() < 1e-6);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum Gauge {
+    /// Only the poses fixed with [`BundleAdjustment::fix_pose`] and the priors of the problem constrain it.
+    Manual,
+    /// Hold the first pose fixed, which is enough when the scale is known, such as from a stereo rig.
+    FixFirstPose,
+    /// Hold two poses fixed, which also removes the scale freedom of monocular problems.
+    FixPosePair(usize, usize),
+    /// Add a prior with this isotropic information on the first two poses at their initial values, which softly
+    /// constrains the gauge while still letting every pose move.
+    Prior(f64),
+}
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Self::Manual
+    }
+}
+
+/// A bundle adjustment problem, which jointly refines the poses of cameras and the landmarks they observe.
+///
+/// Landmarks at infinity cannot be refined and are held fixed. With [`GnssPrior`]s, the reconstruction is anchored
+/// in the [`LocalTangentPlane`](crate::LocalTangentPlane) of the priors, which also bounds its drift.
+#[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct BundleAdjustment {
+    pub poses: Vec<WorldToCamera>,
+    pub landmarks: Vec<WorldPoint>,
+    pub observations: Vec<Observation>,
+    /// Measured depths of landmarks, such as from an RGB-D camera, which fix the scale of the reconstruction.
+    pub depths: Vec<DepthObservation>,
+    /// Lines, such as from the edges of walls and doors, which help where there are few textured points.
+    pub lines: Vec<PluckerLine>,
+    pub line_observations: Vec<LineObservation>,
+    /// Planes, such as walls and the ground, which landmarks and cameras can be constrained to.
+    pub planes: Vec<Plane>,
+    pub points_on_planes: Vec<PointOnPlane>,
+    pub cameras_above_planes: Vec<CameraAbovePlane>,
+    pub priors: Vec<PosePrior>,
+    pub gnss: Vec<GnssPrior>,
+    /// Dense priors left behind by marginalizing states (see [`BundleAdjuster::marginalize`]).
+    pub marginal_priors: Vec<MarginalPrior>,
+    /// Poses with `true` at their index are held fixed, which is needed to fix the gauge freedom of the problem.
+    pub fixed_poses: Vec<bool>,
+    pub gauge: Gauge,
+}
+
+impl BundleAdjustment {
+    pub fn new(poses: Vec<WorldToCamera>, landmarks: Vec<WorldPoint>) -> Self {
+        Self {
+            fixed_poses: vec![false; poses.len()],
+            poses,
+            landmarks,
+            observations: vec![],
+            depths: vec![],
+            lines: vec![],
+            line_observations: vec![],
+            planes: vec![],
+            points_on_planes: vec![],
+            cameras_above_planes: vec![],
+            priors: vec![],
+            gnss: vec![],
+            marginal_priors: vec![],
+            gauge: Gauge::Manual,
+        }
+    }
+
+    /// Adds an observation of a landmark.
+    pub fn observe(&mut self, observation: Observation) {
+        self.observations.push(observation);
+    }
+
+    /// Adds a measured depth of a landmark.
+    ///
+    /// ```
+    /// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3}, *};
+    /// use cv_optimize::{BundleAdjuster, BundleAdjustment, DepthObservation, Observation};
+    ///
+    /// // A reconstruction at some scale of two cameras 0.2 meters apart.
+    /// let poses = |scale: f64| -> Vec<WorldToCamera> {
+    ///     (0..2)
+    ///         .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+    ///             Vector3::new(-0.2 * scale * i as f64, 0.0, 0.0).into(),
+    ///             Rotation3::identity(),
+    ///         )))
+    ///         .collect()
+    /// };
+    /// let landmarks = |scale: f64| -> Vec<WorldPoint> {
+    ///     (0..12)
+    ///         .map(|i| WorldPoint::from_point(Point3::new((i % 4) as f64 - 1.5, (i / 4) as f64 - 1.0, 3.0 + 0.1 * i as f64) * scale))
+    ///         .collect()
+    /// };
+    ///
+    /// // Two views only determine the reconstruction up to scale, so start from one which is too small.
+    /// let mut problem = BundleAdjustment::new(poses(0.5), landmarks(0.5));
+    /// problem.fix_pose(0);
+    /// for (p, pose) in poses(1.0).iter().enumerate() {
+    ///     for (l, &landmark) in landmarks(1.0).iter().enumerate() {
+    ///         let camera = pose.transform(landmark);
+    ///         problem.observe(Observation::new(p, l, camera.bearing()));
+    ///         problem.add_depth(DepthObservation::new(p, l, camera.point().unwrap().z, 1.0));
+    ///     }
+    /// }
+    ///
+    /// BundleAdjuster::new().optimize(&mut problem);
+    /// let baseline = problem.poses[1].inverse().isometry().translation.vector.norm();
+    /// assert!((baseline - 0.2).abs() < 1e-6);
+    /// ```
+    pub fn add_depth(&mut self, depth: DepthObservation) {
+        self.depths.push(depth);
+    }
+
+    /// Adds a line landmark, returning its index.
+    pub fn add_line(&mut self, line: PluckerLine) -> usize {
+        self.lines.push(line);
+        self.lines.len() - 1
+    }
+
+    /// Adds an observation of a line.
+    ///
+    /// ```
+    /// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, UnitVector3, Vector3, Vector4}, *};
+    /// use cv_optimize::{BundleAdjuster, BundleAdjustment, LineObservation, Observation, PluckerLine};
+    ///
+    /// let poses: Vec<WorldToCamera> = (0..3)
+    ///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+    ///         Vector3::new(-0.3 * i as f64, 0.1 * i as f64, 0.0).into(),
+    ///         Rotation3::from_euler_angles(0.0, 0.05 * i as f64, 0.0),
+    ///     )))
+    ///     .collect();
+    /// // The vertical and horizontal edges of a door.
+    /// let edges = [
+    ///     (Point3::new(-0.5, -1.0, 5.0), Point3::new(-0.5, 1.0, 5.0)),
+    ///     (Point3::new(0.5, -1.0, 5.0), Point3::new(0.5, 1.0, 5.5)),
+    ///     (Point3::new(-0.5, -1.0, 5.0), Point3::new(0.5, -1.0, 5.5)),
+    /// ];
+    ///
+    /// let mut problem = BundleAdjustment::new(poses.clone(), vec![]);
+    /// for &(a, b) in &edges {
+    ///     let line = PluckerLine::from_points(a, b).unwrap();
+    ///     let line = problem.add_line(line.retract(Vector4::new(0.01, -0.02, 0.01, 0.02)));
+    ///     for (p, pose) in poses.iter().enumerate() {
+    ///         // Only some part of the edge is detected in each image.
+    ///         let endpoint = |t: f64| {
+    ///             let point = WorldPoint::from_point(a + (b - a) * t);
+    ///             pose.transform(point).bearing()
+    ///         };
+    ///         problem.observe_line(LineObservation::new(p, line, [endpoint(0.1 * p as f64), endpoint(0.9)]));
+    ///     }
+    /// }
+    /// for p in 0..3 {
+    ///     problem.fix_pose(p);
+    /// }
+    ///
+    /// BundleAdjuster::new().optimize(&mut problem);
+    /// for (line, &(a, b)) in problem.lines.iter().zip(&edges) {
+    ///     assert!(line.distance(a) < 1e-6 && line.distance(b) < 1e-6);
+    /// }
+    /// ```
+    pub fn observe_line(&mut self, observation: LineObservation) {
+        self.line_observations.push(observation);
+    }
+
+    /// Adds a plane, returning its index.
+    pub fn add_plane(&mut self, plane: Plane) -> usize {
+        self.planes.push(plane);
+        self.planes.len() - 1
+    }
+
+    /// Constrains a landmark to lie on a plane.
+    ///
+    /// ```
+    /// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3}, *};
+    /// use cv_optimize::{BundleAdjuster, BundleAdjustment, CameraAbovePlane, Observation, Plane, PointOnPlane};
+    ///
+    /// // A camera driving along a flat road 1.5 meters above it, where the y axis points down.
+    /// let poses: Vec<WorldToCamera> = (0..3)
+    ///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+    ///         Vector3::new(0.0, 0.0, -1.0 * i as f64).into(),
+    ///         Rotation3::identity(),
+    ///     )))
+    ///     .collect();
+    /// let road: Vec<WorldPoint> = (0..20)
+    ///     .map(|i| WorldPoint::from_point(Point3::new((i % 5) as f64 - 2.0, 1.5, 5.0 + (i / 5) as f64)))
+    ///     .collect();
+    ///
+    /// let mut problem = BundleAdjustment::new(poses.clone(), road.clone());
+    /// let ground = problem.add_plane(Plane::new(Vector3::new(0.05, -1.0, 0.02), -1.4));
+    /// problem.fix_pose(0);
+    /// problem.fix_pose(1);
+    /// for (p, pose) in poses.iter().enumerate() {
+    ///     for (l, &landmark) in road.iter().enumerate() {
+    ///         problem.observe(Observation::new(p, l, pose.transform(landmark).bearing()));
+    ///     }
+    ///     problem.add_camera_above_plane(CameraAbovePlane::new(p, ground, 1.5, 1e4));
+    /// }
+    /// for l in 0..road.len() {
+    ///     problem.add_point_on_plane(PointOnPlane::new(l, ground, 1e4));
+    /// }
+    ///
+    /// BundleAdjuster::new().optimize(&mut problem);
+    /// let plane = problem.planes[ground];
+    /// assert!((plane.normal + Vector3::y()).norm() < 1e-6 && (plane.distance + 1.5).abs() < 1e-6);
+    /// ```
+    pub fn add_point_on_plane(&mut self, factor: PointOnPlane) {
+        self.points_on_planes.push(factor);
+    }
+
+    /// Constrains the camera of a pose to be at a height above a plane.
+    pub fn add_camera_above_plane(&mut self, factor: CameraAbovePlane) {
+        self.cameras_above_planes.push(factor);
+    }
+
+    /// Adds a prior on a pose.
+    pub fn add_prior(&mut self, prior: PosePrior) {
+        self.priors.push(prior);
+    }
+
+    /// Adds a GNSS prior on the position of a camera.
+    pub fn add_gnss(&mut self, prior: GnssPrior) {
+        self.gnss.push(prior);
+    }
+
+    /// Holds a pose fixed during the optimization.
+    pub fn fix_pose(&mut self, pose: usize) {
+        self.fixed_poses.resize(self.poses.len(), false);
+        self.fixed_poses[pose] = true;
+    }
+
+    /// Sets how the gauge freedom of the problem is removed.
+    pub fn set_gauge(&mut self, gauge: Gauge) {
+        self.gauge = gauge;
+    }
+
+    pub(crate) fn is_fixed(&self, pose: usize) -> bool {
+        let gauge = match self.gauge {
+            Gauge::FixFirstPose => pose == 0,
+            Gauge::FixPosePair(a, b) => pose == a || pose == b,
+            Gauge::Manual | Gauge::Prior(_) => false,
+        };
+        gauge || self.fixed_poses.get(pose).copied().unwrap_or(false)
+    }
+
+    /// The priors added by [`Gauge::Prior`] at the current poses.
+    fn gauge_priors(&self) -> Vec<PosePrior> {
+        match self.gauge {
+            Gauge::Prior(information) => self
+                .poses
+                .iter()
+                .take(2)
+                .enumerate()
+                .map(|(ix, &pose)| PosePrior::new(ix, pose, Matrix6::identity() * information))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Whether nothing removes the gauge freedom of the problem.
+    fn is_unconstrained(&self) -> bool {
+        self.gauge == Gauge::Manual
+            && !self.fixed_poses.iter().any(|&fixed| fixed)
+            && self.priors.is_empty()
+            && self.gnss.is_empty()
+            && self.marginal_priors.is_empty()
+    }
+}
+
+/// Solves a [`BundleAdjustment`] with Levenberg-Marquardt.
+///
+/// Every observation only depends on one pose and one landmark, so the Jacobian is made of small blocks and the
+/// landmark block of the normal equations is block diagonal. Each step eliminates the landmarks with the Schur
+/// complement, solves the much smaller reduced camera system for the poses, and then back-substitutes each landmark
+/// independently. The residual of each observation is the difference between the observed bearing and the bearing
+/// of the transformed landmark, as in [`MotionOnly`](crate::MotionOnly). The reduced camera system is solved by
+/// the [`SolverBackend`], which by default picks a dense or sparse factorization or conjugate gradients from its size.
+/// It can be solved in `f32` with [`ReducedSolvePrecision::Single`] on targets where `f64` is slow, while everything
+/// else stays in `f64`.
+/// With the `rayon` feature enabled the residuals and Jacobians of the observations are evaluated in parallel, and
+/// the blocks of each pose and landmark are reduced in parallel over their own observations.
+///
+/// ```
+/// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3, Vector4}, *};
+/// use cv_optimize::{BundleAdjuster, BundleAdjustment, ConjugateGradient, Observation, SolverBackend};
+///
+/// let poses: Vec<WorldToCamera> = (0..3)
+///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+///         Vector3::new(-0.3 * i as f64, 0.05 * i as f64, 0.0).into(),
+///         Rotation3::from_euler_angles(0.0, 0.05 * i as f64, 0.0),
+///     )))
+///     .collect();
+/// let landmarks: Vec<WorldPoint> = (0..20)
+///     .map(|i| WorldPoint::from_point(Point3::new((i % 5) as f64 * 0.4 - 0.8, (i / 5) as f64 * 0.3 - 0.5, 4.0 + (i % 3) as f64)))
+///     .collect();
+///
+/// // Perturb everything except for the first two poses, which fix the gauge freedom.
+/// let mut problem = BundleAdjustment::new(poses.clone(), landmarks.iter().map(|&l| WorldPoint(l.0 + Vector4::new(0.01, -0.01, 0.02, 0.0))).collect());
+/// problem.poses[2].0.translation.vector.x += 0.02;
+/// problem.fix_pose(0);
+/// problem.fix_pose(1);
+/// for (p, pose) in poses.iter().enumerate() {
+///     for (l, &landmark) in landmarks.iter().enumerate() {
+///         problem.observe(Observation::new(p, l, pose.transform(landmark).bearing()));
+///     }
+/// }
+///
+/// // Conjugate gradients is selected explicitly to solve without building the reduced camera system.
+/// for &backend in &[SolverBackend::Auto, SolverBackend::ConjugateGradient(ConjugateGradient::new())] {
+///     let mut problem = problem.clone();
+///     let report = BundleAdjuster::new().linear_solver(backend).optimize(&mut problem);
+///     assert!(report.final_cost < 1e-14);
+///     assert!((problem.poses[2].0.translation.vector - poses[2].0.translation.vector).norm() < 1e-6);
+/// }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct BundleAdjuster {
+    /// The robust loss applied to the norm of each bearing residual, unless the observation has its own loss.
+    pub loss: RobustLoss,
+    /// The maximum number of accepted steps.
+    pub max_iterations: usize,
+    /// The initial damping of the Gauss-Newton step.
+    pub initial_lambda: f64,
+    /// Optimization stops when the norm of a step is below this.
+    pub tolerance: f64,
+    /// The solver of the reduced camera system.
+    ///
+    /// An explicitly selected [`SolverBackend::ConjugateGradient`] never builds the reduced camera system.
+    pub linear_solver: SolverBackend,
+    /// The precision the reduced camera system is solved in.
+    pub precision: ReducedSolvePrecision,
+}
+
+impl Default for BundleAdjuster {
+    fn default() -> Self {
+        Self {
+            loss: RobustLoss::Squared,
+            max_iterations: 50,
+            initial_lambda: 1e-4,
+            tolerance: 1e-12,
+            linear_solver: SolverBackend::Auto,
+            precision: ReducedSolvePrecision::Double,
+        }
+    }
+}
+
+/// The normal equations of the bundle adjustment, split into pose and landmark blocks.
+///
+/// The parameters are the se(3) steps of the free poses followed by the steps of all landmarks.
+pub(crate) struct NormalEquations {
+    pub(crate) pose_hessians: Vec<Matrix6<f64>>,
+    pub(crate) pose_gradients: Vec<Vector6<f64>>,
+    pub(crate) landmark_hessians: Vec<Matrix3<f64>>,
+    pub(crate) landmark_gradients: Vec<Vector3<f64>>,
+    /// The off-diagonal block of each observation.
+    pub(crate) pose_landmark: Vec<Matrix6x3<f64>>,
+    /// The off-diagonal blocks between two poses from dense priors.
+    pub(crate) pose_pairs: Vec<(usize, usize, Matrix6<f64>)>,
+    /// The pose and landmark of each observation.
+    pub(crate) observations: Vec<(usize, usize)>,
+    /// The observations of each landmark.
+    pub(crate) landmark_observations: Vec<Vec<usize>>,
+    /// The block of each pose in the reduced camera system, or `None` if it is fixed.
+    pub(crate) free: Vec<Option<usize>>,
+    pub(crate) blocks: usize,
+    pub(crate) line_hessians: Vec<Matrix4<f64>>,
+    pub(crate) line_gradients: Vec<Vector4<f64>>,
+    /// The off-diagonal block of each line observation.
+    pub(crate) pose_line: Vec<Matrix6x4<f64>>,
+    /// The pose and line of each line observation.
+    pub(crate) observed_lines: Vec<(usize, usize)>,
+    /// The line observations of each line.
+    pub(crate) line_observations: Vec<Vec<usize>>,
+    pub(crate) plane_hessians: Vec<Matrix3<f64>>,
+    pub(crate) plane_gradients: Vec<Vector3<f64>>,
+    /// The plane, the landmark, and the off-diagonal block of each point on a plane.
+    pub(crate) plane_landmark: Vec<(usize, usize, Matrix3<f64>)>,
+    /// The points on planes of each landmark.
+    pub(crate) landmark_planes: Vec<Vec<usize>>,
+    /// The pose, the plane, and the off-diagonal block of each camera above a plane.
+    pub(crate) pose_plane: Vec<(usize, usize, Matrix6x3<f64>)>,
+    pub(crate) linear_solver: SolverBackend,
+    pub(crate) precision: ReducedSolvePrecision,
+}
+
+/// Damps the diagonal of a block of the normal equations, as in [`Linearization::solve`].
+fn damp<const D: usize>(mut block: SMatrix<f64, D, D>, lambda: f64) -> SMatrix<f64, D, D> {
+    for i in 0..D {
+        block[(i, i)] += lambda * block[(i, i)].max(1e-12);
+    }
+    block
+}
+
+impl NormalEquations {
+    /// Inverts the damped landmark and line blocks. Landmarks and lines that can't be inverted (such as landmarks at
+    /// infinity, which have no observations in the normal equations) are not updated.
+    #[allow(clippy::type_complexity)]
+    fn eliminated_inverses(
+        &self,
+        lambda: f64,
+    ) -> (Vec<Option<Matrix3<f64>>>, Vec<Option<Matrix4<f64>>>) {
+        let landmark_inverses = self
+            .landmark_hessians
+            .iter()
+            .map(|&hessian| {
+                Some(hessian)
+                    .filter(|hessian| hessian != &Matrix3::zeros())
+                    .and_then(|hessian| damp(hessian, lambda).try_inverse())
+            })
+            .collect();
+        let line_inverses = self
+            .line_hessians
+            .iter()
+            .map(|&hessian| {
+                Some(hessian)
+                    .filter(|hessian| hessian != &Matrix4::zeros())
+                    .and_then(|hessian| damp(hessian, lambda).try_inverse())
+            })
+            .collect();
+        (landmark_inverses, line_inverses)
+    }
+
+    /// The right hand side `-g` of the reduced camera system with the landmarks and lines eliminated.
+    fn reduced_rhs(
+        &self,
+        landmark_inverses: &[Option<Matrix3<f64>>],
+        line_inverses: &[Option<Matrix4<f64>>],
+    ) -> DVector<f64> {
+        let plane_offset = self.plane_offset();
+        let mut rhs = DVector::zeros(self.landmark_offset());
+        for (pose, block) in self.free.iter().enumerate() {
+            if let Some(block) = *block {
+                rhs.fixed_rows_mut::<6>(6 * block)
+                    .copy_from(&-self.pose_gradients[pose]);
+            }
+        }
+        for (plane, (&hessian, gradient)) in self
+            .plane_hessians
+            .iter()
+            .zip(&self.plane_gradients)
+            .enumerate()
+        {
+            if hessian != Matrix3::zeros() {
+                rhs.fixed_rows_mut::<3>(plane_offset + 3 * plane)
+                    .copy_from(&-gradient);
+            }
+        }
+        for (landmark, observations) in self.landmark_observations.iter().enumerate() {
+            let inverse = match landmark_inverses[landmark] {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            let gradient = inverse * self.landmark_gradients[landmark];
+            for &a in observations {
+                if let Some(block) = self.free[self.observations[a].0] {
+                    let mut rhs_block = rhs.fixed_rows_mut::<6>(6 * block);
+                    rhs_block += self.pose_landmark[a] * gradient;
+                }
+            }
+            for &a in &self.landmark_planes[landmark] {
+                let (plane, _, coupling) = self.plane_landmark[a];
+                let mut rhs_block = rhs.fixed_rows_mut::<3>(plane_offset + 3 * plane);
+                rhs_block += coupling * gradient;
+            }
+        }
+        for (line, observations) in self.line_observations.iter().enumerate() {
+            let inverse = match line_inverses[line] {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            let gradient = inverse * self.line_gradients[line];
+            for &a in observations {
+                if let Some(block) = self.free[self.observed_lines[a].0] {
+                    let mut rhs_block = rhs.fixed_rows_mut::<6>(6 * block);
+                    rhs_block += self.pose_line[a] * gradient;
+                }
+            }
+        }
+        rhs
+    }
+
+    /// Builds the damped reduced camera system `(S, -g)` over the free poses and the planes by eliminating the
+    /// landmarks and lines, along with the inverses of the damped landmark and line blocks.
+    ///
+    /// The system has a block of 6 for every free pose followed by a block of 3 for every plane, and only the blocks
+    /// of poses and planes which share a factor or an eliminated landmark or line are stored.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn reduced_system(
+        &self,
+        lambda: f64,
+    ) -> (
+        BlockSparseMatrix<f64>,
+        DVector<f64>,
+        Vec<Option<Matrix3<f64>>>,
+        Vec<Option<Matrix4<f64>>>,
+    ) {
+        let (landmark_inverses, line_inverses) = self.eliminated_inverses(lambda);
+        let rhs = self.reduced_rhs(&landmark_inverses, &line_inverses);
+
+        let plane_block = |plane: usize| self.blocks + plane;
+        let block_sizes: Vec<usize> = core::iter::repeat(6)
+            .take(self.blocks)
+            .chain(core::iter::repeat(3).take(self.plane_hessians.len()))
+            .collect();
+        let mut reduced = BlockSparseMatrix::new(&block_sizes);
+        for (pose, block) in self.free.iter().enumerate() {
+            if let Some(block) = *block {
+                *reduced.block_mut(block, block) += damp(self.pose_hessians[pose], lambda);
+            }
+        }
+        for &(a, b, coupling) in &self.pose_pairs {
+            if let (Some(block_a), Some(block_b)) = (self.free[a], self.free[b]) {
+                *reduced.block_mut(block_a, block_b) += coupling;
+                *reduced.block_mut(block_b, block_a) += coupling.transpose();
+            }
+        }
+        for (plane, &hessian) in self.plane_hessians.iter().enumerate() {
+            // Planes without any factors are not updated.
+            if hessian == Matrix3::zeros() {
+                reduced
+                    .block_mut(plane_block(plane), plane_block(plane))
+                    .fill_with_identity();
+            } else {
+                *reduced.block_mut(plane_block(plane), plane_block(plane)) += damp(hessian, lambda);
+            }
+        }
+        for &(pose, plane, coupling) in &self.pose_plane {
+            if let Some(block) = self.free[pose] {
+                *reduced.block_mut(block, plane_block(plane)) += coupling;
+                *reduced.block_mut(plane_block(plane), block) += coupling.transpose();
+            }
+        }
+        for (landmark, observations) in self.landmark_observations.iter().enumerate() {
+            let inverse = match landmark_inverses[landmark] {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            for &a in observations {
+                let block_a = match self.free[self.observations[a].0] {
+                    Some(block) => block,
+                    None => continue,
+                };
+                let w = self.pose_landmark[a] * inverse;
+                for &b in observations {
+                    if let Some(block_b) = self.free[self.observations[b].0] {
+                        *reduced.block_mut(block_a, block_b) -=
+                            w * self.pose_landmark[b].transpose();
+                    }
+                }
+                for &b in &self.landmark_planes[landmark] {
+                    let (plane, _, coupling) = self.plane_landmark[b];
+                    let block = w * coupling.transpose();
+                    *reduced.block_mut(block_a, plane_block(plane)) -= block;
+                    *reduced.block_mut(plane_block(plane), block_a) -= block.transpose();
+                }
+            }
+            for &a in &self.landmark_planes[landmark] {
+                let (plane_a, _, coupling_a) = self.plane_landmark[a];
+                let w = coupling_a * inverse;
+                for &b in &self.landmark_planes[landmark] {
+                    let (plane_b, _, coupling_b) = self.plane_landmark[b];
+                    *reduced.block_mut(plane_block(plane_a), plane_block(plane_b)) -=
+                        w * coupling_b.transpose();
+                }
+            }
+        }
+        // Lines are eliminated in the same way.
+        for (line, observations) in self.line_observations.iter().enumerate() {
+            let inverse = match line_inverses[line] {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            for &a in observations {
+                let block_a = match self.free[self.observed_lines[a].0] {
+                    Some(block) => block,
+                    None => continue,
+                };
+                let w = self.pose_line[a] * inverse;
+                for &b in observations {
+                    if let Some(block_b) = self.free[self.observed_lines[b].0] {
+                        *reduced.block_mut(block_a, block_b) -= w * self.pose_line[b].transpose();
+                    }
+                }
+            }
+        }
+        (reduced, rhs, landmark_inverses, line_inverses)
+    }
+
+    /// Multiplies `v` by the damped reduced camera system without building it.
+    ///
+    /// The reduced camera system is `S = A - BC⁻¹Bᵀ`, where `A` is the block of the poses and planes, `B` couples
+    /// them to the landmarks and lines, and `C` is the block diagonal of the landmarks and lines. Both products with
+    /// the couplings come from [`Linearization::hessian_product`].
+    fn reduced_product(
+        &self,
+        lambda: f64,
+        landmark_inverses: &[Option<Matrix3<f64>>],
+        line_inverses: &[Option<Matrix4<f64>>],
+        v: &DVector<f64>,
+    ) -> DVector<f64> {
+        let plane_offset = self.plane_offset();
+        let landmark_offset = self.landmark_offset();
+        let line_offset = self.line_offset();
+        let mut padded = DVector::zeros(line_offset + 4 * self.line_hessians.len());
+        padded.rows_mut(0, landmark_offset).copy_from(v);
+        // This gives both `Av` and `Bᵀv`.
+        let coupled = self.hessian_product(&padded);
+
+        let mut eliminated = DVector::zeros(padded.len());
+        for (landmark, inverse) in landmark_inverses.iter().enumerate() {
+            if let Some(inverse) = inverse {
+                let offset = landmark_offset + 3 * landmark;
+                eliminated
+                    .fixed_rows_mut::<3>(offset)
+                    .copy_from(&(inverse * coupled.fixed_rows::<3>(offset)));
+            }
+        }
+        for (line, inverse) in line_inverses.iter().enumerate() {
+            if let Some(inverse) = inverse {
+                let offset = line_offset + 4 * line;
+                eliminated
+                    .fixed_rows_mut::<4>(offset)
+                    .copy_from(&(inverse * coupled.fixed_rows::<4>(offset)));
+            }
+        }
+        // The poses and planes of this are `BC⁻¹Bᵀv`.
+        let back = self.hessian_product(&eliminated);
+
+        let mut product = coupled.rows(0, landmark_offset) - back.rows(0, landmark_offset);
+        for (pose, block) in self.free.iter().enumerate() {
+            if let Some(block) = *block {
+                let hessian = &self.pose_hessians[pose];
+                for i in 0..6 {
+                    product[6 * block + i] +=
+                        lambda * hessian[(i, i)].max(1e-12) * v[6 * block + i];
+                }
+            }
+        }
+        for (plane, hessian) in self.plane_hessians.iter().enumerate() {
+            let offset = plane_offset + 3 * plane;
+            for i in 0..3 {
+                product[offset + i] += if hessian == &Matrix3::zeros() {
+                    v[offset + i]
+                } else {
+                    lambda * hessian[(i, i)].max(1e-12) * v[offset + i]
+                };
+            }
+        }
+        product
+    }
+
+    /// The diagonal of the damped reduced camera system, which preconditions [`NormalEquations::reduced_product`].
+    fn reduced_diagonal(
+        &self,
+        lambda: f64,
+        landmark_inverses: &[Option<Matrix3<f64>>],
+        line_inverses: &[Option<Matrix4<f64>>],
+    ) -> DVector<f64> {
+        let plane_offset = self.plane_offset();
+        let mut diagonal = DVector::zeros(self.landmark_offset());
+        for (pose, block) in self.free.iter().enumerate() {
+            if let Some(block) = *block {
+                diagonal
+                    .fixed_rows_mut::<6>(6 * block)
+                    .copy_from(&damp(self.pose_hessians[pose], lambda).diagonal());
+            }
+        }
+        for &(a, b, coupling) in &self.pose_pairs {
+            if let (Some(block_a), Some(block_b)) = (self.free[a], self.free[b]) {
+                if block_a == block_b {
+                    let mut rows = diagonal.fixed_rows_mut::<6>(6 * block_a);
+                    rows += coupling.diagonal() * 2.0;
+                }
+            }
+        }
+        for (plane, &hessian) in self.plane_hessians.iter().enumerate() {
+            let diagonal_block = if hessian == Matrix3::zeros() {
+                Vector3::repeat(1.0)
+            } else {
+                damp(hessian, lambda).diagonal()
+            };
+            diagonal
+                .fixed_rows_mut::<3>(plane_offset + 3 * plane)
+                .copy_from(&diagonal_block);
+        }
+        for (landmark, observations) in self.landmark_observations.iter().enumerate() {
+            if let Some(inverse) = landmark_inverses[landmark] {
+                for &a in observations {
+                    if let Some(block) = self.free[self.observations[a].0] {
+                        let coupling = self.pose_landmark[a];
+                        let mut rows = diagonal.fixed_rows_mut::<6>(6 * block);
+                        rows -= (coupling * inverse * coupling.transpose()).diagonal();
+                    }
+                }
+                for &a in &self.landmark_planes[landmark] {
+                    let (plane, _, coupling) = self.plane_landmark[a];
+                    let mut rows = diagonal.fixed_rows_mut::<3>(plane_offset + 3 * plane);
+                    rows -= (coupling * inverse * coupling.transpose()).diagonal();
+                }
+            }
+        }
+        for (line, observations) in self.line_observations.iter().enumerate() {
+            if let Some(inverse) = line_inverses[line] {
+                for &a in observations {
+                    if let Some(block) = self.free[self.observed_lines[a].0] {
+                        let coupling = self.pose_line[a];
+                        let mut rows = diagonal.fixed_rows_mut::<6>(6 * block);
+                        rows -= (coupling * inverse * coupling.transpose()).diagonal();
+                    }
+                }
+            }
+        }
+        diagonal
+    }
+
+    /// The offset of the planes in the step, which come after the poses.
+    fn plane_offset(&self) -> usize {
+        6 * self.blocks
+    }
+
+    /// The offset of the landmarks in the step, which come after the poses and planes. This is also the size of
+    /// the reduced camera system.
+    pub(crate) fn landmark_offset(&self) -> usize {
+        self.plane_offset() + 3 * self.plane_hessians.len()
+    }
+
+    /// The offset of the lines in the step, which come after the landmarks.
+    fn line_offset(&self) -> usize {
+        self.landmark_offset() + 3 * self.landmark_hessians.len()
+    }
+}
+
+impl Linearization for NormalEquations {
+    fn gradient(&self) -> DVector<f64> {
+        let mut gradient = DVector::zeros(self.line_offset() + 4 * self.line_gradients.len());
+        for (pose, block) in self.free.iter().enumerate() {
+            if let Some(block) = *block {
+                gradient
+                    .fixed_rows_mut::<6>(6 * block)
+                    .copy_from(&self.pose_gradients[pose]);
+            }
+        }
+        for (plane, plane_gradient) in self.plane_gradients.iter().enumerate() {
+            gradient
+                .fixed_rows_mut::<3>(self.plane_offset() + 3 * plane)
+                .copy_from(plane_gradient);
+        }
+        for (landmark, landmark_gradient) in self.landmark_gradients.iter().enumerate() {
+            gradient
+                .fixed_rows_mut::<3>(self.landmark_offset() + 3 * landmark)
+                .copy_from(landmark_gradient);
+        }
+        for (line, line_gradient) in self.line_gradients.iter().enumerate() {
+            gradient
+                .fixed_rows_mut::<4>(self.line_offset() + 4 * line)
+                .copy_from(line_gradient);
+        }
+        gradient
+    }
+
+    fn hessian_product(&self, v: &DVector<f64>) -> DVector<f64> {
+        let plane_offset = self.plane_offset();
+        let landmark_offset = self.landmark_offset();
+        let mut product = DVector::zeros(v.len());
+        for (plane, hessian) in self.plane_hessians.iter().enumerate() {
+            let mut rows = product.fixed_rows_mut::<3>(plane_offset + 3 * plane);
+            rows += hessian * v.fixed_rows::<3>(plane_offset + 3 * plane);
+        }
+        for &(plane, landmark, coupling) in &self.plane_landmark {
+            let plane_rows = v.fixed_rows::<3>(plane_offset + 3 * plane).into_owned();
+            let landmark_rows = v
+                .fixed_rows::<3>(landmark_offset + 3 * landmark)
+                .into_owned();
+            let mut rows = product.fixed_rows_mut::<3>(plane_offset + 3 * plane);
+            rows += coupling * landmark_rows;
+            let mut rows = product.fixed_rows_mut::<3>(landmark_offset + 3 * landmark);
+            rows += coupling.transpose() * plane_rows;
+        }
+        for &(pose, plane, coupling) in &self.pose_plane {
+            if let Some(block) = self.free[pose] {
+                let pose_rows = v.fixed_rows::<6>(6 * block).into_owned();
+                let plane_rows = v.fixed_rows::<3>(plane_offset + 3 * plane).into_owned();
+                let mut rows = product.fixed_rows_mut::<6>(6 * block);
+                rows += coupling * plane_rows;
+                let mut rows = product.fixed_rows_mut::<3>(plane_offset + 3 * plane);
+                rows += coupling.transpose() * pose_rows;
+            }
+        }
+        for (pose, block) in self.free.iter().enumerate() {
+            if let Some(block) = *block {
+                let mut rows = product.fixed_rows_mut::<6>(6 * block);
+                rows += self.pose_hessians[pose] * v.fixed_rows::<6>(6 * block);
+            }
+        }
+        for (landmark, hessian) in self.landmark_hessians.iter().enumerate() {
+            let mut rows = product.fixed_rows_mut::<3>(landmark_offset + 3 * landmark);
+            rows += hessian * v.fixed_rows::<3>(landmark_offset + 3 * landmark);
+        }
+        for (&(pose, landmark), coupling) in self.observations.iter().zip(&self.pose_landmark) {
+            if let Some(block) = self.free[pose] {
+                let pose_rows = v.fixed_rows::<6>(6 * block).into_owned();
+                let landmark_rows = v
+                    .fixed_rows::<3>(landmark_offset + 3 * landmark)
+                    .into_owned();
+                let mut rows = product.fixed_rows_mut::<6>(6 * block);
+                rows += coupling * landmark_rows;
+                let mut rows = product.fixed_rows_mut::<3>(landmark_offset + 3 * landmark);
+                rows += coupling.transpose() * pose_rows;
+            }
+        }
+        let line_offset = self.line_offset();
+        for (line, hessian) in self.line_hessians.iter().enumerate() {
+            let mut rows = product.fixed_rows_mut::<4>(line_offset + 4 * line);
+            rows += hessian * v.fixed_rows::<4>(line_offset + 4 * line);
+        }
+        for (&(pose, line), coupling) in self.observed_lines.iter().zip(&self.pose_line) {
+            if let Some(block) = self.free[pose] {
+                let pose_rows = v.fixed_rows::<6>(6 * block).into_owned();
+                let line_rows = v.fixed_rows::<4>(line_offset + 4 * line).into_owned();
+                let mut rows = product.fixed_rows_mut::<6>(6 * block);
+                rows += coupling * line_rows;
+                let mut rows = product.fixed_rows_mut::<4>(line_offset + 4 * line);
+                rows += coupling.transpose() * pose_rows;
+            }
+        }
+        for &(a, b, coupling) in &self.pose_pairs {
+            if let (Some(block_a), Some(block_b)) = (self.free[a], self.free[b]) {
+                let rows_a = v.fixed_rows::<6>(6 * block_a).into_owned();
+                let rows_b = v.fixed_rows::<6>(6 * block_b).into_owned();
+                let mut rows = product.fixed_rows_mut::<6>(6 * block_a);
+                rows += coupling * rows_b;
+                let mut rows = product.fixed_rows_mut::<6>(6 * block_b);
+                rows += coupling.transpose() * rows_a;
+            }
+        }
+        product
+    }
+
+    /// Computes the damped step for the poses, landmarks, and lines by eliminating the landmarks and lines with the
+    /// Schur complement.
+    ///
+    /// An explicitly selected [`SolverBackend::ConjugateGradient`] never builds the reduced camera system, and its
+    /// products are computed in `f64` regardless of the [`ReducedSolvePrecision`].
+    fn solve(&self, lambda: f64) -> Option<DVector<f64>> {
+        let (pose_step, landmark_inverses, line_inverses) = match self.linear_solver {
+            SolverBackend::ConjugateGradient(solver) => {
+                let (landmark_inverses, line_inverses) = self.eliminated_inverses(lambda);
+                let rhs = self.reduced_rhs(&landmark_inverses, &line_inverses);
+                let diagonal = self.reduced_diagonal(lambda, &landmark_inverses, &line_inverses);
+                let pose_step = solver.solve_with(
+                    |v| self.reduced_product(lambda, &landmark_inverses, &line_inverses, v),
+                    &diagonal,
+                    &rhs,
+                )?;
+                (pose_step, landmark_inverses, line_inverses)
+            }
+            _ => {
+                let (reduced, rhs, landmark_inverses, line_inverses) = self.reduced_system(lambda);
+                let pose_step = self.precision.solve(&self.linear_solver, &reduced, &rhs)?;
+                (pose_step, landmark_inverses, line_inverses)
+            }
+        };
+        let landmark_offset = self.landmark_offset();
+        let line_offset = self.line_offset();
+        let mut step = DVector::zeros(line_offset + 4 * self.line_hessians.len());
+        step.rows_mut(0, landmark_offset).copy_from(&pose_step);
+
+        // Back-substitute the pose steps to find the landmark steps.
+        for (landmark, observations) in self.landmark_observations.iter().enumerate() {
+            if let Some(inverse) = landmark_inverses[landmark] {
+                let coupled: Vector3<f64> = observations
+                    .iter()
+                    .filter_map(|&ix| {
+                        let block = self.free[self.observations[ix].0]?;
+                        Some(
+                            self.pose_landmark[ix].transpose()
+                                * pose_step.fixed_rows::<6>(6 * block),
+                        )
+                    })
+                    .chain(self.landmark_planes[landmark].iter().map(|&ix| {
+                        let (plane, _, coupling) = self.plane_landmark[ix];
+                        coupling.transpose()
+                            * pose_step.fixed_rows::<3>(self.plane_offset() + 3 * plane)
+                    }))
+                    .sum();
+                step.fixed_rows_mut::<3>(landmark_offset + 3 * landmark)
+                    .copy_from(&(-inverse * (self.landmark_gradients[landmark] + coupled)));
+            }
+        }
+        for (line, observations) in self.line_observations.iter().enumerate() {
+            if let Some(inverse) = line_inverses[line] {
+                let coupled: Vector4<f64> = observations
+                    .iter()
+                    .filter_map(|&ix| {
+                        let block = self.free[self.observed_lines[ix].0]?;
+                        Some(self.pose_line[ix].transpose() * pose_step.fixed_rows::<6>(6 * block))
+                    })
+                    .sum();
+                step.fixed_rows_mut::<4>(line_offset + 4 * line)
+                    .copy_from(&(-inverse * (self.line_gradients[line] + coupled)));
+            }
+        }
+        Some(step)
+    }
+}
+
+/// The weighted normal equation blocks of a single observation.
+struct ObservationBlocks {
+    pose_hessian: Matrix6<f64>,
+    pose_gradient: Vector6<f64>,
+    landmark_hessian: Matrix3<f64>,
+    landmark_gradient: Vector3<f64>,
+    pose_landmark: Matrix6x3<f64>,
+}
+
+/// A bundle adjustment problem along with the losses of the adjuster.
+#[derive(Clone)]
+struct BundleAdjusting<'a> {
+    adjuster: &'a BundleAdjuster,
+    problem: BundleAdjustment,
+    /// The priors of [`Gauge::Prior`] at the initial poses.
+    gauge_priors: Vec<PosePrior>,
+    free: Vec<Option<usize>>,
+    blocks: usize,
+}
+
+impl<'a> BundleAdjusting<'a> {
+    fn new(adjuster: &'a BundleAdjuster, problem: BundleAdjustment) -> Self {
+        // Assign each free pose a block in the reduced camera system.
+        let mut free = vec![None; problem.poses.len()];
+        let mut blocks = 0;
+        for (ix, block) in free.iter_mut().enumerate() {
+            if !problem.is_fixed(ix) {
+                *block = Some(blocks);
+                blocks += 1;
+            }
+        }
+        if problem.is_unconstrained() {
+            log::warn!("bundle adjustment has no fixed poses or priors, so its gauge freedom is not removed");
+        }
+        Self {
+            adjuster,
+            gauge_priors: problem.gauge_priors(),
+            problem,
+            free,
+            blocks,
+        }
+    }
+}
+
+impl LeastSquaresProblem for BundleAdjusting<'_> {
+    type Linearization = NormalEquations;
+
+    fn cost(&self) -> f64 {
+        self.adjuster.cost(&self.problem)
+            + self
+                .gauge_priors
+                .iter()
+                .map(|prior| prior.cost(self.problem.poses[prior.pose]))
+                .sum::<f64>()
+    }
+
+    /// Builds the normal equations with the iteratively reweighted least squares weights.
+    fn linearize(&self) -> NormalEquations {
+        let problem = &self.problem;
+        let observations: Vec<(usize, usize)> = problem
+            .observations
+            .iter()
+            .map(|observation| (observation.pose, observation.landmark))
+            .collect();
+        let mut pose_observations = vec![vec![]; problem.poses.len()];
+        let mut landmark_observations = vec![vec![]; problem.landmarks.len()];
+        for (ix, &(pose, landmark)) in observations.iter().enumerate() {
+            pose_observations[pose].push(ix);
+            landmark_observations[landmark].push(ix);
+        }
+
+        // Evaluate the residual and Jacobians of every observation, which dominates the time of each iteration.
+        let linearize = |observation: &Observation| -> Option<ObservationBlocks> {
+            let pose = problem.poses[observation.pose];
+            let point = problem.landmarks[observation.landmark].point()?;
+            let (residual, jacobian_pose, jacobian_landmark) =
+                linearize_observation(pose, point, observation.bearing);
+            let weight = observation
+                .loss
+                .unwrap_or(self.adjuster.loss)
+                .weight(residual.norm());
+            Some(ObservationBlocks {
+                pose_hessian: weight * jacobian_pose.transpose() * jacobian_pose,
+                pose_gradient: weight * jacobian_pose.transpose() * residual,
+                landmark_hessian: weight * jacobian_landmark.transpose() * jacobian_landmark,
+                landmark_gradient: weight * jacobian_landmark.transpose() * residual,
+                pose_landmark: weight * jacobian_pose.transpose() * jacobian_landmark,
+            })
+        };
+        #[cfg(feature = "rayon")]
+        let blocks: Vec<Option<ObservationBlocks>> =
+            problem.observations.par_iter().map(linearize).collect();
+        #[cfg(not(feature = "rayon"))]
+        let blocks: Vec<Option<ObservationBlocks>> =
+            problem.observations.iter().map(linearize).collect();
+
+        // Reduce the blocks of each pose and landmark over its own observations, so that no two threads write to the
+        // same block.
+        let reduce_pose = |observations: &Vec<usize>| {
+            observations
+                .iter()
+                .filter_map(|&ix| blocks[ix].as_ref())
+                .fold((Matrix6::zeros(), Vector6::zeros()), |(h, g), b| {
+                    (h + b.pose_hessian, g + b.pose_gradient)
+                })
+        };
+        let reduce_landmark = |observations: &Vec<usize>| {
+            observations
+                .iter()
+                .filter_map(|&ix| blocks[ix].as_ref())
+                .fold((Matrix3::zeros(), Vector3::zeros()), |(h, g), b| {
+                    (h + b.landmark_hessian, g + b.landmark_gradient)
+                })
+        };
+        #[cfg(feature = "rayon")]
+        let ((pose_hessians, pose_gradients), (landmark_hessians, landmark_gradients)) = (
+            pose_observations.par_iter().map(reduce_pose).unzip(),
+            landmark_observations
+                .par_iter()
+                .map(reduce_landmark)
+                .unzip(),
+        );
+        #[cfg(not(feature = "rayon"))]
+        let ((pose_hessians, pose_gradients), (landmark_hessians, landmark_gradients)) = (
+            pose_observations.iter().map(reduce_pose).unzip(),
+            landmark_observations.iter().map(reduce_landmark).unzip(),
+        );
+
+        let mut normal = NormalEquations {
+            pose_hessians,
+            pose_gradients,
+            landmark_hessians,
+            landmark_gradients,
+            pose_landmark: blocks
+                .iter()
+                .map(|b| {
+                    b.as_ref()
+                        .map_or_else(Matrix6x3::zeros, |b| b.pose_landmark)
+                })
+                .collect(),
+            pose_pairs: vec![],
+            observations,
+            landmark_observations,
+            free: self.free.clone(),
+            blocks: self.blocks,
+            line_hessians: vec![Matrix4::zeros(); problem.lines.len()],
+            line_gradients: vec![Vector4::zeros(); problem.lines.len()],
+            pose_line: Vec::with_capacity(problem.line_observations.len()),
+            observed_lines: Vec::with_capacity(problem.line_observations.len()),
+            line_observations: vec![vec![]; problem.lines.len()],
+            plane_hessians: vec![Matrix3::zeros(); problem.planes.len()],
+            plane_gradients: vec![Vector3::zeros(); problem.planes.len()],
+            plane_landmark: Vec::with_capacity(problem.points_on_planes.len()),
+            landmark_planes: vec![vec![]; problem.landmarks.len()],
+            pose_plane: Vec::with_capacity(problem.cameras_above_planes.len()),
+            linear_solver: self.adjuster.linear_solver,
+            precision: self.adjuster.precision,
+        };
+        for factor in &problem.points_on_planes {
+            let point = match problem.landmarks[factor.landmark].point() {
+                Some(point) => point,
+                None => continue,
+            };
+            let (error, jacobian_landmark, jacobian_plane) =
+                factor.linearize(&problem.planes[factor.plane], point);
+            let weight = factor.loss.weight(error);
+            normal.landmark_hessians[factor.landmark] +=
+                weight * jacobian_landmark.transpose() * jacobian_landmark;
+            normal.landmark_gradients[factor.landmark] +=
+                weight * jacobian_landmark.transpose() * error;
+            normal.plane_hessians[factor.plane] +=
+                weight * jacobian_plane.transpose() * jacobian_plane;
+            normal.plane_gradients[factor.plane] += weight * jacobian_plane.transpose() * error;
+            normal.landmark_planes[factor.landmark].push(normal.plane_landmark.len());
+            normal.plane_landmark.push((
+                factor.plane,
+                factor.landmark,
+                weight * jacobian_plane.transpose() * jacobian_landmark,
+            ));
+        }
+        for factor in &problem.cameras_above_planes {
+            let (error, jacobian_pose, jacobian_plane) =
+                factor.linearize(&problem.planes[factor.plane], problem.poses[factor.pose]);
+            let weight = factor.loss.weight(error);
+            normal.pose_hessians[factor.pose] += weight * jacobian_pose.transpose() * jacobian_pose;
+            normal.pose_gradients[factor.pose] += weight * jacobian_pose.transpose() * error;
+            normal.plane_hessians[factor.plane] +=
+                weight * jacobian_plane.transpose() * jacobian_plane;
+            normal.plane_gradients[factor.plane] += weight * jacobian_plane.transpose() * error;
+            normal.pose_plane.push((
+                factor.pose,
+                factor.plane,
+                weight * jacobian_pose.transpose() * jacobian_plane,
+            ));
+        }
+        // Depths couple a pose and a landmark like an observation, so they are eliminated with the observations.
+        for factor in &problem.depths {
+            let point = match problem.landmarks[factor.landmark].point() {
+                Some(point) => point,
+                None => continue,
+            };
+            let (error, jacobian_pose, jacobian_landmark) =
+                factor.linearize(problem.poses[factor.pose], point);
+            let weight = factor.loss.weight(error);
+            normal.pose_hessians[factor.pose] += weight * jacobian_pose.transpose() * jacobian_pose;
+            normal.pose_gradients[factor.pose] += weight * jacobian_pose.transpose() * error;
+            normal.landmark_hessians[factor.landmark] +=
+                weight * jacobian_landmark.transpose() * jacobian_landmark;
+            normal.landmark_gradients[factor.landmark] +=
+                weight * jacobian_landmark.transpose() * error;
+            normal.landmark_observations[factor.landmark].push(normal.observations.len());
+            normal.observations.push((factor.pose, factor.landmark));
+            normal
+                .pose_landmark
+                .push(weight * jacobian_pose.transpose() * jacobian_landmark);
+        }
+        for (ix, observation) in problem.line_observations.iter().enumerate() {
+            normal
+                .observed_lines
+                .push((observation.pose, observation.line));
+            normal.line_observations[observation.line].push(ix);
+            let (residual, jacobian_pose, jacobian_line) = linearize_line_observation(
+                observation,
+                problem.poses[observation.pose],
+                &problem.lines[observation.line],
+            );
+            let weight = observation
+                .loss
+                .unwrap_or(self.adjuster.loss)
+                .weight(residual.norm());
+            normal.pose_hessians[observation.pose] +=
+                weight * jacobian_pose.transpose() * jacobian_pose;
+            normal.pose_gradients[observation.pose] +=
+                weight * jacobian_pose.transpose() * residual;
+            normal.line_hessians[observation.line] +=
+                weight * jacobian_line.transpose() * jacobian_line;
+            normal.line_gradients[observation.line] +=
+                weight * jacobian_line.transpose() * residual;
+            normal
+                .pose_line
+                .push(weight * jacobian_pose.transpose() * jacobian_line);
+        }
+        for prior in problem.priors.iter().chain(&self.gauge_priors) {
+            let (error, norm, jacobian) = prior.linearize(problem.poses[prior.pose]);
+            let information = prior.loss.weight(norm) * prior.information;
+            normal.pose_hessians[prior.pose] += jacobian.transpose() * information * jacobian;
+            normal.pose_gradients[prior.pose] += jacobian.transpose() * information * error;
+        }
+        for prior in &problem.gnss {
+            let (error, jacobian) = prior.linearize(problem.poses[prior.pose]);
+            let information = prior.loss.weight(prior.mahalanobis(error)) * prior.information;
+            normal.pose_hessians[prior.pose] += jacobian.transpose() * information * jacobian;
+            normal.pose_gradients[prior.pose] += jacobian.transpose() * information * error;
+        }
+        for prior in &problem.marginal_priors {
+            let error = prior.error(&problem.poses);
+            for (i, &a) in prior.poses.iter().enumerate() {
+                let jacobian_a = prior.jacobian.columns(6 * i, 6);
+                let gradient: Vector6<f64> = (jacobian_a.transpose() * &error)
+                    .fixed_rows::<6>(0)
+                    .into_owned();
+                normal.pose_gradients[a] += gradient;
+                for (j, &b) in prior.poses.iter().enumerate().skip(i) {
+                    let block = jacobian_a.transpose() * prior.jacobian.columns(6 * j, 6);
+                    let block: Matrix6<f64> = block.fixed_slice::<6, 6>(0, 0).into_owned();
+                    if i == j {
+                        normal.pose_hessians[a] += block;
+                    } else {
+                        normal.pose_pairs.push((a, b, block));
+                    }
+                }
+            }
+        }
+        normal
+    }
+
+    fn retract(&self, step: &DVector<f64>) -> Self {
+        let mut candidate = self.clone();
+        for (pose, block) in candidate.problem.poses.iter_mut().zip(&self.free) {
+            if let Some(block) = *block {
+                *pose = update_pose(*pose, step.fixed_rows::<6>(6 * block).into_owned());
+            }
+        }
+        let plane_offset = 6 * self.blocks;
+        for (ix, plane) in candidate.problem.planes.iter_mut().enumerate() {
+            *plane = plane.retract(step.fixed_rows::<3>(plane_offset + 3 * ix).into_owned());
+        }
+        let landmark_offset = plane_offset + 3 * self.problem.planes.len();
+        for (ix, landmark) in candidate.problem.landmarks.iter_mut().enumerate() {
+            if let Some(point) = landmark.point() {
+                *landmark = WorldPoint::from_point(
+                    point + step.fixed_rows::<3>(landmark_offset + 3 * ix).into_owned(),
+                );
+            }
+        }
+        let line_offset = landmark_offset + 3 * self.problem.landmarks.len();
+        for (ix, line) in candidate.problem.lines.iter_mut().enumerate() {
+            *line = line.retract(step.fixed_rows::<4>(line_offset + 4 * ix).into_owned());
+        }
+        candidate
+    }
+}
+
+impl BundleAdjuster {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    #[must_use]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn initial_lambda(self, initial_lambda: f64) -> Self {
+        Self {
+            initial_lambda,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    #[must_use]
+    pub fn linear_solver(self, linear_solver: SolverBackend) -> Self {
+        Self {
+            linear_solver,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn precision(self, precision: ReducedSolvePrecision) -> Self {
+        Self { precision, ..self }
+    }
+
+    /// The total robust loss of the problem.
+    pub fn cost(&self, problem: &BundleAdjustment) -> f64 {
+        let observation_cost = |observation: &Observation| {
+            let pose = problem.poses[observation.pose];
+            let landmark = problem.landmarks[observation.landmark];
+            let residual =
+                pose.transform(landmark).bearing().into_inner() - observation.bearing.into_inner();
+            observation.loss.unwrap_or(self.loss).loss(residual.norm())
+        };
+        #[cfg(feature = "rayon")]
+        let observations: f64 = problem.observations.par_iter().map(observation_cost).sum();
+        #[cfg(not(feature = "rayon"))]
+        let observations: f64 = problem.observations.iter().map(observation_cost).sum();
+        observations
+            + problem
+                .line_observations
+                .iter()
+                .map(|observation| {
+                    let residual = observation.residual(
+                        problem.poses[observation.pose],
+                        &problem.lines[observation.line],
+                    );
+                    observation.loss.unwrap_or(self.loss).loss(residual.norm())
+                })
+                .sum::<f64>()
+            + problem
+                .depths
+                .iter()
+                .filter_map(|factor| {
+                    let point = problem.landmarks[factor.landmark].point()?;
+                    let error = factor.error(problem.poses[factor.pose], point);
+                    Some(factor.loss.loss(error))
+                })
+                .sum::<f64>()
+            + problem
+                .points_on_planes
+                .iter()
+                .filter_map(|factor| {
+                    let point = problem.landmarks[factor.landmark].point()?;
+                    let error = factor.error(&problem.planes[factor.plane], point);
+                    Some(factor.loss.loss(error))
+                })
+                .sum::<f64>()
+            + problem
+                .cameras_above_planes
+                .iter()
+                .map(|factor| {
+                    let error =
+                        factor.error(&problem.planes[factor.plane], problem.poses[factor.pose]);
+                    factor.loss.loss(error)
+                })
+                .sum::<f64>()
+            + problem
+                .priors
+                .iter()
+                .map(|prior| prior.cost(problem.poses[prior.pose]))
+                .chain(
+                    problem
+                        .gnss
+                        .iter()
+                        .map(|prior| prior.cost(problem.poses[prior.pose])),
+                )
+                .chain(
+                    problem
+                        .marginal_priors
+                        .iter()
+                        .map(|prior| prior.cost(&problem.poses)),
+                )
+                .sum::<f64>()
+    }
+
+    /// Linearizes the problem at its current parameters.
+    pub(crate) fn normal_equations(&self, problem: &BundleAdjustment) -> NormalEquations {
+        BundleAdjusting::new(self, problem.clone()).linearize()
+    }
+
+    /// Optimizes the problem in place.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "bundle_adjustment", skip_all, fields(poses = problem.poses.len(), landmarks = problem.landmarks.len(), observations = problem.observations.len(), iterations, initial_cost, final_cost))
+    )]
+    pub fn optimize(&self, problem: &mut BundleAdjustment) -> OptimizationReport {
+        let mut adjusting = BundleAdjusting::new(self, core::mem::take(problem));
+        let report = LevenbergMarquardt::new()
+            .trust_region(TrustRegion::Damping(self.initial_lambda))
+            .max_iterations(self.max_iterations)
+            .step_tolerance(self.tolerance)
+            .minimize(&mut adjusting);
+        *problem = adjusting.problem;
+        log::info!(
+            "bundle adjustment finished after {} iterations with cost {} (initially {})",
+            report.iterations,
+            report.final_cost,
+            report.initial_cost
+        );
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("iterations", &report.iterations);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("initial_cost", &report.initial_cost);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("final_cost", &report.final_cost);
+        report
+    }
+}
+
+/// The bearing residual of an observation of a euclidean landmark and its Jacobians in respect to the pose in
+/// se(3) and the landmark.
+pub(crate) fn linearize_observation(
+    pose: WorldToCamera,
+    point: Point3<f64>,
+    bearing: UnitVector3<f64>,
+) -> (Vector3<f64>, Matrix3x6<f64>, Matrix3<f64>) {
+    let isometry = pose.isometry();
+    let rotated = isometry.rotation * point.coords;
+    let camera = rotated + isometry.translation.vector;
+    let norm = camera.norm();
+    let direction = camera / norm;
+    // The Jacobian of normalizing the point.
+    let normalize = (Matrix3::identity() - direction * direction.transpose()) / norm;
+    let mut jacobian_pose = Matrix3x6::zeros();
+    jacobian_pose
+        .fixed_columns_mut::<3>(0)
+        .copy_from(&normalize);
+    jacobian_pose
+        .fixed_columns_mut::<3>(3)
+        .copy_from(&(-normalize * rotated.cross_matrix()));
+    let jacobian_landmark = normalize * isometry.rotation.matrix();
+    (
+        direction - bearing.into_inner(),
+        jacobian_pose,
+        jacobian_landmark,
+    )
+}
+
+/// Applies a step in se(3) matching the convention of [`Pose::transform_jacobian_self`].
+pub(crate) fn update_pose(pose: WorldToCamera, step: Vector6<f64>) -> WorldToCamera {
+    let isometry = pose.isometry();
+    let rotation: Rotation3<f64> = Skew3(step.fixed_rows::<3>(3).into_owned()).into();
+    WorldToCamera(IsometryMatrix3::from_parts(
+        (isometry.translation.vector + step.xyz()).into(),
+        rotation * isometry.rotation,
+    ))
+}
@@ -0,0 +1,162 @@
+use crate::{BundleAdjuster, BundleAdjustment};
+use cv_core::nalgebra::{DMatrix, Matrix3, Matrix6, Matrix6x3, Vector3, Vector6};
+
+/// The marginal covariances of selected poses and landmarks of a [`BundleAdjustment`].
+///
+/// Pose covariances are of the se(3) step `(δt, ω)` that the [`BundleAdjuster`] applies (see
+/// [`Se3TangentSpace`](cv_core::Se3TangentSpace)) and landmark covariances are of the euclidean point. The residuals
+/// of observations are bearing differences with unit information, so the covariances must be scaled by the variance
+/// of the bearing noise, such as the squared pixel noise divided by the squared focal length.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Covariances {
+    /// The covariance of each requested pose, which is zero for fixed poses.
+    pub poses: Vec<Matrix6<f64>>,
+    /// The covariance of each requested landmark.
+    pub landmarks: Vec<Matrix3<f64>>,
+}
+
+impl Covariances {
+    /// The Mahalanobis distance of the translation and rotation difference `delta` of a requested pose.
+    ///
+    /// This is used to gate whether a measurement of a pose (such as a loop closure) agrees with the estimate.
+    pub fn pose_mahalanobis(&self, pose: usize, delta: Vector6<f64>) -> Option<f64> {
+        let information = self.poses[pose].try_inverse()?;
+        Some(delta.dot(&(information * delta)).max(0.0).sqrt())
+    }
+
+    /// The Mahalanobis distance of the difference `delta` of the position of a requested landmark.
+    pub fn landmark_mahalanobis(&self, landmark: usize, delta: Vector3<f64>) -> Option<f64> {
+        let information = self.landmarks[landmark].try_inverse()?;
+        Some(delta.dot(&(information * delta)).max(0.0).sqrt())
+    }
+}
+
+impl BundleAdjuster {
+    /// Computes the marginal covariances of `poses` and `landmarks` from the Hessian of the problem at its current
+    /// parameters, which should be optimized.
+    ///
+    /// Only the block columns of the inverse of the reduced camera system which are needed for the requested poses
+    /// and the poses and planes coupled to the requested landmarks are solved for, using its Cholesky factorization. The
+    /// landmark covariance is then recovered from the Schur complement as
+    /// `V⁻¹ + V⁻¹ Wᵀ Σ W V⁻¹`, where `V` is the landmark block, `W` is the coupling with the poses that observe it,
+    /// and `Σ` is the joint covariance of those poses.
+    ///
+    /// Returns `None` if the gauge freedom of the problem is not fixed (by fixed poses or priors) or a requested
+    /// landmark is not constrained, since the Hessian is then singular.
+    ///
+    /// ```
+    /// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3, Vector6}, *};
+    /// use cv_optimize::{BundleAdjuster, BundleAdjustment, Observation};
+    ///
+    /// let poses: Vec<WorldToCamera> = (0..3)
+    ///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+    ///         Vector3::new(-0.3 * i as f64, 0.0, 0.0).into(),
+    ///         Rotation3::identity(),
+    ///     )))
+    ///     .collect();
+    /// let landmarks: Vec<WorldPoint> = (0..20)
+    ///     .map(|i| WorldPoint::from_point(Point3::new((i % 5) as f64 * 0.4 - 0.8, (i / 5) as f64 * 0.3 - 0.5, 4.0 + (i % 3) as f64)))
+    ///     .collect();
+    /// let mut problem = BundleAdjustment::new(poses.clone(), landmarks.clone());
+    /// problem.fix_pose(0);
+    /// problem.fix_pose(1);
+    /// for (p, pose) in poses.iter().enumerate() {
+    ///     for (l, &landmark) in landmarks.iter().enumerate() {
+    ///         problem.observe(Observation::new(p, l, pose.transform(landmark).bearing()));
+    ///     }
+    /// }
+    ///
+    /// let covariances = BundleAdjuster::new().covariances(&problem, &[1, 2], &[0, 19]).unwrap();
+    /// assert_eq!(covariances.poses[0], Default::default());
+    /// assert!(covariances.poses[1].symmetric_eigenvalues().iter().all(|&e| e > 0.0));
+    /// // The depth of a landmark is far less certain than its lateral position.
+    /// let landmark = covariances.landmarks[1];
+    /// assert!(landmark[(2, 2)] > 10.0 * landmark[(0, 0)]);
+    ///
+    /// // Gate a measurement on its Mahalanobis distance with a bearing noise of 1e-3.
+    /// let noise = 1e-3f64;
+    /// let scaled = cv_optimize::Covariances {
+    ///     poses: covariances.poses.iter().map(|c| c * noise * noise).collect(),
+    ///     landmarks: covariances.landmarks.iter().map(|c| c * noise * noise).collect(),
+    /// };
+    /// let close = Vector6::new(1e-5, 0.0, 0.0, 0.0, 0.0, 0.0);
+    /// let far = Vector6::new(1e-1, 0.0, 0.0, 0.0, 0.0, 0.0);
+    /// assert!(scaled.pose_mahalanobis(1, close).unwrap() < 3.0);
+    /// assert!(scaled.pose_mahalanobis(1, far).unwrap() > 3.0);
+    /// ```
+    pub fn covariances(
+        &self,
+        problem: &BundleAdjustment,
+        poses: &[usize],
+        landmarks: &[usize],
+    ) -> Option<Covariances> {
+        let normal = self.normal_equations(problem);
+        let (reduced, _, landmark_inverses, _) = normal.reduced_system(0.0);
+        let cholesky = reduced.to_dense().cholesky()?;
+
+        // The block columns of the inverse of the reduced camera system (over the free poses and the planes) by their
+        // offset, which are solved for as they are needed.
+        let size = normal.landmark_offset();
+        let mut columns: Vec<Option<DMatrix<f64>>> = vec![None; size];
+        let mut joint = |a: usize, a_width: usize, b: usize, b_width: usize| -> DMatrix<f64> {
+            columns[b]
+                .get_or_insert_with(|| {
+                    let mut identity = DMatrix::zeros(size, b_width);
+                    identity
+                        .slice_mut((b, 0), (b_width, b_width))
+                        .fill_with_identity();
+                    cholesky.solve(&identity)
+                })
+                .rows(a, a_width)
+                .into_owned()
+        };
+
+        let pose_covariances = poses
+            .iter()
+            .map(|&pose| match normal.free[pose] {
+                Some(block) => joint(6 * block, 6, 6 * block, 6)
+                    .fixed_slice::<6, 6>(0, 0)
+                    .into_owned(),
+                None => Matrix6::zeros(),
+            })
+            .collect();
+
+        let mut landmark_covariances = Vec::with_capacity(landmarks.len());
+        for &landmark in landmarks {
+            let inverse = landmark_inverses[landmark]?;
+            // The offset, width, and coupling times the inverse landmark block of each pose and plane that the
+            // landmark is coupled to.
+            let couplings: Vec<(usize, usize, DMatrix<f64>)> = normal.landmark_observations
+                [landmark]
+                .iter()
+                .filter_map(|&observation| {
+                    let block = normal.free[normal.observations[observation].0]?;
+                    let w: Matrix6x3<f64> = normal.pose_landmark[observation] * inverse;
+                    Some((6 * block, 6, DMatrix::from_column_slice(6, 3, w.as_slice())))
+                })
+                .chain(normal.landmark_planes[landmark].iter().map(|&ix| {
+                    let (plane, _, coupling) = normal.plane_landmark[ix];
+                    let w = coupling * inverse;
+                    (
+                        6 * normal.blocks + 3 * plane,
+                        3,
+                        DMatrix::from_column_slice(3, 3, w.as_slice()),
+                    )
+                }))
+                .collect();
+            let mut covariance = inverse;
+            for (a, a_width, w_a) in &couplings {
+                for (b, b_width, w_b) in &couplings {
+                    let block = w_a.transpose() * joint(*a, *a_width, *b, *b_width) * w_b;
+                    covariance += block.fixed_slice::<3, 3>(0, 0);
+                }
+            }
+            landmark_covariances.push(covariance);
+        }
+
+        Some(Covariances {
+            poses: pose_covariances,
+            landmarks: landmark_covariances,
+        })
+    }
+}
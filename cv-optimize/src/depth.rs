@@ -0,0 +1,76 @@
+use crate::RobustLoss;
+use cv_core::{
+    nalgebra::{Matrix1x3, Matrix1x6, Point3, Vector3},
+    Pose, WorldToCamera,
+};
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// A factor which holds a landmark of a [`BundleAdjustment`](crate::BundleAdjustment) at a measured depth from one
+/// of its poses, such as from the depth image of an RGB-D camera.
+///
+/// The depth is the distance of the landmark along the optical axis (the z axis of the camera), which is what
+/// structured light and time of flight sensors measure. Depth measurements give the reconstruction a metric scale.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct DepthObservation {
+    /// The index of the pose the depth was measured from.
+    pub pose: usize,
+    /// The index of the landmark.
+    pub landmark: usize,
+    /// The measured depth of the landmark.
+    pub depth: f64,
+    /// The inverse variance of the depth.
+    pub information: f64,
+    /// The robust loss applied to the normalized error, which lets depths at occlusion boundaries be rejected.
+    pub loss: RobustLoss,
+}
+
+impl DepthObservation {
+    pub fn new(pose: usize, landmark: usize, depth: f64, information: f64) -> Self {
+        Self {
+            pose,
+            landmark,
+            depth,
+            information,
+            loss: RobustLoss::Huber(3.0),
+        }
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    /// The difference of the depth of the landmark from the measured depth normalized by its standard deviation.
+    pub fn error(&self, pose: WorldToCamera, point: Point3<f64>) -> f64 {
+        let camera = pose.isometry() * point;
+        self.information.sqrt() * (camera.z - self.depth)
+    }
+
+    /// The error and its Jacobians in respect to the pose in se(3) and the landmark.
+    pub(crate) fn linearize(
+        &self,
+        pose: WorldToCamera,
+        point: Point3<f64>,
+    ) -> (f64, Matrix1x6<f64>, Matrix1x3<f64>) {
+        let root = self.information.sqrt();
+        let isometry = pose.isometry();
+        let rotated = isometry.rotation * point.coords;
+        let depth = rotated.z + isometry.translation.vector.z;
+        // A step `t ← t + δt`, `R ← exp(ω)R` moves the point in the camera by `δt - [Rp]×ω`.
+        let axis = Vector3::z().transpose();
+        let mut jacobian_pose = Matrix1x6::zeros();
+        jacobian_pose
+            .fixed_columns_mut::<3>(0)
+            .copy_from(&(root * axis));
+        jacobian_pose
+            .fixed_columns_mut::<3>(3)
+            .copy_from(&(-root * axis * rotated.cross_matrix()));
+        (
+            root * (depth - self.depth),
+            jacobian_pose,
+            root * axis * isometry.rotation.matrix(),
+        )
+    }
+}
@@ -0,0 +1,239 @@
+use crate::RobustLoss;
+use cv_core::{
+    nalgebra::{Matrix3, Matrix3x6, Vector3},
+    Pose, WorldToCamera,
+};
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// A reference ellipsoid which approximates the shape of the earth for geodetic coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct Ellipsoid {
+    /// The equatorial radius in meters.
+    pub semi_major_axis: f64,
+    /// The flattening `(a - b) / a` of the ellipsoid.
+    pub flattening: f64,
+}
+
+impl Ellipsoid {
+    /// The ellipsoid of the World Geodetic System 1984, which is used by GPS.
+    pub const WGS84: Self = Self {
+        semi_major_axis: 6_378_137.0,
+        flattening: 1.0 / 298.257_223_563,
+    };
+
+    /// The ellipsoid of the Geodetic Reference System 1980, which is used by ETRS89 and NAD83.
+    pub const GRS80: Self = Self {
+        semi_major_axis: 6_378_137.0,
+        flattening: 1.0 / 298.257_222_101,
+    };
+
+    /// The square of the eccentricity of the ellipsoid.
+    fn eccentricity_squared(&self) -> f64 {
+        self.flattening * (2.0 - self.flattening)
+    }
+}
+
+impl Default for Ellipsoid {
+    fn default() -> Self {
+        Self::WGS84
+    }
+}
+
+/// A position given by its latitude and longitude in degrees and its height above the ellipsoid in meters.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct Geodetic {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+}
+
+impl Geodetic {
+    pub fn new(latitude: f64, longitude: f64, altitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+            altitude,
+        }
+    }
+
+    /// Converts the position to earth-centered, earth-fixed (ECEF) coordinates in meters.
+    pub fn to_ecef(&self, ellipsoid: &Ellipsoid) -> Vector3<f64> {
+        let (sin_lat, cos_lat) = self.latitude.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.to_radians().sin_cos();
+        let e2 = ellipsoid.eccentricity_squared();
+        let n = ellipsoid.semi_major_axis / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+        Vector3::new(
+            (n + self.altitude) * cos_lat * cos_lon,
+            (n + self.altitude) * cos_lat * sin_lon,
+            (n * (1.0 - e2) + self.altitude) * sin_lat,
+        )
+    }
+
+    /// Converts earth-centered, earth-fixed (ECEF) coordinates in meters to a geodetic position.
+    pub fn from_ecef(ecef: Vector3<f64>, ellipsoid: &Ellipsoid) -> Self {
+        let e2 = ellipsoid.eccentricity_squared();
+        let p = ecef.xy().norm();
+        let longitude = ecef.y.atan2(ecef.x);
+        // Iterate on the latitude, which converges to below a millimeter in a few iterations.
+        let mut latitude = ecef.z.atan2(p * (1.0 - e2));
+        let mut altitude = 0.0;
+        for _ in 0..8 {
+            let sin_lat = latitude.sin();
+            let n = ellipsoid.semi_major_axis / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+            altitude = if latitude.cos().abs() > 1e-12 {
+                p / latitude.cos() - n
+            } else {
+                ecef.z.abs() - n * (1.0 - e2)
+            };
+            latitude = ecef.z.atan2(p * (1.0 - e2 * n / (n + altitude)));
+        }
+        Self {
+            latitude: latitude.to_degrees(),
+            longitude: longitude.to_degrees(),
+            altitude,
+        }
+    }
+}
+
+/// A local east-north-up (ENU) frame tangent to the ellipsoid at a datum, which is the world frame that GNSS
+/// positions are converted into so that they can constrain a reconstruction in meters.
+///
+/// ```
+/// use cv_optimize::{Ellipsoid, Geodetic, LocalTangentPlane};
+///
+/// let datum = LocalTangentPlane::new(Geodetic::new(48.8584, 2.2945, 35.0), Ellipsoid::WGS84);
+/// // One thousandth of a degree north is about 111 meters.
+/// let north = datum.to_enu(&Geodetic::new(48.8594, 2.2945, 35.0));
+/// assert!(north.x.abs() < 1e-6 && (north.y - 111.2).abs() < 0.1 && north.z.abs() < 1e-2);
+/// let back = datum.to_geodetic(north);
+/// assert!((back.latitude - 48.8594).abs() < 1e-9 && (back.altitude - 35.0).abs() < 1e-6);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct LocalTangentPlane {
+    /// The origin of the frame.
+    pub datum: Geodetic,
+    pub ellipsoid: Ellipsoid,
+    /// The origin in ECEF coordinates.
+    origin: Vector3<f64>,
+    /// The rotation from ECEF to ENU.
+    rotation: Matrix3<f64>,
+}
+
+impl LocalTangentPlane {
+    pub fn new(datum: Geodetic, ellipsoid: Ellipsoid) -> Self {
+        let (sin_lat, cos_lat) = datum.latitude.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = datum.longitude.to_radians().sin_cos();
+        let rotation = Matrix3::new(
+            -sin_lon,
+            cos_lon,
+            0.0,
+            -sin_lat * cos_lon,
+            -sin_lat * sin_lon,
+            cos_lat,
+            cos_lat * cos_lon,
+            cos_lat * sin_lon,
+            sin_lat,
+        );
+        Self {
+            datum,
+            ellipsoid,
+            origin: datum.to_ecef(&ellipsoid),
+            rotation,
+        }
+    }
+
+    /// Converts a geodetic position to east, north, and up in meters from the datum.
+    pub fn to_enu(&self, position: &Geodetic) -> Vector3<f64> {
+        self.rotation * (position.to_ecef(&self.ellipsoid) - self.origin)
+    }
+
+    /// Converts east, north, and up in meters from the datum to a geodetic position.
+    pub fn to_geodetic(&self, enu: Vector3<f64>) -> Geodetic {
+        Geodetic::from_ecef(
+            self.origin + self.rotation.transpose() * enu,
+            &self.ellipsoid,
+        )
+    }
+}
+
+/// A prior on the position of the camera of a pose from a GNSS receiver.
+///
+/// The world frame of the optimization must be the [`LocalTangentPlane`] the position was converted into. GNSS
+/// positions suffer from multipath outliers near buildings, so a robust loss should normally be used.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct GnssPrior {
+    /// The index of the pose.
+    pub pose: usize,
+    /// The measured position of the camera in the world.
+    pub position: Vector3<f64>,
+    /// The inverse covariance of the position, which the receiver reports as horizontal and vertical accuracies.
+    pub information: Matrix3<f64>,
+    /// The robust loss applied to the Mahalanobis norm of the error.
+    pub loss: RobustLoss,
+}
+
+impl GnssPrior {
+    pub fn new(pose: usize, position: Vector3<f64>, information: Matrix3<f64>) -> Self {
+        Self {
+            pose,
+            position,
+            information,
+            loss: RobustLoss::Huber(3.0),
+        }
+    }
+
+    /// Creates a prior from a geodetic position with the horizontal and vertical standard deviations in meters.
+    pub fn from_geodetic(
+        pose: usize,
+        frame: &LocalTangentPlane,
+        position: &Geodetic,
+        horizontal: f64,
+        vertical: f64,
+    ) -> Self {
+        let information = Matrix3::from_diagonal(&Vector3::new(
+            (horizontal * horizontal).recip(),
+            (horizontal * horizontal).recip(),
+            (vertical * vertical).recip(),
+        ));
+        Self::new(pose, frame.to_enu(position), information)
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    /// The difference between the position of the camera and the measured position.
+    pub fn error(&self, pose: WorldToCamera) -> Vector3<f64> {
+        pose.inverse().isometry().translation.vector - self.position
+    }
+
+    /// The error and its Jacobian in respect to the pose in se(3).
+    pub(crate) fn linearize(&self, pose: WorldToCamera) -> (Vector3<f64>, Matrix3x6<f64>) {
+        let isometry = pose.isometry();
+        let inverse_rotation = *isometry.rotation.inverse().matrix();
+        // The center is `-Rᵀt`, so a step `t ← t + δt`, `R ← exp(ω)R` moves it by `-Rᵀδt - Rᵀ[t]×ω`.
+        let mut jacobian = Matrix3x6::zeros();
+        jacobian
+            .fixed_columns_mut::<3>(0)
+            .copy_from(&-inverse_rotation);
+        jacobian
+            .fixed_columns_mut::<3>(3)
+            .copy_from(&(-inverse_rotation * isometry.translation.vector.cross_matrix()));
+        (self.error(pose), jacobian)
+    }
+
+    /// The Mahalanobis norm of the error.
+    pub(crate) fn mahalanobis(&self, error: Vector3<f64>) -> f64 {
+        error.dot(&(self.information * error)).max(0.0).sqrt()
+    }
+
+    pub(crate) fn cost(&self, pose: WorldToCamera) -> f64 {
+        self.loss.loss(self.mahalanobis(self.error(pose)))
+    }
+}
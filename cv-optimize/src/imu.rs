@@ -0,0 +1,366 @@
+use cv_core::nalgebra::{Matrix3, Matrix6, Rotation3, SMatrix, SVector, Vector3};
+
+/// The noise characteristics of an IMU, as given in the datasheet or estimated from an Allan variance plot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImuNoise {
+    /// The noise density of the gyroscope in `rad/s/√Hz`.
+    pub gyroscope: f64,
+    /// The noise density of the accelerometer in `m/s²/√Hz`.
+    pub accelerometer: f64,
+    /// The random walk of the gyroscope bias in `rad/s²/√Hz`.
+    pub gyroscope_walk: f64,
+    /// The random walk of the accelerometer bias in `m/s³/√Hz`.
+    pub accelerometer_walk: f64,
+}
+
+impl Default for ImuNoise {
+    /// The noise of a typical consumer grade MEMS IMU.
+    fn default() -> Self {
+        Self {
+            gyroscope: 1.7e-4,
+            accelerometer: 2.0e-3,
+            gyroscope_walk: 1.9e-5,
+            accelerometer_walk: 3.0e-3,
+        }
+    }
+}
+
+impl ImuNoise {
+    /// The inverse covariance of the change in the biases `(gyroscope, accelerometer)` over `delta_time` seconds,
+    /// which is the information of the residual `b_j - b_i` between the biases of two states.
+    pub fn bias_information(&self, delta_time: f64) -> Matrix6<f64> {
+        let gyroscope = self.gyroscope_walk * self.gyroscope_walk * delta_time;
+        let accelerometer = self.accelerometer_walk * self.accelerometer_walk * delta_time;
+        Matrix6::from_diagonal(&SVector::<f64, 6>::from_column_slice(&[
+            gyroscope.recip(),
+            gyroscope.recip(),
+            gyroscope.recip(),
+            accelerometer.recip(),
+            accelerometer.recip(),
+            accelerometer.recip(),
+        ]))
+    }
+}
+
+/// The biases of the gyroscope and accelerometer, which are subtracted from their measurements.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct ImuBias {
+    pub gyroscope: Vector3<f64>,
+    pub accelerometer: Vector3<f64>,
+}
+
+/// The navigation state of the IMU body at one instant, in the world frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImuState {
+    /// The rotation from the body to the world.
+    pub rotation: Rotation3<f64>,
+    /// The velocity of the body in the world.
+    pub velocity: Vector3<f64>,
+    /// The position of the body in the world.
+    pub position: Vector3<f64>,
+}
+
+impl ImuState {
+    /// Applies a step `(δφ, δv, δp)` as `R ← R exp(δφ)`, `v ← v + δv`, and `p ← p + R δp`, which is the convention
+    /// of the Jacobians of [`PreintegratedImu::linearize`].
+    pub fn retract(&self, step: SVector<f64, 9>) -> Self {
+        Self {
+            rotation: self.rotation * exp(step.fixed_rows::<3>(0).into_owned()),
+            velocity: self.velocity + step.fixed_rows::<3>(3),
+            position: self.position + self.rotation * step.fixed_rows::<3>(6).into_owned(),
+        }
+    }
+}
+
+/// The Jacobians of the residual of a [`PreintegratedImu`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImuJacobians {
+    /// The Jacobian in respect to a step of the first state (see [`ImuState::retract`]).
+    pub first: SMatrix<f64, 9, 9>,
+    /// The Jacobian in respect to a step of the second state.
+    pub second: SMatrix<f64, 9, 9>,
+    /// The Jacobian in respect to a change of the `(gyroscope, accelerometer)` biases.
+    pub bias: SMatrix<f64, 9, 6>,
+}
+
+/// IMU measurements between two keyframes integrated into a single relative motion constraint.
+///
+/// This follows "On-Manifold Preintegration for Real-Time Visual-Inertial Odometry" by Forster et al. The
+/// measurements are integrated relative to the first state, so they don't have to be integrated again when the
+/// states change during optimization, and the Jacobians in respect to the biases correct the preintegrated motion to
+/// first order when the biases change. Quantities are ordered as `(rotation, velocity, position)`.
+///
+/// An [`ImuWindow`](crate::ImuWindow) estimates the velocity and biases of each keyframe along with its pose, with an
+/// [`ImuFactor`](crate::ImuFactor) of the preintegrated measurements between consecutive keyframes.
+///
+/// ```
+/// use cv_core::nalgebra::{Rotation3, Vector3};
+/// use cv_optimize::{ImuBias, ImuNoise, ImuState, PreintegratedImu};
+///
+/// let gravity = Vector3::new(0.0, 0.0, -9.81);
+/// let rate = Vector3::new(0.1, -0.2, 0.3);
+/// let first = ImuState {
+///     rotation: Rotation3::from_euler_angles(0.1, 0.2, 0.3),
+///     velocity: Vector3::zeros(),
+///     position: Vector3::new(1.0, 2.0, 3.0),
+/// };
+///
+/// // A stationary IMU which is rotating in place only measures the reaction to gravity.
+/// let mut preintegrated = PreintegratedImu::new(ImuBias::default(), ImuNoise::default());
+/// let dt = 0.005;
+/// for k in 0..200 {
+///     let rotation = first.rotation * Rotation3::new(rate * (k as f64 * dt));
+///     preintegrated.integrate(rate, rotation.inverse() * -gravity, dt);
+/// }
+/// let second = ImuState { rotation: first.rotation * Rotation3::new(rate), ..first };
+/// assert!((preintegrated.delta_time - 1.0).abs() < 1e-12);
+/// assert!(preintegrated.residual(&first, &second, &ImuBias::default(), gravity).norm() < 1e-9);
+/// assert!(preintegrated.information().is_some());
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PreintegratedImu {
+    /// The biases which the measurements were integrated with.
+    pub bias: ImuBias,
+    pub noise: ImuNoise,
+    /// The total integration time.
+    pub delta_time: f64,
+    /// The rotation of the body at the end relative to the start.
+    pub delta_rotation: Rotation3<f64>,
+    /// The change in velocity without gravity, in the body frame at the start.
+    pub delta_velocity: Vector3<f64>,
+    /// The change in position without gravity, in the body frame at the start.
+    pub delta_position: Vector3<f64>,
+    /// The covariance of the preintegrated `(rotation, velocity, position)`.
+    pub covariance: SMatrix<f64, 9, 9>,
+    /// The Jacobians of the preintegrated `(rotation, velocity, position)` in respect to the gyroscope bias.
+    pub gyroscope_jacobians: [Matrix3<f64>; 3],
+    /// The Jacobians of the preintegrated velocity and position in respect to the accelerometer bias.
+    pub accelerometer_jacobians: [Matrix3<f64>; 2],
+}
+
+impl PreintegratedImu {
+    /// Starts preintegrating measurements with the current estimate of the biases.
+    pub fn new(bias: ImuBias, noise: ImuNoise) -> Self {
+        Self {
+            bias,
+            noise,
+            delta_time: 0.0,
+            delta_rotation: Rotation3::identity(),
+            delta_velocity: Vector3::zeros(),
+            delta_position: Vector3::zeros(),
+            covariance: SMatrix::zeros(),
+            gyroscope_jacobians: [Matrix3::zeros(); 3],
+            accelerometer_jacobians: [Matrix3::zeros(); 2],
+        }
+    }
+
+    /// Integrates a gyroscope measurement in `rad/s` and an accelerometer measurement in `m/s²`, which were
+    /// constant for `dt` seconds.
+    pub fn integrate(&mut self, gyroscope: Vector3<f64>, accelerometer: Vector3<f64>, dt: f64) {
+        let rate = gyroscope - self.bias.gyroscope;
+        let acceleration = accelerometer - self.bias.accelerometer;
+        let increment = exp(rate * dt);
+        let right_jacobian = so3_right_jacobian(rate * dt);
+        let rotation = *self.delta_rotation.matrix();
+        let acceleration_hat = acceleration.cross_matrix();
+
+        // Propagate the covariance with the previous rotation.
+        let mut a = SMatrix::<f64, 9, 9>::identity();
+        a.fixed_slice_mut::<3, 3>(0, 0)
+            .copy_from(&increment.matrix().transpose());
+        a.fixed_slice_mut::<3, 3>(3, 0)
+            .copy_from(&(-rotation * acceleration_hat * dt));
+        a.fixed_slice_mut::<3, 3>(6, 0)
+            .copy_from(&(-0.5 * rotation * acceleration_hat * dt * dt));
+        a.fixed_slice_mut::<3, 3>(6, 3)
+            .copy_from(&(Matrix3::identity() * dt));
+        let mut b = SMatrix::<f64, 9, 3>::zeros();
+        b.fixed_slice_mut::<3, 3>(0, 0)
+            .copy_from(&(right_jacobian * dt));
+        let mut c = SMatrix::<f64, 9, 3>::zeros();
+        c.fixed_slice_mut::<3, 3>(3, 0).copy_from(&(rotation * dt));
+        c.fixed_slice_mut::<3, 3>(6, 0)
+            .copy_from(&(0.5 * rotation * dt * dt));
+        // The noise densities are converted to the variance of the discrete measurements.
+        let gyroscope_variance = self.noise.gyroscope * self.noise.gyroscope / dt;
+        let accelerometer_variance = self.noise.accelerometer * self.noise.accelerometer / dt;
+        self.covariance = a * self.covariance * a.transpose()
+            + gyroscope_variance * b * b.transpose()
+            + accelerometer_variance * c * c.transpose();
+
+        // Update the bias Jacobians with the previous rotation and velocity.
+        let [rotation_gyroscope, velocity_gyroscope, position_gyroscope] =
+            &mut self.gyroscope_jacobians;
+        let [velocity_accelerometer, position_accelerometer] = &mut self.accelerometer_jacobians;
+        *position_accelerometer += *velocity_accelerometer * dt - 0.5 * rotation * dt * dt;
+        *position_gyroscope += *velocity_gyroscope * dt
+            - 0.5 * rotation * acceleration_hat * *rotation_gyroscope * dt * dt;
+        *velocity_accelerometer -= rotation * dt;
+        *velocity_gyroscope -= rotation * acceleration_hat * *rotation_gyroscope * dt;
+        *rotation_gyroscope =
+            increment.matrix().transpose() * *rotation_gyroscope - right_jacobian * dt;
+
+        // Integrate the motion.
+        self.delta_position += self.delta_velocity * dt + 0.5 * (rotation * acceleration) * dt * dt;
+        self.delta_velocity += rotation * acceleration * dt;
+        self.delta_rotation *= increment;
+        self.delta_time += dt;
+    }
+
+    /// The inverse covariance of the residual.
+    pub fn information(&self) -> Option<SMatrix<f64, 9, 9>> {
+        self.covariance.try_inverse()
+    }
+
+    /// The preintegrated `(rotation, velocity, position)` corrected to first order for a different `bias`.
+    pub fn corrected(&self, bias: &ImuBias) -> (Rotation3<f64>, Vector3<f64>, Vector3<f64>) {
+        let gyroscope = bias.gyroscope - self.bias.gyroscope;
+        let accelerometer = bias.accelerometer - self.bias.accelerometer;
+        let [rotation_gyroscope, velocity_gyroscope, position_gyroscope] = self.gyroscope_jacobians;
+        let [velocity_accelerometer, position_accelerometer] = self.accelerometer_jacobians;
+        (
+            self.delta_rotation * exp(rotation_gyroscope * gyroscope),
+            self.delta_velocity
+                + velocity_gyroscope * gyroscope
+                + velocity_accelerometer * accelerometer,
+            self.delta_position
+                + position_gyroscope * gyroscope
+                + position_accelerometer * accelerometer,
+        )
+    }
+
+    /// The residual between the preintegrated motion and the motion from the `first` state to the `second` state
+    /// with the `bias` during the interval and the `gravity` vector of the world (such as `[0, 0, -9.81]`).
+    pub fn residual(
+        &self,
+        first: &ImuState,
+        second: &ImuState,
+        bias: &ImuBias,
+        gravity: Vector3<f64>,
+    ) -> SVector<f64, 9> {
+        let (delta_rotation, delta_velocity, delta_position) = self.corrected(bias);
+        let dt = self.delta_time;
+        let inverse = first.rotation.inverse();
+        let rotation = log(delta_rotation.inverse() * inverse * second.rotation);
+        let velocity = inverse * (second.velocity - first.velocity - gravity * dt) - delta_velocity;
+        let position = inverse
+            * (second.position - first.position - first.velocity * dt - 0.5 * gravity * dt * dt)
+            - delta_position;
+        let mut residual = SVector::zeros();
+        residual.fixed_rows_mut::<3>(0).copy_from(&rotation);
+        residual.fixed_rows_mut::<3>(3).copy_from(&velocity);
+        residual.fixed_rows_mut::<3>(6).copy_from(&position);
+        residual
+    }
+
+    /// The residual along with its Jacobians in respect to both states and the biases.
+    pub fn linearize(
+        &self,
+        first: &ImuState,
+        second: &ImuState,
+        bias: &ImuBias,
+        gravity: Vector3<f64>,
+    ) -> (SVector<f64, 9>, ImuJacobians) {
+        let residual = self.residual(first, second, bias, gravity);
+        let dt = self.delta_time;
+        let inverse = *first.rotation.inverse().matrix();
+        let rotation_error = residual.fixed_rows::<3>(0).into_owned();
+        let right_inverse = so3_right_jacobian_inverse(rotation_error);
+        let relative = first.rotation.inverse() * second.rotation;
+        let [rotation_gyroscope, velocity_gyroscope, position_gyroscope] = self.gyroscope_jacobians;
+        let [velocity_accelerometer, position_accelerometer] = self.accelerometer_jacobians;
+        let gyroscope_change = rotation_gyroscope * (bias.gyroscope - self.bias.gyroscope);
+
+        let mut first_jacobian = SMatrix::<f64, 9, 9>::zeros();
+        first_jacobian
+            .fixed_slice_mut::<3, 3>(0, 0)
+            .copy_from(&(-right_inverse * relative.inverse().matrix()));
+        first_jacobian.fixed_slice_mut::<3, 3>(3, 0).copy_from(
+            &(inverse * (second.velocity - first.velocity - gravity * dt)).cross_matrix(),
+        );
+        first_jacobian
+            .fixed_slice_mut::<3, 3>(3, 3)
+            .copy_from(&-inverse);
+        first_jacobian.fixed_slice_mut::<3, 3>(6, 0).copy_from(
+            &(inverse
+                * (second.position
+                    - first.position
+                    - first.velocity * dt
+                    - 0.5 * gravity * dt * dt))
+                .cross_matrix(),
+        );
+        first_jacobian
+            .fixed_slice_mut::<3, 3>(6, 3)
+            .copy_from(&(-inverse * dt));
+        first_jacobian
+            .fixed_slice_mut::<3, 3>(6, 6)
+            .copy_from(&-Matrix3::identity());
+
+        let mut second_jacobian = SMatrix::<f64, 9, 9>::zeros();
+        second_jacobian
+            .fixed_slice_mut::<3, 3>(0, 0)
+            .copy_from(&right_inverse);
+        second_jacobian
+            .fixed_slice_mut::<3, 3>(3, 3)
+            .copy_from(&inverse);
+        second_jacobian
+            .fixed_slice_mut::<3, 3>(6, 6)
+            .copy_from(relative.matrix());
+
+        let mut bias_jacobian = SMatrix::<f64, 9, 6>::zeros();
+        bias_jacobian.fixed_slice_mut::<3, 3>(0, 0).copy_from(
+            &(-right_inverse
+                * exp(rotation_error).inverse().matrix()
+                * so3_right_jacobian(gyroscope_change)
+                * rotation_gyroscope),
+        );
+        bias_jacobian
+            .fixed_slice_mut::<3, 3>(3, 0)
+            .copy_from(&-velocity_gyroscope);
+        bias_jacobian
+            .fixed_slice_mut::<3, 3>(3, 3)
+            .copy_from(&-velocity_accelerometer);
+        bias_jacobian
+            .fixed_slice_mut::<3, 3>(6, 0)
+            .copy_from(&-position_gyroscope);
+        bias_jacobian
+            .fixed_slice_mut::<3, 3>(6, 3)
+            .copy_from(&-position_accelerometer);
+
+        (
+            residual,
+            ImuJacobians {
+                first: first_jacobian,
+                second: second_jacobian,
+                bias: bias_jacobian,
+            },
+        )
+    }
+}
+
+/// The exponential map of SO(3).
+fn exp(phi: Vector3<f64>) -> Rotation3<f64> {
+    Rotation3::new(phi)
+}
+
+/// The logarithm map of SO(3).
+fn log(rotation: Rotation3<f64>) -> Vector3<f64> {
+    rotation.scaled_axis()
+}
+
+/// The right Jacobian of SO(3), which maps a change in `φ` to a rotation applied on the right of `exp(φ)`.
+fn so3_right_jacobian(phi: Vector3<f64>) -> Matrix3<f64> {
+    let theta = phi.norm();
+    let hat = phi.cross_matrix();
+    if theta < 1e-6 {
+        return Matrix3::identity() - 0.5 * hat + hat * hat / 6.0;
+    }
+    Matrix3::identity() - (1.0 - theta.cos()) / (theta * theta) * hat
+        + (theta - theta.sin()) / (theta * theta * theta) * hat * hat
+}
+
+/// The inverse of the right Jacobian of SO(3).
+pub(crate) fn so3_right_jacobian_inverse(phi: Vector3<f64>) -> Matrix3<f64> {
+    // The right Jacobian is the left Jacobian of the inverse rotation.
+    crate::pose_graph::so3_left_jacobian_inverse(-phi)
+}
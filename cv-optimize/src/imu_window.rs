@@ -0,0 +1,403 @@
+use crate::{
+    imu::so3_right_jacobian_inverse, DenseLinearization, ImuBias, ImuState, LeastSquaresProblem,
+    PreintegratedImu,
+};
+use cv_core::nalgebra::{DMatrix, DVector, Matrix6, Rotation3, SMatrix, SVector, Vector3, Vector6};
+
+/// The preintegrated IMU measurements between two states of an [`ImuWindow`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImuFactor {
+    /// The index of the state the measurements start from.
+    pub from: usize,
+    /// The index of the state the measurements end at.
+    pub to: usize,
+    pub preintegrated: PreintegratedImu,
+    /// The inverse covariance of the residual of the preintegrated measurements.
+    pub information: SMatrix<f64, 9, 9>,
+}
+
+impl ImuFactor {
+    /// Returns `None` if the covariance of the preintegrated measurements is singular, such as when no measurements
+    /// were integrated or the noise is zero.
+    pub fn new(from: usize, to: usize, preintegrated: PreintegratedImu) -> Option<Self> {
+        Some(Self {
+            from,
+            to,
+            preintegrated,
+            information: preintegrated.information()?,
+        })
+    }
+}
+
+/// A prior on the pose of the body of a state of an [`ImuWindow`], such as from visual odometry.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImuPosePrior {
+    /// The index of the state.
+    pub state: usize,
+    /// The expected rotation from the body to the world.
+    pub rotation: Rotation3<f64>,
+    /// The expected position of the body in the world.
+    pub position: Vector3<f64>,
+    /// The inverse covariance of the error `(log(R̂⁻¹R), R̂⁻¹(p - p̂))` of the prior.
+    pub information: Matrix6<f64>,
+}
+
+impl ImuPosePrior {
+    pub fn new(
+        state: usize,
+        rotation: Rotation3<f64>,
+        position: Vector3<f64>,
+        information: Matrix6<f64>,
+    ) -> Self {
+        Self {
+            state,
+            rotation,
+            position,
+            information,
+        }
+    }
+
+    /// The error of the prior, which is zero when the state has the expected pose.
+    pub fn error(&self, state: &ImuState) -> Vector6<f64> {
+        let inverse = self.rotation.inverse();
+        let mut error = Vector6::zeros();
+        error
+            .fixed_rows_mut::<3>(0)
+            .copy_from(&(inverse * state.rotation).scaled_axis());
+        error
+            .fixed_rows_mut::<3>(3)
+            .copy_from(&(inverse * (state.position - self.position)));
+        error
+    }
+
+    /// The error and its Jacobian in respect to a step of the state (see [`ImuState::retract`]).
+    fn linearize(&self, state: &ImuState) -> (Vector6<f64>, SMatrix<f64, 6, 9>) {
+        let error = self.error(state);
+        let mut jacobian = SMatrix::<f64, 6, 9>::zeros();
+        jacobian
+            .fixed_slice_mut::<3, 3>(0, 0)
+            .copy_from(&so3_right_jacobian_inverse(
+                error.fixed_rows::<3>(0).into_owned(),
+            ));
+        jacobian
+            .fixed_slice_mut::<3, 3>(3, 6)
+            .copy_from((self.rotation.inverse() * state.rotation).matrix());
+        (error, jacobian)
+    }
+}
+
+/// The keyframes of a visual-inertial odometry window, whose poses, velocities and IMU biases are estimated from
+/// [`PreintegratedImu`] measurements between them and priors on their poses.
+///
+/// Each [`ImuFactor`] constrains the two states it connects with the biases of the state it starts from, and the
+/// biases of the two states are tied together by the random walk of the [`ImuNoise`](crate::ImuNoise) over the
+/// time between them. The gravity vector of the world is known, so the roll and pitch of the states are observable
+/// from the IMU alone, but their yaw and position need a prior or a fixed state.
+///
+/// The states should be in the order the keyframes were created. [`ImuWindow::slide`] drops the oldest states and
+/// holds the oldest state which remains fixed, so that the window stays anchored to the trajectory before it, as
+/// the [`SlidingWindow`](crate::SlidingWindow) of a [`BundleAdjustment`](crate::BundleAdjustment) does.
+///
+/// ```
+/// use cv_core::nalgebra::{Matrix6, Rotation3, Vector3};
+/// use cv_optimize::{
+///     ImuBias, ImuFactor, ImuNoise, ImuPosePrior, ImuState, ImuWindow, LeastSquaresProblem, LevenbergMarquardt,
+///     PreintegratedImu,
+/// };
+///
+/// // A body accelerating and rotating at constant rates, measured by a biased IMU.
+/// let gravity = Vector3::new(0.0, 0.0, -9.81);
+/// let rate = Vector3::new(0.3, -0.2, 0.1);
+/// let acceleration = Vector3::new(0.5, 0.2, -0.1);
+/// let truth = |t: f64| ImuState {
+///     rotation: Rotation3::from_euler_angles(0.1, 0.2, 0.3) * Rotation3::new(rate * t),
+///     velocity: Vector3::new(1.0, 0.0, 0.0) + acceleration * t,
+///     position: Vector3::new(1.0, 0.0, 0.0) * t + 0.5 * acceleration * t * t,
+/// };
+/// let bias = ImuBias {
+///     gyroscope: Vector3::new(0.01, -0.02, 0.005),
+///     accelerometer: Vector3::new(0.05, 0.0, -0.03),
+/// };
+///
+/// let mut window = ImuWindow::new(gravity);
+/// let dt = 0.005;
+/// for keyframe in 0..4 {
+///     let t = keyframe as f64 * 0.2;
+///     let state = window.push(truth(t), bias);
+///     window.add_prior(ImuPosePrior::new(state, truth(t).rotation, truth(t).position, Matrix6::identity() * 1e4));
+///     if keyframe > 0 {
+///         let mut preintegrated = PreintegratedImu::new(bias, ImuNoise::default());
+///         for k in 0..40 {
+///             let measured = truth(t - 0.2 + k as f64 * dt);
+///             let specific_force = measured.rotation.inverse() * (acceleration - gravity);
+///             preintegrated.integrate(rate + bias.gyroscope, specific_force + bias.accelerometer, dt);
+///         }
+///         window.connect(ImuFactor::new(state - 1, state, preintegrated).unwrap());
+///     }
+/// }
+/// // The residuals are zero at the true states and biases.
+/// assert!(window.cost() < 1e-9, "cost {}", window.cost());
+///
+/// // The velocities and biases are recovered from the poses and the IMU measurements.
+/// for (state, bias) in window.states.iter_mut().zip(&mut window.biases) {
+///     state.velocity = Vector3::zeros();
+///     *bias = ImuBias::default();
+/// }
+/// LevenbergMarquardt::new().minimize(&mut window);
+/// for (keyframe, state) in window.states.iter().enumerate() {
+///     assert!((state.velocity - truth(keyframe as f64 * 0.2).velocity).norm() < 1e-3);
+/// }
+/// assert!((window.biases[0].gyroscope - bias.gyroscope).norm() < 1e-3);
+/// assert!((window.biases[0].accelerometer - bias.accelerometer).norm() < 1e-3);
+///
+/// // Sliding the window holds the oldest remaining state fixed.
+/// window.slide(2);
+/// assert_eq!(window.states.len(), 2);
+/// assert_eq!(window.factors.len(), 1);
+/// assert_eq!(window.fixed_states, [true, false]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImuWindow {
+    pub states: Vec<ImuState>,
+    /// The biases of the IMU at each state.
+    pub biases: Vec<ImuBias>,
+    pub factors: Vec<ImuFactor>,
+    pub priors: Vec<ImuPosePrior>,
+    /// The gravity vector of the world, such as `[0, 0, -9.81]`.
+    pub gravity: Vector3<f64>,
+    /// States with `true` at their index are held fixed, while their biases are still estimated.
+    pub fixed_states: Vec<bool>,
+}
+
+impl ImuWindow {
+    pub fn new(gravity: Vector3<f64>) -> Self {
+        Self {
+            states: vec![],
+            biases: vec![],
+            factors: vec![],
+            priors: vec![],
+            gravity,
+            fixed_states: vec![],
+        }
+    }
+
+    /// Adds a state with the estimate of its biases, returning its index.
+    pub fn push(&mut self, state: ImuState, bias: ImuBias) -> usize {
+        self.states.push(state);
+        self.biases.push(bias);
+        self.fixed_states.push(false);
+        self.states.len() - 1
+    }
+
+    /// Adds the IMU measurements between two states.
+    pub fn connect(&mut self, factor: ImuFactor) {
+        self.factors.push(factor);
+    }
+
+    pub fn add_prior(&mut self, prior: ImuPosePrior) {
+        self.priors.push(prior);
+    }
+
+    /// Holds a state fixed during the optimization.
+    pub fn fix_state(&mut self, state: usize) {
+        self.fixed_states.resize(self.states.len(), false);
+        self.fixed_states[state] = true;
+    }
+
+    /// Drops all but the `window` most recent states along with the factors and priors which refer to them, and
+    /// holds the oldest remaining state fixed.
+    pub fn slide(&mut self, window: usize) {
+        let start = self.states.len().saturating_sub(window);
+        if start == 0 {
+            return;
+        }
+        self.fixed_states.resize(self.states.len(), false);
+        self.states.drain(..start);
+        self.biases.drain(..start);
+        self.fixed_states.drain(..start);
+        self.factors = self
+            .factors
+            .iter()
+            .filter(|factor| factor.from >= start && factor.to >= start)
+            .map(|factor| ImuFactor {
+                from: factor.from - start,
+                to: factor.to - start,
+                ..*factor
+            })
+            .collect();
+        self.priors = self
+            .priors
+            .iter()
+            .filter(|prior| prior.state >= start)
+            .map(|prior| ImuPosePrior {
+                state: prior.state - start,
+                ..*prior
+            })
+            .collect();
+        if let Some(first) = self.fixed_states.first_mut() {
+            *first = true;
+        }
+    }
+
+    fn is_fixed(&self, state: usize) -> bool {
+        self.fixed_states.get(state).copied().unwrap_or(false)
+    }
+
+    /// Assigns each free state a block of nine parameters, returning the blocks and the number of free states.
+    fn free_blocks(&self) -> (Vec<Option<usize>>, usize) {
+        let mut free = vec![None; self.states.len()];
+        let mut blocks = 0;
+        for (ix, block) in free.iter_mut().enumerate() {
+            if !self.is_fixed(ix) {
+                *block = Some(blocks);
+                blocks += 1;
+            }
+        }
+        (free, blocks)
+    }
+
+    /// The change in the biases between the states of a factor.
+    fn bias_error(&self, factor: &ImuFactor) -> Vector6<f64> {
+        let (from, to) = (&self.biases[factor.from], &self.biases[factor.to]);
+        let mut error = Vector6::zeros();
+        error
+            .fixed_rows_mut::<3>(0)
+            .copy_from(&(to.gyroscope - from.gyroscope));
+        error
+            .fixed_rows_mut::<3>(3)
+            .copy_from(&(to.accelerometer - from.accelerometer));
+        error
+    }
+}
+
+/// Adds a residual with the Jacobians of the parameter blocks it depends on to the normal equations, skipping fixed
+/// blocks.
+fn accumulate(
+    normal: &mut DenseLinearization,
+    residual: &DVector<f64>,
+    information: &DMatrix<f64>,
+    jacobians: &[(Option<usize>, DMatrix<f64>)],
+) {
+    for (a, jacobian_a) in jacobians {
+        let a = match *a {
+            Some(offset) => offset,
+            None => continue,
+        };
+        let weighted = jacobian_a.transpose() * information;
+        let mut gradient_block = normal.gradient.rows_mut(a, jacobian_a.ncols());
+        gradient_block += &weighted * residual;
+        for (b, jacobian_b) in jacobians {
+            if let Some(b) = *b {
+                let mut hessian_block = normal
+                    .hessian
+                    .slice_mut((a, b), (jacobian_a.ncols(), jacobian_b.ncols()));
+                hessian_block += &weighted * jacobian_b;
+            }
+        }
+    }
+}
+
+fn dynamic<const R: usize, const C: usize>(matrix: &SMatrix<f64, R, C>) -> DMatrix<f64> {
+    DMatrix::from_column_slice(R, C, matrix.as_slice())
+}
+
+/// The parameters are the steps of the free states in order (see [`ImuState::retract`]), followed by the
+/// `(gyroscope, accelerometer)` bias steps of every state in order.
+impl LeastSquaresProblem for ImuWindow {
+    type Linearization = DenseLinearization;
+
+    fn cost(&self) -> f64 {
+        let imu: f64 = self
+            .factors
+            .iter()
+            .map(|factor| {
+                let residual = factor.preintegrated.residual(
+                    &self.states[factor.from],
+                    &self.states[factor.to],
+                    &self.biases[factor.from],
+                    self.gravity,
+                );
+                let bias = self.bias_error(factor);
+                let bias_information = factor
+                    .preintegrated
+                    .noise
+                    .bias_information(factor.preintegrated.delta_time);
+                residual.dot(&(factor.information * residual))
+                    + bias.dot(&(bias_information * bias))
+            })
+            .sum();
+        let priors: f64 = self
+            .priors
+            .iter()
+            .map(|prior| {
+                let error = prior.error(&self.states[prior.state]);
+                error.dot(&(prior.information * error))
+            })
+            .sum();
+        imu + priors
+    }
+
+    fn linearize(&self) -> DenseLinearization {
+        let (free, blocks) = self.free_blocks();
+        let state_offset = |state: usize| free[state].map(|block| 9 * block);
+        let bias_offset = |state: usize| Some(9 * blocks + 6 * state);
+        let mut normal = DenseLinearization::zeros(9 * blocks + 6 * self.states.len());
+        for factor in &self.factors {
+            let (residual, jacobians) = factor.preintegrated.linearize(
+                &self.states[factor.from],
+                &self.states[factor.to],
+                &self.biases[factor.from],
+                self.gravity,
+            );
+            accumulate(
+                &mut normal,
+                &dynamic(&residual),
+                &dynamic(&factor.information),
+                &[
+                    (state_offset(factor.from), dynamic(&jacobians.first)),
+                    (state_offset(factor.to), dynamic(&jacobians.second)),
+                    (bias_offset(factor.from), dynamic(&jacobians.bias)),
+                ],
+            );
+            let bias_information = factor
+                .preintegrated
+                .noise
+                .bias_information(factor.preintegrated.delta_time);
+            accumulate(
+                &mut normal,
+                &dynamic(&self.bias_error(factor)),
+                &dynamic(&bias_information),
+                &[
+                    (bias_offset(factor.from), -DMatrix::identity(6, 6)),
+                    (bias_offset(factor.to), DMatrix::identity(6, 6)),
+                ],
+            );
+        }
+        for prior in &self.priors {
+            let (error, jacobian) = prior.linearize(&self.states[prior.state]);
+            accumulate(
+                &mut normal,
+                &dynamic(&error),
+                &dynamic(&prior.information),
+                &[(state_offset(prior.state), dynamic(&jacobian))],
+            );
+        }
+        normal
+    }
+
+    fn retract(&self, step: &DVector<f64>) -> Self {
+        let (free, blocks) = self.free_blocks();
+        let mut window = self.clone();
+        for (state, block) in window.states.iter_mut().zip(&free) {
+            if let Some(block) = *block {
+                *state = state.retract(step.fixed_rows::<9>(9 * block).into_owned());
+            }
+        }
+        for (ix, bias) in window.biases.iter_mut().enumerate() {
+            let bias_step: SVector<f64, 6> = step.fixed_rows::<6>(9 * blocks + 6 * ix).into_owned();
+            bias.gyroscope += bias_step.fixed_rows::<3>(0);
+            bias.accelerometer += bias_step.fixed_rows::<3>(3);
+        }
+        window
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{
+    BundleAdjuster, BundleAdjustment, GnssPrior, Observation, OptimizationReport, PosePrior,
+    SlidingWindow,
+};
+use cv_core::{Pose, WorldPoint, WorldToCamera};
+
+/// Incrementally smooths a [`BundleAdjustment`] as keyframes, landmarks, and loop closures arrive, in the manner of
+/// iSAM2.
+///
+/// iSAM2 keeps the factorization of the problem in a Bayes tree, and new factors only change the cliques on the
+/// paths from their variables to the root. With the poses eliminated in the order they were added, the path from a
+/// pose to the root passes through every later pose, so an update only needs to solve the poses from the oldest pose
+/// touched by a new factor up to the newest pose, along with the landmarks they observe. Older poses keep their
+/// current solution and anchor the update. A new observation touches the oldest pose that observes its landmark,
+/// since the landmark is eliminated before that pose, so closing a loop by observing an old landmark again updates
+/// the whole loop, while tracking recent landmarks only updates the last few poses.
+///
+/// As with the fluid relinearization of iSAM2, poses which still moved by more than the relinearization threshold
+/// in an update are solved again in the next update, even if no new factor touches them.
+///
+/// ```
+/// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3}, *};
+/// use cv_optimize::{IncrementalSmoother, Observation};
+///
+/// let poses: Vec<WorldToCamera> = (0..8)
+///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+///         Vector3::new(-0.2 * i as f64, 0.0, 0.0).into(),
+///         Rotation3::identity(),
+///     )))
+///     .collect();
+/// let landmarks: Vec<WorldPoint> = (0..40)
+///     .map(|i| WorldPoint::from_point(Point3::new((i % 8) as f64 * 0.3 - 0.5, (i / 8) as f64 * 0.3 - 0.6, 4.0 + (i % 3) as f64)))
+///     .collect();
+///
+/// let mut smoother = IncrementalSmoother::new();
+/// for &landmark in &landmarks {
+///     smoother.add_landmark(landmark);
+/// }
+/// for (p, pose) in poses.iter().enumerate() {
+///     // Each new keyframe starts at the previous estimate, as if from a motion model.
+///     let guess = if p == 0 { *pose } else { smoother.problem().poses[p - 1] };
+///     let index = smoother.add_pose(guess);
+///     if p < 2 {
+///         smoother.fix_pose(index);
+///     }
+///     for (l, &landmark) in landmarks.iter().enumerate() {
+///         smoother.observe(Observation::new(index, l, pose.transform(landmark).bearing()));
+///     }
+///     smoother.update();
+/// }
+/// for (estimate, pose) in smoother.problem().poses.iter().zip(&poses) {
+///     assert!((estimate.0.translation.vector - pose.0.translation.vector).norm() < 1e-6);
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct IncrementalSmoother {
+    /// The adjuster used to solve each update.
+    pub adjuster: BundleAdjuster,
+    /// Poses which move by more than this (the larger of the translation and the rotation angle) in an update are
+    /// also solved in the next update.
+    pub relinearize_threshold: f64,
+    problem: BundleAdjustment,
+    /// The oldest pose observing each landmark.
+    first_observers: Vec<Option<usize>>,
+    /// The poses touched since the last update.
+    touched: Vec<bool>,
+}
+
+impl Default for IncrementalSmoother {
+    fn default() -> Self {
+        Self::from_problem(BundleAdjustment::default())
+    }
+}
+
+impl IncrementalSmoother {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Continues smoothing an existing problem, where every pose is solved in the first update.
+    pub fn from_problem(problem: BundleAdjustment) -> Self {
+        let mut first_observers = vec![None; problem.landmarks.len()];
+        for observation in &problem.observations {
+            let first = first_observers[observation.landmark].get_or_insert(observation.pose);
+            *first = (*first).min(observation.pose);
+        }
+        Self {
+            adjuster: BundleAdjuster::new(),
+            relinearize_threshold: 1e-4,
+            touched: vec![true; problem.poses.len()],
+            first_observers,
+            problem,
+        }
+    }
+
+    #[must_use]
+    pub fn adjuster(self, adjuster: BundleAdjuster) -> Self {
+        Self { adjuster, ..self }
+    }
+
+    /// Set the relinearization threshold.
+    ///
+    /// Default is `1e-4`.
+    #[must_use]
+    pub fn relinearize_threshold(self, relinearize_threshold: f64) -> Self {
+        Self {
+            relinearize_threshold,
+            ..self
+        }
+    }
+
+    /// The current estimate of the problem.
+    pub fn problem(&self) -> &BundleAdjustment {
+        &self.problem
+    }
+
+    pub fn into_problem(self) -> BundleAdjustment {
+        self.problem
+    }
+
+    /// Adds a pose with an initial estimate, returning its index. Poses must be added in the order they occur.
+    pub fn add_pose(&mut self, pose: WorldToCamera) -> usize {
+        self.problem.poses.push(pose);
+        self.problem
+            .fixed_poses
+            .resize(self.problem.poses.len(), false);
+        self.touched.push(true);
+        self.problem.poses.len() - 1
+    }
+
+    /// Adds a landmark with an initial estimate, returning its index.
+    pub fn add_landmark(&mut self, landmark: WorldPoint) -> usize {
+        self.problem.landmarks.push(landmark);
+        self.first_observers.push(None);
+        self.problem.landmarks.len() - 1
+    }
+
+    /// Adds an observation of a landmark, which touches the oldest pose that observes it.
+    pub fn observe(&mut self, observation: Observation) {
+        let first = self.first_observers[observation.landmark].get_or_insert(observation.pose);
+        *first = (*first).min(observation.pose);
+        self.touched[*first] = true;
+        self.touched[observation.pose] = true;
+        self.problem.observe(observation);
+    }
+
+    /// Adds a prior on a pose, such as a loop closure from place recognition.
+    pub fn add_prior(&mut self, prior: PosePrior) {
+        self.touched[prior.pose] = true;
+        self.problem.add_prior(prior);
+    }
+
+    pub fn add_gnss(&mut self, prior: GnssPrior) {
+        self.touched[prior.pose] = true;
+        self.problem.add_gnss(prior);
+    }
+
+    /// Holds a pose fixed, such as the first pose to fix the gauge freedom.
+    pub fn fix_pose(&mut self, pose: usize) {
+        self.problem.fix_pose(pose);
+    }
+
+    /// The oldest pose which is solved in the next update, or `None` if nothing was touched.
+    pub fn affected(&self) -> Option<usize> {
+        self.touched.iter().position(|&touched| touched)
+    }
+
+    /// Solves the poses affected by the factors added since the last update and the landmarks they observe.
+    ///
+    /// Returns `None` if there was nothing to update.
+    pub fn update(&mut self) -> Option<OptimizationReport> {
+        let start = self.affected()?;
+        let window = SlidingWindow::new()
+            .adjuster(self.adjuster)
+            .window(self.problem.poses.len() - start);
+        let mut local = window.local_problem(&self.problem);
+        let before = local.problem.poses.clone();
+        let report = self.adjuster.optimize(&mut local.problem);
+        local.write_back(&mut self.problem);
+
+        for touched in &mut self.touched {
+            *touched = false;
+        }
+        for ((&pose, before), after) in local.poses.iter().zip(before).zip(&local.problem.poses) {
+            let delta = after.isometry() * before.isometry().inverse();
+            let moved = delta.translation.vector.norm().max(delta.rotation.angle());
+            if moved > self.relinearize_threshold {
+                self.touched[pose] = true;
+            }
+        }
+        log::info!(
+            "incremental update solved {} poses and {} landmarks from pose {}",
+            local.poses.len(),
+            local.landmarks.len(),
+            start
+        );
+        Some(report)
+    }
+}
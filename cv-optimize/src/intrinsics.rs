@@ -0,0 +1,512 @@
+use crate::{
+    bundle_adjustment::update_pose, BlockSparseMatrix, BundleAdjuster, LeastSquaresProblem,
+    LevenbergMarquardt, Linearization, OptimizationReport, ReducedSolvePrecision, RobustLoss,
+    SolverBackend, TrustRegion,
+};
+use cv_core::{
+    nalgebra::{DMatrix, DVector, Matrix2x3, Matrix3, Point3, Vector2, Vector3},
+    ImagePoint, KeyPoint, ParametricCameraModel, Pose, WorldPoint, WorldToCamera,
+};
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// An observation of a landmark in the pixel coordinates of the image of a pose of an [`IntrinsicsAdjustment`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct PixelObservation {
+    /// The index of the pose the landmark was observed from.
+    pub pose: usize,
+    /// The index of the landmark.
+    pub landmark: usize,
+    /// The observed keypoint in the image.
+    pub keypoint: KeyPoint,
+    /// The robust loss of this observation, which overrides the loss of the [`BundleAdjuster`].
+    pub loss: Option<RobustLoss>,
+}
+
+impl PixelObservation {
+    pub fn new(pose: usize, landmark: usize, keypoint: KeyPoint) -> Self {
+        Self {
+            pose,
+            landmark,
+            keypoint,
+            loss: None,
+        }
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self {
+            loss: Some(loss),
+            ..self
+        }
+    }
+}
+
+/// A bundle adjustment problem which also refines the parameters of the camera models, such as the focal lengths,
+/// principal points, and distortion coefficients.
+///
+/// EXIF focal lengths are only approximate, so photo collections need their intrinsics refined to reach a good
+/// accuracy. Each pose is taken by one of the `cameras`, so images from the same physical camera share its
+/// parameters. Unlike [`BundleAdjustment`](crate::BundleAdjustment), the residuals are reprojection errors in pixels,
+/// since bearings can't be computed without knowing the intrinsics.
+#[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct IntrinsicsAdjustment<C> {
+    pub poses: Vec<WorldToCamera>,
+    pub landmarks: Vec<WorldPoint>,
+    pub cameras: Vec<C>,
+    /// The index of the camera of each pose.
+    pub pose_cameras: Vec<usize>,
+    pub observations: Vec<PixelObservation>,
+    /// Poses with `true` at their index are held fixed, which is needed to fix the gauge freedom of the problem.
+    pub fixed_poses: Vec<bool>,
+    /// Cameras with `true` at their index are held fixed, such as those that were calibrated beforehand.
+    pub fixed_cameras: Vec<bool>,
+    /// Parameters with `true` at their index are held fixed in every camera, such as the skew, which is
+    /// zero for almost all cameras and is poorly constrained.
+    pub fixed_parameters: Vec<bool>,
+}
+
+impl<C> IntrinsicsAdjustment<C>
+where
+    C: ParametricCameraModel,
+{
+    pub fn new(
+        poses: Vec<WorldToCamera>,
+        landmarks: Vec<WorldPoint>,
+        cameras: Vec<C>,
+        pose_cameras: Vec<usize>,
+    ) -> Self {
+        Self {
+            fixed_poses: vec![false; poses.len()],
+            fixed_cameras: vec![false; cameras.len()],
+            fixed_parameters: vec![false; C::PARAMETERS],
+            poses,
+            landmarks,
+            cameras,
+            pose_cameras,
+            observations: vec![],
+        }
+    }
+
+    /// Adds an observation of a landmark.
+    pub fn observe(&mut self, observation: PixelObservation) {
+        self.observations.push(observation);
+    }
+
+    /// Holds a pose fixed during the optimization.
+    pub fn fix_pose(&mut self, pose: usize) {
+        self.fixed_poses.resize(self.poses.len(), false);
+        self.fixed_poses[pose] = true;
+    }
+
+    /// Holds all of the parameters of a camera fixed during the optimization.
+    pub fn fix_camera(&mut self, camera: usize) {
+        self.fixed_cameras.resize(self.cameras.len(), false);
+        self.fixed_cameras[camera] = true;
+    }
+
+    /// Holds a parameter (see [`ParametricCameraModel::parameters`]) fixed in every camera.
+    pub fn fix_parameter(&mut self, parameter: usize) {
+        self.fixed_parameters.resize(C::PARAMETERS, false);
+        self.fixed_parameters[parameter] = true;
+    }
+
+    /// The reprojection error of an observation in pixels, or `None` if the landmark can't be projected.
+    pub fn reprojection_error(&self, observation: &PixelObservation) -> Option<Vector2<f64>> {
+        let point = self.landmarks[observation.landmark].point()?;
+        let camera = self.poses[observation.pose].isometry() * point;
+        let (pixel, _) = self.cameras[self.pose_cameras[observation.pose]]
+            .project_jacobians(camera.coords, &mut vec![Vector2::zeros(); C::PARAMETERS])?;
+        Some(pixel - observation.keypoint.image_point())
+    }
+}
+
+/// The layout of the pose and camera parameters of an [`IntrinsicsAdjustment`] in each step.
+#[derive(Clone, Debug, PartialEq)]
+struct IntrinsicsLayout {
+    /// The offset of each pose, or `None` if it is fixed.
+    poses: Vec<Option<usize>>,
+    /// The offset of each camera, or `None` if it is fixed.
+    cameras: Vec<Option<usize>>,
+    /// The free parameters of each camera.
+    parameters: Vec<usize>,
+    /// The number of pose and camera parameters.
+    len: usize,
+}
+
+impl IntrinsicsLayout {
+    fn new<C: ParametricCameraModel>(problem: &IntrinsicsAdjustment<C>) -> Self {
+        let is_set = |flags: &[bool], ix: usize| flags.get(ix).copied().unwrap_or(false);
+        let parameters: Vec<usize> = (0..C::PARAMETERS)
+            .filter(|&ix| !is_set(&problem.fixed_parameters, ix))
+            .collect();
+        let mut len = 0;
+        let poses = (0..problem.poses.len())
+            .map(|ix| {
+                (!is_set(&problem.fixed_poses, ix)).then(|| {
+                    len += 6;
+                    len - 6
+                })
+            })
+            .collect();
+        let cameras = (0..problem.cameras.len())
+            .map(|ix| {
+                (!is_set(&problem.fixed_cameras, ix) && !parameters.is_empty()).then(|| {
+                    len += parameters.len();
+                    len - parameters.len()
+                })
+            })
+            .collect();
+        Self {
+            poses,
+            cameras,
+            parameters,
+            len,
+        }
+    }
+}
+
+/// The normal equations of an [`IntrinsicsAdjustment`], where the landmarks are eliminated with the Schur
+/// complement as in [`BundleAdjuster`].
+struct IntrinsicsNormalEquations {
+    /// The Hessian and gradient of the pose and camera parameters.
+    hessian: DMatrix<f64>,
+    gradient: DVector<f64>,
+    landmark_hessians: Vec<Matrix3<f64>>,
+    landmark_gradients: Vec<Vector3<f64>>,
+    /// The landmark, the indices of the free pose and camera parameters, and the row of the coupling block of each
+    /// of those parameters with the landmark, for each observation.
+    couplings: Vec<(usize, Vec<usize>, Vec<Vector3<f64>>)>,
+    linear_solver: SolverBackend,
+    precision: ReducedSolvePrecision,
+}
+
+impl IntrinsicsNormalEquations {
+    fn landmark_offset(&self) -> usize {
+        self.gradient.len()
+    }
+}
+
+impl Linearization for IntrinsicsNormalEquations {
+    fn gradient(&self) -> DVector<f64> {
+        let offset = self.landmark_offset();
+        let mut gradient = DVector::zeros(offset + 3 * self.landmark_gradients.len());
+        gradient.rows_mut(0, offset).copy_from(&self.gradient);
+        for (landmark, landmark_gradient) in self.landmark_gradients.iter().enumerate() {
+            gradient
+                .fixed_rows_mut::<3>(offset + 3 * landmark)
+                .copy_from(landmark_gradient);
+        }
+        gradient
+    }
+
+    fn hessian_product(&self, v: &DVector<f64>) -> DVector<f64> {
+        let offset = self.landmark_offset();
+        let mut product = DVector::zeros(v.len());
+        product
+            .rows_mut(0, offset)
+            .copy_from(&(&self.hessian * v.rows(0, offset)));
+        for (landmark, hessian) in self.landmark_hessians.iter().enumerate() {
+            let mut block = product.fixed_rows_mut::<3>(offset + 3 * landmark);
+            block += hessian * v.fixed_rows::<3>(offset + 3 * landmark);
+        }
+        for (landmark, indices, coupling) in &self.couplings {
+            let v_landmark = v.fixed_rows::<3>(offset + 3 * landmark).into_owned();
+            let mut transposed = Vector3::zeros();
+            for (row, &ix) in coupling.iter().zip(indices) {
+                product[ix] += row.dot(&v_landmark);
+                transposed += row * v[ix];
+            }
+            let mut block = product.fixed_rows_mut::<3>(offset + 3 * landmark);
+            block += transposed;
+        }
+        product
+    }
+
+    fn solve(&self, lambda: f64) -> Option<DVector<f64>> {
+        let offset = self.landmark_offset();
+        let mut reduced = self.hessian.clone();
+        for i in 0..offset {
+            reduced[(i, i)] += lambda * self.hessian[(i, i)].max(1e-12);
+        }
+        let mut rhs = -&self.gradient;
+
+        // Invert the damped landmark blocks, skipping landmarks that aren't observed.
+        let landmark_inverses: Vec<Option<Matrix3<f64>>> = self
+            .landmark_hessians
+            .iter()
+            .map(|&hessian| {
+                (hessian != Matrix3::zeros()).then(|| ())?;
+                let mut damped = hessian;
+                for i in 0..3 {
+                    damped[(i, i)] += lambda * hessian[(i, i)].max(1e-12);
+                }
+                damped.try_inverse()
+            })
+            .collect();
+
+        let mut landmark_couplings = vec![vec![]; self.landmark_hessians.len()];
+        for (ix, (landmark, _, _)) in self.couplings.iter().enumerate() {
+            landmark_couplings[*landmark].push(ix);
+        }
+        for (landmark, couplings) in landmark_couplings.iter().enumerate() {
+            let inverse = match landmark_inverses[landmark] {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            let gradient = self.landmark_gradients[landmark];
+            for &a in couplings {
+                let (_, indices_a, coupling_a) = &self.couplings[a];
+                for (row_a, &ix) in coupling_a.iter().zip(indices_a) {
+                    let w = inverse * row_a;
+                    rhs[ix] += w.dot(&gradient);
+                    for &b in couplings {
+                        let (_, indices_b, coupling_b) = &self.couplings[b];
+                        for (row_b, &jx) in coupling_b.iter().zip(indices_b) {
+                            reduced[(ix, jx)] -= w.dot(row_b);
+                        }
+                    }
+                }
+            }
+        }
+        // Shared cameras couple all of their poses, so the system is built densely and only split into the blocks of
+        // the poses for the solver.
+        let reduced = BlockSparseMatrix::from_dense(&reduced, 6);
+        let camera_step = self.precision.solve(&self.linear_solver, &reduced, &rhs)?;
+
+        // Back-substitute the landmarks.
+        let mut step = DVector::zeros(offset + 3 * self.landmark_hessians.len());
+        step.rows_mut(0, offset).copy_from(&camera_step);
+        for (landmark, couplings) in landmark_couplings.iter().enumerate() {
+            let inverse = match landmark_inverses[landmark] {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            let mut landmark_rhs = -self.landmark_gradients[landmark];
+            for &a in couplings {
+                let (_, indices, coupling) = &self.couplings[a];
+                for (row, &ix) in coupling.iter().zip(indices) {
+                    landmark_rhs -= row * camera_step[ix];
+                }
+            }
+            step.fixed_rows_mut::<3>(offset + 3 * landmark)
+                .copy_from(&(inverse * landmark_rhs));
+        }
+        Some(step)
+    }
+}
+
+/// An [`IntrinsicsAdjustment`] along with the losses of the adjuster.
+#[derive(Clone)]
+struct IntrinsicsAdjusting<'a, C> {
+    adjuster: &'a BundleAdjuster,
+    problem: IntrinsicsAdjustment<C>,
+    layout: IntrinsicsLayout,
+}
+
+impl<C> LeastSquaresProblem for IntrinsicsAdjusting<'_, C>
+where
+    C: ParametricCameraModel + Clone,
+{
+    type Linearization = IntrinsicsNormalEquations;
+
+    fn cost(&self) -> f64 {
+        self.adjuster.intrinsics_cost(&self.problem)
+    }
+
+    /// Builds the normal equations with the iteratively reweighted least squares weights.
+    fn linearize(&self) -> IntrinsicsNormalEquations {
+        let problem = &self.problem;
+        let layout = &self.layout;
+        let mut normal = IntrinsicsNormalEquations {
+            hessian: DMatrix::zeros(layout.len, layout.len),
+            gradient: DVector::zeros(layout.len),
+            landmark_hessians: vec![Matrix3::zeros(); problem.landmarks.len()],
+            landmark_gradients: vec![Vector3::zeros(); problem.landmarks.len()],
+            couplings: Vec::with_capacity(problem.observations.len()),
+            linear_solver: self.adjuster.linear_solver,
+            precision: self.adjuster.precision,
+        };
+        let mut parameter_jacobian = vec![Vector2::zeros(); C::PARAMETERS];
+        for observation in &problem.observations {
+            let point = match problem.landmarks[observation.landmark].point() {
+                Some(point) => point,
+                None => continue,
+            };
+            let isometry = problem.poses[observation.pose].isometry();
+            let rotated = isometry.rotation * point.coords;
+            let camera_index = problem.pose_cameras[observation.pose];
+            let (pixel, jacobian_point) = match problem.cameras[camera_index].project_jacobians(
+                rotated + isometry.translation.vector,
+                &mut parameter_jacobian,
+            ) {
+                Some(projection) => projection,
+                None => continue,
+            };
+            let residual = pixel - observation.keypoint.image_point();
+            let weight = observation
+                .loss
+                .unwrap_or(self.adjuster.loss)
+                .weight(residual.norm());
+
+            // Gather the Jacobian columns of the free pose and camera parameters.
+            let mut indices = vec![];
+            let mut columns: Vec<Vector2<f64>> = vec![];
+            if let Some(offset) = layout.poses[observation.pose] {
+                let jacobian_rotation: Matrix2x3<f64> = -jacobian_point * rotated.cross_matrix();
+                for i in 0..3 {
+                    indices.push(offset + i);
+                    columns.push(jacobian_point.column(i).into_owned());
+                }
+                for i in 0..3 {
+                    indices.push(offset + 3 + i);
+                    columns.push(jacobian_rotation.column(i).into_owned());
+                }
+            }
+            if let Some(offset) = layout.cameras[camera_index] {
+                for (i, &parameter) in layout.parameters.iter().enumerate() {
+                    indices.push(offset + i);
+                    columns.push(parameter_jacobian[parameter]);
+                }
+            }
+            let jacobian_landmark = jacobian_point * isometry.rotation.matrix();
+
+            for (a, (&ix, column_a)) in indices.iter().zip(&columns).enumerate() {
+                normal.gradient[ix] += weight * column_a.dot(&residual);
+                for (&jx, column_b) in indices.iter().zip(&columns).skip(a) {
+                    let value = weight * column_a.dot(column_b);
+                    normal.hessian[(ix, jx)] += value;
+                    if ix != jx {
+                        normal.hessian[(jx, ix)] += value;
+                    }
+                }
+            }
+            normal.landmark_hessians[observation.landmark] +=
+                weight * jacobian_landmark.transpose() * jacobian_landmark;
+            normal.landmark_gradients[observation.landmark] +=
+                weight * jacobian_landmark.transpose() * residual;
+            let coupling = columns
+                .iter()
+                .map(|column| weight * jacobian_landmark.transpose() * column)
+                .collect();
+            normal
+                .couplings
+                .push((observation.landmark, indices, coupling));
+        }
+        normal
+    }
+
+    fn retract(&self, step: &DVector<f64>) -> Self {
+        let layout = &self.layout;
+        let mut candidate = self.clone();
+        for (pose, offset) in candidate.problem.poses.iter_mut().zip(&layout.poses) {
+            if let Some(offset) = *offset {
+                *pose = update_pose(*pose, step.fixed_rows::<6>(offset).into_owned());
+            }
+        }
+        let mut parameters = vec![0.0; C::PARAMETERS];
+        for (camera, offset) in candidate.problem.cameras.iter_mut().zip(&layout.cameras) {
+            if let Some(offset) = *offset {
+                camera.parameters(&mut parameters);
+                for (i, &parameter) in layout.parameters.iter().enumerate() {
+                    parameters[parameter] += step[offset + i];
+                }
+                camera.set_parameters(&parameters);
+            }
+        }
+        for (ix, landmark) in candidate.problem.landmarks.iter_mut().enumerate() {
+            if let Some(point) = landmark.point() {
+                *landmark = WorldPoint::from_point(Point3::from(
+                    point.coords + step.fixed_rows::<3>(layout.len + 3 * ix),
+                ));
+            }
+        }
+        candidate
+    }
+}
+
+impl BundleAdjuster {
+    /// The total robust loss of the reprojection errors of an [`IntrinsicsAdjustment`].
+    pub fn intrinsics_cost<C>(&self, problem: &IntrinsicsAdjustment<C>) -> f64
+    where
+        C: ParametricCameraModel,
+    {
+        problem
+            .observations
+            .iter()
+            .filter_map(|observation| {
+                let error = problem.reprojection_error(observation)?;
+                Some(observation.loss.unwrap_or(self.loss).loss(error.norm()))
+            })
+            .sum()
+    }
+
+    /// Optimizes the poses, landmarks, and camera parameters of an [`IntrinsicsAdjustment`] in place.
+    ///
+    /// The residuals are in pixels, so the scale of the robust loss should be in pixels as well.
+    ///
+    /// ```
+    /// use cv_core::{nalgebra::{IsometryMatrix3, Point2, Point3, Rotation3, Vector2, Vector3}, *};
+    /// use cv_optimize::{BundleAdjuster, IntrinsicsAdjustment, PixelObservation};
+    /// use cv_pinhole::CameraIntrinsics;
+    ///
+    /// let camera = CameraIntrinsics::identity()
+    ///     .focals(Vector2::new(800.0, 800.0))
+    ///     .principal_point(Point2::new(320.0, 240.0));
+    /// let poses: Vec<WorldToCamera> = (0..4)
+    ///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+    ///         Vector3::new(-0.3 * i as f64, 0.1 * (i % 2) as f64, 0.05 * i as f64).into(),
+    ///         Rotation3::from_euler_angles(0.04 * (i % 2) as f64, 0.06 * i as f64, 0.03 * (i / 2) as f64),
+    ///     )))
+    ///     .collect();
+    /// let landmarks: Vec<WorldPoint> = (0..40)
+    ///     .map(|i| WorldPoint::from_point(Point3::new((i % 8) as f64 * 0.3 - 1.0, (i / 8) as f64 * 0.3 - 0.6, 3.0 + (i % 5) as f64)))
+    ///     .collect();
+    ///
+    /// // Start from an EXIF focal length that is off by a few percent.
+    /// let guess = camera.focals(Vector2::new(770.0, 770.0));
+    /// let mut problem = IntrinsicsAdjustment::new(poses.clone(), landmarks.clone(), vec![guess], vec![0; 4]);
+    /// problem.fix_pose(0);
+    /// problem.fix_pose(1);
+    /// // Hold the skew at zero.
+    /// problem.fix_parameter(4);
+    /// for (p, pose) in poses.iter().enumerate() {
+    ///     for (l, &landmark) in landmarks.iter().enumerate() {
+    ///         let keypoint = camera.uncalibrate(pose.transform(landmark).bearing()).unwrap();
+    ///         problem.observe(PixelObservation::new(p, l, keypoint));
+    ///     }
+    /// }
+    ///
+    /// BundleAdjuster::new().max_iterations(100).optimize_intrinsics(&mut problem);
+    /// assert!((problem.cameras[0].focals - camera.focals).norm() < 1e-3);
+    /// assert!((problem.cameras[0].principal_point - camera.principal_point).norm() < 1e-3);
+    /// ```
+    pub fn optimize_intrinsics<C>(
+        &self,
+        problem: &mut IntrinsicsAdjustment<C>,
+    ) -> OptimizationReport
+    where
+        C: ParametricCameraModel + Clone,
+    {
+        let mut adjusting = IntrinsicsAdjusting {
+            adjuster: self,
+            problem: problem.clone(),
+            layout: IntrinsicsLayout::new(problem),
+        };
+        let report = LevenbergMarquardt::new()
+            .trust_region(TrustRegion::Damping(self.initial_lambda))
+            .max_iterations(self.max_iterations)
+            .step_tolerance(self.tolerance)
+            .minimize(&mut adjusting);
+        *problem = adjusting.problem;
+        log::info!(
+            "intrinsics adjustment finished after {} iterations with cost {} (initially {})",
+            report.iterations,
+            report.final_cost,
+            report.initial_cost
+        );
+        report
+    }
+}
@@ -1,7 +1,49 @@
+mod bundle_adjustment;
+mod covariance;
+mod depth;
+mod gnss;
+mod imu;
+mod imu_window;
+mod incremental;
+mod intrinsics;
+mod linear_solver;
+mod lines;
+mod marginalization;
+mod motion_only;
+mod planes;
+mod pose_graph;
+#[cfg(feature = "serde-serialize")]
+mod recording;
+mod robust;
+mod sim3_pose_graph;
 mod single_view_optimizer;
+mod sliding_window;
+mod solver;
+mod structure_only;
 mod three_view_optimizer;
 
+pub use bundle_adjustment::*;
+pub use covariance::*;
+pub use depth::*;
+pub use gnss::*;
+pub use imu::*;
+pub use imu_window::*;
+pub use incremental::*;
+pub use intrinsics::*;
+pub use linear_solver::*;
+pub use lines::*;
+pub use marginalization::*;
+pub use motion_only::*;
+pub use planes::*;
+pub use pose_graph::*;
+#[cfg(feature = "serde-serialize")]
+pub use recording::*;
+pub use robust::*;
+pub use sim3_pose_graph::*;
 pub use single_view_optimizer::*;
+pub use sliding_window::*;
+pub use solver::*;
+pub use structure_only::*;
 pub use three_view_optimizer::*;
 
 use cv_core::{
@@ -0,0 +1,587 @@
+use core::{cmp::Ordering, ops::Mul};
+use cv_core::nalgebra::{self, DMatrix, DVector, RealField};
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A symmetric matrix made of dense blocks, of which only the nonzero blocks are stored.
+///
+/// Each pose in the reduced camera system of a [`BundleAdjuster`](crate::BundleAdjuster) is only coupled to the poses
+/// which observe the same landmarks, so storing the system by blocks keeps its memory and the work of the sparse
+/// solvers proportional to the number of coupled poses rather than to the square of the number of poses.
+///
+/// ```
+/// use cv_core::nalgebra::{DMatrix, DVector};
+/// use cv_optimize::BlockSparseMatrix;
+///
+/// let mut matrix = BlockSparseMatrix::new(&[2, 1, 2]);
+/// matrix.block_mut(0, 0).fill_with_identity();
+/// *matrix.block_mut(0, 2) += DMatrix::from_element(2, 2, 0.5);
+/// *matrix.block_mut(2, 0) += DMatrix::from_element(2, 2, 0.5);
+/// matrix.block_mut(2, 2).fill_with_identity();
+/// assert!(matrix.block(0, 1).is_none());
+///
+/// let v = DVector::from_fn(5, |i, _| i as f64);
+/// assert_eq!(&matrix * &v, matrix.to_dense() * &v);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockSparseMatrix<T: RealField = f64> {
+    /// The offset of every block row (and column) followed by the size of the matrix.
+    offsets: Vec<usize>,
+    /// The stored blocks of every block row by their block column, including both triangles.
+    rows: Vec<BTreeMap<usize, DMatrix<T>>>,
+}
+
+impl<T: RealField> BlockSparseMatrix<T> {
+    /// Creates a zero matrix with blocks of the given sizes along the diagonal.
+    pub fn new(block_sizes: &[usize]) -> Self {
+        let mut offsets = Vec::with_capacity(block_sizes.len() + 1);
+        offsets.push(0);
+        for &size in block_sizes {
+            offsets.push(offsets[offsets.len() - 1] + size);
+        }
+        Self {
+            offsets,
+            rows: vec![BTreeMap::new(); block_sizes.len()],
+        }
+    }
+
+    /// Splits a dense symmetric matrix into blocks of `block_size`, only storing the blocks which aren't zero.
+    ///
+    /// The last block is smaller if the size of the matrix isn't a multiple of `block_size`.
+    pub fn from_dense(matrix: &DMatrix<T>, block_size: usize) -> Self {
+        assert!(block_size > 0, "the block size must be positive");
+        let n = matrix.nrows();
+        let block_sizes: Vec<usize> = (0..n)
+            .step_by(block_size)
+            .map(|offset| block_size.min(n - offset))
+            .collect();
+        let mut sparse = Self::new(&block_sizes);
+        for i in 0..block_sizes.len() {
+            for j in 0..block_sizes.len() {
+                let block = matrix.slice(
+                    (sparse.offsets[i], sparse.offsets[j]),
+                    (block_sizes[i], block_sizes[j]),
+                );
+                if block.iter().any(|x| !x.is_zero()) {
+                    sparse.block_mut(i, j).copy_from(&block);
+                }
+            }
+        }
+        sparse
+    }
+
+    /// The number of rows (and columns) of the matrix.
+    pub fn size(&self) -> usize {
+        self.offsets[self.offsets.len() - 1]
+    }
+
+    /// The number of block rows (and columns) of the matrix.
+    pub fn blocks(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The offset of the first row (and column) of a block.
+    pub fn offset(&self, block: usize) -> usize {
+        self.offsets[block]
+    }
+
+    /// The size of a block row (or column).
+    pub fn block_size(&self, block: usize) -> usize {
+        self.offsets[block + 1] - self.offsets[block]
+    }
+
+    /// The block at the block row and column, or `None` if it is zero and not stored.
+    pub fn block(&self, row: usize, column: usize) -> Option<&DMatrix<T>> {
+        self.rows[row].get(&column)
+    }
+
+    /// The block at the block row and column, which is stored as zero if it wasn't stored yet.
+    ///
+    /// The matrix is expected to stay symmetric, so the transposed block should be changed as well.
+    pub fn block_mut(&mut self, row: usize, column: usize) -> &mut DMatrix<T> {
+        let (rows, columns) = (self.block_size(row), self.block_size(column));
+        self.rows[row]
+            .entry(column)
+            .or_insert_with(|| DMatrix::zeros(rows, columns))
+    }
+
+    /// The diagonal of the matrix.
+    pub fn diagonal(&self) -> DVector<T> {
+        let mut diagonal = DVector::zeros(self.size());
+        for (block, row) in self.rows.iter().enumerate() {
+            if let Some(matrix) = row.get(&block) {
+                diagonal
+                    .rows_mut(self.offsets[block], matrix.nrows())
+                    .copy_from(&matrix.diagonal());
+            }
+        }
+        diagonal
+    }
+
+    /// Converts the matrix to a dense matrix.
+    pub fn to_dense(&self) -> DMatrix<T> {
+        let mut dense = DMatrix::zeros(self.size(), self.size());
+        for (row, blocks) in self.rows.iter().enumerate() {
+            for (&column, block) in blocks {
+                dense
+                    .slice_mut(
+                        (self.offsets[row], self.offsets[column]),
+                        (block.nrows(), block.ncols()),
+                    )
+                    .copy_from(block);
+            }
+        }
+        dense
+    }
+
+    /// Applies `f` to every stored entry, such as to convert the matrix to another precision.
+    pub fn map<U: RealField>(&self, f: impl Fn(T) -> U) -> BlockSparseMatrix<U> {
+        BlockSparseMatrix {
+            offsets: self.offsets.clone(),
+            rows: self
+                .rows
+                .iter()
+                .map(|blocks| {
+                    blocks
+                        .iter()
+                        .map(|(&column, block)| (column, block.map(&f)))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Iterates over the row, column, and value of every nonzero entry.
+    fn entries(&self) -> impl Iterator<Item = (usize, usize, T)> + '_ {
+        let offsets = &self.offsets;
+        self.rows
+            .iter()
+            .enumerate()
+            .flat_map(move |(row, blocks)| {
+                blocks.iter().flat_map(move |(&column, block)| {
+                    (0..block.nrows()).flat_map(move |i| {
+                        (0..block.ncols())
+                            .map(move |j| (offsets[row] + i, offsets[column] + j, block[(i, j)]))
+                    })
+                })
+            })
+            .filter(|&(_, _, value)| !value.is_zero())
+    }
+}
+
+impl<'a, 'b, T: RealField> Mul<&'b DVector<T>> for &'a BlockSparseMatrix<T> {
+    type Output = DVector<T>;
+
+    fn mul(self, v: &'b DVector<T>) -> DVector<T> {
+        let mut product = DVector::zeros(self.size());
+        for (row, blocks) in self.rows.iter().enumerate() {
+            let mut rows = product.rows_mut(self.offsets[row], self.block_size(row));
+            for (&column, block) in blocks {
+                rows += block * v.rows(self.offsets[column], block.ncols());
+            }
+        }
+        product
+    }
+}
+
+/// Solves a symmetric positive definite system `Ax = b`, such as the reduced camera system of a
+/// [`BundleAdjuster`](crate::BundleAdjuster).
+///
+/// The built-in solvers work in any precision, so `f32` systems take half the memory and are faster to factor on
+/// targets with poor `f64` throughput (see [`ReducedSolvePrecision::Single`]).
+///
+/// Returns `None` if the system is not positive definite or the solve fails.
+pub trait LinearSolver<T: RealField = f64> {
+    fn solve(&self, matrix: &BlockSparseMatrix<T>, rhs: &DVector<T>) -> Option<DVector<T>>;
+}
+
+/// Whether a value is positive, which is `false` for NaN.
+fn is_positive<T: RealField>(value: T) -> bool {
+    value.partial_cmp(&T::zero()) == Some(Ordering::Greater)
+}
+
+/// Solves the system with a dense Cholesky factorization, which is the fastest for small systems.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct DenseCholesky;
+
+impl<T: RealField> LinearSolver<T> for DenseCholesky {
+    fn solve(&self, matrix: &BlockSparseMatrix<T>, rhs: &DVector<T>) -> Option<DVector<T>> {
+        Some(matrix.to_dense().cholesky()?.solve(rhs))
+    }
+}
+
+/// Solves the system with an envelope (skyline) Cholesky factorization after a reverse Cuthill-McKee ordering.
+///
+/// The factor of a symmetric matrix has no fill outside of the envelope of its rows, which is the span from the
+/// first nonzero column of each row to the diagonal. Poses of a sequence are mostly coupled to their neighbors, so
+/// the envelope of the reduced camera system is a narrow band and only a fraction of the dense work is done.
+///
+/// ```
+/// use cv_core::nalgebra::{DMatrix, DVector};
+/// use cv_optimize::{BlockSparseMatrix, DenseCholesky, LinearSolver, SparseCholesky};
+///
+/// // A tridiagonal system with its rows shuffled.
+/// let n = 50;
+/// let order: Vec<usize> = (0..n).map(|i| (i * 7) % n).collect();
+/// let mut matrix = DMatrix::zeros(n, n);
+/// for i in 0..n {
+///     matrix[(order[i], order[i])] = 4.0;
+///     if i + 1 < n {
+///         matrix[(order[i], order[i + 1])] = -1.0;
+///         matrix[(order[i + 1], order[i])] = -1.0;
+///     }
+/// }
+/// let matrix = BlockSparseMatrix::from_dense(&matrix, 1);
+/// let rhs = DVector::from_fn(n, |i, _| i as f64);
+/// let sparse = SparseCholesky.solve(&matrix, &rhs).unwrap();
+/// let dense = DenseCholesky.solve(&matrix, &rhs).unwrap();
+/// assert!((sparse - dense).norm() < 1e-9);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct SparseCholesky;
+
+impl SparseCholesky {
+    /// The reverse Cuthill-McKee ordering of the nonzero pattern of the matrix, which narrows its envelope.
+    fn ordering<T: RealField>(matrix: &BlockSparseMatrix<T>) -> Vec<usize> {
+        let n = matrix.size();
+        let mut neighbors: Vec<Vec<usize>> = vec![vec![]; n];
+        for (i, j, _) in matrix.entries() {
+            if i != j {
+                neighbors[i].push(j);
+            }
+        }
+        let mut visited = vec![false; n];
+        let mut order = Vec::with_capacity(n);
+        while order.len() < n {
+            // Start each connected component from its unvisited row with the fewest neighbors.
+            let start = (0..n)
+                .filter(|&i| !visited[i])
+                .min_by_key(|&i| neighbors[i].len())
+                .unwrap();
+            visited[start] = true;
+            let first = order.len();
+            order.push(start);
+            let mut next = first;
+            while next < order.len() {
+                let row = order[next];
+                next += 1;
+                let mut adjacent: Vec<usize> = neighbors[row]
+                    .iter()
+                    .copied()
+                    .filter(|&j| !visited[j])
+                    .collect();
+                adjacent.sort_by_key(|&j| neighbors[j].len());
+                for j in adjacent {
+                    visited[j] = true;
+                    order.push(j);
+                }
+            }
+        }
+        order.reverse();
+        order
+    }
+}
+
+impl<T: RealField> LinearSolver<T> for SparseCholesky {
+    fn solve(&self, matrix: &BlockSparseMatrix<T>, rhs: &DVector<T>) -> Option<DVector<T>> {
+        let n = matrix.size();
+        let order = Self::ordering(matrix);
+        let mut position = vec![0; n];
+        for (i, &row) in order.iter().enumerate() {
+            position[row] = i;
+        }
+
+        // The entries of the lower triangle of each row of the permuted matrix.
+        let mut lower: Vec<Vec<(usize, T)>> = vec![vec![]; n];
+        for (i, j, value) in matrix.entries() {
+            let (i, j) = (position[i], position[j]);
+            if j <= i {
+                lower[i].push((j, value));
+            }
+        }
+        // The first column of the envelope of each row of the permuted matrix.
+        let first: Vec<usize> = lower
+            .iter()
+            .enumerate()
+            .map(|(i, entries)| entries.iter().map(|&(j, _)| j).min().unwrap_or(i))
+            .collect();
+        // The rows of the factor `L` within the envelope, which starts at `first[i]` for row `i`.
+        let mut factor: Vec<Vec<T>> = Vec::with_capacity(n);
+        for (i, &begin) in first.iter().enumerate() {
+            let mut row = vec![T::zero(); i + 1 - begin];
+            for &(j, value) in &lower[i] {
+                row[j - begin] = value;
+            }
+            for j in begin..=i {
+                let mut sum = row[j - begin];
+                for k in begin.max(first[j])..j {
+                    let left = if j == i {
+                        row[k - begin]
+                    } else {
+                        factor[j][k - first[j]]
+                    };
+                    sum -= row[k - begin] * left;
+                }
+                if j == i {
+                    if !is_positive(sum) {
+                        return None;
+                    }
+                    row[j - begin] = sum.sqrt();
+                } else {
+                    row[j - begin] = sum / factor[j][j - first[j]];
+                }
+            }
+            factor.push(row);
+        }
+
+        // Solve `Ly = b` and then `Lᵀx = y`.
+        let mut x: Vec<T> = order.iter().map(|&i| rhs[i]).collect();
+        for (i, (row, &begin)) in factor.iter().zip(&first).enumerate() {
+            let sum = (begin..i).fold(T::zero(), |sum, k| sum + row[k - begin] * x[k]);
+            x[i] = (x[i] - sum) / row[i - begin];
+        }
+        for (i, (row, &begin)) in factor.iter().zip(&first).enumerate().rev() {
+            x[i] /= row[i - begin];
+            for k in begin..i {
+                x[k] -= row[k - begin] * x[i];
+            }
+        }
+
+        let mut solution = DVector::zeros(n);
+        for (i, &row) in order.iter().enumerate() {
+            solution[row] = x[i];
+        }
+        Some(solution)
+    }
+}
+
+/// Solves the system iteratively with the conjugate gradient method, preconditioned by the inverse of the
+/// diagonal.
+///
+/// This never factors the matrix, so it is suitable for large reconstructions where a factorization has too much
+/// fill. The step is only approximate when it stops at `max_iterations`, which Levenberg-Marquardt tolerates, since
+/// steps that don't reduce the cost are rejected.
+///
+/// Only products of the matrix with vectors are needed, so [`ConjugateGradient::solve_with`] solves systems which
+/// are never formed. When it is selected explicitly as the [`SolverBackend`] of a
+/// [`BundleAdjuster`](crate::BundleAdjuster), the reduced camera system is applied through the Schur complement of
+/// the normal equations instead of being built.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct ConjugateGradient {
+    /// The maximum number of iterations.
+    pub max_iterations: usize,
+    /// The iterations stop when the norm of the residual is below this fraction of the norm of the right hand side.
+    pub tolerance: f64,
+}
+
+impl Default for ConjugateGradient {
+    fn default() -> Self {
+        Self {
+            max_iterations: 500,
+            tolerance: 1e-10,
+        }
+    }
+}
+
+impl ConjugateGradient {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    /// Solves the system given only a function to `multiply` a vector by the matrix and the `diagonal` of the
+    /// matrix, which is used as the preconditioner.
+    ///
+    /// ```
+    /// use cv_core::nalgebra::DVector;
+    /// use cv_optimize::ConjugateGradient;
+    ///
+    /// // The matrix `4I + 11ᵀ`, which is never formed.
+    /// let n = 100;
+    /// let multiply = |v: &DVector<f64>| v * 4.0 + DVector::repeat(n, v.sum());
+    /// let rhs = DVector::from_fn(n, |i, _| i as f64);
+    /// let x = ConjugateGradient::new()
+    ///     .solve_with(multiply, &DVector::repeat(n, 5.0), &rhs)
+    ///     .unwrap();
+    /// assert!((multiply(&x) - rhs).norm() < 1e-6);
+    /// ```
+    pub fn solve_with<T: RealField>(
+        &self,
+        multiply: impl Fn(&DVector<T>) -> DVector<T>,
+        diagonal: &DVector<T>,
+        rhs: &DVector<T>,
+    ) -> Option<DVector<T>> {
+        let preconditioner = diagonal.map(|d| if is_positive(d) { d.recip() } else { T::one() });
+        let threshold = nalgebra::convert::<f64, T>(self.tolerance) * rhs.norm();
+        let mut x = DVector::zeros(rhs.len());
+        let mut residual = rhs.clone();
+        let mut preconditioned = residual.component_mul(&preconditioner);
+        let mut direction = preconditioned.clone();
+        let mut alignment = residual.dot(&preconditioned);
+        for _ in 0..self.max_iterations {
+            if residual.norm() <= threshold {
+                break;
+            }
+            let product = multiply(&direction);
+            let curvature = direction.dot(&product);
+            // The matrix is not positive definite along the direction.
+            if !is_positive(curvature) {
+                return None;
+            }
+            let alpha = alignment / curvature;
+            x.axpy(alpha, &direction, T::one());
+            residual.axpy(-alpha, &product, T::one());
+            preconditioned = residual.component_mul(&preconditioner);
+            let next_alignment = residual.dot(&preconditioned);
+            direction = &preconditioned + (next_alignment / alignment) * direction;
+            alignment = next_alignment;
+        }
+        Some(x)
+    }
+}
+
+impl<T: RealField> LinearSolver<T> for ConjugateGradient {
+    fn solve(&self, matrix: &BlockSparseMatrix<T>, rhs: &DVector<T>) -> Option<DVector<T>> {
+        self.solve_with(|v| matrix * v, &matrix.diagonal(), rhs)
+    }
+}
+
+/// The [`LinearSolver`] which solves the reduced system of the normal equations of an optimizer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum SolverBackend {
+    DenseCholesky,
+    SparseCholesky,
+    ConjugateGradient(ConjugateGradient),
+    /// Picks the backend from the size and sparsity of each system: [`DenseCholesky`] for small systems,
+    /// [`SparseCholesky`] for larger systems with a narrow envelope, and [`ConjugateGradient`] otherwise.
+    Auto,
+}
+
+impl Default for SolverBackend {
+    fn default() -> Self {
+        Self::Auto
+    }
+}
+
+impl SolverBackend {
+    /// Systems up to this size are always solved with [`DenseCholesky`] by [`SolverBackend::Auto`].
+    const DENSE_SIZE: usize = 600;
+    /// The largest envelope, as a fraction of the lower triangle, that [`SolverBackend::Auto`] factors with
+    /// [`SparseCholesky`].
+    const SPARSE_ENVELOPE: f64 = 0.25;
+
+    /// The backend which [`SolverBackend::Auto`] picks for a system.
+    fn pick<T: RealField>(matrix: &BlockSparseMatrix<T>) -> Self {
+        let n = matrix.size();
+        if n <= Self::DENSE_SIZE {
+            return Self::DenseCholesky;
+        }
+        // Estimate the envelope in the given order from the stored blocks, which the reordering only narrows.
+        let envelope: usize = (0..matrix.blocks())
+            .map(|row| {
+                let first = matrix.rows[row]
+                    .keys()
+                    .next()
+                    .map_or(row, |&column| column.min(row));
+                let size = matrix.block_size(row);
+                size * (matrix.offset(row) - matrix.offset(first)) + size * (size - 1) / 2
+            })
+            .sum();
+        if (envelope as f64) < Self::SPARSE_ENVELOPE * (n * (n - 1) / 2) as f64 {
+            Self::SparseCholesky
+        } else {
+            Self::ConjugateGradient(ConjugateGradient::default())
+        }
+    }
+}
+
+impl<T: RealField> LinearSolver<T> for SolverBackend {
+    fn solve(&self, matrix: &BlockSparseMatrix<T>, rhs: &DVector<T>) -> Option<DVector<T>> {
+        match *self {
+            Self::DenseCholesky => DenseCholesky.solve(matrix, rhs),
+            Self::SparseCholesky => SparseCholesky.solve(matrix, rhs),
+            Self::ConjugateGradient(solver) => solver.solve(matrix, rhs),
+            Self::Auto => Self::pick(matrix).solve(matrix, rhs),
+        }
+    }
+}
+
+/// The precision that only the reduced system of the normal equations is solved in.
+///
+/// The bundle adjusters aren't generic over the scalar type like a [`LeastSquaresProblem`](crate::LeastSquaresProblem)
+/// can be: their parameters, residuals, Jacobians, and normal equations always stay in `f64`, since the sums over
+/// many observations lose too much precision in `f32`, and only the reduced system is converted for the solve. The reduced system dominates the memory and the time of large
+/// problems, so solving it in single precision helps on embedded targets with poor `f64` throughput.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum ReducedSolvePrecision {
+    /// Solve in `f64`.
+    Double,
+    /// Solve in `f32`, followed by the given number of steps of iterative refinement in which the residual of the
+    /// solution is computed in `f64` and a correction is solved for in `f32`.
+    Single(usize),
+}
+
+impl Default for ReducedSolvePrecision {
+    fn default() -> Self {
+        Self::Double
+    }
+}
+
+impl ReducedSolvePrecision {
+    /// Solves the system with the solver in this precision.
+    ///
+    /// ```
+    /// use cv_core::nalgebra::{DMatrix, DVector};
+    /// use cv_optimize::{BlockSparseMatrix, ReducedSolvePrecision, SolverBackend};
+    ///
+    /// let matrix = DMatrix::from_fn(30, 30, |i, j| if i == j { 4.0 } else { 1.0 / (1.0 + (i + j) as f64) });
+    /// let matrix = BlockSparseMatrix::from_dense(&matrix, 6);
+    /// let rhs = DVector::from_fn(30, |i, _| (i as f64).sin());
+    /// let double = ReducedSolvePrecision::Double.solve(&SolverBackend::DenseCholesky, &matrix, &rhs).unwrap();
+    /// let single = ReducedSolvePrecision::Single(0).solve(&SolverBackend::DenseCholesky, &matrix, &rhs).unwrap();
+    /// let refined = ReducedSolvePrecision::Single(2).solve(&SolverBackend::DenseCholesky, &matrix, &rhs).unwrap();
+    /// assert!((&single - &double).norm() < 1e-5);
+    /// assert!((&refined - &double).norm() < 1e-10);
+    /// ```
+    pub fn solve<S>(
+        self,
+        solver: &S,
+        matrix: &BlockSparseMatrix<f64>,
+        rhs: &DVector<f64>,
+    ) -> Option<DVector<f64>>
+    where
+        S: LinearSolver<f32> + LinearSolver<f64>,
+    {
+        match self {
+            Self::Double => LinearSolver::<f64>::solve(solver, matrix, rhs),
+            Self::Single(refinements) => {
+                let single = matrix.map(|x| x as f32);
+                let solve = |rhs: &DVector<f64>| {
+                    LinearSolver::<f32>::solve(solver, &single, &rhs.map(|x| x as f32))
+                        .map(|x| x.map(f64::from))
+                };
+                let mut x = solve(rhs)?;
+                for _ in 0..refinements {
+                    x += solve(&(rhs - matrix * &x))?;
+                }
+                Some(x)
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::RobustLoss;
+use cv_core::{
+    nalgebra::{
+        Matrix2x3, Matrix2x4, Matrix2x6, Matrix3, Matrix3x4, Point3, Rotation3, UnitVector3,
+        Vector2, Vector3, Vector4,
+    },
+    Pose, WorldToCamera,
+};
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// An infinite line in 3d in Plücker coordinates.
+///
+/// The line passes through the point `direction × moment`, and the moment is `p × direction` for any point `p` on
+/// the line, so its norm is the distance of the line from the origin. The direction is kept at unit length.
+///
+/// Plücker coordinates have six numbers for four degrees of freedom, so optimizers update lines in the orthonormal
+/// representation of Bartoli and Sturm through [`PluckerLine::retract`], which has exactly four parameters.
+///
+/// ```
+/// use cv_core::nalgebra::{Point3, Vector4};
+/// use cv_optimize::PluckerLine;
+///
+/// let line = PluckerLine::from_points(Point3::new(1.0, 0.0, 2.0), Point3::new(1.0, 1.0, 2.0)).unwrap();
+/// assert!((line.distance(Point3::new(1.0, 5.0, 2.0))).abs() < 1e-12);
+/// assert!((line.distance(Point3::origin()) - 5.0f64.sqrt()).abs() < 1e-12);
+///
+/// // A step in the orthonormal representation keeps a valid line.
+/// let moved = line.retract(Vector4::new(0.1, -0.2, 0.05, 0.1));
+/// assert!(moved.moment.dot(&moved.direction).abs() < 1e-12);
+/// assert!((moved.direction.norm() - 1.0).abs() < 1e-12);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct PluckerLine {
+    pub direction: Vector3<f64>,
+    pub moment: Vector3<f64>,
+}
+
+impl PluckerLine {
+    /// Creates a line from a direction and the moment, normalizing the direction.
+    pub fn new(direction: Vector3<f64>, moment: Vector3<f64>) -> Self {
+        let norm = direction.norm();
+        Self {
+            direction: direction / norm,
+            moment: moment / norm,
+        }
+    }
+
+    /// Creates the line through two points, or returns `None` if they are the same point.
+    pub fn from_points(a: Point3<f64>, b: Point3<f64>) -> Option<Self> {
+        let direction = b - a;
+        (direction.norm() > 0.0).then(|| ())?;
+        let direction = direction.normalize();
+        Some(Self {
+            direction,
+            moment: a.coords.cross(&direction),
+        })
+    }
+
+    /// The point on the line closest to the origin.
+    pub fn closest_point(&self) -> Point3<f64> {
+        Point3::from(self.direction.cross(&self.moment))
+    }
+
+    /// The distance of a point from the line.
+    pub fn distance(&self, point: Point3<f64>) -> f64 {
+        (point.coords.cross(&self.direction) - self.moment).norm()
+    }
+
+    /// Transforms the line from the world into the camera of a pose.
+    pub fn transform(&self, pose: WorldToCamera) -> Self {
+        let isometry = pose.isometry();
+        let direction = isometry.rotation * self.direction;
+        Self {
+            direction,
+            moment: isometry.rotation * self.moment + isometry.translation.vector.cross(&direction),
+        }
+    }
+
+    /// The orthonormal representation `(U, (w1, w2))` of the line, where the columns of `U` are the directions of the
+    /// moment, the direction, and their cross product, and `(w1, w2)` is the normalized `(|moment|, 1)`.
+    fn orthonormal(&self) -> (Matrix3<f64>, Vector2<f64>) {
+        let moment_norm = self.moment.norm();
+        // Lines through the origin have no moment direction, so pick any direction perpendicular to the line.
+        let u1 = if moment_norm > 1e-12 {
+            self.moment / moment_norm
+        } else {
+            let axis = if self.direction.x.abs() < 0.9 {
+                Vector3::x()
+            } else {
+                Vector3::y()
+            };
+            self.direction.cross(&axis).normalize()
+        };
+        let u3 = u1.cross(&self.direction);
+        let u = Matrix3::from_columns(&[u1, self.direction, u3]);
+        (u, Vector2::new(moment_norm, 1.0).normalize())
+    }
+
+    /// Applies a step `(ψ, φ)` in the orthonormal representation, which updates `U ← U exp([ψ]×)` and rotates
+    /// `(w1, w2)` by `φ`.
+    pub fn retract(&self, step: Vector4<f64>) -> Self {
+        let (u, w) = self.orthonormal();
+        let rotation = Rotation3::new(step.xyz());
+        let u = u * rotation.matrix();
+        let (sin, cos) = step.w.sin_cos();
+        let w = Vector2::new(w.x * cos - w.y * sin, w.y * cos + w.x * sin);
+        Self::new(
+            w.y * u.column(1).into_owned(),
+            w.x * u.column(0).into_owned(),
+        )
+    }
+
+    /// The Jacobian of the moment and direction in respect to a step of [`PluckerLine::retract`], given as the
+    /// moment rows then the direction rows.
+    fn retract_jacobian(&self) -> (Matrix3x4<f64>, Matrix3x4<f64>) {
+        let (u, w) = self.orthonormal();
+        let (u1, u2, u3) = (u.column(0), u.column(1), u.column(2));
+        // The moment is `w1 u1` and the direction is `w2 u2` up to the common scale of the line.
+        let moment = Matrix3x4::from_columns(&[Vector3::zeros(), -w.x * u3, w.x * u2, -w.y * u1]);
+        let direction = Matrix3x4::from_columns(&[w.y * u3, Vector3::zeros(), -w.y * u1, w.x * u2]);
+        // Match the scale of the coordinates, whose direction has unit length. This only differs from the exact
+        // Jacobian by a change of the common scale, which doesn't move the line.
+        (moment / w.y, direction / w.y)
+    }
+}
+
+/// An observation of a [`PluckerLine`] of a [`BundleAdjustment`](crate::BundleAdjustment) as the bearings of the two
+/// endpoints of a detected line segment.
+///
+/// The endpoints don't need to correspond to any particular points on the line, since the residual is the
+/// distance of each endpoint bearing from the plane through the optical center and the line.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct LineObservation {
+    /// The index of the pose the line was observed from.
+    pub pose: usize,
+    /// The index of the line.
+    pub line: usize,
+    /// The bearings of the endpoints of the segment.
+    pub endpoints: [UnitVector3<f64>; 2],
+    /// The robust loss of this observation, which overrides the loss of the
+    /// [`BundleAdjuster`](crate::BundleAdjuster).
+    pub loss: Option<RobustLoss>,
+}
+
+impl LineObservation {
+    pub fn new(pose: usize, line: usize, endpoints: [UnitVector3<f64>; 2]) -> Self {
+        Self {
+            pose,
+            line,
+            endpoints,
+            loss: None,
+        }
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self {
+            loss: Some(loss),
+            ..self
+        }
+    }
+
+    /// The sine of the angle between each endpoint bearing and the plane through the optical center and the line.
+    pub fn residual(&self, pose: WorldToCamera, line: &PluckerLine) -> Vector2<f64> {
+        let normal = line.transform(pose).moment.normalize();
+        Vector2::new(
+            self.endpoints[0].dot(&normal),
+            self.endpoints[1].dot(&normal),
+        )
+    }
+}
+
+/// The residual of a line observation and its Jacobians in respect to the pose in se(3) and the line in the
+/// orthonormal representation.
+pub(crate) fn linearize_line_observation(
+    observation: &LineObservation,
+    pose: WorldToCamera,
+    line: &PluckerLine,
+) -> (Vector2<f64>, Matrix2x6<f64>, Matrix2x4<f64>) {
+    let isometry = pose.isometry();
+    let rotation = *isometry.rotation.matrix();
+    let translation = isometry.translation.vector.cross_matrix();
+    let rotated_direction = rotation * line.direction;
+    let rotated_moment = rotation * line.moment;
+    let moment = rotated_moment + isometry.translation.vector.cross(&rotated_direction);
+    let norm = moment.norm();
+    let normal = moment / norm;
+    // The Jacobian of normalizing the moment.
+    let normalize = (Matrix3::identity() - normal * normal.transpose()) / norm;
+    let endpoints = Matrix2x3::from_rows(&[
+        observation.endpoints[0].transpose(),
+        observation.endpoints[1].transpose(),
+    ]);
+    let residual_moment = endpoints * normalize;
+
+    // A step `t ← t + δt`, `R ← exp(ω)R` moves the moment `Rm + t × Rd` by
+    // `-[Rd]×δt - ([Rm]× + [t]×[Rd]×)ω`.
+    let mut jacobian_pose = Matrix2x6::zeros();
+    jacobian_pose
+        .fixed_columns_mut::<3>(0)
+        .copy_from(&(residual_moment * -rotated_direction.cross_matrix()));
+    jacobian_pose.fixed_columns_mut::<3>(3).copy_from(
+        &(residual_moment
+            * -(rotated_moment.cross_matrix() + translation * rotated_direction.cross_matrix())),
+    );
+    let (moment_step, direction_step) = line.retract_jacobian();
+    let jacobian_line =
+        residual_moment * (rotation * moment_step + translation * rotation * direction_step);
+    (endpoints * normal, jacobian_pose, jacobian_line)
+}
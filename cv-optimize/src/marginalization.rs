@@ -0,0 +1,327 @@
+use crate::{bundle_adjustment::linearize_observation, BundleAdjuster, BundleAdjustment};
+use cv_core::{
+    nalgebra::{DMatrix, DVector, Matrix3, Matrix3x6, Matrix6, Vector3},
+    Pose, Skew3, WorldToCamera,
+};
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// A dense prior over poses which is left behind when states are marginalized, so that the information of the
+/// dropped states is kept instead of discarded, as in VINS and OKVIS.
+///
+/// The prior is the linearized residual `r + Jδ`, where `δ` is the difference of each pose from the pose at the
+/// time of marginalization in se(3) (with `t ← t + δt` and `R ← exp(ω)R`). The Jacobian is not relinearized
+/// afterwards, which keeps it consistent with the first estimate of the poses.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct MarginalPrior {
+    /// The indices of the poses the prior constrains.
+    pub poses: Vec<usize>,
+    /// The poses at the time of marginalization, which the prior is linearized at.
+    pub linearization: Vec<WorldToCamera>,
+    /// The Jacobian of the residual with six columns per pose.
+    pub jacobian: DMatrix<f64>,
+    /// The residual at the linearization point.
+    pub residual: DVector<f64>,
+}
+
+impl MarginalPrior {
+    /// Creates the prior from the normal equations `(H, g)` over the poses that remain after marginalization.
+    ///
+    /// The residual and Jacobian are recovered from the eigendecomposition of `H`, so that `JᵀJ = H` and `Jᵀr = g`.
+    /// Directions with no information are dropped.
+    pub fn from_normal_equations(
+        poses: Vec<usize>,
+        linearization: Vec<WorldToCamera>,
+        hessian: DMatrix<f64>,
+        gradient: &DVector<f64>,
+    ) -> Self {
+        let eigen = hessian.symmetric_eigen();
+        let max = eigen.eigenvalues.iter().copied().fold(0.0, f64::max);
+        let kept: Vec<usize> = (0..eigen.eigenvalues.len())
+            .filter(|&ix| eigen.eigenvalues[ix] > 1e-8 * max)
+            .collect();
+        let mut jacobian = DMatrix::zeros(kept.len(), hessian.ncols());
+        let mut residual = DVector::zeros(kept.len());
+        for (row, &ix) in kept.iter().enumerate() {
+            let root = eigen.eigenvalues[ix].sqrt();
+            let vector = eigen.eigenvectors.column(ix);
+            jacobian
+                .row_mut(row)
+                .copy_from(&(vector.transpose() * root));
+            residual[row] = vector.dot(gradient) / root;
+        }
+        Self {
+            poses,
+            linearization,
+            jacobian,
+            residual,
+        }
+    }
+
+    /// The difference of each pose from its linearization point in se(3).
+    fn delta(&self, poses: &[WorldToCamera]) -> DVector<f64> {
+        let mut delta = DVector::zeros(6 * self.poses.len());
+        for (ix, (&pose, linearization)) in self.poses.iter().zip(&self.linearization).enumerate() {
+            let isometry = poses[pose].isometry();
+            let linearization = linearization.isometry();
+            let rotation: Skew3 = (isometry.rotation * linearization.rotation.inverse()).into();
+            delta
+                .fixed_rows_mut::<3>(6 * ix)
+                .copy_from(&(isometry.translation.vector - linearization.translation.vector));
+            delta.fixed_rows_mut::<3>(6 * ix + 3).copy_from(&rotation.0);
+        }
+        delta
+    }
+
+    /// The residual of the prior at the current poses.
+    pub fn error(&self, poses: &[WorldToCamera]) -> DVector<f64> {
+        &self.residual + &self.jacobian * self.delta(poses)
+    }
+
+    /// The squared norm of the residual of the prior at the current poses.
+    pub fn cost(&self, poses: &[WorldToCamera]) -> f64 {
+        self.error(poses).norm_squared()
+    }
+}
+
+impl BundleAdjuster {
+    /// Marginalizes `poses` and every landmark they observe out of the problem, which creates a [`MarginalPrior`]
+    /// over the remaining poses that observed those landmarks.
+    ///
+    /// As in VINS, the marginalized landmarks are removed along with all of their observations, so a visual
+    /// odometry front-end should triangulate new landmarks for the features which are still tracked. The
+    /// marginalized poses are held fixed afterwards, since nothing constrains them anymore. Fixed poses are never
+    /// marginalized. Line observations are kept as observations from the fixed poses, while plane factors on the
+    /// marginalized states are not summarized and are removed.
+    ///
+    /// ```
+    /// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3}, *};
+    /// use cv_optimize::{BundleAdjuster, BundleAdjustment, Observation, PosePrior};
+    /// use cv_core::nalgebra::Matrix6;
+    ///
+    /// let poses: Vec<WorldToCamera> = (0..4)
+    ///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+    ///         Vector3::new(-0.2 * i as f64, 0.0, 0.0).into(),
+    ///         Rotation3::identity(),
+    ///     )))
+    ///     .collect();
+    /// let landmarks: Vec<WorldPoint> = (0..40)
+    ///     .map(|i| WorldPoint::from_point(Point3::new((i % 5) as f64 * 0.3 - 0.6, (i / 5) as f64 * 0.2 - 0.7, 4.0 + (i % 3) as f64)))
+    ///     .collect();
+    /// let mut problem = BundleAdjustment::new(poses.clone(), landmarks.clone());
+    /// // The first pose is anchored by a prior, which will be summarized by the marginal prior.
+    /// problem.add_prior(PosePrior::new(0, poses[0], Matrix6::identity() * 1e6));
+    /// for (p, pose) in poses.iter().enumerate() {
+    ///     for (l, &landmark) in landmarks.iter().enumerate() {
+    ///         // The first pose only sees the first half of the landmarks and the last pose only sees the rest.
+    ///         if (l < 20 && p < 3) || (l >= 20 && p > 0) {
+    ///             problem.observe(Observation::new(p, l, pose.transform(landmark).bearing()));
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let adjuster = BundleAdjuster::new();
+    /// adjuster.marginalize(&mut problem, &[0]);
+    /// assert!(problem.priors.is_empty());
+    /// assert_eq!(problem.marginal_priors.len(), 1);
+    /// assert!(problem.observations.iter().all(|observation| observation.pose != 0));
+    ///
+    /// // The marginal prior still anchors the remaining poses (except for the scale, which was never observable).
+    /// problem.poses[3].0.translation.vector.y += 0.02;
+    /// let report = adjuster.optimize(&mut problem);
+    /// assert!(report.final_cost < 1e-12);
+    /// assert!(problem.poses[3].0.translation.vector.y.abs() < 1e-6);
+    /// ```
+    pub fn marginalize(&self, problem: &mut BundleAdjustment, poses: &[usize]) {
+        let mut dropped = vec![false; problem.poses.len()];
+        for &pose in poses {
+            dropped[pose] = !problem.is_fixed(pose);
+        }
+        let mut marginalized_landmarks = vec![false; problem.landmarks.len()];
+        for observation in &problem.observations {
+            if dropped[observation.pose] {
+                marginalized_landmarks[observation.landmark] = true;
+            }
+        }
+        let touches_dropped = |prior: &MarginalPrior| prior.poses.iter().any(|&pose| dropped[pose]);
+
+        // Order the variables as the remaining poses, then the dropped poses, then the landmarks.
+        let mut remaining = vec![];
+        let mut is_remaining = vec![false; problem.poses.len()];
+        let mut add_remaining = |pose: usize| {
+            if !dropped[pose] && !problem.is_fixed(pose) && !is_remaining[pose] {
+                is_remaining[pose] = true;
+                remaining.push(pose);
+            }
+        };
+        for observation in &problem.observations {
+            if marginalized_landmarks[observation.landmark] {
+                add_remaining(observation.pose);
+            }
+        }
+        for prior in problem
+            .marginal_priors
+            .iter()
+            .filter(|prior| touches_dropped(prior))
+        {
+            prior.poses.iter().copied().for_each(&mut add_remaining);
+        }
+        let mut pose_offsets = vec![None; problem.poses.len()];
+        for (ix, &pose) in remaining.iter().enumerate() {
+            pose_offsets[pose] = Some(6 * ix);
+        }
+        let mut dimension = 6 * remaining.len();
+        for (pose, offset) in pose_offsets.iter_mut().enumerate() {
+            if dropped[pose] {
+                *offset = Some(dimension);
+                dimension += 6;
+            }
+        }
+        let mut landmark_offsets = vec![None; problem.landmarks.len()];
+        for (landmark, offset) in landmark_offsets.iter_mut().enumerate() {
+            if marginalized_landmarks[landmark] && problem.landmarks[landmark].point().is_some() {
+                *offset = Some(dimension);
+                dimension += 3;
+            }
+        }
+
+        // Build the normal equations of every term that involves the marginalized states.
+        let mut hessian = DMatrix::zeros(dimension, dimension);
+        let mut gradient = DVector::zeros(dimension);
+        for observation in &problem.observations {
+            let point = match problem.landmarks[observation.landmark].point() {
+                Some(point) if marginalized_landmarks[observation.landmark] => point,
+                _ => continue,
+            };
+            let (residual, jacobian_pose, jacobian_landmark) =
+                linearize_observation(problem.poses[observation.pose], point, observation.bearing);
+            let weight = observation
+                .loss
+                .unwrap_or(self.loss)
+                .weight(residual.norm());
+            let landmark = landmark_offsets[observation.landmark].unwrap();
+            let mut landmark_gradient = gradient.fixed_rows_mut::<3>(landmark);
+            landmark_gradient += weight * jacobian_landmark.transpose() * residual;
+            let mut landmark_block = hessian.fixed_slice_mut::<3, 3>(landmark, landmark);
+            landmark_block += weight * jacobian_landmark.transpose() * jacobian_landmark;
+            if let Some(pose) = pose_offsets[observation.pose] {
+                let mut pose_gradient = gradient.fixed_rows_mut::<6>(pose);
+                pose_gradient += weight * jacobian_pose.transpose() * residual;
+                let mut pose_block = hessian.fixed_slice_mut::<6, 6>(pose, pose);
+                pose_block += weight * jacobian_pose.transpose() * jacobian_pose;
+                let coupling = weight * jacobian_pose.transpose() * jacobian_landmark;
+                let mut block = hessian.fixed_slice_mut::<6, 3>(pose, landmark);
+                block += coupling;
+                let mut block = hessian.fixed_slice_mut::<3, 6>(landmark, pose);
+                block += coupling.transpose();
+            }
+        }
+        let mut add_pose_term = |pose: usize, error: &DVector<f64>, jacobian: DMatrix<f64>| {
+            let offset = pose_offsets[pose].unwrap();
+            let transpose = jacobian.transpose();
+            let mut pose_gradient = gradient.rows_mut(offset, 6);
+            pose_gradient += &transpose * error;
+            let mut pose_block = hessian.slice_mut((offset, offset), (6, 6));
+            pose_block += transpose * jacobian;
+        };
+        for prior in problem.priors.iter().filter(|prior| dropped[prior.pose]) {
+            let (error, norm, jacobian) = prior.linearize(problem.poses[prior.pose]);
+            // Whiten the residual with the square root of the information matrix.
+            let root = (prior.loss.weight(norm) * prior.information)
+                .cholesky()
+                .map(|cholesky| cholesky.l().transpose())
+                .unwrap_or_else(Matrix6::zeros);
+            add_pose_term(
+                prior.pose,
+                &DVector::from_column_slice((root * error).as_slice()),
+                DMatrix::from_column_slice(6, 6, (root * jacobian).as_slice()),
+            );
+        }
+        for prior in problem.gnss.iter().filter(|prior| dropped[prior.pose]) {
+            let (error, jacobian): (Vector3<f64>, Matrix3x6<f64>) =
+                prior.linearize(problem.poses[prior.pose]);
+            let root = (prior.loss.weight(prior.mahalanobis(error)) * prior.information)
+                .cholesky()
+                .map(|cholesky| cholesky.l().transpose())
+                .unwrap_or_else(Matrix3::zeros);
+            add_pose_term(
+                prior.pose,
+                &DVector::from_column_slice((root * error).as_slice()),
+                DMatrix::from_column_slice(3, 6, (root * jacobian).as_slice()),
+            );
+        }
+        for prior in problem
+            .marginal_priors
+            .iter()
+            .filter(|prior| touches_dropped(prior))
+        {
+            let error = prior.error(&problem.poses);
+            for (i, &a) in prior.poses.iter().enumerate() {
+                let offset_a = match pose_offsets[a] {
+                    Some(offset) => offset,
+                    None => continue,
+                };
+                let jacobian_a = prior.jacobian.columns(6 * i, 6);
+                let mut pose_gradient = gradient.rows_mut(offset_a, 6);
+                pose_gradient += jacobian_a.transpose() * &error;
+                for (j, &b) in prior.poses.iter().enumerate() {
+                    if let Some(offset_b) = pose_offsets[b] {
+                        let mut block = hessian.slice_mut((offset_a, offset_b), (6, 6));
+                        block += jacobian_a.transpose() * prior.jacobian.columns(6 * j, 6);
+                    }
+                }
+            }
+        }
+
+        // Eliminate the marginalized states with the Schur complement.
+        let kept = 6 * remaining.len();
+        let new_prior = (kept > 0).then(|| {
+            let marginalized = dimension - kept;
+            let hessian_mm = hessian
+                .slice((kept, kept), (marginalized, marginalized))
+                .into_owned();
+            let inverse = hessian_mm
+                .pseudo_inverse(1e-12)
+                .unwrap_or_else(|_| DMatrix::zeros(marginalized, marginalized));
+            let hessian_km = hessian.slice((0, kept), (kept, marginalized));
+            let coupling = &hessian_km * inverse;
+            let schur_hessian =
+                hessian.slice((0, 0), (kept, kept)) - &coupling * hessian_km.transpose();
+            let schur_gradient =
+                gradient.rows(0, kept) - coupling * gradient.rows(kept, marginalized);
+            let linearization = remaining.iter().map(|&pose| problem.poses[pose]).collect();
+            MarginalPrior::from_normal_equations(
+                remaining,
+                linearization,
+                (&schur_hessian + schur_hessian.transpose()) * 0.5,
+                &schur_gradient,
+            )
+        });
+
+        // Remove the terms which were summarized by the prior.
+        problem
+            .observations
+            .retain(|observation| !marginalized_landmarks[observation.landmark]);
+        problem
+            .depths
+            .retain(|factor| !marginalized_landmarks[factor.landmark]);
+        problem
+            .points_on_planes
+            .retain(|factor| !marginalized_landmarks[factor.landmark]);
+        problem
+            .cameras_above_planes
+            .retain(|factor| !dropped[factor.pose]);
+        problem.priors.retain(|prior| !dropped[prior.pose]);
+        problem.gnss.retain(|prior| !dropped[prior.pose]);
+        problem
+            .marginal_priors
+            .retain(|prior| !prior.poses.iter().any(|&pose| dropped[pose]));
+        problem.marginal_priors.extend(new_prior);
+        for (pose, &dropped) in dropped.iter().enumerate() {
+            if dropped {
+                problem.fix_pose(pose);
+            }
+        }
+    }
+}
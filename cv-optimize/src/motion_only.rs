@@ -0,0 +1,193 @@
+use crate::{
+    bundle_adjustment::update_pose, DenseLinearization, LeastSquaresProblem, LevenbergMarquardt,
+    RobustLoss, TrustRegion,
+};
+use cv_core::{
+    nalgebra::{DMatrix, DVector, Matrix3, Matrix3x6, Matrix6, Vector3, Vector6},
+    FeatureWorldMatch, Pose, Projective, WorldToCamera,
+};
+
+/// Refines a [`WorldToCamera`] pose with Levenberg-Marquardt while keeping the world points fixed
+/// (motion-only optimization).
+///
+/// This is the polish step after estimating a pose with a PnP solver and consensus. The residual of each match is
+/// the difference between the observed bearing and the bearing of the transformed world point, which is minimized
+/// under the [`RobustLoss`] using the se(3) Jacobians from [`Pose::transform_jacobian_self`].
+///
+/// ```
+/// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3}, *};
+/// use cv_optimize::{MotionOnly, RobustLoss};
+///
+/// let pose = WorldToCamera(IsometryMatrix3::from_parts(
+///     Vector3::new(0.1, -0.2, 0.3).into(),
+///     Rotation3::from_euler_angles(0.1, 0.2, -0.1),
+/// ));
+/// let matches: Vec<FeatureWorldMatch> = [
+///     Point3::new(0.5, 0.2, 3.0),
+///     Point3::new(-0.7, 0.4, 4.0),
+///     Point3::new(0.1, -0.6, 3.5),
+///     Point3::new(-0.3, -0.2, 5.0),
+///     Point3::new(0.8, 0.9, 4.5),
+/// ]
+/// .iter()
+/// .map(|&camera| {
+///     let camera = CameraPoint::from_point(camera);
+///     FeatureWorldMatch(camera.bearing(), pose.inverse().transform(camera))
+/// })
+/// .collect();
+///
+/// let perturbed = WorldToCamera(IsometryMatrix3::from_parts(
+///     Vector3::new(0.15, -0.25, 0.2).into(),
+///     Rotation3::from_euler_angles(0.12, 0.17, -0.08),
+/// ));
+/// let refined = MotionOnly::new().loss(RobustLoss::Huber(1e-2)).refine(perturbed, &matches);
+/// assert!(refined.0.rotation.rotation_to(&pose.0.rotation).angle() < 1e-6);
+/// assert!((refined.0.translation.vector - pose.0.translation.vector).norm() < 1e-6);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MotionOnly {
+    /// The robust loss applied to the norm of each bearing residual.
+    pub loss: RobustLoss,
+    /// The maximum number of accepted steps.
+    pub max_iterations: usize,
+    /// The initial damping of the Gauss-Newton step.
+    pub initial_lambda: f64,
+    /// Optimization stops when the norm of a step is below this.
+    pub tolerance: f64,
+}
+
+impl Default for MotionOnly {
+    fn default() -> Self {
+        Self {
+            loss: RobustLoss::Squared,
+            max_iterations: 50,
+            initial_lambda: 1e-4,
+            tolerance: 1e-12,
+        }
+    }
+}
+
+impl MotionOnly {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    #[must_use]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn initial_lambda(self, initial_lambda: f64) -> Self {
+        Self {
+            initial_lambda,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    /// The solver configured with the settings of the optimizer.
+    fn solver(&self) -> LevenbergMarquardt {
+        LevenbergMarquardt::new()
+            .trust_region(TrustRegion::Damping(self.initial_lambda))
+            .max_iterations(self.max_iterations)
+            .step_tolerance(self.tolerance)
+    }
+
+    /// Refines the pose to best fit the matches, which should already be filtered down to inliers.
+    pub fn refine(&self, pose: WorldToCamera, matches: &[FeatureWorldMatch]) -> WorldToCamera {
+        if matches.len() < 3 {
+            return pose;
+        }
+        let mut problem = MotionOnlyProblem {
+            loss: self.loss,
+            pose,
+            matches,
+        };
+        let report = self.solver().minimize(&mut problem);
+        log::info!(
+            "motion-only optimization finished after {} iterations with cost {}",
+            report.iterations,
+            report.final_cost
+        );
+        problem.pose
+    }
+}
+
+/// A pose being refined against fixed world points.
+#[derive(Copy, Clone)]
+struct MotionOnlyProblem<'a> {
+    loss: RobustLoss,
+    pose: WorldToCamera,
+    matches: &'a [FeatureWorldMatch],
+}
+
+impl MotionOnlyProblem<'_> {
+    /// The residual of a match and its Jacobian in respect to the pose in se(3).
+    fn residual_jacobian(
+        &self,
+        &FeatureWorldMatch(bearing, world): &FeatureWorldMatch,
+    ) -> (Vector3<f64>, Matrix3x6<f64>) {
+        let (camera, jacobian) = self.pose.transform_jacobian_self(world);
+        // Points at infinity or behind the world origin are in the direction of the sign of `w`.
+        let homogeneous = camera.homogeneous();
+        let sign = homogeneous.w.signum();
+        let point = homogeneous.xyz() * sign;
+        let norm = point.norm();
+        let direction = point / norm;
+        // The Jacobian of normalizing the point.
+        let normalize = (Matrix3::identity() - direction * direction.transpose()) / norm;
+        let jacobian = normalize * jacobian.fixed_rows::<3>(0) * sign;
+        (direction - bearing.into_inner(), jacobian)
+    }
+}
+
+impl LeastSquaresProblem for MotionOnlyProblem<'_> {
+    type Linearization = DenseLinearization;
+
+    fn cost(&self) -> f64 {
+        self.matches
+            .iter()
+            .map(|&FeatureWorldMatch(bearing, world)| {
+                let residual =
+                    self.pose.transform(world).bearing().into_inner() - bearing.into_inner();
+                self.loss.loss(residual.norm())
+            })
+            .sum()
+    }
+
+    fn linearize(&self) -> DenseLinearization {
+        // Build the normal equations with the iteratively reweighted least squares weights.
+        let mut hessian = Matrix6::zeros();
+        let mut gradient = Vector6::zeros();
+        for m in self.matches {
+            let (residual, jacobian) = self.residual_jacobian(m);
+            let weight = self.loss.weight(residual.norm());
+            hessian += weight * jacobian.transpose() * jacobian;
+            gradient += weight * jacobian.transpose() * residual;
+        }
+        DenseLinearization {
+            hessian: DMatrix::from_column_slice(6, 6, hessian.as_slice()),
+            gradient: DVector::from_column_slice(gradient.as_slice()),
+        }
+    }
+
+    fn retract(&self, step: &DVector<f64>) -> Self {
+        Self {
+            pose: update_pose(self.pose, Vector6::from_column_slice(step.as_slice())),
+            ..*self
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::RobustLoss;
+use cv_core::{
+    nalgebra::{Matrix1x6, Point3, Rotation3, RowVector3, Vector3},
+    Pose, WorldToCamera,
+};
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// A plane in the world, which contains the points `x` where `normal · x = distance`.
+///
+/// Planes are updated with a rotation of the normal in its tangent plane and a change of the distance through
+/// [`Plane::retract`], which has exactly three parameters.
+///
+/// ```
+/// use cv_core::nalgebra::{Point3, Vector3};
+/// use cv_optimize::Plane;
+///
+/// let ground = Plane::from_points(Point3::new(0.0, 1.0, 0.0), Point3::new(1.0, 1.0, 0.0), Point3::new(0.0, 1.0, 1.0)).unwrap();
+/// assert!((ground.normal.abs() - Vector3::y()).norm() < 1e-12);
+/// assert!((ground.signed_distance(Point3::new(3.0, 1.0, -2.0))).abs() < 1e-12);
+/// assert!((ground.signed_distance(Point3::new(3.0, 2.5, -2.0)).abs() - 1.5).abs() < 1e-12);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct Plane {
+    pub normal: Vector3<f64>,
+    pub distance: f64,
+}
+
+impl Plane {
+    /// Creates a plane from a normal and a distance, normalizing the normal.
+    pub fn new(normal: Vector3<f64>, distance: f64) -> Self {
+        let norm = normal.norm();
+        Self {
+            normal: normal / norm,
+            distance: distance / norm,
+        }
+    }
+
+    /// Creates the plane through three points, or returns `None` if they are collinear.
+    pub fn from_points(a: Point3<f64>, b: Point3<f64>, c: Point3<f64>) -> Option<Self> {
+        let normal = (b - a).cross(&(c - a));
+        (normal.norm() > 0.0).then(|| ())?;
+        let normal = normal.normalize();
+        Some(Self {
+            normal,
+            distance: normal.dot(&a.coords),
+        })
+    }
+
+    /// The signed distance of a point from the plane, which is positive on the side the normal points to.
+    pub fn signed_distance(&self, point: Point3<f64>) -> f64 {
+        self.normal.dot(&point.coords) - self.distance
+    }
+
+    /// Two unit vectors which form a right-handed basis with the normal.
+    fn tangents(&self) -> (Vector3<f64>, Vector3<f64>) {
+        let axis = if self.normal.x.abs() < 0.9 {
+            Vector3::x()
+        } else {
+            Vector3::y()
+        };
+        let first = axis.cross(&self.normal).normalize();
+        (first, self.normal.cross(&first))
+    }
+
+    /// Applies a step of the normal by the rotation `α t₁ + β t₂` about the tangents of the plane and of the
+    /// distance by `δ`, where the step is `(α, β, δ)`.
+    pub fn retract(&self, step: Vector3<f64>) -> Self {
+        let (first, second) = self.tangents();
+        let rotation = Rotation3::new(first * step.x + second * step.y);
+        Self::new(rotation * self.normal, self.distance + step.z)
+    }
+
+    /// The Jacobian of the signed distance of a point in respect to a step of [`Plane::retract`].
+    fn signed_distance_jacobian(&self, point: Point3<f64>) -> RowVector3<f64> {
+        let (first, second) = self.tangents();
+        // Rotating about the tangents moves the normal by `t₁ × n = -t₂` and `t₂ × n = t₁`.
+        RowVector3::new(-second.dot(&point.coords), first.dot(&point.coords), -1.0)
+    }
+}
+
+/// A factor which pulls a landmark of a [`BundleAdjustment`](crate::BundleAdjustment) onto a [`Plane`], such as
+/// the points detected on a wall or on the floor.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct PointOnPlane {
+    /// The index of the landmark.
+    pub landmark: usize,
+    /// The index of the plane.
+    pub plane: usize,
+    /// The inverse variance of the distance of the landmark from the plane.
+    pub information: f64,
+    /// The robust loss applied to the normalized distance, which lets points off of the plane be rejected.
+    pub loss: RobustLoss,
+}
+
+impl PointOnPlane {
+    pub fn new(landmark: usize, plane: usize, information: f64) -> Self {
+        Self {
+            landmark,
+            plane,
+            information,
+            loss: RobustLoss::Huber(3.0),
+        }
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    /// The distance of the landmark from the plane normalized by its standard deviation.
+    pub fn error(&self, plane: &Plane, point: Point3<f64>) -> f64 {
+        self.information.sqrt() * plane.signed_distance(point)
+    }
+
+    /// The error and its Jacobians in respect to the landmark and the plane.
+    pub(crate) fn linearize(
+        &self,
+        plane: &Plane,
+        point: Point3<f64>,
+    ) -> (f64, RowVector3<f64>, RowVector3<f64>) {
+        let root = self.information.sqrt();
+        (
+            self.error(plane, point),
+            root * plane.normal.transpose(),
+            root * plane.signed_distance_jacobian(point),
+        )
+    }
+}
+
+/// A factor which holds the camera of a pose at a height above a [`Plane`].
+///
+/// Cameras mounted on vehicles stay at a constant height above the road, so this constrains the vertical drift of
+/// visual odometry.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct CameraAbovePlane {
+    /// The index of the pose.
+    pub pose: usize,
+    /// The index of the plane.
+    pub plane: usize,
+    /// The signed distance of the camera from the plane.
+    pub height: f64,
+    /// The inverse variance of the height.
+    pub information: f64,
+    pub loss: RobustLoss,
+}
+
+impl CameraAbovePlane {
+    pub fn new(pose: usize, plane: usize, height: f64, information: f64) -> Self {
+        Self {
+            pose,
+            plane,
+            height,
+            information,
+            loss: RobustLoss::Squared,
+        }
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    /// The difference of the height of the camera from the expected height normalized by its standard deviation.
+    pub fn error(&self, plane: &Plane, pose: WorldToCamera) -> f64 {
+        let center = Point3::from(pose.inverse().isometry().translation.vector);
+        self.information.sqrt() * (plane.signed_distance(center) - self.height)
+    }
+
+    /// The error and its Jacobians in respect to the pose in se(3) and the plane.
+    pub(crate) fn linearize(
+        &self,
+        plane: &Plane,
+        pose: WorldToCamera,
+    ) -> (f64, Matrix1x6<f64>, RowVector3<f64>) {
+        let root = self.information.sqrt();
+        let isometry = pose.isometry();
+        let inverse_rotation = *isometry.rotation.inverse().matrix();
+        let center = Point3::from(-(inverse_rotation * isometry.translation.vector));
+        // The center is `-Rᵀt`, so a step `t ← t + δt`, `R ← exp(ω)R` moves it by `-Rᵀδt - Rᵀ[t]×ω`.
+        let normal = plane.normal.transpose() * inverse_rotation;
+        let mut jacobian_pose = Matrix1x6::zeros();
+        jacobian_pose
+            .fixed_columns_mut::<3>(0)
+            .copy_from(&(-root * normal));
+        jacobian_pose
+            .fixed_columns_mut::<3>(3)
+            .copy_from(&(-root * normal * isometry.translation.vector.cross_matrix()));
+        (
+            root * (plane.signed_distance(center) - self.height),
+            jacobian_pose,
+            root * plane.signed_distance_jacobian(center),
+        )
+    }
+}
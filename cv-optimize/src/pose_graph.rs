@@ -0,0 +1,326 @@
+use crate::{
+    bundle_adjustment::update_pose, DenseLinearization, LeastSquaresProblem, LevenbergMarquardt,
+    OptimizationReport, RobustLoss, TrustRegion,
+};
+use cv_core::{
+    nalgebra::{DVector, Matrix3, Matrix6, Vector3, Vector6},
+    CameraToCamera, Pose, WorldToCamera,
+};
+
+/// A relative pose constraint between two poses of a [`PoseGraph`], such as from odometry or a loop closure.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PoseGraphEdge {
+    /// The index of the pose the measurement starts from.
+    pub from: usize,
+    /// The index of the pose the measurement ends at.
+    pub to: usize,
+    /// The measured transformation from the camera of `from` to the camera of `to`.
+    pub measurement: CameraToCamera,
+    /// The inverse covariance of the measurement in se(3) (with translation components before so(3) components).
+    pub information: Matrix6<f64>,
+    /// The robust loss applied to the Mahalanobis norm of the error, which should be robust for loop closures
+    /// that might be wrong.
+    pub loss: RobustLoss,
+}
+
+impl PoseGraphEdge {
+    pub fn new(
+        from: usize,
+        to: usize,
+        measurement: CameraToCamera,
+        information: Matrix6<f64>,
+    ) -> Self {
+        Self {
+            from,
+            to,
+            measurement,
+            information,
+            loss: RobustLoss::Squared,
+        }
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    /// The error of the edge in se(3), which is zero when the poses agree with the measurement.
+    pub fn error(&self, poses: &[WorldToCamera]) -> Vector6<f64> {
+        let relative = poses[self.to].isometry() * poses[self.from].isometry().inverse();
+        CameraToCamera(self.measurement.isometry().inverse() * relative).se3()
+    }
+
+    /// The error and its Jacobians in respect to the `from` and `to` poses in se(3).
+    pub(crate) fn linearize(
+        &self,
+        poses: &[WorldToCamera],
+    ) -> (Vector6<f64>, Matrix6<f64>, Matrix6<f64>) {
+        let from = poses[self.from].isometry();
+        let relative = poses[self.to].isometry() * from.inverse();
+        let error = self.error(poses);
+        let rotation = *relative.rotation.matrix();
+        let measurement_inverse = *self.measurement.isometry().rotation.matrix();
+        let measurement_inverse = measurement_inverse.transpose();
+        let left_jacobian_inverse =
+            so3_left_jacobian_inverse(Vector3::new(error[3], error[4], error[5]));
+
+        let mut jacobian_from = Matrix6::zeros();
+        jacobian_from
+            .fixed_slice_mut::<3, 3>(0, 0)
+            .copy_from(&(-measurement_inverse * rotation));
+        jacobian_from
+            .fixed_slice_mut::<3, 3>(0, 3)
+            .copy_from(&(-measurement_inverse * rotation * from.translation.vector.cross_matrix()));
+        jacobian_from
+            .fixed_slice_mut::<3, 3>(3, 3)
+            .copy_from(&(-left_jacobian_inverse * measurement_inverse * rotation));
+
+        let mut jacobian_to = Matrix6::zeros();
+        jacobian_to
+            .fixed_slice_mut::<3, 3>(0, 0)
+            .copy_from(&measurement_inverse);
+        jacobian_to.fixed_slice_mut::<3, 3>(0, 3).copy_from(
+            &(measurement_inverse * (rotation * from.translation.vector).cross_matrix()),
+        );
+        jacobian_to
+            .fixed_slice_mut::<3, 3>(3, 3)
+            .copy_from(&(left_jacobian_inverse * measurement_inverse));
+        (error, jacobian_from, jacobian_to)
+    }
+
+    /// The Mahalanobis norm of the error.
+    pub(crate) fn mahalanobis(&self, error: Vector6<f64>) -> f64 {
+        error.dot(&(self.information * error)).max(0.0).sqrt()
+    }
+}
+
+/// A graph of poses connected by relative pose measurements.
+///
+/// Pose graph optimization distributes the drift accumulated by odometry over the whole trajectory when a loop
+/// closure is found, without the cost of optimizing the landmarks as in bundle adjustment.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct PoseGraph {
+    pub poses: Vec<WorldToCamera>,
+    pub edges: Vec<PoseGraphEdge>,
+    /// Poses with `true` at their index are held fixed. If no pose is fixed, the first pose is held fixed.
+    pub fixed_poses: Vec<bool>,
+}
+
+impl PoseGraph {
+    pub fn new(poses: Vec<WorldToCamera>) -> Self {
+        Self {
+            fixed_poses: vec![false; poses.len()],
+            poses,
+            edges: vec![],
+        }
+    }
+
+    /// Adds a relative pose measurement.
+    pub fn connect(&mut self, edge: PoseGraphEdge) {
+        self.edges.push(edge);
+    }
+
+    /// Holds a pose fixed during the optimization.
+    pub fn fix_pose(&mut self, pose: usize) {
+        self.fixed_poses.resize(self.poses.len(), false);
+        self.fixed_poses[pose] = true;
+    }
+
+    fn is_fixed(&self, pose: usize) -> bool {
+        if self.fixed_poses.iter().any(|&fixed| fixed) {
+            self.fixed_poses.get(pose).copied().unwrap_or(false)
+        } else {
+            pose == 0
+        }
+    }
+
+    /// Assigns each free pose a block of six parameters, returning the blocks and the number of free poses.
+    fn free_blocks(&self) -> (Vec<Option<usize>>, usize) {
+        let mut free = vec![None; self.poses.len()];
+        let mut blocks = 0;
+        for (ix, block) in free.iter_mut().enumerate() {
+            if !self.is_fixed(ix) {
+                *block = Some(blocks);
+                blocks += 1;
+            }
+        }
+        (free, blocks)
+    }
+}
+
+/// The parameters are the se(3) steps of the free poses in order.
+impl LeastSquaresProblem for PoseGraph {
+    type Linearization = DenseLinearization;
+
+    fn cost(&self) -> f64 {
+        self.edges
+            .iter()
+            .map(|edge| edge.loss.loss(edge.mahalanobis(edge.error(&self.poses))))
+            .sum()
+    }
+
+    fn linearize(&self) -> DenseLinearization {
+        let (free, blocks) = self.free_blocks();
+        let mut normal = DenseLinearization::zeros(6 * blocks);
+        for edge in &self.edges {
+            let (error, jacobian_from, jacobian_to) = edge.linearize(&self.poses);
+            let weight = edge.loss.weight(edge.mahalanobis(error));
+            let information = weight * edge.information;
+            let jacobians = [(edge.from, jacobian_from), (edge.to, jacobian_to)];
+            for &(a, jacobian_a) in &jacobians {
+                let block_a = match free[a] {
+                    Some(block) => block,
+                    None => continue,
+                };
+                let mut gradient_block = normal.gradient.fixed_rows_mut::<6>(6 * block_a);
+                gradient_block += jacobian_a.transpose() * information * error;
+                for &(b, jacobian_b) in &jacobians {
+                    if let Some(block_b) = free[b] {
+                        let mut hessian_block = normal
+                            .hessian
+                            .fixed_slice_mut::<6, 6>(6 * block_a, 6 * block_b);
+                        hessian_block += jacobian_a.transpose() * information * jacobian_b;
+                    }
+                }
+            }
+        }
+        normal
+    }
+
+    fn retract(&self, step: &DVector<f64>) -> Self {
+        let (free, _) = self.free_blocks();
+        let mut graph = self.clone();
+        for (pose, block) in graph.poses.iter_mut().zip(&free) {
+            if let Some(block) = *block {
+                *pose = update_pose(*pose, step.fixed_rows::<6>(6 * block).into_owned());
+            }
+        }
+        graph
+    }
+}
+
+/// Optimizes a [`PoseGraph`] with Levenberg-Marquardt.
+///
+/// ```
+/// use cv_core::{nalgebra::{IsometryMatrix3, Matrix6, Rotation3, Vector3}, *};
+/// use cv_optimize::{PoseGraph, PoseGraphEdge, PoseGraphOptimizer, RobustLoss};
+///
+/// // Poses around a circle.
+/// let truth: Vec<WorldToCamera> = (0..8)
+///     .map(|i| {
+///         let angle = i as f64 * std::f64::consts::PI / 4.0;
+///         CameraToWorld(IsometryMatrix3::from_parts(
+///             Vector3::new(angle.cos(), 0.0, angle.sin()).into(),
+///             Rotation3::from_euler_angles(0.0, -angle, 0.0),
+///         )).inverse()
+///     })
+///     .collect();
+/// let relative = |a: usize, b: usize| CameraToCamera(truth[b].isometry() * truth[a].isometry().inverse());
+///
+/// // Odometry drifts, so integrate a slightly wrong measurement and then close the loop.
+/// let mut drift = Vec::new();
+/// let mut pose = truth[0];
+/// for i in 0..8 {
+///     drift.push(pose);
+///     let odometry = relative(i, (i + 1) % 8).isometry() * IsometryMatrix3::translation(0.01, 0.0, 0.0);
+///     pose = WorldToCamera(odometry * pose.isometry());
+/// }
+/// let mut graph = PoseGraph::new(drift);
+/// for i in 0..7 {
+///     graph.connect(PoseGraphEdge::new(i, i + 1, relative(i, i + 1), Matrix6::identity()));
+/// }
+/// graph.connect(PoseGraphEdge::new(7, 0, relative(7, 0), Matrix6::identity()).loss(RobustLoss::Huber(0.1)));
+///
+/// PoseGraphOptimizer::new().optimize(&mut graph);
+/// for (optimized, truth) in graph.poses.iter().zip(&truth) {
+///     assert!((optimized.isometry().translation.vector - truth.isometry().translation.vector).norm() < 1e-6);
+/// }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PoseGraphOptimizer {
+    /// The maximum number of accepted steps.
+    pub max_iterations: usize,
+    /// The initial damping of the Gauss-Newton step.
+    pub initial_lambda: f64,
+    /// Optimization stops when the norm of a step is below this.
+    pub tolerance: f64,
+}
+
+impl Default for PoseGraphOptimizer {
+    fn default() -> Self {
+        Self {
+            max_iterations: 100,
+            initial_lambda: 1e-4,
+            tolerance: 1e-12,
+        }
+    }
+}
+
+impl PoseGraphOptimizer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn initial_lambda(self, initial_lambda: f64) -> Self {
+        Self {
+            initial_lambda,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    /// The total robust loss of the graph.
+    pub fn cost(&self, graph: &PoseGraph) -> f64 {
+        graph.cost()
+    }
+
+    /// Optimizes the graph in place.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "pose_graph_optimization", skip_all, fields(poses = graph.poses.len(), edges = graph.edges.len(), iterations, initial_cost, final_cost))
+    )]
+    pub fn optimize(&self, graph: &mut PoseGraph) -> OptimizationReport {
+        let report = LevenbergMarquardt::new()
+            .trust_region(TrustRegion::Damping(self.initial_lambda))
+            .max_iterations(self.max_iterations)
+            .step_tolerance(self.tolerance)
+            .minimize(graph);
+        log::info!(
+            "pose graph optimization finished after {} iterations with cost {}",
+            report.iterations,
+            report.final_cost
+        );
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("iterations", &report.iterations);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("initial_cost", &report.initial_cost);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("final_cost", &report.final_cost);
+        report
+    }
+}
+
+/// The inverse of the left Jacobian of SO(3), which maps a rotation applied on the left of `exp(φ)` to the change
+/// in `φ`.
+pub(crate) fn so3_left_jacobian_inverse(phi: Vector3<f64>) -> Matrix3<f64> {
+    let theta = phi.norm();
+    let hat = phi.cross_matrix();
+    if theta < 1e-6 {
+        return Matrix3::identity() - 0.5 * hat + hat * hat / 12.0;
+    }
+    let coefficient = 1.0 / (theta * theta) - (1.0 + theta.cos()) / (2.0 * theta * theta.sin());
+    Matrix3::identity() - 0.5 * hat + coefficient * hat * hat
+}
@@ -0,0 +1,176 @@
+use crate::{BundleAdjuster, BundleAdjustment, IntrinsicsAdjustment, OptimizationReport};
+use cv_core::ParametricCameraModel;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// The magic bytes at the start of a saved [`Recording`].
+const RECORDING_MAGIC: [u8; 4] = *b"CVOR";
+/// The version of the saved [`Recording`] format.
+const RECORDING_VERSION: u32 = 1;
+
+/// A recording of an optimization, with the adjuster settings, the problem before it was optimized, and the report
+/// of the original run.
+///
+/// Recordings are saved in a versioned binary format, so that a problem which fails to converge can be sent along
+/// with a bug report and replayed offline, or kept as a regression test.
+///
+/// ```
+/// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3}, *};
+/// use cv_optimize::{BundleAdjuster, BundleAdjustment, Observation, Recording};
+///
+/// let poses: Vec<WorldToCamera> = (0..3)
+///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+///         Vector3::new(-0.3 * i as f64, 0.0, 0.0).into(),
+///         Rotation3::identity(),
+///     )))
+///     .collect();
+/// let landmarks: Vec<WorldPoint> = (0..20)
+///     .map(|i| WorldPoint::from_point(Point3::new((i % 5) as f64 * 0.4 - 0.8, (i / 5) as f64 * 0.3 - 0.5, 4.0)))
+///     .collect();
+/// let mut problem = BundleAdjustment::new(poses.clone(), landmarks.clone());
+/// problem.fix_pose(0);
+/// problem.fix_pose(1);
+/// problem.poses[2].0.translation.vector.y += 0.05;
+/// for (p, pose) in poses.iter().enumerate() {
+///     for (l, &landmark) in landmarks.iter().enumerate() {
+///         problem.observe(Observation::new(p, l, pose.transform(landmark).bearing()));
+///     }
+/// }
+///
+/// let recording = BundleAdjuster::new().record(&mut problem);
+/// let mut file = vec![];
+/// recording.save(&mut file).unwrap();
+///
+/// let loaded: Recording<BundleAdjustment> = Recording::load(file.as_slice()).unwrap();
+/// assert_eq!(loaded, recording);
+/// let (replayed, report) = loaded.replay();
+/// let original = recording.report.unwrap();
+/// assert_eq!(report.iterations, original.iterations);
+/// assert!((report.final_cost - original.final_cost).abs() < 1e-12);
+/// assert!((replayed.poses[2].0.translation.vector - problem.poses[2].0.translation.vector).norm() < 1e-9);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recording<P> {
+    pub adjuster: BundleAdjuster,
+    /// The problem before it was optimized.
+    pub problem: P,
+    /// The report of the original run, if it was recorded from one.
+    pub report: Option<OptimizationReport>,
+}
+
+impl<P> Recording<P> {
+    /// Creates a recording of a problem which has not been optimized yet.
+    pub fn new(adjuster: BundleAdjuster, problem: P) -> Self {
+        Self {
+            adjuster,
+            problem,
+            report: None,
+        }
+    }
+
+    /// Saves the recording to a writer in a versioned binary format.
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()>
+    where
+        P: Serialize,
+    {
+        writer.write_all(&RECORDING_MAGIC)?;
+        writer.write_all(&RECORDING_VERSION.to_le_bytes())?;
+        bincode::serialize_into(writer, self).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+
+    /// Loads a recording saved with [`Recording::save`].
+    pub fn load(mut reader: impl Read) -> io::Result<Self>
+    where
+        P: DeserializeOwned,
+    {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != RECORDING_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an optimization recording",
+            ));
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != RECORDING_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported optimization recording version {}", version),
+            ));
+        }
+        bincode::deserialize_from(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Saves the recording to a file.
+    pub fn save_path(&self, path: impl AsRef<Path>) -> io::Result<()>
+    where
+        P: Serialize,
+    {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads the recording from a file.
+    pub fn load_path(path: impl AsRef<Path>) -> io::Result<Self>
+    where
+        P: DeserializeOwned,
+    {
+        Self::load(BufReader::new(File::open(path)?))
+    }
+}
+
+impl Recording<BundleAdjustment> {
+    /// Optimizes the recorded problem again with the recorded adjuster, returning the optimized problem and the
+    /// report.
+    ///
+    /// Replays match the original run, except for rounding differences from the order of parallel sums with the
+    /// `rayon` feature.
+    pub fn replay(&self) -> (BundleAdjustment, OptimizationReport) {
+        let mut problem = self.problem.clone();
+        let report = self.adjuster.optimize(&mut problem);
+        (problem, report)
+    }
+}
+
+impl<C> Recording<IntrinsicsAdjustment<C>>
+where
+    C: ParametricCameraModel + Clone,
+{
+    /// Optimizes the recorded problem again with the recorded adjuster, returning the optimized problem and the
+    /// report.
+    pub fn replay(&self) -> (IntrinsicsAdjustment<C>, OptimizationReport) {
+        let mut problem = self.problem.clone();
+        let report = self.adjuster.optimize_intrinsics(&mut problem);
+        (problem, report)
+    }
+}
+
+impl BundleAdjuster {
+    /// Optimizes the problem in place like [`BundleAdjuster::optimize`], returning a [`Recording`] of the run.
+    pub fn record(&self, problem: &mut BundleAdjustment) -> Recording<BundleAdjustment> {
+        let mut recording = Recording::new(*self, problem.clone());
+        recording.report = Some(self.optimize(problem));
+        recording
+    }
+
+    /// Optimizes the problem in place like [`BundleAdjuster::optimize_intrinsics`], returning a [`Recording`] of
+    /// the run.
+    pub fn record_intrinsics<C>(
+        &self,
+        problem: &mut IntrinsicsAdjustment<C>,
+    ) -> Recording<IntrinsicsAdjustment<C>>
+    where
+        C: ParametricCameraModel + Clone,
+    {
+        let mut recording = Recording::new(*self, problem.clone());
+        recording.report = Some(self.optimize_intrinsics(problem));
+        recording
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// A robust loss function, which reduces the influence of outliers on an optimization.
+///
+/// Each loss is a function `ρ` of the norm `r` of a residual. Optimizers minimize `ρ(r)` with iteratively
+/// reweighted least squares, where each residual is weighted by [`RobustLoss::weight`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum RobustLoss {
+    /// The squared loss `r²`, which is not robust to outliers at all.
+    Squared,
+    /// The Huber loss, which is quadratic below the scale and linear above it.
+    Huber(f64),
+    /// The Cauchy loss, which is quadratic below the scale and logarithmic above it, so it almost ignores outliers.
+    Cauchy(f64),
+}
+
+impl Default for RobustLoss {
+    fn default() -> Self {
+        Self::Squared
+    }
+}
+
+impl RobustLoss {
+    /// The loss of a residual with the norm `r`.
+    pub fn loss(self, r: f64) -> f64 {
+        let r = r.abs();
+        match self {
+            Self::Squared => r * r,
+            Self::Huber(scale) => {
+                if r <= scale {
+                    r * r
+                } else {
+                    2.0 * scale * r - scale * scale
+                }
+            }
+            Self::Cauchy(scale) => scale * scale * (r * r / (scale * scale)).ln_1p(),
+        }
+    }
+
+    /// The weight of a residual with the norm `r` in iteratively reweighted least squares.
+    ///
+    /// This is `ρ'(r²)`, which is `1.0` for residuals that are treated as inliers and decreases for outliers.
+    pub fn weight(self, r: f64) -> f64 {
+        let r = r.abs();
+        match self {
+            Self::Squared => 1.0,
+            Self::Huber(scale) => {
+                if r <= scale {
+                    1.0
+                } else {
+                    scale / r
+                }
+            }
+            Self::Cauchy(scale) => 1.0 / (1.0 + r * r / (scale * scale)),
+        }
+    }
+}
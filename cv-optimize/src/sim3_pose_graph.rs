@@ -0,0 +1,277 @@
+use crate::{
+    DenseLinearization, LeastSquaresProblem, LevenbergMarquardt, OptimizationReport,
+    PoseGraphOptimizer, RobustLoss, TrustRegion,
+};
+use cv_core::{
+    nalgebra::{DVector, SMatrix, SVector},
+    Sim3, WorldPoint, WorldToCamera,
+};
+
+/// A relative similarity constraint between two poses of a [`Sim3PoseGraph`].
+///
+/// Odometry edges have a scale of `1.0` (the relative pose of the two keyframes), while loop closure edges carry
+/// the scale drift that accumulated around the loop, such as from a [`Sim3`] estimated between the map points of
+/// the two keyframes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sim3Edge {
+    /// The index of the pose the measurement starts from.
+    pub from: usize,
+    /// The index of the pose the measurement ends at.
+    pub to: usize,
+    /// The measured similarity from the camera of `from` to the camera of `to`.
+    pub measurement: Sim3,
+    /// The inverse covariance of the measurement in sim(3) (see [`Sim3::sim3`]).
+    pub information: SMatrix<f64, 7, 7>,
+    /// The robust loss applied to the Mahalanobis norm of the error.
+    pub loss: RobustLoss,
+}
+
+impl Sim3Edge {
+    pub fn new(from: usize, to: usize, measurement: Sim3, information: SMatrix<f64, 7, 7>) -> Self {
+        Self {
+            from,
+            to,
+            measurement,
+            information,
+            loss: RobustLoss::Squared,
+        }
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    /// The error of the edge in sim(3), which is zero when the poses agree with the measurement.
+    pub fn error(&self, poses: &[Sim3]) -> SVector<f64, 7> {
+        self.error_between(poses[self.from], poses[self.to])
+    }
+
+    fn error_between(&self, from: Sim3, to: Sim3) -> SVector<f64, 7> {
+        (self.measurement.inverse() * to * from.inverse()).sim3()
+    }
+
+    /// The error and its Jacobians in respect to the `from` and `to` poses in sim(3).
+    ///
+    /// The Jacobians of the similarity logarithm are lengthy, so as in ORB-SLAM they are computed with central
+    /// differences.
+    fn linearize(
+        &self,
+        poses: &[Sim3],
+    ) -> (SVector<f64, 7>, SMatrix<f64, 7, 7>, SMatrix<f64, 7, 7>) {
+        const EPSILON: f64 = 1e-6;
+        let (from, to) = (poses[self.from], poses[self.to]);
+        let mut jacobian_from = SMatrix::<f64, 7, 7>::zeros();
+        let mut jacobian_to = SMatrix::<f64, 7, 7>::zeros();
+        for i in 0..7 {
+            let mut step = SVector::<f64, 7>::zeros();
+            step[i] = EPSILON;
+            let (plus, minus) = (Sim3::from_sim3(step), Sim3::from_sim3(-step));
+            jacobian_from.set_column(
+                i,
+                &((self.error_between(plus * from, to) - self.error_between(minus * from, to))
+                    / (2.0 * EPSILON)),
+            );
+            jacobian_to.set_column(
+                i,
+                &((self.error_between(from, plus * to) - self.error_between(from, minus * to))
+                    / (2.0 * EPSILON)),
+            );
+        }
+        (self.error_between(from, to), jacobian_from, jacobian_to)
+    }
+
+    /// The Mahalanobis norm of the error.
+    fn mahalanobis(&self, error: SVector<f64, 7>) -> f64 {
+        error.dot(&(self.information * error)).max(0.0).sqrt()
+    }
+}
+
+/// A graph of similarities from the world to each keyframe, connected by relative similarity measurements.
+///
+/// This corrects the scale drift of monocular SLAM when a loop is closed, as in ORB-SLAM. Each keyframe starts as
+/// [`Sim3::from_pose`] of its [`WorldToCamera`] pose, the loop closure edge contains the scale drift, and after
+/// optimization [`Sim3PoseGraph::pose`] gives the corrected rigid poses while [`Sim3PoseGraph::correct_point`]
+/// moves the map points observed by each keyframe along with it.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Sim3PoseGraph {
+    pub poses: Vec<Sim3>,
+    pub edges: Vec<Sim3Edge>,
+    /// Poses with `true` at their index are held fixed. If no pose is fixed, the first pose is held fixed.
+    pub fixed_poses: Vec<bool>,
+}
+
+impl Sim3PoseGraph {
+    pub fn new(poses: Vec<Sim3>) -> Self {
+        Self {
+            fixed_poses: vec![false; poses.len()],
+            poses,
+            edges: vec![],
+        }
+    }
+
+    /// Creates a graph from the rigid poses of keyframes with a scale of `1.0`.
+    pub fn from_poses(poses: &[WorldToCamera]) -> Self {
+        Self::new(poses.iter().copied().map(Sim3::from_pose).collect())
+    }
+
+    /// Adds a relative similarity measurement.
+    pub fn connect(&mut self, edge: Sim3Edge) {
+        self.edges.push(edge);
+    }
+
+    /// Adds an edge with a scale of `1.0` between two poses from their current relative pose.
+    pub fn connect_odometry(&mut self, from: usize, to: usize, information: SMatrix<f64, 7, 7>) {
+        let measurement = self.poses[to] * self.poses[from].inverse();
+        self.connect(Sim3Edge::new(from, to, measurement, information));
+    }
+
+    /// Holds a pose fixed during the optimization.
+    pub fn fix_pose(&mut self, pose: usize) {
+        self.fixed_poses.resize(self.poses.len(), false);
+        self.fixed_poses[pose] = true;
+    }
+
+    /// The rigid pose of a keyframe with the scale removed.
+    pub fn pose(&self, pose: usize) -> WorldToCamera {
+        WorldToCamera(self.poses[pose].isometry())
+    }
+
+    /// Moves a point that was observed by a keyframe with the `original` pose before the optimization so that it
+    /// stays in the same place relative to the corrected keyframe.
+    pub fn correct_point(
+        &self,
+        pose: usize,
+        original: WorldToCamera,
+        point: WorldPoint,
+    ) -> WorldPoint {
+        let correction = self.poses[pose].inverse() * Sim3::from_pose(original);
+        correction.transform(point)
+    }
+
+    fn is_fixed(&self, pose: usize) -> bool {
+        if self.fixed_poses.iter().any(|&fixed| fixed) {
+            self.fixed_poses.get(pose).copied().unwrap_or(false)
+        } else {
+            pose == 0
+        }
+    }
+
+    /// Assigns each free pose a block of seven parameters, returning the blocks and the number of free poses.
+    fn free_blocks(&self) -> (Vec<Option<usize>>, usize) {
+        let mut free = vec![None; self.poses.len()];
+        let mut blocks = 0;
+        for (ix, block) in free.iter_mut().enumerate() {
+            if !self.is_fixed(ix) {
+                *block = Some(blocks);
+                blocks += 1;
+            }
+        }
+        (free, blocks)
+    }
+}
+
+/// The parameters are the sim(3) steps of the free poses in order, which are applied on the left of each pose.
+impl LeastSquaresProblem for Sim3PoseGraph {
+    type Linearization = DenseLinearization;
+
+    fn cost(&self) -> f64 {
+        self.edges
+            .iter()
+            .map(|edge| edge.loss.loss(edge.mahalanobis(edge.error(&self.poses))))
+            .sum()
+    }
+
+    fn linearize(&self) -> DenseLinearization {
+        let (free, blocks) = self.free_blocks();
+        let mut normal = DenseLinearization::zeros(7 * blocks);
+        for edge in &self.edges {
+            let (error, jacobian_from, jacobian_to) = edge.linearize(&self.poses);
+            let weight = edge.loss.weight(edge.mahalanobis(error));
+            let information = weight * edge.information;
+            let jacobians = [(edge.from, jacobian_from), (edge.to, jacobian_to)];
+            for &(a, jacobian_a) in &jacobians {
+                let block_a = match free[a] {
+                    Some(block) => block,
+                    None => continue,
+                };
+                let mut gradient_block = normal.gradient.fixed_rows_mut::<7>(7 * block_a);
+                gradient_block += jacobian_a.transpose() * information * error;
+                for &(b, jacobian_b) in &jacobians {
+                    if let Some(block_b) = free[b] {
+                        let mut hessian_block = normal
+                            .hessian
+                            .fixed_slice_mut::<7, 7>(7 * block_a, 7 * block_b);
+                        hessian_block += jacobian_a.transpose() * information * jacobian_b;
+                    }
+                }
+            }
+        }
+        normal
+    }
+
+    fn retract(&self, step: &DVector<f64>) -> Self {
+        let (free, _) = self.free_blocks();
+        let mut graph = self.clone();
+        for (pose, block) in graph.poses.iter_mut().zip(&free) {
+            if let Some(block) = *block {
+                *pose = Sim3::from_sim3(step.fixed_rows::<7>(7 * block).into_owned()) * *pose;
+            }
+        }
+        graph
+    }
+}
+
+impl PoseGraphOptimizer {
+    /// Optimizes a [`Sim3PoseGraph`] in place.
+    ///
+    /// ```
+    /// use cv_core::{nalgebra::{IsometryMatrix3, Rotation3, SMatrix, Vector3}, *};
+    /// use cv_optimize::{PoseGraphOptimizer, Sim3Edge, Sim3PoseGraph};
+    ///
+    /// // Keyframes around a circle.
+    /// let poses: Vec<WorldToCamera> = (0..8)
+    ///     .map(|i| {
+    ///         let angle = i as f64 * std::f64::consts::PI / 4.0;
+    ///         CameraToWorld(IsometryMatrix3::from_parts(
+    ///             Vector3::new(angle.cos(), 0.0, angle.sin()).into(),
+    ///             Rotation3::from_euler_angles(0.0, -angle, 0.0),
+    ///         )).inverse()
+    ///     })
+    ///     .collect();
+    /// // The similarities the measurements agree with, where the scale changes by 3% at each keyframe.
+    /// let expected: Vec<Sim3> = poses
+    ///     .iter()
+    ///     .enumerate()
+    ///     .map(|(i, pose)| {
+    ///         let isometry = pose.isometry();
+    ///         Sim3::from_parts(isometry.translation.vector, isometry.rotation, 1.03f64.powi(i as i32))
+    ///     })
+    ///     .collect();
+    ///
+    /// // Start from the rigid poses with a scale of `1.0`.
+    /// let mut graph = Sim3PoseGraph::from_poses(&poses);
+    /// for i in 0..7 {
+    ///     graph.connect(Sim3Edge::new(i, i + 1, expected[i + 1] * expected[i].inverse(), SMatrix::identity()));
+    /// }
+    /// graph.connect(Sim3Edge::new(7, 0, expected[0] * expected[7].inverse(), SMatrix::identity()));
+    ///
+    /// PoseGraphOptimizer::new().optimize_sim3(&mut graph);
+    /// for (i, expected) in expected.iter().enumerate() {
+    ///     assert!((graph.poses[i].sim3() - expected.sim3()).norm() < 1e-6);
+    /// }
+    /// ```
+    pub fn optimize_sim3(&self, graph: &mut Sim3PoseGraph) -> OptimizationReport {
+        let report = LevenbergMarquardt::new()
+            .trust_region(TrustRegion::Damping(self.initial_lambda))
+            .max_iterations(self.max_iterations)
+            .step_tolerance(self.tolerance)
+            .minimize(graph);
+        log::info!(
+            "sim3 pose graph optimization finished after {} iterations with cost {}",
+            report.iterations,
+            report.final_cost
+        );
+        report
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{
+    BundleAdjuster, BundleAdjustment, CameraAbovePlane, DepthObservation, GnssPrior,
+    LineObservation, MarginalPrior, Observation, OptimizationReport, PointOnPlane, PosePrior,
+};
+
+/// Optimizes the most recent keyframes of a [`BundleAdjustment`] and the landmarks they observe, which keeps the
+/// cost of each optimization bounded for real-time visual odometry.
+///
+/// The poses of the problem must be in the order the keyframes were created. The last `window` poses are
+/// optimized along with every landmark they observe, while older poses which also observe those landmarks are
+/// held fixed so that they anchor the window to the rest of the map, as in the local bundle adjustment of
+/// ORB-SLAM. Alternatively, poses which leave the window can be marginalized into a
+/// [`MarginalPrior`] with [`SlidingWindow::marginalize`], which keeps their information.
+///
+/// ```
+/// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3}, *};
+/// use cv_optimize::{BundleAdjustment, Observation, SlidingWindow};
+///
+/// let poses: Vec<WorldToCamera> = (0..6)
+///     .map(|i| WorldToCamera(IsometryMatrix3::from_parts(
+///         Vector3::new(-0.2 * i as f64, 0.0, 0.0).into(),
+///         Rotation3::identity(),
+///     )))
+///     .collect();
+/// let landmarks: Vec<WorldPoint> = (0..30)
+///     .map(|i| WorldPoint::from_point(Point3::new((i % 6) as f64 * 0.3 - 0.5, (i / 6) as f64 * 0.3 - 0.6, 4.0 + (i % 4) as f64)))
+///     .collect();
+///
+/// let mut problem = BundleAdjustment::new(poses.clone(), landmarks.clone());
+/// problem.poses[5].0.translation.vector.y += 0.02;
+/// for (p, pose) in poses.iter().enumerate() {
+///     for (l, &landmark) in landmarks.iter().enumerate() {
+///         problem.observe(Observation::new(p, l, pose.transform(landmark).bearing()));
+///     }
+/// }
+///
+/// SlidingWindow::new().window(2).optimize(&mut problem);
+/// assert!((problem.poses[5].0.translation.vector - poses[5].0.translation.vector).norm() < 1e-6);
+/// // Poses outside of the window are never changed.
+/// assert_eq!(problem.poses[0], poses[0]);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SlidingWindow {
+    /// The adjuster used to optimize the window.
+    pub adjuster: BundleAdjuster,
+    /// The number of most recent poses which are optimized.
+    pub window: usize,
+    /// Marginalize poses which are older than the window instead of holding them fixed.
+    pub marginalize: bool,
+}
+
+impl Default for SlidingWindow {
+    fn default() -> Self {
+        Self {
+            adjuster: BundleAdjuster::new(),
+            window: 10,
+            marginalize: false,
+        }
+    }
+}
+
+/// The part of a [`BundleAdjustment`] that a [`SlidingWindow`] optimizes, which has every plane of the full
+/// problem at the same index.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct LocalProblem {
+    /// The local problem, where poses outside of the window are fixed.
+    pub problem: BundleAdjustment,
+    /// The index of each local pose in the full problem.
+    pub poses: Vec<usize>,
+    /// The index of each local landmark in the full problem.
+    pub landmarks: Vec<usize>,
+    /// The index of each local line in the full problem.
+    pub lines: Vec<usize>,
+}
+
+impl LocalProblem {
+    /// Writes the optimized poses and landmarks back into the full problem.
+    pub fn write_back(&self, problem: &mut BundleAdjustment) {
+        for (&global, &pose) in self.poses.iter().zip(&self.problem.poses) {
+            problem.poses[global] = pose;
+        }
+        for (&global, &landmark) in self.landmarks.iter().zip(&self.problem.landmarks) {
+            problem.landmarks[global] = landmark;
+        }
+        for (&global, &line) in self.lines.iter().zip(&self.problem.lines) {
+            problem.lines[global] = line;
+        }
+        problem.planes.clone_from(&self.problem.planes);
+    }
+}
+
+impl SlidingWindow {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn adjuster(self, adjuster: BundleAdjuster) -> Self {
+        Self { adjuster, ..self }
+    }
+
+    /// Set the number of most recent poses which are optimized.
+    ///
+    /// Default is `10`.
+    #[must_use]
+    pub fn window(self, window: usize) -> Self {
+        Self { window, ..self }
+    }
+
+    /// Set whether poses which are older than the window are marginalized (see [`BundleAdjuster::marginalize`])
+    /// instead of held fixed.
+    ///
+    /// Default is `false`.
+    #[must_use]
+    pub fn marginalize(self, marginalize: bool) -> Self {
+        Self {
+            marginalize,
+            ..self
+        }
+    }
+
+    /// The index of the first pose in the window.
+    pub fn start(&self, problem: &BundleAdjustment) -> usize {
+        problem.poses.len().saturating_sub(self.window)
+    }
+
+    /// Extracts the window and the landmarks it observes from the full problem.
+    pub fn local_problem(&self, problem: &BundleAdjustment) -> LocalProblem {
+        let start = self.start(problem);
+
+        // Find the landmarks observed in the window.
+        let mut landmark_map = vec![None; problem.landmarks.len()];
+        let mut landmarks = vec![];
+        for observation in &problem.observations {
+            if observation.pose >= start && landmark_map[observation.landmark].is_none() {
+                landmark_map[observation.landmark] = Some(landmarks.len());
+                landmarks.push(observation.landmark);
+            }
+        }
+
+        let mut line_map = vec![None; problem.lines.len()];
+        let mut lines = vec![];
+        for observation in &problem.line_observations {
+            if observation.pose >= start && line_map[observation.line].is_none() {
+                line_map[observation.line] = Some(lines.len());
+                lines.push(observation.line);
+            }
+        }
+
+        // Find the poses that observe them, which includes all poses in the window that observe anything.
+        let mut pose_map = vec![None; problem.poses.len()];
+        let mut poses = vec![];
+        let observing = problem
+            .observations
+            .iter()
+            .filter(|observation| landmark_map[observation.landmark].is_some())
+            .map(|observation| observation.pose)
+            .chain(
+                problem
+                    .line_observations
+                    .iter()
+                    .filter(|observation| line_map[observation.line].is_some())
+                    .map(|observation| observation.pose),
+            );
+        for pose in observing {
+            if pose_map[pose].is_none() {
+                pose_map[pose] = Some(poses.len());
+                poses.push(pose);
+            }
+        }
+        // Dense priors need all of their poses.
+        let marginal_priors: Vec<&MarginalPrior> = problem
+            .marginal_priors
+            .iter()
+            .filter(|prior| prior.poses.iter().any(|&pose| pose_map[pose].is_some()))
+            .collect();
+        for prior in &marginal_priors {
+            for &pose in &prior.poses {
+                if pose_map[pose].is_none() {
+                    pose_map[pose] = Some(poses.len());
+                    poses.push(pose);
+                }
+            }
+        }
+
+        let mut local = BundleAdjustment::new(
+            poses.iter().map(|&pose| problem.poses[pose]).collect(),
+            landmarks
+                .iter()
+                .map(|&landmark| problem.landmarks[landmark])
+                .collect(),
+        );
+        local.lines = lines.iter().map(|&line| problem.lines[line]).collect();
+        local.planes.clone_from(&problem.planes);
+        for (local_pose, &pose) in poses.iter().enumerate() {
+            if pose < start || problem.is_fixed(pose) {
+                local.fix_pose(local_pose);
+            }
+        }
+        for observation in &problem.observations {
+            if let (Some(pose), Some(landmark)) = (
+                pose_map[observation.pose],
+                landmark_map[observation.landmark],
+            ) {
+                local.observe(Observation {
+                    pose,
+                    landmark,
+                    ..*observation
+                });
+            }
+        }
+        for factor in &problem.depths {
+            if let (Some(pose), Some(landmark)) =
+                (pose_map[factor.pose], landmark_map[factor.landmark])
+            {
+                local.add_depth(DepthObservation {
+                    pose,
+                    landmark,
+                    ..*factor
+                });
+            }
+        }
+        for observation in &problem.line_observations {
+            if let (Some(pose), Some(line)) =
+                (pose_map[observation.pose], line_map[observation.line])
+            {
+                local.observe_line(LineObservation {
+                    pose,
+                    line,
+                    ..*observation
+                });
+            }
+        }
+        for factor in &problem.points_on_planes {
+            if let Some(landmark) = landmark_map[factor.landmark] {
+                local.add_point_on_plane(PointOnPlane {
+                    landmark,
+                    ..*factor
+                });
+            }
+        }
+        for factor in &problem.cameras_above_planes {
+            if let Some(pose) = pose_map[factor.pose] {
+                local.add_camera_above_plane(CameraAbovePlane { pose, ..*factor });
+            }
+        }
+        for prior in &problem.priors {
+            if let Some(pose) = pose_map[prior.pose] {
+                local.add_prior(PosePrior { pose, ..*prior });
+            }
+        }
+        for prior in &problem.gnss {
+            if let Some(pose) = pose_map[prior.pose] {
+                local.add_gnss(GnssPrior { pose, ..*prior });
+            }
+        }
+        for prior in marginal_priors {
+            local.marginal_priors.push(MarginalPrior {
+                poses: prior
+                    .poses
+                    .iter()
+                    .map(|&pose| pose_map[pose].unwrap())
+                    .collect(),
+                ..prior.clone()
+            });
+        }
+        LocalProblem {
+            problem: local,
+            poses,
+            landmarks,
+            lines,
+        }
+    }
+
+    /// Optimizes the window of the problem in place.
+    pub fn optimize(&self, problem: &mut BundleAdjustment) -> OptimizationReport {
+        if self.marginalize {
+            let old: Vec<usize> = (0..self.start(problem)).collect();
+            self.adjuster.marginalize(problem, &old);
+        }
+        let mut local = self.local_problem(problem);
+        let report = self.adjuster.optimize(&mut local.problem);
+        local.write_back(problem);
+        report
+    }
+}
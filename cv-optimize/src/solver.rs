@@ -0,0 +1,467 @@
+use cv_core::nalgebra::{self, DMatrix, DVector, RealField};
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The normal equations of a [`LeastSquaresProblem`] linearized at its current parameters.
+///
+/// Following the usual convention for least squares, the normal equations describe half of the cost
+/// `½Σρ(r²)`: for residuals `r` with the Jacobian `J`, `g = Jᵀr` and `H = JᵀJ` (weighted by any robust losses).
+/// Half of the cost is approximated by `½cost + gᵀx + ½xᵀHx` for a step `x`, where `g` is the gradient and `H` is
+/// the Gauss-Newton approximation of the Hessian. Problems with structure, such as bundle adjustment, implement this
+/// to solve the normal equations efficiently.
+///
+/// The normal equations are in the scalar type `T` of the parameters of the problem, which can be `f32` on targets
+/// where `f64` is slow and memory is tight.
+pub trait Linearization<T: RealField = f64> {
+    /// The gradient `g` of half of the cost.
+    fn gradient(&self) -> DVector<T>;
+
+    /// The product `Hv` of the Hessian with a vector.
+    fn hessian_product(&self, v: &DVector<T>) -> DVector<T>;
+
+    /// Solves the damped normal equations `(H + λD)x = -g`, where `D` is the diagonal of `H`.
+    ///
+    /// The diagonal should be clamped to a small positive value so that the damped system is always invertible
+    /// for a positive `λ`. A `λ` of zero gives the Gauss-Newton step.
+    fn solve(&self, lambda: T) -> Option<DVector<T>>;
+}
+
+/// Dense normal equations, which are suitable for problems with few parameters.
+///
+/// ```
+/// use cv_core::nalgebra::{DMatrix, DVector};
+/// use cv_optimize::{DenseLinearization, LeastSquaresProblem, LevenbergMarquardt};
+///
+/// /// Fits a line `y = a * x + b` in single precision.
+/// #[derive(Clone)]
+/// struct Line {
+///     parameters: [f32; 2],
+///     samples: Vec<(f32, f32)>,
+/// }
+///
+/// impl LeastSquaresProblem<f32> for Line {
+///     type Linearization = DenseLinearization<f32>;
+///
+///     fn cost(&self) -> f64 {
+///         let [a, b] = self.parameters;
+///         self.samples.iter().map(|&(x, y)| f64::from(a * x + b - y).powi(2)).sum()
+///     }
+///
+///     fn linearize(&self) -> DenseLinearization<f32> {
+///         let [a, b] = self.parameters;
+///         let mut normal = DenseLinearization::zeros(2);
+///         for &(x, y) in &self.samples {
+///             let jacobian = DMatrix::from_row_slice(1, 2, &[x, 1.0]);
+///             normal.hessian += jacobian.transpose() * &jacobian;
+///             normal.gradient += jacobian.transpose() * (a * x + b - y);
+///         }
+///         normal
+///     }
+///
+///     fn retract(&self, step: &DVector<f32>) -> Self {
+///         let [a, b] = self.parameters;
+///         Self { parameters: [a + step[0], b + step[1]], ..self.clone() }
+///     }
+/// }
+///
+/// let samples = (0..20).map(|i| i as f32 * 0.5).map(|x| (x, 3.0 * x - 2.0)).collect();
+/// let mut problem = Line { parameters: [0.0, 0.0], samples };
+/// LevenbergMarquardt::new().minimize(&mut problem);
+/// assert!((problem.parameters[0] - 3.0).abs() < 1e-4);
+/// assert!((problem.parameters[1] + 2.0).abs() < 1e-3);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct DenseLinearization<T: RealField = f64> {
+    pub hessian: DMatrix<T>,
+    pub gradient: DVector<T>,
+}
+
+impl<T: RealField> DenseLinearization<T> {
+    /// Creates zeroed normal equations for `parameters` parameters.
+    pub fn zeros(parameters: usize) -> Self {
+        Self {
+            hessian: DMatrix::zeros(parameters, parameters),
+            gradient: DVector::zeros(parameters),
+        }
+    }
+}
+
+impl<T: RealField> Linearization<T> for DenseLinearization<T> {
+    fn gradient(&self) -> DVector<T> {
+        self.gradient.clone()
+    }
+
+    fn hessian_product(&self, v: &DVector<T>) -> DVector<T> {
+        &self.hessian * v
+    }
+
+    fn solve(&self, lambda: T) -> Option<DVector<T>> {
+        let mut damped = self.hessian.clone();
+        let min_diagonal = nalgebra::convert::<f64, T>(1e-12);
+        for i in 0..damped.nrows() {
+            damped[(i, i)] += lambda * self.hessian[(i, i)].max(min_diagonal);
+        }
+        Some(-damped.cholesky()?.solve(&self.gradient))
+    }
+}
+
+/// A nonlinear least squares problem that can be minimized by [`LevenbergMarquardt`].
+///
+/// The parameters of the problem live on a manifold (such as poses), so steps are applied with
+/// [`LeastSquaresProblem::retract`] instead of being added to the parameters directly. To reuse the analytic
+/// Jacobians of [`Pose::transform_jacobian_self`](cv_core::Pose::transform_jacobian_self), a step `(δt, ω)` of a
+/// pose should be retracted as `t ← t + δt` and `R ← exp(ω)R`.
+///
+/// The parameters, steps, and normal equations are in the scalar type `T`, while the cost is always accumulated in
+/// `f64`: it sums over every residual, and the solver decides whether a step is accepted from the difference of two
+/// costs, which is lost in rounding in `f32` close to the minimum.
+pub trait LeastSquaresProblem<T: RealField = f64>: Clone {
+    type Linearization: Linearization<T>;
+
+    /// The total cost `Σρ(r²)` at the current parameters, including any robust losses.
+    ///
+    /// This is not halved, so its gradient is twice the gradient of the [`LeastSquaresProblem::Linearization`].
+    fn cost(&self) -> f64;
+
+    /// Linearizes the problem at the current parameters.
+    fn linearize(&self) -> Self::Linearization;
+
+    /// Creates the problem with the step applied to the parameters.
+    fn retract(&self, step: &DVector<T>) -> Self;
+}
+
+/// Converts a scalar of a problem to `f64` for the bookkeeping of the solver.
+fn to_f64<T: RealField>(value: T) -> f64 {
+    nalgebra::try_convert::<T, f64>(value).unwrap_or(f64::NAN)
+}
+
+/// How the size of steps is controlled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TrustRegion {
+    /// Levenberg-Marquardt damping, which starts at the given `λ`.
+    ///
+    /// The damping is divided by ten after each successful step and multiplied by ten after each failed step.
+    Damping(f64),
+    /// Powell's dogleg method, which starts with the given trust region radius.
+    ///
+    /// Each step interpolates between the steepest descent and Gauss-Newton steps within the trust region,
+    /// which only requires one solve of the normal equations per linearization.
+    Dogleg(f64),
+}
+
+/// Why a [`LevenbergMarquardt`] minimization stopped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum Termination {
+    /// The norm of a step was below the step tolerance.
+    StepTolerance,
+    /// The relative decrease of the cost was below the cost tolerance.
+    CostTolerance,
+    /// The norm of the gradient was below the gradient tolerance.
+    GradientTolerance,
+    /// The maximum number of iterations was reached.
+    MaxIterations,
+    /// No step could be found which reduces the cost.
+    NoProgress,
+    /// The callback requested to stop.
+    Callback,
+}
+
+/// The state of a minimization after each accepted step, which is passed to callbacks.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IterationSummary {
+    /// The number of accepted steps.
+    pub iteration: usize,
+    /// The cost after the step.
+    pub cost: f64,
+    /// The norm of the step.
+    pub step_norm: f64,
+    /// The damping or trust region radius for the next step.
+    pub trust_region: f64,
+}
+
+/// The outcome of a minimization.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct OptimizationReport {
+    /// The number of accepted steps.
+    pub iterations: usize,
+    /// The cost before the optimization.
+    pub initial_cost: f64,
+    /// The cost after the optimization.
+    pub final_cost: f64,
+    /// Why the optimization stopped.
+    pub termination: Termination,
+}
+
+/// A reusable Levenberg-Marquardt (or dogleg) solver for any [`LeastSquaresProblem`].
+///
+/// ```
+/// use cv_core::nalgebra::{DMatrix, DVector};
+/// use cv_optimize::{DenseLinearization, LeastSquaresProblem, LevenbergMarquardt, TrustRegion};
+///
+/// /// Fits `y = a * exp(b * x)` to samples.
+/// #[derive(Clone)]
+/// struct Exponential {
+///     parameters: [f64; 2],
+///     samples: Vec<(f64, f64)>,
+/// }
+///
+/// impl LeastSquaresProblem for Exponential {
+///     type Linearization = DenseLinearization;
+///
+///     fn cost(&self) -> f64 {
+///         let [a, b] = self.parameters;
+///         self.samples.iter().map(|&(x, y)| (a * (b * x).exp() - y).powi(2)).sum()
+///     }
+///
+///     fn linearize(&self) -> DenseLinearization {
+///         let [a, b] = self.parameters;
+///         let mut normal = DenseLinearization::zeros(2);
+///         for &(x, y) in &self.samples {
+///             let e = (b * x).exp();
+///             let jacobian = DMatrix::from_row_slice(1, 2, &[e, a * x * e]);
+///             normal.hessian += jacobian.transpose() * &jacobian;
+///             normal.gradient += jacobian.transpose() * (a * e - y);
+///         }
+///         normal
+///     }
+///
+///     fn retract(&self, step: &DVector<f64>) -> Self {
+///         let [a, b] = self.parameters;
+///         Self { parameters: [a + step[0], b + step[1]], ..self.clone() }
+///     }
+/// }
+///
+/// let samples: Vec<(f64, f64)> = (0..10).map(|i| i as f64 * 0.1).map(|x| (x, 2.0 * (-1.5 * x).exp())).collect();
+/// for &trust_region in &[TrustRegion::Damping(1e-3), TrustRegion::Dogleg(1.0)] {
+///     let mut problem = Exponential { parameters: [1.0, 0.0], samples: samples.clone() };
+///     let report = LevenbergMarquardt::new().trust_region(trust_region).minimize(&mut problem);
+///     assert!(report.final_cost < 1e-20, "{:?}", report);
+///     assert!((problem.parameters[0] - 2.0).abs() < 1e-8);
+///     assert!((problem.parameters[1] + 1.5).abs() < 1e-8);
+/// }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LevenbergMarquardt {
+    /// How the size of steps is controlled.
+    pub trust_region: TrustRegion,
+    /// The maximum number of accepted steps.
+    pub max_iterations: usize,
+    /// Optimization stops when the norm of a step is below this.
+    pub step_tolerance: f64,
+    /// Optimization stops when the relative decrease of the cost of a step is below this.
+    pub cost_tolerance: f64,
+    /// Optimization stops when the norm of the gradient is below this.
+    pub gradient_tolerance: f64,
+}
+
+impl Default for LevenbergMarquardt {
+    fn default() -> Self {
+        Self {
+            trust_region: TrustRegion::Damping(1e-4),
+            max_iterations: 50,
+            step_tolerance: 1e-12,
+            cost_tolerance: 0.0,
+            gradient_tolerance: 0.0,
+        }
+    }
+}
+
+impl LevenbergMarquardt {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn trust_region(self, trust_region: TrustRegion) -> Self {
+        Self {
+            trust_region,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn step_tolerance(self, step_tolerance: f64) -> Self {
+        Self {
+            step_tolerance,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn cost_tolerance(self, cost_tolerance: f64) -> Self {
+        Self {
+            cost_tolerance,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn gradient_tolerance(self, gradient_tolerance: f64) -> Self {
+        Self {
+            gradient_tolerance,
+            ..self
+        }
+    }
+
+    /// Minimizes the problem in place.
+    pub fn minimize<T, P>(&self, problem: &mut P) -> OptimizationReport
+    where
+        T: RealField,
+        P: LeastSquaresProblem<T>,
+    {
+        self.minimize_with(problem, |_, _| true)
+    }
+
+    /// Minimizes the problem in place, calling `callback` after every accepted step.
+    ///
+    /// The minimization stops if the callback returns `false`.
+    pub fn minimize_with<T, P, F>(&self, problem: &mut P, mut callback: F) -> OptimizationReport
+    where
+        T: RealField,
+        P: LeastSquaresProblem<T>,
+        F: FnMut(&P, &IterationSummary) -> bool,
+    {
+        let initial_cost = problem.cost();
+        let mut cost = initial_cost;
+        let mut trust_region = match self.trust_region {
+            TrustRegion::Damping(lambda) | TrustRegion::Dogleg(lambda) => lambda,
+        };
+        let mut iteration = 0;
+        let termination = 'outer: loop {
+            if iteration >= self.max_iterations {
+                break Termination::MaxIterations;
+            }
+            let linearization = problem.linearize();
+            let gradient = linearization.gradient();
+            if to_f64(gradient.norm()) <= self.gradient_tolerance {
+                break Termination::GradientTolerance;
+            }
+            // The Gauss-Newton step only needs to be computed once per linearization for dogleg.
+            let gauss_newton = match self.trust_region {
+                TrustRegion::Damping(_) => None,
+                TrustRegion::Dogleg(_) => {
+                    match linearization
+                        .solve(T::zero())
+                        .or_else(|| linearization.solve(nalgebra::convert(1e-9)))
+                    {
+                        Some(step) => Some(step),
+                        None => break Termination::NoProgress,
+                    }
+                }
+            };
+            // Shrink the trust region until a step reduces the cost.
+            loop {
+                let step = match &gauss_newton {
+                    None => match linearization.solve(nalgebra::convert(trust_region)) {
+                        Some(step) => step,
+                        None => break 'outer Termination::NoProgress,
+                    },
+                    Some(gauss_newton) => {
+                        dogleg(&linearization, &gradient, gauss_newton, trust_region)
+                    }
+                };
+                let step_norm = to_f64(step.norm());
+                let candidate = problem.retract(&step);
+                let candidate_cost = candidate.cost();
+                let decrease = cost - candidate_cost;
+                if decrease > 0.0 {
+                    *problem = candidate;
+                    cost = candidate_cost;
+                    iteration += 1;
+                    trust_region = match self.trust_region {
+                        TrustRegion::Damping(_) => (trust_region * 0.1).max(1e-12),
+                        TrustRegion::Dogleg(_) => {
+                            // The linearization models half of the cost.
+                            let predicted = -2.0 * to_f64(gradient.dot(&step))
+                                - to_f64(step.dot(&linearization.hessian_product(&step)));
+                            let ratio = decrease / predicted;
+                            if ratio > 0.75 {
+                                trust_region.max(3.0 * step_norm)
+                            } else if ratio < 0.25 {
+                                trust_region * 0.5
+                            } else {
+                                trust_region
+                            }
+                        }
+                    };
+                    let summary = IterationSummary {
+                        iteration,
+                        cost,
+                        step_norm,
+                        trust_region,
+                    };
+                    if !callback(problem, &summary) {
+                        break 'outer Termination::Callback;
+                    }
+                    if step_norm < self.step_tolerance {
+                        break 'outer Termination::StepTolerance;
+                    }
+                    if decrease <= self.cost_tolerance * (cost + decrease) {
+                        break 'outer Termination::CostTolerance;
+                    }
+                    break;
+                }
+                trust_region = match self.trust_region {
+                    TrustRegion::Damping(_) => trust_region * 10.0,
+                    TrustRegion::Dogleg(_) => trust_region * 0.5,
+                };
+                let exhausted = match self.trust_region {
+                    TrustRegion::Damping(_) => trust_region > 1e12,
+                    TrustRegion::Dogleg(_) => trust_region < self.step_tolerance,
+                };
+                if exhausted || step_norm < self.step_tolerance {
+                    break 'outer Termination::NoProgress;
+                }
+            }
+        };
+        OptimizationReport {
+            iterations: iteration,
+            initial_cost,
+            final_cost: cost,
+            termination,
+        }
+    }
+}
+
+/// Computes the dogleg step within the trust region `radius`.
+///
+/// The scalars of the step are computed in `f64`, so that the curvature doesn't underflow in `f32`.
+fn dogleg<T, L>(
+    linearization: &L,
+    gradient: &DVector<T>,
+    gauss_newton: &DVector<T>,
+    radius: f64,
+) -> DVector<T>
+where
+    T: RealField,
+    L: Linearization<T>,
+{
+    if to_f64(gauss_newton.norm()) <= radius {
+        return gauss_newton.clone();
+    }
+    // The minimum of the cost along the steepest descent direction (the Cauchy point).
+    let curvature = to_f64(gradient.dot(&linearization.hessian_product(gradient)));
+    let scale = -(to_f64(gradient.norm_squared()) / curvature.max(1e-300));
+    let steepest = gradient * nalgebra::convert::<f64, T>(scale);
+    let steepest_norm = to_f64(steepest.norm());
+    if steepest_norm >= radius {
+        return steepest * nalgebra::convert::<f64, T>(radius / steepest_norm);
+    }
+    // Find where the path from the Cauchy point to the Gauss-Newton step leaves the trust region.
+    let direction = gauss_newton - &steepest;
+    let a = to_f64(direction.norm_squared());
+    let b = 2.0 * to_f64(steepest.dot(&direction));
+    let c = steepest_norm * steepest_norm - radius * radius;
+    let beta = (-b + (b * b - 4.0 * a * c).max(0.0).sqrt()) / (2.0 * a);
+    steepest + direction * nalgebra::convert::<f64, T>(beta)
+}
@@ -0,0 +1,204 @@
+use crate::{
+    bundle_adjustment::linearize_observation, BundleAdjustment, DenseLinearization,
+    LeastSquaresProblem, LevenbergMarquardt, RobustLoss, TrustRegion,
+};
+use cv_core::{
+    nalgebra::{DMatrix, DVector, Matrix3, Point3, UnitVector3, Vector3},
+    Pose, Projective, WorldPoint, WorldToCamera,
+};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// Refines landmarks while keeping the poses fixed (structure-only optimization).
+///
+/// Every landmark is independent when the poses are fixed, so each one is refined with its own tiny
+/// Levenberg-Marquardt problem, which is much faster than a full [`BundleAdjuster`](crate::BundleAdjuster) run.
+/// This is useful to move the landmarks along after a pose graph correction and as a cheap step between full
+/// bundle adjustment runs. With the `rayon` feature enabled the landmarks are refined in parallel.
+///
+/// ```
+/// use cv_core::{nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3}, *};
+/// use cv_optimize::StructureOnly;
+///
+/// let point = WorldPoint::from_point(Point3::new(0.3, -0.2, 5.0));
+/// let observations: Vec<(WorldToCamera, _)> = (0..3)
+///     .map(|i| {
+///         let pose = WorldToCamera(IsometryMatrix3::from_parts(
+///             Vector3::new(-0.5 * i as f64, 0.0, 0.0).into(),
+///             Rotation3::from_euler_angles(0.0, 0.05 * i as f64, 0.0),
+///         ));
+///         (pose, pose.transform(point).bearing())
+///     })
+///     .collect();
+///
+/// let guess = WorldPoint::from_point(Point3::new(0.35, -0.1, 4.0));
+/// let refined = StructureOnly::new().refine(guess, &observations).unwrap();
+/// assert!((refined.point().unwrap() - point.point().unwrap()).norm() < 1e-8);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StructureOnly {
+    /// The robust loss applied to the norm of each bearing residual.
+    pub loss: RobustLoss,
+    /// The maximum number of accepted steps for each landmark.
+    pub max_iterations: usize,
+    /// The initial damping of the Gauss-Newton step.
+    pub initial_lambda: f64,
+    /// Optimization stops when the norm of a step is below this.
+    pub tolerance: f64,
+}
+
+impl Default for StructureOnly {
+    fn default() -> Self {
+        Self {
+            loss: RobustLoss::Squared,
+            max_iterations: 10,
+            initial_lambda: 1e-4,
+            tolerance: 1e-12,
+        }
+    }
+}
+
+impl StructureOnly {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn loss(self, loss: RobustLoss) -> Self {
+        Self { loss, ..self }
+    }
+
+    #[must_use]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn initial_lambda(self, initial_lambda: f64) -> Self {
+        Self {
+            initial_lambda,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    fn solver(&self) -> LevenbergMarquardt {
+        LevenbergMarquardt::new()
+            .trust_region(TrustRegion::Damping(self.initial_lambda))
+            .max_iterations(self.max_iterations)
+            .step_tolerance(self.tolerance)
+    }
+
+    /// Refines a landmark to best fit its observations from fixed poses.
+    ///
+    /// This fails for landmarks at infinity, which can't be refined.
+    pub fn refine(
+        &self,
+        point: WorldPoint,
+        observations: &[(WorldToCamera, UnitVector3<f64>)],
+    ) -> Option<WorldPoint> {
+        let observations: Vec<(WorldToCamera, UnitVector3<f64>, RobustLoss)> = observations
+            .iter()
+            .map(|&(pose, bearing)| (pose, bearing, self.loss))
+            .collect();
+        self.refine_point(point, &observations)
+    }
+
+    fn refine_point(
+        &self,
+        point: WorldPoint,
+        observations: &[(WorldToCamera, UnitVector3<f64>, RobustLoss)],
+    ) -> Option<WorldPoint> {
+        let mut problem = LandmarkProblem {
+            point: point.point()?,
+            observations,
+        };
+        self.solver().minimize(&mut problem);
+        Some(WorldPoint::from_point(problem.point))
+    }
+
+    /// Refines every landmark of the problem in place while keeping all of the poses fixed.
+    ///
+    /// The loss of each observation overrides the loss of the optimizer.
+    pub fn optimize(&self, problem: &mut BundleAdjustment) {
+        let mut landmark_observations = vec![vec![]; problem.landmarks.len()];
+        for observation in &problem.observations {
+            landmark_observations[observation.landmark].push((
+                problem.poses[observation.pose],
+                observation.bearing,
+                observation.loss.unwrap_or(self.loss),
+            ));
+        }
+        let refine = |(landmark, observations): (&mut WorldPoint, &Vec<_>)| {
+            if let Some(refined) = self.refine_point(*landmark, observations) {
+                *landmark = refined;
+            }
+        };
+        #[cfg(feature = "rayon")]
+        problem
+            .landmarks
+            .par_iter_mut()
+            .zip(landmark_observations.par_iter())
+            .for_each(refine);
+        #[cfg(not(feature = "rayon"))]
+        problem
+            .landmarks
+            .iter_mut()
+            .zip(landmark_observations.iter())
+            .for_each(refine);
+    }
+}
+
+/// A single landmark being refined against observations from fixed poses.
+#[derive(Copy, Clone)]
+struct LandmarkProblem<'a> {
+    point: Point3<f64>,
+    observations: &'a [(WorldToCamera, UnitVector3<f64>, RobustLoss)],
+}
+
+impl LeastSquaresProblem for LandmarkProblem<'_> {
+    type Linearization = DenseLinearization;
+
+    fn cost(&self) -> f64 {
+        self.observations
+            .iter()
+            .map(|&(pose, bearing, loss)| {
+                let residual = pose
+                    .transform(WorldPoint::from_point(self.point))
+                    .bearing()
+                    .into_inner()
+                    - bearing.into_inner();
+                loss.loss(residual.norm())
+            })
+            .sum()
+    }
+
+    fn linearize(&self) -> DenseLinearization {
+        let mut hessian = Matrix3::zeros();
+        let mut gradient = Vector3::zeros();
+        for &(pose, bearing, loss) in self.observations {
+            let (residual, _, jacobian) = linearize_observation(pose, self.point, bearing);
+            let weight = loss.weight(residual.norm());
+            hessian += weight * jacobian.transpose() * jacobian;
+            gradient += weight * jacobian.transpose() * residual;
+        }
+        DenseLinearization {
+            hessian: DMatrix::from_column_slice(3, 3, hessian.as_slice()),
+            gradient: DVector::from_column_slice(gradient.as_slice()),
+        }
+    }
+
+    fn retract(&self, step: &DVector<f64>) -> Self {
+        Self {
+            point: self.point + Vector3::from_column_slice(step.as_slice()),
+            ..*self
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::CameraIntrinsics;
+use cv_core::{
+    nalgebra::{Point2, UnitVector3, Vector2, Vector3},
+    CameraModel, ImagePoint, KeyPoint,
+};
+use num_traits::Float;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// A fisheye camera with the equidistant distortion model of Kannala and Brandt, as used by OpenCV's fisheye module.
+///
+/// A bearing at the angle `θ` from the optical axis lands on the virtual image plane at the distance
+/// `θ (1 + k1 θ² + k2 θ⁴ + k3 θ⁶ + k4 θ⁸)` from the principal point, which is then converted to pixels by the
+/// [`CameraIntrinsics`]. Unlike the pinhole models, this covers fields of view of 180 degrees and more.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct CameraIntrinsicsFisheye {
+    pub simple_intrinsics: CameraIntrinsics,
+    pub k: [f64; 4],
+}
+
+impl CameraIntrinsicsFisheye {
+    /// Creates the camera intrinsics using simple intrinsics with no distortion and the coefficients `k1` to `k4`.
+    pub fn new(simple_intrinsics: CameraIntrinsics, k: [f64; 4]) -> Self {
+        Self {
+            simple_intrinsics,
+            k,
+        }
+    }
+
+    /// The distorted angle of a bearing at the angle `theta` from the optical axis and its derivative.
+    fn distort(&self, theta: f64) -> (f64, f64) {
+        let theta2 = theta * theta;
+        let [k1, k2, k3, k4] = self.k;
+        let polynomial = 1.0 + theta2 * (k1 + theta2 * (k2 + theta2 * (k3 + theta2 * k4)));
+        let derivative = 1.0
+            + theta2 * (3.0 * k1 + theta2 * (5.0 * k2 + theta2 * (7.0 * k3 + theta2 * 9.0 * k4)));
+        (theta * polynomial, derivative)
+    }
+
+    /// Creates the intrinsics of a virtual pinhole camera in the same position with the given horizontal field of
+    /// view in radians, for images of `width` by `height` pixels.
+    ///
+    /// The virtual camera has square pixels and a centered principal point. Images of this fisheye camera can be
+    /// remapped into the virtual camera, such as with [`fisheye_to_pinhole`](crate::fisheye_to_pinhole), so that
+    /// they can be used with pinhole models.
+    pub fn virtual_pinhole(&self, width: usize, height: usize, fov: f64) -> CameraIntrinsics {
+        let focal = width as f64 / 2.0 / Float::tan(fov / 2.0);
+        CameraIntrinsics::identity()
+            .focal(focal)
+            .principal_point(Point2::new(
+                width as f64 / 2.0 - 0.5,
+                height as f64 / 2.0 - 0.5,
+            ))
+    }
+}
+
+impl CameraModel for CameraIntrinsicsFisheye {
+    /// Takes in a point from an image in pixel coordinates and converts it to a bearing.
+    ///
+    /// ```
+    /// use cv_core::{KeyPoint, CameraModel};
+    /// use cv_pinhole::{CameraIntrinsics, CameraIntrinsicsFisheye};
+    /// use cv_core::nalgebra::{Vector2, Point2};
+    /// let intrinsics = CameraIntrinsicsFisheye::new(
+    ///     CameraIntrinsics::identity()
+    ///         .focals(Vector2::new(300.0, 310.0))
+    ///         .principal_point(Point2::new(640.0, 480.0)),
+    ///     [-0.013, 0.02, -0.011, 0.002],
+    /// );
+    /// // This pixel is more than 90 degrees from the optical axis.
+    /// let kp = KeyPoint(Point2::new(1150.0, 530.0));
+    /// let bearing = intrinsics.calibrate(kp);
+    /// assert!(bearing.z < 0.0);
+    /// let ukp = intrinsics.uncalibrate(bearing).unwrap();
+    /// assert!((kp.0 - ukp.0).norm() < 1e-6);
+    /// ```
+    fn calibrate<P>(&self, point: P) -> UnitVector3<f64>
+    where
+        P: ImagePoint,
+    {
+        let intrinsics = &self.simple_intrinsics;
+        let centered = point.image_point() - intrinsics.principal_point;
+        let y = centered.y / intrinsics.focals.y;
+        let x = (centered.x - intrinsics.skew * y) / intrinsics.focals.x;
+        let distorted = Vector2::new(x, y);
+        let theta_d = distorted.norm();
+        if theta_d == 0.0 {
+            return Vector3::z_axis();
+        }
+        // Invert the distortion with Newton's method, which converges in a few iterations from the distorted angle.
+        let mut theta = theta_d;
+        for _ in 0..20 {
+            let (value, derivative) = self.distort(theta);
+            let step = (value - theta_d) / derivative;
+            theta -= step;
+            if Float::abs(step) < 1e-12 {
+                break;
+            }
+        }
+        let direction = distorted / theta_d;
+        UnitVector3::new_normalize(Vector3::new(
+            direction.x * Float::sin(theta),
+            direction.y * Float::sin(theta),
+            Float::cos(theta),
+        ))
+    }
+
+    /// Converts a bearing back into pixel coordinates.
+    ///
+    /// Fails if the bearing is outside of the range of angles where the distortion polynomial increases, where the
+    /// model isn't invertible.
+    fn uncalibrate(&self, bearing: UnitVector3<f64>) -> Option<KeyPoint> {
+        let radius = bearing.xy().norm();
+        let theta = Float::atan2(radius, bearing.z);
+        let (theta_d, derivative) = self.distort(theta);
+        (derivative > 0.0).then(|| ())?;
+        let distorted = if radius == 0.0 {
+            Vector2::zeros()
+        } else {
+            bearing.xy() * (theta_d / radius)
+        };
+        let intrinsics = &self.simple_intrinsics;
+        let y = distorted.y * intrinsics.focals.y;
+        let x = distorted.x * intrinsics.focals.x + intrinsics.skew * distorted.y;
+        Some(KeyPoint(
+            Point2::new(x, y) + intrinsics.principal_point.coords,
+        ))
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{CameraIntrinsics, EssentialMatrix};
+use cv_core::{
+    nalgebra::{Matrix3, Point2, Vector3},
+    sample_consensus::Model,
+    KeyPointMatch,
+};
+use derive_more::{AsMut, AsRef, Deref, DerefMut, From, Into};
+use num_traits::Float;
+
+/// This stores a fundamental matrix, which is satisfied by the following constraint:
+///
+/// transpose(x') * F * x = 0
+///
+/// Where `x'` and `x` are homogeneous image coordinates in pixels in the second and first image.
+///
+/// The fundamental matrix is the uncalibrated equivalent of the [`EssentialMatrix`]. If the intrinsics `K` and `K'`
+/// of both cameras are known, then `F = inverse(transpose(K')) * E * inverse(K)`. It is estimated when the
+/// intrinsics are unknown, after which it can be upgraded to an essential matrix once they are known with
+/// [`FundamentalMatrix::essential`].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, AsMut, AsRef, Deref, DerefMut, From, Into)]
+pub struct FundamentalMatrix(pub Matrix3<f64>);
+
+impl FundamentalMatrix {
+    /// Creates the fundamental matrix relating pixel coordinates from an essential matrix and the intrinsics of the
+    /// first and second camera.
+    ///
+    /// Returns `None` if either intrinsic matrix is not invertible.
+    pub fn from_essential(
+        essential: EssentialMatrix,
+        a: &CameraIntrinsics,
+        b: &CameraIntrinsics,
+    ) -> Option<Self> {
+        let a_inverse = a.matrix().try_inverse()?;
+        let b_inverse = b.matrix().try_inverse()?;
+        Some(Self(b_inverse.transpose() * essential.0 * a_inverse))
+    }
+
+    /// Upgrades the fundamental matrix to an essential matrix using the intrinsics of the first and second camera.
+    pub fn essential(&self, a: &CameraIntrinsics, b: &CameraIntrinsics) -> EssentialMatrix {
+        EssentialMatrix(b.matrix().transpose() * self.0 * a.matrix())
+    }
+
+    /// Scales the matrix to have a Frobenius norm of `1.0`, which makes fundamental matrices comparable.
+    #[must_use]
+    pub fn normalize(&self) -> Self {
+        Self(self.0.normalize())
+    }
+
+    /// Computes the Sampson distance of a match in pixels, which is a first order approximation of the distance
+    /// that the points must move to exactly satisfy the epipolar constraint.
+    pub fn sampson_distance(&self, a: Point2<f64>, b: Point2<f64>) -> f64 {
+        let a = Vector3::new(a.x, a.y, 1.0);
+        let b = Vector3::new(b.x, b.y, 1.0);
+        let fa = self.0 * a;
+        let ftb = self.0.transpose() * b;
+        let error = b.dot(&fa);
+        let gradient = fa.x * fa.x + fa.y * fa.y + ftb.x * ftb.x + ftb.y * ftb.y;
+        if gradient == 0.0 {
+            return Float::abs(error);
+        }
+        Float::abs(error) / Float::sqrt(gradient)
+    }
+}
+
+impl Model<KeyPointMatch> for FundamentalMatrix {
+    /// The Sampson distance (see [`FundamentalMatrix::sampson_distance`]).
+    fn residual(&self, data: &KeyPointMatch) -> f64 {
+        let &KeyPointMatch(a, b) = data;
+        self.sampson_distance(a.0, b.0)
+    }
+}
+
+/// A [`FundamentalMatrix`] between two images with the same one-parameter division model of radial distortion.
+///
+/// Keypoints are first normalized as `p = (x - center) / scale` and then undistorted to the homogeneous coordinate
+/// `(p.x, p.y, 1 + lambda * |p|²)`, which the fundamental matrix relates. When `center` is the principal point and
+/// `scale` is the focal length, `lambda` is the same as the `k1` of [`crate::CameraIntrinsicsK1Distortion`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RadialFundamentalMatrix {
+    /// The fundamental matrix relating normalized and undistorted coordinates.
+    pub fundamental: FundamentalMatrix,
+    /// The division model distortion parameter.
+    pub lambda: f64,
+    /// The center of distortion in pixels.
+    pub center: Point2<f64>,
+    /// The scale of the normalized coordinates in pixels.
+    pub scale: f64,
+}
+
+impl RadialFundamentalMatrix {
+    /// Normalizes a keypoint without undistorting it.
+    pub fn normalize_point(&self, point: Point2<f64>) -> Point2<f64> {
+        Point2::from((point - self.center) / self.scale)
+    }
+
+    /// Normalizes and undistorts a keypoint into an inhomogeneous normalized coordinate.
+    pub fn undistort(&self, point: Point2<f64>) -> Point2<f64> {
+        let p = self.normalize_point(point);
+        p / (1.0 + self.lambda * p.coords.norm_squared())
+    }
+}
+
+impl Model<KeyPointMatch> for RadialFundamentalMatrix {
+    /// The Sampson distance of the undistorted keypoints, converted back to pixels.
+    fn residual(&self, data: &KeyPointMatch) -> f64 {
+        let &KeyPointMatch(a, b) = data;
+        self.fundamental
+            .sampson_distance(self.undistort(a.0), self.undistort(b.0))
+            * self.scale
+    }
+}
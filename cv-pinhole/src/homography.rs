@@ -0,0 +1,325 @@
+use crate::rotation::closest_rotation;
+use cv_core::{
+    nalgebra::{Matrix3, Point3, Rotation3, SMatrix, Vector3},
+    sample_consensus::{Estimator, Model},
+    CameraToCamera, FeatureMatch, Pose,
+};
+use derive_more::{AsMut, AsRef, Deref, DerefMut, From, Into};
+use num_traits::Float;
+
+/// This stores a homography matrix, which is satisfied by the following constraint:
+///
+/// x' ∝ H * x
+///
+/// Where `x'` and `x` are homogeneous normalized image coordinates (or bearings) in the second and first image.
+///
+/// A homography relates two views when every observed point lies on a single plane, or when the camera
+/// only rotates between the two views. In both of those situations the essential matrix is degenerate,
+/// so a homography should be estimated instead and decomposed with
+/// [`HomographyMatrix::possible_unscaled_poses`].
+///
+/// For a plane `transpose(n) * X = d` in the coordinates of the first camera and a [`CameraToCamera`] pose
+/// `(R, t)`, the homography is `H = R + t * transpose(n) / d`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, AsMut, AsRef, Deref, DerefMut, From, Into)]
+pub struct HomographyMatrix(pub Matrix3<f64>);
+
+/// One of the possible decompositions of a [`HomographyMatrix`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlanarPose {
+    /// The pose from the first camera to the second camera.
+    ///
+    /// The translation is scaled by the inverse of the distance from the first camera to the plane.
+    pub pose: CameraToCamera,
+    /// The normal of the plane in the first camera's coordinates.
+    pub normal: Vector3<f64>,
+}
+
+impl PlanarPose {
+    /// Checks if a match lies in front of both cameras, assuming that the point lies on the plane.
+    pub fn is_in_front(&self, &FeatureMatch(a, b): &FeatureMatch) -> bool {
+        let a_projection = self.normal.dot(&a);
+        if a_projection <= 0.0 {
+            return false;
+        }
+        let point = self.pose.isometry() * Point3::from(a.into_inner() / a_projection);
+        point.coords.dot(&b) > 0.0
+    }
+}
+
+impl HomographyMatrix {
+    /// Creates the homography induced by the plane `transpose(normal) * X = distance` in the first camera's
+    /// coordinates when moving by `pose`.
+    pub fn from_plane(pose: CameraToCamera, normal: Vector3<f64>, distance: f64) -> Self {
+        let isometry = pose.isometry();
+        Self(
+            isometry.rotation.matrix()
+                + isometry.translation.vector * normal.transpose() / distance,
+        )
+    }
+
+    /// Checks if the homography is (nearly) a pure rotation, in which case there is no parallax between the views.
+    ///
+    /// This is the case when the ratio of the largest to the smallest singular value is within `1.0 + threshold`.
+    pub fn is_rotation_only(
+        &self,
+        threshold: f64,
+        epsilon: f64,
+        max_iterations: usize,
+    ) -> Option<bool> {
+        let singular_values = self
+            .0
+            .try_svd(false, false, epsilon, max_iterations)?
+            .singular_values;
+        let largest = singular_values.max();
+        let smallest = singular_values.min();
+        Some(smallest > 0.0 && largest / smallest < 1.0 + threshold)
+    }
+
+    /// Returns the rotation closest to the homography, which is the rotation between the views
+    /// if the camera only rotated.
+    ///
+    /// A homography is only defined up to scale, and the scale may be negative, so the sign is chosen to make the
+    /// determinant positive before projecting onto the closest rotation.
+    ///
+    /// ```
+    /// use cv_core::nalgebra::Rotation3;
+    /// use cv_pinhole::HomographyMatrix;
+    /// let rotation = Rotation3::from_euler_angles(0.2, 0.3, 0.4);
+    /// for &scale in &[2.5, -0.5] {
+    ///     let homography = HomographyMatrix(rotation.matrix() * scale);
+    ///     let recovered = homography.rotation(1e-12, 1000).unwrap();
+    ///     assert!(recovered.rotation_to(&rotation).angle() < 1e-9);
+    /// }
+    /// ```
+    pub fn rotation(&self, epsilon: f64, max_iterations: usize) -> Option<Rotation3<f64>> {
+        let homography = if self.0.determinant() < 0.0 {
+            -self.0
+        } else {
+            self.0
+        };
+        closest_rotation(homography, epsilon, max_iterations)
+    }
+
+    /// Decomposes the homography into the eight possible poses and plane normals using the method of
+    /// Faugeras and Lustman.
+    ///
+    /// Only the decompositions for which the observed points lie in front of both cameras are physically possible,
+    /// which can be checked with [`PlanarPose::is_in_front`]. Typically two decompositions remain, and the
+    /// ambiguity can only be resolved with more views or prior knowledge of the plane.
+    ///
+    /// If the homography is a pure rotation, all of the returned poses have the same rotation and no translation.
+    ///
+    /// ```
+    /// use cv_core::CameraToCamera;
+    /// use cv_core::nalgebra::{IsometryMatrix3, Rotation3, Vector3};
+    /// use cv_pinhole::HomographyMatrix;
+    /// let pose = CameraToCamera(IsometryMatrix3::from_parts(
+    ///     Vector3::new(-0.8, 0.4, 0.5).into(),
+    ///     Rotation3::from_euler_angles(0.2, 0.3, 0.4),
+    /// ));
+    /// let normal = Vector3::new(0.1, -0.2, 1.0).normalize();
+    /// let homography = HomographyMatrix::from_plane(pose, normal, 2.0);
+    /// let decompositions = homography.possible_unscaled_poses(1e-12, 1000).unwrap();
+    /// let one_correct = decompositions.iter().any(|planar| {
+    ///     let angle_residual = planar.pose.0.rotation.rotation_to(&pose.0.rotation).angle();
+    ///     let translation_residual = (planar.pose.0.translation.vector - pose.0.translation.vector / 2.0).norm();
+    ///     let normal_residual = (planar.normal - normal).norm();
+    ///     angle_residual < 1e-6 && translation_residual < 1e-6 && normal_residual < 1e-6
+    /// });
+    /// assert!(one_correct);
+    /// ```
+    pub fn possible_unscaled_poses(
+        &self,
+        epsilon: f64,
+        max_iterations: usize,
+    ) -> Option<[PlanarPose; 8]> {
+        let svd = self.0.try_svd(true, true, epsilon, max_iterations)?;
+        let u = svd.u?;
+        let v_t = svd.v_t?;
+        // Sort the singular values in decreasing order.
+        let mut sources = [0, 1, 2];
+        sources.sort_unstable_by_key(|&ix| float_ord::FloatOrd(-svd.singular_values[ix]));
+        let d = [
+            svd.singular_values[sources[0]],
+            svd.singular_values[sources[1]],
+            svd.singular_values[sources[2]],
+        ];
+        let mut sorted_u = Matrix3::zeros();
+        let mut sorted_v_t = Matrix3::zeros();
+        for (&ix, mut column) in sources.iter().zip(sorted_u.column_iter_mut()) {
+            column.copy_from(&u.column(ix));
+        }
+        for (&ix, mut row) in sources.iter().zip(sorted_v_t.row_iter_mut()) {
+            row.copy_from(&v_t.row(ix));
+        }
+        let u = sorted_u;
+        let v_t = sorted_v_t;
+        let s = u.determinant() * v_t.determinant();
+        let v = v_t.transpose();
+
+        // Normalize the homography so that the middle singular value is 1.
+        if d[1] <= 0.0 {
+            return None;
+        }
+        let [d1, d2, d3] = [d[0] / d[1], 1.0, d[2] / d[1]];
+
+        let rotation_only = PlanarPose {
+            pose: CameraToCamera::from_parts(
+                Vector3::zeros(),
+                Rotation3::from_matrix_unchecked(u * v_t * s),
+            ),
+            normal: Vector3::z(),
+        };
+        if d1 - d3 < epsilon.sqrt() {
+            return Some([rotation_only; 8]);
+        }
+
+        let aux1 = ((d1 * d1 - d2 * d2) / (d1 * d1 - d3 * d3)).sqrt();
+        let aux3 = ((d2 * d2 - d3 * d3) / (d1 * d1 - d3 * d3)).sqrt();
+        let x1 = [aux1, aux1, -aux1, -aux1];
+        let x3 = [aux3, -aux3, aux3, -aux3];
+
+        let mut poses = [rotation_only; 8];
+
+        // The case where d' = d2.
+        let aux_sin = ((d1 * d1 - d2 * d2) * (d2 * d2 - d3 * d3)).sqrt() / ((d1 + d3) * d2);
+        let cos = (d2 * d2 + d1 * d3) / ((d1 + d3) * d2);
+        let sins = [aux_sin, -aux_sin, -aux_sin, aux_sin];
+        let signs = x1.iter().zip(x3.iter()).zip(sins.iter());
+        for (pose, ((&x1, &x3), &sin)) in poses[..4].iter_mut().zip(signs) {
+            #[rustfmt::skip]
+            let rotation = Matrix3::new(
+                cos, 0.0, -sin,
+                0.0, 1.0, 0.0,
+                sin, 0.0, cos,
+            );
+            let translation = u * Vector3::new(x1, 0.0, -x3) * (d1 - d3);
+            let normal = v * Vector3::new(x1, 0.0, x3);
+            *pose = Self::planar_pose(s * u * rotation * v_t, translation, normal, s);
+        }
+
+        // The case where d' = -d2.
+        let aux_sin = ((d1 * d1 - d2 * d2) * (d2 * d2 - d3 * d3)).sqrt() / ((d1 - d3) * d2);
+        let cos = (d1 * d3 - d2 * d2) / ((d1 - d3) * d2);
+        let sins = [aux_sin, -aux_sin, -aux_sin, aux_sin];
+        let signs = x1.iter().zip(x3.iter()).zip(sins.iter());
+        for (pose, ((&x1, &x3), &sin)) in poses[4..].iter_mut().zip(signs) {
+            #[rustfmt::skip]
+            let rotation = Matrix3::new(
+                cos, 0.0,  sin,
+                0.0, -1.0, 0.0,
+                sin, 0.0,  -cos,
+            );
+            let translation = u * Vector3::new(x1, 0.0, x3) * (d1 + d3);
+            let normal = v * Vector3::new(x1, 0.0, x3);
+            *pose = Self::planar_pose(s * u * rotation * v_t, translation, normal, -s);
+        }
+
+        Some(poses)
+    }
+
+    /// Creates a decomposition such that the plane normal faces the first camera.
+    fn planar_pose(
+        rotation: Matrix3<f64>,
+        translation: Vector3<f64>,
+        normal: Vector3<f64>,
+        sign: f64,
+    ) -> PlanarPose {
+        // The homography only has a scale up to sign, so the sign of the plane distance is absorbed
+        // into the translation.
+        let (translation, normal) = if normal.z < 0.0 {
+            (-translation * sign, -normal)
+        } else {
+            (translation * sign, normal)
+        };
+        PlanarPose {
+            pose: CameraToCamera::from_parts(
+                translation,
+                Rotation3::from_matrix_unchecked(rotation),
+            ),
+            normal,
+        }
+    }
+}
+
+impl Model<FeatureMatch> for HomographyMatrix {
+    /// The angle between the bearing in the second image and the first bearing transferred by the homography.
+    fn residual(&self, data: &FeatureMatch) -> f64 {
+        let Self(mat) = *self;
+        let &FeatureMatch(a, b) = data;
+        let transferred = mat * a.into_inner();
+        // The homography has an arbitrary sign, so both directions along the transferred bearing are accepted.
+        Float::atan2(
+            transferred.cross(&b).norm(),
+            Float::abs(transferred.dot(&b)),
+        )
+    }
+}
+
+/// Estimates a [`HomographyMatrix`] from four or more matches with the direct linear transform.
+///
+/// The homography constraint `cross(b, H * a) = 0` is solved in the least-squares sense directly on the bearings,
+/// so no normalization of the input is required.
+#[derive(Copy, Clone, Debug)]
+pub struct FourPoint {
+    pub epsilon: f64,
+    pub iterations: usize,
+}
+
+impl FourPoint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn from_matches<I>(&self, data: I) -> Option<HomographyMatrix>
+    where
+        I: Iterator<Item = FeatureMatch> + Clone,
+    {
+        let mut ata = SMatrix::<f64, 9, 9>::zeros();
+        let mut count = 0;
+        for FeatureMatch(a, b) in data {
+            let cross = b.cross_matrix();
+            let mut rows = SMatrix::<f64, 3, 9>::zeros();
+            for (j, &a) in a.iter().enumerate() {
+                rows.fixed_columns_mut::<3>(3 * j).copy_from(&(cross * a));
+            }
+            ata += rows.transpose() * rows;
+            count += 1;
+        }
+        if count < 4 {
+            return None;
+        }
+        let eigens = ata.try_symmetric_eigen(self.epsilon, self.iterations)?;
+        let eigenvector = eigens
+            .eigenvalues
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &n)| float_ord::FloatOrd(n))
+            .map(|(ix, _)| eigens.eigenvectors.column(ix).into_owned())?;
+        Some(HomographyMatrix(Matrix3::from_iterator(
+            eigenvector.iter().copied(),
+        )))
+    }
+}
+
+impl Default for FourPoint {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-12,
+            iterations: 1000,
+        }
+    }
+}
+
+impl Estimator<FeatureMatch> for FourPoint {
+    type Model = HomographyMatrix;
+    type ModelIter = Option<HomographyMatrix>;
+    const MIN_SAMPLES: usize = 4;
+
+    fn estimate<I>(&self, data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = FeatureMatch> + Clone,
+    {
+        self.from_matches(data)
+    }
+}
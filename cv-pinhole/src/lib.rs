@@ -5,17 +5,31 @@
 
 #![no_std]
 
-#[cfg(feature = "alloc")]
+#[cfg(any(feature = "alloc", feature = "image"))]
 extern crate alloc;
 
 mod essential;
+mod fisheye;
+mod fundamental;
+mod homography;
+#[cfg(feature = "image")]
+mod remap;
+mod rotation;
+mod stereo;
 
 pub use essential::*;
+pub use fisheye::*;
+pub use fundamental::*;
+pub use homography::*;
+#[cfg(feature = "image")]
+pub use remap::*;
+pub use rotation::*;
+pub use stereo::*;
 
 use cv_core::{
-    nalgebra::{Matrix3, Point2, UnitVector3, Vector2},
-    CameraModel, CameraToCamera, FeatureMatch, ImagePoint, KeyPoint, Pose, Projective,
-    TriangulatorRelative,
+    nalgebra::{Matrix2, Matrix2x3, Matrix3, Point2, UnitVector3, Vector2, Vector3},
+    CameraModel, CameraToCamera, FeatureMatch, ImagePoint, KeyPoint, ParametricCameraModel, Pose,
+    Projective, TriangulatorRelative,
 };
 use num_traits::Float;
 
@@ -137,6 +151,89 @@ impl CameraModel for CameraIntrinsics {
     }
 }
 
+impl CameraIntrinsics {
+    /// Maps a distorted point on the virtual image plane to pixel coordinates, writing the Jacobian of the pixel in
+    /// respect to the focals, principal point, and skew into `parameter_jacobian`.
+    ///
+    /// Returns the pixel and its Jacobian in respect to the point on the virtual image plane.
+    fn project_plane(
+        &self,
+        plane: Vector2<f64>,
+        parameter_jacobian: &mut [Vector2<f64>],
+    ) -> (Point2<f64>, Matrix2<f64>) {
+        let pixel = Point2::new(
+            self.focals.x * plane.x + self.skew * plane.y,
+            self.focals.y * plane.y,
+        ) + self.principal_point.coords;
+        parameter_jacobian[0] = Vector2::new(plane.x, 0.0);
+        parameter_jacobian[1] = Vector2::new(0.0, plane.y);
+        parameter_jacobian[2] = Vector2::new(1.0, 0.0);
+        parameter_jacobian[3] = Vector2::new(0.0, 1.0);
+        parameter_jacobian[4] = Vector2::new(plane.y, 0.0);
+        let jacobian = Matrix2::new(self.focals.x, self.skew, 0.0, self.focals.y);
+        (pixel, jacobian)
+    }
+}
+
+/// The Jacobian of projecting a point in front of the camera onto the virtual image plane.
+fn plane_jacobian(point: Vector3<f64>) -> Matrix2x3<f64> {
+    let z = point.z.recip();
+    Matrix2x3::new(z, 0.0, -point.x * z * z, 0.0, z, -point.y * z * z)
+}
+
+/// The parameters are the focals, the principal point, and the skew.
+///
+/// ```
+/// use cv_core::{nalgebra::{Point2, Vector2, Vector3}, ParametricCameraModel};
+/// use cv_pinhole::CameraIntrinsics;
+///
+/// let intrinsics = CameraIntrinsics::identity()
+///     .focals(Vector2::new(800.0, 900.0))
+///     .principal_point(Point2::new(500.0, 600.0));
+/// let point = Vector3::new(0.3, -0.2, 2.0);
+/// let mut jacobian = [Vector2::zeros(); 5];
+/// let (pixel, _) = intrinsics.project_jacobians(point, &mut jacobian).unwrap();
+/// assert!((pixel - Point2::new(620.0, 510.0)).norm() < 1e-9);
+///
+/// // Check the Jacobian of the focal length in x against a central difference.
+/// let mut parameters = [0.0; 5];
+/// intrinsics.parameters(&mut parameters);
+/// let mut moved = intrinsics;
+/// parameters[0] += 1e-3;
+/// moved.set_parameters(&parameters);
+/// let (moved_pixel, _) = moved.project_jacobians(point, &mut [Vector2::zeros(); 5]).unwrap();
+/// assert!(((moved_pixel - pixel) / 1e-3 - jacobian[0]).norm() < 1e-6);
+/// ```
+impl ParametricCameraModel for CameraIntrinsics {
+    const PARAMETERS: usize = 5;
+
+    fn parameters(&self, parameters: &mut [f64]) {
+        parameters[..5].copy_from_slice(&[
+            self.focals.x,
+            self.focals.y,
+            self.principal_point.x,
+            self.principal_point.y,
+            self.skew,
+        ]);
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.focals = Vector2::new(parameters[0], parameters[1]);
+        self.principal_point = Point2::new(parameters[2], parameters[3]);
+        self.skew = parameters[4];
+    }
+
+    fn project_jacobians(
+        &self,
+        point: Vector3<f64>,
+        parameter_jacobian: &mut [Vector2<f64>],
+    ) -> Option<(Point2<f64>, Matrix2x3<f64>)> {
+        (point.z > 0.0).then(|| ())?;
+        let (pixel, jacobian) = self.project_plane(point.xy() / point.z, parameter_jacobian);
+        Some((pixel, jacobian * plane_jacobian(point)))
+    }
+}
+
 /// This contains intrinsic camera parameters as per
 /// [this Wikipedia page](https://en.wikipedia.org/wiki/Camera_resectioning#Intrinsic_parameters).
 ///
@@ -235,6 +332,73 @@ impl CameraModel for CameraIntrinsicsK1Distortion {
     }
 }
 
+/// The parameters are those of the [`CameraIntrinsics`] followed by `k1`.
+///
+/// ```
+/// use cv_core::{nalgebra::{Point2, Vector2, Vector3}, CameraModel, ParametricCameraModel};
+/// use cv_pinhole::{CameraIntrinsics, CameraIntrinsicsK1Distortion};
+///
+/// let intrinsics = CameraIntrinsicsK1Distortion::new(
+///     CameraIntrinsics::identity()
+///         .focals(Vector2::new(800.0, 900.0))
+///         .principal_point(Point2::new(500.0, 600.0)),
+///     -0.164624,
+/// );
+/// let point = Vector3::new(0.3, -0.2, 2.0);
+/// let mut jacobian = [Vector2::zeros(); 6];
+/// let (pixel, point_jacobian) = intrinsics.project_jacobians(point, &mut jacobian).unwrap();
+/// let bearing = cv_core::nalgebra::UnitVector3::new_normalize(point);
+/// assert!((pixel - intrinsics.uncalibrate(bearing).unwrap().0).norm() < 1e-6);
+///
+/// // Check the Jacobians of k1 and the point against central differences.
+/// let mut moved = intrinsics;
+/// moved.k1 += 1e-6;
+/// let (moved_pixel, _) = moved.project_jacobians(point, &mut [Vector2::zeros(); 6]).unwrap();
+/// assert!(((moved_pixel - pixel) / 1e-6 - jacobian[5]).norm() < 1e-3);
+/// let step = Vector3::new(0.0, 1e-6, 0.0);
+/// let (moved_pixel, _) = intrinsics.project_jacobians(point + step, &mut [Vector2::zeros(); 6]).unwrap();
+/// assert!(((moved_pixel - pixel) - point_jacobian * step).norm() < 1e-6);
+/// ```
+impl ParametricCameraModel for CameraIntrinsicsK1Distortion {
+    const PARAMETERS: usize = 6;
+
+    fn parameters(&self, parameters: &mut [f64]) {
+        self.simple_intrinsics.parameters(parameters);
+        parameters[5] = self.k1;
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.simple_intrinsics.set_parameters(parameters);
+        self.k1 = parameters[5];
+    }
+
+    fn project_jacobians(
+        &self,
+        point: Vector3<f64>,
+        parameter_jacobian: &mut [Vector2<f64>],
+    ) -> Option<(Point2<f64>, Matrix2x3<f64>)> {
+        (point.z > 0.0).then(|| ())?;
+        let undistorted = point.xy() / point.z;
+        // The distorted point is `d = s * u` where `s = 1 + k1 |d|²`, so `s = 1 + k1 |u|² s²`.
+        let u2 = undistorted.norm_squared();
+        let discriminant = 1.0 - 4.0 * self.k1 * u2;
+        (discriminant >= 0.0).then(|| ())?;
+        let s = 2.0 / (1.0 + Float::sqrt(discriminant));
+        // Differentiate `s` implicitly.
+        let denominator = 1.0 - 2.0 * self.k1 * u2 * s;
+        let ds_du2 = self.k1 * s * s / denominator;
+        let ds_dk1 = u2 * s * s / denominator;
+        let distorted = undistorted * s;
+        let (pixel, jacobian) = self
+            .simple_intrinsics
+            .project_plane(distorted, parameter_jacobian);
+        parameter_jacobian[5] = jacobian * undistorted * ds_dk1;
+        let distort =
+            Matrix2::identity() * s + undistorted * (2.0 * ds_du2) * undistorted.transpose();
+        Some((pixel, jacobian * distort * plane_jacobian(point)))
+    }
+}
+
 /// This contains basic camera specifications that one could find on a
 /// manufacturer's website. This only contains parameters that cannot
 /// be changed about a camera. The focal length is not included since
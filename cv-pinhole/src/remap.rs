@@ -0,0 +1,91 @@
+use crate::{CameraIntrinsics, CameraIntrinsicsFisheye};
+use alloc::vec::Vec;
+use cv_core::{nalgebra::Point2, CameraModel, KeyPoint};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel};
+use num_traits::Float;
+
+/// Remaps an image of a fisheye camera into a virtual pinhole camera with the given horizontal field of view in
+/// radians.
+///
+/// The output has the same size as the input and the intrinsics of the virtual camera, which come from
+/// [`CameraIntrinsicsFisheye::virtual_pinhole`], are returned with it. Pixels are sampled bilinearly, and pixels of
+/// the virtual camera which see outside of the fisheye image are black. Gray images stay gray and all other images
+/// become RGBA images.
+///
+/// This lets wide-angle footage feed the pinhole pipeline, at the cost of the periphery of the fisheye image which
+/// doesn't fit into the field of view.
+pub fn fisheye_to_pinhole(
+    image: &DynamicImage,
+    fisheye: &CameraIntrinsicsFisheye,
+    fov: f64,
+) -> (DynamicImage, CameraIntrinsics) {
+    let (width, height) = image.dimensions();
+    let pinhole = fisheye.virtual_pinhole(width as usize, height as usize, fov);
+    let map = remap_table(fisheye, &pinhole, width, height);
+    let remapped = match image {
+        DynamicImage::ImageLuma8(image) => DynamicImage::ImageLuma8(remap(image, &map)),
+        _ => DynamicImage::ImageRgba8(remap(&image.to_rgba8(), &map)),
+    };
+    (remapped, pinhole)
+}
+
+/// The position in the source image of every pixel of the target image, row by row.
+fn remap_table(
+    source: &impl CameraModel,
+    target: &impl CameraModel,
+    width: u32,
+    height: u32,
+) -> Vec<Option<Point2<f64>>> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let bearing = target.calibrate(KeyPoint(Point2::new(f64::from(x), f64::from(y))));
+            source.uncalibrate(bearing).map(|KeyPoint(point)| point)
+        })
+        .collect()
+}
+
+/// Samples every pixel of the target bilinearly from the source with a remap table.
+///
+/// Pixels whose position lies outside of the source stay black.
+fn remap<P>(
+    source: &ImageBuffer<P, Vec<u8>>,
+    map: &[Option<Point2<f64>>],
+) -> ImageBuffer<P, Vec<u8>>
+where
+    P: Pixel<Subpixel = u8> + 'static,
+{
+    let (width, height) = source.dimensions();
+    let mut target = ImageBuffer::new(width, height);
+    for (ix, position) in map.iter().enumerate() {
+        let position = match position {
+            Some(position) => position,
+            None => continue,
+        };
+        let (left, top) = (Float::floor(position.x), Float::floor(position.y));
+        if left < 0.0
+            || top < 0.0
+            || left + 1.0 >= f64::from(width)
+            || top + 1.0 >= f64::from(height)
+        {
+            continue;
+        }
+        let (wx, wy) = (position.x - left, position.y - top);
+        let (left, top) = (left as u32, top as u32);
+        let corners = [
+            (source.get_pixel(left, top), (1.0 - wx) * (1.0 - wy)),
+            (source.get_pixel(left + 1, top), wx * (1.0 - wy)),
+            (source.get_pixel(left, top + 1), (1.0 - wx) * wy),
+            (source.get_pixel(left + 1, top + 1), wx * wy),
+        ];
+        let output: &mut P = target.get_pixel_mut(ix as u32 % width, ix as u32 / width);
+        for (channel, out) in output.channels_mut().iter_mut().enumerate() {
+            let value: f64 = corners
+                .iter()
+                .map(|(pixel, weight)| f64::from(pixel.channels()[channel]) * weight)
+                .sum();
+            *out = Float::round(value).max(0.0).min(255.0) as u8;
+        }
+    }
+    target
+}
@@ -0,0 +1,152 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Matrix3, Rotation3, Translation3},
+    sample_consensus::{Estimator, Model},
+    CameraToCamera, FeatureMatch,
+};
+use derive_more::{AsMut, AsRef, Deref, DerefMut, From, Into};
+use num_traits::Float;
+
+/// The relative rotation between two views of a camera which did not translate, such as a camera on a tripod
+/// panning to capture a panorama.
+///
+/// Without translation there is no parallax, so every point along a bearing in the first image appears along the
+/// same bearing in the second image, and the relative pose has no translation to recover. The essential matrix
+/// `E = [t]× R` vanishes in this case, so an estimated essential matrix is dominated by noise and decomposes to a
+/// spurious translation. Use [`PureRotation::explains`] to detect when this model should be preferred.
+#[derive(Debug, Clone, Copy, PartialEq, AsMut, AsRef, Deref, DerefMut, From, Into)]
+pub struct PureRotation(pub Rotation3<f64>);
+
+impl PureRotation {
+    /// The mean angle in radians between the bearings in the second image and the rotated bearings from the first
+    /// image, which measures how much parallax (and therefore translation) is present in the matches.
+    ///
+    /// The matches should be inliers, since each outlier adds an arbitrarily large angle.
+    pub fn parallax<I>(&self, matches: I) -> f64
+    where
+        I: Iterator<Item = FeatureMatch>,
+    {
+        let (sum, count) = matches.fold((0.0, 0usize), |(sum, count), m| {
+            (sum + self.residual(&m), count + 1)
+        });
+        if count == 0 {
+            0.0
+        } else {
+            sum / count as f64
+        }
+    }
+
+    /// Checks if the matches are explained by a pure rotation with less than `threshold` radians of [parallax]
+    /// on average, in which case an essential matrix is ill-conditioned and this model should be used instead.
+    ///
+    /// The threshold should be on the order of the angular noise of the bearings.
+    ///
+    /// [parallax]: PureRotation::parallax
+    pub fn explains<I>(&self, matches: I, threshold: f64) -> bool
+    where
+        I: Iterator<Item = FeatureMatch>,
+    {
+        self.parallax(matches) < threshold
+    }
+
+    /// Creates the relative pose with no translation.
+    pub fn pose(&self) -> CameraToCamera {
+        CameraToCamera(IsometryMatrix3::from_parts(
+            Translation3::identity(),
+            self.0,
+        ))
+    }
+}
+
+impl Model<FeatureMatch> for PureRotation {
+    /// The angle between the bearing in the second image and the rotated bearing from the first image.
+    fn residual(&self, data: &FeatureMatch) -> f64 {
+        let &FeatureMatch(a, b) = data;
+        let rotated = self.0 * a;
+        Float::atan2(rotated.cross(&b).norm(), rotated.dot(&b))
+    }
+}
+
+/// Estimates a [`PureRotation`] from two or more matches.
+///
+/// The rotation which best aligns the bearings in the least-squares sense is found in closed form with the
+/// Kabsch algorithm.
+///
+/// ```
+/// use cv_core::{nalgebra::{Rotation3, UnitVector3, Vector3}, sample_consensus::Model, FeatureMatch};
+/// use cv_pinhole::TwoPointRotation;
+/// let rotation = Rotation3::from_euler_angles(0.1, -0.3, 0.2);
+/// let matches = [Vector3::new(0.1, 0.2, 1.0), Vector3::new(-0.4, 0.1, 1.0), Vector3::new(0.3, -0.2, 1.0)]
+///     .iter()
+///     .map(|&a| FeatureMatch(UnitVector3::new_normalize(a), UnitVector3::new_normalize(rotation * a)));
+/// let estimate = TwoPointRotation::new().from_matches(matches.clone()).unwrap();
+/// assert!(estimate.0.rotation_to(&rotation).angle() < 1e-9);
+/// assert!(estimate.explains(matches, 1e-6));
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct TwoPointRotation {
+    pub epsilon: f64,
+    pub iterations: usize,
+}
+
+impl TwoPointRotation {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn from_matches<I>(&self, data: I) -> Option<PureRotation>
+    where
+        I: Iterator<Item = FeatureMatch>,
+    {
+        let (correlation, count) = data.fold(
+            (Matrix3::zeros(), 0usize),
+            |(correlation, count), FeatureMatch(a, b)| {
+                (correlation + b.into_inner() * a.transpose(), count + 1)
+            },
+        );
+        if count < 2 {
+            return None;
+        }
+        // Unlike a homography, the sign of the correlation matters, and with two matches its determinant is zero
+        // up to noise, so only a reflection of the smallest singular vector is corrected.
+        closest_rotation(correlation, self.epsilon, self.iterations).map(PureRotation)
+    }
+}
+
+/// Finds the rotation closest to `matrix` in the Frobenius norm.
+///
+/// With the singular value decomposition `U * S * transpose(V)`, this is `U * transpose(V)`, except that the last
+/// column of `U` is negated if that would be a reflection.
+pub(crate) fn closest_rotation(
+    matrix: Matrix3<f64>,
+    epsilon: f64,
+    max_iterations: usize,
+) -> Option<Rotation3<f64>> {
+    let svd = matrix.try_svd(true, true, epsilon, max_iterations)?;
+    let u = svd.u?;
+    let v_t = svd.v_t?;
+    let mut correction = Matrix3::identity();
+    correction[(2, 2)] = (u * v_t).determinant().signum();
+    Some(Rotation3::from_matrix_unchecked(u * correction * v_t))
+}
+
+impl Default for TwoPointRotation {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-12,
+            iterations: 1000,
+        }
+    }
+}
+
+impl Estimator<FeatureMatch> for TwoPointRotation {
+    type Model = PureRotation;
+    type ModelIter = Option<PureRotation>;
+    const MIN_SAMPLES: usize = 2;
+
+    fn estimate<I>(&self, data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = FeatureMatch> + Clone,
+    {
+        self.from_matches(data)
+    }
+}
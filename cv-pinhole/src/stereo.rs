@@ -0,0 +1,129 @@
+use crate::CameraIntrinsics;
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Matrix3, Point2, Rotation3, Translation3, Vector3, Vector4},
+    CameraModel, CameraPoint, CameraRig, ImagePoint, KeyPoint, Projective,
+};
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// A rectified stereo pair of cameras, which share the same intrinsics and have image rows aligned with the
+/// baseline, so that a feature at `(u, v)` in the left image appears at `(u - d, v)` in the right image.
+///
+/// The disparity `d` of a feature is inversely proportional to its depth, giving `Z = fx * baseline / d`, so stereo
+/// front-ends can compute points directly from disparities instead of going through two-view triangulation.
+/// Points are in the reference frame of the left camera.
+///
+/// ```
+/// use cv_core::{nalgebra::{Point2, Point3}, CameraPoint, Projective};
+/// use cv_pinhole::{CameraIntrinsics, StereoRig};
+///
+/// let intrinsics = CameraIntrinsics::identity().focal(700.0).principal_point(Point2::new(640.0, 360.0));
+/// let rig = StereoRig::new(intrinsics, 0.12);
+/// let point = CameraPoint::from_point(Point3::new(0.5, -0.2, 6.0));
+/// let disparity = rig.disparity(point).unwrap();
+/// let keypoint = rig.left_keypoint(point).unwrap();
+/// let triangulated = rig.point(keypoint, disparity).unwrap();
+/// assert!((triangulated.point().unwrap() - point.point().unwrap()).norm() < 1e-9);
+///
+/// // A disparity of zero is a point at infinity.
+/// assert!(rig.point(keypoint, 0.0).unwrap().point().is_none());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct StereoRig {
+    /// The intrinsics of both rectified cameras.
+    pub intrinsics: CameraIntrinsics,
+    /// The distance from the left camera to the right camera along the positive X axis.
+    pub baseline: f64,
+}
+
+impl StereoRig {
+    pub fn new(intrinsics: CameraIntrinsics, baseline: f64) -> Self {
+        Self {
+            intrinsics,
+            baseline,
+        }
+    }
+
+    /// The rig with the left camera at the origin and the right camera translated by the baseline.
+    pub fn rig(&self) -> CameraRig<2> {
+        CameraRig::new([
+            IsometryMatrix3::identity(),
+            IsometryMatrix3::from_parts(
+                Translation3::new(-self.baseline, 0.0, 0.0),
+                Rotation3::identity(),
+            ),
+        ])
+    }
+
+    /// Projects a point into the left image.
+    pub fn left_keypoint(&self, point: CameraPoint) -> Option<KeyPoint> {
+        self.intrinsics.uncalibrate(point.bearing())
+    }
+
+    /// The disparity of a point in front of the cameras in pixels.
+    pub fn disparity(&self, point: CameraPoint) -> Option<f64> {
+        let homogeneous = point.homogeneous();
+        (homogeneous.z > 0.0)
+            .then(|| self.intrinsics.focals.x * self.baseline * homogeneous.w / homogeneous.z)
+    }
+
+    /// The point observed at `left` in the left image with a `disparity` in pixels.
+    ///
+    /// A disparity of zero gives a point at infinity, while negative disparities fail.
+    pub fn point(&self, left: impl ImagePoint, disparity: f64) -> Option<CameraPoint> {
+        if disparity.is_sign_negative() || !disparity.is_finite() {
+            return None;
+        }
+        let normalized = self.normalized(left.image_point());
+        Some(CameraPoint::from_homogeneous(Vector4::new(
+            normalized.x,
+            normalized.y,
+            1.0,
+            disparity / (self.intrinsics.focals.x * self.baseline),
+        )))
+    }
+
+    /// The point observed at `left` with a `disparity` along with its 3x3 covariance, propagated to first order
+    /// from the standard deviations of the keypoint and of the disparity in pixels.
+    ///
+    /// This fails for points at infinity. The depth uncertainty grows with the square of the depth, so the
+    /// covariance is useful to discard or downweight distant points.
+    pub fn point_covariance(
+        &self,
+        left: impl ImagePoint,
+        disparity: f64,
+        keypoint_noise: f64,
+        disparity_noise: f64,
+    ) -> Option<(CameraPoint, Matrix3<f64>)> {
+        let point = self.point(left, disparity)?;
+        let coords = point.point()?.coords;
+        let (fx, fy) = (self.intrinsics.focals.x, self.intrinsics.focals.y);
+        let z = coords.z;
+        // The Jacobian of the point in respect to the keypoint and disparity.
+        let jacobian = Matrix3::new(
+            z / fx,
+            -self.intrinsics.skew * z / (fx * fy),
+            -coords.x / disparity,
+            0.0,
+            z / fy,
+            -coords.y / disparity,
+            0.0,
+            0.0,
+            -z / disparity,
+        );
+        let noise = Matrix3::from_diagonal(&Vector3::new(
+            keypoint_noise * keypoint_noise,
+            keypoint_noise * keypoint_noise,
+            disparity_noise * disparity_noise,
+        ));
+        Some((point, jacobian * noise * jacobian.transpose()))
+    }
+
+    /// Removes the intrinsics from a keypoint, giving the point on the normalized image plane.
+    fn normalized(&self, point: Point2<f64>) -> Point2<f64> {
+        let bearing = self.intrinsics.calibrate(KeyPoint(point));
+        Point2::from(bearing.xy() / bearing.z)
+    }
+}
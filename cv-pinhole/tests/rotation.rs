@@ -0,0 +1,33 @@
+use cv_core::{
+    nalgebra::{Rotation3, UnitVector3, Vector3},
+    FeatureMatch,
+};
+use cv_pinhole::TwoPointRotation;
+
+/// With only two matches the correlation matrix has rank two, so the sign of its determinant is decided by noise.
+#[test]
+fn two_noisy_matches() {
+    for k in 0..100 {
+        let k = k as f64;
+        let rotation = Rotation3::from_euler_angles(
+            0.3 * k.sin(),
+            0.5 * (1.3 * k).cos(),
+            0.7 * (0.7 * k).sin(),
+        );
+        let noise = |seed: f64| {
+            Rotation3::new(Vector3::new(seed.sin(), (2.0 * seed).cos(), (3.0 * seed).sin()) * 1e-4)
+        };
+        let bearings = [
+            Vector3::new(0.1 * k.cos(), 0.2, 1.0),
+            Vector3::new(-0.3, 0.2 * k.sin(), 1.0),
+        ];
+        let matches = bearings.iter().enumerate().map(|(ix, &a)| {
+            FeatureMatch(
+                UnitVector3::new_normalize(a),
+                noise(k + ix as f64) * UnitVector3::new_normalize(rotation * a),
+            )
+        });
+        let estimate = TwoPointRotation::new().from_matches(matches).unwrap();
+        assert!(estimate.0.rotation_to(&rotation).angle() < 1e-2);
+    }
+}
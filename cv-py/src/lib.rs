@@ -0,0 +1,339 @@
+//! # `cv-py`
+//!
+//! Python bindings for AKAZE extraction, descriptor matching, pose estimation and triangulation.
+//!
+//! Every function takes and returns numpy arrays, following the conventions of OpenCV where possible: points are
+//! stored one per row, camera intrinsics are `3 x 3` matrices, and poses are a rotation matrix and a translation
+//! which map points from the first frame into the second. The GIL is released while the Rust code runs, so the
+//! functions can be called from several Python threads.
+
+use akaze::{image::GrayFloatImage, Akaze};
+use arrsac::Arrsac;
+use bitarray::{BitArray, Hamming};
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Matrix3, Point2, Point3, Rotation3, Translation3, Vector2},
+    sample_consensus::Consensus,
+    CameraModel, CameraToCamera, FeatureMatch, FeatureWorldMatch, KeyPoint, Pose, Projective,
+    TriangulatorRelative, WorldPoint,
+};
+use cv_geom::triangulation::LinearEigenTriangulator;
+use cv_pinhole::CameraIntrinsics;
+use ndarray::{Array1, Array2};
+use numpy::{IntoPyArray, PyArray1, PyArray2, PyReadonlyArray1, PyReadonlyArray2};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use space::Knn;
+
+/// A rotation matrix, a translation and the indices of the inliers.
+type PoseArrays<'py> = (&'py PyArray2<f64>, &'py PyArray1<f64>, &'py PyArray1<i64>);
+
+/// Extract AKAZE features from a gray image.
+///
+/// The image is a 2D `uint8` array or a 2D `float32` array with values between 0 and 1. Returns the keypoints as
+/// an `N x 6` `float32` array with the columns `x`, `y`, `size`, `angle`, `response` and `octave`, and the
+/// descriptors as an `N x 64` `uint8` array.
+#[pyfunction]
+#[pyo3(text_signature = "(image, threshold=0.001)")]
+#[args(threshold = "0.001")]
+fn extract<'py>(
+    py: Python<'py>,
+    image: &PyAny,
+    threshold: f64,
+) -> PyResult<(&'py PyArray2<f32>, &'py PyArray2<u8>)> {
+    let image = if let Ok(image) = image.extract::<PyReadonlyArray2<u8>>() {
+        GrayFloatImage::from_ndarray(image.as_array().mapv(|pixel| f32::from(pixel) / 255.0))
+    } else {
+        let image: PyReadonlyArray2<f32> = image.extract()?;
+        GrayFloatImage::from_ndarray_view(image.as_array())
+    };
+    let (keypoints, descriptors) = py.allow_threads(|| Akaze::new(threshold).extract_gray(image));
+    let keypoints = Array2::from_shape_fn((keypoints.len(), 6), |(ix, column)| {
+        let keypoint = &keypoints[ix];
+        match column {
+            0 => keypoint.point.0,
+            1 => keypoint.point.1,
+            2 => keypoint.size,
+            3 => keypoint.angle,
+            4 => keypoint.response,
+            _ => keypoint.octave as f32,
+        }
+    });
+    let descriptors =
+        Array2::from_shape_fn((descriptors.len(), 64), |(ix, byte)| descriptors[ix][byte]);
+    Ok((keypoints.into_pyarray(py), descriptors.into_pyarray(py)))
+}
+
+/// Match two sets of AKAZE descriptors by Hamming distance with Lowe's ratio test.
+///
+/// Every descriptor of the first set is matched to its nearest neighbor in the second set if the distance to the
+/// nearest neighbor is below `ratio` times the distance to the second nearest neighbor. Returns an `M x 2` `int64`
+/// array of the indices of the matched descriptors.
+#[pyfunction]
+#[pyo3(text_signature = "(first, second, ratio=0.7)")]
+#[args(ratio = "0.7")]
+fn match_descriptors<'py>(
+    py: Python<'py>,
+    first: PyReadonlyArray2<u8>,
+    second: PyReadonlyArray2<u8>,
+    ratio: f32,
+) -> PyResult<&'py PyArray2<i64>> {
+    let first = descriptors(first)?;
+    let second = descriptors(second)?;
+    let matches: Vec<(usize, usize)> = py.allow_threads(|| {
+        first
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, descriptor)| {
+                let neighbors = space::LinearKnn {
+                    metric: Hamming,
+                    iter: second.iter(),
+                }
+                .knn(descriptor, 2);
+                match neighbors.as_slice() {
+                    [best, next] if (best.distance as f32) < next.distance as f32 * ratio => {
+                        Some((ix, best.index))
+                    }
+                    [best] => Some((ix, best.index)),
+                    _ => None,
+                }
+            })
+            .collect()
+    });
+    let matches = Array2::from_shape_fn((matches.len(), 2), |(ix, column)| {
+        let (a, b) = matches[ix];
+        if column == 0 {
+            a as i64
+        } else {
+            b as i64
+        }
+    });
+    Ok(matches.into_pyarray(py))
+}
+
+/// Estimate the relative pose of two cameras from matching image points with the eight-point algorithm and
+/// ARRSAC.
+///
+/// `first` and `second` are `N x 2` arrays of matching pixel coordinates and `intrinsics` is the `3 x 3` camera
+/// matrix of both cameras. Returns the rotation matrix, the unit translation and the indices of the inlier matches,
+/// or `None` if no pose could be estimated.
+#[pyfunction]
+#[pyo3(text_signature = "(first, second, intrinsics, threshold=0.001)")]
+#[args(threshold = "0.001")]
+fn estimate_essential<'py>(
+    py: Python<'py>,
+    first: PyReadonlyArray2<f64>,
+    second: PyReadonlyArray2<f64>,
+    intrinsics: PyReadonlyArray2<f64>,
+    threshold: f64,
+) -> PyResult<Option<PoseArrays<'py>>> {
+    let intrinsics = camera_intrinsics(intrinsics)?;
+    let first = image_points(first)?;
+    let second = image_points(second)?;
+    if first.len() != second.len() {
+        return Err(PyValueError::new_err(
+            "the point arrays must have the same length",
+        ));
+    }
+    let matches: Vec<FeatureMatch> = first
+        .iter()
+        .zip(&second)
+        .map(|(&a, &b)| FeatureMatch(intrinsics.calibrate(a), intrinsics.calibrate(b)))
+        .collect();
+    let estimate = py.allow_threads(|| {
+        Arrsac::new(threshold, Pcg64::from_seed([1; 32]))
+            .model_inliers(&eight_point::EightPoint::new(), matches.iter().copied())
+    });
+    Ok(estimate.map(|(pose, inliers)| {
+        let pose = pose.isometry();
+        pose_arrays(
+            py,
+            pose.rotation,
+            pose.translation.vector.normalize().into(),
+            inliers,
+        )
+    }))
+}
+
+/// Estimate the pose of a camera from world points and their pixel coordinates with Lambda Twist and ARRSAC.
+///
+/// `world` is an `N x 3` array of world points, `image` is an `N x 2` array of their pixel coordinates and
+/// `intrinsics` is the `3 x 3` camera matrix. Returns the rotation matrix and translation which map world points
+/// into the camera and the indices of the inliers, or `None` if no pose could be estimated.
+#[pyfunction]
+#[pyo3(text_signature = "(world, image, intrinsics, threshold=0.001)")]
+#[args(threshold = "0.001")]
+fn estimate_pnp<'py>(
+    py: Python<'py>,
+    world: PyReadonlyArray2<f64>,
+    image: PyReadonlyArray2<f64>,
+    intrinsics: PyReadonlyArray2<f64>,
+    threshold: f64,
+) -> PyResult<Option<PoseArrays<'py>>> {
+    let intrinsics = camera_intrinsics(intrinsics)?;
+    let world = world.as_array();
+    if world.ncols() != 3 {
+        return Err(PyValueError::new_err(
+            "the world points must be an N x 3 array",
+        ));
+    }
+    let image = image_points(image)?;
+    if world.nrows() != image.len() {
+        return Err(PyValueError::new_err(
+            "the point arrays must have the same length",
+        ));
+    }
+    let matches: Vec<FeatureWorldMatch> = world
+        .rows()
+        .into_iter()
+        .zip(&image)
+        .map(|(point, &keypoint)| {
+            FeatureWorldMatch(
+                intrinsics.calibrate(keypoint),
+                WorldPoint::from_point(Point3::new(point[0], point[1], point[2])),
+            )
+        })
+        .collect();
+    let estimate = py.allow_threads(|| {
+        Arrsac::new(threshold, Pcg64::from_seed([1; 32]))
+            .model_inliers(&lambda_twist::LambdaTwist::new(), matches.iter().copied())
+    });
+    Ok(estimate.map(|(pose, inliers)| {
+        let pose = pose.isometry();
+        pose_arrays(py, pose.rotation, pose.translation, inliers)
+    }))
+}
+
+/// Triangulate matching image points of two cameras with a known relative pose.
+///
+/// `first` and `second` are `N x 2` arrays of matching pixel coordinates, `intrinsics` is the `3 x 3` camera matrix
+/// of both cameras, and `rotation` and `translation` map points from the first camera into the second. Returns an
+/// `N x 3` array of points in the frame of the first camera, where points which could not be triangulated are
+/// `NaN`.
+#[pyfunction]
+#[pyo3(text_signature = "(first, second, intrinsics, rotation, translation)")]
+fn triangulate<'py>(
+    py: Python<'py>,
+    first: PyReadonlyArray2<f64>,
+    second: PyReadonlyArray2<f64>,
+    intrinsics: PyReadonlyArray2<f64>,
+    rotation: PyReadonlyArray2<f64>,
+    translation: PyReadonlyArray1<f64>,
+) -> PyResult<&'py PyArray2<f64>> {
+    let intrinsics = camera_intrinsics(intrinsics)?;
+    let first = image_points(first)?;
+    let second = image_points(second)?;
+    if first.len() != second.len() {
+        return Err(PyValueError::new_err(
+            "the point arrays must have the same length",
+        ));
+    }
+    let pose = CameraToCamera(IsometryMatrix3::from_parts(
+        translation_vector(translation)?,
+        Rotation3::from_matrix(&matrix3(rotation)?),
+    ));
+    let points: Vec<Option<Point3<f64>>> = py.allow_threads(|| {
+        let triangulator = LinearEigenTriangulator::new();
+        first
+            .iter()
+            .zip(&second)
+            .map(|(&a, &b)| {
+                triangulator
+                    .triangulate_relative(pose, intrinsics.calibrate(a), intrinsics.calibrate(b))
+                    .and_then(|point| point.point())
+            })
+            .collect()
+    });
+    let points = Array2::from_shape_fn((points.len(), 3), |(ix, axis)| {
+        points[ix].map_or(f64::NAN, |point| point[axis])
+    });
+    Ok(points.into_pyarray(py))
+}
+
+fn descriptors(array: PyReadonlyArray2<u8>) -> PyResult<Vec<BitArray<64>>> {
+    let array = array.as_array();
+    if array.ncols() != 64 {
+        return Err(PyValueError::new_err(
+            "the descriptors must be an N x 64 array",
+        ));
+    }
+    Ok(array
+        .rows()
+        .into_iter()
+        .map(|row| {
+            let mut descriptor = BitArray::zeros();
+            for (byte, &value) in descriptor.iter_mut().zip(row) {
+                *byte = value;
+            }
+            descriptor
+        })
+        .collect())
+}
+
+fn image_points(array: PyReadonlyArray2<f64>) -> PyResult<Vec<KeyPoint>> {
+    let array = array.as_array();
+    if array.ncols() != 2 {
+        return Err(PyValueError::new_err(
+            "the image points must be an N x 2 array",
+        ));
+    }
+    Ok(array
+        .rows()
+        .into_iter()
+        .map(|row| KeyPoint(Point2::new(row[0], row[1])))
+        .collect())
+}
+
+fn matrix3(array: PyReadonlyArray2<f64>) -> PyResult<Matrix3<f64>> {
+    let array = array.as_array();
+    if array.dim() != (3, 3) {
+        return Err(PyValueError::new_err("the matrix must be a 3 x 3 array"));
+    }
+    Ok(Matrix3::from_fn(|row, column| array[(row, column)]))
+}
+
+fn translation_vector(array: PyReadonlyArray1<f64>) -> PyResult<Translation3<f64>> {
+    let array = array.as_array();
+    if array.len() != 3 {
+        return Err(PyValueError::new_err(
+            "the translation must have 3 elements",
+        ));
+    }
+    Ok(Translation3::new(array[0], array[1], array[2]))
+}
+
+fn camera_intrinsics(array: PyReadonlyArray2<f64>) -> PyResult<CameraIntrinsics> {
+    let matrix = matrix3(array)?;
+    Ok(CameraIntrinsics {
+        focals: Vector2::new(matrix.m11, matrix.m22),
+        principal_point: Point2::new(matrix.m13, matrix.m23),
+        skew: matrix.m12,
+    })
+}
+
+fn pose_arrays(
+    py: Python<'_>,
+    rotation: Rotation3<f64>,
+    translation: Translation3<f64>,
+    inliers: impl IntoIterator<Item = usize>,
+) -> PoseArrays<'_> {
+    let rotation = Array2::from_shape_fn((3, 3), |(row, column)| rotation[(row, column)]);
+    let translation = Array1::from_iter(translation.vector.iter().copied());
+    let inliers: Array1<i64> = inliers.into_iter().map(|ix| ix as i64).collect();
+    (
+        rotation.into_pyarray(py),
+        translation.into_pyarray(py),
+        inliers.into_pyarray(py),
+    )
+}
+
+/// Rust CV bindings for Python.
+#[pymodule]
+fn cv_py(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(extract, module)?)?;
+    module.add_function(wrap_pyfunction!(match_descriptors, module)?)?;
+    module.add_function(wrap_pyfunction!(estimate_essential, module)?)?;
+    module.add_function(wrap_pyfunction!(estimate_pnp, module)?)?;
+    module.add_function(wrap_pyfunction!(triangulate, module)?)?;
+    Ok(())
+}
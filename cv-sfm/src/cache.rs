@@ -0,0 +1,597 @@
+//! A compact binary format for caching the features of images and the matches between them.
+//!
+//! Extracting and matching features are the most expensive steps of structure from motion on small datasets, and
+//! their results only depend on the images and the settings, so they can be saved once and reused between runs or
+//! shared across machines. Unlike the map format, these files have a fixed little-endian layout which does not
+//! depend on `bincode`, so they can be memory-mapped with [`MappedFeatures`] and [`MappedMatches`] and single
+//! keypoints, descriptors or image pairs read without loading the whole file.
+//!
+//! A feature file starts with the magic bytes `CVFT`, a `u32` version and the `u64` number of features, followed by
+//! a record of every keypoint and then the 64 bytes of every descriptor. A keypoint record consists of the `f32`
+//! coordinates `x` and `y`, the `f32` response, size and angle, and the `u32` octave and class ID.
+//!
+//! A match file starts with the magic bytes `CVMT`, a `u32` version, the `u64` number of images and the `u64` number
+//! of image pairs, followed by a table with the `u32` images `a < b` and the `u64` number of matches of every pair,
+//! and then the matches of every pair in the order of the table. A match consists of the `u32` query and target
+//! features and the `f32` distance.
+//!
+//! When a format changes its version is increased, and files saved with earlier versions are still loaded.
+
+use crate::{
+    matching::{DescriptorMatch, MatchMatrix},
+    SfmImage,
+};
+use akaze::KeyPoint;
+use bitarray::BitArray;
+use cv_core::CameraModel;
+use image::DynamicImage;
+use memmap2::Mmap;
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    ops::Range,
+    path::Path,
+};
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The magic bytes at the beginning of saved [`ImageFeatures`].
+const FEATURES_MAGIC: [u8; 4] = *b"CVFT";
+/// The version of the saved [`ImageFeatures`] format.
+const FEATURES_VERSION: u32 = 1;
+/// The size of the header of saved [`ImageFeatures`].
+const FEATURES_HEADER_SIZE: usize = 16;
+/// The size of a saved keypoint.
+const KEYPOINT_SIZE: usize = 28;
+/// The size of a saved descriptor.
+const DESCRIPTOR_SIZE: usize = 64;
+
+/// The magic bytes at the beginning of a saved [`MatchMatrix`].
+const MATCHES_MAGIC: [u8; 4] = *b"CVMT";
+/// The version of the saved [`MatchMatrix`] format.
+const MATCHES_VERSION: u32 = 1;
+/// The size of the header of a saved [`MatchMatrix`].
+const MATCHES_HEADER_SIZE: usize = 24;
+/// The size of an entry in the pair table of a saved [`MatchMatrix`].
+const PAIR_SIZE: usize = 16;
+/// The size of a saved match.
+const MATCH_SIZE: usize = 12;
+
+/// The keypoints and descriptors extracted from one image, where `keypoints[i]` has the descriptor `descriptors[i]`.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct ImageFeatures {
+    pub keypoints: Vec<KeyPoint>,
+    pub descriptors: Vec<BitArray<64>>,
+}
+
+impl ImageFeatures {
+    /// Creates the features of an image from the output of [`akaze::Akaze::extract`].
+    pub fn new(keypoints: Vec<KeyPoint>, descriptors: Vec<BitArray<64>>) -> Self {
+        assert_eq!(
+            keypoints.len(),
+            descriptors.len(),
+            "every keypoint must have one descriptor"
+        );
+        Self {
+            keypoints,
+            descriptors,
+        }
+    }
+
+    /// The number of features.
+    pub fn len(&self) -> usize {
+        self.keypoints.len()
+    }
+
+    /// Whether there are no features.
+    pub fn is_empty(&self) -> bool {
+        self.keypoints.is_empty()
+    }
+
+    /// Calibrates the keypoints with the intrinsics of the camera which took the image to give the features to
+    /// [`IncrementalSfm`](crate::IncrementalSfm).
+    pub fn sfm_image(&self, intrinsics: &impl CameraModel) -> SfmImage<BitArray<64>> {
+        SfmImage::new(
+            self.keypoints
+                .iter()
+                .map(|&keypoint| intrinsics.calibrate(keypoint))
+                .collect(),
+            self.descriptors.clone(),
+        )
+    }
+
+    /// Calibrates the keypoints like [`ImageFeatures::sfm_image`] and samples their colors from the image they were
+    /// extracted from.
+    pub fn colored_sfm_image(
+        &self,
+        intrinsics: &impl CameraModel,
+        image: &DynamicImage,
+    ) -> SfmImage<BitArray<64>> {
+        SfmImage::from_keypoints(intrinsics, image, &self.keypoints, self.descriptors.clone())
+    }
+
+    /// Saves the features to a writer in a versioned binary format which can be memory-mapped with [`MappedFeatures`].
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&FEATURES_MAGIC)?;
+        writer.write_all(&FEATURES_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+        for keypoint in &self.keypoints {
+            writer.write_all(&encode_keypoint(keypoint))?;
+        }
+        for descriptor in &self.descriptors {
+            writer.write_all(&descriptor.bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Loads features saved with [`ImageFeatures::save`] by this or any earlier version of the format.
+    pub fn load(mut reader: impl Read) -> io::Result<Self> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let len = features_len(&bytes)?;
+        Ok(Self {
+            keypoints: (0..len).map(|ix| decode_keypoint(&bytes, ix)).collect(),
+            descriptors: (0..len)
+                .map(|ix| decode_descriptor(&bytes, len, ix))
+                .collect(),
+        })
+    }
+
+    /// Saves the features to a file.
+    pub fn save_path(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads the features from a file.
+    pub fn load_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::load(BufReader::new(File::open(path)?))
+    }
+}
+
+impl From<(Vec<KeyPoint>, Vec<BitArray<64>>)> for ImageFeatures {
+    fn from((keypoints, descriptors): (Vec<KeyPoint>, Vec<BitArray<64>>)) -> Self {
+        Self::new(keypoints, descriptors)
+    }
+}
+
+/// The features of a file saved with [`ImageFeatures::save`], which are read from a memory map on demand.
+///
+/// The file must not be modified while it is mapped.
+pub struct MappedFeatures {
+    map: Mmap,
+    len: usize,
+}
+
+impl MappedFeatures {
+    /// Maps a file of features into memory and checks its header and size.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: the map is only read, and the documentation requires the file to not be modified while mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        let len = features_len(&map)?;
+        Ok(Self { map, len })
+    }
+
+    /// The number of features.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no features.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The keypoint of the feature `ix`.
+    pub fn keypoint(&self, ix: usize) -> KeyPoint {
+        assert!(ix < self.len, "feature index out of bounds");
+        decode_keypoint(&self.map, ix)
+    }
+
+    /// The descriptor of the feature `ix`.
+    pub fn descriptor(&self, ix: usize) -> BitArray<64> {
+        assert!(ix < self.len, "feature index out of bounds");
+        decode_descriptor(&self.map, self.len, ix)
+    }
+
+    /// Iterates over all of the keypoints.
+    pub fn keypoints(&self) -> impl Iterator<Item = KeyPoint> + '_ {
+        (0..self.len).map(move |ix| decode_keypoint(&self.map, ix))
+    }
+
+    /// Iterates over all of the descriptors.
+    pub fn descriptors(&self) -> impl Iterator<Item = BitArray<64>> + '_ {
+        (0..self.len).map(move |ix| decode_descriptor(&self.map, self.len, ix))
+    }
+
+    /// Reads all of the features into memory.
+    pub fn to_features(&self) -> ImageFeatures {
+        ImageFeatures {
+            keypoints: self.keypoints().collect(),
+            descriptors: self.descriptors().collect(),
+        }
+    }
+}
+
+impl MatchMatrix {
+    /// Saves the matches to a writer in a versioned binary format which can be memory-mapped with [`MappedMatches`].
+    ///
+    /// The pairs are saved in order, so the same matches always produce the same file.
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut pairs: Vec<_> = self.pairs.iter().collect();
+        pairs.sort_unstable_by_key(|&(&pair, _)| pair);
+        writer.write_all(&MATCHES_MAGIC)?;
+        writer.write_all(&MATCHES_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.num_images as u64).to_le_bytes())?;
+        writer.write_all(&(pairs.len() as u64).to_le_bytes())?;
+        for (&(a, b), matches) in &pairs {
+            writer.write_all(&(a as u32).to_le_bytes())?;
+            writer.write_all(&(b as u32).to_le_bytes())?;
+            writer.write_all(&(matches.len() as u64).to_le_bytes())?;
+        }
+        for (_, matches) in pairs {
+            for m in matches {
+                writer.write_all(&(m.query as u32).to_le_bytes())?;
+                writer.write_all(&(m.target as u32).to_le_bytes())?;
+                writer.write_all(&m.distance.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads matches saved with [`MatchMatrix::save`] by this or any earlier version of the format.
+    pub fn load(mut reader: impl Read) -> io::Result<Self> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let (num_images, table) = match_table(&bytes)?;
+        Ok(Self {
+            num_images,
+            pairs: table
+                .into_iter()
+                .map(|(pair, range)| (pair, decode_matches(&bytes, range).collect()))
+                .collect(),
+        })
+    }
+
+    /// Saves the matches to a file.
+    pub fn save_path(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads the matches from a file.
+    pub fn load_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::load(BufReader::new(File::open(path)?))
+    }
+}
+
+/// The matches of a file saved with [`MatchMatrix::save`], of which only the pair table is read up front and the
+/// matches of each pair are read from a memory map on demand.
+///
+/// The file must not be modified while it is mapped.
+pub struct MappedMatches {
+    map: Mmap,
+    num_images: usize,
+    pairs: HashMap<(usize, usize), Range<usize>>,
+}
+
+impl MappedMatches {
+    /// Maps a file of matches into memory and reads its pair table.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: the map is only read, and the documentation requires the file to not be modified while mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        let (num_images, pairs) = match_table(&map)?;
+        Ok(Self {
+            map,
+            num_images,
+            pairs: pairs.into_iter().collect(),
+        })
+    }
+
+    /// The number of images in the set.
+    pub fn num_images(&self) -> usize {
+        self.num_images
+    }
+
+    /// Iterates over the matched image pairs `(a, b)` with `a < b` and their number of matches.
+    pub fn pairs(&self) -> impl Iterator<Item = ((usize, usize), usize)> + '_ {
+        self.pairs
+            .iter()
+            .map(|(&pair, range)| (pair, range.len() / MATCH_SIZE))
+    }
+
+    /// The matches from image `a` (query) to image `b` (target) for `a < b`.
+    ///
+    /// Returns `None` if the pair was not matched.
+    pub fn matches(
+        &self,
+        a: usize,
+        b: usize,
+    ) -> Option<impl Iterator<Item = DescriptorMatch> + '_> {
+        let range = self.pairs.get(&(a, b))?.clone();
+        Some(decode_matches(&self.map, range))
+    }
+
+    /// Reads all of the matches into memory.
+    pub fn to_match_matrix(&self) -> MatchMatrix {
+        MatchMatrix {
+            num_images: self.num_images,
+            pairs: self
+                .pairs
+                .iter()
+                .map(|(&pair, range)| (pair, decode_matches(&self.map, range.clone()).collect()))
+                .collect(),
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Checks the magic bytes and returns the version of a file.
+fn version(bytes: &[u8], magic: [u8; 4], kind: &str) -> io::Result<u32> {
+    if bytes.len() < 8 || bytes[..4] != magic {
+        return Err(invalid(format!("not a {} file", kind)));
+    }
+    Ok(read_u32(bytes, 4))
+}
+
+/// Checks the header and size of saved features and returns the number of features.
+fn features_len(bytes: &[u8]) -> io::Result<usize> {
+    match version(bytes, FEATURES_MAGIC, "features")? {
+        FEATURES_VERSION => {}
+        version => return Err(invalid(format!("unsupported features version {}", version))),
+    }
+    if bytes.len() < FEATURES_HEADER_SIZE {
+        return Err(invalid("truncated features header"));
+    }
+    let len = read_u64(bytes, 8) as usize;
+    let size = len
+        .checked_mul(KEYPOINT_SIZE + DESCRIPTOR_SIZE)
+        .and_then(|size| size.checked_add(FEATURES_HEADER_SIZE));
+    if size != Some(bytes.len()) {
+        return Err(invalid(format!(
+            "features file has {} bytes, which does not match its {} features",
+            bytes.len(),
+            len
+        )));
+    }
+    Ok(len)
+}
+
+fn encode_keypoint(keypoint: &KeyPoint) -> [u8; KEYPOINT_SIZE] {
+    let mut bytes = [0; KEYPOINT_SIZE];
+    let (x, y) = keypoint.point;
+    bytes[0..4].copy_from_slice(&x.to_le_bytes());
+    bytes[4..8].copy_from_slice(&y.to_le_bytes());
+    bytes[8..12].copy_from_slice(&keypoint.response.to_le_bytes());
+    bytes[12..16].copy_from_slice(&keypoint.size.to_le_bytes());
+    bytes[16..20].copy_from_slice(&keypoint.angle.to_le_bytes());
+    bytes[20..24].copy_from_slice(&(keypoint.octave as u32).to_le_bytes());
+    bytes[24..28].copy_from_slice(&(keypoint.class_id as u32).to_le_bytes());
+    bytes
+}
+
+fn decode_keypoint(bytes: &[u8], ix: usize) -> KeyPoint {
+    let offset = FEATURES_HEADER_SIZE + ix * KEYPOINT_SIZE;
+    KeyPoint {
+        point: (read_f32(bytes, offset), read_f32(bytes, offset + 4)),
+        response: read_f32(bytes, offset + 8),
+        size: read_f32(bytes, offset + 12),
+        angle: read_f32(bytes, offset + 16),
+        octave: read_u32(bytes, offset + 20) as usize,
+        class_id: read_u32(bytes, offset + 24) as usize,
+    }
+}
+
+fn decode_descriptor(bytes: &[u8], len: usize, ix: usize) -> BitArray<64> {
+    let offset = FEATURES_HEADER_SIZE + len * KEYPOINT_SIZE + ix * DESCRIPTOR_SIZE;
+    BitArray::new(bytes[offset..offset + DESCRIPTOR_SIZE].try_into().unwrap())
+}
+
+/// Checks the header and size of saved matches and returns the number of images and the byte range of the matches
+/// of every pair.
+#[allow(clippy::type_complexity)]
+fn match_table(bytes: &[u8]) -> io::Result<(usize, Vec<((usize, usize), Range<usize>)>)> {
+    match version(bytes, MATCHES_MAGIC, "matches")? {
+        MATCHES_VERSION => {}
+        version => return Err(invalid(format!("unsupported matches version {}", version))),
+    }
+    if bytes.len() < MATCHES_HEADER_SIZE {
+        return Err(invalid("truncated matches header"));
+    }
+    let num_images = read_u64(bytes, 8) as usize;
+    let num_pairs = read_u64(bytes, 16) as usize;
+    let mut offset = num_pairs
+        .checked_mul(PAIR_SIZE)
+        .and_then(|size| size.checked_add(MATCHES_HEADER_SIZE))
+        .filter(|&size| size <= bytes.len())
+        .ok_or_else(|| invalid("truncated pair table"))?;
+    let mut table = Vec::with_capacity(num_pairs);
+    for pair in 0..num_pairs {
+        let entry = MATCHES_HEADER_SIZE + pair * PAIR_SIZE;
+        let a = read_u32(bytes, entry) as usize;
+        let b = read_u32(bytes, entry + 4) as usize;
+        if a >= b || b >= num_images {
+            return Err(invalid(format!("invalid image pair ({}, {})", a, b)));
+        }
+        let end = (read_u64(bytes, entry + 8) as usize)
+            .checked_mul(MATCH_SIZE)
+            .and_then(|size| size.checked_add(offset))
+            .filter(|&end| end <= bytes.len())
+            .ok_or_else(|| invalid(format!("truncated matches of pair ({}, {})", a, b)))?;
+        table.push(((a, b), offset..end));
+        offset = end;
+    }
+    if offset != bytes.len() {
+        return Err(invalid("trailing bytes after the matches"));
+    }
+    Ok((num_images, table))
+}
+
+fn decode_matches(bytes: &[u8], range: Range<usize>) -> impl Iterator<Item = DescriptorMatch> + '_ {
+    bytes[range]
+        .chunks_exact(MATCH_SIZE)
+        .map(|m| DescriptorMatch {
+            query: read_u32(m, 0) as usize,
+            target: read_u32(m, 4) as usize,
+            distance: read_f32(m, 8),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features() -> ImageFeatures {
+        let keypoint = |ix: usize| KeyPoint {
+            point: (ix as f32 * 10.5, 3.25),
+            response: 0.01 * ix as f32,
+            size: 2.0,
+            octave: ix,
+            class_id: 7,
+            angle: -1.5,
+        };
+        let descriptor = |ix: u8| BitArray::new([ix; 64]);
+        ImageFeatures::new(
+            vec![keypoint(0), keypoint(1), keypoint(2)],
+            vec![descriptor(0), descriptor(0x5a), descriptor(0xff)],
+        )
+    }
+
+    fn assert_same_features(a: &ImageFeatures, b: &ImageFeatures) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.keypoints.iter().zip(&b.keypoints) {
+            assert_eq!(encode_keypoint(a), encode_keypoint(b));
+        }
+        assert_eq!(a.descriptors, b.descriptors);
+    }
+
+    fn match_matrix() -> MatchMatrix {
+        let m = |query, target, distance| DescriptorMatch {
+            query,
+            target,
+            distance,
+        };
+        MatchMatrix {
+            num_images: 3,
+            pairs: vec![
+                ((0, 1), vec![m(0, 2, 10.0), m(1, 0, 25.0)]),
+                ((0, 2), vec![]),
+                ((1, 2), vec![m(2, 1, 3.0)]),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    fn saved(save: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> Vec<u8> {
+        let mut bytes = vec![];
+        save(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn assert_invalid<T>(result: io::Result<T>) {
+        match result {
+            Err(error) => assert_eq!(error.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("loaded a file which should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn features_round_trip() {
+        let features = features();
+        let bytes = saved(|bytes| features.save(bytes));
+        assert_eq!(
+            bytes.len(),
+            FEATURES_HEADER_SIZE + 3 * (KEYPOINT_SIZE + DESCRIPTOR_SIZE)
+        );
+        assert_same_features(&ImageFeatures::load(&bytes[..]).unwrap(), &features);
+
+        let path =
+            std::env::temp_dir().join(format!("cv-sfm-features-{}.cvft", std::process::id()));
+        features.save_path(&path).unwrap();
+        let mapped = MappedFeatures::open(&path).unwrap();
+        assert_eq!(mapped.len(), 3);
+        assert_eq!(mapped.descriptor(1), features.descriptors[1]);
+        assert_eq!(mapped.keypoint(2).octave, 2);
+        assert_same_features(&mapped.to_features(), &features);
+        drop(mapped);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn matches_round_trip() {
+        let matrix = match_matrix();
+        let bytes = saved(|bytes| matrix.save(bytes));
+        let loaded = MatchMatrix::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.num_images, 3);
+        assert_eq!(loaded.pairs, matrix.pairs);
+
+        let path = std::env::temp_dir().join(format!("cv-sfm-matches-{}.cvmt", std::process::id()));
+        matrix.save_path(&path).unwrap();
+        let mapped = MappedMatches::open(&path).unwrap();
+        assert_eq!(mapped.num_images(), 3);
+        assert_eq!(
+            mapped.matches(1, 2).unwrap().collect::<Vec<_>>(),
+            matrix.pairs[&(1, 2)]
+        );
+        assert!(mapped.matches(1, 0).is_none());
+        assert_eq!(mapped.to_match_matrix().pairs, matrix.pairs);
+        drop(mapped);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_wrong_magic() {
+        let mut bytes = saved(|bytes| features().save(bytes));
+        bytes[..4].copy_from_slice(&MATCHES_MAGIC);
+        assert_invalid(ImageFeatures::load(&bytes[..]));
+
+        let mut bytes = saved(|bytes| match_matrix().save(bytes));
+        bytes[..4].copy_from_slice(b"CVMX");
+        assert_invalid(MatchMatrix::load(&bytes[..]));
+        assert_invalid(MatchMatrix::load(&b"CV"[..]));
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut bytes = saved(|bytes| features().save(bytes));
+        bytes[4..8].copy_from_slice(&(FEATURES_VERSION + 1).to_le_bytes());
+        assert_invalid(ImageFeatures::load(&bytes[..]));
+
+        let mut bytes = saved(|bytes| match_matrix().save(bytes));
+        bytes[4..8].copy_from_slice(&0u32.to_le_bytes());
+        assert_invalid(MatchMatrix::load(&bytes[..]));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = saved(|bytes| features().save(bytes));
+        assert_invalid(ImageFeatures::load(&bytes[..bytes.len() - 1]));
+
+        let bytes = saved(|bytes| match_matrix().save(bytes));
+        assert_invalid(MatchMatrix::load(&bytes[..bytes.len() - MATCH_SIZE]));
+    }
+}
@@ -0,0 +1,643 @@
+//! Incremental checkpointing of maps during a session.
+//!
+//! Saving the whole [`VSlamData`] with [`VSlamData::save`] rewrites every frame each time, which becomes slow and
+//! wasteful for long mapping runs. A [`MapJournal`] instead appends a checkpoint with only what changed since the
+//! previous checkpoint: the new feeds and frames with their features, the views, landmarks and constraints which were
+//! added or changed, and the keys of everything which was removed. Frames are written once, so a journal grows with
+//! the length of the session rather than with the number of checkpoints.
+//!
+//! To detect changes, the journal only remembers a fingerprint of every view, landmark and constraint it wrote, which
+//! is much smaller than the map itself. A journal is loaded with [`VSlamData::load_journal`], which replays the
+//! checkpoints in order. A checkpoint which was cut off by a crash is ignored, so the map is recovered as it was at
+//! the last complete checkpoint. Journals written with earlier versions of the format are still loaded. The versions
+//! follow those of the maps saved with [`VSlamData::save`].
+//!
+//! The keys of a loaded map differ from the keys of the session which wrote the journal. To continue a session after
+//! loading, start a new journal, whose first checkpoint contains the whole map.
+
+use crate::{
+    map::FrameV1, ConstraintKey, Feed, FeedKey, Frame, FrameKey, Landmark, LandmarkKey,
+    ReconstructionKey, ThreeViewConstraint, VSlamData, View, ViewKey,
+};
+use bitarray::Hamming;
+use cv_pinhole::CameraIntrinsicsK1Distortion;
+use hgg::HggLite;
+use log::*;
+use serde::{Deserialize, Serialize};
+use slotmap::{DenseSlotMap, Key};
+use space::KnnInsert;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fs::{File, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// The magic bytes at the beginning of a [`MapJournal`].
+const JOURNAL_MAGIC: [u8; 4] = *b"CVMJ";
+/// The version of the [`MapJournal`] format.
+const JOURNAL_VERSION: u32 = 2;
+
+/// The view of a frame in a reconstruction.
+type FrameView = Option<(ReconstructionKey, ViewKey)>;
+
+/// The changes to a reconstruction since the previous checkpoint.
+#[derive(Serialize, Deserialize)]
+struct ReconstructionCheckpoint<V, L, C> {
+    reconstruction: ReconstructionKey,
+    views: Vec<(ViewKey, V)>,
+    removed_views: Vec<ViewKey>,
+    landmarks: Vec<(LandmarkKey, L)>,
+    removed_landmarks: Vec<LandmarkKey>,
+    constraints: Vec<(ConstraintKey, C)>,
+    removed_constraints: Vec<ConstraintKey>,
+}
+
+/// The changes to a map since the previous checkpoint.
+///
+/// It is written with references into the map and read back into owned values.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<F, V, L, C> {
+    feeds: Vec<(FeedKey, CameraIntrinsicsK1Distortion)>,
+    frames: Vec<(FrameKey, F)>,
+    frame_views: Vec<(FrameKey, FrameView)>,
+    forgotten_frames: Vec<FrameKey>,
+    reconstructions: Vec<ReconstructionCheckpoint<V, L, C>>,
+    removed_reconstructions: Vec<ReconstructionKey>,
+}
+
+/// The fingerprints of what was written of a reconstruction.
+#[derive(Default)]
+struct ReconstructionFingerprints {
+    views: HashMap<ViewKey, u64>,
+    landmarks: HashMap<LandmarkKey, u64>,
+    constraints: HashMap<ConstraintKey, u64>,
+}
+
+/// Feeds bytes to a hasher.
+struct HashWriter(DefaultHasher);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The fingerprint of the serialized value.
+fn fingerprint(value: &impl Serialize) -> u64 {
+    let mut writer = HashWriter(DefaultHasher::new());
+    bincode::serialize_into(&mut writer, value).expect("failed to fingerprint a map element");
+    writer.0.finish()
+}
+
+/// The elements of a slot map which changed since their fingerprints were taken and the keys which were removed,
+/// updating the fingerprints.
+#[allow(clippy::type_complexity)]
+fn changes<'a, K, T>(
+    map: &'a DenseSlotMap<K, T>,
+    fingerprints: &mut HashMap<K, u64>,
+) -> (Vec<(K, &'a T)>, Vec<K>)
+where
+    K: Key + Hash,
+    T: Serialize,
+{
+    let changed = map
+        .iter()
+        .filter(|&(key, value)| {
+            let new = fingerprint(value);
+            fingerprints.insert(key, new) != Some(new)
+        })
+        .collect();
+    let removed: Vec<K> = fingerprints
+        .keys()
+        .copied()
+        .filter(|&key| !map.contains_key(key))
+        .collect();
+    for key in &removed {
+        fingerprints.remove(key);
+    }
+    (changed, removed)
+}
+
+/// Appends incremental checkpoints of a [`VSlamData`] to a writer.
+pub struct MapJournal<W> {
+    writer: W,
+    interval: usize,
+    /// The feeds which were written
+    feeds: HashSet<FeedKey>,
+    /// The view and the number of features of every frame which was written
+    frames: HashMap<FrameKey, (FrameView, usize)>,
+    /// The fingerprints of every reconstruction which was written
+    reconstructions: HashMap<ReconstructionKey, ReconstructionFingerprints>,
+    /// The number of frames at the last checkpoint
+    checkpointed_frames: usize,
+}
+
+impl<W: Write> MapJournal<W> {
+    /// Starts a journal by writing its header.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&JOURNAL_MAGIC)?;
+        writer.write_all(&JOURNAL_VERSION.to_le_bytes())?;
+        writer.flush()?;
+        Ok(Self {
+            writer,
+            interval: 1 << 4,
+            feeds: HashSet::new(),
+            frames: HashMap::new(),
+            reconstructions: HashMap::new(),
+            checkpointed_frames: 0,
+        })
+    }
+
+    /// Set the number of new frames after which [`MapJournal::checkpoint_if_due`] writes a checkpoint.
+    ///
+    /// Default is `16`.
+    #[must_use]
+    pub fn interval(self, interval: usize) -> Self {
+        Self { interval, ..self }
+    }
+
+    /// Writes a checkpoint if at least `interval` frames were added to the map since the last checkpoint.
+    ///
+    /// This is meant to be called after every frame. Returns `true` if a checkpoint was written.
+    pub fn checkpoint_if_due(&mut self, data: &VSlamData) -> io::Result<bool> {
+        if data.frames.len() < self.checkpointed_frames + self.interval.max(1) {
+            return Ok(false);
+        }
+        self.checkpoint(data)?;
+        Ok(true)
+    }
+
+    /// Appends a checkpoint with everything which changed in the map since the last checkpoint and flushes the
+    /// writer.
+    pub fn checkpoint(&mut self, data: &VSlamData) -> io::Result<()> {
+        let feeds = data
+            .feeds
+            .iter()
+            .filter(|&(feed, _)| self.feeds.insert(feed))
+            .map(|(feed, feed_data)| (feed, feed_data.intrinsics))
+            .collect();
+
+        let mut frames = vec![];
+        let mut frame_views = vec![];
+        let mut forgotten_frames = vec![];
+        for (key, frame) in data.frames.iter() {
+            let state = (frame.view, frame.descriptor_features.len());
+            match self.frames.insert(key, state) {
+                None => {
+                    frames.push((key, frame));
+                    if frame.view.is_some() {
+                        frame_views.push((key, frame.view));
+                    }
+                }
+                Some((view, features)) => {
+                    if view != frame.view {
+                        frame_views.push((key, frame.view));
+                    }
+                    if features > 0 && state.1 == 0 {
+                        forgotten_frames.push(key);
+                    }
+                }
+            }
+        }
+        // The features of each feed are replayed in the order of the feed.
+        frames.sort_by_key(|(_, frame)| frame.feed_frame);
+
+        let reconstructions = data
+            .reconstructions
+            .iter()
+            .map(|(reconstruction, current)| {
+                let fingerprints = self.reconstructions.entry(reconstruction).or_default();
+                let (views, removed_views) = changes(&current.views, &mut fingerprints.views);
+                let (landmarks, removed_landmarks) =
+                    changes(&current.landmarks, &mut fingerprints.landmarks);
+                let (constraints, removed_constraints) =
+                    changes(&current.constraints, &mut fingerprints.constraints);
+                ReconstructionCheckpoint {
+                    reconstruction,
+                    views,
+                    removed_views,
+                    landmarks,
+                    removed_landmarks,
+                    constraints,
+                    removed_constraints,
+                }
+            })
+            .collect();
+        let removed_reconstructions: Vec<ReconstructionKey> = self
+            .reconstructions
+            .keys()
+            .copied()
+            .filter(|&reconstruction| !data.reconstructions.contains_key(reconstruction))
+            .collect();
+        for reconstruction in &removed_reconstructions {
+            self.reconstructions.remove(reconstruction);
+        }
+
+        let checkpoint = Checkpoint {
+            feeds,
+            frames,
+            frame_views,
+            forgotten_frames,
+            reconstructions,
+            removed_reconstructions,
+        };
+        let bytes =
+            bincode::serialize(&checkpoint).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        self.writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&bytes)?;
+        self.writer.flush()?;
+        self.checkpointed_frames = data.frames.len();
+        info!(
+            "wrote a map checkpoint of {} bytes with {} new frames",
+            bytes.len(),
+            checkpoint.frames.len()
+        );
+        Ok(())
+    }
+
+    /// Gives back the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl MapJournal<BufWriter<File>> {
+    /// Starts a journal in a new file, replacing any existing file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?,
+        ))
+    }
+}
+
+/// The keys of the loaded map for the keys in the journal.
+#[derive(Default)]
+struct KeyMaps {
+    feeds: HashMap<FeedKey, FeedKey>,
+    frames: HashMap<FrameKey, FrameKey>,
+    reconstructions: HashMap<ReconstructionKey, ReconstructionKeyMaps>,
+}
+
+/// The keys of a loaded reconstruction for the keys in the journal.
+struct ReconstructionKeyMaps {
+    reconstruction: ReconstructionKey,
+    views: HashMap<ViewKey, ViewKey>,
+    landmarks: HashMap<LandmarkKey, LandmarkKey>,
+    constraints: HashMap<ConstraintKey, ConstraintKey>,
+}
+
+impl<V, L, C> Checkpoint<FrameV1, V, L, C> {
+    /// Converts the frames of a checkpoint of version 1 of the format.
+    fn upgrade(self) -> Checkpoint<Frame, V, L, C> {
+        Checkpoint {
+            feeds: self.feeds,
+            frames: self
+                .frames
+                .into_iter()
+                .map(|(key, frame)| (key, frame.into()))
+                .collect(),
+            frame_views: self.frame_views,
+            forgotten_frames: self.forgotten_frames,
+            reconstructions: self.reconstructions,
+            removed_reconstructions: self.removed_reconstructions,
+        }
+    }
+}
+
+/// Looks up the key of the loaded map for a key in the journal.
+fn remap<K: Key + Hash>(keys: &HashMap<K, K>, key: K) -> io::Result<K> {
+    keys.get(&key).copied().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "map journal refers to an unknown key",
+        )
+    })
+}
+
+impl VSlamData {
+    /// Loads a map from a journal written by a [`MapJournal`] by replaying its checkpoints.
+    pub fn load_journal(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != JOURNAL_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a map journal",
+            ));
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != 1 && version != JOURNAL_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported map journal version {}", version),
+            ));
+        }
+
+        let mut data = Self::default();
+        let mut keys = KeyMaps::default();
+        let mut checkpoints = 0;
+        loop {
+            let mut len = [0; 8];
+            match reader.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            let mut bytes = vec![0; u64::from_le_bytes(len) as usize];
+            match reader.read_exact(&mut bytes) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    info!("ignoring an incomplete checkpoint at the end of the map journal");
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+            let invalid = |e: bincode::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+            let checkpoint = if version == 1 {
+                bincode::deserialize::<Checkpoint<FrameV1, _, _, _>>(&bytes)
+                    .map_err(invalid)?
+                    .upgrade()
+            } else {
+                bincode::deserialize(&bytes).map_err(invalid)?
+            };
+            data.apply_checkpoint(&mut keys, checkpoint)?;
+            checkpoints += 1;
+        }
+        info!(
+            "loaded a map with {} frames from {} checkpoints",
+            data.frames.len(),
+            checkpoints
+        );
+        Ok(data)
+    }
+
+    /// Loads a map from a journal file.
+    pub fn load_journal_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::load_journal(BufReader::new(File::open(path)?))
+    }
+
+    fn apply_checkpoint(
+        &mut self,
+        keys: &mut KeyMaps,
+        checkpoint: Checkpoint<Frame, View, Landmark, ThreeViewConstraint>,
+    ) -> io::Result<()> {
+        for (key, intrinsics) in checkpoint.feeds {
+            let feed = self.feeds.insert(Feed {
+                intrinsics,
+                frames: vec![],
+            });
+            keys.feeds.insert(key, feed);
+        }
+        for (key, mut frame) in checkpoint.frames {
+            let feed = remap(&keys.feeds, frame.feed)?;
+            frame.feed = feed;
+            frame.feed_frame = self.feeds[feed].frames.len();
+            // The view is assigned once the reconstructions are loaded.
+            frame.view = None;
+            let lsh = frame.lsh;
+            let frame = self.frames.insert(frame);
+            self.lsh_to_frame.insert(lsh, frame);
+            self.feeds[feed].frames.push(frame);
+            keys.frames.insert(key, frame);
+        }
+
+        for reconstruction in checkpoint.removed_reconstructions {
+            if let Some(maps) = keys.reconstructions.remove(&reconstruction) {
+                self.reconstructions.remove(maps.reconstruction);
+            }
+        }
+        for reconstruction in checkpoint.reconstructions {
+            self.apply_reconstruction_checkpoint(keys, reconstruction)?;
+        }
+
+        for (frame, view) in checkpoint.frame_views {
+            let frame = remap(&keys.frames, frame)?;
+            // Views of reconstructions which were removed since are dropped.
+            self.frames[frame].view = view.and_then(|(reconstruction, view)| {
+                let maps = keys.reconstructions.get(&reconstruction)?;
+                Some((maps.reconstruction, *maps.views.get(&view)?))
+            });
+        }
+        for frame in checkpoint.forgotten_frames {
+            let frame = remap(&keys.frames, frame)?;
+            self.frames[frame].descriptor_features = HggLite::new(Hamming).insert_knn(32);
+        }
+        Ok(())
+    }
+
+    fn apply_reconstruction_checkpoint(
+        &mut self,
+        keys: &mut KeyMaps,
+        checkpoint: ReconstructionCheckpoint<View, Landmark, ThreeViewConstraint>,
+    ) -> io::Result<()> {
+        let reconstructions = &mut self.reconstructions;
+        let maps = keys
+            .reconstructions
+            .entry(checkpoint.reconstruction)
+            .or_insert_with(|| ReconstructionKeyMaps {
+                reconstruction: reconstructions.insert(Default::default()),
+                views: HashMap::new(),
+                landmarks: HashMap::new(),
+                constraints: HashMap::new(),
+            });
+        let reconstruction = &mut self.reconstructions[maps.reconstruction];
+
+        for view in checkpoint.removed_views {
+            if let Some(view) = maps.views.remove(&view) {
+                reconstruction.views.remove(view);
+            }
+        }
+        for landmark in checkpoint.removed_landmarks {
+            if let Some(landmark) = maps.landmarks.remove(&landmark) {
+                reconstruction.landmarks.remove(landmark);
+            }
+        }
+        for constraint in checkpoint.removed_constraints {
+            if let Some(constraint) = maps.constraints.remove(&constraint) {
+                reconstruction.constraints.remove(constraint);
+            }
+        }
+
+        // Views and landmarks refer to each other, so the new ones are inserted before any references are remapped.
+        for (key, view) in &checkpoint.views {
+            if !maps.views.contains_key(key) {
+                let frame = remap(&keys.frames, view.frame)?;
+                maps.views.insert(
+                    *key,
+                    reconstruction.views.insert(View {
+                        frame,
+                        pose: view.pose,
+                        landmarks: vec![],
+                    }),
+                );
+            }
+        }
+        for (key, _) in &checkpoint.landmarks {
+            if !maps.landmarks.contains_key(key) {
+                maps.landmarks.insert(
+                    *key,
+                    reconstruction.landmarks.insert(Landmark {
+                        observations: HashMap::new(),
+                    }),
+                );
+            }
+        }
+
+        for (key, view) in checkpoint.views {
+            let landmarks = view
+                .landmarks
+                .iter()
+                .map(|&landmark| remap(&maps.landmarks, landmark))
+                .collect::<io::Result<_>>()?;
+            reconstruction.views[maps.views[&key]] = View {
+                frame: remap(&keys.frames, view.frame)?,
+                pose: view.pose,
+                landmarks,
+            };
+        }
+        for (key, landmark) in checkpoint.landmarks {
+            let observations = landmark
+                .observations
+                .iter()
+                .map(|(&view, &feature)| remap(&maps.views, view).map(|view| (view, feature)))
+                .collect::<io::Result<_>>()?;
+            reconstruction.landmarks[maps.landmarks[&key]] = Landmark { observations };
+        }
+        for (key, mut constraint) in checkpoint.constraints {
+            for view in &mut constraint.views {
+                *view = remap(&maps.views, *view)?;
+            }
+            match maps.constraints.get(&key) {
+                Some(&existing) => reconstruction.constraints[existing] = constraint,
+                None => {
+                    maps.constraints
+                        .insert(key, reconstruction.constraints.insert(constraint));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::tests::{describe, downgrade_frame, features, map};
+    use cv_core::{
+        nalgebra::{IsometryMatrix3, Rotation3, Vector3},
+        WorldToCamera,
+    };
+    use std::convert::TryInto;
+
+    #[test]
+    fn journal_round_trip() {
+        let (mut data, frames) = map();
+        let mut journal = MapJournal::new(vec![]).unwrap();
+        journal.checkpoint(&data).unwrap();
+        let first = describe(&data);
+        let first_len = journal.writer.len();
+
+        // Change a pose, forget the features of a frame, remove a view and add a frame.
+        let (reconstruction, view) = data.frames[frames[1]].view.unwrap();
+        data.reconstructions[reconstruction].views[view].pose =
+            WorldToCamera(IsometryMatrix3::from_parts(
+                Vector3::new(0.5, 0.25, -1.0).into(),
+                Rotation3::from_euler_angles(0.1, 0.2, 0.3),
+            ));
+        data.frames[frames[3]].descriptor_features = HggLite::new(Hamming).insert_knn(32);
+        let (_, view) = data.frames[frames[2]].view.unwrap();
+        data.remove_view(reconstruction, view);
+        let feed = data.frames[frames[0]].feed;
+        data.add_frame(feed, features(4));
+        // A checkpoint without changes is empty but still valid.
+        journal.checkpoint(&data).unwrap();
+        journal.checkpoint(&data).unwrap();
+        let second = describe(&data);
+        assert_ne!(first, second);
+
+        let bytes = journal.into_inner();
+        let loaded = VSlamData::load_journal(bytes.as_slice()).unwrap();
+        assert_eq!(describe(&loaded), second);
+        assert_eq!(
+            loaded.feeds.values().next().unwrap().intrinsics,
+            data.feeds[feed].intrinsics
+        );
+
+        // A checkpoint which was cut off is ignored.
+        let loaded = VSlamData::load_journal(&bytes[..first_len + 12]).unwrap();
+        assert_eq!(describe(&loaded), first);
+    }
+
+    #[test]
+    fn journal_continues_after_reload() {
+        let (data, _) = map();
+        let mut journal = MapJournal::new(vec![]).unwrap();
+        journal.checkpoint(&data).unwrap();
+        let loaded = VSlamData::load_journal(journal.into_inner().as_slice()).unwrap();
+
+        // The first checkpoint of a new journal has the whole map.
+        let mut journal = MapJournal::new(vec![]).unwrap().interval(2);
+        assert!(journal.checkpoint_if_due(&loaded).unwrap());
+        assert!(!journal.checkpoint_if_due(&loaded).unwrap());
+        let reloaded = VSlamData::load_journal(journal.into_inner().as_slice()).unwrap();
+        assert_eq!(describe(&reloaded), describe(&data));
+    }
+
+    /// A journal written by version 1 of the format, whose frames had neither motions nor dynamic features.
+    #[test]
+    fn loads_version_1() {
+        let (data, _) = map();
+        let mut journal = MapJournal::new(vec![]).unwrap();
+        journal.checkpoint(&data).unwrap();
+        let bytes = journal.into_inner();
+        let len = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize;
+        let checkpoint: Checkpoint<Frame, View, Landmark, ThreeViewConstraint> =
+            bincode::deserialize(&bytes[16..16 + len]).unwrap();
+        let checkpoint = Checkpoint {
+            feeds: checkpoint.feeds,
+            frames: checkpoint
+                .frames
+                .iter()
+                .map(|(key, frame)| (*key, downgrade_frame(frame)))
+                .collect::<Vec<_>>(),
+            frame_views: checkpoint.frame_views,
+            forgotten_frames: checkpoint.forgotten_frames,
+            reconstructions: checkpoint.reconstructions,
+            removed_reconstructions: checkpoint.removed_reconstructions,
+        };
+        let checkpoint = bincode::serialize(&checkpoint).unwrap();
+
+        let mut v1 = JOURNAL_MAGIC.to_vec();
+        v1.extend(1u32.to_le_bytes());
+        v1.extend((checkpoint.len() as u64).to_le_bytes());
+        v1.extend(checkpoint);
+        let loaded = VSlamData::load_journal(v1.as_slice()).unwrap();
+        assert_eq!(describe(&loaded), describe(&data));
+        assert!(loaded.frames.values().all(|frame| frame.motion.is_none()));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut bytes = MapJournal::new(vec![]).unwrap().into_inner();
+        assert!(VSlamData::load_journal(bytes.as_slice()).is_ok());
+        bytes[4] = JOURNAL_VERSION as u8 + 1;
+        let error = VSlamData::load_journal(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        bytes[0] = b'X';
+        let error = VSlamData::load_journal(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
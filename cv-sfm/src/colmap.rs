@@ -0,0 +1,1012 @@
+//! Export and import of [COLMAP](https://colmap.github.io/format.html) sparse models.
+//!
+//! A COLMAP sparse model is a directory with `cameras`, `images` and `points3D` files, either as text (`.txt`) or as
+//! binary (`.bin`). Writing a reconstruction as a sparse model lets it be densified and meshed with tools which read
+//! COLMAP models, and reading a sparse model lets it be refined, localized against, or compared with the
+//! reconstructions of this crate. COLMAP places the center of the top-left pixel at `(0.5, 0.5)`, while this crate places it at
+//! `(0.0, 0.0)`, so the keypoints are shifted by half a pixel when they are written.
+//!
+//! The features of this crate store calibrated bearings, so the keypoints are written for a `PINHOLE` camera with the
+//! focal lengths and principal point of the intrinsics, without skew or distortion. The images have to be undistorted
+//! with the same intrinsics before they are given to tools which use the pixels along with the model.
+
+use crate::{
+    FeedKey, FrameKey, ReconstructionKey, SfmImage, SparsePoint, SparseReconstruction, Track, VSlam,
+};
+use cv_core::{
+    nalgebra::{
+        IsometryMatrix3, Point2, Point3, Quaternion, Translation3, UnitQuaternion, UnitVector3,
+        Vector2,
+    },
+    sample_consensus::{Consensus, Estimator},
+    CameraModel, CameraToCamera, FeatureMatch, FeatureWorldMatch, ImagePoint, KeyPoint, Pose,
+    Projective, TriangulatorObservations, WorldPoint, WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsics;
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+/// The offset from pixel coordinates in this crate to pixel coordinates in COLMAP.
+const PIXEL_OFFSET: f64 = 0.5;
+
+/// The camera models of COLMAP which can be read and written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColmapCameraModel {
+    /// `f, cx, cy`
+    SimplePinhole,
+    /// `fx, fy, cx, cy`
+    Pinhole,
+    /// `f, cx, cy, k`
+    SimpleRadial,
+    /// `f, cx, cy, k1, k2`
+    Radial,
+    /// `fx, fy, cx, cy, k1, k2, p1, p2`
+    OpenCv,
+}
+
+impl ColmapCameraModel {
+    /// The model id used in the binary format.
+    pub fn id(self) -> i32 {
+        match self {
+            Self::SimplePinhole => 0,
+            Self::Pinhole => 1,
+            Self::SimpleRadial => 2,
+            Self::Radial => 3,
+            Self::OpenCv => 4,
+        }
+    }
+
+    /// The model name used in the text format.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SimplePinhole => "SIMPLE_PINHOLE",
+            Self::Pinhole => "PINHOLE",
+            Self::SimpleRadial => "SIMPLE_RADIAL",
+            Self::Radial => "RADIAL",
+            Self::OpenCv => "OPENCV",
+        }
+    }
+
+    /// The number of parameters of the model.
+    pub fn num_params(self) -> usize {
+        match self {
+            Self::SimplePinhole => 3,
+            Self::Pinhole => 4,
+            Self::SimpleRadial => 4,
+            Self::Radial => 5,
+            Self::OpenCv => 8,
+        }
+    }
+}
+
+/// A camera of a COLMAP sparse model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColmapCamera {
+    pub id: u32,
+    pub model: ColmapCameraModel,
+    pub width: u64,
+    pub height: u64,
+    /// The parameters in the order of the model.
+    pub params: Vec<f64>,
+}
+
+impl ColmapCamera {
+    /// Creates a `PINHOLE` camera from the focal lengths and principal point of the intrinsics.
+    ///
+    /// The skew is not representable and is ignored.
+    pub fn pinhole(id: u32, intrinsics: &CameraIntrinsics, width: u64, height: u64) -> Self {
+        Self {
+            id,
+            model: ColmapCameraModel::Pinhole,
+            width,
+            height,
+            params: vec![
+                intrinsics.focals.x,
+                intrinsics.focals.y,
+                intrinsics.principal_point.x + PIXEL_OFFSET,
+                intrinsics.principal_point.y + PIXEL_OFFSET,
+            ],
+        }
+    }
+}
+
+/// A keypoint of an image of a COLMAP sparse model.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ColmapPoint2D {
+    /// The position in COLMAP pixel coordinates.
+    pub position: Point2<f64>,
+    /// The id of the 3d point observed by this keypoint, if any.
+    pub point3d: Option<u64>,
+}
+
+/// A registered image of a COLMAP sparse model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColmapImage {
+    pub id: u32,
+    pub pose: WorldToCamera,
+    pub camera: u32,
+    /// The file name of the image relative to the image directory.
+    pub name: String,
+    pub points: Vec<ColmapPoint2D>,
+}
+
+/// A 3d point of a COLMAP sparse model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColmapPoint3D {
+    pub id: u64,
+    pub position: Point3<f64>,
+    pub color: [u8; 3],
+    /// The mean reprojection error in pixels.
+    pub error: f64,
+    /// The observations of the point as `(image id, keypoint index)`.
+    pub track: Vec<(u32, u32)>,
+}
+
+/// A COLMAP sparse model.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColmapModel {
+    pub cameras: Vec<ColmapCamera>,
+    pub images: Vec<ColmapImage>,
+    pub points: Vec<ColmapPoint3D>,
+}
+
+impl ColmapModel {
+    /// Writes `cameras.txt`, `images.txt` and `points3D.txt` to a directory, which is created if needed.
+    pub fn save_text(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        write_file(dir.join("cameras.txt"), |w| self.write_cameras_text(w))?;
+        write_file(dir.join("images.txt"), |w| self.write_images_text(w))?;
+        write_file(dir.join("points3D.txt"), |w| self.write_points_text(w))
+    }
+
+    /// Writes `cameras.bin`, `images.bin` and `points3D.bin` to a directory, which is created if needed.
+    pub fn save_binary(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        write_file(dir.join("cameras.bin"), |w| self.write_cameras_binary(w))?;
+        write_file(dir.join("images.bin"), |w| self.write_images_binary(w))?;
+        write_file(dir.join("points3D.bin"), |w| self.write_points_binary(w))
+    }
+
+    /// Writes the cameras in the format of `cameras.txt`.
+    pub fn write_cameras_text(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "# Camera list with one line of data per camera:")?;
+        writeln!(writer, "#   CAMERA_ID, MODEL, WIDTH, HEIGHT, PARAMS[]")?;
+        writeln!(writer, "# Number of cameras: {}", self.cameras.len())?;
+        for camera in &self.cameras {
+            write!(
+                writer,
+                "{} {} {} {}",
+                camera.id,
+                camera.model.name(),
+                camera.width,
+                camera.height
+            )?;
+            for param in &camera.params {
+                write!(writer, " {}", param)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the images in the format of `images.txt`.
+    pub fn write_images_text(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "# Image list with two lines of data per image:")?;
+        writeln!(
+            writer,
+            "#   IMAGE_ID, QW, QX, QY, QZ, TX, TY, TZ, CAMERA_ID, NAME"
+        )?;
+        writeln!(writer, "#   POINTS2D[] as (X, Y, POINT3D_ID)")?;
+        writeln!(writer, "# Number of images: {}", self.images.len())?;
+        for image in &self.images {
+            let ([qw, qx, qy, qz], [tx, ty, tz]) = pose_parts(image.pose);
+            writeln!(
+                writer,
+                "{} {} {} {} {} {} {} {} {} {}",
+                image.id, qw, qx, qy, qz, tx, ty, tz, image.camera, image.name
+            )?;
+            for (ix, point) in image.points.iter().enumerate() {
+                if ix != 0 {
+                    write!(writer, " ")?;
+                }
+                match point.point3d {
+                    Some(id) => write!(writer, "{} {} {}", point.position.x, point.position.y, id)?,
+                    None => write!(writer, "{} {} -1", point.position.x, point.position.y)?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the 3d points in the format of `points3D.txt`.
+    pub fn write_points_text(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "# 3D point list with one line of data per point:")?;
+        writeln!(
+            writer,
+            "#   POINT3D_ID, X, Y, Z, R, G, B, ERROR, TRACK[] as (IMAGE_ID, POINT2D_IDX)"
+        )?;
+        writeln!(writer, "# Number of points: {}", self.points.len())?;
+        for point in &self.points {
+            let [r, g, b] = point.color;
+            write!(
+                writer,
+                "{} {} {} {} {} {} {} {}",
+                point.id,
+                point.position.x,
+                point.position.y,
+                point.position.z,
+                r,
+                g,
+                b,
+                point.error
+            )?;
+            for &(image, point2d) in &point.track {
+                write!(writer, " {} {}", image, point2d)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the cameras in the format of `cameras.bin`.
+    pub fn write_cameras_binary(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&(self.cameras.len() as u64).to_le_bytes())?;
+        for camera in &self.cameras {
+            writer.write_all(&(camera.id as i32).to_le_bytes())?;
+            writer.write_all(&camera.model.id().to_le_bytes())?;
+            writer.write_all(&camera.width.to_le_bytes())?;
+            writer.write_all(&camera.height.to_le_bytes())?;
+            for param in &camera.params {
+                writer.write_all(&param.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the images in the format of `images.bin`.
+    pub fn write_images_binary(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&(self.images.len() as u64).to_le_bytes())?;
+        for image in &self.images {
+            writer.write_all(&(image.id as i32).to_le_bytes())?;
+            let (rotation, translation) = pose_parts(image.pose);
+            for value in rotation.iter().chain(&translation) {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&(image.camera as i32).to_le_bytes())?;
+            writer.write_all(image.name.as_bytes())?;
+            writer.write_all(&[0])?;
+            writer.write_all(&(image.points.len() as u64).to_le_bytes())?;
+            for point in &image.points {
+                writer.write_all(&point.position.x.to_le_bytes())?;
+                writer.write_all(&point.position.y.to_le_bytes())?;
+                let id = point.point3d.map_or(-1, |id| id as i64);
+                writer.write_all(&id.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the 3d points in the format of `points3D.bin`.
+    pub fn write_points_binary(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&(self.points.len() as u64).to_le_bytes())?;
+        for point in &self.points {
+            writer.write_all(&point.id.to_le_bytes())?;
+            for value in point.position.coords.iter() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&point.color)?;
+            writer.write_all(&point.error.to_le_bytes())?;
+            writer.write_all(&(point.track.len() as u64).to_le_bytes())?;
+            for &(image, point2d) in &point.track {
+                writer.write_all(&(image as i32).to_le_bytes())?;
+                writer.write_all(&(point2d as i32).to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_file(
+    path: impl AsRef<Path>,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer.flush()
+}
+
+/// Splits a pose into the `[qw, qx, qy, qz]` quaternion and `[tx, ty, tz]` translation used by COLMAP.
+fn pose_parts(pose: WorldToCamera) -> ([f64; 4], [f64; 3]) {
+    let rotation = UnitQuaternion::from_rotation_matrix(&pose.0.rotation);
+    let translation = pose.0.translation.vector;
+    (
+        [rotation.w, rotation.i, rotation.j, rotation.k],
+        [translation.x, translation.y, translation.z],
+    )
+}
+
+/// Converts bearings to COLMAP pixel coordinates of a `PINHOLE` camera.
+struct PinholeProjection(CameraIntrinsics);
+
+impl PinholeProjection {
+    fn new(intrinsics: &CameraIntrinsics) -> Self {
+        Self(CameraIntrinsics {
+            skew: 0.0,
+            ..*intrinsics
+        })
+    }
+
+    fn pixel(&self, bearing: UnitVector3<f64>) -> Option<Point2<f64>> {
+        let keypoint = self.0.uncalibrate(bearing)?;
+        Some(keypoint.0 + Vector2::repeat(PIXEL_OFFSET))
+    }
+
+    /// The distance in pixels between the projection of a point and a keypoint.
+    fn error(&self, pose: WorldToCamera, point: Point3<f64>, keypoint: Point2<f64>) -> Option<f64> {
+        let camera_point = pose.isometry() * point;
+        Some((self.pixel(UnitVector3::try_new(camera_point.coords, 0.0)?)? - keypoint).norm())
+    }
+}
+
+/// Collects the 3d points of a model from points and their observations as `(image id, keypoint index)`.
+///
+/// The keypoints of `images` are updated with the ids of the points, and the error of each point is measured
+/// against them with the projection of the camera of its image.
+fn add_points(
+    model: &mut ColmapModel,
+    projections: &HashMap<u32, PinholeProjection>,
+    points: impl Iterator<Item = (Point3<f64>, [u8; 3], Vec<(u32, u32)>)>,
+) {
+    let image_indices: HashMap<u32, usize> = model
+        .images
+        .iter()
+        .enumerate()
+        .map(|(ix, image)| (image.id, ix))
+        .collect();
+    for (position, color, track) in points {
+        let id = model.points.len() as u64 + 1;
+        let mut error_sum = 0.0;
+        let mut error_count = 0;
+        for &(image, point2d) in &track {
+            let image = &mut model.images[image_indices[&image]];
+            let keypoint = &mut image.points[point2d as usize];
+            keypoint.point3d = Some(id);
+            if let Some(error) =
+                projections[&image.camera].error(image.pose, position, keypoint.position)
+            {
+                error_sum += error;
+                error_count += 1;
+            }
+        }
+        model.points.push(ColmapPoint3D {
+            id,
+            position,
+            color,
+            error: if error_count == 0 {
+                0.0
+            } else {
+                error_sum / error_count as f64
+            },
+            track,
+        });
+    }
+}
+
+impl SparseReconstruction {
+    /// Converts the reconstruction to a COLMAP sparse model, where every image was taken with the same camera.
+    ///
+    /// `images` must be the images the reconstruction was made from. The registered images are written with the
+    /// names given by `image_name` for their index in `images`.
+    pub fn colmap_model<D>(
+        &self,
+        images: &[SfmImage<D>],
+        intrinsics: &CameraIntrinsics,
+        width: u64,
+        height: u64,
+        image_name: impl Fn(usize) -> String,
+    ) -> ColmapModel {
+        let projection = PinholeProjection::new(intrinsics);
+        let mut model = ColmapModel {
+            cameras: vec![ColmapCamera::pinhole(1, intrinsics, width, height)],
+            images: self
+                .registered()
+                .map(|(image, pose)| ColmapImage {
+                    id: image as u32 + 1,
+                    pose,
+                    camera: 1,
+                    name: image_name(image),
+                    points: images[image]
+                        .bearings
+                        .iter()
+                        .map(|&bearing| ColmapPoint2D {
+                            position: projection.pixel(bearing).unwrap_or_else(Point2::origin),
+                            point3d: None,
+                        })
+                        .collect(),
+                })
+                .collect(),
+            points: vec![],
+        };
+        let points = self.points.iter().filter_map(|point| {
+            let track = point
+                .observations
+                .iter()
+                .map(|&(image, feature)| (image as u32 + 1, feature as u32))
+                .collect();
+            Some((point.point.point()?, point.color, track))
+        });
+        let projections = std::iter::once((1, projection)).collect();
+        add_points(&mut model, &projections, points);
+        model
+    }
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Converts a reconstruction to a COLMAP sparse model.
+    ///
+    /// Every feed becomes a camera with the size given by `image_size`, and every view becomes an image with the name
+    /// given by `image_name` for its frame. Only the robust landmarks are written.
+    pub fn colmap_model(
+        &self,
+        reconstruction: ReconstructionKey,
+        image_size: impl Fn(FeedKey) -> (u64, u64),
+        image_name: impl Fn(FrameKey) -> String,
+    ) -> ColmapModel {
+        let mut model = ColmapModel::default();
+        let mut feed_cameras: HashMap<FeedKey, u32> = HashMap::new();
+        let mut projections: HashMap<u32, PinholeProjection> = HashMap::new();
+        let mut view_images = HashMap::new();
+        for (view, view_object) in self.data.reconstruction(reconstruction).views.iter() {
+            let frame = self.data.frame(view_object.frame);
+            let camera = *feed_cameras.entry(frame.feed).or_insert_with(|| {
+                let id = model.cameras.len() as u32 + 1;
+                let intrinsics = &self.data.feeds[frame.feed].intrinsics.simple_intrinsics;
+                let (width, height) = image_size(frame.feed);
+                model
+                    .cameras
+                    .push(ColmapCamera::pinhole(id, intrinsics, width, height));
+                projections.insert(id, PinholeProjection::new(intrinsics));
+                id
+            });
+            let id = model.images.len() as u32 + 1;
+            view_images.insert(view, id);
+            model.images.push(ColmapImage {
+                id,
+                pose: view_object.pose,
+                camera,
+                name: image_name(view_object.frame),
+                points: (0..frame.descriptor_features.len())
+                    .map(|feature| ColmapPoint2D {
+                        position: projections[&camera]
+                            .pixel(frame.bearing(feature))
+                            .unwrap_or_else(Point2::origin),
+                        point3d: None,
+                    })
+                    .collect(),
+            });
+        }
+
+        let points: Vec<_> = self
+            .data
+            .reconstruction(reconstruction)
+            .landmarks
+            .iter()
+            .filter_map(|(landmark, landmark_object)| {
+                let position = self
+                    .triangulate_landmark_robust(reconstruction, landmark)?
+                    .point()?;
+                let (&view, &feature) = landmark_object.observations.iter().next()?;
+                let color = self.data.observation_color(reconstruction, view, feature);
+                let mut track: Vec<(u32, u32)> = landmark_object
+                    .observations
+                    .iter()
+                    .map(|(view, &feature)| (view_images[view], feature as u32))
+                    .collect();
+                track.sort_unstable();
+                Some((position, color, track))
+            })
+            .collect();
+        add_points(&mut model, &projections, points.into_iter());
+        model
+    }
+}
+
+impl ColmapCameraModel {
+    /// Finds the model with an id of the binary format.
+    pub fn from_id(id: i32) -> Option<Self> {
+        MODELS.iter().copied().find(|model| model.id() == id)
+    }
+
+    /// Finds the model with a name of the text format.
+    pub fn from_name(name: &str) -> Option<Self> {
+        MODELS.iter().copied().find(|model| model.name() == name)
+    }
+}
+
+const MODELS: [ColmapCameraModel; 5] = [
+    ColmapCameraModel::SimplePinhole,
+    ColmapCameraModel::Pinhole,
+    ColmapCameraModel::SimpleRadial,
+    ColmapCameraModel::Radial,
+    ColmapCameraModel::OpenCv,
+];
+
+/// The number of iterations used to remove the distortion of a keypoint.
+const UNDISTORT_ITERATIONS: usize = 100;
+
+impl ColmapCamera {
+    /// The focal lengths and principal point in COLMAP pixel coordinates.
+    fn pinhole_params(&self) -> (Vector2<f64>, Point2<f64>) {
+        let p = &self.params;
+        match self.model {
+            ColmapCameraModel::SimplePinhole
+            | ColmapCameraModel::SimpleRadial
+            | ColmapCameraModel::Radial => (Vector2::repeat(p[0]), Point2::new(p[1], p[2])),
+            ColmapCameraModel::Pinhole | ColmapCameraModel::OpenCv => {
+                (Vector2::new(p[0], p[1]), Point2::new(p[2], p[3]))
+            }
+        }
+    }
+
+    /// The offset added by the distortion to a point on the normalized image plane.
+    fn distortion(&self, point: Vector2<f64>) -> Vector2<f64> {
+        let p = &self.params;
+        let r2 = point.norm_squared();
+        match self.model {
+            ColmapCameraModel::SimplePinhole | ColmapCameraModel::Pinhole => Vector2::zeros(),
+            ColmapCameraModel::SimpleRadial => point * (p[3] * r2),
+            ColmapCameraModel::Radial => point * (p[3] * r2 + p[4] * r2 * r2),
+            ColmapCameraModel::OpenCv => {
+                let (k1, k2, p1, p2) = (p[4], p[5], p[6], p[7]);
+                let (u, v) = (point.x, point.y);
+                let radial = k1 * r2 + k2 * r2 * r2;
+                Vector2::new(
+                    u * radial + 2.0 * p1 * u * v + p2 * (r2 + 2.0 * u * u),
+                    v * radial + 2.0 * p2 * u * v + p1 * (r2 + 2.0 * v * v),
+                )
+            }
+        }
+    }
+
+    /// Converts a keypoint in COLMAP pixel coordinates to a bearing.
+    pub fn calibrate_colmap(&self, position: Point2<f64>) -> UnitVector3<f64> {
+        let (focals, principal_point) = self.pinhole_params();
+        let distorted = (position - principal_point).component_div(&focals);
+        // The distortion is removed by fixed point iteration, which converges for the distortion of real lenses.
+        let mut undistorted = distorted;
+        for _ in 0..UNDISTORT_ITERATIONS {
+            let next = distorted - self.distortion(undistorted);
+            let converged = (next - undistorted).norm_squared() < 1e-24;
+            undistorted = next;
+            if converged {
+                break;
+            }
+        }
+        UnitVector3::new_normalize(undistorted.push(1.0))
+    }
+
+    /// Converts a bearing to a keypoint in COLMAP pixel coordinates.
+    pub fn uncalibrate_colmap(&self, bearing: UnitVector3<f64>) -> Option<Point2<f64>> {
+        (bearing.z > 0.0).then(|| ())?;
+        let (focals, principal_point) = self.pinhole_params();
+        let undistorted = bearing.xy() / bearing.z;
+        let distorted = undistorted + self.distortion(undistorted);
+        Some(principal_point + distorted.component_mul(&focals))
+    }
+}
+
+/// Converts between keypoints in the pixel coordinates of this crate and bearings.
+impl CameraModel for ColmapCamera {
+    fn calibrate<P>(&self, point: P) -> UnitVector3<f64>
+    where
+        P: ImagePoint,
+    {
+        self.calibrate_colmap(point.image_point() + Vector2::repeat(PIXEL_OFFSET))
+    }
+
+    fn uncalibrate(&self, bearing: UnitVector3<f64>) -> Option<KeyPoint> {
+        Some(KeyPoint(
+            self.uncalibrate_colmap(bearing)? - Vector2::repeat(PIXEL_OFFSET),
+        ))
+    }
+}
+
+impl ColmapModel {
+    /// Loads a sparse model from a directory, reading the binary files if they exist and the text files otherwise.
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        if dir.join("cameras.bin").exists() {
+            Self::load_binary(dir)
+        } else {
+            Self::load_text(dir)
+        }
+    }
+
+    /// Reads `cameras.txt`, `images.txt` and `points3D.txt` from a directory.
+    pub fn load_text(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        Ok(Self {
+            cameras: Self::read_cameras_text(BufReader::new(File::open(dir.join("cameras.txt"))?))?,
+            images: Self::read_images_text(BufReader::new(File::open(dir.join("images.txt"))?))?,
+            points: Self::read_points_text(BufReader::new(File::open(dir.join("points3D.txt"))?))?,
+        })
+    }
+
+    /// Reads `cameras.bin`, `images.bin` and `points3D.bin` from a directory.
+    pub fn load_binary(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        Ok(Self {
+            cameras: Self::read_cameras_binary(BufReader::new(File::open(
+                dir.join("cameras.bin"),
+            )?))?,
+            images: Self::read_images_binary(BufReader::new(File::open(dir.join("images.bin"))?))?,
+            points: Self::read_points_binary(BufReader::new(File::open(
+                dir.join("points3D.bin"),
+            )?))?,
+        })
+    }
+
+    /// Reads cameras in the format of `cameras.txt`.
+    pub fn read_cameras_text(reader: impl BufRead) -> io::Result<Vec<ColmapCamera>> {
+        let mut cameras = vec![];
+        for line in reader.lines() {
+            let line = line?;
+            if is_comment(&line) {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let id = parse(fields.next())?;
+            let name = fields
+                .next()
+                .ok_or_else(|| invalid("missing camera model"))?;
+            let model = ColmapCameraModel::from_name(name)
+                .ok_or_else(|| invalid(format!("unsupported camera model {}", name)))?;
+            let width = parse(fields.next())?;
+            let height = parse(fields.next())?;
+            let params = fields
+                .map(|field| parse(Some(field)))
+                .collect::<io::Result<Vec<f64>>>()?;
+            cameras.push(camera(id, model, width, height, params)?);
+        }
+        Ok(cameras)
+    }
+
+    /// Reads images in the format of `images.txt`.
+    pub fn read_images_text(reader: impl BufRead) -> io::Result<Vec<ColmapImage>> {
+        let mut images = vec![];
+        let mut lines = reader.lines();
+        while let Some(line) = lines.next() {
+            let line = line?;
+            if is_comment(&line) {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                return Err(invalid("image line has too few fields"));
+            }
+            let values = fields[1..8]
+                .iter()
+                .map(|&field| parse(Some(field)))
+                .collect::<io::Result<Vec<f64>>>()?;
+            // The keypoints are on the next line, which is empty if there are none.
+            let points_line = lines.next().transpose()?.unwrap_or_default();
+            let points_fields: Vec<&str> = points_line.split_whitespace().collect();
+            let points = points_fields
+                .chunks(3)
+                .map(|chunk| {
+                    if chunk.len() != 3 {
+                        return Err(invalid("keypoint has too few fields"));
+                    }
+                    let id: i64 = parse(Some(chunk[2]))?;
+                    Ok(ColmapPoint2D {
+                        position: Point2::new(parse(Some(chunk[0]))?, parse(Some(chunk[1]))?),
+                        point3d: (id >= 0).then(|| id as u64),
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            images.push(ColmapImage {
+                id: parse(Some(fields[0]))?,
+                pose: pose_from_parts(&values),
+                camera: parse(Some(fields[8]))?,
+                // Names may contain spaces, so the name is the rest of the line.
+                name: fields[9..].join(" "),
+                points,
+            });
+        }
+        Ok(images)
+    }
+
+    /// Reads 3d points in the format of `points3D.txt`.
+    pub fn read_points_text(reader: impl BufRead) -> io::Result<Vec<ColmapPoint3D>> {
+        let mut points = vec![];
+        for line in reader.lines() {
+            let line = line?;
+            if is_comment(&line) {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 || (fields.len() - 8) % 2 != 0 {
+                return Err(invalid("3d point line has the wrong number of fields"));
+            }
+            points.push(ColmapPoint3D {
+                id: parse(Some(fields[0]))?,
+                position: Point3::new(
+                    parse(Some(fields[1]))?,
+                    parse(Some(fields[2]))?,
+                    parse(Some(fields[3]))?,
+                ),
+                color: [
+                    parse(Some(fields[4]))?,
+                    parse(Some(fields[5]))?,
+                    parse(Some(fields[6]))?,
+                ],
+                error: parse(Some(fields[7]))?,
+                track: fields[8..]
+                    .chunks(2)
+                    .map(|chunk| Ok((parse(Some(chunk[0]))?, parse(Some(chunk[1]))?)))
+                    .collect::<io::Result<Vec<_>>>()?,
+            });
+        }
+        Ok(points)
+    }
+
+    /// Reads cameras in the format of `cameras.bin`.
+    pub fn read_cameras_binary(mut reader: impl Read) -> io::Result<Vec<ColmapCamera>> {
+        let count = read_u64(&mut reader)?;
+        (0..count)
+            .map(|_| {
+                let id = read_i32(&mut reader)? as u32;
+                let model_id = read_i32(&mut reader)?;
+                let model = ColmapCameraModel::from_id(model_id)
+                    .ok_or_else(|| invalid(format!("unsupported camera model id {}", model_id)))?;
+                let width = read_u64(&mut reader)?;
+                let height = read_u64(&mut reader)?;
+                let params = (0..model.num_params())
+                    .map(|_| read_f64(&mut reader))
+                    .collect::<io::Result<Vec<f64>>>()?;
+                camera(id, model, width, height, params)
+            })
+            .collect()
+    }
+
+    /// Reads images in the format of `images.bin`.
+    pub fn read_images_binary(mut reader: impl Read) -> io::Result<Vec<ColmapImage>> {
+        let count = read_u64(&mut reader)?;
+        (0..count)
+            .map(|_| {
+                let id = read_i32(&mut reader)? as u32;
+                let values = (0..7)
+                    .map(|_| read_f64(&mut reader))
+                    .collect::<io::Result<Vec<f64>>>()?;
+                let camera = read_i32(&mut reader)? as u32;
+                let mut name = vec![];
+                loop {
+                    let [byte] = read_bytes::<1>(&mut reader)?;
+                    if byte == 0 {
+                        break;
+                    }
+                    name.push(byte);
+                }
+                let name = String::from_utf8(name).map_err(invalid)?;
+                let num_points = read_u64(&mut reader)?;
+                let points = (0..num_points)
+                    .map(|_| {
+                        let position = Point2::new(read_f64(&mut reader)?, read_f64(&mut reader)?);
+                        let id = i64::from_le_bytes(read_bytes(&mut reader)?);
+                        Ok(ColmapPoint2D {
+                            position,
+                            point3d: (id >= 0).then(|| id as u64),
+                        })
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                Ok(ColmapImage {
+                    id,
+                    pose: pose_from_parts(&values),
+                    camera,
+                    name,
+                    points,
+                })
+            })
+            .collect()
+    }
+
+    /// Reads 3d points in the format of `points3D.bin`.
+    pub fn read_points_binary(mut reader: impl Read) -> io::Result<Vec<ColmapPoint3D>> {
+        let count = read_u64(&mut reader)?;
+        (0..count)
+            .map(|_| {
+                let id = read_u64(&mut reader)?;
+                let position = Point3::new(
+                    read_f64(&mut reader)?,
+                    read_f64(&mut reader)?,
+                    read_f64(&mut reader)?,
+                );
+                let color = read_bytes::<3>(&mut reader)?;
+                let error = read_f64(&mut reader)?;
+                let track_length = read_u64(&mut reader)?;
+                let track = (0..track_length)
+                    .map(|_| Ok((read_i32(&mut reader)? as u32, read_i32(&mut reader)? as u32)))
+                    .collect::<io::Result<Vec<_>>>()?;
+                Ok(ColmapPoint3D {
+                    id,
+                    position,
+                    color,
+                    error,
+                    track,
+                })
+            })
+            .collect()
+    }
+
+    /// Finds a camera by its id.
+    pub fn camera(&self, id: u32) -> Option<&ColmapCamera> {
+        self.cameras.iter().find(|camera| camera.id == id)
+    }
+
+    /// Computes the bearings of the keypoints of an image with its camera.
+    ///
+    /// Returns `None` if the camera of the image is missing.
+    pub fn bearings(&self, image: &ColmapImage) -> Option<Vec<UnitVector3<f64>>> {
+        let camera = self.camera(image.camera)?;
+        Some(
+            image
+                .points
+                .iter()
+                .map(|point| camera.calibrate_colmap(point.position))
+                .collect(),
+        )
+    }
+
+    /// Converts the model to a [`SparseReconstruction`] along with the bearings of the keypoints of each image.
+    ///
+    /// The images of the reconstruction are the images of the model in order, and the observations of each point
+    /// refer to those images and the indices of their keypoints. Observations of missing images are dropped.
+    pub fn sparse_reconstruction(
+        &self,
+    ) -> io::Result<(SparseReconstruction, Vec<Vec<UnitVector3<f64>>>)> {
+        let image_indices: HashMap<u32, usize> = self
+            .images
+            .iter()
+            .enumerate()
+            .map(|(ix, image)| (image.id, ix))
+            .collect();
+        let bearings = self
+            .images
+            .iter()
+            .map(|image| {
+                self.bearings(image)
+                    .ok_or_else(|| invalid(format!("image {} has a missing camera", image.id)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let points = self
+            .points
+            .iter()
+            .map(|point| SparsePoint {
+                point: WorldPoint::from_point(point.position),
+                color: point.color,
+                observations: point
+                    .track
+                    .iter()
+                    .filter_map(|&(image, point2d)| {
+                        Some((*image_indices.get(&image)?, point2d as usize))
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok((
+            SparseReconstruction {
+                poses: self.images.iter().map(|image| Some(image.pose)).collect(),
+                points,
+            },
+            bearings,
+        ))
+    }
+
+    /// The tracks of the 3d points, where images are identified by their id.
+    pub fn tracks(&self) -> Vec<Track<u32>> {
+        self.points
+            .iter()
+            .map(|point| {
+                let mut observations: Vec<(u32, usize)> = point
+                    .track
+                    .iter()
+                    .map(|&(image, point2d)| (image, point2d as usize))
+                    .collect();
+                observations.sort_unstable();
+                Track { observations }
+            })
+            .collect()
+    }
+}
+
+/// Checks that a camera has the right number of parameters for its model.
+fn camera(
+    id: u32,
+    model: ColmapCameraModel,
+    width: u64,
+    height: u64,
+    params: Vec<f64>,
+) -> io::Result<ColmapCamera> {
+    if params.len() != model.num_params() {
+        return Err(invalid(format!(
+            "camera {} has {} parameters but {} needs {}",
+            id,
+            params.len(),
+            model.name(),
+            model.num_params()
+        )));
+    }
+    Ok(ColmapCamera {
+        id,
+        model,
+        width,
+        height,
+        params,
+    })
+}
+
+/// Creates a pose from the `qw, qx, qy, qz, tx, ty, tz` used by COLMAP.
+fn pose_from_parts(values: &[f64]) -> WorldToCamera {
+    let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+        values[0], values[1], values[2], values[3],
+    ));
+    WorldToCamera(IsometryMatrix3::from_parts(
+        Translation3::new(values[4], values[5], values[6]),
+        rotation.to_rotation_matrix(),
+    ))
+}
+
+pub(crate) fn is_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+pub(crate) fn invalid(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+pub(crate) fn parse<F>(field: Option<&str>) -> io::Result<F>
+where
+    F: FromStr,
+    F::Err: std::error::Error + Send + Sync + 'static,
+{
+    field
+        .ok_or_else(|| invalid("line has too few fields"))?
+        .parse()
+        .map_err(invalid)
+}
+
+fn read_bytes<const N: usize>(mut reader: impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u64(reader: impl Read) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_i32(reader: impl Read) -> io::Result<i32> {
+    Ok(i32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_f64(reader: impl Read) -> io::Result<f64> {
+    Ok(f64::from_le_bytes(read_bytes(reader)?))
+}
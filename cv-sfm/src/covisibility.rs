@@ -0,0 +1,256 @@
+//! The covisibility graph of the views of a reconstruction.
+//!
+//! Two views are covisible if they observe the same robust landmarks, and the number of landmarks they share is the
+//! weight of the edge between them. The graph is the basis for choosing the neighborhood of a view for local
+//! optimization, for propagating a loop correction along the views and for finding redundant keyframes. The
+//! spanning tree keeps the strongest edge which connects each view, and the essential graph adds the strong edges to
+//! it, which keeps every view connected with far fewer edges than the full graph.
+
+use crate::{LandmarkKey, ReconstructionKey, VSlam, ViewKey};
+use cv_core::{
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, TriangulatorObservations, WorldToCamera,
+};
+use rand::Rng;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The views of a reconstruction with the number of robust landmarks each pair of views shares.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct CovisibilityGraph {
+    /// The weight of the edges to the neighbors of every view, including views without neighbors.
+    edges: HashMap<ViewKey, HashMap<ViewKey, usize>>,
+}
+
+impl CovisibilityGraph {
+    /// Creates a graph of views without edges.
+    pub fn new(views: impl IntoIterator<Item = ViewKey>) -> Self {
+        Self {
+            edges: views
+                .into_iter()
+                .map(|view| (view, HashMap::new()))
+                .collect(),
+        }
+    }
+
+    /// Adds `weight` to the edge between two views, adding the views if they aren't in the graph.
+    pub fn add_weight(&mut self, a: ViewKey, b: ViewKey, weight: usize) {
+        if a == b {
+            return;
+        }
+        *self.edges.entry(a).or_default().entry(b).or_default() += weight;
+        *self.edges.entry(b).or_default().entry(a).or_default() += weight;
+    }
+
+    /// The views in the graph.
+    pub fn views(&self) -> impl Iterator<Item = ViewKey> + '_ {
+        self.edges.keys().copied()
+    }
+
+    /// The number of views in the graph.
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Whether the graph has no views.
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    /// The number of robust landmarks two views share, which is `0` if they aren't connected.
+    pub fn weight(&self, a: ViewKey, b: ViewKey) -> usize {
+        self.edges
+            .get(&a)
+            .and_then(|neighbors| neighbors.get(&b))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Every edge `(a, b, weight)` once, with `a < b`.
+    pub fn edges(&self) -> impl Iterator<Item = (ViewKey, ViewKey, usize)> + '_ {
+        self.edges.iter().flat_map(|(&a, neighbors)| {
+            neighbors
+                .iter()
+                .filter(move |&(&b, _)| a < b)
+                .map(move |(&b, &weight)| (a, b, weight))
+        })
+    }
+
+    /// The neighbors of a view with the weights of their edges, from the strongest to the weakest edge.
+    pub fn neighbors(&self, view: ViewKey) -> Vec<(ViewKey, usize)> {
+        let mut neighbors: Vec<(ViewKey, usize)> = self
+            .edges
+            .get(&view)
+            .into_iter()
+            .flatten()
+            .map(|(&neighbor, &weight)| (neighbor, weight))
+            .collect();
+        neighbors.sort_unstable_by_key(|&(neighbor, weight)| (Reverse(weight), neighbor));
+        neighbors
+    }
+
+    /// The `count` neighbors of a view with the strongest edges.
+    pub fn best_neighbors(&self, view: ViewKey, count: usize) -> Vec<(ViewKey, usize)> {
+        let mut neighbors = self.neighbors(view);
+        neighbors.truncate(count);
+        neighbors
+    }
+
+    /// The neighbors of a view which share at least `minimum_weight` robust landmarks with it.
+    pub fn neighbors_with_weight(
+        &self,
+        view: ViewKey,
+        minimum_weight: usize,
+    ) -> Vec<(ViewKey, usize)> {
+        let mut neighbors = self.neighbors(view);
+        neighbors.retain(|&(_, weight)| weight >= minimum_weight);
+        neighbors
+    }
+
+    /// The graph with only the edges of at least `minimum_weight`.
+    #[must_use]
+    pub fn filtered(&self, minimum_weight: usize) -> Self {
+        Self {
+            edges: self
+                .edges
+                .iter()
+                .map(|(&view, neighbors)| {
+                    let neighbors = neighbors
+                        .iter()
+                        .filter(|&(_, &weight)| weight >= minimum_weight)
+                        .map(|(&neighbor, &weight)| (neighbor, weight))
+                        .collect();
+                    (view, neighbors)
+                })
+                .collect(),
+        }
+    }
+
+    /// The maximum spanning forest of the graph, which connects each connected component with its strongest edges.
+    ///
+    /// Returns the edges `(a, b, weight)` of the forest.
+    pub fn spanning_tree(&self) -> Vec<(ViewKey, ViewKey, usize)> {
+        // Kruskal's algorithm, adding the strongest edges first.
+        let mut edges: Vec<(ViewKey, ViewKey, usize)> = self.edges().collect();
+        edges.sort_unstable_by_key(|&(a, b, weight)| (Reverse(weight), a, b));
+        let mut parents: HashMap<ViewKey, ViewKey> = HashMap::new();
+        let mut tree = vec![];
+        for (a, b, weight) in edges {
+            let root_a = find_root(&mut parents, a);
+            let root_b = find_root(&mut parents, b);
+            if root_a != root_b {
+                parents.insert(root_a, root_b);
+                tree.push((a, b, weight));
+            }
+        }
+        tree
+    }
+
+    /// The essential graph, which is the spanning tree together with the edges of at least `minimum_weight`.
+    #[must_use]
+    pub fn essential_graph(&self, minimum_weight: usize) -> Self {
+        let mut essential = Self::new(self.views());
+        for (a, b, weight) in self.spanning_tree() {
+            essential.add_weight(a, b, weight);
+        }
+        for (a, b, weight) in self.edges() {
+            if weight >= minimum_weight && essential.weight(a, b) == 0 {
+                essential.add_weight(a, b, weight);
+            }
+        }
+        essential
+    }
+
+    /// The views of each connected component, from the largest to the smallest component.
+    pub fn components(&self) -> Vec<Vec<ViewKey>> {
+        let mut visited: HashSet<ViewKey> = HashSet::new();
+        let mut components = vec![];
+        for start in self.views() {
+            if !visited.insert(start) {
+                continue;
+            }
+            // Flood fill the component from the view.
+            let mut component = vec![];
+            let mut stack = vec![start];
+            while let Some(view) = stack.pop() {
+                component.push(view);
+                for &neighbor in self.edges[&view].keys() {
+                    if visited.insert(neighbor) {
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components.sort_unstable_by_key(|component| Reverse(component.len()));
+        components
+    }
+}
+
+/// Finds the root of the set of a view in a union-find forest, compressing the path to it.
+fn find_root(parents: &mut HashMap<ViewKey, ViewKey>, view: ViewKey) -> ViewKey {
+    let mut root = view;
+    while let Some(&parent) = parents.get(&root) {
+        root = parent;
+    }
+    let mut current = view;
+    while let Some(&parent) = parents.get(&current) {
+        if parent != root {
+            parents.insert(current, root);
+        }
+        current = parent;
+    }
+    root
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Builds the covisibility graph of a reconstruction from its robust landmarks (see
+    /// [`VSlam::is_landmark_robust`]).
+    pub fn covisibility_graph(&self, reconstruction: ReconstructionKey) -> CovisibilityGraph {
+        let landmarks: Vec<LandmarkKey> = self
+            .data
+            .reconstruction(reconstruction)
+            .landmarks
+            .keys()
+            .filter(|&landmark| self.is_landmark_robust(reconstruction, landmark))
+            .collect();
+        self.covisibility_graph_of(reconstruction, landmarks)
+    }
+
+    /// Builds the covisibility graph of a reconstruction from the given landmarks.
+    pub(crate) fn covisibility_graph_of(
+        &self,
+        reconstruction: ReconstructionKey,
+        landmarks: impl IntoIterator<Item = LandmarkKey>,
+    ) -> CovisibilityGraph {
+        let mut graph =
+            CovisibilityGraph::new(self.data.reconstruction(reconstruction).views.keys());
+        for landmark in landmarks {
+            let views: Vec<ViewKey> = self
+                .data
+                .landmark_observations(reconstruction, landmark)
+                .map(|(view, _)| view)
+                .collect();
+            for (i, &a) in views.iter().enumerate() {
+                for &b in &views[i + 1..] {
+                    graph.add_weight(a, b, 1);
+                }
+            }
+        }
+        graph
+    }
+}
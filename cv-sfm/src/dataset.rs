@@ -0,0 +1,530 @@
+//! Loaders for the KITTI odometry, TUM RGB-D and EuRoC MAV benchmarks.
+//!
+//! A [`Dataset`] holds the calibration of one camera of a sequence and its frames in order, each with a timestamp in
+//! seconds, the path of its image, the path of its depth image for RGB-D sequences, its ground-truth pose when the
+//! benchmark has one and the IMU samples since the previous frame. The images are only read when a frame is used, so
+//! a sequence can be fed to [`VisualOdometry`](crate::VisualOdometry) one frame at a time.
+//!
+//! The ground-truth poses are of the camera which the images come from, even when a benchmark records the pose of
+//! another body, so they can be compared against the poses of the pipeline directly.
+
+use crate::{
+    colmap::{invalid, is_comment, parse},
+    DepthMap,
+};
+use cv_core::{
+    nalgebra::{
+        IsometryMatrix3, Matrix3, Point2, Quaternion, Rotation3, Translation3, UnitQuaternion,
+        Vector2, Vector3,
+    },
+    CameraToWorld,
+};
+use cv_pinhole::{CameraIntrinsics, CameraIntrinsicsK1Distortion};
+use image::{DynamicImage, ImageResult};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The largest difference in seconds between the timestamps of a color image and the depth image or ground-truth
+/// pose which is associated with it in TUM RGB-D sequences, as in the tools of the benchmark.
+const TUM_MAX_DIFFERENCE: f64 = 0.02;
+
+/// The largest difference in seconds between the timestamps of an image and its ground-truth pose in EuRoC
+/// sequences, whose ground truth is recorded at 200 Hz.
+const EUROC_MAX_DIFFERENCE: f64 = 0.005;
+
+/// The depth in meters of one unit of the depth images of TUM RGB-D sequences.
+const TUM_DEPTH_SCALE: f32 = 1.0 / 5000.0;
+
+/// A measurement of an inertial measurement unit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImuSample {
+    /// The timestamp in seconds.
+    pub timestamp: f64,
+    /// The angular velocity in radians per second, or `None` if the IMU has no gyroscope.
+    pub angular_velocity: Option<Vector3<f64>>,
+    /// The linear acceleration in meters per second squared.
+    pub linear_acceleration: Vector3<f64>,
+}
+
+/// One frame of a [`Dataset`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DatasetFrame {
+    /// The timestamp in seconds.
+    pub timestamp: f64,
+    /// The path of the image.
+    pub image: PathBuf,
+    /// The path of the depth image registered to the image, for RGB-D sequences.
+    pub depth: Option<PathBuf>,
+    /// The ground-truth pose of the camera, if the benchmark has one for this frame.
+    pub ground_truth: Option<CameraToWorld>,
+    /// The IMU samples after the previous frame up to and including this frame.
+    pub imu: Vec<ImuSample>,
+}
+
+impl DatasetFrame {
+    /// Reads the image.
+    pub fn load_image(&self) -> ImageResult<DynamicImage> {
+        image::open(&self.image)
+    }
+
+    /// Reads the depth image as a depth map in meters, or returns `None` if the frame has no depth image.
+    pub fn load_depth_map(&self, depth_scale: f32) -> ImageResult<Option<DepthMap>> {
+        self.depth
+            .as_ref()
+            .map(|path| {
+                Ok(DepthMap::from_depth_image(
+                    &image::open(path)?.into_luma16(),
+                    depth_scale,
+                ))
+            })
+            .transpose()
+    }
+}
+
+/// One camera of a benchmark sequence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dataset {
+    /// The calibration of the camera.
+    pub intrinsics: CameraIntrinsicsK1Distortion,
+    /// The depth in meters of one unit of the depth images, for RGB-D sequences.
+    pub depth_scale: Option<f32>,
+    /// The frames in the order of their timestamps.
+    pub frames: Vec<DatasetFrame>,
+}
+
+impl Dataset {
+    /// Loads a sequence of the [KITTI odometry benchmark](http://www.cvlibs.net/datasets/kitti/eval_odometry.php)
+    /// from the left gray camera.
+    ///
+    /// `root` is the directory which contains `sequences` and, for the sequences `00` to `10`, `poses`. The images
+    /// of KITTI are rectified, so the intrinsics have no distortion.
+    pub fn kitti(root: impl AsRef<Path>, sequence: &str) -> io::Result<Self> {
+        let root = root.as_ref();
+        let sequence_dir = root.join("sequences").join(sequence);
+        let calibration = fs::read_to_string(sequence_dir.join("calib.txt"))?;
+        let projection = calibration
+            .lines()
+            .find_map(|line| line.strip_prefix("P0:"))
+            .ok_or_else(|| invalid("calib.txt has no P0 line"))?;
+        let projection = parse_fields(projection.split_whitespace())?;
+        if projection.len() != 12 {
+            return Err(invalid("P0 must have 12 entries"));
+        }
+        let intrinsics = CameraIntrinsicsK1Distortion::new(
+            CameraIntrinsics {
+                focals: Vector2::new(projection[0], projection[5]),
+                principal_point: Point2::new(projection[2], projection[6]),
+                skew: projection[1],
+            },
+            0.0,
+        );
+
+        let poses = match fs::read_to_string(root.join("poses").join(format!("{}.txt", sequence))) {
+            Ok(poses) => poses
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    let values = parse_fields(line.split_whitespace())?;
+                    if values.len() != 12 {
+                        return Err(invalid("a pose must have 12 entries"));
+                    }
+                    Ok(pose_from_matrix(
+                        Matrix3::new(
+                            values[0], values[1], values[2], values[4], values[5], values[6],
+                            values[8], values[9], values[10],
+                        ),
+                        Vector3::new(values[3], values[7], values[11]),
+                    ))
+                })
+                .collect::<io::Result<Vec<_>>>()?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => vec![],
+            Err(error) => return Err(error),
+        };
+
+        let frames = fs::read_to_string(sequence_dir.join("times.txt"))?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(ix, line)| {
+                Ok(DatasetFrame {
+                    timestamp: parse_number(Some(line.trim()))?,
+                    image: sequence_dir.join("image_0").join(format!("{:06}.png", ix)),
+                    depth: None,
+                    ground_truth: poses.get(ix).copied(),
+                    imu: vec![],
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            intrinsics,
+            depth_scale: None,
+            frames,
+        })
+    }
+
+    /// Loads a sequence of the [TUM RGB-D benchmark](https://vision.in.tum.de/data/datasets/rgbd-dataset) with
+    /// the calibration of the Freiburg camera which recorded it.
+    ///
+    /// The camera is recognized by `freiburg1`, `freiburg2` or `freiburg3` in the name of the directory. Sequences
+    /// under other names have to be loaded with [`Dataset::tum_rgbd_with_intrinsics`].
+    pub fn tum_rgbd(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .canonicalize()?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // The calibrations published with the benchmark. Only the first radial coefficient is kept, which matches the
+        // distortion of the benchmark to first order.
+        let (focals, principal_point, k1) = if name.contains("freiburg1") {
+            ([517.3, 516.5], [318.6, 255.3], 0.2624)
+        } else if name.contains("freiburg2") {
+            ([520.9, 521.0], [325.1, 249.7], 0.2312)
+        } else if name.contains("freiburg3") {
+            ([535.4, 539.2], [320.1, 247.6], 0.0)
+        } else {
+            return Err(invalid(
+                "the directory name doesn't tell which Freiburg camera recorded the sequence",
+            ));
+        };
+        Self::tum_rgbd_with_intrinsics(
+            path,
+            CameraIntrinsicsK1Distortion::new(
+                CameraIntrinsics {
+                    focals: Vector2::from(focals),
+                    principal_point: Point2::from(principal_point),
+                    skew: 0.0,
+                },
+                k1,
+            ),
+        )
+    }
+
+    /// Loads a sequence of the TUM RGB-D benchmark with the given intrinsics.
+    ///
+    /// Every color image is associated with the depth image and ground-truth pose closest in time, if they are
+    /// within 20 milliseconds, and with the accelerometer samples since the previous color image.
+    pub fn tum_rgbd_with_intrinsics(
+        path: impl AsRef<Path>,
+        intrinsics: CameraIntrinsicsK1Distortion,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        let images = read_tum_list(&path.join("rgb.txt"))?;
+        let depths = match read_tum_list(&path.join("depth.txt")) {
+            Ok(depths) => depths,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => vec![],
+            Err(error) => return Err(error),
+        };
+        let ground_truth = read_optional(&path.join("groundtruth.txt"), |fields| {
+            let values = parse_fields(fields)?;
+            if values.len() != 8 {
+                return Err(invalid("a ground-truth line must have 8 fields"));
+            }
+            let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+                values[7], values[4], values[5], values[6],
+            ));
+            Ok((
+                values[0],
+                pose_from_parts(rotation, Vector3::new(values[1], values[2], values[3])),
+            ))
+        })?;
+        let imu = read_optional(&path.join("accelerometer.txt"), |fields| {
+            let values = parse_fields(fields)?;
+            if values.len() != 4 {
+                return Err(invalid("an accelerometer line must have 4 fields"));
+            }
+            Ok(ImuSample {
+                timestamp: values[0],
+                angular_velocity: None,
+                linear_acceleration: Vector3::new(values[1], values[2], values[3]),
+            })
+        })?;
+
+        let depth_times: Vec<f64> = depths.iter().map(|&(timestamp, _)| timestamp).collect();
+        let ground_truth_times: Vec<f64> = ground_truth
+            .iter()
+            .map(|&(timestamp, _)| timestamp)
+            .collect();
+        let frames = images
+            .into_iter()
+            .map(|(timestamp, image)| DatasetFrame {
+                timestamp,
+                image: path.join(image),
+                depth: nearest(&depth_times, timestamp, TUM_MAX_DIFFERENCE)
+                    .map(|ix| path.join(&depths[ix].1)),
+                ground_truth: nearest(&ground_truth_times, timestamp, TUM_MAX_DIFFERENCE)
+                    .map(|ix| ground_truth[ix].1),
+                imu: vec![],
+            })
+            .collect();
+        Ok(Self {
+            intrinsics,
+            depth_scale: Some(TUM_DEPTH_SCALE),
+            frames,
+        }
+        .with_imu(imu))
+    }
+
+    /// Loads a sequence of the [EuRoC MAV benchmark](https://projects.asl.ethz.ch/datasets/doku.php?id=kmavvisualinertialdatasets)
+    /// from the left camera `cam0`.
+    ///
+    /// `path` is the directory which contains `mav0`. The ground truth of EuRoC is the pose of the IMU, so it is
+    /// transformed into the pose of the camera with the extrinsics of the camera.
+    pub fn euroc(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mav = path.as_ref().join("mav0");
+        let camera_dir = mav.join("cam0");
+        let sensor = fs::read_to_string(camera_dir.join("sensor.yaml"))?;
+        let projection = yaml_list(&sensor, "intrinsics")
+            .ok_or_else(|| invalid("sensor.yaml has no intrinsics"))??;
+        let distortion = yaml_list(&sensor, "distortion_coefficients")
+            .ok_or_else(|| invalid("sensor.yaml has no distortion coefficients"))??;
+        let extrinsics = sensor
+            .find("T_BS")
+            .and_then(|start| yaml_list(&sensor[start..], "data"))
+            .ok_or_else(|| invalid("sensor.yaml has no T_BS"))??;
+        if projection.len() != 4 || distortion.is_empty() || extrinsics.len() != 16 {
+            return Err(invalid("sensor.yaml has the wrong number of entries"));
+        }
+        // The camera uses the radial-tangential model, of which the first radial coefficient matches to first
+        // order.
+        let intrinsics = CameraIntrinsicsK1Distortion::new(
+            CameraIntrinsics {
+                focals: Vector2::new(projection[0], projection[1]),
+                principal_point: Point2::new(projection[2], projection[3]),
+                skew: 0.0,
+            },
+            distortion[0],
+        );
+        let camera_to_body = pose_from_matrix(
+            Matrix3::new(
+                extrinsics[0],
+                extrinsics[1],
+                extrinsics[2],
+                extrinsics[4],
+                extrinsics[5],
+                extrinsics[6],
+                extrinsics[8],
+                extrinsics[9],
+                extrinsics[10],
+            ),
+            Vector3::new(extrinsics[3], extrinsics[7], extrinsics[11]),
+        );
+
+        let ground_truth = read_optional_csv(
+            &mav.join("state_groundtruth_estimate0").join("data.csv"),
+            |fields| {
+                let values = parse_fields(fields.take(8))?;
+                if values.len() != 8 {
+                    return Err(invalid("a ground-truth line must have at least 8 fields"));
+                }
+                let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+                    values[4], values[5], values[6], values[7],
+                ));
+                let body_to_world =
+                    pose_from_parts(rotation, Vector3::new(values[1], values[2], values[3]));
+                Ok((
+                    values[0] * 1e-9,
+                    CameraToWorld(body_to_world.0 * camera_to_body.0),
+                ))
+            },
+        )?;
+        let imu = read_optional_csv(&mav.join("imu0").join("data.csv"), |fields| {
+            let values = parse_fields(fields)?;
+            if values.len() != 7 {
+                return Err(invalid("an IMU line must have 7 fields"));
+            }
+            Ok(ImuSample {
+                timestamp: values[0] * 1e-9,
+                angular_velocity: Some(Vector3::new(values[1], values[2], values[3])),
+                linear_acceleration: Vector3::new(values[4], values[5], values[6]),
+            })
+        })?;
+
+        let ground_truth_times: Vec<f64> = ground_truth
+            .iter()
+            .map(|&(timestamp, _)| timestamp)
+            .collect();
+        let frames = read_csv(&camera_dir.join("data.csv"), |fields| {
+            let timestamp = parse_number(fields.next())? * 1e-9;
+            let image = fields
+                .next()
+                .ok_or_else(|| invalid("an image line must have 2 fields"))?;
+            Ok(DatasetFrame {
+                timestamp,
+                image: camera_dir.join("data").join(image),
+                depth: None,
+                ground_truth: nearest(&ground_truth_times, timestamp, EUROC_MAX_DIFFERENCE)
+                    .map(|ix| ground_truth[ix].1),
+                imu: vec![],
+            })
+        })?;
+        Ok(Self {
+            intrinsics,
+            depth_scale: None,
+            frames,
+        }
+        .with_imu(imu))
+    }
+
+    /// The number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether the sequence has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Iterates over the frames in order.
+    pub fn iter(&self) -> std::slice::Iter<'_, DatasetFrame> {
+        self.frames.iter()
+    }
+
+    /// The timestamps and ground-truth poses of the frames which have one.
+    pub fn ground_truth(&self) -> Vec<(f64, CameraToWorld)> {
+        self.frames
+            .iter()
+            .filter_map(|frame| Some((frame.timestamp, frame.ground_truth?)))
+            .collect()
+    }
+
+    /// Gives every frame the IMU samples after the previous frame up to and including itself.
+    fn with_imu(mut self, mut imu: Vec<ImuSample>) -> Self {
+        self.frames
+            .sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
+        imu.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
+        let mut samples = imu.into_iter().peekable();
+        for frame in &mut self.frames {
+            while let Some(sample) = samples.next_if(|sample| sample.timestamp <= frame.timestamp) {
+                frame.imu.push(sample);
+            }
+        }
+        self
+    }
+}
+
+impl<'a> IntoIterator for &'a Dataset {
+    type Item = &'a DatasetFrame;
+    type IntoIter = std::slice::Iter<'a, DatasetFrame>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// The index of the timestamp closest to `timestamp`, if it is at most `max_difference` away.
+fn nearest(timestamps: &[f64], timestamp: f64, max_difference: f64) -> Option<usize> {
+    timestamps
+        .iter()
+        .enumerate()
+        .map(|(ix, &other)| (ix, (other - timestamp).abs()))
+        .filter(|&(_, difference)| difference <= max_difference)
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(ix, _)| ix)
+}
+
+fn pose_from_parts(rotation: UnitQuaternion<f64>, translation: Vector3<f64>) -> CameraToWorld {
+    CameraToWorld(IsometryMatrix3::from_parts(
+        Translation3::from(translation),
+        rotation.to_rotation_matrix(),
+    ))
+}
+
+/// A pose from a rotation matrix which may be slightly off from orthonormal, as in text files.
+fn pose_from_matrix(rotation: Matrix3<f64>, translation: Vector3<f64>) -> CameraToWorld {
+    CameraToWorld(IsometryMatrix3::from_parts(
+        Translation3::from(translation),
+        Rotation3::from_matrix(&rotation),
+    ))
+}
+
+/// Reads a TUM list of timestamps and file names.
+fn read_tum_list(path: &Path) -> io::Result<Vec<(f64, String)>> {
+    read_lines(path, |mut fields| {
+        let timestamp = parse_number(fields.next())?;
+        let name = fields
+            .next()
+            .ok_or_else(|| invalid("line has too few fields"))?;
+        Ok((timestamp, name.to_owned()))
+    })
+}
+
+/// Reads the lines of a text file with whitespace-separated fields and `#` comments.
+fn read_lines<T>(
+    path: &Path,
+    mut parse_line: impl FnMut(std::str::SplitWhitespace<'_>) -> io::Result<T>,
+) -> io::Result<Vec<T>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !is_comment(line))
+        .map(|line| parse_line(line.split_whitespace()))
+        .collect()
+}
+
+/// Like [`read_lines`], but returns no lines if the file doesn't exist.
+fn read_optional<T>(
+    path: &Path,
+    parse_line: impl FnMut(std::str::SplitWhitespace<'_>) -> io::Result<T>,
+) -> io::Result<Vec<T>> {
+    match read_lines(path, parse_line) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        result => result,
+    }
+}
+
+/// Reads the lines of a CSV file with `#` comments.
+fn read_csv<T>(
+    path: &Path,
+    mut parse_line: impl FnMut(&mut dyn Iterator<Item = &str>) -> io::Result<T>,
+) -> io::Result<Vec<T>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !is_comment(line))
+        .map(|line| parse_line(&mut line.trim().split(',').map(str::trim)))
+        .collect()
+}
+
+/// Like [`read_csv`], but returns no lines if the file doesn't exist.
+fn read_optional_csv<T>(
+    path: &Path,
+    parse_line: impl FnMut(&mut dyn Iterator<Item = &str>) -> io::Result<T>,
+) -> io::Result<Vec<T>> {
+    match read_csv(path, parse_line) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        result => result,
+    }
+}
+
+/// Finds the first flow sequence of numbers after `key:` in a YAML document, such as `key: [1.0, 2.0]`.
+fn yaml_list(yaml: &str, key: &str) -> Option<io::Result<Vec<f64>>> {
+    let start = yaml.find(&format!("{}:", key))?;
+    let rest = &yaml[start..];
+    let open = rest.find('[')?;
+    let close = rest[open..].find(']')? + open;
+    Some(parse_fields(
+        rest[open + 1..close]
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty()),
+    ))
+}
+
+fn parse_fields<'a>(fields: impl Iterator<Item = &'a str>) -> io::Result<Vec<f64>> {
+    fields.map(|field| parse_number(Some(field))).collect()
+}
+
+/// Parses a number, rejecting `nan` and `inf` so that timestamps can always be ordered.
+fn parse_number(field: Option<&str>) -> io::Result<f64> {
+    let number: f64 = parse(field)?;
+    if number.is_finite() {
+        Ok(number)
+    } else {
+        Err(invalid(format!(
+            "expected a finite number, found {}",
+            number
+        )))
+    }
+}
@@ -0,0 +1,397 @@
+//! Absolute trajectory error and relative pose error of estimated trajectories.
+//!
+//! A trajectory is a list of timestamps in seconds and poses of the camera in the world, such as the ground truth of a
+//! [`Dataset`](crate::Dataset) or the poses of [`OdometryUpdate`](crate::OdometryUpdate)s inverted with
+//! [`Pose::inverse`]. The estimated poses are associated with the ground-truth poses closest in time, the estimated
+//! trajectory is aligned to the ground truth with [`Umeyama`], and then:
+//!
+//! * the absolute trajectory error (ATE) compares every aligned pose with its ground-truth pose, which measures the
+//!   global consistency of the trajectory, and
+//! * the relative pose error (RPE) compares the motion over segments of a number of frames, a duration or a distance
+//!   travelled with the same motion of the ground truth, which measures drift independently of earlier errors.
+//!
+//! Monocular trajectories are only known up to scale, so they need [`Alignment::Sim3`], while stereo, RGB-D and
+//! visual-inertial trajectories are metric and should use [`Alignment::Se3`] so scale errors show up in the results.
+
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point3},
+    sample_consensus::Estimator,
+    CameraToWorld, Pose, Sim3,
+};
+use cv_geom::alignment::{PointMatch, Umeyama};
+use float_ord::FloatOrd;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// How the estimated trajectory is aligned to the ground truth before the errors are computed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum Alignment {
+    /// The trajectories are already in the same world.
+    None,
+    /// A rigid transformation of the estimated camera centers onto the ground truth.
+    Se3,
+    /// A similarity of the estimated camera centers onto the ground truth, which also corrects the scale.
+    Sim3,
+}
+
+/// The length of the segments the relative pose error is computed over.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum Segment {
+    /// A number of associated poses, where `Frames(1)` compares consecutive poses.
+    Frames(usize),
+    /// A duration in seconds, such as `1.0` for the drift per second of the TUM RGB-D benchmark.
+    Seconds(f64),
+    /// A distance travelled by the ground truth, such as the lengths of 100 to 800 meters of the KITTI benchmark.
+    Distance(f64),
+}
+
+/// Summary statistics of a list of errors.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct ErrorStatistics {
+    /// The number of errors.
+    pub count: usize,
+    /// The root mean square error.
+    pub rmse: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ErrorStatistics {
+    /// Computes the statistics of a list of errors, or returns `None` if it is empty.
+    pub fn from_errors(errors: &[f64]) -> Option<Self> {
+        if errors.is_empty() {
+            return None;
+        }
+        let count = errors.len();
+        let mean = errors.iter().sum::<f64>() / count as f64;
+        let mean_square = errors.iter().map(|error| error * error).sum::<f64>() / count as f64;
+        let mut sorted = errors.to_vec();
+        sorted.sort_unstable_by_key(|&error| FloatOrd(error));
+        let median = if count % 2 == 0 {
+            (sorted[count / 2 - 1] + sorted[count / 2]) / 2.0
+        } else {
+            sorted[count / 2]
+        };
+        Some(Self {
+            count,
+            rmse: mean_square.sqrt(),
+            mean,
+            median,
+            std_dev: (mean_square - mean * mean).max(0.0).sqrt(),
+            min: sorted[0],
+            max: sorted[count - 1],
+        })
+    }
+}
+
+/// The absolute trajectory error of an estimated trajectory.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct AbsoluteTrajectoryError {
+    /// The similarity from the world of the estimated trajectory to the world of the ground truth.
+    pub alignment: Sim3,
+    /// The associated indices of the estimated and ground-truth poses, as `(estimated, ground_truth)`.
+    pub associations: Vec<(usize, usize)>,
+    /// The distance between every aligned camera center and its ground-truth camera center, in the order of
+    /// `associations`.
+    pub translation_errors: Vec<f64>,
+    /// The angle in degrees between every aligned camera orientation and its ground-truth orientation.
+    pub rotation_errors: Vec<f64>,
+    /// The statistics of the translation errors in the units of the ground truth.
+    pub translation: ErrorStatistics,
+    /// The statistics of the rotation errors in degrees.
+    pub rotation: ErrorStatistics,
+}
+
+/// The relative pose error of an estimated trajectory over one segment length.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct RelativePoseError {
+    /// The segment length.
+    pub segment: Segment,
+    /// The number of segments which were compared.
+    pub segments: usize,
+    /// The statistics of the translation errors of the relative motions in the units of the ground truth.
+    pub translation: ErrorStatistics,
+    /// The statistics of the rotation errors of the relative motions in degrees.
+    pub rotation: ErrorStatistics,
+    /// The statistics of the translation errors divided by the distance travelled by the ground truth, which is the
+    /// drift reported by the KITTI benchmark as a ratio rather than a percentage.
+    ///
+    /// Segments where the ground truth didn't move are left out, so this is `None` if it never moved.
+    pub translation_drift: Option<ErrorStatistics>,
+    /// The statistics of the rotation errors divided by the distance travelled by the ground truth in degrees per
+    /// unit of distance.
+    pub rotation_drift: Option<ErrorStatistics>,
+}
+
+/// Associates two lists of timestamps with each other, such as the timestamps of estimated and ground-truth poses.
+///
+/// `offset` is added to the timestamps of `second`. Pairs of timestamps at most `max_difference` apart are matched
+/// in order of their difference, and every timestamp is used at most once, as in the tools of the TUM RGB-D
+/// benchmark. The pairs of indices are returned in the order of `first`.
+pub fn associate(
+    first: &[f64],
+    second: &[f64],
+    offset: f64,
+    max_difference: f64,
+) -> Vec<(usize, usize)> {
+    let mut sorted_second: Vec<(f64, usize)> = second
+        .iter()
+        .enumerate()
+        .map(|(ix, &timestamp)| (timestamp + offset, ix))
+        .collect();
+    sorted_second.sort_unstable_by_key(|&(timestamp, _)| FloatOrd(timestamp));
+
+    let mut candidates = vec![];
+    for (a, &timestamp) in first.iter().enumerate() {
+        let start = sorted_second.partition_point(|&(other, _)| other < timestamp - max_difference);
+        for &(other, b) in &sorted_second[start..] {
+            if other > timestamp + max_difference {
+                break;
+            }
+            candidates.push(((other - timestamp).abs(), a, b));
+        }
+    }
+    candidates.sort_unstable_by_key(|&(difference, a, b)| (FloatOrd(difference), a, b));
+
+    let mut used_first = vec![false; first.len()];
+    let mut used_second = vec![false; second.len()];
+    let mut pairs = vec![];
+    for (_, a, b) in candidates {
+        if !used_first[a] && !used_second[b] {
+            used_first[a] = true;
+            used_second[b] = true;
+            pairs.push((a, b));
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Computes the absolute trajectory error and relative pose error of estimated trajectories.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct TrajectoryEvaluator {
+    alignment: Alignment,
+    max_time_difference: f64,
+    time_offset: f64,
+}
+
+impl TrajectoryEvaluator {
+    /// Creates a `TrajectoryEvaluator` with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how the estimated trajectory is aligned to the ground truth.
+    ///
+    /// Default is [`Alignment::Se3`].
+    #[must_use]
+    pub fn alignment(self, alignment: Alignment) -> Self {
+        Self { alignment, ..self }
+    }
+
+    /// Set the largest difference in seconds between the timestamps of an estimated pose and the ground-truth pose it
+    /// is associated with.
+    ///
+    /// Default is `0.02`, as in the tools of the TUM RGB-D benchmark.
+    #[must_use]
+    pub fn max_time_difference(self, max_time_difference: f64) -> Self {
+        Self {
+            max_time_difference,
+            ..self
+        }
+    }
+
+    /// Set the offset in seconds which is added to the timestamps of the ground truth before they are associated,
+    /// for when the clocks of the ground truth and of the camera differ.
+    ///
+    /// Default is `0.0`.
+    #[must_use]
+    pub fn time_offset(self, time_offset: f64) -> Self {
+        Self {
+            time_offset,
+            ..self
+        }
+    }
+
+    /// Computes the absolute trajectory error of an estimated trajectory.
+    ///
+    /// Returns `None` if too few poses could be associated to align the trajectories.
+    pub fn absolute_trajectory_error(
+        &self,
+        estimated: &[(f64, CameraToWorld)],
+        ground_truth: &[(f64, CameraToWorld)],
+    ) -> Option<AbsoluteTrajectoryError> {
+        let (associations, alignment) = self.associate_and_align(estimated, ground_truth)?;
+        let (translation_errors, rotation_errors): (Vec<f64>, Vec<f64>) = associations
+            .iter()
+            .map(|&(e, g)| {
+                let aligned = align(alignment, estimated[e].1);
+                let truth = ground_truth[g].1.isometry();
+                (
+                    (aligned.translation.vector - truth.translation.vector).norm(),
+                    aligned
+                        .rotation
+                        .rotation_to(&truth.rotation)
+                        .angle()
+                        .to_degrees(),
+                )
+            })
+            .unzip();
+        Some(AbsoluteTrajectoryError {
+            alignment,
+            translation: ErrorStatistics::from_errors(&translation_errors)?,
+            rotation: ErrorStatistics::from_errors(&rotation_errors)?,
+            associations,
+            translation_errors,
+            rotation_errors,
+        })
+    }
+
+    /// Computes the relative pose error of an estimated trajectory over each of the given segment lengths.
+    ///
+    /// Segments start at every associated pose and end at the first associated pose which is at least the segment
+    /// length away. The result has one entry per segment length which any segment fit into. The trajectories are
+    /// aligned first so that [`Alignment::Sim3`] corrects the scale of the relative motions.
+    pub fn relative_pose_error(
+        &self,
+        estimated: &[(f64, CameraToWorld)],
+        ground_truth: &[(f64, CameraToWorld)],
+        segments: &[Segment],
+    ) -> Vec<RelativePoseError> {
+        let (associations, alignment) = match self.associate_and_align(estimated, ground_truth) {
+            Some(aligned) => aligned,
+            None => return vec![],
+        };
+        let poses: Vec<(f64, IsometryMatrix3<f64>, IsometryMatrix3<f64>)> = associations
+            .iter()
+            .map(|&(e, g)| {
+                (
+                    estimated[e].0,
+                    align(alignment, estimated[e].1),
+                    ground_truth[g].1.isometry(),
+                )
+            })
+            .collect();
+        // The distance travelled by the ground truth up to every pose.
+        let distances: Vec<f64> = poses
+            .iter()
+            .scan((0.0, None), |(distance, previous), (_, _, truth)| {
+                if let Some(previous) = previous {
+                    *distance += (truth.translation.vector - *previous).norm();
+                }
+                *previous = Some(truth.translation.vector);
+                Some(*distance)
+            })
+            .collect();
+
+        segments
+            .iter()
+            .filter_map(|&segment| {
+                let mut translation_errors = vec![];
+                let mut rotation_errors = vec![];
+                let mut translation_drifts = vec![];
+                let mut rotation_drifts = vec![];
+                for start in 0..poses.len() {
+                    let end = match segment {
+                        Segment::Frames(frames) => Some(start + frames.max(1)),
+                        Segment::Seconds(seconds) => (start + 1..poses.len())
+                            .find(|&end| poses[end].0 - poses[start].0 >= seconds),
+                        Segment::Distance(distance) => (start + 1..poses.len())
+                            .find(|&end| distances[end] - distances[start] >= distance),
+                    };
+                    let end = match end {
+                        Some(end) if end < poses.len() => end,
+                        _ => continue,
+                    };
+                    let (_, estimated_start, truth_start) = poses[start];
+                    let (_, estimated_end, truth_end) = poses[end];
+                    let estimated_motion = estimated_start.inverse() * estimated_end;
+                    let truth_motion = truth_start.inverse() * truth_end;
+                    let error = truth_motion.inverse() * estimated_motion;
+                    let translation_error = error.translation.vector.norm();
+                    let rotation_error = error.rotation.angle().to_degrees();
+                    translation_errors.push(translation_error);
+                    rotation_errors.push(rotation_error);
+                    let travelled = distances[end] - distances[start];
+                    if travelled > 0.0 {
+                        translation_drifts.push(translation_error / travelled);
+                        rotation_drifts.push(rotation_error / travelled);
+                    }
+                }
+                Some(RelativePoseError {
+                    segment,
+                    segments: translation_errors.len(),
+                    translation: ErrorStatistics::from_errors(&translation_errors)?,
+                    rotation: ErrorStatistics::from_errors(&rotation_errors)?,
+                    translation_drift: ErrorStatistics::from_errors(&translation_drifts),
+                    rotation_drift: ErrorStatistics::from_errors(&rotation_drifts),
+                })
+            })
+            .collect()
+    }
+
+    /// Associates the poses by their timestamps and aligns the estimated camera centers to the ground truth.
+    fn associate_and_align(
+        &self,
+        estimated: &[(f64, CameraToWorld)],
+        ground_truth: &[(f64, CameraToWorld)],
+    ) -> Option<(Vec<(usize, usize)>, Sim3)> {
+        let estimated_times: Vec<f64> = estimated.iter().map(|&(timestamp, _)| timestamp).collect();
+        let ground_truth_times: Vec<f64> = ground_truth
+            .iter()
+            .map(|&(timestamp, _)| timestamp)
+            .collect();
+        let associations = associate(
+            &estimated_times,
+            &ground_truth_times,
+            self.time_offset,
+            self.max_time_difference,
+        );
+        if associations.is_empty() {
+            return None;
+        }
+        let center = |pose: CameraToWorld| Point3::from(pose.isometry().translation.vector);
+        let alignment = match self.alignment {
+            Alignment::None => Sim3::identity(),
+            Alignment::Se3 | Alignment::Sim3 => Umeyama::new()
+                .scale(self.alignment == Alignment::Sim3)
+                .estimate(associations.iter().map(|&(e, g)| {
+                    PointMatch(center(estimated[e].1), center(ground_truth[g].1))
+                }))?,
+        };
+        Some((associations, alignment))
+    }
+}
+
+impl Default for TrajectoryEvaluator {
+    fn default() -> Self {
+        Self {
+            alignment: Alignment::Se3,
+            max_time_difference: 0.02,
+            time_offset: 0.0,
+        }
+    }
+}
+
+/// Moves a camera pose with a similarity of the world, keeping the camera rigid.
+fn align(alignment: Sim3, pose: CameraToWorld) -> IsometryMatrix3<f64> {
+    let pose = pose.isometry();
+    IsometryMatrix3::from_parts(
+        (alignment.0 * Point3::from(pose.translation.vector))
+            .coords
+            .into(),
+        alignment.rotation() * pose.rotation,
+    )
+}
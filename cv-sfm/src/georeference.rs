@@ -0,0 +1,349 @@
+//! Georeferencing of reconstructions with GPS tags.
+//!
+//! Images from drones and phones are usually tagged with the geodetic position of the camera. The tags are converted
+//! into east, north and up in meters from a datum at their center, and a [`Sim3`] from the world of the
+//! reconstruction to that local tangent plane is fit to the camera centers with [`Umeyama`] inside sample consensus,
+//! since GPS tags suffer from multipath outliers and jumps. The resulting [`Georeference`] carries the datum, so a
+//! point cloud transformed with [`PointCloud::georeferenced`] exports real-world coordinates.
+//!
+//! The camera centers must not all lie on a line, as the rotation about the line is otherwise unknown. Drone flights
+//! which turn at least once are sufficient.
+
+use crate::{opeek, FrameKey, PointCloud, ReconstructionKey, SparseReconstruction, VSlam, ViewKey};
+use cv_core::{
+    nalgebra::{Point3, Vector3},
+    sample_consensus::{Consensus, Estimator, Model},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Sim3, TriangulatorObservations,
+    WorldToCamera,
+};
+use cv_geom::alignment::{PointMatch, Umeyama};
+use cv_optimize::{Ellipsoid, Geodetic, LocalTangentPlane};
+use log::*;
+use rand::{seq::SliceRandom, Rng};
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The alignment of a reconstruction to the earth.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct Georeference {
+    /// The local tangent plane at the center of the tags.
+    pub datum: LocalTangentPlane,
+    /// The similarity from the world of the reconstruction to east, north and up in meters from the datum.
+    pub alignment: Sim3,
+    /// The indices of the tags which agree with the alignment.
+    pub inliers: Vec<usize>,
+}
+
+impl Georeference {
+    /// Converts a point in the world of the reconstruction to east, north and up in meters from the datum.
+    pub fn to_enu(&self, point: Point3<f64>) -> Point3<f64> {
+        self.alignment.0 * point
+    }
+
+    /// Converts a point in the world of the reconstruction to a geodetic position.
+    pub fn to_geodetic(&self, point: Point3<f64>) -> Geodetic {
+        self.datum.to_geodetic(self.to_enu(point).coords)
+    }
+
+    /// Converts a pose in the world of the reconstruction to a pose in the local tangent plane.
+    pub fn pose(&self, pose: WorldToCamera) -> WorldToCamera {
+        WorldToCamera((Sim3::from_pose(pose) * self.alignment.inverse()).isometry())
+    }
+}
+
+/// Fits a [`Georeference`] to the camera centers of a reconstruction and their GPS tags.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct Georeferencer {
+    threshold: f64,
+    iterations: usize,
+    scale: bool,
+    ellipsoid: Ellipsoid,
+}
+
+impl Georeferencer {
+    /// Creates a `Georeferencer` with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the distance in meters between a transformed camera center and its tag below which the tag is an inlier.
+    ///
+    /// Default is `5.0`, which suits the receivers of consumer drones and phones.
+    #[must_use]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self { threshold, ..self }
+    }
+
+    /// Set the number of minimal samples which are tried.
+    ///
+    /// Default is `256`.
+    #[must_use]
+    pub fn iterations(self, iterations: usize) -> Self {
+        Self { iterations, ..self }
+    }
+
+    /// Set whether the scale is estimated, which should be disabled for reconstructions which are already metric.
+    ///
+    /// Default is `true`.
+    #[must_use]
+    pub fn scale(self, scale: bool) -> Self {
+        Self { scale, ..self }
+    }
+
+    /// Set the ellipsoid the tags are given on.
+    ///
+    /// Default is [`Ellipsoid::WGS84`], which GPS uses.
+    #[must_use]
+    pub fn ellipsoid(self, ellipsoid: Ellipsoid) -> Self {
+        Self { ellipsoid, ..self }
+    }
+
+    /// Fits a georeference to camera centers in the world of a reconstruction and their GPS tags.
+    ///
+    /// Returns `None` if there are fewer than three tags or fewer than three of them agree on an alignment.
+    pub fn align(
+        &self,
+        tags: &[(Point3<f64>, Geodetic)],
+        rng: &mut impl Rng,
+    ) -> Option<Georeference> {
+        self.align_indexed(
+            tags.iter()
+                .enumerate()
+                .map(|(index, &(center, tag))| (index, center, tag))
+                .collect(),
+            rng,
+        )
+    }
+
+    /// Fits a georeference to camera centers with the indices they are reported as inliers with.
+    fn align_indexed(
+        &self,
+        tags: Vec<(usize, Point3<f64>, Geodetic)>,
+        rng: &mut impl Rng,
+    ) -> Option<Georeference> {
+        let minimum = <Umeyama as Estimator<PointMatch>>::MIN_SAMPLES;
+        if tags.len() < minimum {
+            info!(
+                "only got {} GPS tags for georeferencing, need {}",
+                tags.len(),
+                minimum
+            );
+            return None;
+        }
+
+        // The datum is the center of the tags, which keeps the local tangent plane close to the earth everywhere.
+        let center = tags
+            .iter()
+            .map(|(_, _, tag)| tag.to_ecef(&self.ellipsoid))
+            .sum::<Vector3<f64>>()
+            / tags.len() as f64;
+        let datum =
+            LocalTangentPlane::new(Geodetic::from_ecef(center, &self.ellipsoid), self.ellipsoid);
+        let points: Vec<(usize, PointMatch)> = tags
+            .iter()
+            .map(|&(index, center, tag)| {
+                (index, PointMatch(center, Point3::from(datum.to_enu(&tag))))
+            })
+            .collect();
+
+        let is_inlier = |sim3: &Sim3, point: &PointMatch| sim3.residual(point) < self.threshold;
+        let count_inliers = |sim3: &Sim3| {
+            points
+                .iter()
+                .filter(|(_, point)| is_inlier(sim3, point))
+                .count()
+        };
+
+        let estimator = Umeyama::new().scale(self.scale);
+        let mut best: Option<(Sim3, usize)> = None;
+        for _ in 0..self.iterations {
+            let sample: Vec<PointMatch> = points
+                .choose_multiple(rng, minimum)
+                .map(|&(_, point)| point)
+                .collect();
+            if let Some(sim3) = estimator.estimate(sample.iter().copied()) {
+                let count = count_inliers(&sim3);
+                if best.map_or(true, |(_, best_count)| count > best_count) {
+                    best = Some((sim3, count));
+                }
+            }
+        }
+        let (sim3, _) = best.or_else(opeek(|| info!("failed to estimate the georeference")))?;
+
+        // Refit the similarity to all of the inliers.
+        let inliers: Vec<PointMatch> = points
+            .iter()
+            .filter(|(_, point)| is_inlier(&sim3, point))
+            .map(|&(_, point)| point)
+            .collect();
+        let alignment = estimator.estimate(inliers.iter().copied()).unwrap_or(sim3);
+        let inliers: Vec<usize> = points
+            .iter()
+            .filter(|(_, point)| is_inlier(&alignment, point))
+            .map(|&(index, _)| index)
+            .collect();
+        info!(
+            "georeference has {} inliers among {} GPS tags with scale {}",
+            inliers.len(),
+            points.len(),
+            alignment.scale()
+        );
+        if inliers.len() < minimum {
+            info!(
+                "only found {} georeference inliers, need {}",
+                inliers.len(),
+                minimum
+            );
+            return None;
+        }
+        Some(Georeference {
+            datum,
+            alignment,
+            inliers,
+        })
+    }
+}
+
+impl Default for Georeferencer {
+    fn default() -> Self {
+        Self {
+            threshold: 5.0,
+            iterations: 256,
+            scale: true,
+            ellipsoid: Ellipsoid::WGS84,
+        }
+    }
+}
+
+/// The center of the camera of a pose.
+fn camera_center(pose: WorldToCamera) -> Point3<f64> {
+    Point3::from(pose.inverse().isometry().translation.vector)
+}
+
+impl PointCloud {
+    /// Transforms the point cloud from the world of a reconstruction into the local tangent plane of a georeference
+    /// and stores its datum, so that the exported files carry real-world coordinates.
+    #[must_use]
+    pub fn georeferenced(self, georeference: &Georeference) -> Self {
+        let sim3 = georeference.alignment;
+        let rotation = sim3.rotation();
+        let variance_scale = sim3.scale() * sim3.scale();
+        Self {
+            points: self
+                .points
+                .into_iter()
+                .map(|mut point| {
+                    point.position = georeference.to_enu(point.position);
+                    point.normal = point.normal.map(|normal| rotation * normal);
+                    point.covariance = point.covariance.map(|covariance| {
+                        variance_scale
+                            * rotation.matrix()
+                            * covariance
+                            * rotation.matrix().transpose()
+                    });
+                    point
+                })
+                .collect(),
+            datum: Some(georeference.datum),
+        }
+    }
+}
+
+impl SparseReconstruction {
+    /// Fits a georeference to the GPS tags of the images, where `tags[i]` is the tag of image `i`, if it has one.
+    ///
+    /// The inliers of the georeference are the indices of the images.
+    pub fn georeference(
+        &self,
+        tags: &[Option<Geodetic>],
+        georeferencer: &Georeferencer,
+        rng: &mut impl Rng,
+    ) -> Option<Georeference> {
+        georeferencer.align_indexed(
+            self.registered()
+                .filter_map(|(image, pose)| {
+                    Some((image, camera_center(pose), (*tags.get(image)?)?))
+                })
+                .collect(),
+            rng,
+        )
+    }
+
+    /// Moves the reconstruction into the local tangent plane of a georeference.
+    pub fn apply_georeference(&mut self, georeference: &Georeference) {
+        for pose in self.poses.iter_mut().flatten() {
+            *pose = georeference.pose(*pose);
+        }
+        for point in &mut self.points {
+            point.point = georeference.alignment.transform(point.point);
+        }
+    }
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Fits a georeference to the GPS tags of the frames of a reconstruction.
+    ///
+    /// Tags of frames which are not views of the reconstruction are ignored. The inliers of the georeference are the
+    /// indices into `tags`.
+    pub fn georeference(
+        &self,
+        reconstruction: ReconstructionKey,
+        tags: &[(FrameKey, Geodetic)],
+        georeferencer: &Georeferencer,
+    ) -> Option<Georeference> {
+        let tags = tags
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &(frame, tag))| {
+                let (frame_reconstruction, view) = self.data.frame(frame).view?;
+                if frame_reconstruction != reconstruction {
+                    return None;
+                }
+                Some((
+                    index,
+                    camera_center(self.data.pose(reconstruction, view)),
+                    tag,
+                ))
+            })
+            .collect();
+        georeferencer.align_indexed(tags, &mut *self.rng.borrow_mut())
+    }
+
+    /// Moves a reconstruction into the local tangent plane of a georeference.
+    ///
+    /// Landmarks are triangulated from the poses of their views, so moving the poses moves the landmarks too.
+    pub fn apply_georeference(
+        &mut self,
+        reconstruction: ReconstructionKey,
+        georeference: &Georeference,
+    ) {
+        let views: Vec<ViewKey> = self
+            .data
+            .reconstruction(reconstruction)
+            .views
+            .keys()
+            .collect();
+        for view in views {
+            let view = self.data.view_mut(reconstruction, view);
+            view.pose = georeference.pose(view.pose);
+        }
+        if let Some((_, tracked, pose)) = self.last_tracked.as_mut() {
+            if *tracked == reconstruction {
+                *pose = georeference.pose(*pose);
+            }
+        }
+    }
+}
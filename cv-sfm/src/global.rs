@@ -0,0 +1,441 @@
+//! Global structure from motion by rotation and translation averaging.
+
+use crate::{
+    matching::Matcher,
+    pipeline::{build_tracks, verify_pairs, Scene},
+    SfmImage, SparseReconstruction,
+};
+use cv_core::{
+    nalgebra::{DMatrix, IsometryMatrix3, Rotation3, Vector3},
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, Pose, TriangulatorObservations, WorldToCamera,
+};
+use cv_optimize::BundleAdjuster;
+use log::*;
+use std::collections::VecDeque;
+
+/// A relative rotation `rotation` from image `a` to image `b`, such that `R_b = rotation * R_a` for the
+/// world-to-camera rotations of the images, with the weight of the measurement.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RelativeRotation {
+    pub a: usize,
+    pub b: usize,
+    pub rotation: Rotation3<f64>,
+    pub weight: f64,
+}
+
+/// The direction from the optical center of image `a` to that of image `b` in the world, with the weight of the
+/// measurement.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RelativeDirection {
+    pub a: usize,
+    pub b: usize,
+    pub direction: Vector3<f64>,
+    pub weight: f64,
+}
+
+/// Estimates the world-to-camera rotation of every image from relative rotations by robust rotation averaging.
+///
+/// The rotations are initialized along the maximum weight spanning tree grown from `root`, which is held at the
+/// identity. Each image is then repeatedly moved to the geodesic L1 median of the rotations its neighbors predict
+/// for it with a step of the Weiszfeld algorithm, so that a few wrong relative rotations don't bias the result.
+///
+/// Returns `None` for the images which are not connected to `root`.
+pub fn average_rotations(
+    num_images: usize,
+    relative: &[RelativeRotation],
+    root: usize,
+    iterations: usize,
+) -> Vec<Option<Rotation3<f64>>> {
+    let mut rotations = vec![None; num_images];
+    rotations[root] = Some(Rotation3::identity());
+
+    // Grow the maximum weight spanning tree one edge at a time.
+    loop {
+        let next = relative
+            .iter()
+            .filter_map(|edge| match (rotations[edge.a], rotations[edge.b]) {
+                (Some(ra), None) => Some((edge.weight, edge.b, edge.rotation * ra)),
+                (None, Some(rb)) => Some((edge.weight, edge.a, edge.rotation.inverse() * rb)),
+                _ => None,
+            })
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        match next {
+            Some((_, image, rotation)) => rotations[image] = Some(rotation),
+            None => break,
+        }
+    }
+
+    for iteration in 0..iterations {
+        let mut max_step: f64 = 0.0;
+        for image in (0..num_images).filter(|&image| image != root) {
+            let current = match rotations[image] {
+                Some(current) => current,
+                None => continue,
+            };
+            let (sum, weights) = relative
+                .iter()
+                .filter_map(|edge| {
+                    let predicted = if edge.b == image {
+                        edge.rotation * rotations[edge.a]?
+                    } else if edge.a == image {
+                        edge.rotation.inverse() * rotations[edge.b]?
+                    } else {
+                        return None;
+                    };
+                    let residual = (predicted * current.inverse()).scaled_axis();
+                    let weight = edge.weight / residual.norm().max(1e-9);
+                    Some((weight * residual, weight))
+                })
+                .fold(
+                    (Vector3::zeros(), 0.0),
+                    |(sum, weights), (residual, weight)| (sum + residual, weights + weight),
+                );
+            if weights > 0.0 {
+                let step = sum / weights;
+                max_step = max_step.max(step.norm());
+                rotations[image] = Some(Rotation3::new(step) * current);
+            }
+        }
+        if max_step < 1e-12 {
+            info!(
+                "rotation averaging converged after {} iterations",
+                iteration + 1
+            );
+            break;
+        }
+    }
+    rotations
+}
+
+/// Estimates the optical center of every image from the directions between them by translation averaging.
+///
+/// This solves the least unsquared deviations (LUD) problem of Özyeşil and Singer, where every pair of centers is
+/// `c_b - c_a = s_ab d_ab` for a scale `s_ab ≥ 1`, with iteratively reweighted least squares. The L1 cost makes it
+/// robust to wrong directions, and the constraint on the scales avoids the collapse of all centers to a point.
+/// `root` is held at the origin.
+///
+/// Returns `None` for the images which are not connected to `root`, or for every image if the directions do not
+/// constrain the centers.
+pub fn average_translations(
+    num_images: usize,
+    relative: &[RelativeDirection],
+    root: usize,
+    iterations: usize,
+) -> Vec<Option<Vector3<f64>>> {
+    // Only the images connected to the root can be solved.
+    let mut connected = vec![false; num_images];
+    connected[root] = true;
+    let mut queue = VecDeque::from(vec![root]);
+    while let Some(image) = queue.pop_front() {
+        for edge in relative {
+            let other = if edge.a == image {
+                edge.b
+            } else if edge.b == image {
+                edge.a
+            } else {
+                continue;
+            };
+            if !connected[other] {
+                connected[other] = true;
+                queue.push_back(other);
+            }
+        }
+    }
+    // The root is removed from the system, since it is fixed at the origin.
+    let mut unknowns = vec![None; num_images];
+    let mut num_unknowns = 0;
+    for image in (0..num_images).filter(|&image| connected[image] && image != root) {
+        unknowns[image] = Some(num_unknowns);
+        num_unknowns += 1;
+    }
+    let edges: Vec<&RelativeDirection> = relative
+        .iter()
+        .filter(|edge| connected[edge.a] && connected[edge.b])
+        .collect();
+
+    let mut centers = vec![Vector3::zeros(); num_images];
+    let mut scales = vec![1.0; edges.len()];
+    let mut weights: Vec<f64> = edges.iter().map(|edge| edge.weight).collect();
+    for _ in 0..iterations {
+        let mut laplacian = DMatrix::<f64>::zeros(num_unknowns, num_unknowns);
+        let mut rhs = DMatrix::<f64>::zeros(num_unknowns, 3);
+        for ((edge, &scale), &weight) in edges.iter().zip(&scales).zip(&weights) {
+            let measured = weight * scale * edge.direction;
+            if let Some(a) = unknowns[edge.a] {
+                laplacian[(a, a)] += weight;
+                for (axis, &value) in measured.iter().enumerate() {
+                    rhs[(a, axis)] -= value;
+                }
+            }
+            if let Some(b) = unknowns[edge.b] {
+                laplacian[(b, b)] += weight;
+                for (axis, &value) in measured.iter().enumerate() {
+                    rhs[(b, axis)] += value;
+                }
+            }
+            if let (Some(a), Some(b)) = (unknowns[edge.a], unknowns[edge.b]) {
+                laplacian[(a, b)] -= weight;
+                laplacian[(b, a)] -= weight;
+            }
+        }
+        let solution = match laplacian.cholesky() {
+            Some(cholesky) => cholesky.solve(&rhs),
+            None => {
+                info!("translation averaging system was singular");
+                return vec![None; num_images];
+            }
+        };
+        for (image, unknown) in unknowns.iter().enumerate() {
+            if let Some(unknown) = *unknown {
+                centers[image] = Vector3::new(
+                    solution[(unknown, 0)],
+                    solution[(unknown, 1)],
+                    solution[(unknown, 2)],
+                );
+            }
+        }
+
+        let mut max_change: f64 = 0.0;
+        for ((edge, scale), weight) in edges.iter().zip(&mut scales).zip(&mut weights) {
+            let baseline = centers[edge.b] - centers[edge.a];
+            let new_scale = edge.direction.dot(&baseline).max(1.0);
+            max_change = max_change.max((new_scale - *scale).abs() / new_scale);
+            *scale = new_scale;
+            *weight = edge.weight / (baseline - *scale * edge.direction).norm().max(1e-6);
+        }
+        if max_change < 1e-9 {
+            break;
+        }
+    }
+
+    (0..num_images)
+        .map(|image| connected[image].then(|| centers[image]))
+        .collect()
+}
+
+/// Reconstructs the poses of a set of images and a sparse point cloud by global structure from motion.
+///
+/// Rather than registering images one at a time like [`IncrementalSfm`](crate::IncrementalSfm), this estimates
+/// the relative pose of every verified pair of images, recovers all rotations at once with [`average_rotations`],
+/// drops the pairs which disagree with the averaged rotations, and recovers all optical centers at once with
+/// [`average_translations`]. The tracks are then triangulated and refined with one global bundle adjustment.
+/// Since every image is solved at once, the cost grows much more slowly with the number of images, and drift does
+/// not accumulate along the order of registration.
+pub struct GlobalSfm<M, CE, EE, T> {
+    pub matcher: M,
+    pub essential_consensus: CE,
+    pub essential_estimator: EE,
+    pub triangulator: T,
+    /// The adjuster used for the global bundle adjustment.
+    pub adjuster: BundleAdjuster,
+    /// The minimum number of descriptor matches for a pair of images to be verified.
+    pub min_matches: usize,
+    /// The minimum number of inliers to the essential matrix for a pair of images to be kept.
+    pub min_inliers: usize,
+    /// The maximum angle in radians between a relative rotation and the averaged rotations for the pair to be kept.
+    pub max_rotation_error: f64,
+    /// The minimum angle in radians between the rays of a point for it to be triangulated.
+    pub min_triangulation_angle: f64,
+    /// The maximum cosine distance between an observation and its point.
+    pub max_cosine_distance: f64,
+    /// The maximum number of iterations of rotation and of translation averaging.
+    pub averaging_iterations: usize,
+}
+
+impl<M, CE, EE, T> GlobalSfm<M, CE, EE, T> {
+    pub fn new(
+        matcher: M,
+        essential_consensus: CE,
+        essential_estimator: EE,
+        triangulator: T,
+    ) -> Self {
+        Self {
+            matcher,
+            essential_consensus,
+            essential_estimator,
+            triangulator,
+            adjuster: BundleAdjuster::new(),
+            min_matches: 30,
+            min_inliers: 20,
+            max_rotation_error: 0.1,
+            min_triangulation_angle: 0.02,
+            max_cosine_distance: 5e-6,
+            averaging_iterations: 100,
+        }
+    }
+
+    #[must_use]
+    pub fn adjuster(self, adjuster: BundleAdjuster) -> Self {
+        Self { adjuster, ..self }
+    }
+
+    #[must_use]
+    pub fn min_matches(self, min_matches: usize) -> Self {
+        Self {
+            min_matches,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn min_inliers(self, min_inliers: usize) -> Self {
+        Self {
+            min_inliers,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn max_rotation_error(self, max_rotation_error: f64) -> Self {
+        Self {
+            max_rotation_error,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn min_triangulation_angle(self, min_triangulation_angle: f64) -> Self {
+        Self {
+            min_triangulation_angle,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn max_cosine_distance(self, max_cosine_distance: f64) -> Self {
+        Self {
+            max_cosine_distance,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn averaging_iterations(self, averaging_iterations: usize) -> Self {
+        Self {
+            averaging_iterations,
+            ..self
+        }
+    }
+
+    /// Reconstructs the images.
+    ///
+    /// Returns `None` if no pair of images could be verified.
+    pub fn reconstruct<D>(&mut self, images: &[SfmImage<D>]) -> Option<SparseReconstruction>
+    where
+        D: Sync,
+        M: Matcher<D> + Sync,
+        CE: Consensus<EE, FeatureMatch>,
+        EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+        T: TriangulatorObservations,
+    {
+        let pairs = verify_pairs(
+            images,
+            &self.matcher,
+            &mut self.essential_consensus,
+            &self.essential_estimator,
+            self.min_matches,
+            self.min_inliers,
+        );
+        info!("verified {} image pairs", pairs.len());
+        // The pair with the most inliers fixes the gauge, and its first image is the root of the averaging.
+        let gauge = pairs.iter().max_by_key(|pair| pair.matches.len())?.images;
+
+        let relative_rotations: Vec<RelativeRotation> = pairs
+            .iter()
+            .map(|pair| RelativeRotation {
+                a: pair.images.0,
+                b: pair.images.1,
+                rotation: pair.pose.isometry().rotation,
+                weight: pair.matches.len() as f64,
+            })
+            .collect();
+        let rotations = average_rotations(
+            images.len(),
+            &relative_rotations,
+            gauge.0,
+            self.averaging_iterations,
+        );
+
+        // Keep the pairs which agree with the averaged rotations, and turn their translations into world directions.
+        let relative_directions: Vec<RelativeDirection> = pairs
+            .iter()
+            .zip(&relative_rotations)
+            .filter_map(|(pair, relative)| {
+                let ra = rotations[relative.a]?;
+                let rb = rotations[relative.b]?;
+                let error = (relative.rotation * ra * rb.inverse()).angle();
+                if error > self.max_rotation_error {
+                    return None;
+                }
+                // The translation is `t_b - R_ab t_a = -R_b (c_b - c_a)` up to scale.
+                let direction = -(rb.inverse() * pair.pose.isometry().translation.vector);
+                (direction.norm() > 0.0).then(|| RelativeDirection {
+                    a: relative.a,
+                    b: relative.b,
+                    direction: direction.normalize(),
+                    weight: 1.0,
+                })
+            })
+            .collect();
+        info!(
+            "kept {} of {} pairs after rotation averaging",
+            relative_directions.len(),
+            pairs.len()
+        );
+        let centers = average_translations(
+            images.len(),
+            &relative_directions,
+            gauge.0,
+            self.averaging_iterations,
+        );
+
+        let mut scene = Scene::new(images, build_tracks(&pairs), gauge);
+        for (image, (rotation, center)) in rotations.iter().zip(&centers).enumerate() {
+            if let (Some(rotation), Some(center)) = (*rotation, *center) {
+                scene.poses[image] = Some(WorldToCamera(IsometryMatrix3::from_parts(
+                    (-(rotation * center)).into(),
+                    rotation,
+                )));
+            }
+        }
+        if scene.poses[gauge.1].is_none() {
+            info!("the gauge pair was not connected after averaging");
+            return None;
+        }
+
+        let triangulated = scene.triangulate(
+            &self.triangulator,
+            self.max_cosine_distance,
+            self.min_triangulation_angle,
+        );
+        info!(
+            "triangulated {} points from the averaged poses",
+            triangulated
+        );
+        scene.bundle_adjust(&self.adjuster);
+        // The averaged poses are only approximate, so points rejected before the adjustment get another chance.
+        let removed = scene.filter(self.max_cosine_distance, self.min_triangulation_angle);
+        let triangulated = scene.triangulate(
+            &self.triangulator,
+            self.max_cosine_distance,
+            self.min_triangulation_angle,
+        );
+        info!(
+            "removed {} inconsistent points and triangulated {} points after bundle adjustment",
+            removed, triangulated
+        );
+        if removed + triangulated > 0 {
+            scene.bundle_adjust(&self.adjuster);
+        }
+
+        let reconstruction = scene.into_reconstruction();
+        info!(
+            "reconstructed {} of {} images with {} points",
+            reconstruction.num_registered(),
+            images.len(),
+            reconstruction.points.len()
+        );
+        Some(reconstruction)
+    }
+}
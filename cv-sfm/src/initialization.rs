@@ -0,0 +1,335 @@
+use cv_consensus::{ConsensusReport, ReportingConsensus};
+use cv_core::{
+    nalgebra::Vector3,
+    sample_consensus::{Consensus, Estimator, Model},
+    CameraToCamera, FeatureMatch, Pose,
+};
+use cv_pinhole::{HomographyMatrix, PlanarPose};
+use log::*;
+use std::cmp::Reverse;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The model chosen to initialize from two views.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum TwoViewModel {
+    /// The scene has general structure, and the pose came from the essential matrix.
+    Essential,
+    /// The scene is planar, and the pose came from decomposing the homography.
+    Planar,
+    /// The camera only rotated, so there is no parallax and no translation.
+    ///
+    /// A reconstruction can't be initialized from the two views.
+    Rotation,
+}
+
+/// The result of [`TwoViewInitializer::initialize`].
+#[derive(Debug, Clone)]
+pub struct TwoViewInitialization {
+    /// The model which was selected.
+    pub model: TwoViewModel,
+    /// The relative pose between the two views.
+    pub pose: CameraToCamera,
+    /// The indices of the matches which are inliers to the selected model.
+    pub inliers: Vec<usize>,
+    /// The GRIC score of the essential matrix (lower is better), if one was found.
+    pub essential_gric: Option<f64>,
+    /// The GRIC score of the homography (lower is better), if one was found.
+    pub homography_gric: Option<f64>,
+    /// The normal of the plane in the coordinates of the first view if the model is [`TwoViewModel::Planar`].
+    pub plane_normal: Option<Vector3<f64>>,
+}
+
+/// The consensus reports of both models of [`TwoViewInitializer::initialize_report`].
+#[derive(Debug, Clone, Default)]
+pub struct TwoViewReports {
+    /// The report of the essential matrix, if consensus found one.
+    pub essential: Option<ConsensusReport>,
+    /// The report of the homography, if consensus found one.
+    pub homography: Option<ConsensusReport>,
+}
+
+/// Computes the Geometric Robust Information Criterion (GRIC) by Phil Torr.
+///
+/// * `residuals` - The residual of every match (not only the inliers) to the model.
+/// * `sigma` - The standard deviation of the residuals of inliers.
+/// * `model_dimension` - The dimension of the manifold the model constrains the data to
+///   (3 for an essential matrix, 2 for a homography).
+/// * `model_parameters` - The number of degrees of freedom of the model (5 for an essential matrix, 8 for a homography).
+///
+/// The data dimension is 4, since each match is a pair of image points. Lower scores are better.
+pub fn gric(
+    residuals: impl Iterator<Item = f64>,
+    sigma: f64,
+    model_dimension: usize,
+    model_parameters: usize,
+) -> f64 {
+    const DATA_DIMENSION: f64 = 4.0;
+    let lambda3 = 2.0;
+    let max_penalty = lambda3 * (DATA_DIMENSION - model_dimension as f64);
+    let (count, penalty) = residuals.fold((0usize, 0.0), |(count, penalty), residual| {
+        let normalized = residual * residual / (sigma * sigma);
+        (count + 1, penalty + normalized.min(max_penalty))
+    });
+    let lambda1 = DATA_DIMENSION.ln();
+    let lambda2 = (DATA_DIMENSION * count as f64).ln();
+    penalty + lambda1 * model_dimension as f64 * count as f64 + lambda2 * model_parameters as f64
+}
+
+/// Initializes two views by estimating both an essential matrix and a homography and selecting the model
+/// which best explains the matches.
+///
+/// Initializing only from an essential matrix silently fails on planar scenes and when the camera only rotates,
+/// since the essential matrix is degenerate in both cases. The two models are compared with [`gric`], and when the
+/// homography is selected, it is decomposed into a pose (or detected as a pure rotation).
+///
+/// A planar scene usually has two decompositions which put the points in front of both cameras. When neither
+/// is clearly better, no initialization is returned, and a different pair of views should be tried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TwoViewInitializer {
+    /// The standard deviation of the angular residuals of inliers in radians.
+    pub sigma: f64,
+    /// The residual below which a match is considered an inlier to the homography.
+    pub homography_threshold: f64,
+    /// The homography is considered a pure rotation when its singular values are within this ratio of each other.
+    pub rotation_threshold: f64,
+    /// The minimum ratio of in front matches between the best and second best homography decomposition.
+    ///
+    /// If the decompositions can't be disambiguated, no pose is returned for a planar scene.
+    pub planar_ambiguity_ratio: f64,
+    pub epsilon: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for TwoViewInitializer {
+    fn default() -> Self {
+        Self {
+            sigma: 0.001,
+            homography_threshold: 0.003,
+            rotation_threshold: 0.01,
+            planar_ambiguity_ratio: 0.75,
+            epsilon: 1e-12,
+            max_iterations: 1000,
+        }
+    }
+}
+
+impl TwoViewInitializer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn sigma(self, sigma: f64) -> Self {
+        Self { sigma, ..self }
+    }
+
+    #[must_use]
+    pub fn homography_threshold(self, homography_threshold: f64) -> Self {
+        Self {
+            homography_threshold,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn rotation_threshold(self, rotation_threshold: f64) -> Self {
+        Self {
+            rotation_threshold,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn planar_ambiguity_ratio(self, planar_ambiguity_ratio: f64) -> Self {
+        Self {
+            planar_ambiguity_ratio,
+            ..self
+        }
+    }
+
+    /// Estimates both models on `matches` and selects one.
+    ///
+    /// The consensus algorithms are provided separately since they typically need different inlier thresholds.
+    pub fn initialize<CE, EE, CH, HE>(
+        &self,
+        essential_consensus: &mut CE,
+        essential_estimator: &EE,
+        homography_consensus: &mut CH,
+        homography_estimator: &HE,
+        matches: &[FeatureMatch],
+    ) -> Option<TwoViewInitialization>
+    where
+        CE: Consensus<EE, FeatureMatch>,
+        EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+        CH: Consensus<HE, FeatureMatch>,
+        HE: Estimator<FeatureMatch, Model = HomographyMatrix>,
+    {
+        let essential = essential_consensus
+            .model_inliers(essential_estimator, matches.iter().copied())
+            .map(|(pose, inliers)| (pose, inliers.into_iter().collect()));
+        let homography = homography_consensus
+            .model_inliers(homography_estimator, matches.iter().copied())
+            .map(|(homography, _)| homography);
+        self.select(matches, essential, homography)
+    }
+
+    /// Estimates both models on `matches` and selects one like [`TwoViewInitializer::initialize`], and also returns
+    /// the [`ConsensusReport`] of each model to analyze why initialization succeeded or failed.
+    pub fn initialize_report<CE, EE, CH, HE>(
+        &self,
+        essential_consensus: &mut CE,
+        essential_estimator: &EE,
+        homography_consensus: &mut CH,
+        homography_estimator: &HE,
+        matches: &[FeatureMatch],
+    ) -> (Option<TwoViewInitialization>, TwoViewReports)
+    where
+        CE: ReportingConsensus<EE, FeatureMatch>,
+        EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+        CH: ReportingConsensus<HE, FeatureMatch>,
+        HE: Estimator<FeatureMatch, Model = HomographyMatrix>,
+    {
+        let (essential, essential_report) = essential_consensus
+            .model_report(essential_estimator, matches.iter().copied())
+            .map(|(pose, report)| (Some((pose, report.inliers.clone())), Some(report)))
+            .unwrap_or_default();
+        let (homography, homography_report) = homography_consensus
+            .model_report(homography_estimator, matches.iter().copied())
+            .map(|(homography, report)| (Some(homography), Some(report)))
+            .unwrap_or_default();
+        let initialization = self.select(matches, essential, homography);
+        let reports = TwoViewReports {
+            essential: essential_report,
+            homography: homography_report,
+        };
+        (initialization, reports)
+    }
+
+    /// Selects the model which best explains the matches from the models found by consensus.
+    fn select(
+        &self,
+        matches: &[FeatureMatch],
+        essential: Option<(CameraToCamera, Vec<usize>)>,
+        homography: Option<HomographyMatrix>,
+    ) -> Option<TwoViewInitialization> {
+        let score = |model: &dyn Fn(&FeatureMatch) -> f64, dimension, parameters| {
+            gric(matches.iter().map(model), self.sigma, dimension, parameters)
+        };
+        let essential_gric = essential
+            .as_ref()
+            .map(|(pose, _)| score(&|m| pose.residual(m), 3, 5));
+        let homography_gric = homography
+            .as_ref()
+            .map(|homography| score(&|m| homography.residual(m), 2, 8));
+        info!(
+            "two-view model selection GRIC: essential {:?}, homography {:?}",
+            essential_gric, homography_gric
+        );
+
+        let prefer_homography = match (essential_gric, homography_gric) {
+            (Some(essential_gric), Some(homography_gric)) => homography_gric < essential_gric,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+
+        if !prefer_homography {
+            let (pose, inliers) = essential?;
+            info!("selected essential matrix with {} inliers", inliers.len());
+            return Some(TwoViewInitialization {
+                model: TwoViewModel::Essential,
+                pose,
+                inliers,
+                essential_gric,
+                homography_gric,
+                plane_normal: None,
+            });
+        }
+
+        let homography = homography?;
+        let inliers: Vec<usize> = matches
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| homography.residual(m) < self.homography_threshold)
+            .map(|(ix, _)| ix)
+            .collect();
+
+        if homography.is_rotation_only(
+            self.rotation_threshold,
+            self.epsilon,
+            self.max_iterations,
+        )? {
+            info!(
+                "selected pure rotation homography with {} inliers",
+                inliers.len()
+            );
+            let rotation = homography.rotation(self.epsilon, self.max_iterations)?;
+            return Some(TwoViewInitialization {
+                model: TwoViewModel::Rotation,
+                pose: CameraToCamera::from_parts(Vector3::zeros(), rotation),
+                inliers,
+                essential_gric,
+                homography_gric,
+                plane_normal: None,
+            });
+        }
+
+        // Select the decomposition which puts the most inliers in front of both cameras.
+        let decompositions =
+            homography.possible_unscaled_poses(self.epsilon, self.max_iterations)?;
+        let mut in_front: Vec<(usize, PlanarPose)> = decompositions
+            .iter()
+            .map(|&planar| {
+                let count = inliers
+                    .iter()
+                    .filter(|&&ix| planar.is_in_front(&matches[ix]))
+                    .count();
+                (count, planar)
+            })
+            .collect();
+        in_front.sort_unstable_by_key(|&(count, _)| Reverse(count));
+        let (best_count, best) = in_front[0];
+        // Decompositions which are identical up to rounding error aren't ambiguous.
+        let second_count = in_front[1..]
+            .iter()
+            .find(|(_, planar)| {
+                planar
+                    .pose
+                    .0
+                    .rotation
+                    .rotation_to(&best.pose.0.rotation)
+                    .angle()
+                    > self.sigma
+                    || (planar.normal - best.normal).norm() > self.sigma
+            })
+            .map(|&(count, _)| count)
+            .unwrap_or(0);
+        if best_count == 0 || second_count as f64 > self.planar_ambiguity_ratio * best_count as f64
+        {
+            info!(
+                "planar decomposition was ambiguous with {} and {} inliers in front",
+                best_count, second_count
+            );
+            return None;
+        }
+        info!(
+            "selected planar homography with {} inliers, {} in front",
+            inliers.len(),
+            best_count
+        );
+        let inliers = inliers
+            .into_iter()
+            .filter(|&ix| best.is_in_front(&matches[ix]))
+            .collect();
+        Some(TwoViewInitialization {
+            model: TwoViewModel::Planar,
+            pose: best.pose,
+            inliers,
+            essential_gric,
+            homography_gric,
+            plane_normal: Some(best.normal),
+        })
+    }
+}
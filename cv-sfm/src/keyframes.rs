@@ -0,0 +1,384 @@
+//! Keyframe selection.
+//!
+//! Every frame which is registered into a reconstruction becomes a view by default. Views are what the map is made
+//! of, so adding one for every frame of a video makes the map grow with time instead of with the area covered, and
+//! views which barely moved from the last one add little to the triangulation. A [`KeyframePolicy`] decides from a
+//! [`KeyframeCandidate`] whether a registered frame is kept as a view. Handheld cameras mostly rotate and are best
+//! served by [`TrackedRatio`] or [`Parallax`], while vehicles move steadily forward and are best served by [`Motion`].
+
+use crate::{FrameKey, LandmarkKey, ReconstructionKey, VSlam, ViewKey};
+use cv_core::{
+    nalgebra::UnitVector3,
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, TriangulatorObservations, WorldToCamera,
+};
+use float_ord::FloatOrd;
+use rand::Rng;
+use std::collections::HashMap;
+
+/// A frame which was registered into a reconstruction, compared to the last keyframe of its feed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeyframeCandidate {
+    /// The number of landmarks of the keyframe which were observed by at least two views.
+    pub keyframe_landmarks: usize,
+    /// The number of `keyframe_landmarks` which were matched in the frame.
+    pub tracked_landmarks: usize,
+    /// The median angle in radians between the bearings of the tracked landmarks in the keyframe and in the frame,
+    /// after the rotation between them is removed.
+    pub parallax: f64,
+    /// The distance between the centers of the keyframe and the frame in the units of the reconstruction.
+    pub translation: f64,
+    /// The angle in radians of the rotation between the keyframe and the frame.
+    pub rotation: f64,
+    /// The number of frames of the feed since the keyframe.
+    pub frames: usize,
+}
+
+impl KeyframeCandidate {
+    /// The fraction of the landmarks of the keyframe which are still tracked, or `0.0` if it has none.
+    pub fn tracked_ratio(&self) -> f64 {
+        if self.keyframe_landmarks == 0 {
+            0.0
+        } else {
+            self.tracked_landmarks as f64 / self.keyframe_landmarks as f64
+        }
+    }
+}
+
+/// Decides which registered frames become keyframes.
+///
+/// Frames from a feed which has no keyframe in the reconstruction yet are always kept, so the policy is only consulted
+/// when there is a keyframe to compare against.
+pub trait KeyframePolicy {
+    /// Returns `true` if the frame should be added to the reconstruction as a view.
+    fn is_keyframe(&mut self, candidate: &KeyframeCandidate) -> bool;
+
+    /// Creates a policy which makes a keyframe if either policy does.
+    fn or<P>(self, other: P) -> Or<Self, P>
+    where
+        Self: Sized,
+        P: KeyframePolicy,
+    {
+        Or(self, other)
+    }
+
+    /// Creates a policy which makes a keyframe only if both policies do.
+    fn and<P>(self, other: P) -> And<Self, P>
+    where
+        Self: Sized,
+        P: KeyframePolicy,
+    {
+        And(self, other)
+    }
+}
+
+impl<P> KeyframePolicy for Box<P>
+where
+    P: KeyframePolicy + ?Sized,
+{
+    fn is_keyframe(&mut self, candidate: &KeyframeCandidate) -> bool {
+        (**self).is_keyframe(candidate)
+    }
+}
+
+/// Makes a keyframe if either policy does. See [`KeyframePolicy::or`].
+///
+/// Both policies are always consulted, so that policies which keep state see every frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Or<A, B>(pub A, pub B);
+
+impl<A, B> KeyframePolicy for Or<A, B>
+where
+    A: KeyframePolicy,
+    B: KeyframePolicy,
+{
+    fn is_keyframe(&mut self, candidate: &KeyframeCandidate) -> bool {
+        let a = self.0.is_keyframe(candidate);
+        let b = self.1.is_keyframe(candidate);
+        a || b
+    }
+}
+
+/// Makes a keyframe only if both policies do. See [`KeyframePolicy::and`].
+///
+/// Both policies are always consulted, so that policies which keep state see every frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct And<A, B>(pub A, pub B);
+
+impl<A, B> KeyframePolicy for And<A, B>
+where
+    A: KeyframePolicy,
+    B: KeyframePolicy,
+{
+    fn is_keyframe(&mut self, candidate: &KeyframeCandidate) -> bool {
+        let a = self.0.is_keyframe(candidate);
+        let b = self.1.is_keyframe(candidate);
+        a && b
+    }
+}
+
+/// Makes every registered frame a keyframe.
+///
+/// This is the default policy of [`VSlam`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EveryFrame;
+
+impl KeyframePolicy for EveryFrame {
+    fn is_keyframe(&mut self, _: &KeyframeCandidate) -> bool {
+        true
+    }
+}
+
+/// Makes a keyframe once the frame tracks less than a fraction of the landmarks of the last keyframe.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrackedRatio {
+    ratio: f64,
+}
+
+impl TrackedRatio {
+    /// Creates a `TrackedRatio` policy with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the fraction of the landmarks of the last keyframe below which a keyframe is made.
+    ///
+    /// Default is `0.9`.
+    #[must_use]
+    pub fn ratio(self, ratio: f64) -> Self {
+        Self { ratio }
+    }
+}
+
+impl Default for TrackedRatio {
+    fn default() -> Self {
+        Self { ratio: 0.9 }
+    }
+}
+
+impl KeyframePolicy for TrackedRatio {
+    fn is_keyframe(&mut self, candidate: &KeyframeCandidate) -> bool {
+        candidate.tracked_ratio() < self.ratio
+    }
+}
+
+/// Makes a keyframe once the landmarks tracked from the last keyframe have enough parallax to be triangulated well.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Parallax {
+    parallax: f64,
+}
+
+impl Parallax {
+    /// Creates a `Parallax` policy with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the median parallax in radians at which a keyframe is made.
+    ///
+    /// Default is `0.02`.
+    #[must_use]
+    pub fn parallax(self, parallax: f64) -> Self {
+        Self { parallax }
+    }
+}
+
+impl Default for Parallax {
+    fn default() -> Self {
+        Self { parallax: 0.02 }
+    }
+}
+
+impl KeyframePolicy for Parallax {
+    fn is_keyframe(&mut self, candidate: &KeyframeCandidate) -> bool {
+        candidate.parallax >= self.parallax
+    }
+}
+
+/// Makes a keyframe once the camera has moved or turned far enough from the last keyframe.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Motion {
+    translation: f64,
+    rotation: f64,
+}
+
+impl Motion {
+    /// Creates a `Motion` policy with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the distance in the units of the reconstruction at which a keyframe is made.
+    ///
+    /// Monocular reconstructions have an arbitrary scale, so this is mostly useful once the scale is known.
+    ///
+    /// Default is `f64::INFINITY`.
+    #[must_use]
+    pub fn translation(self, translation: f64) -> Self {
+        Self {
+            translation,
+            ..self
+        }
+    }
+
+    /// Set the angle of rotation in radians at which a keyframe is made.
+    ///
+    /// Default is `0.2`.
+    #[must_use]
+    pub fn rotation(self, rotation: f64) -> Self {
+        Self { rotation, ..self }
+    }
+}
+
+impl Default for Motion {
+    fn default() -> Self {
+        Self {
+            translation: f64::INFINITY,
+            rotation: 0.2,
+        }
+    }
+}
+
+impl KeyframePolicy for Motion {
+    fn is_keyframe(&mut self, candidate: &KeyframeCandidate) -> bool {
+        candidate.translation >= self.translation || candidate.rotation >= self.rotation
+    }
+}
+
+/// Makes a keyframe once enough frames of the feed passed since the last keyframe.
+///
+/// For a feed with a fixed frame rate, this makes keyframes at a fixed time interval.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameInterval {
+    frames: usize,
+}
+
+impl FrameInterval {
+    /// Creates a `FrameInterval` policy with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the number of frames after the last keyframe at which a keyframe is made.
+    ///
+    /// Default is `30`.
+    #[must_use]
+    pub fn frames(self, frames: usize) -> Self {
+        Self { frames }
+    }
+}
+
+impl Default for FrameInterval {
+    fn default() -> Self {
+        Self { frames: 30 }
+    }
+}
+
+impl KeyframePolicy for FrameInterval {
+    fn is_keyframe(&mut self, candidate: &KeyframeCandidate) -> bool {
+        candidate.frames >= self.frames
+    }
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Finds the last view of a reconstruction which came from the same feed as `frame` before it.
+    pub fn last_keyframe(
+        &self,
+        reconstruction: ReconstructionKey,
+        frame: FrameKey,
+    ) -> Option<ViewKey> {
+        let frame = self.data.frame(frame);
+        self.data.feeds[frame.feed].frames[..frame.feed_frame]
+            .iter()
+            .rev()
+            .find_map(|&earlier| match self.data.frame(earlier).view {
+                Some((found_reconstruction, view)) if found_reconstruction == reconstruction => {
+                    Some(view)
+                }
+                _ => None,
+            })
+    }
+
+    /// Compares a frame registered at `pose` with the matches `landmarks` from its features to the landmarks of the
+    /// reconstruction against the last keyframe of its feed.
+    ///
+    /// Returns `None` if the feed has no keyframe in the reconstruction before the frame.
+    pub fn keyframe_candidate(
+        &self,
+        reconstruction: ReconstructionKey,
+        frame: FrameKey,
+        pose: WorldToCamera,
+        landmarks: &HashMap<usize, LandmarkKey>,
+    ) -> Option<KeyframeCandidate> {
+        let keyframe = self.last_keyframe(reconstruction, frame)?;
+        let keyframe_pose = self.data.pose(reconstruction, keyframe);
+        let keyframe_frame = self.data.view(reconstruction, keyframe).frame;
+        let tracked: HashMap<LandmarkKey, usize> = landmarks
+            .iter()
+            .map(|(&feature, &landmark)| (landmark, feature))
+            .collect();
+
+        // The rotation from the keyframe to the frame is removed from the bearings to only measure parallax.
+        let relative = CameraToCamera(pose.isometry() * keyframe_pose.isometry().inverse());
+        let mut keyframe_landmarks = 0;
+        let mut parallaxes = vec![];
+        for (keyframe_feature, &landmark) in self
+            .data
+            .view(reconstruction, keyframe)
+            .landmarks
+            .iter()
+            .enumerate()
+        {
+            if self
+                .data
+                .landmark(reconstruction, landmark)
+                .observations
+                .len()
+                < 2
+            {
+                continue;
+            }
+            keyframe_landmarks += 1;
+            if let Some(&feature) = tracked.get(&landmark) {
+                let keyframe_bearing: UnitVector3<f64> = relative.isometry().rotation
+                    * self.data.bearing(keyframe_frame, keyframe_feature);
+                let bearing = self.data.bearing(frame, feature);
+                parallaxes.push(keyframe_bearing.angle(&bearing));
+            }
+        }
+        let tracked_landmarks = parallaxes.len();
+        let parallax = if parallaxes.is_empty() {
+            0.0
+        } else {
+            let middle = parallaxes.len() / 2;
+            *parallaxes
+                .select_nth_unstable_by_key(middle, |&parallax| FloatOrd(parallax))
+                .1
+        };
+
+        Some(KeyframeCandidate {
+            keyframe_landmarks,
+            tracked_landmarks,
+            parallax,
+            translation: relative.isometry().translation.vector.norm(),
+            rotation: relative.isometry().rotation.angle(),
+            frames: self.data.frame(frame).feed_frame - self.data.frame(keyframe_frame).feed_frame,
+        })
+    }
+}
@@ -1,10 +1,81 @@
 mod bicubic;
+mod cache;
+#[cfg(feature = "serde-serialize")]
+mod checkpoint;
 mod codewords;
+mod colmap;
+mod covisibility;
+mod dataset;
+mod evaluation;
 mod export;
+mod georeference;
+mod global;
+mod initialization;
+mod keyframes;
+mod localizer;
+mod loop_closure;
+mod maintenance;
+#[cfg(feature = "serde-serialize")]
+mod map;
+mod masking;
+pub mod matching;
+mod merging;
+mod motion;
+mod mvs;
+mod odometry;
+mod pipeline;
+mod point_cloud;
+mod quality;
+mod relocalization;
+#[cfg(feature = "ros2")]
+mod ros;
+mod scale;
+#[cfg(feature = "onnx")]
+mod segmentation;
 mod settings;
-
+mod sgm;
+mod threaded;
+mod tracks;
+mod tsdf;
+#[cfg(feature = "rerun")]
+mod visualization;
+
+pub use cache::*;
+#[cfg(feature = "serde-serialize")]
+pub use checkpoint::*;
+pub use colmap::*;
+pub use covisibility::*;
+pub use dataset::*;
+pub use evaluation::*;
 pub use export::*;
+pub use georeference::*;
+pub use global::*;
+pub use initialization::*;
+pub use keyframes::*;
+pub use localizer::*;
+pub use loop_closure::*;
+pub use maintenance::*;
+pub use masking::*;
+pub use merging::*;
+pub use motion::*;
+pub use mvs::*;
+pub use odometry::*;
+pub use pipeline::*;
+pub use point_cloud::*;
+pub use quality::*;
+pub use relocalization::*;
+#[cfg(feature = "ros2")]
+pub use ros::*;
+pub use scale::*;
+#[cfg(feature = "onnx")]
+pub use segmentation::*;
 pub use settings::*;
+pub use sgm::*;
+pub use threaded::*;
+pub use tracks::*;
+pub use tsdf::*;
+#[cfg(feature = "rerun")]
+pub use visualization::*;
 
 use average::Mean;
 use bitarray::{BitArray, Hamming};
@@ -23,10 +94,11 @@ use cv_pinhole::CameraIntrinsicsK1Distortion;
 use float_ord::FloatOrd;
 use hamming_lsh::HammingHasher;
 use hgg::HggLite;
-use image::DynamicImage;
+use image::{DynamicImage, GrayImage};
 use itertools::{izip, Itertools};
 use log::*;
 use maplit::hashmap;
+use matching::symmetric_matching;
 use rand::{seq::SliceRandom, Rng};
 use slotmap::{new_key_type, DenseSlotMap};
 use space::{Knn, KnnInsert, KnnMap};
@@ -37,6 +109,7 @@ use std::{
     mem,
     ops::{Range, Sub},
     path::Path,
+    sync::Arc,
 };
 
 #[cfg(feature = "serde-serialize")]
@@ -62,6 +135,9 @@ pub struct Feature {
     pub bearing: UnitVector3<f64>,
     pub response: f32,
     pub color: [u8; 3],
+    /// Whether the feature lies on or next to an object masked as dynamic (see [`DynamicMasker`]).
+    #[cfg_attr(feature = "serde-serialize", serde(default))]
+    pub dynamic: bool,
 }
 
 #[derive(Debug)]
@@ -77,6 +153,9 @@ pub struct Frame {
     pub view: Option<(ReconstructionKey, ViewKey)>,
     /// The LSH of this frame.
     pub lsh: BitArray<512>,
+    /// The motion of the camera from the previous frame of the feed to this frame, such as from wheel odometry.
+    #[cfg_attr(feature = "serde-serialize", serde(default))]
+    pub motion: Option<CameraToCamera>,
 }
 
 impl Frame {
@@ -95,6 +174,10 @@ impl Frame {
     pub fn color(&self, ix: usize) -> [u8; 3] {
         self.feature(ix).color
     }
+
+    pub fn dynamic(&self, ix: usize) -> bool {
+        self.feature(ix).dynamic
+    }
 }
 
 /// A 3d point in space that has been observed on two or more frames
@@ -243,6 +326,10 @@ impl VSlamData {
         self.frame(frame).color(feature)
     }
 
+    pub fn dynamic(&self, frame: FrameKey, feature: usize) -> bool {
+        self.frame(frame).dynamic(feature)
+    }
+
     pub fn reconstructions(&self) -> impl Iterator<Item = ReconstructionKey> + '_ {
         self.reconstructions.keys()
     }
@@ -289,6 +376,15 @@ impl VSlamData {
         self.color(self.view(reconstruction, view).frame, feature)
     }
 
+    pub fn observation_dynamic(
+        &self,
+        reconstruction: ReconstructionKey,
+        view: ViewKey,
+        feature: usize,
+    ) -> bool {
+        self.dynamic(self.view(reconstruction, view).frame, feature)
+    }
+
     pub fn observation_bearing(
         &self,
         reconstruction: ReconstructionKey,
@@ -593,7 +689,8 @@ impl VSlamData {
                     .entry(reconstruction)
                     .or_default()
                     .push(found_view);
-            } else {
+            } else if self.frames[found_frame].descriptor_features.len() > 0 {
+                // Frames whose features were released by map maintenance cannot be matched.
                 free_frames.push(found_frame);
             }
         }
@@ -627,6 +724,7 @@ impl VSlamData {
             descriptor_features,
             view: None,
             lsh,
+            motion: None,
         });
         self.lsh_to_frame.insert(lsh, frame);
         self.feeds[feed].frames.push(frame);
@@ -658,6 +756,12 @@ pub struct VSlam<C1, C2, PE, EE, T, R> {
     pub triangulator: T,
     /// The random number generator
     pub rng: RefCell<R>,
+    /// Decides which registered frames become views
+    pub keyframe_policy: Box<dyn KeyframePolicy + Send>,
+    /// Masks out dynamic objects before feature extraction
+    pub dynamic_masker: Option<Arc<dyn DynamicMasker + Send + Sync>>,
+    /// The last frame which was registered into a reconstruction and its pose, even if it did not become a view
+    pub last_tracked: Option<(FrameKey, ReconstructionKey, WorldToCamera)>,
 }
 
 impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
@@ -690,6 +794,34 @@ where
             camera_to_camera_estimator,
             triangulator,
             rng: RefCell::new(rng),
+            keyframe_policy: Box::new(EveryFrame),
+            dynamic_masker: None,
+            last_tracked: None,
+        }
+    }
+
+    /// Set the policy which decides which registered frames become views.
+    ///
+    /// Default is [`EveryFrame`].
+    #[must_use]
+    pub fn keyframe_policy(self, keyframe_policy: impl KeyframePolicy + Send + 'static) -> Self {
+        Self {
+            keyframe_policy: Box::new(keyframe_policy),
+            ..self
+        }
+    }
+
+    /// Set the masker of dynamic objects which is run on every image before feature extraction.
+    ///
+    /// Default is `None`, which keeps every feature.
+    #[must_use]
+    pub fn dynamic_masker(
+        self,
+        dynamic_masker: impl DynamicMasker + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            dynamic_masker: Some(Arc::new(dynamic_masker)),
+            ..self
         }
     }
 
@@ -703,15 +835,49 @@ where
 
     /// Add frame.
     ///
-    /// This may perform camera tracking and will always extract features.
+    /// This may perform camera tracking and loop closure and will always extract features.
     ///
     /// Returns a `(Reconstruction, View)` pair if the frame was incorporated in a reconstruction.
     /// Returns the `Frame` in all cases.
     pub fn add_frame(&mut self, feed: FeedKey, image: &DynamicImage) -> FrameKey {
-        // Extract the features for the frame and add the frame object.
+        // Extract the features for the frame.
         let features = self.kps_descriptors(&self.data.feeds[feed].intrinsics, image);
+        self.add_frame_features(feed, features)
+    }
+
+    /// Add a frame from features which were already extracted with [`extract_features`].
+    ///
+    /// This performs the same tracking and loop closure as [`VSlam::add_frame`].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "vslam::add_frame", skip_all, fields(features = features.len(), tracked))
+    )]
+    pub fn add_frame_features(
+        &mut self,
+        feed: FeedKey,
+        features: Vec<(BitArray<64>, Feature)>,
+    ) -> FrameKey {
         let frame = self.data.add_frame(feed, features);
+        self.track_frame(frame);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("tracked", &self.data.frame(frame).view.is_some());
+        frame
+    }
 
+    /// Tracks a frame which was just added, closing loops and maintaining the reconstruction it was tracked in.
+    fn track_frame(&mut self, frame: FrameKey) {
+        if let Some((reconstruction, view)) = self.localize_frame(frame) {
+            self.close_view_loop(reconstruction, view);
+        }
+        if let Some(reconstruction) = self.maintenance_due(frame) {
+            self.maintain_reconstruction(reconstruction);
+        }
+    }
+
+    /// Localizes a frame which was just added with the frames which are most visually similar to it.
+    ///
+    /// Returns the view of the frame if it was kept as a view of a reconstruction.
+    fn localize_frame(&mut self, frame: FrameKey) -> Option<(ReconstructionKey, ViewKey)> {
         // Find the frames which are most visually similar to this frame.
         let (reconstruction_frames, free_frames) =
             self.data.find_visually_similar_and_recent_frames(
@@ -723,9 +889,44 @@ where
             );
 
         // Try to localize this new frame with all of the similar frames.
-        self.try_localize(frame, reconstruction_frames, free_frames);
+        self.try_localize(frame, reconstruction_frames, free_frames)
+    }
 
-        frame
+    /// Checks if a new view returned to a place that was already mapped and closes the loop if so.
+    ///
+    /// Nothing happens if loop closure is disabled or the view was removed since it was added.
+    fn close_view_loop(&mut self, reconstruction: ReconstructionKey, view: ViewKey) {
+        let exists = self
+            .data
+            .reconstructions
+            .get(reconstruction)
+            .map_or(false, |data| data.views.contains_key(view));
+        if self.settings.loop_closure
+            && exists
+            && self.close_loop(reconstruction, view).is_some()
+            && self.settings.maintenance_interval != 0
+        {
+            // Landmarks on both sides of the loop which it did not fuse are likely duplicates.
+            self.fuse_landmarks(reconstruction);
+        }
+    }
+
+    /// The reconstruction a frame was tracked in if it is due to be cleaned up by the periodic maintenance.
+    fn maintenance_due(&self, frame: FrameKey) -> Option<ReconstructionKey> {
+        let interval = self.settings.maintenance_interval;
+        if interval == 0 || (self.data.frame(frame).feed_frame + 1) % interval != 0 {
+            return None;
+        }
+        self.data
+            .frame(frame)
+            .view
+            .map(|(reconstruction, _)| reconstruction)
+            .or_else(|| {
+                self.last_tracked
+                    .filter(|&(tracked_frame, _, _)| tracked_frame == frame)
+                    .map(|(_, reconstruction, _)| reconstruction)
+            })
+            .filter(|&reconstruction| self.data.reconstructions.contains_key(reconstruction))
     }
 
     /// Attempts to match a frame pair, creating a new reconstruction from a two view pair.
@@ -772,7 +973,10 @@ where
     ) -> Option<(ReconstructionKey, ViewKey)> {
         // Sort the reconstruction frames by the number of views matched in them.
         let mut reconstruction_frames = reconstruction_frames.into_iter().collect_vec();
-        reconstruction_frames.sort_unstable_by_key(|(_, views)| Reverse(views.len()));
+        // Break ties by key, since the order of the map differs between runs.
+        reconstruction_frames.sort_unstable_by_key(|&(reconstruction, ref views)| {
+            (Reverse(views.len()), reconstruction)
+        });
 
         // Handle all the bag matches with existing reconstructions.
         for (dest_reconstruction, view_matches) in reconstruction_frames {
@@ -883,6 +1087,10 @@ where
     /// The last step we perform is to optimize and filter the resulting three-view reconstruction repeatedly.
     /// The optimized poses are used to compute all of the valid matches, which are then returned along with the frames and poses.
     #[allow(clippy::type_complexity)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "vslam::init_reconstruction", skip_all)
+    )]
     fn init_reconstruction(
         &self,
         center: FrameKey,
@@ -1327,9 +1535,12 @@ where
         view_matches: &[ViewKey],
         add_features: Range<usize>,
         original_matches: &mut Vec<(LandmarkKey, usize)>,
+        prediction: Option<WorldToCamera>,
     ) -> Option<(WorldToCamera, HashMap<usize, LandmarkKey>)> {
         let reconstruction = self.data.reconstruction(reconstruction_key);
         let new_frame = self.data.frame(new_frame_key);
+        // The bearings of the landmarks at the predicted pose, which are only computed for candidate matches.
+        let mut predicted_bearings: HashMap<LandmarkKey, Option<UnitVector3<f64>>> = HashMap::new();
 
         info!(
             "performing matching of features {:?} against {} views",
@@ -1340,7 +1551,7 @@ where
             // Get the self feature descriptor.
             let self_descriptor = new_frame.descriptor(self_feature);
             // Find the top features in every view match, and collect those together.
-            let lm_matches = view_matches
+            let mut lm_matches = view_matches
                 .iter()
                 .flat_map(|&view_match| {
                     let frame_match = reconstruction.views[view_match].frame;
@@ -1357,6 +1568,29 @@ where
                 })
                 .collect_vec();
 
+            // Only search the window around the predicted bearing of each landmark if the pose was predicted.
+            if let Some(prediction) = prediction {
+                let bearing = new_frame.bearing(self_feature);
+                let window = self.settings.odometry_search_window_cosine_distance;
+                lm_matches.retain(|&(landmark, _)| {
+                    let predicted = *predicted_bearings.entry(landmark).or_insert_with(|| {
+                        let point =
+                            self.triangulate_landmark_robust(reconstruction_key, landmark)?;
+                        Some(prediction.transform(point).bearing())
+                    });
+                    predicted.map_or(true, |predicted| 1.0 - predicted.dot(&bearing) < window)
+                });
+                match lm_matches[..] {
+                    [] => continue,
+                    // A single candidate within the window is accepted.
+                    [(landmark, _)] => {
+                        original_matches.push((landmark, self_feature));
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
             // Find the top 2 landmark matches overall.
             // Create an array where the best items will go.
             // Note that these two matches come from the same frame and therefore are two different landmarks.
@@ -1559,6 +1793,10 @@ where
     /// Attempts to register the frame into the given reconstruction.
     ///
     /// Returns the pose and a map from feature indices to landmarks.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "vslam::register_frame", skip_all)
+    )]
     fn register_frame(
         &self,
         reconstruction: ReconstructionKey,
@@ -1566,6 +1804,13 @@ where
         view_matches: Vec<ViewKey>,
     ) -> Option<(WorldToCamera, HashMap<usize, LandmarkKey>)> {
         info!("trying to register frame into existing reconstruction");
+        let prediction = self
+            .predict_pose(frame)
+            .filter(|&(predicted_reconstruction, _)| predicted_reconstruction == reconstruction)
+            .map(|(_, pose)| pose);
+        if prediction.is_some() {
+            info!("searching for matches around the pose predicted by the motion prior");
+        }
 
         let mut original_matches: Vec<(LandmarkKey, usize)> = vec![];
         let new_frame_num_features = self.data.frame(frame).descriptor_features.len();
@@ -1580,6 +1825,7 @@ where
                 &view_matches,
                 add_features.clone(),
                 &mut original_matches,
+                prediction,
             ) {
                 return Some(success);
             }
@@ -1833,6 +2079,14 @@ where
         let (pose, matches) = self
             .register_frame(reconstruction, frame, view_matches)
             .or_else(opeek(|| info!("failed to register frame")))?;
+        self.last_tracked = Some((frame, reconstruction, pose));
+
+        if let Some(candidate) = self.keyframe_candidate(reconstruction, frame, pose, &matches) {
+            if !self.keyframe_policy.is_keyframe(&candidate) {
+                info!("frame is not a keyframe: {:?}", candidate);
+                return None;
+            }
+        }
 
         let view = self.data.add_view(reconstruction, frame, pose, |feature| {
             matches.get(&feature).copied()
@@ -1949,44 +2203,25 @@ where
         Some(dest_reconstruction)
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "vslam::extract", skip_all)
+    )]
     fn kps_descriptors(
         &self,
         intrinsics: &CameraIntrinsicsK1Distortion,
         image: &DynamicImage,
     ) -> Vec<(BitArray<64>, Feature)> {
-        let (keypoints, descriptors) =
-            akaze::Akaze::new(self.settings.akaze_threshold).extract(image);
-        let rbg_image = image.to_rgb8();
-
-        // Use bicubic interpolation to extract colors from the image.
-        let colors: Vec<[u8; 3]> = keypoints
-            .iter()
-            .map(|kp| {
-                use image::Rgb;
-                let (x, y) = kp.point;
-                let Rgb(color) = bicubic::interpolate_bicubic(&rbg_image, x, y, Rgb([0, 0, 0]));
-                color
-            })
-            .collect();
-
-        // Calibrate keypoint and combine into features.
-        let mut features: Vec<(BitArray<64>, Feature)> = izip!(keypoints, descriptors, colors)
-            .map(|(keypoint, descriptor, color)| {
-                let bearing = intrinsics.calibrate(keypoint);
-                let response = keypoint.response;
-                (
-                    descriptor,
-                    Feature {
-                        bearing,
-                        response,
-                        color,
-                    },
-                )
-            })
-            .collect();
-        // Sort the features by response (higher response comes first).
-        features.sort_unstable_by_key(|&(_, Feature { response, .. })| Reverse(FloatOrd(response)));
-        features
+        let mask = self
+            .dynamic_masker
+            .as_ref()
+            .and_then(|masker| masker.dynamic_mask(image));
+        extract_masked_features(
+            self.settings.akaze_threshold,
+            intrinsics,
+            image,
+            mask.as_ref(),
+        )
     }
 
     /// This will take the first view of the reconstruction and scale everything so that the
@@ -2088,6 +2323,10 @@ where
     }
 
     /// Runs bundle adjustment (camera pose optimization), landmark filtering, and landmark merging.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "vslam::optimize_reconstruction", skip_all)
+    )]
     pub fn optimize_reconstruction(
         &mut self,
         reconstruction: ReconstructionKey,
@@ -2103,6 +2342,10 @@ where
     }
 
     /// Optimizes reconstruction camera poses.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "vslam::bundle_adjust", skip_all)
+    )]
     pub fn bundle_adjust_reconstruction(
         &mut self,
         reconstruction: ReconstructionKey,
@@ -2208,9 +2451,10 @@ where
             .collect_vec();
 
         // Sort the covisibilities such that the best ones show up first.
-        robust_three_view_covisibilities.sort_unstable_by_key(|(_, covisible_landmarks)| {
-            cmp::Reverse(covisible_landmarks.len())
-        });
+        // Ties are broken by the views, so the constraints don't depend on the order of the maps.
+        robust_three_view_covisibilities.sort_unstable_by_key(
+            |&(views, ref covisible_landmarks)| (cmp::Reverse(covisible_landmarks.len()), views),
+        );
 
         // Limit the number of constraints to only the best constraints.
         // Start by getting only constraints that incorporate new views.
@@ -2255,6 +2499,11 @@ where
         {
             edges.entry(view).or_default().push(constraint);
         }
+        if self.settings.odometry_constraints {
+            for (view, constraint) in self.odometry_edge_constraints(reconstruction) {
+                edges.entry(view).or_default().push(constraint);
+            }
+        }
         edges
     }
 
@@ -2649,6 +2898,10 @@ where
         reconstruction: ReconstructionKey,
         landmark: LandmarkKey,
     ) -> bool {
+        // Landmarks on moving objects don't stay in place.
+        if self.is_landmark_dynamic(reconstruction, landmark) {
+            return false;
+        }
         // Ensure at least two observations have an incidence angle between them exceeding the minimum.
         self.data
             .landmark_observations(reconstruction, landmark)
@@ -2732,42 +2985,98 @@ where
     }
 }
 
-fn matching(a_frame: &Frame, b_frame: &Frame, better_by: u32) -> Vec<Option<usize>> {
-    // If there arent at least 2 features in both frames, we produce no matches.
-    if a_frame.descriptor_features.len() < 2 || b_frame.descriptor_features.len() < 2 {
-        return vec![];
-    }
-    (0..a_frame.descriptor_features.len())
-        .map(|a_feature| {
-            let knn = b_frame
-                .descriptor_features
-                .knn(a_frame.descriptor(a_feature), 2);
-            if knn[0].distance + better_by <= knn[1].distance {
-                Some(knn[0].index)
-            } else {
-                None
+/// Extracts the AKAZE features of an image and calibrates them with the intrinsics of its feed.
+///
+/// The features are sorted by response, with the highest response first. This is what [`VSlam::add_frame`] extracts,
+/// so it can run on another thread before the features are given to [`VSlam::add_frame_features`].
+pub fn extract_features(
+    akaze_threshold: f64,
+    intrinsics: &CameraIntrinsicsK1Distortion,
+    image: &DynamicImage,
+) -> Vec<(BitArray<64>, Feature)> {
+    extract_masked_features(akaze_threshold, intrinsics, image, None)
+}
+
+/// Extracts features like [`extract_features`], but drops the keypoints which lie on the nonzero pixels of a mask of
+/// dynamic objects and flags the features whose descriptor region touches the mask as [`Feature::dynamic`].
+pub fn extract_masked_features(
+    akaze_threshold: f64,
+    intrinsics: &CameraIntrinsicsK1Distortion,
+    image: &DynamicImage,
+    mask: Option<&GrayImage>,
+) -> Vec<(BitArray<64>, Feature)> {
+    let (keypoints, descriptors) = akaze::Akaze::new(akaze_threshold).extract(image);
+    let (keypoints, descriptors, dynamic) = match mask {
+        Some(mask) => {
+            let mut kept_keypoints = vec![];
+            let mut kept_descriptors = vec![];
+            let mut dynamic = vec![];
+            for (keypoint, descriptor) in keypoints.into_iter().zip(descriptors) {
+                let (x, y) = keypoint.point;
+                if is_masked(mask, x, y) {
+                    continue;
+                }
+                dynamic.push(is_region_masked(mask, x, y, keypoint.size));
+                kept_keypoints.push(keypoint);
+                kept_descriptors.push(descriptor);
             }
+            (kept_keypoints, kept_descriptors, dynamic)
+        }
+        None => {
+            let dynamic = vec![false; keypoints.len()];
+            (keypoints, descriptors, dynamic)
+        }
+    };
+    let rbg_image = image.to_rgb8();
+
+    // Use bicubic interpolation to extract colors from the image.
+    let colors: Vec<[u8; 3]> = keypoints
+        .iter()
+        .map(|kp| {
+            use image::Rgb;
+            let (x, y) = kp.point;
+            let Rgb(color) = bicubic::interpolate_bicubic(&rbg_image, x, y, Rgb([0, 0, 0]));
+            color
         })
-        .collect()
+        .collect();
+
+    // Calibrate keypoint and combine into features.
+    let mut features: Vec<(BitArray<64>, Feature)> = izip!(keypoints, descriptors, colors, dynamic)
+        .map(|(keypoint, descriptor, color, dynamic)| {
+            let bearing = intrinsics.calibrate(keypoint);
+            let response = keypoint.response;
+            (
+                descriptor,
+                Feature {
+                    bearing,
+                    response,
+                    color,
+                    dynamic,
+                },
+            )
+        })
+        .collect();
+    // Sort the features by response (higher response comes first).
+    features.sort_unstable_by_key(|&(_, Feature { response, .. })| Reverse(FloatOrd(response)));
+    features
 }
 
-fn symmetric_matching(a: &Frame, b: &Frame, better_by: u32) -> Vec<[usize; 2]> {
-    // The best match for each feature in frame a to frame b's features.
-    let forward_matches = matching(a, b, better_by);
-    // The best match for each feature in frame b to frame a's features.
-    let reverse_matches = matching(b, a, better_by);
-    forward_matches
-        .into_iter()
-        .enumerate()
-        .filter_map(move |(aix, bix)| {
-            // First we only proceed if there was a sufficient bix match.
-            // Filter out matches which are not symmetric.
-            // Symmetric is defined as the best and sufficient match of a being b,
-            // and likewise the best and sufficient match of b being a.
-            bix.map(|bix| [aix, bix])
-                .filter(|&[aix, bix]| reverse_matches[bix] == Some(aix))
-        })
-        .collect()
+/// Whether the pixel of a mask at a point is nonzero, where points outside of the mask are not masked.
+fn is_masked(mask: &GrayImage, x: f32, y: f32) -> bool {
+    let (x, y) = (x.round(), y.round());
+    x >= 0.0
+        && y >= 0.0
+        && (x as u32) < mask.width()
+        && (y as u32) < mask.height()
+        && mask.get_pixel(x as u32, y as u32)[0] != 0
+}
+
+/// Whether a mask is nonzero anywhere on the circle of a keypoint, sampled at eight points.
+fn is_region_masked(mask: &GrayImage, x: f32, y: f32, radius: f32) -> bool {
+    (0..8).any(|step| {
+        let angle = step as f32 * std::f32::consts::FRAC_PI_4;
+        is_masked(mask, x + radius * angle.cos(), y + radius * angle.sin())
+    })
 }
 
 fn abs_difference<T: Sub<Output = T> + Ord>(x: T, y: T) -> T {
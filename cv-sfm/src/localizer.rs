@@ -0,0 +1,118 @@
+//! Localization against a previously built map.
+//!
+//! Deployments such as AR anchors and warehouse robots build the map once and then only need to know where the
+//! camera is in it. [`Localizer`] owns a [`VSlam`] with a finished map, usually loaded with
+//! [`VSlamData::load_path`](crate::VSlamData), and localizes every image against it with [`VSlam::localize`]. The map
+//! is never modified: images don't become frames or views, no landmarks are triangulated and no maintenance or loop
+//! closure runs, so the map stays exactly as it was built and the cost of every image stays constant over time.
+
+use crate::{relocalization::features_image, Localization, ReconstructionKey, VSlam};
+use cv_core::{
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, TriangulatorObservations, WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsicsK1Distortion;
+use image::DynamicImage;
+use log::*;
+use rand::Rng;
+use std::cmp::Reverse;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The outcome of localizing one image with a [`Localizer`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct LocalizationUpdate {
+    /// The timestamp the image was fed with.
+    pub timestamp: f64,
+    /// The reconstruction the camera was localized in and the pose of the camera in it.
+    pub pose: Option<(ReconstructionKey, WorldToCamera)>,
+    /// Statistics about the localization if it succeeded.
+    pub localization: Option<Localization>,
+}
+
+/// Localizes a stream of images from one camera in a read-only map.
+pub struct Localizer<C1, C2, PE, EE, T, R> {
+    /// The map, which is only read
+    vslam: VSlam<C1, C2, PE, EE, T, R>,
+    /// The intrinsics of the camera
+    intrinsics: CameraIntrinsicsK1Distortion,
+    /// The last update which had a pose
+    last_pose: Option<LocalizationUpdate>,
+}
+
+impl<C1, C2, PE, EE, T, R> Localizer<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Creates a localizer for a camera with the given intrinsics in the map of `vslam`.
+    pub fn new(
+        vslam: VSlam<C1, C2, PE, EE, T, R>,
+        intrinsics: CameraIntrinsicsK1Distortion,
+    ) -> Self {
+        Self {
+            vslam,
+            intrinsics,
+            last_pose: None,
+        }
+    }
+
+    /// The map the camera is localized in.
+    pub fn vslam(&self) -> &VSlam<C1, C2, PE, EE, T, R> {
+        &self.vslam
+    }
+
+    /// Gives back the map, which is unchanged.
+    pub fn into_inner(self) -> VSlam<C1, C2, PE, EE, T, R> {
+        self.vslam
+    }
+
+    /// The last update which had a pose, if the camera was ever localized.
+    pub fn last_pose(&self) -> Option<LocalizationUpdate> {
+        self.last_pose
+    }
+
+    /// Localizes the next image of the camera, taken at `timestamp`.
+    ///
+    /// The reconstruction the camera was last localized in is tried first, followed by the other reconstructions
+    /// from the largest to the smallest. Returns an update without a pose if the image could not be localized in any
+    /// of them.
+    pub fn feed(&mut self, image: &DynamicImage, timestamp: f64) -> LocalizationUpdate {
+        let features = features_image(self.vslam.kps_descriptors(&self.intrinsics, image));
+        let last = self
+            .last_pose
+            .and_then(|update| update.pose)
+            .map(|(reconstruction, _)| reconstruction);
+        let mut reconstructions: Vec<ReconstructionKey> =
+            self.vslam.data.reconstructions().collect();
+        reconstructions.sort_by_key(|&reconstruction| {
+            (
+                Some(reconstruction) != last,
+                Reverse(self.vslam.data.reconstruction(reconstruction).views.len()),
+            )
+        });
+
+        let found = reconstructions.into_iter().find_map(|reconstruction| {
+            self.vslam
+                .localize(reconstruction, &features)
+                .map(|(pose, localization)| (reconstruction, pose, localization))
+        });
+        let update = LocalizationUpdate {
+            timestamp,
+            pose: found.map(|(reconstruction, pose, _)| (reconstruction, pose)),
+            localization: found.map(|(_, _, localization)| localization),
+        };
+        if update.pose.is_some() {
+            self.last_pose = Some(update);
+        } else {
+            info!("failed to localize the image in the map");
+        }
+        update
+    }
+}
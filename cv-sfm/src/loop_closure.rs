@@ -0,0 +1,323 @@
+//! Loop closure detection and correction.
+//!
+//! When a camera returns to a place it has already mapped, the new views are registered against recently created
+//! landmarks, so the same place ends up in the map twice with the drift accumulated around the loop between them.
+//! A loop is detected by querying the place recognition index for views which look like the current view but are far
+//! from it in the feed, verified with two-view estimation and a similarity between the landmarks on both sides, and
+//! corrected by spreading the similarity over the trajectory with a [`Sim3PoseGraph`] before fusing the landmarks.
+
+use crate::{abs_difference, opeek, ReconstructionKey, VSlam, ViewKey};
+use cv_core::{
+    nalgebra::SMatrix,
+    sample_consensus::{Consensus, Estimator, Model},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Projective, Sim3,
+    TriangulatorObservations, WorldToCamera,
+};
+use cv_geom::alignment::{PointMatch, Umeyama};
+use cv_optimize::{PoseGraphOptimizer, Sim3Edge, Sim3PoseGraph};
+use log::*;
+use rand::{seq::SliceRandom, Rng};
+use space::KnnMap;
+use std::collections::HashMap;
+
+/// A loop closed by [`VSlam::close_loop`].
+#[derive(Clone, Debug)]
+pub struct LoopClosure {
+    pub reconstruction: ReconstructionKey,
+    /// The view which closed the loop.
+    pub view: ViewKey,
+    /// The earlier view which the loop was closed with.
+    pub candidate: ViewKey,
+    /// The similarity which mapped the landmarks seen by `view` onto the same landmarks seen by `candidate` before
+    /// the loop was corrected.
+    pub correction: Sim3,
+    /// The number of landmark pairs which agreed with the correction.
+    pub inliers: usize,
+    /// The number of landmarks seen by `view` which were fused into the landmarks seen by `candidate`.
+    pub merged_landmarks: usize,
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Finds the views of a reconstruction which could close a loop with `view`.
+    ///
+    /// These are the most visually similar views which are at least `loop_closure_minimum_frame_gap` frames away
+    /// from `view` in its feed and share no more than `loop_closure_maximum_shared_landmarks` landmarks with it, since
+    /// views which already share landmarks are connected through the map.
+    pub fn loop_candidates(
+        &self,
+        reconstruction: ReconstructionKey,
+        view: ViewKey,
+    ) -> Vec<ViewKey> {
+        let frame = self.data.view_frame(reconstruction, view);
+        let landmarks = &self.data.view(reconstruction, view).landmarks;
+        self.data
+            .lsh_to_frame
+            .knn_values(&frame.lsh, self.settings.loop_closure_search_num)
+            .into_iter()
+            .filter_map(|(_, &found_frame)| {
+                let found = self.data.frame(found_frame);
+                let (found_reconstruction, found_view) = found.view?;
+                (found_reconstruction == reconstruction && found_view != view).then(|| ())?;
+                let is_recent = found.feed == frame.feed
+                    && abs_difference(found.feed_frame, frame.feed_frame)
+                        < self.settings.loop_closure_minimum_frame_gap;
+                (!is_recent).then(|| ())?;
+                let shared = landmarks
+                    .iter()
+                    .filter(|&&landmark| {
+                        self.data
+                            .landmark(reconstruction, landmark)
+                            .observations
+                            .contains_key(&found_view)
+                    })
+                    .count();
+                (shared <= self.settings.loop_closure_maximum_shared_landmarks).then(|| found_view)
+            })
+            .take(self.settings.loop_closure_candidates)
+            .collect()
+    }
+
+    /// Verifies that `view` and `candidate` see the same place.
+    ///
+    /// The features of the two views are matched with two-view estimation, and the robust landmarks of the matches
+    /// are aligned with the [`Umeyama`] estimator in sample consensus. Returns the similarity from the landmarks of
+    /// `view` to those of `candidate` and the `[view_feature, candidate_feature]` matches which agree with it.
+    pub fn verify_loop(
+        &self,
+        reconstruction: ReconstructionKey,
+        view: ViewKey,
+        candidate: ViewKey,
+    ) -> Option<(Sim3, Vec<[usize; 2]>)> {
+        self.align_views((reconstruction, view), (reconstruction, candidate))
+    }
+
+    /// Estimates the similarity from the landmarks seen by the `source` view to the landmarks seen by the `target`
+    /// view, which may be in different reconstructions, as described in [`VSlam::verify_loop`].
+    pub(crate) fn align_views(
+        &self,
+        (source_reconstruction, source_view): (ReconstructionKey, ViewKey),
+        (target_reconstruction, target_view): (ReconstructionKey, ViewKey),
+    ) -> Option<(Sim3, Vec<[usize; 2]>)> {
+        let (_, matches) = self
+            .init_two_view(
+                self.data.view(source_reconstruction, source_view).frame,
+                self.data.view(target_reconstruction, target_view).frame,
+            )
+            .or_else(opeek(|| info!("view alignment failed two-view estimation")))?;
+
+        let points: Vec<([usize; 2], PointMatch)> = matches
+            .iter()
+            .filter_map(|&[source_feature, target_feature]| {
+                let source_landmark = self.data.observation_landmark(
+                    source_reconstruction,
+                    source_view,
+                    source_feature,
+                );
+                let target_landmark = self.data.observation_landmark(
+                    target_reconstruction,
+                    target_view,
+                    target_feature,
+                );
+                let source = self
+                    .triangulate_landmark_robust(source_reconstruction, source_landmark)?
+                    .point()?;
+                let target = self
+                    .triangulate_landmark_robust(target_reconstruction, target_landmark)?
+                    .point()?;
+                Some(([source_feature, target_feature], PointMatch(source, target)))
+            })
+            .collect();
+        if points.len() < self.settings.loop_closure_minimum_inliers {
+            info!(
+                "only found {} robust landmark pairs for view alignment, need {}",
+                points.len(),
+                self.settings.loop_closure_minimum_inliers
+            );
+            return None;
+        }
+
+        // The landmarks far from the camera are triangulated less accurately, so the threshold grows with distance.
+        let center = self
+            .data
+            .pose(target_reconstruction, target_view)
+            .inverse()
+            .isometry()
+            .translation
+            .vector;
+        let threshold = self.settings.loop_closure_sim3_threshold;
+        let is_inlier = |sim3: &Sim3, point: &PointMatch| {
+            sim3.residual(point) < threshold * (point.1.coords - center).norm()
+        };
+        let count_inliers = |sim3: &Sim3| {
+            points
+                .iter()
+                .filter(|(_, point)| is_inlier(sim3, point))
+                .count()
+        };
+
+        let estimator = Umeyama::new();
+        let mut best: Option<(Sim3, usize)> = None;
+        for _ in 0..self.settings.loop_closure_sim3_iterations {
+            let sample: Vec<PointMatch> = points
+                .choose_multiple(
+                    &mut *self.rng.borrow_mut(),
+                    <Umeyama as Estimator<PointMatch>>::MIN_SAMPLES,
+                )
+                .map(|&(_, point)| point)
+                .collect();
+            if let Some(sim3) = estimator.estimate(sample.iter().copied()) {
+                let count = count_inliers(&sim3);
+                if best.map_or(true, |(_, best_count)| count > best_count) {
+                    best = Some((sim3, count));
+                }
+            }
+        }
+        let (sim3, _) = best.or_else(opeek(|| info!("failed to estimate view similarity")))?;
+
+        // Refit the similarity to all of the inliers.
+        let inliers: Vec<PointMatch> = points
+            .iter()
+            .filter(|(_, point)| is_inlier(&sim3, point))
+            .map(|&(_, point)| point)
+            .collect();
+        let sim3 = estimator.estimate(inliers.iter().copied()).unwrap_or(sim3);
+        let inliers: Vec<[usize; 2]> = points
+            .iter()
+            .filter(|(_, point)| is_inlier(&sim3, point))
+            .map(|&(matches, _)| matches)
+            .collect();
+        info!(
+            "view similarity has {} inliers among {} landmark pairs with scale {}",
+            inliers.len(),
+            points.len(),
+            sim3.scale()
+        );
+        if inliers.len() < self.settings.loop_closure_minimum_inliers {
+            info!(
+                "only found {} view similarity inliers, need {}",
+                inliers.len(),
+                self.settings.loop_closure_minimum_inliers
+            );
+            return None;
+        }
+        Some((sim3, inliers))
+    }
+
+    /// Tries to close a loop between `view` and an earlier view of the reconstruction.
+    ///
+    /// The first candidate from [`VSlam::loop_candidates`] which passes [`VSlam::verify_loop`] is used to correct the
+    /// poses of the reconstruction, after which the matched landmarks are fused and the reconstruction is optimized.
+    pub fn close_loop(
+        &mut self,
+        reconstruction: ReconstructionKey,
+        view: ViewKey,
+    ) -> Option<LoopClosure> {
+        let (candidate, (correction, matches)) = self
+            .loop_candidates(reconstruction, view)
+            .into_iter()
+            .find_map(|candidate| {
+                Some((
+                    candidate,
+                    self.verify_loop(reconstruction, view, candidate)?,
+                ))
+            })
+            .or_else(opeek(|| info!("no loop closure candidate was verified")))?;
+        info!("closing loop with {} inliers", matches.len());
+
+        self.correct_loop(reconstruction, view, candidate, correction);
+
+        // Fuse the landmarks on both sides of the loop.
+        let mut merged_landmarks = 0;
+        for &[feature, candidate_feature] in &matches {
+            // Landmarks are looked up again since earlier merges may have replaced them.
+            let landmark = self
+                .data
+                .observation_landmark(reconstruction, view, feature);
+            let candidate_landmark =
+                self.data
+                    .observation_landmark(reconstruction, candidate, candidate_feature);
+            if landmark != candidate_landmark
+                && self
+                    .merge_landmarks(reconstruction, candidate_landmark, landmark)
+                    .is_some()
+            {
+                merged_landmarks += 1;
+            }
+        }
+        info!("fused {} landmarks across the loop", merged_landmarks);
+        self.optimize_reconstruction(reconstruction);
+
+        Some(LoopClosure {
+            reconstruction,
+            view,
+            candidate,
+            correction,
+            inliers: matches.len(),
+            merged_landmarks,
+        })
+    }
+
+    /// Spreads the correction of a loop over the poses of the reconstruction with a [`Sim3PoseGraph`].
+    ///
+    /// The views are connected in the order of their feed by odometry edges, and `candidate` is connected to `view`
+    /// by an edge which moves the landmarks of `view` onto those of `candidate`. The candidate is held fixed, so the
+    /// drift is removed from the newer side of the loop.
+    fn correct_loop(
+        &mut self,
+        reconstruction: ReconstructionKey,
+        view: ViewKey,
+        candidate: ViewKey,
+        correction: Sim3,
+    ) {
+        let mut views: Vec<ViewKey> = self
+            .data
+            .reconstruction(reconstruction)
+            .views
+            .keys()
+            .collect();
+        views.sort_unstable_by_key(|&view| {
+            let frame = self.data.view_frame(reconstruction, view);
+            (frame.feed, frame.feed_frame)
+        });
+        let index: HashMap<ViewKey, usize> = views
+            .iter()
+            .enumerate()
+            .map(|(ix, &view)| (view, ix))
+            .collect();
+        let poses: Vec<WorldToCamera> = views
+            .iter()
+            .map(|&view| self.data.pose(reconstruction, view))
+            .collect();
+
+        let information = SMatrix::<f64, 7, 7>::identity();
+        let mut graph = Sim3PoseGraph::from_poses(&poses);
+        for ix in 1..views.len() {
+            graph.connect_odometry(ix - 1, ix, information);
+        }
+        let (from, to) = (index[&candidate], index[&view]);
+        // The corrected pose of `view` first maps the candidate side of the map onto its own side.
+        let measurement = Sim3::from_pose(poses[to])
+            * correction.inverse()
+            * Sim3::from_pose(poses[from]).inverse();
+        graph.connect(Sim3Edge::new(from, to, measurement, information));
+        graph.fix_pose(from);
+
+        let report = PoseGraphOptimizer::new().optimize_sim3(&mut graph);
+        info!(
+            "corrected {} poses around the loop: {:?}",
+            views.len(),
+            report
+        );
+        for (ix, &view) in views.iter().enumerate() {
+            self.data.view_mut(reconstruction, view).pose = graph.pose(ix);
+        }
+    }
+}
@@ -0,0 +1,359 @@
+//! Map maintenance for long sessions.
+//!
+//! Every frame keeps its features and every view keeps a landmark for each of its features, so a map which is never
+//! cleaned up grows with the length of the session rather than with the area covered. Map maintenance removes
+//! landmarks which are poorly triangulated or were never seen again, fuses landmarks which are the same point seen
+//! from both sides of a loop, removes views whose landmarks are all seen by enough other views, and releases the
+//! features of frames which never became views. The newest views of each feed are left untouched, since they are
+//! still being matched against by tracking.
+
+use crate::{LandmarkKey, ReconstructionKey, VSlam, ViewKey};
+use bitarray::{BitArray, Hamming};
+use cv_core::{
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Projective, TriangulatorObservations,
+    WorldPoint, WorldToCamera,
+};
+use hgg::HggLite;
+use log::*;
+use rand::Rng;
+use space::Knn;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// Statistics about a [`VSlam::maintain_reconstruction`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct MaintenanceReport {
+    /// The number of landmarks which were split into separate observations.
+    pub culled_landmarks: usize,
+    /// The number of landmarks which were fused into another landmark.
+    pub fused_landmarks: usize,
+    /// The number of views which were removed as redundant.
+    pub pruned_views: usize,
+    /// The number of frames whose features were released.
+    pub forgotten_frames: usize,
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Runs every map maintenance step on the reconstruction and optimizes it if it changed.
+    ///
+    /// Returns `None` if the optimization rejected the reconstruction.
+    pub fn maintain_reconstruction(
+        &mut self,
+        reconstruction: ReconstructionKey,
+    ) -> Option<MaintenanceReport> {
+        info!("running map maintenance");
+        let report = MaintenanceReport {
+            culled_landmarks: self.cull_landmarks(reconstruction),
+            fused_landmarks: self.fuse_landmarks(reconstruction),
+            pruned_views: self.prune_redundant_views(reconstruction),
+            forgotten_frames: self.forget_frames(),
+        };
+        info!("map maintenance finished: {:?}", report);
+        if report.fused_landmarks != 0 || report.pruned_views != 0 {
+            self.optimize_reconstruction(reconstruction)?;
+        }
+        Some(report)
+    }
+
+    /// Splits the landmarks which are not observed by a recent view and either have fewer than
+    /// `maintenance_minimum_observations` observations or observations which disagree with their triangulated point
+    /// by a mean cosine distance above `maintenance_maximum_mean_cosine_distance`, as well as dynamic landmarks (see
+    /// [`VSlam::is_landmark_dynamic`]).
+    ///
+    /// Returns the number of landmarks which were split.
+    pub fn cull_landmarks(&mut self, reconstruction: ReconstructionKey) -> usize {
+        let recent = self.recent_views(reconstruction);
+        let landmarks: Vec<LandmarkKey> = self
+            .data
+            .reconstruction(reconstruction)
+            .landmarks
+            .keys()
+            .collect();
+        let mut culled = 0;
+        for landmark in landmarks {
+            let observations: Vec<(ViewKey, usize)> = self
+                .data
+                .landmark_observations(reconstruction, landmark)
+                .collect();
+            if observations.len() < 2 || observations.iter().any(|(view, _)| recent.contains(view))
+            {
+                continue;
+            }
+            let cull = observations.len() < self.settings.maintenance_minimum_observations
+                || self.is_landmark_dynamic(reconstruction, landmark)
+                || self
+                    .triangulate_landmark(reconstruction, landmark)
+                    .map_or(true, |point| {
+                        let total: f64 = observations
+                            .iter()
+                            .map(|&(view, feature)| {
+                                self.observation_cosine_distance(
+                                    reconstruction,
+                                    view,
+                                    feature,
+                                    point,
+                                )
+                            })
+                            .sum();
+                        total / observations.len() as f64
+                            > self.settings.maintenance_maximum_mean_cosine_distance
+                    });
+            if cull {
+                self.split_landmark(reconstruction, landmark);
+                culled += 1;
+            }
+        }
+        info!("culled {} landmarks", culled);
+        culled
+    }
+
+    /// Fuses landmarks which are the same point observed by different features, which happens when the camera
+    /// returns to a place after tracking was lost or on the far side of a closed loop.
+    ///
+    /// The robust landmarks seen by the covisible views of each view are searched for among the features of the view
+    /// with their descriptors, and a landmark is fused with the landmark of a matching feature if every observation of
+    /// both agrees with the triangulated point within `merge_maximum_cosine_distance`.
+    ///
+    /// Returns the number of landmarks which were fused.
+    pub fn fuse_landmarks(&mut self, reconstruction: ReconstructionKey) -> usize {
+        let views: Vec<ViewKey> = self
+            .data
+            .reconstruction(reconstruction)
+            .views
+            .keys()
+            .collect();
+        let mut fused = 0;
+        for view in views {
+            if !self
+                .data
+                .reconstruction(reconstruction)
+                .views
+                .contains_key(view)
+            {
+                continue;
+            }
+            for landmark in self.covisible_landmarks(reconstruction, view) {
+                // Earlier fusions may have removed the landmark or added this view to it.
+                match self
+                    .data
+                    .reconstruction(reconstruction)
+                    .landmarks
+                    .get(landmark)
+                {
+                    Some(found) if !found.observations.contains_key(&view) => {}
+                    _ => continue,
+                }
+                let point = match self.triangulate_landmark_robust(reconstruction, landmark) {
+                    Some(point) if self.landmark_agrees(reconstruction, landmark, point) => point,
+                    _ => continue,
+                };
+                let (first_view, first_feature) = self
+                    .data
+                    .landmark_observations(reconstruction, landmark)
+                    .next()
+                    .unwrap();
+                let descriptor: BitArray<64> = *self.data.descriptor(
+                    self.data.view(reconstruction, first_view).frame,
+                    first_feature,
+                );
+                let neighbors = self
+                    .data
+                    .view_frame(reconstruction, view)
+                    .descriptor_features
+                    .knn(&descriptor, 2);
+                let duplicate = neighbors.into_iter().find_map(|neighbor| {
+                    let other =
+                        self.data
+                            .observation_landmark(reconstruction, view, neighbor.index);
+                    (other != landmark && self.landmark_agrees(reconstruction, other, point))
+                        .then(|| other)
+                });
+                if let Some(other) = duplicate {
+                    if self
+                        .merge_landmarks(reconstruction, landmark, other)
+                        .is_some()
+                    {
+                        fused += 1;
+                    }
+                }
+            }
+        }
+        info!("fused {} landmarks", fused);
+        fused
+    }
+
+    /// Removes the views which are not recent and for which at least `maintenance_redundant_view_ratio` of the
+    /// landmarks with two or more observations are observed by `maintenance_redundant_view_observers` other views.
+    ///
+    /// The oldest views are considered first, and a reconstruction is never reduced below three views.
+    ///
+    /// Returns the number of views which were removed.
+    pub fn prune_redundant_views(&mut self, reconstruction: ReconstructionKey) -> usize {
+        let recent = self.recent_views(reconstruction);
+        let mut views: Vec<ViewKey> = self
+            .data
+            .reconstruction(reconstruction)
+            .views
+            .keys()
+            .filter(|view| !recent.contains(view))
+            .collect();
+        views.sort_unstable_by_key(|&view| {
+            let frame = self.data.view_frame(reconstruction, view);
+            (frame.feed, frame.feed_frame)
+        });
+        let mut pruned = 0;
+        for view in views {
+            if self.data.reconstruction(reconstruction).views.len() <= 3 {
+                break;
+            }
+            let observation_counts: Vec<usize> = self
+                .data
+                .view(reconstruction, view)
+                .landmarks
+                .iter()
+                .map(|&landmark| {
+                    self.data
+                        .landmark(reconstruction, landmark)
+                        .observations
+                        .len()
+                })
+                .filter(|&observations| observations >= 2)
+                .collect();
+            if observation_counts.is_empty() {
+                continue;
+            }
+            let redundant = observation_counts
+                .iter()
+                .filter(|&&observations| {
+                    observations > self.settings.maintenance_redundant_view_observers
+                })
+                .count();
+            if redundant as f64
+                >= self.settings.maintenance_redundant_view_ratio * observation_counts.len() as f64
+            {
+                self.data.remove_view(reconstruction, view);
+                pruned += 1;
+            }
+        }
+        info!("pruned {} redundant views", pruned);
+        pruned
+    }
+
+    /// Releases the features of the frames which are not views and are at least `maintenance_forget_frames` frames
+    /// older than the newest frame of their feed. These frames can no longer initialize a reconstruction.
+    ///
+    /// Returns the number of frames whose features were released.
+    pub fn forget_frames(&mut self) -> usize {
+        if self.settings.maintenance_forget_frames == 0 {
+            return 0;
+        }
+        let newest: HashMap<_, usize> = self
+            .data
+            .feeds
+            .iter()
+            .map(|(feed, data)| (feed, data.frames.len()))
+            .collect();
+        let mut forgotten = 0;
+        for frame in self.data.frames.values_mut() {
+            if frame.view.is_none()
+                && frame.descriptor_features.len() > 0
+                && frame.feed_frame + self.settings.maintenance_forget_frames <= newest[&frame.feed]
+            {
+                frame.descriptor_features = HggLite::new(Hamming).insert_knn(32);
+                forgotten += 1;
+            }
+        }
+        info!("released the features of {} frames", forgotten);
+        forgotten
+    }
+
+    /// The `maintenance_recent_views` newest views of each feed in the reconstruction.
+    fn recent_views(&self, reconstruction: ReconstructionKey) -> HashSet<ViewKey> {
+        let mut feed_views: HashMap<_, Vec<(usize, ViewKey)>> = HashMap::new();
+        for (view_key, view) in self.data.reconstruction(reconstruction).views.iter() {
+            let frame = self.data.frame(view.frame);
+            feed_views
+                .entry(frame.feed)
+                .or_default()
+                .push((frame.feed_frame, view_key));
+        }
+        feed_views
+            .into_iter()
+            .flat_map(|(_, mut views)| {
+                views.sort_unstable_by_key(|&(feed_frame, _)| Reverse(feed_frame));
+                views
+                    .into_iter()
+                    .take(self.settings.maintenance_recent_views)
+                    .map(|(_, view)| view)
+            })
+            .collect()
+    }
+
+    /// The landmarks with two or more observations which are seen by views sharing a landmark with `view`, but which
+    /// are not seen by `view` itself.
+    fn covisible_landmarks(
+        &self,
+        reconstruction: ReconstructionKey,
+        view: ViewKey,
+    ) -> HashSet<LandmarkKey> {
+        let landmarks = &self.data.view(reconstruction, view).landmarks;
+        let covisible: HashSet<ViewKey> = landmarks
+            .iter()
+            .flat_map(|&landmark| self.data.landmark_observations(reconstruction, landmark))
+            .map(|(covisible, _)| covisible)
+            .filter(|&covisible| covisible != view)
+            .collect();
+        covisible
+            .into_iter()
+            .flat_map(|covisible| self.data.view(reconstruction, covisible).landmarks.iter())
+            .copied()
+            .filter(|&landmark| {
+                let observations = &self.data.landmark(reconstruction, landmark).observations;
+                observations.len() >= 2 && !observations.contains_key(&view)
+            })
+            .collect()
+    }
+
+    /// Checks that every observation of the landmark agrees with `point` within `merge_maximum_cosine_distance`.
+    fn landmark_agrees(
+        &self,
+        reconstruction: ReconstructionKey,
+        landmark: LandmarkKey,
+        point: WorldPoint,
+    ) -> bool {
+        self.data
+            .landmark_observations(reconstruction, landmark)
+            .all(|(view, feature)| {
+                self.observation_cosine_distance(reconstruction, view, feature, point)
+                    < self.settings.merge_maximum_cosine_distance
+            })
+    }
+
+    /// The cosine distance between the bearing of an observation and the bearing of `point` from its view.
+    fn observation_cosine_distance(
+        &self,
+        reconstruction: ReconstructionKey,
+        view: ViewKey,
+        feature: usize,
+        point: WorldPoint,
+    ) -> f64 {
+        let pose = self.data.view(reconstruction, view).pose;
+        let bearing = self.data.observation_bearing(reconstruction, view, feature);
+        1.0 - pose.transform(point).bearing().dot(&bearing)
+    }
+}
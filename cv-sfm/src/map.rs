@@ -0,0 +1,420 @@
+//! Saving and loading of maps.
+//!
+//! A saved map contains all of the [`VSlamData`]: the feeds with their intrinsics, the frames with their features and
+//! descriptors, and every reconstruction with the poses of its views, the landmarks with the views which observe them
+//! (from which the covisibility between views follows), and the pose constraints. The format starts with magic bytes
+//! and a version number. When the format changes, the version is increased and maps saved with earlier versions are
+//! still loaded. Long sessions can instead be saved as they go with a [`MapJournal`](crate::MapJournal).
+//!
+//! The versions of the format are:
+//!
+//! 1. The initial format.
+//! 2. Frames have the motion from the previous frame of their feed and features are flagged as dynamic.
+
+use crate::{
+    Feature, Feed, FeedKey, Frame, FrameKey, Reconstruction, ReconstructionKey, VSlamData, ViewKey,
+};
+use bitarray::{BitArray, Hamming};
+use cv_core::nalgebra::UnitVector3;
+use hamming_lsh::HammingHasher;
+use hgg::HggLite;
+use serde::Deserialize;
+use slotmap::DenseSlotMap;
+use space::KnnInsert;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+#[cfg(test)]
+use serde::Serialize;
+
+/// The magic bytes at the beginning of a saved [`VSlamData`].
+const MAP_MAGIC: [u8; 4] = *b"CVMP";
+/// The version of the saved [`VSlamData`] format.
+const MAP_VERSION: u32 = 2;
+
+/// A [`Feature`] of version 1 of the format.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub(crate) struct FeatureV1 {
+    bearing: UnitVector3<f64>,
+    response: f32,
+    color: [u8; 3],
+}
+
+/// A [`Frame`] of version 1 of the format.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub(crate) struct FrameV1 {
+    feed: FeedKey,
+    feed_frame: usize,
+    descriptor_features: HggLite<Hamming, BitArray<64>, FeatureV1>,
+    view: Option<(ReconstructionKey, ViewKey)>,
+    lsh: BitArray<512>,
+}
+
+impl From<FrameV1> for Frame {
+    fn from(frame: FrameV1) -> Self {
+        // The features are inserted in their original order so that they keep their indices.
+        let mut descriptor_features = HggLite::new(Hamming).insert_knn(32);
+        let features = &frame.descriptor_features;
+        for ix in 0..features.len() {
+            let feature = features.get_value(ix).unwrap();
+            descriptor_features.insert(
+                *features.get_key(ix).unwrap(),
+                Feature {
+                    bearing: feature.bearing,
+                    response: feature.response,
+                    color: feature.color,
+                    dynamic: false,
+                },
+            );
+        }
+        Self {
+            feed: frame.feed,
+            feed_frame: frame.feed_frame,
+            descriptor_features,
+            view: frame.view,
+            lsh: frame.lsh,
+            motion: None,
+        }
+    }
+}
+
+/// A [`VSlamData`] of version 1 of the format.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct VSlamDataV1 {
+    feeds: DenseSlotMap<FeedKey, Feed>,
+    reconstructions: DenseSlotMap<ReconstructionKey, Reconstruction>,
+    frames: DenseSlotMap<FrameKey, FrameV1>,
+    hasher: HammingHasher<64, 512>,
+    lsh_to_frame: HggLite<Hamming, BitArray<512>, FrameKey>,
+}
+
+impl From<VSlamDataV1> for VSlamData {
+    fn from(data: VSlamDataV1) -> Self {
+        let mut upgraded = Self {
+            feeds: data.feeds,
+            reconstructions: data.reconstructions,
+            frames: DenseSlotMap::with_key(),
+            hasher: data.hasher,
+            lsh_to_frame: Default::default(),
+        };
+        // The frames are inserted again, so everything which refers to a frame is given its new key.
+        let mut keys = HashMap::new();
+        for (key, frame) in data.frames {
+            let frame = Frame::from(frame);
+            let lsh = frame.lsh;
+            let new_key = upgraded.frames.insert(frame);
+            upgraded.lsh_to_frame.insert(lsh, new_key);
+            keys.insert(key, new_key);
+        }
+        for feed in upgraded.feeds.values_mut() {
+            for frame in &mut feed.frames {
+                *frame = keys[&*frame];
+            }
+        }
+        for reconstruction in upgraded.reconstructions.values_mut() {
+            for view in reconstruction.views.values_mut() {
+                view.frame = keys[&view.frame];
+            }
+        }
+        upgraded
+    }
+}
+
+impl VSlamData {
+    /// Saves the map to a writer in a versioned binary format.
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&MAP_MAGIC)?;
+        writer.write_all(&MAP_VERSION.to_le_bytes())?;
+        bincode::serialize_into(writer, self).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+
+    /// Loads a map saved with [`VSlamData::save`] by this or any earlier version of the format.
+    ///
+    /// Returns an [`io::ErrorKind::InvalidData`] error if the map was saved with a newer version of the format.
+    pub fn load(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAP_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a map"));
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let invalid = |e: bincode::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+        match u32::from_le_bytes(version) {
+            1 => bincode::deserialize_from::<_, VSlamDataV1>(reader)
+                .map(Self::from)
+                .map_err(invalid),
+            MAP_VERSION => bincode::deserialize_from(reader).map_err(invalid),
+            version => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported map version {}", version),
+            )),
+        }
+    }
+
+    /// Saves the map to a file.
+    pub fn save_path(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads the map from a file.
+    pub fn load_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::load(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use cv_core::{
+        nalgebra::{IsometryMatrix3, Vector3},
+        CameraToCamera, WorldToCamera,
+    };
+    use cv_pinhole::{CameraIntrinsics, CameraIntrinsicsK1Distortion};
+    use space::KnnMap;
+
+    /// What a map contains without its keys, which is the same for a map and the map it is loaded as.
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct Description {
+        /// The index in the feed, descriptors and pose of each frame.
+        frames: Vec<(usize, Vec<BitArray<64>>, Option<WorldToCamera>)>,
+        /// The frame and feature of each observation of each landmark.
+        landmarks: Vec<Vec<(usize, usize)>>,
+        /// The frames of the views of each constraint.
+        constraints: Vec<[usize; 3]>,
+    }
+
+    pub(crate) fn describe(data: &VSlamData) -> Description {
+        let frames = data
+            .feeds
+            .values()
+            .flat_map(|feed| &feed.frames)
+            .map(|&frame| {
+                let frame = &data.frames[frame];
+                let descriptors = (0..frame.descriptor_features.len())
+                    .map(|ix| *frame.descriptor(ix))
+                    .collect();
+                let pose = frame
+                    .view
+                    .map(|(reconstruction, view)| data.pose(reconstruction, view));
+                (frame.feed_frame, descriptors, pose)
+            })
+            .collect();
+        let mut landmarks = vec![];
+        let mut constraints = vec![];
+        for reconstruction in data.reconstructions.values() {
+            let view_frame =
+                |view: ViewKey| data.frames[reconstruction.views[view].frame].feed_frame;
+            for landmark in reconstruction.landmarks.values() {
+                let mut observations: Vec<(usize, usize)> = landmark
+                    .observations
+                    .iter()
+                    .map(|(&view, &feature)| (view_frame(view), feature))
+                    .collect();
+                observations.sort_unstable();
+                landmarks.push(observations);
+            }
+            for constraint in reconstruction.constraints.values() {
+                constraints.push(constraint.views.map(view_frame));
+            }
+        }
+        landmarks.sort();
+        constraints.sort();
+        Description {
+            frames,
+            landmarks,
+            constraints,
+        }
+    }
+
+    pub(crate) fn features(frame: u8) -> Vec<(BitArray<64>, Feature)> {
+        (0..4)
+            .map(|ix| {
+                let feature = Feature {
+                    bearing: UnitVector3::new_normalize(Vector3::new(
+                        0.1 * ix as f64,
+                        -0.05 * frame as f64,
+                        1.0,
+                    )),
+                    response: 0.5,
+                    color: [frame, ix, 7],
+                    dynamic: false,
+                };
+                (BitArray::new([frame * 16 + ix; 64]), feature)
+            })
+            .collect()
+    }
+
+    fn pose(x: f64) -> CameraToCamera {
+        CameraToCamera(IsometryMatrix3::new(
+            Vector3::new(x, 0.0, 0.0),
+            Vector3::new(0.0, 0.1 * x, 0.0),
+        ))
+    }
+
+    /// A map with one reconstruction of three frames and a frame which is not in it.
+    pub(crate) fn map() -> (VSlamData, [FrameKey; 4]) {
+        let mut data = VSlamData::default();
+        let feed = data.feeds.insert(Feed {
+            intrinsics: CameraIntrinsicsK1Distortion::new(CameraIntrinsics::identity(), 0.01),
+            frames: vec![],
+        });
+        let frames = [0, 1, 2, 3].map(|frame| data.add_frame(feed, features(frame)));
+        data.add_reconstruction(
+            frames[0],
+            frames[1],
+            frames[2],
+            pose(1.0),
+            pose(2.0),
+            vec![(0, 0, 0), (1, 1, 1)],
+            vec![[2, 2]],
+            vec![[3, 3]],
+        );
+        (data, frames)
+    }
+
+    /// Converts a frame to the layout of version 1 of the format, dropping what it didn't have.
+    pub(crate) fn downgrade_frame(frame: &Frame) -> FrameV1 {
+        let mut descriptor_features = HggLite::new(Hamming).insert_knn(32);
+        for ix in 0..frame.descriptor_features.len() {
+            let feature = frame.feature(ix);
+            descriptor_features.insert(
+                *frame.descriptor(ix),
+                FeatureV1 {
+                    bearing: feature.bearing,
+                    response: feature.response,
+                    color: feature.color,
+                },
+            );
+        }
+        FrameV1 {
+            feed: frame.feed,
+            feed_frame: frame.feed_frame,
+            descriptor_features,
+            view: frame.view,
+            lsh: frame.lsh,
+        }
+    }
+
+    /// Converts a map to the layout of version 1 of the format.
+    fn downgrade(data: VSlamData) -> VSlamDataV1 {
+        let mut frames = DenseSlotMap::with_key();
+        let mut keys = HashMap::new();
+        for (key, frame) in data.frames {
+            keys.insert(key, frames.insert(downgrade_frame(&frame)));
+        }
+        let mut feeds = data.feeds;
+        for feed in feeds.values_mut() {
+            for frame in &mut feed.frames {
+                *frame = keys[&*frame];
+            }
+        }
+        let mut reconstructions = data.reconstructions;
+        for reconstruction in reconstructions.values_mut() {
+            for view in reconstruction.views.values_mut() {
+                view.frame = keys[&view.frame];
+            }
+        }
+        let mut lsh_to_frame = HggLite::default();
+        for frame in frames.values() {
+            lsh_to_frame.insert(frame.lsh, feeds[frame.feed].frames[frame.feed_frame]);
+        }
+        VSlamDataV1 {
+            feeds,
+            reconstructions,
+            frames,
+            hasher: data.hasher,
+            lsh_to_frame,
+        }
+    }
+
+    fn save_v1(data: VSlamData) -> Vec<u8> {
+        let mut bytes = MAP_MAGIC.to_vec();
+        bytes.extend(1u32.to_le_bytes());
+        bincode::serialize_into(&mut bytes, &downgrade(data)).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let (mut data, frames) = map();
+        data.frames[frames[1]].motion = Some(CameraToCamera(IsometryMatrix3::new(
+            Vector3::new(0.0, 0.0, 0.5),
+            Vector3::zeros(),
+        )));
+        let mut features = features(4);
+        features[3].1.dynamic = true;
+        let dynamic = data.add_frame(data.frames[frames[0]].feed, features);
+        let mut bytes = vec![];
+        data.save(&mut bytes).unwrap();
+        let loaded = VSlamData::load(bytes.as_slice()).unwrap();
+        assert_eq!(describe(&loaded), describe(&data));
+        // The keys are kept, and so is everything which is not in the description.
+        assert_eq!(
+            loaded.frames.keys().collect::<Vec<_>>(),
+            data.frames.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            loaded.frames[frames[1]].motion,
+            data.frames[frames[1]].motion
+        );
+        assert!(loaded.dynamic(dynamic, 3));
+        assert!(!loaded.dynamic(dynamic, 2));
+        assert_eq!(loaded.color(frames[3], 1), [3, 1, 7]);
+        assert_eq!(
+            loaded.feed(data.frames[frames[0]].feed).intrinsics,
+            data.feed(data.frames[frames[0]].feed).intrinsics
+        );
+    }
+
+    #[test]
+    fn loads_version_1() {
+        let loaded = VSlamData::load(save_v1(map().0).as_slice()).unwrap();
+        let (data, _) = map();
+        assert_eq!(describe(&loaded), describe(&data));
+        for frame in loaded.frames.values() {
+            assert_eq!(frame.motion, None);
+            assert!((0..frame.descriptor_features.len()).all(|ix| !frame.dynamic(ix)));
+        }
+        // Frames are found by their LSH under their new keys.
+        for frame in loaded.frames.values() {
+            let (_, &found) = loaded.lsh_to_frame.knn_values(&frame.lsh, 1)[0];
+            assert_eq!(loaded.frames[found].lsh, frame.lsh);
+        }
+    }
+
+    /// A map saved by version 1 of the format, which is kept to make sure that it can still be loaded.
+    #[test]
+    fn loads_version_1_fixture() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/map_v1.bin");
+        // The fixture is written from the layout of version 1 if it is missing, so that it can be checked in.
+        if !path.exists() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, save_v1(map().0)).unwrap();
+        }
+        let loaded = VSlamData::load_path(&path).unwrap();
+        assert_eq!(describe(&loaded), describe(&map().0));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut bytes = vec![];
+        VSlamData::default().save(&mut bytes).unwrap();
+        assert!(VSlamData::load(bytes.as_slice()).is_ok());
+        bytes[4] = MAP_VERSION as u8 + 1;
+        let error = VSlamData::load(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        bytes[0] = b'X';
+        let error = VSlamData::load(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,65 @@
+//! Masking of dynamic objects.
+//!
+//! Moving objects, such as people and vehicles in urban sequences, produce features which match consistently between
+//! frames, but whose landmarks don't stay in place. A [`DynamicMasker`] is run on every image before feature
+//! extraction (see [`VSlam::dynamic_masker`]) and marks the pixels which belong to dynamic objects. Keypoints on the
+//! mask are dropped, and keypoints whose descriptor region touches the mask are kept, but flagged as
+//! [`Feature::dynamic`](crate::Feature::dynamic), since good features on the border of a parked car are still useful
+//! when the car doesn't move. Landmarks whose observations are predominantly dynamic (see
+//! [`VSlamSettings::dynamic_maximum_observation_ratio`](crate::VSlamSettings)) are not robust, so they are neither
+//! used for tracking nor for optimization, and map maintenance splits them.
+//!
+//! With the `onnx` feature, [`SegmentationMasker`](crate::SegmentationMasker) masks the dynamic classes of a semantic
+//! segmentation model.
+
+use crate::{LandmarkKey, ReconstructionKey, VSlam};
+use cv_core::{
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, TriangulatorObservations, WorldToCamera,
+};
+use image::{DynamicImage, GrayImage};
+use rand::Rng;
+
+/// Produces a mask of the dynamic objects in an image.
+pub trait DynamicMasker {
+    /// Returns a mask the size of the image which is nonzero on the pixels of dynamic objects.
+    ///
+    /// Returns `None` if the image couldn't be masked, in which case every feature is kept.
+    fn dynamic_mask(&self, image: &DynamicImage) -> Option<GrayImage>;
+}
+
+impl<F> DynamicMasker for F
+where
+    F: Fn(&DynamicImage) -> Option<GrayImage>,
+{
+    fn dynamic_mask(&self, image: &DynamicImage) -> Option<GrayImage> {
+        self(image)
+    }
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Whether more than `dynamic_maximum_observation_ratio` of the observations of a landmark are dynamic.
+    pub fn is_landmark_dynamic(
+        &self,
+        reconstruction: ReconstructionKey,
+        landmark: LandmarkKey,
+    ) -> bool {
+        let (dynamic, total) = self
+            .data
+            .landmark_observations(reconstruction, landmark)
+            .fold((0, 0), |(dynamic, total), (view, feature)| {
+                let is_dynamic = self.data.observation_dynamic(reconstruction, view, feature);
+                (dynamic + is_dynamic as usize, total + 1)
+            });
+        total != 0
+            && dynamic as f64 > self.settings.dynamic_maximum_observation_ratio * total as f64
+    }
+}
@@ -0,0 +1,619 @@
+//! Descriptor matching.
+//!
+//! Binary descriptors (such as those produced by AKAZE) are compared with the [`Hamming`] distance, while
+//! floating point descriptors (KAZE, SuperPoint, etc.) are compared with [`L2`] or [`Dot`]. All of these
+//! implement [`DescriptorMetric`] so that the same matching functions can be used regardless of descriptor type.
+
+mod batch;
+mod database;
+mod diagnostics;
+#[cfg(feature = "gpu")]
+mod gpu;
+#[cfg(feature = "onnx")]
+mod learned;
+mod threshold;
+
+pub use batch::*;
+pub use database::*;
+pub use diagnostics::*;
+#[cfg(feature = "gpu")]
+pub use gpu::*;
+#[cfg(feature = "onnx")]
+pub use learned::*;
+pub use threshold::*;
+
+use crate::Frame;
+use bitarray::{BitArray, Hamming};
+use cv_core::nalgebra::{Point2, Vector2};
+use space::{Knn, Metric};
+use std::collections::HashMap;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The number of lanes the float distance kernels accumulate in parallel.
+///
+/// Eight `f32` lanes fill a 256-bit register, and the compiler will vectorize the inner loops to SSE/AVX/NEON.
+const LANES: usize = 8;
+
+/// A [`Metric`] over descriptors which can also report the real distance represented by its units.
+///
+/// [`Metric::Unit`] must be an unsigned integer so that descriptors can be stored in the approximate
+/// nearest neighbor maps from the `space` ecosystem. Float metrics satisfy this by returning the bit
+/// pattern of a non-negative `f32`, which orders identically to the float itself.
+pub trait DescriptorMetric<D>: Metric<D> {
+    /// Converts a distance produced by [`Metric::distance`] into the real distance it represents.
+    fn real_distance(&self, distance: Self::Unit) -> f32;
+}
+
+impl<const B: usize> DescriptorMetric<BitArray<B>> for Hamming {
+    fn real_distance(&self, distance: u32) -> f32 {
+        distance as f32
+    }
+}
+
+/// Euclidean distance between float descriptors.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct L2;
+
+impl<const N: usize> Metric<[f32; N]> for L2 {
+    type Unit = u32;
+
+    fn distance(&self, a: &[f32; N], b: &[f32; N]) -> u32 {
+        l2_squared(a, b).sqrt().to_bits()
+    }
+}
+
+impl Metric<Vec<f32>> for L2 {
+    type Unit = u32;
+
+    fn distance(&self, a: &Vec<f32>, b: &Vec<f32>) -> u32 {
+        l2_squared(a, b).sqrt().to_bits()
+    }
+}
+
+impl<D> DescriptorMetric<D> for L2
+where
+    L2: Metric<D, Unit = u32>,
+{
+    fn real_distance(&self, distance: u32) -> f32 {
+        f32::from_bits(distance)
+    }
+}
+
+/// Angular distance `1 - a·b` between float descriptors which are already normalized.
+///
+/// This is cheaper than [`L2`] and ranks normalized descriptors identically, since `|a - b|² = 2 - 2a·b`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dot;
+
+impl<const N: usize> Metric<[f32; N]> for Dot {
+    type Unit = u32;
+
+    fn distance(&self, a: &[f32; N], b: &[f32; N]) -> u32 {
+        (1.0 - dot(a, b)).max(0.0).to_bits()
+    }
+}
+
+impl Metric<Vec<f32>> for Dot {
+    type Unit = u32;
+
+    fn distance(&self, a: &Vec<f32>, b: &Vec<f32>) -> u32 {
+        (1.0 - dot(a, b)).max(0.0).to_bits()
+    }
+}
+
+impl<D> DescriptorMetric<D> for Dot
+where
+    Dot: Metric<D, Unit = u32>,
+{
+    fn real_distance(&self, distance: u32) -> f32 {
+        f32::from_bits(distance)
+    }
+}
+
+/// Computes the squared euclidean distance between two float descriptors of equal length.
+pub fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(&a, &b)| (a - b) * (a - b))
+        .sum();
+    let mut lanes = [0.0f32; LANES];
+    for (a, b) in a_chunks.zip(b_chunks) {
+        for (lane, (&a, &b)) in lanes.iter_mut().zip(a.iter().zip(b)) {
+            *lane += (a - b) * (a - b);
+        }
+    }
+    lanes.iter().sum::<f32>() + tail
+}
+
+/// Computes the dot product between two float descriptors of equal length.
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(&a, &b)| a * b)
+        .sum();
+    let mut lanes = [0.0f32; LANES];
+    for (a, b) in a_chunks.zip(b_chunks) {
+        for (lane, (&a, &b)) in lanes.iter_mut().zip(a.iter().zip(b)) {
+            *lane += a * b;
+        }
+    }
+    lanes.iter().sum::<f32>() + tail
+}
+
+/// Finds the two nearest descriptors in `targets` to `query` by linear search.
+///
+/// Returns `(index, distance)` of the best and second best targets, or `None` when there are not at least two targets.
+fn best_two<M, D>(metric: &M, query: &D, targets: &[D]) -> Option<[(usize, M::Unit); 2]>
+where
+    M: Metric<D>,
+{
+    if targets.len() < 2 {
+        return None;
+    }
+    let mut best = (0, metric.distance(query, &targets[0]));
+    let mut second = (1, metric.distance(query, &targets[1]));
+    if second.1 < best.1 {
+        core::mem::swap(&mut best, &mut second);
+    }
+    for (ix, target) in targets.iter().enumerate().skip(2) {
+        let distance = metric.distance(query, target);
+        if distance < best.1 {
+            second = best;
+            best = (ix, distance);
+        } else if distance < second.1 {
+            second = (ix, distance);
+        }
+    }
+    Some([best, second])
+}
+
+/// Matches each descriptor in `a` to its nearest neighbor in `b` with a brute force search.
+///
+/// A match is only produced if the real distance of the best match is no more than `ratio` times the
+/// real distance of the second best match (Lowe's ratio test). A `ratio` of `1.0` accepts every match.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", name = "descriptor_matching", skip_all, fields(a = a.len(), b = b.len()))
+)]
+pub fn descriptor_matching<M, D>(metric: &M, a: &[D], b: &[D], ratio: f32) -> Vec<Option<usize>>
+where
+    M: DescriptorMetric<D>,
+{
+    a.iter()
+        .map(|query| {
+            let [(best_ix, best), (_, second)] = best_two(metric, query, b)?;
+            if metric.real_distance(best) <= ratio * metric.real_distance(second) {
+                Some(best_ix)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Matches descriptors between `a` and `b`, keeping only matches which are mutually the best in both directions.
+///
+/// See [`descriptor_matching`] for the meaning of `ratio`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", name = "symmetric_descriptor_matching", skip_all, fields(a = a.len(), b = b.len(), matches))
+)]
+pub fn symmetric_descriptor_matching<M, D>(
+    metric: &M,
+    a: &[D],
+    b: &[D],
+    ratio: f32,
+) -> Vec<[usize; 2]>
+where
+    M: DescriptorMetric<D>,
+{
+    let forward_matches = descriptor_matching(metric, a, b, ratio);
+    let reverse_matches = descriptor_matching(metric, b, a, ratio);
+    let matches = symmetric_pairs(forward_matches, &reverse_matches);
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("matches", &matches.len());
+    matches
+}
+
+/// Combines forward and reverse matches into pairs that agree with each other.
+fn symmetric_pairs(forward: Vec<Option<usize>>, reverse: &[Option<usize>]) -> Vec<[usize; 2]> {
+    forward
+        .into_iter()
+        .enumerate()
+        .filter_map(move |(aix, bix)| {
+            // First we only proceed if there was a sufficient bix match.
+            // Filter out matches which are not symmetric.
+            // Symmetric is defined as the best and sufficient match of a being b,
+            // and likewise the best and sufficient match of b being a.
+            bix.map(|bix| [aix, bix])
+                .filter(|&[aix, bix]| reverse[bix] == Some(aix))
+        })
+        .collect()
+}
+
+/// Predicts where points will be in the next frame with a constant velocity motion model.
+///
+/// `previous` and `current` are the positions of the same tracked points in the two most recent frames.
+/// The displacement between them is assumed to repeat, so the prediction is `current + (current - previous)`.
+pub fn constant_velocity_prediction(
+    previous: &[Point2<f64>],
+    current: &[Point2<f64>],
+) -> Vec<Point2<f64>> {
+    previous
+        .iter()
+        .zip(current)
+        .map(|(previous, current)| current + (current - previous))
+        .collect()
+}
+
+/// Predicts where points will be in the next frame by applying an optical flow (such as from KLT) to them.
+pub fn flow_prediction(current: &[Point2<f64>], flow: &[Vector2<f64>]) -> Vec<Point2<f64>> {
+    current.iter().zip(flow).map(|(p, f)| p + f).collect()
+}
+
+/// A uniform grid over image points that allows finding all points within a radius quickly.
+struct PointGrid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl PointGrid {
+    fn new(points: &[Point2<f64>], cell_size: f64) -> Self {
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (ix, point) in points.iter().enumerate() {
+            cells
+                .entry(Self::cell(point, cell_size))
+                .or_default()
+                .push(ix);
+        }
+        Self { cell_size, cells }
+    }
+
+    fn cell(point: &Point2<f64>, cell_size: f64) -> (i64, i64) {
+        (
+            (point.x / cell_size).floor() as i64,
+            (point.y / cell_size).floor() as i64,
+        )
+    }
+
+    /// Iterates over every point index in the cells that could contain points within `cell_size` of `point`.
+    fn candidates(&self, point: &Point2<f64>) -> impl Iterator<Item = usize> + '_ {
+        let (cx, cy) = Self::cell(point, self.cell_size);
+        // Points far outside of the image saturate the cell coordinates.
+        (cx.saturating_sub(1)..=cx.saturating_add(1))
+            .flat_map(move |x| (cy.saturating_sub(1)..=cy.saturating_add(1)).map(move |y| (x, y)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
+
+/// Matches each descriptor in `a` only against the descriptors in `b` whose points lie within `radius` pixels of
+/// the predicted position of the `a` feature in image `b`.
+///
+/// `predicted` contains the predicted position in image `b` of every feature in `a` (see [`constant_velocity_prediction`]
+/// and [`flow_prediction`]) and `b_points` contains the position of every feature in `b`. Constraining the search region
+/// cuts both the matching time and the number of wrong matches in video, where the motion between frames is small and predictable.
+///
+/// The ratio test from [`descriptor_matching`] is applied against the second best candidate within the window.
+/// If there is only a single candidate within the window it is accepted.
+///
+/// If `radius` isn't a positive finite number, the window is empty and no matches are returned. Likewise, features
+/// whose predicted position isn't finite aren't matched, and features in `b` whose position isn't finite are never
+/// within a window.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", name = "windowed_descriptor_matching", skip_all, fields(a = a.len(), b = b.len()))
+)]
+pub fn windowed_descriptor_matching<M, D>(
+    metric: &M,
+    a: &[D],
+    predicted: &[Point2<f64>],
+    b: &[D],
+    b_points: &[Point2<f64>],
+    radius: f64,
+    ratio: f32,
+) -> Vec<Option<usize>>
+where
+    M: DescriptorMetric<D>,
+{
+    assert_eq!(a.len(), predicted.len());
+    assert_eq!(b.len(), b_points.len());
+    if !(radius > 0.0 && radius.is_finite()) {
+        return vec![None; a.len()];
+    }
+    let grid = PointGrid::new(b_points, radius);
+    let radius_squared = radius * radius;
+    a.iter()
+        .zip(predicted)
+        .map(|(query, prediction)| {
+            if !(prediction.x.is_finite() && prediction.y.is_finite()) {
+                return None;
+            }
+            let mut best: Option<(usize, M::Unit)> = None;
+            let mut second: Option<M::Unit> = None;
+            for ix in grid.candidates(prediction) {
+                let distance_squared = (b_points[ix] - prediction).norm_squared();
+                if distance_squared.is_nan() || distance_squared > radius_squared {
+                    continue;
+                }
+                let distance = metric.distance(query, &b[ix]);
+                match best {
+                    Some((_, best_distance)) if distance >= best_distance => {
+                        if second.map(|second| distance < second).unwrap_or(true) {
+                            second = Some(distance);
+                        }
+                    }
+                    _ => {
+                        second = best.map(|(_, best_distance)| best_distance);
+                        best = Some((ix, distance));
+                    }
+                }
+            }
+            let (best_ix, best) = best?;
+            match second {
+                Some(second)
+                    if metric.real_distance(best) > ratio * metric.real_distance(second) =>
+                {
+                    None
+                }
+                _ => Some(best_ix),
+            }
+        })
+        .collect()
+}
+
+/// A match between a query descriptor and a target descriptor.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct DescriptorMatch {
+    /// The index of the query descriptor.
+    pub query: usize,
+    /// The index of the target descriptor.
+    pub target: usize,
+    /// The real distance between the descriptors (lower is better).
+    pub distance: f32,
+}
+
+impl DescriptorMatch {
+    /// Gets the match as a pair of indices `[query, target]`.
+    pub fn indices(&self) -> [usize; 2] {
+        [self.query, self.target]
+    }
+}
+
+/// Matches query descriptors against target descriptors.
+///
+/// This allows higher level code to be written without depending on a specific matching strategy.
+/// Users can implement this to inject their own matcher.
+///
+/// It is implemented by [`BruteForceMatcher`], [`GuidedMatcher`], and [`HnswMatcher`], and by `GpuHammingMatcher`
+/// for binary descriptors with the `gpu` feature.
+pub trait Matcher<D> {
+    /// Produces scored matches from `query` to `target`.
+    ///
+    /// Each query descriptor appears in at most one match.
+    fn match_descriptors(&self, query: &[D], target: &[D]) -> Vec<DescriptorMatch>;
+
+    /// Prepares `target` to be matched against many queries, such as by building a search index over it once.
+    ///
+    /// The default borrows the target and matches every query with [`Matcher::match_descriptors`]. Matchers which
+    /// index the target, such as [`HnswMatcher`], build the index here so that it is shared by all of the queries.
+    fn prepare_target<'a>(&'a self, target: &'a [D]) -> Box<dyn MatchTarget<D> + 'a>
+    where
+        Self: Sized,
+    {
+        Box::new(BorrowedTarget {
+            matcher: self,
+            target,
+        })
+    }
+}
+
+/// Target descriptors prepared by [`Matcher::prepare_target`].
+pub trait MatchTarget<D> {
+    /// Produces scored matches from `query` to the target, the same as [`Matcher::match_descriptors`].
+    fn match_query(&self, query: &[D]) -> Vec<DescriptorMatch>;
+}
+
+/// A target which is matched with [`Matcher::match_descriptors`] as is.
+struct BorrowedTarget<'a, M, D> {
+    matcher: &'a M,
+    target: &'a [D],
+}
+
+impl<'a, M, D> MatchTarget<D> for BorrowedTarget<'a, M, D>
+where
+    M: Matcher<D>,
+{
+    fn match_query(&self, query: &[D]) -> Vec<DescriptorMatch> {
+        self.matcher.match_descriptors(query, self.target)
+    }
+}
+
+/// Computes the scored matches from pairs of `[query, target]` indices.
+fn scored_matches<M, D>(
+    metric: &M,
+    query: &[D],
+    target: &[D],
+    matches: impl IntoIterator<Item = [usize; 2]>,
+) -> Vec<DescriptorMatch>
+where
+    M: DescriptorMetric<D>,
+{
+    matches
+        .into_iter()
+        .map(|[query_ix, target_ix]| DescriptorMatch {
+            query: query_ix,
+            target: target_ix,
+            distance: metric.real_distance(metric.distance(&query[query_ix], &target[target_ix])),
+        })
+        .collect()
+}
+
+/// Matches descriptors by exhaustively comparing every query to every target.
+#[derive(Copy, Clone, Debug)]
+pub struct BruteForceMatcher<M> {
+    /// The metric used to compare descriptors.
+    pub metric: M,
+    /// The ratio test threshold (see [`descriptor_matching`]).
+    pub ratio: f32,
+    /// Only keep matches which are the best in both directions.
+    pub symmetric: bool,
+}
+
+impl<M> BruteForceMatcher<M> {
+    pub fn new(metric: M) -> Self {
+        Self {
+            metric,
+            ratio: 0.8,
+            symmetric: true,
+        }
+    }
+
+    #[must_use]
+    pub fn ratio(self, ratio: f32) -> Self {
+        Self { ratio, ..self }
+    }
+
+    #[must_use]
+    pub fn symmetric(self, symmetric: bool) -> Self {
+        Self { symmetric, ..self }
+    }
+}
+
+impl<M, D> Matcher<D> for BruteForceMatcher<M>
+where
+    M: DescriptorMetric<D>,
+{
+    fn match_descriptors(&self, query: &[D], target: &[D]) -> Vec<DescriptorMatch> {
+        if self.symmetric {
+            let matches = symmetric_descriptor_matching(&self.metric, query, target, self.ratio);
+            scored_matches(&self.metric, query, target, matches)
+        } else {
+            let matches = descriptor_matching(&self.metric, query, target, self.ratio);
+            let matches = matches
+                .into_iter()
+                .enumerate()
+                .filter_map(|(query_ix, target_ix)| Some([query_ix, target_ix?]));
+            scored_matches(&self.metric, query, target, matches)
+        }
+    }
+}
+
+/// Matches descriptors only within a window around the predicted position of each query feature.
+///
+/// See [`windowed_descriptor_matching`] for details.
+#[derive(Copy, Clone, Debug)]
+pub struct GuidedMatcher<'a, M> {
+    /// The metric used to compare descriptors.
+    pub metric: M,
+    /// The predicted position of every query feature in the target image.
+    pub predicted: &'a [Point2<f64>],
+    /// The position of every target feature.
+    pub target_points: &'a [Point2<f64>],
+    /// The search radius around the prediction in pixels.
+    ///
+    /// No matches are found unless this is positive and finite.
+    pub radius: f64,
+    /// The ratio test threshold (see [`descriptor_matching`]).
+    pub ratio: f32,
+}
+
+impl<'a, M> GuidedMatcher<'a, M> {
+    pub fn new(metric: M, predicted: &'a [Point2<f64>], target_points: &'a [Point2<f64>]) -> Self {
+        Self {
+            metric,
+            predicted,
+            target_points,
+            radius: 20.0,
+            ratio: 0.8,
+        }
+    }
+
+    #[must_use]
+    pub fn radius(self, radius: f64) -> Self {
+        Self { radius, ..self }
+    }
+
+    #[must_use]
+    pub fn ratio(self, ratio: f32) -> Self {
+        Self { ratio, ..self }
+    }
+}
+
+impl<'a, M, D> Matcher<D> for GuidedMatcher<'a, M>
+where
+    M: DescriptorMetric<D>,
+{
+    fn match_descriptors(&self, query: &[D], target: &[D]) -> Vec<DescriptorMatch> {
+        let matches = windowed_descriptor_matching(
+            &self.metric,
+            query,
+            self.predicted,
+            target,
+            self.target_points,
+            self.radius,
+            self.ratio,
+        );
+        let matches = matches
+            .into_iter()
+            .enumerate()
+            .filter_map(|(query_ix, target_ix)| Some([query_ix, target_ix?]));
+        scored_matches(&self.metric, query, target, matches)
+    }
+}
+
+/// Matches the features of two frames using the frame's descriptor maps.
+///
+/// A match is only produced if the best match is closer than the second best match by `better_by` bits.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", name = "matching", skip_all, fields(a = a_frame.descriptor_features.len(), b = b_frame.descriptor_features.len()))
+)]
+pub fn matching(a_frame: &Frame, b_frame: &Frame, better_by: u32) -> Vec<Option<usize>> {
+    // If there arent at least 2 features in both frames, we produce no matches.
+    if a_frame.descriptor_features.len() < 2 || b_frame.descriptor_features.len() < 2 {
+        return vec![];
+    }
+    (0..a_frame.descriptor_features.len())
+        .map(|a_feature| {
+            let knn = b_frame
+                .descriptor_features
+                .knn(a_frame.descriptor(a_feature), 2);
+            if knn[0].distance + better_by <= knn[1].distance {
+                Some(knn[0].index)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Matches the features of two frames, keeping only matches which are mutually the best in both directions.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", name = "symmetric_matching", skip_all, fields(a = a.descriptor_features.len(), b = b.descriptor_features.len(), matches))
+)]
+pub fn symmetric_matching(a: &Frame, b: &Frame, better_by: u32) -> Vec<[usize; 2]> {
+    // The best match for each feature in frame a to frame b's features.
+    let forward_matches = matching(a, b, better_by);
+    // The best match for each feature in frame b to frame a's features.
+    let reverse_matches = matching(b, a, better_by);
+    let matches = symmetric_pairs(forward_matches, &reverse_matches);
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("matches", &matches.len());
+    matches
+}
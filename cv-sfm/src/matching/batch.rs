@@ -0,0 +1,135 @@
+use super::{DescriptorMatch, Matcher};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The matches between every pair of images in a set.
+///
+/// Only pairs `(a, b)` with `a < b` are stored. Use [`MatchMatrix::get`] to retrieve the matches
+/// between any two images regardless of order.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct MatchMatrix {
+    /// The number of images in the set.
+    pub num_images: usize,
+    /// The matches from image `a` (query) to image `b` (target) for `a < b`.
+    pub pairs: HashMap<(usize, usize), Vec<DescriptorMatch>>,
+}
+
+impl MatchMatrix {
+    /// Gets the matches between images `a` and `b` as `[a_feature, b_feature]` pairs.
+    ///
+    /// Returns `None` if the pair was not matched.
+    pub fn get(&self, a: usize, b: usize) -> Option<Vec<[usize; 2]>> {
+        if a < b {
+            self.pairs
+                .get(&(a, b))
+                .map(|matches| matches.iter().map(DescriptorMatch::indices).collect())
+        } else {
+            self.pairs
+                .get(&(b, a))
+                .map(|matches| matches.iter().map(|m| [m.target, m.query]).collect())
+        }
+    }
+
+    /// The number of matches between every pair of images as a dense row-major `num_images x num_images` matrix.
+    pub fn counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.num_images * self.num_images];
+        for (&(a, b), matches) in &self.pairs {
+            counts[a * self.num_images + b] = matches.len();
+            counts[b * self.num_images + a] = matches.len();
+        }
+        counts
+    }
+
+    /// Iterates over the image pairs with at least `min_matches` matches.
+    pub fn connected_pairs(&self, min_matches: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.pairs
+            .iter()
+            .filter(move |(_, matches)| matches.len() >= min_matches)
+            .map(|(&pair, _)| pair)
+    }
+}
+
+/// Matches one query image against many target images in parallel.
+///
+/// The descriptors of every image are borrowed and shared between all of the worker threads.
+/// The output contains the matches for each target in the same order as `targets`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", name = "match_one_to_many", skip_all, fields(query = query.len(), targets = targets.len()))
+)]
+pub fn match_one_to_many<M, D>(
+    matcher: &M,
+    query: &[D],
+    targets: &[&[D]],
+) -> Vec<Vec<DescriptorMatch>>
+where
+    M: Matcher<D> + Sync,
+    D: Sync,
+{
+    targets
+        .par_iter()
+        .map(|target| matcher.match_descriptors(query, target))
+        .collect()
+}
+
+/// Matches every pair of images in parallel.
+///
+/// Exhaustive pair matching is the dominant cost in small SfM datasets. Every image is prepared as a target with
+/// [`Matcher::prepare_target`] once, such as by building its search index, and the images before it are matched
+/// against it. The targets are independent, so they scale with the number of available threads.
+pub fn match_all_pairs<M, D>(matcher: &M, images: &[&[D]]) -> MatchMatrix
+where
+    M: Matcher<D> + Sync,
+    D: Sync,
+{
+    let pairs: Vec<(usize, usize)> = (0..images.len())
+        .flat_map(|a| (a + 1..images.len()).map(move |b| (a, b)))
+        .collect();
+    match_pairs(matcher, images, &pairs)
+}
+
+/// Matches only the given pairs of images in parallel, such as those selected by image retrieval.
+///
+/// The later image of each pair is the target, and each target is prepared once for all of its pairs.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", name = "match_pairs", skip_all, fields(images = images.len(), pairs = pairs.len()))
+)]
+pub fn match_pairs<M, D>(matcher: &M, images: &[&[D]], pairs: &[(usize, usize)]) -> MatchMatrix
+where
+    M: Matcher<D> + Sync,
+    D: Sync,
+{
+    let mut queries: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for &(a, b) in pairs {
+        if a != b {
+            queries.entry(a.max(b)).or_default().push(a.min(b));
+        }
+    }
+    let targets: Vec<(usize, Vec<usize>)> = queries
+        .into_iter()
+        .map(|(target, mut queries)| {
+            queries.sort_unstable();
+            queries.dedup();
+            (target, queries)
+        })
+        .collect();
+    let pairs = targets
+        .par_iter()
+        .flat_map(|(b, queries)| {
+            let target = matcher.prepare_target(images[*b]);
+            queries
+                .iter()
+                .map(|&a| ((a, *b), target.match_query(images[a])))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    MatchMatrix {
+        num_images: images.len(),
+        pairs,
+    }
+}
@@ -0,0 +1,282 @@
+use super::{DescriptorMatch, DescriptorMetric, MatchTarget, Matcher};
+use bitarray::{BitArray, Hamming};
+use hnsw::{Hnsw, Searcher};
+use rand_pcg::Pcg64;
+use space::Neighbor;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde-serialize")]
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// The magic bytes at the beginning of a saved [`DescriptorDatabase`].
+#[cfg(feature = "serde-serialize")]
+const DATABASE_MAGIC: [u8; 4] = *b"CVDD";
+/// The version of the saved [`DescriptorDatabase`] format.
+#[cfg(feature = "serde-serialize")]
+const DATABASE_VERSION: u32 = 1;
+
+/// Identifies a feature in an image which has been inserted into a [`DescriptorDatabase`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct FeatureId {
+    /// The image the feature came from.
+    pub image: u64,
+    /// The index of the feature in the image.
+    pub feature: u32,
+}
+
+/// A result from [`DescriptorDatabase::knn`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DatabaseNeighbor {
+    /// The feature that was found.
+    pub id: FeatureId,
+    /// The Hamming distance from the query to the feature.
+    pub distance: u32,
+}
+
+/// A persistent database of binary descriptors backed by an HNSW index, for large-scale retrieval and
+/// incremental mapping sessions.
+///
+/// Each descriptor is stored with the [`FeatureId`] of the image and feature it came from.
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct DescriptorDatabase {
+    hnsw: Hnsw<Hamming, BitArray<64>, Pcg64, 12, 24>,
+    ids: Vec<FeatureId>,
+    /// The size of the dynamic candidate list used when searching.
+    ///
+    /// Higher values make searches slower and more accurate.
+    pub ef: usize,
+}
+
+impl Default for DescriptorDatabase {
+    fn default() -> Self {
+        Self {
+            hnsw: Hnsw::new(Hamming),
+            ids: vec![],
+            ef: 64,
+        }
+    }
+}
+
+impl DescriptorDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of descriptors in the database.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns `true` if the database contains no descriptors.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Inserts a descriptor into the database.
+    pub fn insert(&mut self, id: FeatureId, descriptor: BitArray<64>) {
+        let mut searcher = Searcher::default();
+        self.hnsw.insert(descriptor, &mut searcher);
+        self.ids.push(id);
+    }
+
+    /// Inserts all of the descriptors from an image into the database.
+    ///
+    /// The feature index of each descriptor is its position in `descriptors`.
+    pub fn insert_image(&mut self, image: u64, descriptors: &[BitArray<64>]) {
+        let mut searcher = Searcher::default();
+        for (feature, &descriptor) in descriptors.iter().enumerate() {
+            self.hnsw.insert(descriptor, &mut searcher);
+            self.ids.push(FeatureId {
+                image,
+                feature: feature as u32,
+            });
+        }
+    }
+
+    /// Finds the (approximate) `k` nearest descriptors in the database to `query`, closest first.
+    pub fn knn(&self, query: &BitArray<64>, k: usize) -> Vec<DatabaseNeighbor> {
+        let mut searcher = Searcher::default();
+        let mut neighbors = vec![
+            Neighbor {
+                index: !0,
+                distance: !0,
+            };
+            k
+        ];
+        self.hnsw
+            .nearest(query, self.ef.max(k), &mut searcher, &mut neighbors)
+            .iter()
+            .map(|neighbor| DatabaseNeighbor {
+                id: self.ids[neighbor.index],
+                distance: neighbor.distance,
+            })
+            .collect()
+    }
+
+    /// Gets the descriptor stored at an insertion index.
+    pub fn descriptor(&self, ix: usize) -> &BitArray<64> {
+        self.hnsw.feature(ix)
+    }
+
+    /// Gets the [`FeatureId`] stored at an insertion index.
+    pub fn id(&self, ix: usize) -> FeatureId {
+        self.ids[ix]
+    }
+
+    /// Saves the database to a writer in a versioned binary format.
+    #[cfg(feature = "serde-serialize")]
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&DATABASE_MAGIC)?;
+        writer.write_all(&DATABASE_VERSION.to_le_bytes())?;
+        bincode::serialize_into(writer, self).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+
+    /// Loads a database saved with [`DescriptorDatabase::save`].
+    #[cfg(feature = "serde-serialize")]
+    pub fn load(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != DATABASE_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a descriptor database",
+            ));
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != DATABASE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported descriptor database version {}", version),
+            ));
+        }
+        bincode::deserialize_from(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Saves the database to a file.
+    #[cfg(feature = "serde-serialize")]
+    pub fn save_path(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads the database from a file.
+    #[cfg(feature = "serde-serialize")]
+    pub fn load_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::load(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Matches descriptors by building an HNSW index over the target descriptors.
+///
+/// This is approximate, but scales much better than [`super::BruteForceMatcher`] to large target sets.
+#[derive(Copy, Clone, Debug)]
+pub struct HnswMatcher<M> {
+    /// The metric used to compare descriptors.
+    pub metric: M,
+    /// The size of the dynamic candidate list used when searching.
+    pub ef: usize,
+    /// The ratio test threshold (see [`super::descriptor_matching`]).
+    pub ratio: f32,
+}
+
+impl<M> HnswMatcher<M> {
+    pub fn new(metric: M) -> Self {
+        Self {
+            metric,
+            ef: 32,
+            ratio: 0.8,
+        }
+    }
+
+    #[must_use]
+    pub fn ef(self, ef: usize) -> Self {
+        Self { ef, ..self }
+    }
+
+    #[must_use]
+    pub fn ratio(self, ratio: f32) -> Self {
+        Self { ratio, ..self }
+    }
+}
+
+impl<M, D> Matcher<D> for HnswMatcher<M>
+where
+    M: DescriptorMetric<D> + Clone,
+    D: Clone,
+{
+    fn match_descriptors(&self, query: &[D], target: &[D]) -> Vec<DescriptorMatch> {
+        self.prepare_target(target).match_query(query)
+    }
+
+    /// Builds the HNSW index over the target.
+    fn prepare_target<'a>(&'a self, target: &'a [D]) -> Box<dyn MatchTarget<D> + 'a> {
+        let mut searcher = Searcher::default();
+        let mut hnsw: Hnsw<M, D, Pcg64, 12, 24> = Hnsw::new(self.metric.clone());
+        for descriptor in target {
+            hnsw.insert(descriptor.clone(), &mut searcher);
+        }
+        Box::new(HnswTarget {
+            matcher: self,
+            target,
+            hnsw,
+        })
+    }
+}
+
+/// The target of an [`HnswMatcher`] with the HNSW index over its descriptors.
+struct HnswTarget<'a, M, D> {
+    matcher: &'a HnswMatcher<M>,
+    target: &'a [D],
+    hnsw: Hnsw<M, D, Pcg64, 12, 24>,
+}
+
+impl<'a, M, D> MatchTarget<D> for HnswTarget<'a, M, D>
+where
+    M: DescriptorMetric<D>,
+{
+    fn match_query(&self, query: &[D]) -> Vec<DescriptorMatch> {
+        if self.target.len() < 2 {
+            return vec![];
+        }
+        let metric = &self.matcher.metric;
+        let mut searcher = Searcher::default();
+        query
+            .iter()
+            .enumerate()
+            .filter_map(|(query_ix, descriptor)| {
+                // The initial neighbors are only placeholders which are overwritten by the search.
+                let placeholder = Neighbor {
+                    index: !0,
+                    distance: metric.distance(descriptor, &self.target[0]),
+                };
+                let mut neighbors = vec![placeholder; 2];
+                let found =
+                    self.hnsw
+                        .nearest(descriptor, self.matcher.ef, &mut searcher, &mut neighbors);
+                if found.len() < 2 {
+                    return None;
+                }
+                let best = metric.real_distance(found[0].distance);
+                let second = metric.real_distance(found[1].distance);
+                if best <= self.matcher.ratio * second {
+                    Some(DescriptorMatch {
+                        query: query_ix,
+                        target: found[0].index,
+                        distance: best,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,119 @@
+use super::{best_two, DescriptorMatch, DescriptorMetric, DistanceHistogram};
+
+/// The number of bins used for the ratio histogram in [`Distinctiveness`].
+const RATIO_BINS: usize = 20;
+
+/// Statistics describing how distinctive a set of descriptors are against a target set.
+///
+/// The distinctiveness of a descriptor is measured by the ratio of the distance to its nearest neighbor and its
+/// second nearest neighbor. Distinctive descriptors have a low ratio, while ambiguous descriptors (from repetitive
+/// texture or a poor descriptor) have a ratio close to `1.0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distinctiveness {
+    /// A histogram of nearest to second nearest distance ratios from `0.0` to `1.0`.
+    pub ratio_histogram: DistanceHistogram,
+    /// The mean nearest to second nearest distance ratio.
+    pub mean_ratio: f32,
+    /// The median nearest to second nearest distance ratio (to the resolution of the histogram).
+    pub median_ratio: f32,
+}
+
+impl Distinctiveness {
+    /// Computes the distinctiveness of `query` descriptors against `target` descriptors.
+    ///
+    /// Returns `None` if there are less than two targets.
+    pub fn compute<M, D>(metric: &M, query: &[D], target: &[D]) -> Option<Self>
+    where
+        M: DescriptorMetric<D>,
+    {
+        let mut ratio_histogram = DistanceHistogram::new(1.0 / RATIO_BINS as f32, RATIO_BINS);
+        let mut ratio_sum = 0.0;
+        for query in query {
+            let [(_, best), (_, second)] = best_two(metric, query, target)?;
+            let second = metric.real_distance(second);
+            let ratio = if second > 0.0 {
+                metric.real_distance(best) / second
+            } else {
+                1.0
+            };
+            ratio_histogram.add(ratio);
+            ratio_sum += ratio;
+        }
+        Some(Self {
+            mean_ratio: ratio_sum / query.len().max(1) as f32,
+            median_ratio: ratio_histogram.percentile(0.5),
+            ratio_histogram,
+        })
+    }
+}
+
+/// Diagnostics of matching between two images, used to quantitatively compare detector and descriptor configurations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchDiagnostics {
+    /// The number of query descriptors that were matched.
+    pub num_queries: usize,
+    /// The number of matches produced.
+    pub num_matches: usize,
+    /// A histogram of the distances of the matches.
+    pub distance_histogram: DistanceHistogram,
+    /// The mean distance of the matches.
+    pub mean_distance: f32,
+    /// The median distance of the matches (to the resolution of the histogram).
+    pub median_distance: f32,
+    /// The fraction of matches which survived geometric verification, if it was performed.
+    pub inlier_ratio: Option<f32>,
+    /// A histogram of the distances of the matches which survived geometric verification, if it was performed.
+    pub inlier_distance_histogram: Option<DistanceHistogram>,
+}
+
+impl MatchDiagnostics {
+    /// Computes diagnostics from the matches produced for `num_queries` query descriptors.
+    ///
+    /// For binary descriptors, a `bin_width` of `1.0` with `num_bins` equal to the number of bits gives a
+    /// histogram with a bin per Hamming distance.
+    pub fn new(
+        matches: &[DescriptorMatch],
+        num_queries: usize,
+        bin_width: f32,
+        num_bins: usize,
+    ) -> Self {
+        let distance_histogram = DistanceHistogram::from_distances(
+            bin_width,
+            num_bins,
+            matches.iter().map(|m| m.distance),
+        );
+        let mean_distance =
+            matches.iter().map(|m| m.distance).sum::<f32>() / matches.len().max(1) as f32;
+        Self {
+            num_queries,
+            num_matches: matches.len(),
+            median_distance: distance_histogram.percentile(0.5),
+            mean_distance,
+            distance_histogram,
+            inlier_ratio: None,
+            inlier_distance_histogram: None,
+        }
+    }
+
+    /// Adds the result of geometric verification, where `inliers` are indices into `matches`.
+    ///
+    /// `matches` must be the same matches the diagnostics were created with.
+    #[must_use]
+    pub fn verified(self, matches: &[DescriptorMatch], inliers: &[usize]) -> Self {
+        let inlier_distance_histogram = DistanceHistogram::from_distances(
+            self.distance_histogram.bin_width,
+            self.distance_histogram.bins.len(),
+            inliers.iter().map(|&ix| matches[ix].distance),
+        );
+        Self {
+            inlier_ratio: Some(inliers.len() as f32 / matches.len().max(1) as f32),
+            inlier_distance_histogram: Some(inlier_distance_histogram),
+            ..self
+        }
+    }
+
+    /// The fraction of queries that produced a match.
+    pub fn match_ratio(&self) -> f32 {
+        self.num_matches as f32 / self.num_queries.max(1) as f32
+    }
+}
@@ -0,0 +1,225 @@
+use super::{scored_matches, symmetric_pairs, DescriptorMatch, Matcher};
+use bitarray::{BitArray, Hamming};
+use bytemuck::cast_slice;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// Invocations run per workgroup, which must match the shader.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Matches binary descriptors by exhaustively comparing every query to every target on the GPU with [`wgpu`].
+///
+/// Each query is compared to every target in its own invocation of a compute shader, which finds the same two
+/// nearest targets as a [`BruteForceMatcher`](super::BruteForceMatcher) with the [`Hamming`] metric, so the matches
+/// are the same as on the CPU. The brute force search is quadratic in the number of features, which the GPU makes
+/// practical for thousands of features per image.
+pub struct GpuHammingMatcher {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    /// The ratio test threshold (see [`descriptor_matching`](super::descriptor_matching)).
+    pub ratio: f32,
+    /// Only keep matches which are the best in both directions.
+    pub symmetric: bool,
+}
+
+impl GpuHammingMatcher {
+    /// Creates a matcher on the default adapter.
+    ///
+    /// Returns `None` if there is no adapter or device available.
+    pub async fn new() -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .await
+            .ok()?;
+        Some(Self::from_device(device, queue))
+    }
+
+    /// Creates a matcher which shares an existing device, such as the device of a renderer.
+    pub fn from_device(device: wgpu::Device, queue: wgpu::Queue) -> Self {
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("hamming"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("hamming.wgsl"))),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("hamming"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+        });
+        Self {
+            device,
+            queue,
+            pipeline,
+            ratio: 0.8,
+            symmetric: true,
+        }
+    }
+
+    #[must_use]
+    pub fn ratio(self, ratio: f32) -> Self {
+        Self { ratio, ..self }
+    }
+
+    #[must_use]
+    pub fn symmetric(self, symmetric: bool) -> Self {
+        Self { symmetric, ..self }
+    }
+
+    /// Matches each query to its nearest target, the same as [`descriptor_matching`](super::descriptor_matching).
+    pub async fn descriptor_matching<const B: usize>(
+        &self,
+        query: &[BitArray<B>],
+        target: &[BitArray<B>],
+    ) -> Vec<Option<usize>> {
+        if target.len() < 2 {
+            return vec![None; query.len()];
+        }
+        self.nearest_two(query, target)
+            .await
+            .into_iter()
+            .map(|[best_ix, best, second]| {
+                Some(best_ix as usize).filter(|_| best as f32 <= self.ratio * second as f32)
+            })
+            .collect()
+    }
+
+    /// Runs the shader and reads back the index and distance of the best target followed by the distance of the
+    /// second best target of every query, of which there must be at least two.
+    async fn nearest_two<const B: usize>(
+        &self,
+        query: &[BitArray<B>],
+        target: &[BitArray<B>],
+    ) -> Vec<[u32; 3]> {
+        if query.is_empty() {
+            return vec![];
+        }
+        let words = (B + 3) / 4;
+        let sizes = [query.len() as u32, target.len() as u32, words as u32, 0];
+        let sizes = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("sizes"),
+                contents: cast_slice(&sizes),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let queries = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("queries"),
+                contents: cast_slice(&pack(query)),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let targets = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("targets"),
+                contents: cast_slice(&pack(target)),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let size = (query.len() * 4 * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+        let nearest = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("nearest"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("hamming"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: sizes.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: queries.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: targets.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: nearest.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch(
+                (query.len() as u32 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                1,
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&nearest, 0, &staging, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        if mapping.await.is_err() {
+            // A best distance above the second best fails the ratio test for every query.
+            return vec![[u32::MAX, u32::MAX, 0]; query.len()];
+        }
+        let nearest = cast_slice::<u8, u32>(&slice.get_mapped_range())
+            .chunks_exact(4)
+            .map(|n| [n[0], n[1], n[2]])
+            .collect();
+        staging.unmap();
+        nearest
+    }
+}
+
+/// Packs descriptors into little endian words, padding each descriptor with zeros to a whole number of words.
+fn pack<const B: usize>(descriptors: &[BitArray<B>]) -> Vec<u32> {
+    let mut words = Vec::with_capacity(descriptors.len() * (B + 3) / 4);
+    for descriptor in descriptors {
+        for chunk in descriptor.bytes.chunks(4) {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            words.push(u32::from_le_bytes(word));
+        }
+    }
+    words
+}
+
+impl<const B: usize> Matcher<BitArray<B>> for GpuHammingMatcher {
+    /// Blocks until the GPU has matched the descriptors.
+    fn match_descriptors(
+        &self,
+        query: &[BitArray<B>],
+        target: &[BitArray<B>],
+    ) -> Vec<DescriptorMatch> {
+        let forward_matches = pollster::block_on(self.descriptor_matching(query, target));
+        if self.symmetric {
+            let reverse_matches = pollster::block_on(self.descriptor_matching(target, query));
+            let matches = symmetric_pairs(forward_matches, &reverse_matches);
+            scored_matches(&Hamming, query, target, matches)
+        } else {
+            let matches = forward_matches
+                .into_iter()
+                .enumerate()
+                .filter_map(|(query_ix, target_ix)| Some([query_ix, target_ix?]));
+            scored_matches(&Hamming, query, target, matches)
+        }
+    }
+}
@@ -0,0 +1,75 @@
+// Finds the two nearest targets of one query descriptor per invocation by their Hamming distance.
+//
+// Each descriptor is packed into `words` 32-bit words. The output of each query is the index and distance of the best
+// target followed by the distance of the second best target. Ties go to the lower index, as in the CPU matchers.
+
+[[block]]
+struct Sizes {
+    queries: u32;
+    targets: u32;
+    words: u32;
+    padding: u32;
+};
+
+[[block]]
+struct Descriptors {
+    data: array<u32>;
+};
+
+[[block]]
+struct Nearest {
+    data: array<vec4<u32>>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> sizes: Sizes;
+
+[[group(0), binding(1)]]
+var<storage, read> queries: Descriptors;
+
+[[group(0), binding(2)]]
+var<storage, read> targets: Descriptors;
+
+[[group(0), binding(3)]]
+var<storage, read_write> nearest: Nearest;
+
+fn distance(query: u32, candidate: u32) -> u32 {
+    var total = 0u;
+    for (var w = 0u; w < sizes.words; w = w + 1u) {
+        let bits = queries.data[query * sizes.words + w] ^ targets.data[candidate * sizes.words + w];
+        total = total + countOneBits(bits);
+    }
+    return total;
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let query = id.x;
+    if (query >= sizes.queries) {
+        return;
+    }
+
+    // There are always at least two targets.
+    var best_ix = 0u;
+    var best = distance(query, 0u);
+    var second = distance(query, 1u);
+    if (second < best) {
+        best_ix = 1u;
+        let swap = best;
+        best = second;
+        second = swap;
+    }
+    for (var candidate = 2u; candidate < sizes.targets; candidate = candidate + 1u) {
+        let d = distance(query, candidate);
+        if (d < best) {
+            second = best;
+            best = d;
+            best_ix = candidate;
+        } else {
+            if (d < second) {
+                second = d;
+            }
+        }
+    }
+    nearest.data[query] = vec4<u32>(best_ix, best, second, 0u);
+}
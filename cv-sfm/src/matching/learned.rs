@@ -0,0 +1,175 @@
+use cv_core::{
+    nalgebra::{Point2, UnitVector3},
+    FeatureMatch,
+};
+use std::{convert::TryFrom, path::Path};
+use tract_onnx::prelude::*;
+
+/// A match produced by a learned matcher.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LearnedMatch {
+    /// The indices `[a, b]` of the matched features in each image.
+    pub indices: [usize; 2],
+    /// The confidence of the match from `0.0` to `1.0` (higher is better).
+    pub confidence: f32,
+}
+
+impl LearnedMatch {
+    /// Converts the match into a [`FeatureMatch`] given the bearings of the features in each image.
+    pub fn feature_match(
+        &self,
+        a_bearings: &[UnitVector3<f64>],
+        b_bearings: &[UnitVector3<f64>],
+    ) -> FeatureMatch {
+        let [a, b] = self.indices;
+        FeatureMatch(a_bearings[a], b_bearings[b])
+    }
+}
+
+/// The features of a single image given to a [`LearnedMatcher`].
+#[derive(Debug)]
+pub struct LearnedMatcherInput<'a, D> {
+    /// The image `(width, height)` in pixels, used to normalize keypoints the way the model was trained.
+    pub image_size: (u32, u32),
+    /// The keypoint positions in pixels.
+    pub keypoints: &'a [Point2<f32>],
+    /// The float descriptor of each keypoint.
+    pub descriptors: &'a [D],
+}
+
+/// Runs a LightGlue/SuperGlue style ONNX model to match the features of two images.
+///
+/// The model is expected to follow the layout of the common LightGlue ONNX exports:
+/// * inputs `kpts0: [1, N, 2]`, `kpts1: [1, M, 2]`, `desc0: [1, N, C]`, `desc1: [1, M, C]` (all `f32`)
+/// * outputs `matches0: [K, 2]` (`i64` indices) and `mscores0: [K]` (`f32` confidences)
+///
+/// Keypoints are normalized to `[-1, 1]` by the larger image dimension before being passed to the model.
+pub struct LearnedMatcher {
+    model: InferenceModel,
+    /// Matches with a confidence below this are discarded.
+    pub min_confidence: f32,
+}
+
+impl LearnedMatcher {
+    /// Loads an ONNX matching model from disk.
+    pub fn from_path(path: impl AsRef<Path>) -> TractResult<Self> {
+        Ok(Self {
+            model: tract_onnx::onnx().model_for_path(path)?,
+            min_confidence: 0.1,
+        })
+    }
+
+    #[must_use]
+    pub fn min_confidence(self, min_confidence: f32) -> Self {
+        Self {
+            min_confidence,
+            ..self
+        }
+    }
+
+    /// Matches the features of image `a` to image `b`.
+    pub fn match_features<D: AsRef<[f32]>>(
+        &self,
+        a: LearnedMatcherInput<'_, D>,
+        b: LearnedMatcherInput<'_, D>,
+    ) -> TractResult<Vec<LearnedMatch>> {
+        let channels = a
+            .descriptors
+            .first()
+            .or_else(|| b.descriptors.first())
+            .map(|d| d.as_ref().len())
+            .unwrap_or(0);
+        if a.keypoints.is_empty() || b.keypoints.is_empty() {
+            return Ok(vec![]);
+        }
+        let inputs = tvec!(
+            keypoint_tensor(a.keypoints, a.image_size)?,
+            keypoint_tensor(b.keypoints, b.image_size)?,
+            descriptor_tensor(a.descriptors, channels)?,
+            descriptor_tensor(b.descriptors, channels)?,
+        );
+        // The number of keypoints varies per image, so the model is specialized to the input shapes every run.
+        let mut model = self.model.clone();
+        for (ix, input) in inputs.iter().enumerate() {
+            model = model.with_input_fact(
+                ix,
+                InferenceFact::dt_shape(f32::datum_type(), input.shape()),
+            )?;
+        }
+        let outputs = model.into_optimized()?.into_runnable()?.run(inputs)?;
+        decode_matches(
+            &outputs[0],
+            &outputs[1],
+            [a.keypoints.len(), b.keypoints.len()],
+            self.min_confidence,
+        )
+    }
+}
+
+/// Converts the `matches0` and `mscores0` outputs of a model into matches with at least `min_confidence`.
+///
+/// Models mark unmatched features with negative indices such as `-1`, so pairs with an index which is negative or
+/// not less than the number of keypoints `counts` of its image are dropped.
+fn decode_matches(
+    matches: &Tensor,
+    scores: &Tensor,
+    counts: [usize; 2],
+    min_confidence: f32,
+) -> TractResult<Vec<LearnedMatch>> {
+    let matches = matches.to_array_view::<i64>()?;
+    let scores = scores.to_array_view::<f32>()?;
+    let index =
+        |index: i64, count: usize| usize::try_from(index).ok().filter(|&index| index < count);
+    Ok(matches
+        .outer_iter()
+        .zip(scores.iter())
+        .filter(|&(_, &confidence)| confidence >= min_confidence)
+        .filter_map(|(indices, &confidence)| {
+            Some(LearnedMatch {
+                indices: [index(indices[0], counts[0])?, index(indices[1], counts[1])?],
+                confidence,
+            })
+        })
+        .collect())
+}
+
+fn keypoint_tensor(keypoints: &[Point2<f32>], (width, height): (u32, u32)) -> TractResult<Tensor> {
+    let center = Point2::new(width as f32 / 2.0, height as f32 / 2.0);
+    let scale = width.max(height) as f32 / 2.0;
+    let data = keypoints
+        .iter()
+        .flat_map(|p| {
+            let normalized = (p - center) / scale;
+            [normalized.x, normalized.y]
+        })
+        .collect();
+    Ok(tract_ndarray::Array3::from_shape_vec((1, keypoints.len(), 2), data)?.into())
+}
+
+fn descriptor_tensor<D: AsRef<[f32]>>(descriptors: &[D], channels: usize) -> TractResult<Tensor> {
+    let data = descriptors
+        .iter()
+        .flat_map(|d| d.as_ref().iter().copied())
+        .collect();
+    Ok(tract_ndarray::Array3::from_shape_vec((1, descriptors.len(), channels), data)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_matches_drops_invalid_indices() {
+        let matches: Tensor =
+            tract_ndarray::arr2(&[[0i64, 1], [-1, 0], [1, -1], [2, 0], [1, 3], [1, 2]]).into();
+        let scores: Tensor = tract_ndarray::arr1(&[0.9f32, 0.9, 0.9, 0.9, 0.9, 0.05]).into();
+        let matches = decode_matches(&matches, &scores, [2, 3], 0.1).unwrap();
+        assert_eq!(
+            matches,
+            vec![LearnedMatch {
+                indices: [0, 1],
+                confidence: 0.9,
+            }]
+        );
+    }
+}
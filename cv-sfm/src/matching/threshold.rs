@@ -0,0 +1,176 @@
+use super::{best_two, DescriptorMetric};
+
+/// A histogram of descriptor distances with fixed width bins starting at `0.0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DistanceHistogram {
+    /// The width of every bin.
+    pub bin_width: f32,
+    /// The number of distances which fell into each bin.
+    ///
+    /// Distances beyond the last bin are counted in the last bin.
+    pub bins: Vec<u64>,
+}
+
+impl DistanceHistogram {
+    /// Creates an empty histogram with `num_bins` bins of width `bin_width`.
+    pub fn new(bin_width: f32, num_bins: usize) -> Self {
+        assert!(bin_width > 0.0 && num_bins > 0);
+        Self {
+            bin_width,
+            bins: vec![0; num_bins],
+        }
+    }
+
+    /// Creates a histogram from a set of distances.
+    pub fn from_distances(
+        bin_width: f32,
+        num_bins: usize,
+        distances: impl IntoIterator<Item = f32>,
+    ) -> Self {
+        let mut histogram = Self::new(bin_width, num_bins);
+        for distance in distances {
+            histogram.add(distance);
+        }
+        histogram
+    }
+
+    /// Adds a distance to the histogram.
+    pub fn add(&mut self, distance: f32) {
+        let bin = ((distance / self.bin_width).max(0.0) as usize).min(self.bins.len() - 1);
+        self.bins[bin] += 1;
+    }
+
+    /// The total number of distances in the histogram.
+    pub fn total(&self) -> u64 {
+        self.bins.iter().sum()
+    }
+
+    /// The upper edge of a bin, which is the threshold that accepts exactly the bins up to and including `bin`.
+    pub fn bin_upper_edge(&self, bin: usize) -> f32 {
+        (bin + 1) as f32 * self.bin_width
+    }
+
+    /// Iterates over the cumulative counts of the bins.
+    pub fn cumulative(&self) -> impl Iterator<Item = u64> + '_ {
+        self.bins.iter().scan(0, |sum, &count| {
+            *sum += count;
+            Some(*sum)
+        })
+    }
+
+    /// Estimates the distance below which the `percentile` (from `0.0` to `1.0`) of the distances fall.
+    pub fn percentile(&self, percentile: f64) -> f32 {
+        let target = (percentile.clamp(0.0, 1.0) * self.total() as f64).ceil() as u64;
+        let bin = self
+            .cumulative()
+            .position(|cumulative| cumulative >= target)
+            .unwrap_or(self.bins.len() - 1);
+        self.bin_upper_edge(bin)
+    }
+}
+
+/// A recommended match acceptance threshold.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ThresholdEstimate {
+    /// Matches with a distance less than this threshold should be accepted.
+    pub threshold: f32,
+    /// The expected fraction of accepted matches that are correct.
+    pub precision: f32,
+    /// The expected fraction of correct matches that are accepted.
+    pub recall: f32,
+}
+
+/// Recommends a threshold from the distances of known correct and incorrect matches, such as from a small
+/// validation set with ground truth.
+///
+/// The largest threshold whose precision is at least `min_precision` is chosen, which maximizes recall.
+/// If no threshold achieves `min_precision`, the threshold with the highest precision is returned.
+pub fn estimate_threshold(
+    correct: &DistanceHistogram,
+    incorrect: &DistanceHistogram,
+    min_precision: f32,
+) -> ThresholdEstimate {
+    assert_eq!(correct.bin_width, incorrect.bin_width);
+    assert_eq!(correct.bins.len(), incorrect.bins.len());
+    let total_correct = correct.total().max(1) as f32;
+    let mut best_precision: Option<ThresholdEstimate> = None;
+    let mut best_recall: Option<ThresholdEstimate> = None;
+    for (bin, (true_positives, false_positives)) in
+        correct.cumulative().zip(incorrect.cumulative()).enumerate()
+    {
+        let accepted = true_positives + false_positives;
+        if accepted == 0 {
+            continue;
+        }
+        let estimate = ThresholdEstimate {
+            threshold: correct.bin_upper_edge(bin),
+            precision: true_positives as f32 / accepted as f32,
+            recall: true_positives as f32 / total_correct,
+        };
+        if estimate.precision >= min_precision {
+            best_recall = Some(estimate);
+        }
+        if best_precision
+            .map(|best| estimate.precision > best.precision)
+            .unwrap_or(true)
+        {
+            best_precision = Some(estimate);
+        }
+    }
+    best_recall.or(best_precision).unwrap_or(ThresholdEstimate {
+        threshold: 0.0,
+        precision: 0.0,
+        recall: 0.0,
+    })
+}
+
+/// Recommends a threshold without ground truth by using second-nearest neighbor distances.
+///
+/// The second nearest neighbor of a query is almost never its correct match, so the distribution of second
+/// nearest distances is used as a model of the incorrect match distribution. The nearest neighbor distances
+/// are a mixture of correct and incorrect matches. The threshold is placed where the cumulative count of
+/// nearest distances exceeds the cumulative count of second nearest distances by the largest margin, beyond
+/// which accepting more matches admits as many incorrect-looking matches as it does nearest neighbors.
+///
+/// The precision and recall are estimates derived from this mixture model. Since it is unknown how many queries
+/// have a correct match in `target` at all, the recall is relative to the total number of queries.
+pub fn estimate_threshold_unsupervised<M, D>(
+    metric: &M,
+    query: &[D],
+    target: &[D],
+    bin_width: f32,
+    num_bins: usize,
+) -> ThresholdEstimate
+where
+    M: DescriptorMetric<D>,
+{
+    let mut nearest = DistanceHistogram::new(bin_width, num_bins);
+    let mut second_nearest = DistanceHistogram::new(bin_width, num_bins);
+    for query in query {
+        if let Some([(_, best), (_, second)]) = best_two(metric, query, target) {
+            nearest.add(metric.real_distance(best));
+            second_nearest.add(metric.real_distance(second));
+        }
+    }
+    let mut best_bin = 0;
+    let mut best_margin = 0i64;
+    for (bin, (nearest, second)) in nearest
+        .cumulative()
+        .zip(second_nearest.cumulative())
+        .enumerate()
+    {
+        let margin = nearest as i64 - second as i64;
+        if margin > best_margin {
+            best_margin = margin;
+            best_bin = bin;
+        }
+    }
+    // At the chosen threshold every accepted nearest neighbor in excess of the second nearest count is assumed correct.
+    let accepted = nearest.cumulative().nth(best_bin).unwrap_or(0).max(1);
+    let correct = best_margin as f32;
+    ThresholdEstimate {
+        threshold: nearest.bin_upper_edge(best_bin),
+        precision: correct / accepted as f32,
+        recall: correct / nearest.total().max(1) as f32,
+    }
+}
@@ -0,0 +1,194 @@
+//! Merging of separate reconstructions which overlap.
+//!
+//! A new session started on top of a saved map, or a camera which lost tracking and initialized again, produces a
+//! reconstruction of its own in an unrelated world space with an unrelated scale. Agents which map together are
+//! added as separate feeds and produce the same situation. Two reconstructions overlap when views of one are
+//! retrieved from the place recognition index for views of the other. The overlap is verified in the same way as a
+//! loop closure, and the similarity between the landmarks seen on both sides moves the source reconstruction into
+//! the target reconstruction, after which the landmarks seen on both sides are fused and the merged reconstruction
+//! is optimized as a whole.
+
+use crate::{opeek, LandmarkKey, ReconstructionKey, VSlam, ViewKey};
+use cv_core::{
+    nalgebra::IsometryMatrix3,
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, Sim3, TriangulatorObservations, WorldToCamera,
+    WorldToWorld,
+};
+use log::*;
+use rand::Rng;
+use space::KnnMap;
+use std::collections::HashMap;
+
+/// Two reconstructions merged by [`VSlam::merge_reconstructions`].
+#[derive(Clone, Debug)]
+pub struct ReconstructionMerge {
+    /// The reconstruction which now contains both maps.
+    pub reconstruction: ReconstructionKey,
+    /// The view of the source reconstruction which verified the overlap, before it was moved.
+    pub source_view: ViewKey,
+    /// The view of the target reconstruction which verified the overlap.
+    pub target_view: ViewKey,
+    /// The similarity from the world space of the source reconstruction to that of the target reconstruction.
+    pub alignment: Sim3,
+    /// The number of landmark pairs which agreed with the alignment.
+    pub inliers: usize,
+    /// The number of views which were moved from the source reconstruction.
+    pub merged_views: usize,
+    /// The number of landmarks of the source reconstruction which were fused into landmarks of the target.
+    pub merged_landmarks: usize,
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Finds `(source_view, target_view)` pairs which could show the same place in two reconstructions.
+    ///
+    /// For every view of `source`, the `map_merge_search_num` most similar frames are retrieved from the place
+    /// recognition index, and the ones with a view in `target` are kept. The `map_merge_candidates` most similar pairs
+    /// are returned, most similar first.
+    pub fn overlap_candidates(
+        &self,
+        source: ReconstructionKey,
+        target: ReconstructionKey,
+    ) -> Vec<(ViewKey, ViewKey)> {
+        let mut candidates: Vec<_> = self
+            .data
+            .reconstruction(source)
+            .views
+            .iter()
+            .flat_map(|(source_view, view)| {
+                self.data
+                    .lsh_to_frame
+                    .knn_values(
+                        &self.data.frame(view.frame).lsh,
+                        self.settings.map_merge_search_num,
+                    )
+                    .into_iter()
+                    .filter_map(move |(neighbor, &found_frame)| {
+                        let (found_reconstruction, found_view) =
+                            self.data.frame(found_frame).view?;
+                        (found_reconstruction == target)
+                            .then(|| (neighbor.distance, source_view, found_view))
+                    })
+            })
+            .collect();
+        candidates.sort_unstable_by_key(|&(distance, _, _)| distance);
+        candidates
+            .into_iter()
+            .map(|(_, source_view, target_view)| (source_view, target_view))
+            .take(self.settings.map_merge_candidates)
+            .collect()
+    }
+
+    /// Merges the `source` reconstruction into the `target` reconstruction if they overlap.
+    ///
+    /// Every pair from [`VSlam::overlap_candidates`] is verified like a loop closure (see [`VSlam::verify_loop`]), so
+    /// the `loop_closure_sim3_*` and `loop_closure_minimum_inliers` settings apply, and the pair with the most inliers
+    /// is used. The views of `source` are moved into `target` with the similarity between them, the landmarks matched
+    /// by the pair are fused, and `target` is optimized. The `source` reconstruction is removed.
+    pub fn merge_reconstructions(
+        &mut self,
+        source: ReconstructionKey,
+        target: ReconstructionKey,
+    ) -> Option<ReconstructionMerge> {
+        info!(
+            "trying to merge a reconstruction with {} views into a reconstruction with {} views",
+            self.data.reconstruction(source).views.len(),
+            self.data.reconstruction(target).views.len()
+        );
+        let (source_view, target_view, (alignment, matches)) = self
+            .overlap_candidates(source, target)
+            .into_iter()
+            .filter_map(|(source_view, target_view)| {
+                Some((
+                    source_view,
+                    target_view,
+                    self.align_views((source, source_view), (target, target_view))?,
+                ))
+            })
+            .max_by_key(|(_, _, (_, matches))| matches.len())
+            .or_else(opeek(|| {
+                info!("no overlap between the reconstructions was verified")
+            }))?;
+        info!(
+            "merging reconstructions with {} inliers and scale {}",
+            matches.len(),
+            alignment.scale()
+        );
+
+        let landmark_map: HashMap<LandmarkKey, LandmarkKey> = matches
+            .iter()
+            .map(|&[source_feature, target_feature]| {
+                (
+                    self.data
+                        .observation_landmark(source, source_view, source_feature),
+                    self.data
+                        .observation_landmark(target, target_view, target_feature),
+                )
+            })
+            .collect();
+        let merged_landmarks = landmark_map.len();
+
+        // Move the source views into the world space of the target before they are incorporated, since the
+        // incorporation only handles rigid transformations.
+        let source_views: Vec<ViewKey> = self.data.reconstruction(source).views.keys().collect();
+        for &view in &source_views {
+            let pose = self.data.pose(source, view);
+            self.data.view_mut(source, view).pose =
+                WorldToCamera((Sim3::from_pose(pose) * alignment.inverse()).isometry());
+        }
+        self.incorporate_reconstruction(
+            source,
+            target,
+            WorldToWorld(IsometryMatrix3::identity()),
+            landmark_map,
+        );
+        self.optimize_reconstruction(target).or_else(opeek(|| {
+            info!("failed to optimize the merged reconstruction")
+        }))?;
+
+        Some(ReconstructionMerge {
+            reconstruction: target,
+            source_view,
+            target_view,
+            alignment,
+            inliers: matches.len(),
+            merged_views: source_views.len(),
+            merged_landmarks,
+        })
+    }
+
+    /// Merges every pair of reconstructions which overlap until no more merges succeed.
+    ///
+    /// Smaller reconstructions are merged into larger ones, so the world space of the largest reconstruction is
+    /// kept. Returns the merges in the order they happened.
+    pub fn merge_all_reconstructions(&mut self) -> Vec<ReconstructionMerge> {
+        let mut merges = vec![];
+        'outer: loop {
+            let mut reconstructions: Vec<ReconstructionKey> = self.data.reconstructions().collect();
+            reconstructions.sort_unstable_by_key(|&reconstruction| {
+                self.data.reconstruction(reconstruction).views.len()
+            });
+            for (ix, &source) in reconstructions.iter().enumerate() {
+                for &target in reconstructions[ix + 1..].iter().rev() {
+                    if let Some(merge) = self.merge_reconstructions(source, target) {
+                        merges.push(merge);
+                        continue 'outer;
+                    }
+                    if !self.data.reconstructions.contains_key(source) {
+                        // The merge moved the views but the optimization removed the result.
+                        continue 'outer;
+                    }
+                }
+            }
+            return merges;
+        }
+    }
+}
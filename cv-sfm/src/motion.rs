@@ -0,0 +1,198 @@
+//! Relative motion priors, such as from wheel odometry or the kinematics of a robot.
+//!
+//! A frame can be added with the motion of the camera since the previous frame of its feed. Odometry drifts, but it
+//! is reliable over the short time between frames, where vision fails in textureless or motion-blurred stretches.
+//! The motions are used in two places:
+//!
+//! * The pose of a frame is predicted from the last frame with a known pose, which limits the search for matches to
+//!   a window around the predicted bearing of each landmark (see
+//!   [`VSlamSettings::odometry_search_window_cosine_distance`](crate::VSlamSettings)) and lets [`VisualOdometry`]
+//!   report a pose for frames which could not be registered.
+//! * The motions between consecutive views constrain their poses during optimization alongside the three-view
+//!   constraints (see [`VSlamSettings::odometry_constraints`](crate::VSlamSettings)).
+//!
+//! The motions can be in any unit, since the translation of the reconstruction is only known up to scale. The
+//! scale between them is the median ratio between the distances of consecutive views in the reconstruction and the
+//! distances the odometry measured between them.
+//!
+//! [`VisualOdometry`]: crate::VisualOdometry
+
+use crate::{Feature, FeedKey, FrameKey, ReconstructionKey, VSlam, ViewKey};
+use bitarray::BitArray;
+use cv_core::{
+    nalgebra::IsometryMatrix3,
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, TriangulatorObservations, WorldToCamera,
+};
+use float_ord::FloatOrd;
+use image::DynamicImage;
+use log::*;
+use rand::Rng;
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Adds a frame with the motion of the camera from the previous frame of the feed to this frame.
+    ///
+    /// This performs the same tracking and loop closure as [`VSlam::add_frame`].
+    pub fn add_frame_with_motion(
+        &mut self,
+        feed: FeedKey,
+        image: &DynamicImage,
+        motion: CameraToCamera,
+    ) -> FrameKey {
+        let features = self.kps_descriptors(&self.data.feeds[feed].intrinsics, image);
+        self.add_frame_features_with_motion(feed, features, motion)
+    }
+
+    /// Adds a frame from features which were already extracted with [`extract_features`](crate::extract_features)
+    /// with the motion of the camera from the previous frame of the feed to this frame.
+    pub fn add_frame_features_with_motion(
+        &mut self,
+        feed: FeedKey,
+        features: Vec<(BitArray<64>, Feature)>,
+        motion: CameraToCamera,
+    ) -> FrameKey {
+        let frame = self.data.add_frame(feed, features);
+        self.data.frame_mut(frame).motion = Some(motion);
+        self.track_frame(frame);
+        frame
+    }
+
+    /// The motion measured by the odometry from the camera of one frame to the camera of a later frame of the same
+    /// feed, if every frame after `from` up to `to` has a motion.
+    pub fn odometry_between(&self, from: FrameKey, to: FrameKey) -> Option<CameraToCamera> {
+        let from = self.data.frame(from);
+        let to = self.data.frame(to);
+        if from.feed != to.feed || from.feed_frame >= to.feed_frame {
+            return None;
+        }
+        self.data.feeds[to.feed].frames[from.feed_frame + 1..=to.feed_frame]
+            .iter()
+            .try_fold(IsometryMatrix3::identity(), |motion, &frame| {
+                Some(self.data.frame(frame).motion?.isometry() * motion)
+            })
+            .map(CameraToCamera)
+    }
+
+    /// The consecutive views of each feed in a reconstruction with the odometry between them.
+    fn odometry_view_pairs(
+        &self,
+        reconstruction: ReconstructionKey,
+    ) -> Vec<(ViewKey, ViewKey, CameraToCamera)> {
+        let mut views: Vec<(FeedKey, usize, ViewKey, FrameKey)> = self
+            .data
+            .reconstruction(reconstruction)
+            .views
+            .iter()
+            .map(|(view, view_data)| {
+                let frame = self.data.frame(view_data.frame);
+                (frame.feed, frame.feed_frame, view, view_data.frame)
+            })
+            .collect();
+        views.sort_unstable_by_key(|&(feed, feed_frame, _, _)| (feed, feed_frame));
+        views
+            .windows(2)
+            .filter_map(|pair| {
+                let (_, _, a, a_frame) = pair[0];
+                let (_, _, b, b_frame) = pair[1];
+                Some((a, b, self.odometry_between(a_frame, b_frame)?))
+            })
+            .collect()
+    }
+
+    /// The scale from the units of the odometry to the units of a reconstruction.
+    ///
+    /// Returns `None` if no two consecutive views of the reconstruction were measured to move by the odometry.
+    pub fn odometry_scale(&self, reconstruction: ReconstructionKey) -> Option<f64> {
+        let mut ratios: Vec<f64> = self
+            .odometry_view_pairs(reconstruction)
+            .into_iter()
+            .filter_map(|(a, b, odometry)| {
+                let measured = odometry.isometry().translation.vector.norm();
+                let relative = self.data.pose(reconstruction, b).isometry()
+                    * self.data.pose(reconstruction, a).isometry().inverse();
+                let distance = relative.translation.vector.norm();
+                (measured > f64::EPSILON).then(|| distance / measured)
+            })
+            .collect();
+        if ratios.is_empty() {
+            return None;
+        }
+        let middle = ratios.len() / 2;
+        Some(
+            *ratios
+                .select_nth_unstable_by_key(middle, |&ratio| FloatOrd(ratio))
+                .1,
+        )
+    }
+
+    /// Predicts the pose of a frame from the last earlier frame of its feed with a known pose and the motions of the
+    /// frames since.
+    ///
+    /// A pose is known if the frame is a view of a reconstruction or is the last tracked frame (see
+    /// [`VSlam::last_tracked`]). Returns `None` if there is no such frame, the motion of a frame in between is
+    /// missing, or the scale of the odometry in the reconstruction is unknown (see [`VSlam::odometry_scale`]).
+    pub fn predict_pose(&self, frame: FrameKey) -> Option<(ReconstructionKey, WorldToCamera)> {
+        let frame_data = self.data.frame(frame);
+        frame_data.motion?;
+        let feed_frames = &self.data.feeds[frame_data.feed].frames[..frame_data.feed_frame];
+        let mut base = None;
+        for &previous in feed_frames.iter().rev() {
+            base = self.known_pose(previous).map(|known| (previous, known));
+            if base.is_some() || self.data.frame(previous).motion.is_none() {
+                break;
+            }
+        }
+        let (previous, (reconstruction, pose)) = base?;
+        let odometry = self.odometry_between(previous, frame)?;
+        let scale = self.odometry_scale(reconstruction)?;
+        Some((
+            reconstruction,
+            WorldToCamera(odometry.scale(scale).isometry() * pose.isometry()),
+        ))
+    }
+
+    /// The pose of a frame if it is a view of a reconstruction or the last tracked frame.
+    fn known_pose(&self, frame: FrameKey) -> Option<(ReconstructionKey, WorldToCamera)> {
+        if let Some((reconstruction, view)) = self.data.frame(frame).view {
+            return Some((reconstruction, self.data.pose(reconstruction, view)));
+        }
+        self.last_tracked
+            .filter(|&(tracked, reconstruction, _)| {
+                tracked == frame && self.data.reconstructions.contains_key(reconstruction)
+            })
+            .map(|(_, reconstruction, pose)| (reconstruction, pose))
+    }
+
+    /// The constraints between consecutive views from the odometry, in the same form as the edge constraints of the
+    /// three-view constraints.
+    pub(crate) fn odometry_edge_constraints(
+        &self,
+        reconstruction: ReconstructionKey,
+    ) -> Vec<(ViewKey, (ViewKey, IsometryMatrix3<f64>))> {
+        let scale = match self.odometry_scale(reconstruction) {
+            Some(scale) => scale,
+            None => return vec![],
+        };
+        let pairs = self.odometry_view_pairs(reconstruction);
+        info!(
+            "constraining {} pairs of consecutive views with odometry at scale {}",
+            pairs.len(),
+            scale
+        );
+        pairs
+            .into_iter()
+            .flat_map(|(a, b, odometry)| {
+                let a_to_b = odometry.scale(scale).isometry();
+                vec![(b, (a, a_to_b)), (a, (b, a_to_b.inverse()))]
+            })
+            .collect()
+    }
+}
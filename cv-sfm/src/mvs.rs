@@ -0,0 +1,809 @@
+//! Dense depth estimation with PatchMatch multi-view stereo.
+//!
+//! A sparse reconstruction only has points where features were matched. To estimate the depth of every pixel of a
+//! registered image, [`PatchMatch`] assigns each pixel a plane hypothesis (a depth and a normal) and scores it by the
+//! photometric consistency of the window around the pixel with its projection into neighboring views through the
+//! homography induced by the plane. Hypotheses start out random, and good hypotheses spread to adjacent pixels, which
+//! very likely lie on the same surface, while random perturbations refine them. This is done with a red-black
+//! checkerboard order, so that all pixels of one color can be updated in parallel.
+//!
+//! After the photometric passes, the depth maps of all views are refined again with an additional geometric cost for
+//! hypotheses which disagree with the depth maps of the neighboring views, and finally only the pixels which are
+//! consistent with enough neighboring views are kept. The images must be undistorted, since only pinhole
+//! [`CameraIntrinsics`] are supported.
+
+use crate::{CloudPoint, PointCloud, SparseReconstruction};
+use cv_core::{
+    nalgebra::{Matrix3, Point2, Point3, Rotation3, Vector3},
+    CameraPoint, Pose, Projective, WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsics;
+use float_ord::FloatOrd;
+use image::{GrayImage, ImageBuffer, Luma, RgbImage};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// The pixel offsets of the hypotheses which are propagated to a pixel, which all have the other checkerboard color.
+const PROPAGATION_OFFSETS: [(i64, i64); 8] = [
+    (-1, 0),
+    (1, 0),
+    (0, -1),
+    (0, 1),
+    (-3, 0),
+    (3, 0),
+    (0, -3),
+    (0, 3),
+];
+
+/// The depth and normal of every pixel of an image.
+///
+/// The depths are along the optical axis (the Z coordinate in the camera) and the normals are in camera coordinates.
+/// Pixels without a depth have a depth of `0.0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthMap {
+    pub width: u32,
+    pub height: u32,
+    pub depths: Vec<f32>,
+    pub normals: Vec<Vector3<f32>>,
+    /// The matching cost of every pixel, with lower being better.
+    pub costs: Vec<f32>,
+}
+
+impl DepthMap {
+    /// Creates a depth map where no pixel has a depth.
+    pub fn new(width: u32, height: u32) -> Self {
+        let len = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            depths: vec![0.0; len],
+            normals: vec![Vector3::zeros(); len],
+            costs: vec![f32::INFINITY; len],
+        }
+    }
+
+    /// Creates a depth map from the depths of a sensor, such as a stereo pair or an RGB-D camera, in row-major order.
+    ///
+    /// Pixels without a depth must have a depth of `0.0`. The normals are unknown and left at zero.
+    pub fn from_depths(width: u32, height: u32, depths: Vec<f32>) -> Self {
+        assert_eq!(depths.len(), width as usize * height as usize);
+        let costs = depths
+            .iter()
+            .map(|&depth| if depth > 0.0 { 0.0 } else { f32::INFINITY })
+            .collect();
+        Self {
+            width,
+            height,
+            normals: vec![Vector3::zeros(); depths.len()],
+            depths,
+            costs,
+        }
+    }
+
+    /// Creates a depth map from the 16 bit depth image of an RGB-D camera, where each pixel is a depth in units of
+    /// `scale`, such as `0.001` for the millimeters of Kinect and RealSense cameras.
+    ///
+    /// Pixels of `0` have no depth.
+    pub fn from_depth_image(image: &ImageBuffer<Luma<u16>, Vec<u16>>, scale: f32) -> Self {
+        let depths = image
+            .pixels()
+            .map(|&Luma([depth])| depth as f32 * scale)
+            .collect();
+        Self::from_depths(image.width(), image.height(), depths)
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    /// The depth of a pixel, if it has one.
+    pub fn depth(&self, x: u32, y: u32) -> Option<f32> {
+        let depth = self.depths[self.index(x, y)];
+        (depth > 0.0).then(|| depth)
+    }
+
+    /// The normal of a pixel, if it has a depth.
+    pub fn normal(&self, x: u32, y: u32) -> Option<Vector3<f32>> {
+        self.depth(x, y)?;
+        Some(self.normals[self.index(x, y)])
+    }
+
+    /// The number of pixels with a depth.
+    pub fn num_valid(&self) -> usize {
+        self.depths.iter().filter(|&&depth| depth > 0.0).count()
+    }
+
+    /// The point seen at a pixel in the camera, if the pixel has a depth.
+    pub fn camera_point(
+        &self,
+        x: u32,
+        y: u32,
+        intrinsics: &CameraIntrinsics,
+    ) -> Option<CameraPoint> {
+        let depth = self.depth(x, y)? as f64;
+        Some(CameraPoint::from_point(Point3::from(
+            ray(&intrinsics.matrix().try_inverse()?, x as f64, y as f64) * depth,
+        )))
+    }
+
+    /// Creates a point cloud in world coordinates from the pixels with a depth.
+    ///
+    /// The points are colored from `image` if it is given and white otherwise.
+    pub fn point_cloud(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        pose: WorldToCamera,
+        image: Option<&RgbImage>,
+    ) -> PointCloud {
+        let camera_to_world = pose.inverse().isometry();
+        let points = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter_map(|(x, y)| {
+                let point = self.camera_point(x, y, intrinsics)?.point()?;
+                let normal = self.normal(x, y)?.cast::<f64>();
+                let color = image.map_or([255; 3], |image| image.get_pixel(x, y).0);
+                Some(CloudPoint {
+                    normal: Some(camera_to_world.rotation * normal),
+                    ..CloudPoint::new(camera_to_world * point, color)
+                })
+            })
+            .collect();
+        PointCloud::new(points)
+    }
+}
+
+/// A registered image given to [`PatchMatch`].
+#[derive(Clone, Debug)]
+pub struct MvsView<'a> {
+    /// The undistorted image.
+    pub image: &'a GrayImage,
+    pub intrinsics: CameraIntrinsics,
+    pub pose: WorldToCamera,
+    /// The indices of the views which are matched against this view.
+    pub neighbors: Vec<usize>,
+    /// The minimum and maximum depth of the scene in this view.
+    pub depth_range: [f64; 2],
+}
+
+/// A plane hypothesis of a pixel.
+#[derive(Copy, Clone, Debug)]
+struct Hypothesis {
+    depth: f64,
+    normal: Vector3<f64>,
+    cost: f64,
+}
+
+/// An image with floating point intensities.
+struct Intensities {
+    width: usize,
+    height: usize,
+    data: Vec<f64>,
+}
+
+impl Intensities {
+    fn new(image: &GrayImage) -> Self {
+        Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            data: image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f64 / 255.0)
+                .collect(),
+        }
+    }
+
+    fn get(&self, x: usize, y: usize) -> f64 {
+        self.data[y * self.width + x]
+    }
+
+    /// Samples the image with bilinear interpolation, or returns `None` outside of the image.
+    fn sample(&self, point: Point2<f64>) -> Option<f64> {
+        let (x, y) = (point.x, point.y);
+        if !(x >= 0.0 && y >= 0.0 && x < (self.width - 1) as f64 && y < (self.height - 1) as f64) {
+            return None;
+        }
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+        let top = self.get(x0, y0) * (1.0 - fx) + self.get(x0 + 1, y0) * fx;
+        let bottom = self.get(x0, y0 + 1) * (1.0 - fx) + self.get(x0 + 1, y0 + 1) * fx;
+        Some(top * (1.0 - fy) + bottom * fy)
+    }
+}
+
+/// A neighboring view with the transformation from the reference view to it.
+struct Neighbor<'a> {
+    index: usize,
+    image: &'a Intensities,
+    rotation: Rotation3<f64>,
+    translation: Vector3<f64>,
+    intrinsics: Matrix3<f64>,
+    inverse_intrinsics: Matrix3<f64>,
+}
+
+impl Neighbor<'_> {
+    /// Projects a point in the reference camera into the neighbor, returning the pixel and the depth.
+    fn project(&self, point: Vector3<f64>) -> Option<(Point2<f64>, f64)> {
+        let camera = self.rotation * point + self.translation;
+        (camera.z > 0.0).then(|| ())?;
+        let pixel = self.intrinsics * camera;
+        Some((Point2::new(pixel.x / pixel.z, pixel.y / pixel.z), camera.z))
+    }
+}
+
+/// The ray through a pixel, scaled to a depth of one.
+fn ray(inverse_intrinsics: &Matrix3<f64>, x: f64, y: f64) -> Vector3<f64> {
+    let ray = inverse_intrinsics * Vector3::new(x, y, 1.0);
+    ray / ray.z
+}
+
+fn random_normal(rng: &mut impl Rng, ray: Vector3<f64>) -> Vector3<f64> {
+    loop {
+        let normal = Vector3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        if let Some(normal) = normal.try_normalize(1e-6) {
+            return face_camera(normal, ray);
+        }
+    }
+}
+
+/// Flips a normal so that it faces the camera looking along `ray`.
+fn face_camera(normal: Vector3<f64>, ray: Vector3<f64>) -> Vector3<f64> {
+    if normal.dot(&ray) > 0.0 {
+        -normal
+    } else {
+        normal
+    }
+}
+
+/// Estimates depth and normal maps of registered images with PatchMatch multi-view stereo.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PatchMatch {
+    window_radius: usize,
+    iterations: usize,
+    geometric_iterations: usize,
+    aggregated_neighbors: usize,
+    geometric_weight: f64,
+    geometric_max_error: f64,
+    max_cost: f64,
+    consistency_max_error: f64,
+    min_consistent_views: usize,
+    seed: u64,
+}
+
+impl PatchMatch {
+    /// Creates a `PatchMatch` estimator with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the radius in pixels of the window compared between views.
+    ///
+    /// Default is `4`.
+    #[must_use]
+    pub fn window_radius(self, window_radius: usize) -> Self {
+        Self {
+            window_radius,
+            ..self
+        }
+    }
+
+    /// Set the number of photometric iterations, each of which updates every pixel once.
+    ///
+    /// Default is `4`.
+    #[must_use]
+    pub fn iterations(self, iterations: usize) -> Self {
+        Self { iterations, ..self }
+    }
+
+    /// Set the number of iterations which also use the geometric consistency with the neighboring depth maps.
+    ///
+    /// Default is `2`.
+    #[must_use]
+    pub fn geometric_iterations(self, geometric_iterations: usize) -> Self {
+        Self {
+            geometric_iterations,
+            ..self
+        }
+    }
+
+    /// Set the number of neighboring views with the lowest cost which are averaged into the cost of a hypothesis.
+    ///
+    /// Surfaces are often occluded in some of the neighbors, so not all neighbors are used.
+    ///
+    /// Default is `3`.
+    #[must_use]
+    pub fn aggregated_neighbors(self, aggregated_neighbors: usize) -> Self {
+        Self {
+            aggregated_neighbors,
+            ..self
+        }
+    }
+
+    /// Set the weight of the geometric cost per pixel of reprojection error.
+    ///
+    /// Default is `0.2`.
+    #[must_use]
+    pub fn geometric_weight(self, geometric_weight: f64) -> Self {
+        Self {
+            geometric_weight,
+            ..self
+        }
+    }
+
+    /// Set the reprojection error in pixels at which the geometric cost stops growing.
+    ///
+    /// Default is `3.0`.
+    #[must_use]
+    pub fn geometric_max_error(self, geometric_max_error: f64) -> Self {
+        Self {
+            geometric_max_error,
+            ..self
+        }
+    }
+
+    /// Set the maximum photometric cost of a pixel which is kept, where the cost is one minus the normalized
+    /// cross-correlation.
+    ///
+    /// Default is `0.6`.
+    #[must_use]
+    pub fn max_cost(self, max_cost: f64) -> Self {
+        Self { max_cost, ..self }
+    }
+
+    /// Set the reprojection error in pixels below which a neighboring depth map agrees with a pixel.
+    ///
+    /// Default is `1.0`.
+    #[must_use]
+    pub fn consistency_max_error(self, consistency_max_error: f64) -> Self {
+        Self {
+            consistency_max_error,
+            ..self
+        }
+    }
+
+    /// Set the number of neighboring depth maps which must agree with a pixel for it to be kept.
+    ///
+    /// Default is `2`.
+    #[must_use]
+    pub fn min_consistent_views(self, min_consistent_views: usize) -> Self {
+        Self {
+            min_consistent_views,
+            ..self
+        }
+    }
+
+    /// Set the seed of the random hypotheses.
+    ///
+    /// Default is `0`.
+    #[must_use]
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Estimates the depth map of every view.
+    pub fn estimate(&self, views: &[MvsView<'_>]) -> Vec<DepthMap> {
+        let images: Vec<Intensities> = views
+            .iter()
+            .map(|view| Intensities::new(view.image))
+            .collect();
+        let mut maps: Vec<Vec<Hypothesis>> = (0..views.len())
+            .map(|view| self.estimate_view(views, &images, view, None, self.iterations))
+            .collect();
+        for _ in 0..self.geometric_iterations {
+            let previous = maps;
+            maps = (0..views.len())
+                .map(|view| self.estimate_view(views, &images, view, Some(&previous), 1))
+                .collect();
+        }
+        (0..views.len())
+            .map(|view| self.filter(views, &images, &maps, view))
+            .collect()
+    }
+
+    fn neighbors<'a>(
+        &self,
+        views: &[MvsView<'_>],
+        images: &'a [Intensities],
+        view: usize,
+    ) -> Vec<Neighbor<'a>> {
+        let reference = &views[view];
+        reference
+            .neighbors
+            .iter()
+            .map(|&index| {
+                let relative = views[index].pose.isometry() * reference.pose.isometry().inverse();
+                Neighbor {
+                    index,
+                    image: &images[index],
+                    rotation: relative.rotation,
+                    translation: relative.translation.vector,
+                    intrinsics: views[index].intrinsics.matrix(),
+                    inverse_intrinsics: inverse_intrinsics(&views[index]),
+                }
+            })
+            .collect()
+    }
+
+    /// Runs PatchMatch on one view, starting from random hypotheses or the hypotheses of `previous`, which are also
+    /// used for the geometric cost.
+    fn estimate_view(
+        &self,
+        views: &[MvsView<'_>],
+        images: &[Intensities],
+        view: usize,
+        previous: Option<&[Vec<Hypothesis>]>,
+        iterations: usize,
+    ) -> Vec<Hypothesis> {
+        let reference = &images[view];
+        let (width, height) = (reference.width, reference.height);
+        let neighbors = self.neighbors(views, images, view);
+        let intrinsics = views[view].intrinsics.matrix();
+        let inverse_intrinsics = inverse_intrinsics(&views[view]);
+        let [min_depth, max_depth] = views[view].depth_range;
+        let rng_for = |pass: u64, ix: usize| {
+            Pcg64::seed_from_u64(
+                self.seed
+                    ^ (view as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                    ^ (ix as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9)
+                    ^ pass.wrapping_mul(0x94D0_49BB_1331_11EB),
+            )
+        };
+        let cost = |ix: usize, depth: f64, normal: Vector3<f64>| {
+            self.cost(
+                reference,
+                &neighbors,
+                &intrinsics,
+                &inverse_intrinsics,
+                ix % width,
+                ix / width,
+                depth,
+                normal,
+                previous.map(|previous| (views, previous)),
+            )
+        };
+
+        let mut hypotheses: Vec<Hypothesis> = (0..width * height)
+            .into_par_iter()
+            .map(|ix| {
+                let (depth, normal) = match previous {
+                    Some(previous) => (previous[view][ix].depth, previous[view][ix].normal),
+                    None => {
+                        let mut rng = rng_for(0, ix);
+                        let ray = ray(
+                            &inverse_intrinsics,
+                            (ix % width) as f64,
+                            (ix / width) as f64,
+                        );
+                        let inverse_depth = rng.gen_range(1.0 / max_depth..=1.0 / min_depth);
+                        (1.0 / inverse_depth, random_normal(&mut rng, ray))
+                    }
+                };
+                Hypothesis {
+                    depth,
+                    normal,
+                    cost: cost(ix, depth, normal),
+                }
+            })
+            .collect();
+
+        for iteration in 0..iterations {
+            // The perturbations get smaller as the hypotheses converge.
+            let scale = 0.5f64.powi(iteration as i32 + 1);
+            for color in 0..2 {
+                let pass = 1 + 2 * iteration as u64 + color as u64;
+                let updates: Vec<(usize, Hypothesis)> = (0..width * height)
+                    .into_par_iter()
+                    .filter(|&ix| (ix % width + ix / width) % 2 == color)
+                    .filter_map(|ix| {
+                        let (x, y) = ((ix % width) as i64, (ix / width) as i64);
+                        let mut rng = rng_for(pass, ix);
+                        let current = hypotheses[ix];
+                        let ray = ray(&inverse_intrinsics, x as f64, y as f64);
+                        let mut candidates: Vec<(f64, Vector3<f64>)> = PROPAGATION_OFFSETS
+                            .iter()
+                            .filter_map(|&(dx, dy)| {
+                                let (nx, ny) = (x + dx, y + dy);
+                                (nx >= 0 && ny >= 0 && nx < width as i64 && ny < height as i64)
+                                    .then(|| ())?;
+                                let neighbor = hypotheses[ny as usize * width + nx as usize];
+                                Some((neighbor.depth, neighbor.normal))
+                            })
+                            .collect();
+                        let inverse_depth = rng.gen_range(1.0 / max_depth..=1.0 / min_depth);
+                        candidates.push((1.0 / inverse_depth, random_normal(&mut rng, ray)));
+                        let perturbed_depth =
+                            current.depth * (1.0 + scale * rng.gen_range(-1.0..1.0));
+                        let perturbed_normal = (current.normal
+                            + scale * random_normal(&mut rng, ray))
+                        .try_normalize(1e-6)
+                        .map_or(current.normal, |normal| face_camera(normal, ray));
+                        candidates.push((perturbed_depth, current.normal));
+                        candidates.push((current.depth, perturbed_normal));
+                        candidates.push((perturbed_depth, perturbed_normal));
+
+                        let best = candidates
+                            .into_iter()
+                            .filter(|&(depth, _)| depth >= min_depth && depth <= max_depth)
+                            .map(|(depth, normal)| Hypothesis {
+                                depth,
+                                normal,
+                                cost: cost(ix, depth, normal),
+                            })
+                            .min_by_key(|hypothesis| FloatOrd(hypothesis.cost))?;
+                        (best.cost < current.cost).then(|| (ix, best))
+                    })
+                    .collect();
+                for (ix, hypothesis) in updates {
+                    hypotheses[ix] = hypothesis;
+                }
+            }
+        }
+        hypotheses
+    }
+
+    /// The cost of a plane hypothesis at a pixel of the reference view.
+    ///
+    /// The photometric cost with each neighbor is one minus the normalized cross-correlation of the windows, or `2.0`
+    /// if the window leaves the neighbor. If `geometric` is given, the reprojection error through the depth map of
+    /// each neighbor is added. The lowest costs of `aggregated_neighbors` neighbors are averaged.
+    #[allow(clippy::too_many_arguments)]
+    fn cost(
+        &self,
+        reference: &Intensities,
+        neighbors: &[Neighbor<'_>],
+        intrinsics: &Matrix3<f64>,
+        inverse_intrinsics: &Matrix3<f64>,
+        x: usize,
+        y: usize,
+        depth: f64,
+        normal: Vector3<f64>,
+        geometric: Option<(&[MvsView<'_>], &[Vec<Hypothesis>])>,
+    ) -> f64 {
+        let radius = self.window_radius as i64;
+        let point = ray(inverse_intrinsics, x as f64, y as f64) * depth;
+        // The plane is `normal . X = distance` in the reference camera.
+        let distance = normal.dot(&point);
+        if neighbors.is_empty() || distance.abs() < 1e-12 {
+            return f64::INFINITY;
+        }
+
+        let mut costs: Vec<f64> = neighbors
+            .iter()
+            .map(|neighbor| {
+                let homography = neighbor.intrinsics
+                    * (neighbor.rotation.matrix()
+                        + neighbor.translation * normal.transpose() / distance)
+                    * inverse_intrinsics;
+                let mut sums = [0.0; 5];
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        let (wx, wy) = (x as i64 + dx, y as i64 + dy);
+                        if wx < 0
+                            || wy < 0
+                            || wx >= reference.width as i64
+                            || wy >= reference.height as i64
+                        {
+                            return 2.0;
+                        }
+                        let mapped = homography * Vector3::new(wx as f64, wy as f64, 1.0);
+                        if mapped.z <= 0.0 {
+                            return 2.0;
+                        }
+                        let a = reference.get(wx as usize, wy as usize);
+                        let b = match neighbor
+                            .image
+                            .sample(Point2::new(mapped.x / mapped.z, mapped.y / mapped.z))
+                        {
+                            Some(b) => b,
+                            None => return 2.0,
+                        };
+                        sums[0] += a;
+                        sums[1] += b;
+                        sums[2] += a * a;
+                        sums[3] += b * b;
+                        sums[4] += a * b;
+                    }
+                }
+                let n = ((2 * radius + 1) * (2 * radius + 1)) as f64;
+                let [sa, sb, saa, sbb, sab] = sums;
+                let covariance = sab / n - sa * sb / (n * n);
+                let variance_a = saa / n - sa * sa / (n * n);
+                let variance_b = sbb / n - sb * sb / (n * n);
+                let photometric = if variance_a * variance_b > 1e-10 {
+                    1.0 - (covariance / (variance_a * variance_b).sqrt())
+                        .max(-1.0)
+                        .min(1.0)
+                } else {
+                    2.0
+                };
+                let geometric = geometric.map_or(0.0, |(views, maps)| {
+                    self.geometric_weight
+                        * self
+                            .reprojection_error(maps, neighbor, intrinsics, point, x, y)
+                            .unwrap_or(self.geometric_max_error)
+                            .min(self.geometric_max_error)
+                });
+                photometric + geometric
+            })
+            .collect();
+        costs.sort_unstable_by_key(|&cost| FloatOrd(cost));
+        let count = self.aggregated_neighbors.max(1).min(costs.len());
+        costs[..count].iter().sum::<f64>() / count as f64
+    }
+
+    /// The error in pixels of projecting `point` of the reference view into a neighbor, looking up the depth of the
+    /// neighbor there, and projecting the resulting point back into the reference view at `(x, y)`.
+    fn reprojection_error(
+        &self,
+        maps: &[Vec<Hypothesis>],
+        neighbor: &Neighbor<'_>,
+        intrinsics: &Matrix3<f64>,
+        point: Vector3<f64>,
+        x: usize,
+        y: usize,
+    ) -> Option<f64> {
+        let (pixel, _) = neighbor.project(point)?;
+        let image = neighbor.image;
+        let (nx, ny) = (pixel.x.round(), pixel.y.round());
+        (nx >= 0.0 && ny >= 0.0 && nx < image.width as f64 && ny < image.height as f64)
+            .then(|| ())?;
+        let depth = maps[neighbor.index][ny as usize * image.width + nx as usize].depth;
+        let neighbor_point = ray(&neighbor.inverse_intrinsics, nx, ny) * depth;
+        let back = neighbor.rotation.inverse() * (neighbor_point - neighbor.translation);
+        (back.z > 0.0).then(|| ())?;
+        let projected = intrinsics * back;
+        let projected = Point2::new(projected.x / projected.z, projected.y / projected.z);
+        Some((projected - Point2::new(x as f64, y as f64)).norm())
+    }
+
+    /// Keeps only the pixels with a low enough cost which agree with enough neighboring depth maps.
+    fn filter(
+        &self,
+        views: &[MvsView<'_>],
+        images: &[Intensities],
+        maps: &[Vec<Hypothesis>],
+        view: usize,
+    ) -> DepthMap {
+        let neighbors = self.neighbors(views, images, view);
+        let intrinsics = views[view].intrinsics.matrix();
+        let inverse_intrinsics = inverse_intrinsics(&views[view]);
+        let (width, height) = (images[view].width, images[view].height);
+        let mut depth_map = DepthMap::new(width as u32, height as u32);
+        for (ix, hypothesis) in maps[view].iter().enumerate() {
+            let (x, y) = (ix % width, ix / width);
+            let point = ray(&inverse_intrinsics, x as f64, y as f64) * hypothesis.depth;
+            let consistent = neighbors
+                .iter()
+                .filter(|neighbor| {
+                    self.reprojection_error(maps, neighbor, &intrinsics, point, x, y)
+                        .map_or(false, |error| error < self.consistency_max_error)
+                })
+                .count();
+            // The final cost also contains the geometric cost, which is zero for perfectly consistent pixels.
+            if hypothesis.cost <= self.max_cost && consistent >= self.min_consistent_views {
+                depth_map.depths[ix] = hypothesis.depth as f32;
+                depth_map.normals[ix] = hypothesis.normal.cast();
+                depth_map.costs[ix] = hypothesis.cost as f32;
+            }
+        }
+        depth_map
+    }
+}
+
+impl Default for PatchMatch {
+    fn default() -> Self {
+        Self {
+            window_radius: 4,
+            iterations: 4,
+            geometric_iterations: 2,
+            aggregated_neighbors: 3,
+            geometric_weight: 0.2,
+            geometric_max_error: 3.0,
+            max_cost: 0.6,
+            consistency_max_error: 1.0,
+            min_consistent_views: 2,
+            seed: 0,
+        }
+    }
+}
+
+fn inverse_intrinsics(view: &MvsView<'_>) -> Matrix3<f64> {
+    view.intrinsics
+        .matrix()
+        .try_inverse()
+        .expect("camera intrinsics must be invertible")
+}
+
+impl SparseReconstruction {
+    /// Creates the [`MvsView`]s of the registered images which observe at least one point, along with the index of
+    /// the image of each view.
+    ///
+    /// The neighbors of each view are the `num_neighbors` views which share the most points with it that are seen
+    /// with at least `min_angle` radians between the two rays, and the depth range is taken from the points seen by
+    /// the view with some margin. `images` must be undistorted with `intrinsics`.
+    pub fn mvs_views<'a>(
+        &self,
+        images: &'a [GrayImage],
+        intrinsics: &CameraIntrinsics,
+        num_neighbors: usize,
+        min_angle: f64,
+    ) -> Vec<(usize, MvsView<'a>)> {
+        let centers: HashMap<usize, Vector3<f64>> = self
+            .registered()
+            .map(|(image, pose)| (image, pose.inverse().isometry().translation.vector))
+            .collect();
+        let mut depths: HashMap<usize, Vec<f64>> = HashMap::new();
+        let mut shared: HashMap<(usize, usize), usize> = HashMap::new();
+        for point in &self.points {
+            let position = match point.point.point() {
+                Some(position) => position,
+                None => continue,
+            };
+            let observers: Vec<usize> = point
+                .observations
+                .iter()
+                .map(|&(image, _)| image)
+                .filter(|image| centers.contains_key(image))
+                .collect();
+            for &image in &observers {
+                let depth = (self.poses[image].unwrap().isometry() * position).z;
+                if depth > 0.0 {
+                    depths.entry(image).or_default().push(depth);
+                }
+            }
+            for (ix, &a) in observers.iter().enumerate() {
+                for &b in &observers[ix + 1..] {
+                    let ray_a = position.coords - centers[&a];
+                    let ray_b = position.coords - centers[&b];
+                    if ray_a.angle(&ray_b) >= min_angle {
+                        *shared.entry((a, b)).or_default() += 1;
+                        *shared.entry((b, a)).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        let mut eligible: Vec<usize> = depths.keys().copied().collect();
+        eligible.sort_unstable();
+        let view_indices: HashMap<usize, usize> = eligible
+            .iter()
+            .enumerate()
+            .map(|(view, &image)| (image, view))
+            .collect();
+        eligible
+            .iter()
+            .map(|&image| {
+                let mut image_depths = depths.remove(&image).unwrap();
+                image_depths.sort_unstable_by_key(|&depth| FloatOrd(depth));
+                let low = image_depths[image_depths.len() / 100];
+                let high = image_depths[image_depths.len() - 1 - image_depths.len() / 100];
+                let mut neighbors: Vec<(usize, usize)> = eligible
+                    .iter()
+                    .filter_map(|&other| Some((other, *shared.get(&(image, other))?)))
+                    .collect();
+                neighbors.sort_unstable_by_key(|&(other, count)| (std::cmp::Reverse(count), other));
+                (
+                    image,
+                    MvsView {
+                        image: &images[image],
+                        intrinsics: *intrinsics,
+                        pose: self.poses[image].unwrap(),
+                        neighbors: neighbors
+                            .into_iter()
+                            .take(num_neighbors)
+                            .map(|(other, _)| view_indices[&other])
+                            .collect(),
+                        depth_range: [low * 0.75, high * 1.25],
+                    },
+                )
+            })
+            .collect()
+    }
+}
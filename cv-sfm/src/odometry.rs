@@ -0,0 +1,277 @@
+//! Streaming visual odometry over a single camera.
+//!
+//! [`VSlam`] is built from frames added in any order from any number of feeds, which makes it awkward to drive from
+//! a robot loop that only wants to know where the camera is right now. [`VisualOdometry`] owns a [`VSlam`] with a
+//! single feed and turns each new image into an [`OdometryUpdate`] with the pose of the camera and whether it is
+//! still being tracked. The features are extracted, matched against similar and recent frames, registered with PnP,
+//! filtered by the keyframe policy of the [`VSlam`] and bundle adjusted exactly as in [`VSlam::add_frame`], so the
+//! settings of the [`VSlam`] apply unchanged.
+
+use crate::{Feature, FeedKey, FrameKey, ReconstructionKey, VSlam};
+use bitarray::BitArray;
+use cv_core::{
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, TriangulatorObservations, WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsicsK1Distortion;
+use image::DynamicImage;
+use log::*;
+use rand::Rng;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// Whether [`VisualOdometry`] knows where the camera is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum OdometryStatus {
+    /// No reconstruction has been initialized yet, which requires enough frames with parallax between them.
+    Initializing,
+    /// The frame was registered into the reconstruction.
+    Tracking,
+    /// The frame could not be registered into the reconstruction the camera was last tracked in.
+    ///
+    /// Every later frame is still matched against the whole map, so tracking recovers when the camera returns to a
+    /// place it has seen before, or continues in a new reconstruction when one can be initialized.
+    Lost,
+    /// The frame could not be registered, so its pose was predicted from the motion priors of the frames since the
+    /// camera was last tracked (see [`VisualOdometry::feed_with_motion`]).
+    ///
+    /// This bridges textureless or motion-blurred stretches until frames can be registered again.
+    Predicted,
+}
+
+/// The outcome of feeding one image to [`VisualOdometry`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct OdometryUpdate {
+    /// The frame which was created for the image.
+    pub frame: FrameKey,
+    /// The timestamp the image was fed with.
+    pub timestamp: f64,
+    /// Whether the camera is being tracked.
+    pub status: OdometryStatus,
+    /// The reconstruction the camera is tracked in.
+    ///
+    /// This changes when tracking continues in a new reconstruction after being lost, in which case the poses before
+    /// and after the change are not in the same world space.
+    pub reconstruction: Option<ReconstructionKey>,
+    /// The pose of the camera in the reconstruction if it is being tracked or was predicted.
+    pub pose: Option<WorldToCamera>,
+    /// Whether the frame was kept as a view of the reconstruction.
+    pub keyframe: bool,
+}
+
+/// Incremental visual odometry which turns a stream of images from one camera into poses.
+pub struct VisualOdometry<C1, C2, PE, EE, T, R> {
+    /// The underlying map, which can be saved, exported or queried as usual
+    pub vslam: VSlam<C1, C2, PE, EE, T, R>,
+    /// The feed the images are added to
+    feed: FeedKey,
+    /// The reconstruction the camera was last tracked in
+    reconstruction: Option<ReconstructionKey>,
+    /// The status after the last image
+    status: OdometryStatus,
+    /// The last update which had a pose
+    last_pose: Option<OdometryUpdate>,
+}
+
+impl<C1, C2, PE, EE, T, R> VisualOdometry<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Creates visual odometry for a camera with the given intrinsics.
+    ///
+    /// The `vslam` may already contain a map, in which case the camera is localized in it as soon as it sees a
+    /// mapped place.
+    pub fn new(
+        mut vslam: VSlam<C1, C2, PE, EE, T, R>,
+        intrinsics: CameraIntrinsicsK1Distortion,
+    ) -> Self {
+        let feed = vslam.add_feed(intrinsics);
+        Self {
+            vslam,
+            feed,
+            reconstruction: None,
+            status: OdometryStatus::Initializing,
+            last_pose: None,
+        }
+    }
+
+    /// The feed of the [`VSlam`] which the images are added to.
+    pub fn feed_key(&self) -> FeedKey {
+        self.feed
+    }
+
+    /// The status after the last image.
+    pub fn status(&self) -> OdometryStatus {
+        self.status
+    }
+
+    /// The last update which had a pose, if the camera was ever tracked.
+    pub fn last_pose(&self) -> Option<OdometryUpdate> {
+        self.last_pose
+    }
+
+    /// Adds the next image of the camera, taken at `timestamp`, and returns where the camera is.
+    pub fn feed(&mut self, image: &DynamicImage, timestamp: f64) -> OdometryUpdate {
+        let features = self
+            .vslam
+            .kps_descriptors(&self.vslam.data.feed(self.feed).intrinsics, image);
+        self.feed_features(features, timestamp)
+    }
+
+    /// Adds the next image of the camera, taken at `timestamp`, with the motion of the camera since the previous
+    /// image, and returns where the camera is.
+    ///
+    /// The `motion` goes from the previous camera to this camera, such as from wheel odometry or the kinematics of
+    /// the robot, and may be in any unit (see [`VSlam::odometry_scale`]). It narrows the search for matches, and the
+    /// pose is predicted from it when the image can't be registered.
+    pub fn feed_with_motion(
+        &mut self,
+        image: &DynamicImage,
+        timestamp: f64,
+        motion: CameraToCamera,
+    ) -> OdometryUpdate {
+        let features = self
+            .vslam
+            .kps_descriptors(&self.vslam.data.feed(self.feed).intrinsics, image);
+        self.feed_features_with_motion(features, timestamp, motion)
+    }
+
+    /// Adds the features of the next image of the camera, extracted with [`extract_features`], and returns where the
+    /// camera is.
+    pub fn feed_features(
+        &mut self,
+        features: Vec<(BitArray<64>, Feature)>,
+        timestamp: f64,
+    ) -> OdometryUpdate {
+        let frame = self.vslam.add_frame_features(self.feed, features);
+        self.update(frame, timestamp)
+    }
+
+    /// Adds the features of the next image of the camera with the motion of the camera since the previous image (see
+    /// [`VisualOdometry::feed_with_motion`]), and returns where the camera is.
+    pub fn feed_features_with_motion(
+        &mut self,
+        features: Vec<(BitArray<64>, Feature)>,
+        timestamp: f64,
+        motion: CameraToCamera,
+    ) -> OdometryUpdate {
+        let frame = self
+            .vslam
+            .add_frame_features_with_motion(self.feed, features, motion);
+        self.update(frame, timestamp)
+    }
+
+    /// Adds the features of the next image with its motion prior, if any, but only tracks the frame.
+    ///
+    /// Loop closure and map maintenance are left to the caller, which is how [`ThreadedOdometry`] runs them on other
+    /// threads.
+    ///
+    /// [`ThreadedOdometry`]: crate::ThreadedOdometry
+    pub(crate) fn track_features(
+        &mut self,
+        features: Vec<(BitArray<64>, Feature)>,
+        timestamp: f64,
+        motion: Option<CameraToCamera>,
+    ) -> OdometryUpdate {
+        let frame = self.vslam.data.add_frame(self.feed, features);
+        self.vslam.data.frame_mut(frame).motion = motion;
+        self.vslam.localize_frame(frame);
+        self.update(frame, timestamp)
+    }
+
+    /// Finds where the camera is after a frame was added.
+    fn update(&mut self, frame: FrameKey, timestamp: f64) -> OdometryUpdate {
+        // Bundle adjustment failures can remove the reconstruction the camera was tracked in.
+        if let Some(reconstruction) = self.reconstruction {
+            if !self.vslam.data.reconstructions.contains_key(reconstruction) {
+                info!("the tracked reconstruction was removed; reinitializing");
+                self.reconstruction = None;
+            }
+        }
+
+        let tracked = if let Some((reconstruction, view)) = self.vslam.data.frame(frame).view {
+            Some((
+                reconstruction,
+                self.vslam.data.pose(reconstruction, view),
+                true,
+            ))
+        } else {
+            self.vslam
+                .last_tracked
+                .filter(|&(tracked_frame, reconstruction, _)| {
+                    tracked_frame == frame
+                        && self.vslam.data.reconstructions.contains_key(reconstruction)
+                })
+                .map(|(_, reconstruction, pose)| (reconstruction, pose, false))
+        };
+
+        // Dead reckon with the motion priors when the frame can't be registered.
+        let predicted = if tracked.is_none() {
+            self.vslam
+                .predict_pose(frame)
+                .filter(|&(reconstruction, _)| Some(reconstruction) == self.reconstruction)
+        } else {
+            None
+        };
+        if let Some((reconstruction, pose)) = predicted {
+            info!("tracking lost; predicting the pose from the motion prior");
+            // The next frame is predicted from this one.
+            self.vslam.last_tracked = Some((frame, reconstruction, pose));
+        }
+
+        let update = match tracked {
+            Some((reconstruction, pose, keyframe)) => {
+                if self
+                    .reconstruction
+                    .map_or(false, |old| old != reconstruction)
+                {
+                    info!("tracking continued in a different reconstruction");
+                }
+                self.reconstruction = Some(reconstruction);
+                OdometryUpdate {
+                    frame,
+                    timestamp,
+                    status: OdometryStatus::Tracking,
+                    reconstruction: Some(reconstruction),
+                    pose: Some(pose),
+                    keyframe,
+                }
+            }
+            None if predicted.is_some() => OdometryUpdate {
+                frame,
+                timestamp,
+                status: OdometryStatus::Predicted,
+                reconstruction: self.reconstruction,
+                pose: predicted.map(|(_, pose)| pose),
+                keyframe: false,
+            },
+            None => OdometryUpdate {
+                frame,
+                timestamp,
+                status: if self.reconstruction.is_some() {
+                    info!("tracking lost");
+                    OdometryStatus::Lost
+                } else {
+                    OdometryStatus::Initializing
+                },
+                reconstruction: self.reconstruction,
+                pose: None,
+                keyframe: false,
+            },
+        };
+
+        self.status = update.status;
+        if update.pose.is_some() {
+            self.last_pose = Some(update);
+        }
+        update
+    }
+}
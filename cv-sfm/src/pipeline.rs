@@ -0,0 +1,931 @@
+//! A high-level structure from motion pipeline over a set of images.
+
+use crate::{
+    bicubic,
+    matching::{match_all_pairs, Matcher},
+    DepthMap, Observation, Track, TrackBuilder,
+};
+use bitarray::BitArray;
+use cv_core::{
+    nalgebra::{Point3, UnitVector3},
+    sample_consensus::{Consensus, Estimator},
+    CameraModel, CameraPoint, CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Projective,
+    TriangulatorObservations, WorldPoint, WorldToCamera,
+};
+use cv_optimize::{BundleAdjuster, BundleAdjustment, DepthObservation, Gauge, MotionOnly};
+use float_ord::FloatOrd;
+use image::DynamicImage;
+use log::*;
+use std::collections::HashMap;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// The features of one image given to a structure from motion pipeline.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct SfmImage<D> {
+    /// The calibrated bearing of every feature.
+    pub bearings: Vec<UnitVector3<f64>>,
+    /// The descriptor of every feature.
+    pub descriptors: Vec<D>,
+    /// The color of every feature, or empty if the colors are unknown.
+    pub colors: Vec<[u8; 3]>,
+    /// The depth of every feature along the optical axis as measured by an RGB-D camera, or empty if the depths are
+    /// unknown.
+    #[cfg_attr(feature = "serde-serialize", serde(default))]
+    pub depths: Vec<Option<f64>>,
+}
+
+impl<D> SfmImage<D> {
+    /// Creates an image from precomputed features, where `bearings[i]` has the descriptor `descriptors[i]`.
+    pub fn new(bearings: Vec<UnitVector3<f64>>, descriptors: Vec<D>) -> Self {
+        assert_eq!(
+            bearings.len(),
+            descriptors.len(),
+            "every feature must have one bearing and one descriptor"
+        );
+        Self {
+            bearings,
+            descriptors,
+            colors: vec![],
+            depths: vec![],
+        }
+    }
+
+    #[must_use]
+    pub fn colors(self, colors: Vec<[u8; 3]>) -> Self {
+        Self { colors, ..self }
+    }
+
+    #[must_use]
+    pub fn depths(self, depths: Vec<Option<f64>>) -> Self {
+        Self { depths, ..self }
+    }
+
+    /// The number of features in the image.
+    pub fn len(&self) -> usize {
+        self.bearings.len()
+    }
+
+    /// Returns `true` if the image has no features.
+    pub fn is_empty(&self) -> bool {
+        self.bearings.is_empty()
+    }
+
+    /// The color of a feature, which is white if the colors are unknown.
+    pub fn color(&self, feature: usize) -> [u8; 3] {
+        self.colors.get(feature).copied().unwrap_or([255; 3])
+    }
+
+    /// The measured depth of a feature, if it has one.
+    pub fn depth(&self, feature: usize) -> Option<f64> {
+        self.depths.get(feature).copied().flatten()
+    }
+
+    /// The point of a feature in the camera from its measured depth.
+    fn depth_point(&self, feature: usize) -> Option<CameraPoint> {
+        let depth = self.depth(feature)?;
+        let bearing = self.bearings[feature];
+        (bearing.z > 0.0).then(|| ())?;
+        Some(CameraPoint::from_point(Point3::from(
+            bearing.into_inner() * (depth / bearing.z),
+        )))
+    }
+}
+
+impl SfmImage<BitArray<64>> {
+    /// Extracts AKAZE features from an image and calibrates them with the camera model of the image.
+    pub fn from_image<C: CameraModel>(
+        camera: &C,
+        image: &DynamicImage,
+        akaze_threshold: f64,
+    ) -> Self {
+        let (keypoints, descriptors) = akaze::Akaze::new(akaze_threshold).extract(image);
+        Self::from_keypoints(camera, image, &keypoints, descriptors)
+    }
+
+    /// Extracts AKAZE features from the color image of an RGB-D camera and looks up the depth of every feature in
+    /// the depth map, which must be registered to the color image.
+    ///
+    /// Images with depths give a reconstruction a metric scale and let points be initialized from a single image.
+    pub fn from_rgbd<C: CameraModel>(
+        camera: &C,
+        image: &DynamicImage,
+        depth_map: &DepthMap,
+        akaze_threshold: f64,
+    ) -> Self {
+        let (keypoints, descriptors) = akaze::Akaze::new(akaze_threshold).extract(image);
+        let depths = keypoints
+            .iter()
+            .map(|kp| {
+                let (x, y) = kp.point;
+                let (x, y) = (x.round(), y.round());
+                if x < 0.0 || y < 0.0 || x as u32 >= depth_map.width || y as u32 >= depth_map.height
+                {
+                    return None;
+                }
+                depth_map.depth(x as u32, y as u32).map(f64::from)
+            })
+            .collect();
+        Self::from_keypoints(camera, image, &keypoints, descriptors).depths(depths)
+    }
+
+    /// Calibrates the keypoints and samples their colors from the image.
+    pub(crate) fn from_keypoints<C: CameraModel>(
+        camera: &C,
+        image: &DynamicImage,
+        keypoints: &[akaze::KeyPoint],
+        descriptors: Vec<BitArray<64>>,
+    ) -> Self {
+        let rgb_image = image.to_rgb8();
+        let colors = keypoints
+            .iter()
+            .map(|kp| {
+                use image::Rgb;
+                let (x, y) = kp.point;
+                let Rgb(color) = bicubic::interpolate_bicubic(&rgb_image, x, y, Rgb([0, 0, 0]));
+                color
+            })
+            .collect();
+        let bearings = keypoints
+            .iter()
+            .map(|&keypoint| camera.calibrate(keypoint))
+            .collect();
+        Self::new(bearings, descriptors).colors(colors)
+    }
+}
+
+/// A triangulated point of a [`SparseReconstruction`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct SparsePoint {
+    pub point: WorldPoint,
+    /// The color of the first observation of the point.
+    pub color: [u8; 3],
+    /// The observations of the point in registered images, as `(image, feature)`.
+    pub observations: Vec<Observation<usize>>,
+}
+
+/// The output of a structure from motion pipeline, with the poses of the images and a sparse point cloud.
+///
+/// Monocular reconstructions are only known up to scale, which is chosen so the two initial images are a unit
+/// distance apart. Reconstructions of images with depths (see [`SfmImage::from_rgbd`]) are metric instead.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct SparseReconstruction {
+    /// The pose of every input image, or `None` if it could not be registered.
+    pub poses: Vec<Option<WorldToCamera>>,
+    pub points: Vec<SparsePoint>,
+}
+
+impl SparseReconstruction {
+    /// Iterates over the registered images and their poses.
+    pub fn registered(&self) -> impl Iterator<Item = (usize, WorldToCamera)> + '_ {
+        self.poses
+            .iter()
+            .enumerate()
+            .filter_map(|(image, pose)| Some((image, (*pose)?)))
+    }
+
+    /// The number of registered images.
+    pub fn num_registered(&self) -> usize {
+        self.registered().count()
+    }
+}
+
+/// The relative pose between two images and the matches which are inliers to it.
+#[derive(Clone, Debug)]
+pub(crate) struct VerifiedPair {
+    pub images: (usize, usize),
+    pub pose: CameraToCamera,
+    pub matches: Vec<[usize; 2]>,
+}
+
+impl VerifiedPair {
+    /// The median angle between the bearings of the inliers after compensating for the rotation, which measures
+    /// how much parallax the pair has.
+    pub fn median_parallax<D>(&self, images: &[SfmImage<D>]) -> f64 {
+        let rotation = self.pose.isometry().rotation;
+        let mut angles: Vec<f64> = self
+            .matches
+            .iter()
+            .map(|&[a, b]| {
+                let a = rotation * images[self.images.0].bearings[a];
+                a.angle(&images[self.images.1].bearings[b])
+            })
+            .collect();
+        if angles.is_empty() {
+            return 0.0;
+        }
+        let middle = angles.len() / 2;
+        *angles
+            .select_nth_unstable_by_key(middle, |&angle| FloatOrd(angle))
+            .1
+    }
+
+    /// The median ratio between the measured depths of the inliers and their depths when triangulated with the
+    /// relative pose, which is the scale that makes the pair metric.
+    ///
+    /// Returns `None` if no inlier with a depth could be triangulated.
+    pub fn depth_scale<D, T: TriangulatorObservations>(
+        &self,
+        images: &[SfmImage<D>],
+        triangulator: &T,
+    ) -> Option<f64> {
+        let (a, b) = self.images;
+        let first = WorldToCamera::identity();
+        let second = WorldToCamera(self.pose.0);
+        let ratio = |pose: WorldToCamera, point: WorldPoint, image: &SfmImage<D>, feature| {
+            let depth = pose.transform(point).point()?.z;
+            if depth <= 0.0 {
+                return None;
+            }
+            Some(image.depth(feature)? / depth)
+        };
+        let mut ratios: Vec<f64> = self
+            .matches
+            .iter()
+            .flat_map(|&[fa, fb]| {
+                let point = triangulator.triangulate_observations(
+                    [
+                        (first, images[a].bearings[fa]),
+                        (second, images[b].bearings[fb]),
+                    ]
+                    .iter()
+                    .copied(),
+                );
+                let ratios = point.map(|point| {
+                    vec![
+                        ratio(first, point, &images[a], fa),
+                        ratio(second, point, &images[b], fb),
+                    ]
+                });
+                ratios.into_iter().flatten().flatten()
+            })
+            .collect();
+        if ratios.is_empty() {
+            return None;
+        }
+        let middle = ratios.len() / 2;
+        Some(
+            *ratios
+                .select_nth_unstable_by_key(middle, |&ratio| FloatOrd(ratio))
+                .1,
+        )
+    }
+}
+
+/// Matches every pair of images and keeps the pairs which have enough inliers to an essential matrix.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", name = "sfm::verify_pairs", skip_all, fields(images = images.len()))
+)]
+pub(crate) fn verify_pairs<D, M, CE, EE>(
+    images: &[SfmImage<D>],
+    matcher: &M,
+    essential_consensus: &mut CE,
+    essential_estimator: &EE,
+    min_matches: usize,
+    min_inliers: usize,
+) -> Vec<VerifiedPair>
+where
+    D: Sync,
+    M: Matcher<D> + Sync,
+    CE: Consensus<EE, FeatureMatch>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+{
+    let descriptors: Vec<&[D]> = images
+        .iter()
+        .map(|image| image.descriptors.as_slice())
+        .collect();
+    let matrix = match_all_pairs(matcher, &descriptors);
+    let mut pairs: Vec<(usize, usize)> = matrix.connected_pairs(min_matches).collect();
+    pairs.sort_unstable();
+    info!(
+        "verifying {} connected image pairs of {}",
+        pairs.len(),
+        matrix.pairs.len()
+    );
+
+    pairs
+        .into_iter()
+        .filter_map(|(a, b)| {
+            let matches = matrix.get(a, b)?;
+            let (pose, inliers) = essential_consensus.model_inliers(
+                essential_estimator,
+                matches
+                    .iter()
+                    .map(|&[fa, fb]| FeatureMatch(images[a].bearings[fa], images[b].bearings[fb])),
+            )?;
+            let matches: Vec<[usize; 2]> = inliers.into_iter().map(|ix| matches[ix]).collect();
+            if matches.len() < min_inliers {
+                return None;
+            }
+            Some(VerifiedPair {
+                images: (a, b),
+                pose,
+                matches,
+            })
+        })
+        .collect()
+}
+
+/// Builds the consistent tracks from the inliers of the verified pairs.
+pub(crate) fn build_tracks(pairs: &[VerifiedPair]) -> Vec<Track<usize>> {
+    let mut builder = TrackBuilder::new();
+    for pair in pairs {
+        builder.add_matches(pair.images.0, pair.images.1, &pair.matches);
+    }
+    let tracks = builder.build(2);
+    info!(
+        "built {} tracks and discarded {} conflicting tracks",
+        tracks.tracks.len(),
+        tracks.conflicting.len()
+    );
+    tracks.tracks
+}
+
+/// The state of a reconstruction while it is being built by a pipeline.
+pub(crate) struct Scene<'a, D> {
+    pub images: &'a [SfmImage<D>],
+    pub tracks: Vec<Track<usize>>,
+    /// The triangulated point of every track.
+    pub points: Vec<Option<WorldPoint>>,
+    /// The track of every feature in every image.
+    pub feature_tracks: Vec<HashMap<usize, usize>>,
+    pub poses: Vec<Option<WorldToCamera>>,
+    /// The two images which are held fixed in bundle adjustment to remove the gauge freedom.
+    pub gauge: (usize, usize),
+    /// The standard deviation of the measured depths relative to the square of the depth, or `None` if the scene is
+    /// not metric and the depths are ignored.
+    pub depth_noise: Option<f64>,
+}
+
+impl<'a, D> Scene<'a, D> {
+    pub fn new(
+        images: &'a [SfmImage<D>],
+        tracks: Vec<Track<usize>>,
+        gauge: (usize, usize),
+    ) -> Self {
+        let mut feature_tracks = vec![HashMap::new(); images.len()];
+        for (track_ix, track) in tracks.iter().enumerate() {
+            for &(image, feature) in &track.observations {
+                feature_tracks[image].insert(feature, track_ix);
+            }
+        }
+        Self {
+            images,
+            points: vec![None; tracks.len()],
+            tracks,
+            feature_tracks,
+            poses: vec![None; images.len()],
+            gauge,
+            depth_noise: None,
+        }
+    }
+
+    /// The standard deviation of a measured depth, if depths are used.
+    fn depth_deviation(&self, depth: f64) -> Option<f64> {
+        Some(self.depth_noise? * depth * depth)
+    }
+
+    /// The point of a track from the first registered observation with a measured depth, if depths are used.
+    fn depth_point(&self, track: usize) -> Option<WorldPoint> {
+        self.depth_noise?;
+        self.tracks[track]
+            .observations
+            .iter()
+            .find_map(|&(image, feature)| {
+                let pose = self.poses[image]?;
+                Some(
+                    pose.inverse()
+                        .transform(self.images[image].depth_point(feature)?),
+                )
+            })
+    }
+
+    /// The observations of a track in registered images, as pose and bearing.
+    fn registered_observations(
+        &self,
+        track: usize,
+    ) -> impl Iterator<Item = (WorldToCamera, UnitVector3<f64>)> + Clone + '_ {
+        self.tracks[track]
+            .observations
+            .iter()
+            .filter_map(move |&(image, feature)| {
+                Some((self.poses[image]?, self.images[image].bearings[feature]))
+            })
+    }
+
+    /// Checks that every registered observation of a point agrees with it, and that the rays from the observing
+    /// cameras meet at an angle of at least `min_angle`.
+    ///
+    /// If depths are used, points observed with a depth don't need to meet the angle, but every measured depth must
+    /// agree with the point within three standard deviations.
+    fn is_point_consistent(
+        &self,
+        track: usize,
+        point: WorldPoint,
+        max_cosine_distance: f64,
+        min_angle: f64,
+    ) -> bool {
+        let position = match point.point() {
+            Some(position) => position,
+            None => return false,
+        };
+        let consistent = self.registered_observations(track).all(|(pose, bearing)| {
+            1.0 - pose.transform(point).bearing().dot(&bearing) < max_cosine_distance
+        });
+        let mut depths = self.tracks[track]
+            .observations
+            .iter()
+            .filter_map(|&(image, feature)| {
+                let depth = self.images[image].depth(feature)?;
+                Some((self.poses[image]?, depth, self.depth_deviation(depth)?))
+            })
+            .peekable();
+        let measured = depths.peek().is_some();
+        let depths_agree = depths.all(|(pose, depth, deviation)| {
+            pose.transform(point)
+                .point()
+                .map_or(false, |camera| (camera.z - depth).abs() <= 3.0 * deviation)
+        });
+        if measured {
+            return consistent && depths_agree;
+        }
+        let rays: Vec<_> = self
+            .registered_observations(track)
+            .map(|(pose, _)| position.coords - pose.inverse().isometry().translation.vector)
+            .collect();
+        let max_angle = rays
+            .iter()
+            .enumerate()
+            .flat_map(|(ix, a)| rays[ix + 1..].iter().map(move |b| a.angle(b)))
+            .fold(0.0, f64::max);
+        consistent && max_angle >= min_angle
+    }
+
+    /// Triangulates every track without a point which is observed by at least two registered images, returning
+    /// the number of new points.
+    ///
+    /// If depths are used, tracks with a measured depth in a registered image are initialized from the depth when
+    /// they can't be triangulated, which only needs one registered image.
+    pub fn triangulate<T: TriangulatorObservations>(
+        &mut self,
+        triangulator: &T,
+        max_cosine_distance: f64,
+        min_angle: f64,
+    ) -> usize {
+        let mut added = 0;
+        for track in 0..self.tracks.len() {
+            if self.points[track].is_some() {
+                continue;
+            }
+            let triangulated = if self.registered_observations(track).count() >= 2 {
+                triangulator
+                    .triangulate_observations(self.registered_observations(track))
+                    .filter(|&point| {
+                        self.is_point_consistent(track, point, max_cosine_distance, min_angle)
+                    })
+            } else {
+                None
+            };
+            let point = triangulated.or_else(|| {
+                self.depth_point(track).filter(|&point| {
+                    self.is_point_consistent(track, point, max_cosine_distance, min_angle)
+                })
+            });
+            if let Some(point) = point {
+                self.points[track] = Some(point);
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Removes the points which are no longer consistent with their observations, returning the number removed.
+    ///
+    /// Removed tracks are triangulated again by the next call to [`Scene::triangulate`].
+    pub fn filter(&mut self, max_cosine_distance: f64, min_angle: f64) -> usize {
+        let mut removed = 0;
+        for track in 0..self.tracks.len() {
+            if let Some(point) = self.points[track] {
+                if !self.is_point_consistent(track, point, max_cosine_distance, min_angle) {
+                    self.points[track] = None;
+                    removed += 1;
+                }
+            }
+        }
+        removed
+    }
+
+    /// The 3d matches of the features of an image which belong to triangulated tracks.
+    pub fn world_matches(&self, image: usize) -> Vec<FeatureWorldMatch> {
+        let mut matches: Vec<(usize, FeatureWorldMatch)> = self.feature_tracks[image]
+            .iter()
+            .filter_map(|(&feature, &track)| {
+                Some((
+                    feature,
+                    FeatureWorldMatch(self.images[image].bearings[feature], self.points[track]?),
+                ))
+            })
+            .collect();
+        // Sort so that consensus sees the same order regardless of hash map ordering.
+        matches.sort_unstable_by_key(|&(feature, _)| feature);
+        matches.into_iter().map(|(_, m)| m).collect()
+    }
+
+    /// Jointly refines the registered poses and the triangulated points, along with the measured depths if depths
+    /// are used.
+    pub fn bundle_adjust(&mut self, adjuster: &BundleAdjuster) {
+        let registered: Vec<usize> = (0..self.images.len())
+            .filter(|&image| self.poses[image].is_some())
+            .collect();
+        let tracks: Vec<usize> = (0..self.tracks.len())
+            .filter(|&track| self.points[track].is_some())
+            .collect();
+        let pose_index: HashMap<usize, usize> = registered
+            .iter()
+            .enumerate()
+            .map(|(ix, &image)| (image, ix))
+            .collect();
+
+        let mut problem = BundleAdjustment::new(
+            registered
+                .iter()
+                .map(|&image| self.poses[image].unwrap())
+                .collect(),
+            tracks
+                .iter()
+                .map(|&track| self.points[track].unwrap())
+                .collect(),
+        );
+        problem.set_gauge(Gauge::FixPosePair(
+            pose_index[&self.gauge.0],
+            pose_index[&self.gauge.1],
+        ));
+        for (landmark, &track) in tracks.iter().enumerate() {
+            for &(image, feature) in &self.tracks[track].observations {
+                if let Some(&pose) = pose_index.get(&image) {
+                    problem.observe(cv_optimize::Observation::new(
+                        pose,
+                        landmark,
+                        self.images[image].bearings[feature],
+                    ));
+                    let depth = self.images[image].depth(feature);
+                    if let Some((depth, deviation)) =
+                        depth.and_then(|depth| Some((depth, self.depth_deviation(depth)?)))
+                    {
+                        problem.add_depth(DepthObservation::new(
+                            pose,
+                            landmark,
+                            depth,
+                            deviation.powi(-2),
+                        ));
+                    }
+                }
+            }
+        }
+
+        let report = adjuster.optimize(&mut problem);
+        info!(
+            "bundle adjusted {} poses and {} points: {:?}",
+            registered.len(),
+            tracks.len(),
+            report
+        );
+        for (&image, &pose) in registered.iter().zip(&problem.poses) {
+            self.poses[image] = Some(pose);
+        }
+        for (&track, &point) in tracks.iter().zip(&problem.landmarks) {
+            self.points[track] = Some(point);
+        }
+    }
+
+    pub fn into_reconstruction(self) -> SparseReconstruction {
+        let points = self
+            .tracks
+            .iter()
+            .zip(&self.points)
+            .filter_map(|(track, &point)| {
+                let point = point?;
+                let observations: Vec<Observation<usize>> = track
+                    .observations
+                    .iter()
+                    .copied()
+                    .filter(|&(image, _)| self.poses[image].is_some())
+                    .collect();
+                let (image, feature) = observations[0];
+                Some(SparsePoint {
+                    point,
+                    color: self.images[image].color(feature),
+                    observations,
+                })
+            })
+            .collect();
+        SparseReconstruction {
+            poses: self.poses,
+            points,
+        }
+    }
+}
+
+/// Reconstructs the poses of a set of images and a sparse point cloud by incremental structure from motion.
+///
+/// The pipeline:
+///
+/// 1. Matches every pair of images with the matcher and verifies the matches with an essential matrix.
+/// 2. Builds feature tracks from the verified matches with a [`TrackBuilder`].
+/// 3. Initializes from the verified pair with the most inliers which has enough parallax.
+/// 4. Registers the image which sees the most triangulated points with PnP and consensus, refines its pose with
+///    [`MotionOnly`], and triangulates the tracks it makes visible, until no more images can be registered.
+/// 5. Runs bundle adjustment every few registrations and once at the end, removing points which are inconsistent
+///    with their observations afterwards.
+///
+/// The essential matrix consensus and estimator are used on [`FeatureMatch`]es, while the PnP consensus and
+/// estimator are used on [`FeatureWorldMatch`]es, since they typically need different inlier thresholds.
+///
+/// If the initial pair has depths (see [`SfmImage::from_rgbd`]), the pair is scaled by the measured depths, tracks
+/// with a depth are initialized as soon as one of their images is registered, and the depths are factors of the
+/// bundle adjustment, so the reconstruction is metric.
+pub struct IncrementalSfm<M, CE, EE, CP, PE, T> {
+    pub matcher: M,
+    pub essential_consensus: CE,
+    pub essential_estimator: EE,
+    pub pnp_consensus: CP,
+    pub pnp_estimator: PE,
+    pub triangulator: T,
+    /// The adjuster used for bundle adjustment.
+    pub adjuster: BundleAdjuster,
+    /// The minimum number of descriptor matches for a pair of images to be verified.
+    pub min_matches: usize,
+    /// The minimum number of inliers to the essential matrix for a pair of images to be kept.
+    pub min_inliers: usize,
+    /// The minimum median parallax in radians of the initial pair.
+    pub min_init_parallax: f64,
+    /// The minimum angle in radians between the rays of a point for it to be triangulated.
+    pub min_triangulation_angle: f64,
+    /// The maximum cosine distance between an observation and its point.
+    pub max_cosine_distance: f64,
+    /// The minimum number of 3d matches (and inliers) to register an image.
+    pub min_pnp_matches: usize,
+    /// The number of registrations between each bundle adjustment.
+    pub bundle_adjust_interval: usize,
+    /// The standard deviation of the depths of images with depths relative to the square of the depth, which is
+    /// how the noise of structured light and time of flight sensors grows.
+    pub depth_noise: f64,
+}
+
+impl<M, CE, EE, CP, PE, T> IncrementalSfm<M, CE, EE, CP, PE, T> {
+    pub fn new(
+        matcher: M,
+        essential_consensus: CE,
+        essential_estimator: EE,
+        pnp_consensus: CP,
+        pnp_estimator: PE,
+        triangulator: T,
+    ) -> Self {
+        Self {
+            matcher,
+            essential_consensus,
+            essential_estimator,
+            pnp_consensus,
+            pnp_estimator,
+            triangulator,
+            adjuster: BundleAdjuster::new(),
+            min_matches: 30,
+            min_inliers: 20,
+            min_init_parallax: 0.03,
+            min_triangulation_angle: 0.02,
+            max_cosine_distance: 5e-6,
+            min_pnp_matches: 12,
+            bundle_adjust_interval: 5,
+            depth_noise: 1.5e-3,
+        }
+    }
+
+    #[must_use]
+    pub fn adjuster(self, adjuster: BundleAdjuster) -> Self {
+        Self { adjuster, ..self }
+    }
+
+    #[must_use]
+    pub fn min_matches(self, min_matches: usize) -> Self {
+        Self {
+            min_matches,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn min_inliers(self, min_inliers: usize) -> Self {
+        Self {
+            min_inliers,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn min_init_parallax(self, min_init_parallax: f64) -> Self {
+        Self {
+            min_init_parallax,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn min_triangulation_angle(self, min_triangulation_angle: f64) -> Self {
+        Self {
+            min_triangulation_angle,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn max_cosine_distance(self, max_cosine_distance: f64) -> Self {
+        Self {
+            max_cosine_distance,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn min_pnp_matches(self, min_pnp_matches: usize) -> Self {
+        Self {
+            min_pnp_matches,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn bundle_adjust_interval(self, bundle_adjust_interval: usize) -> Self {
+        Self {
+            bundle_adjust_interval,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn depth_noise(self, depth_noise: f64) -> Self {
+        Self {
+            depth_noise,
+            ..self
+        }
+    }
+
+    /// Reconstructs the images.
+    ///
+    /// Returns `None` if no pair of images could initialize the reconstruction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "sfm::reconstruct", skip_all, fields(images = images.len()))
+    )]
+    pub fn reconstruct<D>(&mut self, images: &[SfmImage<D>]) -> Option<SparseReconstruction>
+    where
+        D: Sync,
+        M: Matcher<D> + Sync,
+        CE: Consensus<EE, FeatureMatch>,
+        EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+        CP: Consensus<PE, FeatureWorldMatch>,
+        PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+        T: TriangulatorObservations,
+    {
+        let pairs = verify_pairs(
+            images,
+            &self.matcher,
+            &mut self.essential_consensus,
+            &self.essential_estimator,
+            self.min_matches,
+            self.min_inliers,
+        );
+        info!("verified {} image pairs", pairs.len());
+
+        let init = pairs
+            .iter()
+            .filter(|pair| pair.median_parallax(images) >= self.min_init_parallax)
+            .max_by_key(|pair| pair.matches.len())
+            .or_else(|| {
+                info!("no verified pair had enough parallax to initialize");
+                None
+            })?;
+        info!(
+            "initializing from images {:?} with {} inliers",
+            init.images,
+            init.matches.len()
+        );
+
+        let mut scene = Scene::new(images, build_tracks(&pairs), init.images);
+        scene.poses[init.images.0] = Some(WorldToCamera::identity());
+        let mut pose = init.pose.0;
+        // Scale the initial pair with the measured depths to make the whole reconstruction metric.
+        if let Some(scale) = init.depth_scale(images, &self.triangulator) {
+            info!("initializing with a metric scale of {} from depths", scale);
+            pose.translation.vector *= scale;
+            scene.depth_noise = Some(self.depth_noise);
+        }
+        scene.poses[init.images.1] = Some(WorldToCamera(pose));
+        let triangulated = scene.triangulate(
+            &self.triangulator,
+            self.max_cosine_distance,
+            self.min_triangulation_angle,
+        );
+        if triangulated < self.min_pnp_matches {
+            info!(
+                "only triangulated {} points from the initial pair, need {}",
+                triangulated, self.min_pnp_matches
+            );
+            return None;
+        }
+        scene.bundle_adjust(&self.adjuster);
+
+        let mut failed = vec![false; images.len()];
+        let mut since_bundle_adjust = 0;
+        while let Some(image) = self.next_image(&scene, &failed) {
+            let matches = scene.world_matches(image);
+            let registered = self
+                .pnp_consensus
+                .model_inliers(&self.pnp_estimator, matches.iter().copied())
+                .map(|(pose, inliers)| {
+                    let inliers: Vec<FeatureWorldMatch> =
+                        inliers.into_iter().map(|ix| matches[ix]).collect();
+                    (pose, inliers)
+                })
+                .filter(|(_, inliers)| inliers.len() >= self.min_pnp_matches);
+            let (pose, inliers) = match registered {
+                Some(registered) => registered,
+                None => {
+                    info!("failed to register image {}", image);
+                    failed[image] = true;
+                    continue;
+                }
+            };
+            let pose = MotionOnly::new().refine(pose, &inliers);
+            scene.poses[image] = Some(pose);
+            // New points may let the images which failed before be registered.
+            failed.iter_mut().for_each(|failed| *failed = false);
+
+            let triangulated = scene.triangulate(
+                &self.triangulator,
+                self.max_cosine_distance,
+                self.min_triangulation_angle,
+            );
+            info!(
+                "registered image {} with {} inliers and triangulated {} new points",
+                image,
+                inliers.len(),
+                triangulated
+            );
+
+            since_bundle_adjust += 1;
+            if since_bundle_adjust >= self.bundle_adjust_interval {
+                since_bundle_adjust = 0;
+                self.refine(&mut scene);
+            }
+        }
+        self.refine(&mut scene);
+
+        let reconstruction = scene.into_reconstruction();
+        info!(
+            "reconstructed {} of {} images with {} points",
+            reconstruction.num_registered(),
+            images.len(),
+            reconstruction.points.len()
+        );
+        Some(reconstruction)
+    }
+
+    /// The unregistered image which sees the most triangulated points, if it sees enough to be registered.
+    fn next_image<D>(&self, scene: &Scene<'_, D>, failed: &[bool]) -> Option<usize> {
+        (0..scene.images.len())
+            .filter(|&image| scene.poses[image].is_none() && !failed[image])
+            .map(|image| {
+                let visible = scene.feature_tracks[image]
+                    .values()
+                    .filter(|&&track| scene.points[track].is_some())
+                    .count();
+                (visible, image)
+            })
+            .filter(|&(visible, _)| visible >= self.min_pnp_matches)
+            .max()
+            .map(|(_, image)| image)
+    }
+
+    /// Bundle adjusts the scene, then removes the inconsistent points and triangulates the tracks again.
+    fn refine<D>(&self, scene: &mut Scene<'_, D>)
+    where
+        T: TriangulatorObservations,
+    {
+        scene.bundle_adjust(&self.adjuster);
+        let removed = scene.filter(self.max_cosine_distance, self.min_triangulation_angle);
+        let triangulated = scene.triangulate(
+            &self.triangulator,
+            self.max_cosine_distance,
+            self.min_triangulation_angle,
+        );
+        info!(
+            "removed {} inconsistent points and triangulated {} points after bundle adjustment",
+            removed, triangulated
+        );
+    }
+}
@@ -0,0 +1,464 @@
+//! Export of point clouds to PLY, PCD and LAS files.
+//!
+//! Points are written with their color and, when every point of the cloud has them, their normal and covariance.
+//! PLY stores the normals as `nx`, `ny` and `nz` and the upper triangle of the covariance as `cov_xx`, `cov_xy`,
+//! `cov_xz`, `cov_yy`, `cov_yz` and `cov_zz`. PCD stores the normals as `normal_x`, `normal_y` and `normal_z` and the
+//! covariance as a `covariance` field with the same six values. LAS has no place for either and only stores the
+//! positions and colors.
+//!
+//! A georeferenced point cloud (see [`PointCloud::georeferenced`]) has its points in east, north and up in meters from
+//! its datum. PLY and PCD keep these local coordinates, since PCD only stores single precision, and record the datum in
+//! a `datum <latitude> <longitude> <altitude>` comment. LAS stores the points in earth-centered, earth-fixed
+//! coordinates, which its integer coordinates can hold to the millimeter, along with the GeoTIFF key of the geocentric
+//! WGS 84 coordinate system (EPSG:4978) when the datum is on the WGS 84 ellipsoid.
+
+use crate::{ReconstructionKey, SparseReconstruction, VSlam};
+use cv_core::{
+    nalgebra::{Matrix3, Point3, Vector3},
+    point_covariance,
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, ObservationCovariance, Pose, Projective,
+    TriangulatorObservations, WorldPoint, WorldToCamera,
+};
+use cv_optimize::{Ellipsoid, LocalTangentPlane};
+use ply_rs::{
+    ply::{
+        Addable, DefaultElement, ElementDef, Encoding, Ply, Property, PropertyDef, PropertyType,
+        ScalarType,
+    },
+    writer::Writer,
+};
+use rand::Rng;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// The encoding of the data of a PLY or PCD file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PointCloudEncoding {
+    Ascii,
+    Binary,
+}
+
+/// A point of a [`PointCloud`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CloudPoint {
+    pub position: Point3<f64>,
+    pub color: [u8; 3],
+    pub normal: Option<Vector3<f64>>,
+    pub covariance: Option<Matrix3<f64>>,
+}
+
+impl CloudPoint {
+    /// Creates a point with a color and no normal or covariance.
+    pub fn new(position: Point3<f64>, color: [u8; 3]) -> Self {
+        Self {
+            position,
+            color,
+            normal: None,
+            covariance: None,
+        }
+    }
+}
+
+/// A colored point cloud, such as the landmarks of a reconstruction.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PointCloud {
+    pub points: Vec<CloudPoint>,
+    /// The local tangent plane the points are in, if the point cloud is georeferenced.
+    pub datum: Option<LocalTangentPlane>,
+}
+
+impl PointCloud {
+    /// Creates a point cloud from its points.
+    pub fn new(points: Vec<CloudPoint>) -> Self {
+        Self {
+            points,
+            datum: None,
+        }
+    }
+
+    /// The position of a point in earth-centered, earth-fixed coordinates if the point cloud is georeferenced.
+    pub fn ecef(&self, point: &CloudPoint) -> Option<Vector3<f64>> {
+        let datum = self.datum?;
+        Some(
+            datum
+                .to_geodetic(point.position.coords)
+                .to_ecef(&datum.ellipsoid),
+        )
+    }
+
+    /// The comment which records the datum.
+    fn datum_comment(&self) -> Option<String> {
+        let datum = self.datum?.datum;
+        Some(format!(
+            "datum {} {} {}",
+            datum.latitude, datum.longitude, datum.altitude
+        ))
+    }
+
+    /// Returns `true` if every point has a normal and there is at least one point.
+    pub fn has_normals(&self) -> bool {
+        !self.points.is_empty() && self.points.iter().all(|point| point.normal.is_some())
+    }
+
+    /// Returns `true` if every point has a covariance and there is at least one point.
+    pub fn has_covariances(&self) -> bool {
+        !self.points.is_empty() && self.points.iter().all(|point| point.covariance.is_some())
+    }
+
+    /// Writes the point cloud as a PLY file.
+    pub fn write_ply(
+        &self,
+        mut writer: impl Write,
+        encoding: PointCloudEncoding,
+    ) -> io::Result<()> {
+        let mut ply = Ply::<DefaultElement>::new();
+        ply.header.encoding = match encoding {
+            PointCloudEncoding::Ascii => Encoding::Ascii,
+            PointCloudEncoding::Binary => Encoding::BinaryLittleEndian,
+        };
+        ply.header
+            .comments
+            .push("Exported from rust-cv/cv-sfm".to_string());
+        ply.header.comments.extend(self.datum_comment());
+
+        let mut names: Vec<&str> = vec!["x", "y", "z"];
+        let normals = self.has_normals();
+        if normals {
+            names.extend(["nx", "ny", "nz"]);
+        }
+        let covariances = self.has_covariances();
+        if covariances {
+            names.extend(COVARIANCE_NAMES);
+        }
+        let mut vertex_element = ElementDef::new("vertex".to_string());
+        for &name in &names {
+            vertex_element.properties.add(PropertyDef::new(
+                name.to_string(),
+                PropertyType::Scalar(ScalarType::Double),
+            ));
+        }
+        for name in ["red", "green", "blue"] {
+            vertex_element.properties.add(PropertyDef::new(
+                name.to_string(),
+                PropertyType::Scalar(ScalarType::UChar),
+            ));
+        }
+        ply.header.elements.add(vertex_element);
+
+        let vertices = self
+            .points
+            .iter()
+            .map(|point| {
+                let mut vertex = DefaultElement::new();
+                for (&name, value) in names.iter().zip(point_values(point, normals, covariances)) {
+                    vertex.insert(name.to_string(), Property::Double(value));
+                }
+                for (name, &value) in ["red", "green", "blue"].iter().zip(&point.color) {
+                    vertex.insert(name.to_string(), Property::UChar(value));
+                }
+                vertex
+            })
+            .collect();
+        ply.payload.insert("vertex".to_string(), vertices);
+
+        Writer::new().write_ply(&mut writer, &mut ply)?;
+        Ok(())
+    }
+
+    /// Writes the point cloud as a PCD file of version 0.7.
+    ///
+    /// The color is packed into an unsigned `rgb` field as is common for PCD files.
+    pub fn write_pcd(
+        &self,
+        mut writer: impl Write,
+        encoding: PointCloudEncoding,
+    ) -> io::Result<()> {
+        let normals = self.has_normals();
+        let covariances = self.has_covariances();
+        let mut fields = vec![("x", 1), ("y", 1), ("z", 1)];
+        if normals {
+            fields.extend([("normal_x", 1), ("normal_y", 1), ("normal_z", 1)]);
+        }
+        if covariances {
+            fields.push(("covariance", 6));
+        }
+        let names: Vec<&str> = fields.iter().map(|&(name, _)| name).collect();
+        let counts: Vec<String> = fields.iter().map(|&(_, count)| count.to_string()).collect();
+
+        writeln!(writer, "# .PCD v0.7 - Point Cloud Data file format")?;
+        if let Some(comment) = self.datum_comment() {
+            writeln!(writer, "# {}", comment)?;
+        }
+        writeln!(writer, "VERSION 0.7")?;
+        writeln!(writer, "FIELDS {} rgb", names.join(" "))?;
+        writeln!(writer, "SIZE {}4", "4 ".repeat(fields.len()))?;
+        writeln!(writer, "TYPE {}U", "F ".repeat(fields.len()))?;
+        writeln!(writer, "COUNT {} 1", counts.join(" "))?;
+        writeln!(writer, "WIDTH {}", self.points.len())?;
+        writeln!(writer, "HEIGHT 1")?;
+        writeln!(writer, "VIEWPOINT 0 0 0 1 0 0 0")?;
+        writeln!(writer, "POINTS {}", self.points.len())?;
+        match encoding {
+            PointCloudEncoding::Ascii => writeln!(writer, "DATA ascii")?,
+            PointCloudEncoding::Binary => writeln!(writer, "DATA binary")?,
+        }
+
+        for point in &self.points {
+            let [r, g, b] = point.color;
+            let rgb = u32::from_be_bytes([0, r, g, b]);
+            let values = point_values(point, normals, covariances);
+            match encoding {
+                PointCloudEncoding::Ascii => {
+                    for value in values {
+                        write!(writer, "{} ", value as f32)?;
+                    }
+                    writeln!(writer, "{}", rgb)?;
+                }
+                PointCloudEncoding::Binary => {
+                    for value in values {
+                        writer.write_all(&(value as f32).to_le_bytes())?;
+                    }
+                    writer.write_all(&rgb.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the point cloud as a LAS 1.2 file with point data format 2.
+    ///
+    /// The coordinates are stored as integers relative to the minimum of the cloud, with a power of ten scale chosen
+    /// to keep the full precision available to the extent of the cloud. Georeferenced point clouds are stored in
+    /// earth-centered, earth-fixed coordinates.
+    pub fn write_las(&self, mut writer: impl Write) -> io::Result<()> {
+        const HEADER_SIZE: u16 = 227;
+        const RECORD_LENGTH: u16 = 26;
+        const RECORD_HEADER_SIZE: u32 = 54;
+
+        let positions: Vec<Vector3<f64>> = self
+            .points
+            .iter()
+            .map(|point| self.ecef(point).unwrap_or(point.position.coords))
+            .collect();
+        // The GeoTIFF keys of a geocentric model in the geocentric WGS 84 coordinate system measured in meters.
+        let geo_keys: Option<[u16; 16]> = self
+            .datum
+            .filter(|datum| datum.ellipsoid == Ellipsoid::WGS84)
+            .map(|_| {
+                [
+                    1, 1, 0, 3, 1024, 0, 1, 3, 2048, 0, 1, 4978, 2052, 0, 1, 9001,
+                ]
+            });
+        let records_size = geo_keys.map_or(0, |keys| RECORD_HEADER_SIZE + 2 * keys.len() as u32);
+
+        let (min, max) = if positions.is_empty() {
+            (Vector3::zeros(), Vector3::zeros())
+        } else {
+            positions.iter().fold(
+                (
+                    Vector3::repeat(f64::INFINITY),
+                    Vector3::repeat(f64::NEG_INFINITY),
+                ),
+                |(min, max), position| (min.inf(position), max.sup(position)),
+            )
+        };
+        let extent = (max - min).max().max(f64::MIN_POSITIVE);
+        let scale = 10.0f64.powf((extent / i32::MAX as f64).log10().ceil());
+
+        writer.write_all(b"LASF")?;
+        // File source id, global encoding and the project GUID.
+        writer.write_all(&[0; 2 + 2 + 16])?;
+        writer.write_all(&[1, 2])?;
+        writer.write_all(&padded::<32>(b"rust-cv"))?;
+        writer.write_all(&padded::<32>(b"cv-sfm"))?;
+        // The creation day of year and year are unknown.
+        writer.write_all(&[0; 4])?;
+        writer.write_all(&HEADER_SIZE.to_le_bytes())?;
+        writer.write_all(&(HEADER_SIZE as u32 + records_size).to_le_bytes())?;
+        writer.write_all(&(geo_keys.is_some() as u32).to_le_bytes())?;
+        writer.write_all(&[2])?;
+        writer.write_all(&RECORD_LENGTH.to_le_bytes())?;
+        let count = self.points.len() as u32;
+        writer.write_all(&count.to_le_bytes())?;
+        for returns in [count, 0, 0, 0, 0] {
+            writer.write_all(&returns.to_le_bytes())?;
+        }
+        for value in [scale, scale, scale, min.x, min.y, min.z] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for value in [max.x, min.x, max.y, min.y, max.z, min.z] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        if let Some(keys) = geo_keys {
+            // The reserved field, user id, record id, record length and description of the GeoKeyDirectoryTag record.
+            writer.write_all(&0u16.to_le_bytes())?;
+            writer.write_all(&padded::<16>(b"LASF_Projection"))?;
+            writer.write_all(&34735u16.to_le_bytes())?;
+            writer.write_all(&(2 * keys.len() as u16).to_le_bytes())?;
+            writer.write_all(&padded::<32>(b"GeoKeyDirectoryTag"))?;
+            for key in keys {
+                writer.write_all(&key.to_le_bytes())?;
+            }
+        }
+
+        for (point, position) in self.points.iter().zip(&positions) {
+            let relative = (position - min) / scale;
+            for value in relative.iter() {
+                writer.write_all(&(value.round() as i32).to_le_bytes())?;
+            }
+            // The intensity is unknown.
+            writer.write_all(&0u16.to_le_bytes())?;
+            // The point is the first of one return, and its classification, scan angle, user data and source are
+            // unknown.
+            writer.write_all(&[1 | 1 << 3, 0, 0, 0])?;
+            writer.write_all(&0u16.to_le_bytes())?;
+            for channel in point.color {
+                writer.write_all(&(channel as u16 * 257).to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the point cloud to a PLY file.
+    pub fn save_ply(&self, path: impl AsRef<Path>, encoding: PointCloudEncoding) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_ply(&mut writer, encoding)?;
+        writer.flush()
+    }
+
+    /// Writes the point cloud to a PCD file.
+    pub fn save_pcd(&self, path: impl AsRef<Path>, encoding: PointCloudEncoding) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_pcd(&mut writer, encoding)?;
+        writer.flush()
+    }
+
+    /// Writes the point cloud to a LAS file.
+    pub fn save_las(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_las(&mut writer)?;
+        writer.flush()
+    }
+}
+
+const COVARIANCE_NAMES: [&str; 6] = ["cov_xx", "cov_xy", "cov_xz", "cov_yy", "cov_yz", "cov_zz"];
+
+/// The position followed by the normal and the upper triangle of the covariance, if they are written.
+fn point_values(point: &CloudPoint, normals: bool, covariances: bool) -> Vec<f64> {
+    let mut values: Vec<f64> = point.position.coords.iter().copied().collect();
+    if normals {
+        values.extend(point.normal.unwrap_or_else(Vector3::zeros).iter());
+    }
+    if covariances {
+        let c = point.covariance.unwrap_or_else(Matrix3::zeros);
+        values.extend([
+            c[(0, 0)],
+            c[(0, 1)],
+            c[(0, 2)],
+            c[(1, 1)],
+            c[(1, 2)],
+            c[(2, 2)],
+        ]);
+    }
+    values
+}
+
+/// Pads a string with zeros to a fixed size character array.
+fn padded<const N: usize>(text: &[u8]) -> [u8; N] {
+    let mut padded = [0; N];
+    padded[..text.len()].copy_from_slice(text);
+    padded
+}
+
+/// Creates a point with the mean direction from the point to the cameras which observe it as its normal.
+///
+/// If `bearing_variance` is given, the covariance of the point is propagated from it with [`point_covariance`].
+fn cloud_point(
+    point: WorldPoint,
+    color: [u8; 3],
+    poses: impl Iterator<Item = WorldToCamera> + Clone,
+    bearing_variance: Option<f64>,
+) -> Option<CloudPoint> {
+    let position = point.point()?;
+    let normal = poses
+        .clone()
+        .map(|pose| (pose.inverse().isometry().translation.vector - position.coords).normalize())
+        .sum::<Vector3<f64>>()
+        .try_normalize(f64::EPSILON);
+    let covariance = bearing_variance.and_then(|variance| {
+        point_covariance(
+            point,
+            poses.map(|pose| (pose, ObservationCovariance::bearing(variance))),
+        )
+    });
+    Some(CloudPoint {
+        position,
+        color,
+        normal,
+        covariance,
+    })
+}
+
+impl SparseReconstruction {
+    /// Creates a point cloud from the points of the reconstruction.
+    ///
+    /// The normal of each point points towards the cameras which observe it. If `bearing_variance` is given in
+    /// squared radians, the covariance of each point is propagated from the bearings of its observations.
+    pub fn point_cloud(&self, bearing_variance: Option<f64>) -> PointCloud {
+        PointCloud::new(
+            self.points
+                .iter()
+                .filter_map(|point| {
+                    let poses = point
+                        .observations
+                        .iter()
+                        .filter_map(|&(image, _)| self.poses[image]);
+                    cloud_point(point.point, point.color, poses, bearing_variance)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Creates a point cloud from the robust landmarks of a reconstruction.
+    ///
+    /// The normal of each point points towards the views which observe it. If `bearing_variance` is given in squared
+    /// radians, the covariance of each point is propagated from the bearings of its observations.
+    pub fn point_cloud(
+        &self,
+        reconstruction: ReconstructionKey,
+        bearing_variance: Option<f64>,
+    ) -> PointCloud {
+        PointCloud::new(
+            self.data
+                .reconstruction(reconstruction)
+                .landmarks
+                .iter()
+                .filter_map(|(landmark, landmark_object)| {
+                    let point = self.triangulate_landmark_robust(reconstruction, landmark)?;
+                    let (&view, &feature) = landmark_object.observations.iter().next()?;
+                    let color = self.data.observation_color(reconstruction, view, feature);
+                    let poses = landmark_object
+                        .observations
+                        .keys()
+                        .map(|&view| self.data.pose(reconstruction, view));
+                    cloud_point(point, color, poses, bearing_variance)
+                })
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,233 @@
+//! Statistics about the quality of a reconstruction.
+//!
+//! A [`QualityReport`] summarizes how well a reconstruction is constrained: how far its robust landmarks reproject
+//! from their observations in pixels, how long the tracks of its landmarks are, how many observations of each view
+//! agree with the reconstruction and how well its views are connected by shared landmarks. With the
+//! `serde-serialize` feature the report can be serialized to JSON for dashboards.
+
+use crate::{FrameKey, LandmarkKey, ReconstructionKey, VSlam, ViewKey};
+use cv_core::{
+    nalgebra::UnitVector3,
+    sample_consensus::{Consensus, Estimator},
+    CameraModel, CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Projective,
+    TriangulatorObservations, WorldPoint, WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsicsK1Distortion;
+use float_ord::FloatOrd;
+use rand::Rng;
+use std::collections::HashMap;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// Statistics about the quality of a reconstruction.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct QualityReport {
+    /// The number of views.
+    pub views: usize,
+    /// The number of landmarks, including those with a single observation.
+    pub landmarks: usize,
+    /// The number of landmarks which could be triangulated robustly.
+    pub robust_landmarks: usize,
+    /// The number of observations of robust landmarks.
+    pub observations: usize,
+    /// The mean reprojection error in pixels of the observations of robust landmarks.
+    pub mean_reprojection_error: f64,
+    /// The median reprojection error in pixels of the observations of robust landmarks.
+    pub median_reprojection_error: f64,
+    /// The number of landmarks with each number of observations, so `track_lengths[n]` landmarks have `n`.
+    pub track_lengths: Vec<usize>,
+    /// The statistics of each view.
+    pub cameras: Vec<CameraQuality>,
+    /// The connectivity of the views by shared robust landmarks.
+    pub covisibility: CovisibilityQuality,
+}
+
+/// Statistics about the observations of a single view.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct CameraQuality {
+    pub view: ViewKey,
+    pub frame: FrameKey,
+    /// The number of features of the frame.
+    pub features: usize,
+    /// The number of features which observe a robust landmark.
+    pub observations: usize,
+    /// The number of observations which reproject within the inlier threshold.
+    pub inliers: usize,
+    /// The fraction of the observations which are inliers, or `0.0` if there are none.
+    pub inlier_ratio: f64,
+    /// The mean reprojection error in pixels of the observations, or `0.0` if there are none.
+    pub mean_reprojection_error: f64,
+}
+
+/// The connectivity of the covisibility graph, where an edge connects two views which share at least
+/// `minimum_landmarks` robust landmarks.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct CovisibilityQuality {
+    /// The number of shared robust landmarks for two views to be connected.
+    pub minimum_landmarks: usize,
+    /// The number of edges.
+    pub edges: usize,
+    /// The mean number of edges of a view.
+    pub mean_degree: f64,
+    /// The number of connected components, which is `1` for a fully connected reconstruction.
+    pub components: usize,
+    /// The number of views in the largest connected component.
+    pub largest_component: usize,
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Computes the statistics of a reconstruction.
+    ///
+    /// Landmarks are triangulated robustly (see [`VSlam::triangulate_landmark_robust`]), and an observation is an
+    /// inlier if it reprojects within `inlier_threshold` pixels. Views are connected in the covisibility graph if
+    /// they share `optimization_robust_covisibility_minimum_landmarks` robust landmarks, as they are for
+    /// optimization.
+    pub fn quality_report(
+        &self,
+        reconstruction: ReconstructionKey,
+        inlier_threshold: f64,
+    ) -> QualityReport {
+        let data = self.data.reconstruction(reconstruction);
+
+        let mut track_lengths = vec![];
+        for landmark in data.landmarks.values() {
+            let length = landmark.observations.len();
+            if track_lengths.len() <= length {
+                track_lengths.resize(length + 1, 0);
+            }
+            track_lengths[length] += 1;
+        }
+
+        let points: HashMap<LandmarkKey, WorldPoint> = data
+            .landmarks
+            .keys()
+            .filter_map(|landmark| {
+                Some((
+                    landmark,
+                    self.triangulate_landmark_robust(reconstruction, landmark)?,
+                ))
+            })
+            .collect();
+
+        let mut errors = vec![];
+        let mut observations = 0;
+        let cameras = data
+            .views
+            .iter()
+            .map(|(view, view_data)| {
+                let intrinsics = self
+                    .data
+                    .feed(self.data.frame(view_data.frame).feed)
+                    .intrinsics;
+                let mut view_observations = 0;
+                let mut view_errors = vec![];
+                for (feature, landmark) in view_data.landmarks.iter().enumerate() {
+                    let point = match points.get(landmark) {
+                        Some(&point) => point,
+                        None => continue,
+                    };
+                    view_observations += 1;
+                    let bearing = self.data.observation_bearing(reconstruction, view, feature);
+                    if let Some(error) =
+                        reprojection_error(&intrinsics, view_data.pose, bearing, point)
+                    {
+                        view_errors.push(error);
+                    }
+                }
+                let inliers = view_errors
+                    .iter()
+                    .filter(|&&error| error < inlier_threshold)
+                    .count();
+                observations += view_observations;
+                errors.extend_from_slice(&view_errors);
+                CameraQuality {
+                    view,
+                    frame: view_data.frame,
+                    features: view_data.landmarks.len(),
+                    observations: view_observations,
+                    inliers,
+                    inlier_ratio: ratio(inliers as f64, view_observations),
+                    mean_reprojection_error: ratio(view_errors.iter().sum(), view_errors.len()),
+                }
+            })
+            .collect();
+
+        let mean_reprojection_error = ratio(errors.iter().sum(), errors.len());
+        let median_reprojection_error = if errors.is_empty() {
+            0.0
+        } else {
+            let middle = errors.len() / 2;
+            *errors
+                .select_nth_unstable_by_key(middle, |&error| FloatOrd(error))
+                .1
+        };
+
+        QualityReport {
+            views: data.views.len(),
+            landmarks: data.landmarks.len(),
+            robust_landmarks: points.len(),
+            observations,
+            mean_reprojection_error,
+            median_reprojection_error,
+            track_lengths,
+            cameras,
+            covisibility: self.covisibility_quality(reconstruction, &points),
+        }
+    }
+
+    /// The connectivity of the views by the robust landmarks in `points`.
+    fn covisibility_quality(
+        &self,
+        reconstruction: ReconstructionKey,
+        points: &HashMap<LandmarkKey, WorldPoint>,
+    ) -> CovisibilityQuality {
+        let minimum_landmarks = self
+            .settings
+            .optimization_robust_covisibility_minimum_landmarks;
+        let graph = self
+            .covisibility_graph_of(reconstruction, points.keys().copied())
+            .filtered(minimum_landmarks);
+        let edges = graph.edges().count();
+        let components = graph.components();
+        CovisibilityQuality {
+            minimum_landmarks,
+            edges,
+            mean_degree: ratio(2.0 * edges as f64, graph.len()),
+            components: components.len(),
+            largest_component: components.first().map_or(0, Vec::len),
+        }
+    }
+}
+
+/// The distance in pixels between an observation and the projection of its point.
+fn reprojection_error(
+    intrinsics: &CameraIntrinsicsK1Distortion,
+    pose: WorldToCamera,
+    bearing: UnitVector3<f64>,
+    point: WorldPoint,
+) -> Option<f64> {
+    let observed = intrinsics.uncalibrate(bearing)?;
+    let projected = intrinsics.uncalibrate(pose.transform(point).bearing())?;
+    Some((observed.0 - projected.0).norm())
+}
+
+/// Divides, returning `0.0` if there is nothing to divide by.
+fn ratio(numerator: f64, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator / denominator as f64
+    }
+}
@@ -0,0 +1,216 @@
+//! Relocalization of images against an existing reconstruction.
+
+use crate::{
+    matching::{BruteForceMatcher, Matcher},
+    opeek, Feature, FeedKey, LandmarkKey, ReconstructionKey, SfmImage, VSlam, ViewKey,
+};
+use bitarray::{BitArray, Hamming};
+use cv_consensus::{ConsensusReport, ReportingConsensus};
+use cv_core::{
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, TriangulatorObservations, WorldToCamera,
+};
+use cv_optimize::MotionOnly;
+use image::DynamicImage;
+use log::*;
+use rand::Rng;
+use space::KnnMap;
+use std::collections::HashMap;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// Statistics about a successful [`VSlam::localize`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct Localization {
+    /// The view of the reconstruction which the image was localized against.
+    pub view: ViewKey,
+    /// The number of candidate views which were tried.
+    pub candidates: usize,
+    /// The number of features of the image matched to robust landmarks of `view`.
+    pub matches: usize,
+    /// The number of matches which agreed with the pose.
+    pub inliers: usize,
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Localizes the features of an image against an existing reconstruction without modifying it.
+    ///
+    /// The views of the reconstruction most similar to the image are retrieved from the place recognition index.
+    /// The descriptors of the image are matched against each view, the matches to robust landmarks are used to
+    /// estimate the pose with PnP in sample consensus, and the pose is refined with [`MotionOnly`]. The pose with the
+    /// most inliers is returned, so this can be used to recover tracking after it was lost or to resume a session
+    /// in a saved map.
+    ///
+    /// Returns `None` if no view gave at least `relocalization_minimum_inliers` inliers.
+    pub fn localize(
+        &self,
+        reconstruction: ReconstructionKey,
+        image: &SfmImage<BitArray<64>>,
+    ) -> Option<(WorldToCamera, Localization)> {
+        self.localize_with(reconstruction, image, |_, matches| {
+            self.single_view_consensus
+                .borrow_mut()
+                .model_inliers(&self.world_to_camera_estimator, matches.iter().copied())
+                .map(|(pose, inliers)| (pose, inliers.into_iter().collect()))
+        })
+    }
+
+    /// Extracts the features of an image from a feed and localizes it with [`VSlam::localize`].
+    pub fn localize_image(
+        &self,
+        reconstruction: ReconstructionKey,
+        feed: FeedKey,
+        image: &DynamicImage,
+    ) -> Option<(WorldToCamera, Localization)> {
+        let features = self.kps_descriptors(&self.data.feeds[feed].intrinsics, image);
+        self.localize(reconstruction, &features_image(features))
+    }
+
+    /// Localizes against each candidate view, where `consensus` estimates the pose and its inliers from the matches.
+    fn localize_with(
+        &self,
+        reconstruction: ReconstructionKey,
+        image: &SfmImage<BitArray<64>>,
+        mut consensus: impl FnMut(ViewKey, &[FeatureWorldMatch]) -> Option<(WorldToCamera, Vec<usize>)>,
+    ) -> Option<(WorldToCamera, Localization)> {
+        let lsh = self.data.hasher.hash_bag(image.descriptors.iter());
+        let candidates: Vec<ViewKey> = self
+            .data
+            .lsh_to_frame
+            .knn_values(&lsh, self.settings.relocalization_search_num)
+            .into_iter()
+            .filter_map(|(_, &frame)| match self.data.frame(frame).view {
+                Some((found_reconstruction, view)) if found_reconstruction == reconstruction => {
+                    Some(view)
+                }
+                _ => None,
+            })
+            .take(self.settings.relocalization_candidates)
+            .collect();
+        info!("trying to relocalize against {} views", candidates.len());
+
+        let matcher = BruteForceMatcher::new(Hamming);
+        let mut best: Option<(WorldToCamera, Localization)> = None;
+        for &view in &candidates {
+            let frame = self.data.view_frame(reconstruction, view);
+            let descriptors: Vec<BitArray<64>> = (0..frame.descriptor_features.len())
+                .map(|feature| *frame.descriptor(feature))
+                .collect();
+
+            // Only keep one match to each landmark.
+            let mut landmark_features: HashMap<LandmarkKey, usize> = HashMap::new();
+            for descriptor_match in matcher.match_descriptors(&image.descriptors, &descriptors) {
+                let landmark =
+                    self.data
+                        .observation_landmark(reconstruction, view, descriptor_match.target);
+                landmark_features
+                    .entry(landmark)
+                    .or_insert(descriptor_match.query);
+            }
+            let mut matches: Vec<(usize, FeatureWorldMatch)> = landmark_features
+                .into_iter()
+                .filter_map(|(landmark, feature)| {
+                    Some((
+                        feature,
+                        FeatureWorldMatch(
+                            image.bearings[feature],
+                            self.triangulate_landmark_robust(reconstruction, landmark)?,
+                        ),
+                    ))
+                })
+                .collect();
+            // Sort so that consensus sees the same order regardless of hash map ordering.
+            matches.sort_unstable_by_key(|&(feature, _)| feature);
+            let matches: Vec<FeatureWorldMatch> = matches.into_iter().map(|(_, m)| m).collect();
+            if matches.len() < self.settings.relocalization_minimum_inliers {
+                info!(
+                    "only matched {} robust landmarks, need {}",
+                    matches.len(),
+                    self.settings.relocalization_minimum_inliers
+                );
+                continue;
+            }
+
+            let (pose, inliers) = match consensus(view, &matches).or_else(opeek(|| {
+                info!("failed to find relocalization pose via consensus")
+            })) {
+                Some(found) => found,
+                None => continue,
+            };
+            let inliers: Vec<FeatureWorldMatch> =
+                inliers.into_iter().map(|ix| matches[ix]).collect();
+            info!(
+                "relocalization found {} inliers among {} matches",
+                inliers.len(),
+                matches.len()
+            );
+            if inliers.len() < self.settings.relocalization_minimum_inliers
+                || best.map_or(false, |(_, best)| best.inliers >= inliers.len())
+            {
+                continue;
+            }
+            let pose = MotionOnly::new().refine(pose, &inliers);
+            best = Some((
+                pose,
+                Localization {
+                    view,
+                    candidates: candidates.len(),
+                    matches: matches.len(),
+                    inliers: inliers.len(),
+                },
+            ));
+        }
+        best.or_else(opeek(|| info!("failed to relocalize")))
+    }
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: ReportingConsensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Localizes the features of an image like [`VSlam::localize`], and also returns the [`ConsensusReport`] of the
+    /// pose of every candidate view which consensus found a pose for, to analyze why relocalization failed.
+    pub fn localize_report(
+        &self,
+        reconstruction: ReconstructionKey,
+        image: &SfmImage<BitArray<64>>,
+    ) -> (
+        Option<(WorldToCamera, Localization)>,
+        Vec<(ViewKey, ConsensusReport)>,
+    ) {
+        let mut reports = vec![];
+        let localization = self.localize_with(reconstruction, image, |view, matches| {
+            let (pose, report) = self
+                .single_view_consensus
+                .borrow_mut()
+                .model_report(&self.world_to_camera_estimator, matches.iter().copied())?;
+            let inliers = report.inliers.clone();
+            reports.push((view, report));
+            Some((pose, inliers))
+        });
+        (localization, reports)
+    }
+}
+
+/// Converts extracted features into an [`SfmImage`] which can be localized.
+pub(crate) fn features_image(features: Vec<(BitArray<64>, Feature)>) -> SfmImage<BitArray<64>> {
+    let (descriptors, features): (Vec<_>, Vec<_>) = features.into_iter().unzip();
+    let bearings = features.iter().map(|feature| feature.bearing).collect();
+    let colors = features.iter().map(|feature| feature.color).collect();
+    SfmImage::new(bearings, descriptors).colors(colors)
+}
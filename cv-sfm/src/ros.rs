@@ -0,0 +1,274 @@
+//! Conversions between the types of Rust CV and common ROS 2 messages of [`r2r`].
+//!
+//! These let the pipeline run inside a ROS 2 node: images and camera info from a camera driver become a
+//! [`GrayFloatImage`] and a camera model, poses are published as `geometry_msgs/PoseStamped`, and reconstructions
+//! are published as `sensor_msgs/PointCloud2`.
+//!
+//! Poses are converted without changing axes, so the camera frame is the ROS optical frame (`x` right, `y` down and
+//! `z` forward), which is the convention of Rust CV as well.
+
+use crate::{ReconstructionKey, VSlam};
+use akaze::image::{GrayConversion, GrayFloatImage};
+use cv_core::{
+    nalgebra::{
+        IsometryMatrix3, Point2, Point3, Quaternion as NQuaternion, Translation3, UnitQuaternion,
+        Vector2,
+    },
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, CameraToWorld, FeatureMatch, FeatureWorldMatch, Projective,
+    TriangulatorObservations, WorldToCamera,
+};
+use cv_pinhole::{CameraIntrinsics, CameraIntrinsicsFisheye, CameraIntrinsicsK1Distortion};
+use r2r::{
+    builtin_interfaces::msg::Time,
+    geometry_msgs::msg::{Point, Pose, PoseStamped, Quaternion},
+    sensor_msgs::msg::{CameraInfo, Image, PointCloud2, PointField},
+    std_msgs::msg::Header,
+};
+use rand::Rng;
+
+/// The `datatype` of a `sensor_msgs/PointField` of 32-bit floats.
+const POINT_FIELD_FLOAT32: u8 = 7;
+
+/// The camera model described by a `sensor_msgs/CameraInfo`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RosCameraModel {
+    /// A `plumb_bob` or `rational_polynomial` camera, of which only the first radial coefficient is kept.
+    Pinhole(CameraIntrinsicsK1Distortion),
+    /// An `equidistant` camera.
+    Fisheye(CameraIntrinsicsFisheye),
+}
+
+/// Creates a header from a frame ID and a timestamp in seconds.
+pub fn ros_header(frame_id: impl Into<String>, timestamp: f64) -> Header {
+    let sec = timestamp.floor();
+    Header {
+        stamp: Time {
+            sec: sec as i32,
+            nanosec: (((timestamp - sec) * 1e9) as u32).min(999_999_999),
+        },
+        frame_id: frame_id.into(),
+    }
+}
+
+/// The timestamp of a header in seconds.
+pub fn timestamp_from_ros(header: &Header) -> f64 {
+    f64::from(header.stamp.sec) + f64::from(header.stamp.nanosec) * 1e-9
+}
+
+/// Converts a `sensor_msgs/Image` to a gray image with pixels between 0 and 1.
+///
+/// The encodings `mono8`, `8UC1`, `mono16`, `16UC1`, `32FC1`, `rgb8`, `bgr8`, `rgba8` and `bgra8` are supported, and
+/// color images are converted to gray with `conversion`. Float images are copied as they are. Returns `None` for other
+/// encodings or if the data is too short for the size and step of the image.
+pub fn gray_image_from_ros(image: &Image, conversion: GrayConversion) -> Option<GrayFloatImage> {
+    let width = image.width as usize;
+    let height = image.height as usize;
+    let step = image.step as usize;
+    let big_endian = image.is_bigendian != 0;
+    let (bytes_per_pixel, convert): (usize, Box<dyn Fn(&[u8]) -> f32>) =
+        match image.encoding.as_str() {
+            "mono8" | "8UC1" => (1, Box::new(|pixel| f32::from(pixel[0]) / 255.0)),
+            "mono16" | "16UC1" => (
+                2,
+                Box::new(move |pixel| {
+                    let bytes = [pixel[0], pixel[1]];
+                    let value = if big_endian {
+                        u16::from_be_bytes(bytes)
+                    } else {
+                        u16::from_le_bytes(bytes)
+                    };
+                    f32::from(value) / 65535.0
+                }),
+            ),
+            "32FC1" => (
+                4,
+                Box::new(move |pixel| {
+                    let bytes = [pixel[0], pixel[1], pixel[2], pixel[3]];
+                    if big_endian {
+                        f32::from_be_bytes(bytes)
+                    } else {
+                        f32::from_le_bytes(bytes)
+                    }
+                }),
+            ),
+            "rgb8" | "rgba8" | "bgr8" | "bgra8" => {
+                let bgr = image.encoding.starts_with("bgr");
+                (
+                    if image.encoding.ends_with("a8") { 4 } else { 3 },
+                    Box::new(move |pixel| {
+                        let channel = |ix: usize| f32::from(pixel[ix]) / 255.0;
+                        let rgb = if bgr {
+                            [channel(2), channel(1), channel(0)]
+                        } else {
+                            [channel(0), channel(1), channel(2)]
+                        };
+                        conversion.convert(rgb)
+                    }),
+                )
+            }
+            _ => return None,
+        };
+    if step < width * bytes_per_pixel
+        || (height > 0 && image.data.len() < (height - 1) * step + width * bytes_per_pixel)
+    {
+        return None;
+    }
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = &image.data[y * step..y * step + width * bytes_per_pixel];
+        pixels.extend(
+            row.chunks_exact(bytes_per_pixel)
+                .map(|pixel| convert(pixel)),
+        );
+    }
+    GrayFloatImage::from_raw(width, height, pixels)
+}
+
+/// Converts a `sensor_msgs/CameraInfo` to a camera model.
+///
+/// The intrinsics come from the `K` matrix. The `plumb_bob` and `rational_polynomial` models distort undistorted
+/// points, while [`CameraIntrinsicsK1Distortion`] undistorts distorted points, so their first radial coefficient is
+/// only the same to first order. Returns `None` for other distortion models or if `K` doesn't have nine entries.
+pub fn camera_model_from_ros(info: &CameraInfo) -> Option<RosCameraModel> {
+    if info.k.len() != 9 {
+        return None;
+    }
+    let intrinsics = CameraIntrinsics {
+        focals: Vector2::new(info.k[0], info.k[4]),
+        principal_point: Point2::new(info.k[2], info.k[5]),
+        skew: info.k[1],
+    };
+    let coefficient = |ix: usize| info.d.get(ix).copied().unwrap_or(0.0);
+    match info.distortion_model.as_str() {
+        "" | "plumb_bob" | "rational_polynomial" => Some(RosCameraModel::Pinhole(
+            CameraIntrinsicsK1Distortion::new(intrinsics, coefficient(0)),
+        )),
+        "equidistant" => Some(RosCameraModel::Fisheye(CameraIntrinsicsFisheye::new(
+            intrinsics,
+            [
+                coefficient(0),
+                coefficient(1),
+                coefficient(2),
+                coefficient(3),
+            ],
+        ))),
+        _ => None,
+    }
+}
+
+/// Converts a `geometry_msgs/Pose` of a camera in the world to the pose which maps camera points into the world.
+pub fn camera_to_world_from_ros(pose: &Pose) -> CameraToWorld {
+    let Quaternion { x, y, z, w } = pose.orientation;
+    let rotation = UnitQuaternion::from_quaternion(NQuaternion::new(w, x, y, z));
+    CameraToWorld(IsometryMatrix3::from_parts(
+        Translation3::new(pose.position.x, pose.position.y, pose.position.z),
+        rotation.to_rotation_matrix(),
+    ))
+}
+
+/// Converts a `geometry_msgs/PoseStamped` of a camera in the world to the pose which maps camera points into the
+/// world.
+pub fn camera_to_world_from_pose_stamped(pose: &PoseStamped) -> CameraToWorld {
+    camera_to_world_from_ros(&pose.pose)
+}
+
+/// Converts the pose of a camera in the world to a `geometry_msgs/Pose`.
+pub fn camera_to_world_to_ros(pose: CameraToWorld) -> Pose {
+    let translation = pose.0.translation.vector;
+    let rotation = UnitQuaternion::from_rotation_matrix(&pose.0.rotation);
+    Pose {
+        position: Point {
+            x: translation.x,
+            y: translation.y,
+            z: translation.z,
+        },
+        orientation: Quaternion {
+            x: rotation.i,
+            y: rotation.j,
+            z: rotation.k,
+            w: rotation.w,
+        },
+    }
+}
+
+/// Converts the pose of a camera in the world to a `geometry_msgs/PoseStamped` with the given header, whose frame
+/// is the world frame.
+pub fn pose_stamped(pose: CameraToWorld, header: Header) -> PoseStamped {
+    PoseStamped {
+        header,
+        pose: camera_to_world_to_ros(pose),
+    }
+}
+
+/// Creates an unordered `sensor_msgs/PointCloud2` with the float fields `x`, `y` and `z` and the colors packed into a
+/// float field `rgb`, as RViz expects.
+pub fn point_cloud2(
+    points: impl IntoIterator<Item = (Point3<f64>, [u8; 3])>,
+    header: Header,
+) -> PointCloud2 {
+    let fields = ["x", "y", "z", "rgb"]
+        .iter()
+        .enumerate()
+        .map(|(ix, &name)| PointField {
+            name: name.into(),
+            offset: 4 * ix as u32,
+            datatype: POINT_FIELD_FLOAT32,
+            count: 1,
+        })
+        .collect();
+    let mut data = vec![];
+    let mut len = 0;
+    for (point, [r, g, b]) in points {
+        for coordinate in point.coords.iter() {
+            data.extend_from_slice(&(*coordinate as f32).to_le_bytes());
+        }
+        let rgb = u32::from(r) << 16 | u32::from(g) << 8 | u32::from(b);
+        data.extend_from_slice(&rgb.to_le_bytes());
+        len += 1;
+    }
+    PointCloud2 {
+        header,
+        height: 1,
+        width: len,
+        fields,
+        is_bigendian: false,
+        point_step: 16,
+        row_step: 16 * len,
+        data,
+        is_dense: true,
+    }
+}
+
+/// Creates a `sensor_msgs/PointCloud2` of the landmarks of a reconstruction which can be triangulated robustly, with
+/// the colors of their first observations.
+pub fn reconstruction_point_cloud2<C1, C2, PE, EE, T, R>(
+    vslam: &VSlam<C1, C2, PE, EE, T, R>,
+    reconstruction: ReconstructionKey,
+    header: Header,
+) -> PointCloud2
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    let points = vslam
+        .data
+        .reconstruction(reconstruction)
+        .landmarks
+        .iter()
+        .filter_map(|(landmark, landmark_data)| {
+            let point = vslam
+                .triangulate_landmark_robust(reconstruction, landmark)?
+                .point()?;
+            let (&view, &feature) = landmark_data.observations.iter().next()?;
+            Some((
+                point,
+                vslam.data.observation_color(reconstruction, view, feature),
+            ))
+        });
+    point_cloud2(points, header)
+}
@@ -0,0 +1,232 @@
+//! Metric scale recovery from known lengths in the scene.
+//!
+//! Reconstructions from a single moving camera are only known up to scale. A known length in the scene, such as the
+//! side of a fiducial tag, the height of a camera mounted on a vehicle or the baseline between two capture
+//! positions, fixes the scale. Every constraint measures its length in the reconstruction and the median ratio
+//! between the known and the measured lengths is the scale, so a few bad constraints don't affect the result.
+//! Rescaling scales the translation of every pose (see [`Pose::scale`]) and every point by the same factor, which
+//! keeps every bearing unchanged.
+
+use crate::{opeek, LandmarkKey, ReconstructionKey, SparseReconstruction, VSlam, ViewKey};
+use cv_core::{
+    nalgebra::{Matrix3, Point3, SymmetricEigen, Vector3, Vector4},
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Projective, TriangulatorObservations,
+    WorldPoint, WorldToCamera,
+};
+use cv_optimize::Plane;
+use float_ord::FloatOrd;
+use log::*;
+use rand::Rng;
+
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// A known metric length in a reconstruction, where `V` identifies views and `L` identifies landmarks.
+///
+/// For a [`SparseReconstruction`], views are the indices of the images and landmarks are the indices of the points.
+/// For a [`VSlam`] reconstruction, they are its [`ViewKey`]s and [`LandmarkKey`]s.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum MetricConstraint<V, L> {
+    /// Two landmarks are a known distance apart, such as two adjacent corners of a fiducial tag of known size.
+    LandmarkDistance(L, L, f64),
+    /// The camera of a view is a known height above the ground, which is the plane best fitting the landmarks.
+    CameraHeight {
+        view: V,
+        ground: Vec<L>,
+        height: f64,
+    },
+    /// The cameras of two views are a known distance apart, such as a baseline measured between two capture
+    /// positions.
+    Baseline(V, V, f64),
+}
+
+impl<V: Copy, L: Copy> MetricConstraint<V, L> {
+    /// The known length and the length measured in the reconstruction, given the camera center of a view and the
+    /// position of a landmark.
+    fn lengths(
+        &self,
+        center: impl Fn(V) -> Option<Point3<f64>>,
+        landmark: impl Fn(L) -> Option<Point3<f64>>,
+    ) -> Option<(f64, f64)> {
+        match *self {
+            Self::LandmarkDistance(a, b, distance) => {
+                Some((distance, (landmark(a)? - landmark(b)?).norm()))
+            }
+            Self::CameraHeight {
+                view,
+                ref ground,
+                height,
+            } => {
+                let points: Vec<Point3<f64>> = ground
+                    .iter()
+                    .filter_map(|&ground| landmark(ground))
+                    .collect();
+                let plane = fit_plane(&points)?;
+                Some((height, plane.signed_distance(center(view)?).abs()))
+            }
+            Self::Baseline(a, b, distance) => Some((distance, (center(a)? - center(b)?).norm())),
+        }
+    }
+}
+
+/// The median ratio between the known and the measured lengths.
+fn median_scale(lengths: impl Iterator<Item = (f64, f64)>) -> Option<f64> {
+    let mut ratios: Vec<f64> = lengths
+        .filter(|&(known, measured)| known > 0.0 && measured > 0.0)
+        .map(|(known, measured)| known / measured)
+        .collect();
+    if ratios.is_empty() {
+        return None;
+    }
+    let middle = ratios.len() / 2;
+    Some(
+        *ratios
+            .select_nth_unstable_by_key(middle, |&ratio| FloatOrd(ratio))
+            .1,
+    )
+}
+
+/// The least squares plane through at least three points.
+fn fit_plane(points: &[Point3<f64>]) -> Option<Plane> {
+    if points.len() < 3 {
+        return None;
+    }
+    let centroid = points
+        .iter()
+        .map(|point| point.coords)
+        .sum::<Vector3<f64>>()
+        / points.len() as f64;
+    let covariance: Matrix3<f64> = points
+        .iter()
+        .map(|point| {
+            let offset = point.coords - centroid;
+            offset * offset.transpose()
+        })
+        .sum();
+    let eigen = SymmetricEigen::new(covariance);
+    let normal = eigen
+        .eigenvectors
+        .column(eigen.eigenvalues.imin())
+        .into_owned();
+    Some(Plane::new(normal, normal.dot(&centroid)))
+}
+
+/// Scales a point about the origin of the world.
+fn scale_point(point: WorldPoint, scale: f64) -> WorldPoint {
+    let coords = point.0;
+    WorldPoint(Vector4::new(
+        scale * coords.x,
+        scale * coords.y,
+        scale * coords.z,
+        coords.w,
+    ))
+}
+
+/// The center of the camera of a pose.
+fn camera_center(pose: WorldToCamera) -> Point3<f64> {
+    Point3::from(pose.inverse().isometry().translation.vector)
+}
+
+impl SparseReconstruction {
+    /// Computes the scale which makes the reconstruction metric from the constraints.
+    ///
+    /// Constraints which refer to unregistered images or points at infinity are ignored. Returns `None` if no
+    /// constraint could be measured.
+    pub fn metric_scale(&self, constraints: &[MetricConstraint<usize, usize>]) -> Option<f64> {
+        median_scale(constraints.iter().filter_map(|constraint| {
+            constraint.lengths(
+                |image| Some(camera_center(self.poses.get(image).copied().flatten()?)),
+                |point| self.points.get(point)?.point.point(),
+            )
+        }))
+    }
+
+    /// Scales the reconstruction about the origin of the world.
+    pub fn rescale(&mut self, scale: f64) {
+        for pose in self.poses.iter_mut().flatten() {
+            *pose = pose.scale(scale);
+        }
+        for point in &mut self.points {
+            point.point = scale_point(point.point, scale);
+        }
+    }
+
+    /// Makes the reconstruction metric with the constraints, returning the scale which was applied.
+    pub fn recover_metric_scale(
+        &mut self,
+        constraints: &[MetricConstraint<usize, usize>],
+    ) -> Option<f64> {
+        let scale = self.metric_scale(constraints)?;
+        self.rescale(scale);
+        Some(scale)
+    }
+}
+
+impl<C1, C2, PE, EE, T, R> VSlam<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch>,
+    C2: Consensus<EE, FeatureMatch>,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+    T: TriangulatorObservations + Clone,
+    R: Rng,
+{
+    /// Computes the scale which makes a reconstruction metric from the constraints.
+    ///
+    /// The landmarks are triangulated robustly (see [`VSlam::triangulate_landmark_robust`]), and constraints which
+    /// refer to views or landmarks which are not in the reconstruction or can't be triangulated are ignored. Returns
+    /// `None` if no constraint could be measured.
+    pub fn metric_scale(
+        &self,
+        reconstruction: ReconstructionKey,
+        constraints: &[MetricConstraint<ViewKey, LandmarkKey>],
+    ) -> Option<f64> {
+        let data = self.data.reconstruction(reconstruction);
+        median_scale(constraints.iter().filter_map(|constraint| {
+            constraint.lengths(
+                |view| Some(camera_center(data.views.get(view)?.pose)),
+                |landmark| {
+                    data.landmarks.get(landmark)?;
+                    self.triangulate_landmark_robust(reconstruction, landmark)?
+                        .point()
+                },
+            )
+        }))
+        .or_else(opeek(|| info!("no metric constraint could be measured")))
+    }
+
+    /// Scales a reconstruction about the origin of its world.
+    ///
+    /// Landmarks are triangulated from the poses of their views, so scaling the poses scales the landmarks too.
+    pub fn rescale_reconstruction(&mut self, reconstruction: ReconstructionKey, scale: f64) {
+        let views: Vec<ViewKey> = self
+            .data
+            .reconstruction(reconstruction)
+            .views
+            .keys()
+            .collect();
+        for view in views {
+            let view = self.data.view_mut(reconstruction, view);
+            view.pose = view.pose.scale(scale);
+        }
+        if let Some((_, tracked, pose)) = self.last_tracked.as_mut() {
+            if *tracked == reconstruction {
+                *pose = pose.scale(scale);
+            }
+        }
+    }
+
+    /// Makes a reconstruction metric with the constraints, returning the scale which was applied.
+    pub fn recover_metric_scale(
+        &mut self,
+        reconstruction: ReconstructionKey,
+        constraints: &[MetricConstraint<ViewKey, LandmarkKey>],
+    ) -> Option<f64> {
+        let scale = self.metric_scale(reconstruction, constraints)?;
+        info!("rescaling the reconstruction by {}", scale);
+        self.rescale_reconstruction(reconstruction, scale);
+        Some(scale)
+    }
+}
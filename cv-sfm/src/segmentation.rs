@@ -0,0 +1,147 @@
+use crate::DynamicMasker;
+use float_ord::FloatOrd;
+use image::{
+    imageops::{self, FilterType},
+    DynamicImage, GrayImage, Luma,
+};
+use imageproc::{distance_transform::Norm, morphology};
+use log::*;
+use std::path::Path;
+use tract_onnx::prelude::*;
+
+/// The person, rider, car, truck, bus, train, motorcycle and bicycle classes of Cityscapes.
+const CITYSCAPES_DYNAMIC_CLASSES: [usize; 8] = [11, 12, 13, 14, 15, 16, 17, 18];
+
+/// Masks the pixels of dynamic classes with a semantic segmentation ONNX model.
+///
+/// The model is expected to follow the layout of the common DeepLab/SegFormer ONNX exports:
+/// * input `[1, 3, height, width]` (`f32`), an RGB image normalized by the mean and standard deviation
+/// * output `[1, classes, h, w]` (`f32` logits) or `[1, h, w]` (`i64` labels) at any resolution
+///
+/// The image is resized to the input size of the model, and the mask is resized back to the image and dilated, so
+/// that keypoints on the edges of objects whose descriptors see the object are also caught.
+pub struct SegmentationMasker {
+    model: TypedRunnableModel<TypedModel>,
+    input_size: (u32, u32),
+    /// The per channel mean which is subtracted from the RGB values in `[0, 1]`.
+    pub mean: [f32; 3],
+    /// The per channel standard deviation which the RGB values are divided by after subtracting the mean.
+    pub std: [f32; 3],
+    /// The classes which are masked.
+    pub dynamic_classes: Vec<usize>,
+    /// The radius in pixels by which the mask is grown.
+    pub dilation: u8,
+}
+
+impl SegmentationMasker {
+    /// Loads an ONNX segmentation model from disk, which takes images of `(width, height)` pixels.
+    pub fn from_path(path: impl AsRef<Path>, input_size: (u32, u32)) -> TractResult<Self> {
+        let (width, height) = input_size;
+        let model = tract_onnx::onnx()
+            .model_for_path(path)?
+            .with_input_fact(
+                0,
+                InferenceFact::dt_shape(
+                    f32::datum_type(),
+                    tvec!(1, 3, height as usize, width as usize),
+                ),
+            )?
+            .into_optimized()?
+            .into_runnable()?;
+        Ok(Self {
+            model,
+            input_size,
+            mean: [0.485, 0.456, 0.406],
+            std: [0.229, 0.224, 0.225],
+            dynamic_classes: CITYSCAPES_DYNAMIC_CLASSES.to_vec(),
+            dilation: 4,
+        })
+    }
+
+    /// Default is the ImageNet normalization `[0.485, 0.456, 0.406]` and `[0.229, 0.224, 0.225]`.
+    #[must_use]
+    pub fn normalization(self, mean: [f32; 3], std: [f32; 3]) -> Self {
+        Self { mean, std, ..self }
+    }
+
+    /// Default is the people and vehicle classes of Cityscapes (`11` to `18`).
+    #[must_use]
+    pub fn dynamic_classes(self, dynamic_classes: Vec<usize>) -> Self {
+        Self {
+            dynamic_classes,
+            ..self
+        }
+    }
+
+    /// Default is `4`.
+    #[must_use]
+    pub fn dilation(self, dilation: u8) -> Self {
+        Self { dilation, ..self }
+    }
+
+    /// Segments an image and returns a mask of its size which is `255` on dynamic classes.
+    pub fn mask(&self, image: &DynamicImage) -> TractResult<GrayImage> {
+        let (width, height) = self.input_size;
+        let resized = imageops::resize(&image.to_rgb8(), width, height, FilterType::Triangle);
+        let input: Tensor = tract_ndarray::Array4::from_shape_fn(
+            (1, 3, height as usize, width as usize),
+            |(_, channel, y, x)| {
+                let value = resized.get_pixel(x as u32, y as u32)[channel] as f32 / 255.0;
+                (value - self.mean[channel]) / self.std[channel]
+            },
+        )
+        .into();
+        let outputs = self.model.run(tvec!(input))?;
+        let labels = labels(&outputs[0])?;
+        let (rows, columns) = labels.dim();
+        let mask = GrayImage::from_fn(columns as u32, rows as u32, |x, y| {
+            let label = labels[(y as usize, x as usize)];
+            Luma([if self.dynamic_classes.contains(&label) {
+                255
+            } else {
+                0
+            }])
+        });
+        let mask = imageops::resize(&mask, image.width(), image.height(), FilterType::Nearest);
+        Ok(if self.dilation == 0 {
+            mask
+        } else {
+            morphology::dilate(&mask, Norm::LInf, self.dilation)
+        })
+    }
+}
+
+impl DynamicMasker for SegmentationMasker {
+    fn dynamic_mask(&self, image: &DynamicImage) -> Option<GrayImage> {
+        match self.mask(image) {
+            Ok(mask) => Some(mask),
+            Err(e) => {
+                info!("failed to segment the image for dynamic objects: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// The class of every pixel from the logits or labels output by the model.
+fn labels(output: &Tensor) -> TractResult<tract_ndarray::Array2<usize>> {
+    if output.datum_type() == i64::datum_type() {
+        let labels = output.to_array_view::<i64>()?;
+        let shape = labels.shape();
+        let (rows, columns) = (shape[shape.len() - 2], shape[shape.len() - 1]);
+        let labels = labels.into_shape((rows, columns))?;
+        Ok(labels.mapv(|label| label.max(0) as usize))
+    } else {
+        let logits = output.to_array_view::<f32>()?;
+        let shape = logits.shape();
+        let (classes, rows, columns) = (shape[1], shape[2], shape[3]);
+        Ok(tract_ndarray::Array2::from_shape_fn(
+            (rows, columns),
+            |(y, x)| {
+                (0..classes)
+                    .max_by_key(|&class| FloatOrd(logits[[0, class, y, x]]))
+                    .unwrap_or(0)
+            },
+        ))
+    }
+}
@@ -283,6 +283,141 @@ pub struct VSlamSettings {
         serde(default = "default_optimization_convergence_rate")
     )]
     pub optimization_convergence_rate: f64,
+    /// Whether to detect and close loops after each frame is incorporated
+    #[cfg_attr(feature = "serde-serialize", serde(default = "default_loop_closure"))]
+    pub loop_closure: bool,
+    /// The maximum number of visually similar views to verify as loop closures
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_loop_closure_candidates")
+    )]
+    pub loop_closure_candidates: usize,
+    /// The number of similar frames to search for loop closure candidates
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_loop_closure_search_num")
+    )]
+    pub loop_closure_search_num: usize,
+    /// The minimum number of frames in a feed between two views for them to close a loop
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_loop_closure_minimum_frame_gap")
+    )]
+    pub loop_closure_minimum_frame_gap: usize,
+    /// Views which already share more landmarks than this are connected, so they can't close a loop
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_loop_closure_maximum_shared_landmarks")
+    )]
+    pub loop_closure_maximum_shared_landmarks: usize,
+    /// The number of sample consensus iterations when estimating the similarity between the two sides of a loop
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_loop_closure_sim3_iterations")
+    )]
+    pub loop_closure_sim3_iterations: usize,
+    /// The maximum distance between aligned landmarks relative to their distance from the camera for them to be a similarity inlier
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_loop_closure_sim3_threshold")
+    )]
+    pub loop_closure_sim3_threshold: f64,
+    /// The minimum number of similarity inliers for a loop closure to be accepted
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_loop_closure_minimum_inliers")
+    )]
+    pub loop_closure_minimum_inliers: usize,
+    /// The maximum number of visually similar views to try when relocalizing
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_relocalization_candidates")
+    )]
+    pub relocalization_candidates: usize,
+    /// The number of similar frames to search for relocalization candidates
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_relocalization_search_num")
+    )]
+    pub relocalization_search_num: usize,
+    /// The minimum number of PnP inliers for a relocalization to be accepted
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_relocalization_minimum_inliers")
+    )]
+    pub relocalization_minimum_inliers: usize,
+    /// The number of similar frames to search for each view when looking for overlap between reconstructions
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_map_merge_search_num")
+    )]
+    pub map_merge_search_num: usize,
+    /// The maximum number of overlapping view pairs to verify when merging reconstructions
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_map_merge_candidates")
+    )]
+    pub map_merge_candidates: usize,
+    /// The number of frames of a feed between runs of map maintenance on the reconstruction of the last frame, or 0 to never run it
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_maintenance_interval")
+    )]
+    pub maintenance_interval: usize,
+    /// The number of newest views of each feed which map maintenance leaves untouched
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_maintenance_recent_views")
+    )]
+    pub maintenance_recent_views: usize,
+    /// The minimum number of observations of a landmark outside of the recent views for it to be kept by map maintenance
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_maintenance_minimum_observations")
+    )]
+    pub maintenance_minimum_observations: usize,
+    /// The maximum mean cosine distance of the observations of a landmark from its triangulated point for it to be kept by map maintenance
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_maintenance_maximum_mean_cosine_distance")
+    )]
+    pub maintenance_maximum_mean_cosine_distance: f64,
+    /// The number of other views which must observe a landmark of a view for the landmark to be redundant
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_maintenance_redundant_view_observers")
+    )]
+    pub maintenance_redundant_view_observers: usize,
+    /// The fraction of the landmarks of a view which must be redundant for map maintenance to remove the view
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_maintenance_redundant_view_ratio")
+    )]
+    pub maintenance_redundant_view_ratio: f64,
+    /// The number of frames of a feed after which map maintenance releases the features of frames which are not views, or 0 to keep them
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_maintenance_forget_frames")
+    )]
+    pub maintenance_forget_frames: usize,
+    /// The maximum cosine distance between the bearing of a feature and the bearing of a landmark at the pose predicted by the motion priors of the frames for them to be matched
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_odometry_search_window_cosine_distance")
+    )]
+    pub odometry_search_window_cosine_distance: f64,
+    /// Whether the motion priors of the frames between consecutive views constrain their poses during optimization
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_odometry_constraints")
+    )]
+    pub odometry_constraints: bool,
+    /// The maximum fraction of the observations of a landmark which lie on dynamic objects for it to be used
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(default = "default_dynamic_maximum_observation_ratio")
+    )]
+    pub dynamic_maximum_observation_ratio: f64,
 }
 
 impl Default for VSlamSettings {
@@ -339,6 +474,31 @@ impl Default for VSlamSettings {
             optimization_robust_covisibility_minimum_landmarks:
                 default_optimization_robust_covisibility_minimum_landmarks(),
             optimization_convergence_rate: default_optimization_convergence_rate(),
+            loop_closure: default_loop_closure(),
+            loop_closure_candidates: default_loop_closure_candidates(),
+            loop_closure_search_num: default_loop_closure_search_num(),
+            loop_closure_minimum_frame_gap: default_loop_closure_minimum_frame_gap(),
+            loop_closure_maximum_shared_landmarks: default_loop_closure_maximum_shared_landmarks(),
+            loop_closure_sim3_iterations: default_loop_closure_sim3_iterations(),
+            loop_closure_sim3_threshold: default_loop_closure_sim3_threshold(),
+            loop_closure_minimum_inliers: default_loop_closure_minimum_inliers(),
+            relocalization_candidates: default_relocalization_candidates(),
+            relocalization_search_num: default_relocalization_search_num(),
+            relocalization_minimum_inliers: default_relocalization_minimum_inliers(),
+            map_merge_search_num: default_map_merge_search_num(),
+            map_merge_candidates: default_map_merge_candidates(),
+            maintenance_interval: default_maintenance_interval(),
+            maintenance_recent_views: default_maintenance_recent_views(),
+            maintenance_minimum_observations: default_maintenance_minimum_observations(),
+            maintenance_maximum_mean_cosine_distance:
+                default_maintenance_maximum_mean_cosine_distance(),
+            maintenance_redundant_view_observers: default_maintenance_redundant_view_observers(),
+            maintenance_redundant_view_ratio: default_maintenance_redundant_view_ratio(),
+            maintenance_forget_frames: default_maintenance_forget_frames(),
+            odometry_search_window_cosine_distance: default_odometry_search_window_cosine_distance(
+            ),
+            odometry_constraints: default_odometry_constraints(),
+            dynamic_maximum_observation_ratio: default_dynamic_maximum_observation_ratio(),
         }
     }
 }
@@ -524,3 +684,95 @@ fn default_optimization_robust_covisibility_minimum_landmarks() -> usize {
 fn default_optimization_convergence_rate() -> f64 {
     0.001
 }
+
+fn default_loop_closure() -> bool {
+    true
+}
+
+fn default_loop_closure_candidates() -> usize {
+    3
+}
+
+fn default_loop_closure_search_num() -> usize {
+    1 << 5
+}
+
+fn default_loop_closure_minimum_frame_gap() -> usize {
+    1 << 5
+}
+
+fn default_loop_closure_maximum_shared_landmarks() -> usize {
+    1 << 3
+}
+
+fn default_loop_closure_sim3_iterations() -> usize {
+    1 << 8
+}
+
+fn default_loop_closure_sim3_threshold() -> f64 {
+    0.05
+}
+
+fn default_loop_closure_minimum_inliers() -> usize {
+    1 << 5
+}
+
+fn default_relocalization_candidates() -> usize {
+    1 << 3
+}
+
+fn default_relocalization_search_num() -> usize {
+    1 << 6
+}
+
+fn default_relocalization_minimum_inliers() -> usize {
+    1 << 5
+}
+
+fn default_map_merge_search_num() -> usize {
+    1 << 5
+}
+
+fn default_map_merge_candidates() -> usize {
+    1 << 3
+}
+
+fn default_maintenance_interval() -> usize {
+    0
+}
+
+fn default_maintenance_recent_views() -> usize {
+    1 << 3
+}
+
+fn default_maintenance_minimum_observations() -> usize {
+    3
+}
+
+fn default_maintenance_maximum_mean_cosine_distance() -> f64 {
+    2.5e-7
+}
+
+fn default_maintenance_redundant_view_observers() -> usize {
+    3
+}
+
+fn default_maintenance_redundant_view_ratio() -> f64 {
+    0.9
+}
+
+fn default_maintenance_forget_frames() -> usize {
+    1 << 6
+}
+
+fn default_odometry_search_window_cosine_distance() -> f64 {
+    0.002
+}
+
+fn default_odometry_constraints() -> bool {
+    true
+}
+
+fn default_dynamic_maximum_observation_ratio() -> f64 {
+    0.5
+}
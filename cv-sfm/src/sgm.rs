@@ -0,0 +1,361 @@
+//! Dense disparity estimation for rectified stereo pairs with semi-global matching.
+//!
+//! The cost of matching a pixel of the left image with a pixel of the right image is the Hamming distance between
+//! their census transforms, which compare each pixel with its surrounding window and are therefore robust to the
+//! differences in exposure between the two cameras. Matching every pixel independently is noisy, so the costs are
+//! aggregated along eight paths through the image, with a small penalty for disparity changes of one pixel, which
+//! happen on slanted surfaces, and a large penalty for larger changes, which only happen at depth discontinuities.
+//!
+//! The disparities follow the convention of [`StereoRig`], where a feature at `(u, v)` in the left image appears at
+//! `(u - d, v)` in the right image.
+
+use crate::DepthMap;
+use cv_core::{nalgebra::Point2, CameraPoint, KeyPoint, Projective};
+use cv_pinhole::StereoRig;
+use image::GrayImage;
+
+/// The directions of the paths costs are aggregated along.
+const PATHS: [(i64, i64); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+];
+
+/// The disparity of every pixel of the left image of a rectified stereo pair.
+///
+/// Pixels without a disparity have a disparity of `f32::NAN`.
+#[derive(Clone, Debug)]
+pub struct DisparityMap {
+    pub width: u32,
+    pub height: u32,
+    pub disparities: Vec<f32>,
+}
+
+impl DisparityMap {
+    /// The disparity of a pixel, if it has one.
+    pub fn disparity(&self, x: u32, y: u32) -> Option<f32> {
+        let disparity = self.disparities[y as usize * self.width as usize + x as usize];
+        (!disparity.is_nan()).then(|| disparity)
+    }
+
+    /// The point seen at a pixel of the left camera, if the pixel has a disparity.
+    pub fn point(&self, x: u32, y: u32, rig: &StereoRig) -> Option<CameraPoint> {
+        rig.point(
+            KeyPoint(Point2::new(x as f64, y as f64)),
+            self.disparity(x, y)? as f64,
+        )
+    }
+
+    /// Converts the disparities to depths in the left camera.
+    ///
+    /// Pixels with a disparity of zero are at infinity and have no depth.
+    pub fn depth_map(&self, rig: &StereoRig) -> DepthMap {
+        let depths = (0..self.disparities.len() as u32)
+            .map(|ix| {
+                let (x, y) = (ix % self.width, ix / self.width);
+                self.point(x, y, rig)
+                    .and_then(|point| point.point())
+                    .map_or(0.0, |point| point.z as f32)
+            })
+            .collect();
+        DepthMap::from_depths(self.width, self.height, depths)
+    }
+}
+
+/// Computes disparity maps of rectified stereo pairs with semi-global matching.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sgm {
+    min_disparity: u32,
+    num_disparities: u32,
+    census_radius: u32,
+    small_penalty: u32,
+    large_penalty: u32,
+    max_lr_difference: f32,
+}
+
+impl Sgm {
+    /// Creates an `Sgm` matcher with default values.
+    ///
+    /// Same as calling [`Default::default`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the smallest disparity which is searched.
+    ///
+    /// Default is `0`.
+    #[must_use]
+    pub fn min_disparity(self, min_disparity: u32) -> Self {
+        Self {
+            min_disparity,
+            ..self
+        }
+    }
+
+    /// Set the number of disparities which are searched, starting at the minimum disparity.
+    ///
+    /// Default is `64`.
+    #[must_use]
+    pub fn num_disparities(self, num_disparities: u32) -> Self {
+        Self {
+            num_disparities,
+            ..self
+        }
+    }
+
+    /// Set the radius of the window of the census transform, which can be at most `3`.
+    ///
+    /// Default is `3`.
+    #[must_use]
+    pub fn census_radius(self, census_radius: u32) -> Self {
+        assert!(
+            census_radius <= 3,
+            "the census transform of a radius above 3 does not fit in 64 bits"
+        );
+        Self {
+            census_radius,
+            ..self
+        }
+    }
+
+    /// Set the penalty of a disparity change of one pixel between neighboring pixels.
+    ///
+    /// Default is `8`.
+    #[must_use]
+    pub fn small_penalty(self, small_penalty: u32) -> Self {
+        Self {
+            small_penalty,
+            ..self
+        }
+    }
+
+    /// Set the penalty of a disparity change of more than one pixel between neighboring pixels.
+    ///
+    /// Default is `96`.
+    #[must_use]
+    pub fn large_penalty(self, large_penalty: u32) -> Self {
+        Self {
+            large_penalty,
+            ..self
+        }
+    }
+
+    /// Set the largest difference in pixels between the disparities of the left image and the right image for the
+    /// same match, above which the pixel is discarded as occluded or mismatched.
+    ///
+    /// Default is `1.0`.
+    #[must_use]
+    pub fn max_lr_difference(self, max_lr_difference: f32) -> Self {
+        Self {
+            max_lr_difference,
+            ..self
+        }
+    }
+
+    /// Computes the disparity of every pixel of the left image.
+    ///
+    /// Both images must be rectified and have the same size.
+    pub fn compute(&self, left: &GrayImage, right: &GrayImage) -> DisparityMap {
+        assert_eq!(left.dimensions(), right.dimensions());
+        let (width, height) = (left.width() as usize, left.height() as usize);
+        let disparities = self.num_disparities.max(1) as usize;
+        let left_census = self.census(left);
+        let right_census = self.census(right);
+        let max_cost = ((2 * self.census_radius + 1).pow(2) - 1) as u16;
+
+        // The matching cost of every pixel at every disparity.
+        let mut costs = vec![max_cost; width * height * disparities];
+        for y in 0..height {
+            for x in 0..width {
+                for d in 0..disparities {
+                    let disparity = self.min_disparity as usize + d;
+                    if disparity <= x {
+                        costs[(y * width + x) * disparities + d] =
+                            (left_census[y * width + x] ^ right_census[y * width + x - disparity])
+                                .count_ones() as u16;
+                    }
+                }
+            }
+        }
+
+        let mut aggregated = vec![0u32; width * height * disparities];
+        for &path in &PATHS {
+            self.aggregate_path(&costs, &mut aggregated, width, height, disparities, path);
+        }
+
+        // The best disparity of each pixel of the left image with subpixel refinement.
+        let left_disparities: Vec<f32> = (0..width * height)
+            .map(|ix| {
+                let costs = &aggregated[ix * disparities..(ix + 1) * disparities];
+                let best = (0..disparities).min_by_key(|&d| costs[d]).unwrap();
+                let offset = if best > 0 && best + 1 < disparities {
+                    let (before, at, after) = (
+                        costs[best - 1] as f32,
+                        costs[best] as f32,
+                        costs[best + 1] as f32,
+                    );
+                    let curvature = before - 2.0 * at + after;
+                    if curvature > 0.0 {
+                        0.5 * (before - after) / curvature
+                    } else {
+                        0.0
+                    }
+                } else {
+                    0.0
+                };
+                self.min_disparity as f32 + best as f32 + offset
+            })
+            .collect();
+
+        // The best disparity of each pixel of the right image from the same aggregated costs.
+        let right_disparities: Vec<u32> = (0..width * height)
+            .map(|ix| {
+                let (x, y) = (ix % width, ix / width);
+                (0..disparities)
+                    .filter(|&d| x + self.min_disparity as usize + d < width)
+                    .min_by_key(|&d| {
+                        aggregated
+                            [(y * width + x + self.min_disparity as usize + d) * disparities + d]
+                    })
+                    .map_or(u32::MAX, |d| self.min_disparity + d as u32)
+            })
+            .collect();
+
+        // Discard the pixels which do not match back to themselves.
+        let disparities = left_disparities
+            .iter()
+            .enumerate()
+            .map(|(ix, &disparity)| {
+                let (x, y) = (ix % width, ix / width);
+                let target = x as f32 - disparity;
+                if target < 0.0 {
+                    return f32::NAN;
+                }
+                let right = right_disparities[y * width + target.round() as usize];
+                if right != u32::MAX && (right as f32 - disparity).abs() <= self.max_lr_difference {
+                    disparity
+                } else {
+                    f32::NAN
+                }
+            })
+            .collect();
+
+        DisparityMap {
+            width: width as u32,
+            height: height as u32,
+            disparities,
+        }
+    }
+
+    /// The census transform of every pixel, with one bit for every other pixel of the window which is darker than
+    /// the center. Pixels of the window outside the image are treated as equal to the center.
+    fn census(&self, image: &GrayImage) -> Vec<u64> {
+        let (width, height) = (image.width() as i64, image.height() as i64);
+        let radius = self.census_radius as i64;
+        let mut census = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let center = image.get_pixel(x as u32, y as u32).0[0];
+                let mut bits = 0u64;
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        if dx == 0 && dy == 0 {
+                            continue;
+                        }
+                        let (wx, wy) = (x + dx, y + dy);
+                        let darker = wx >= 0
+                            && wy >= 0
+                            && wx < width
+                            && wy < height
+                            && image.get_pixel(wx as u32, wy as u32).0[0] < center;
+                        bits = bits << 1 | darker as u64;
+                    }
+                }
+                census.push(bits);
+            }
+        }
+        census
+    }
+
+    /// Aggregates the costs along one path direction and adds them to `aggregated`.
+    fn aggregate_path(
+        &self,
+        costs: &[u16],
+        aggregated: &mut [u32],
+        width: usize,
+        height: usize,
+        disparities: usize,
+        (dx, dy): (i64, i64),
+    ) {
+        let mut path = vec![0u32; costs.len()];
+        // Traverse so that the previous pixel on the path is always processed first.
+        let ys: Vec<usize> = if dy >= 0 {
+            (0..height).collect()
+        } else {
+            (0..height).rev().collect()
+        };
+        let xs: Vec<usize> = if dx >= 0 {
+            (0..width).collect()
+        } else {
+            (0..width).rev().collect()
+        };
+        for &y in &ys {
+            for &x in &xs {
+                let ix = (y * width + x) * disparities;
+                let (px, py) = (x as i64 - dx, y as i64 - dy);
+                if px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+                    // The path starts here.
+                    for d in 0..disparities {
+                        path[ix + d] = costs[ix + d] as u32;
+                    }
+                } else {
+                    let previous_ix = (py as usize * width + px as usize) * disparities;
+                    let (previous, current) = if previous_ix < ix {
+                        let (head, tail) = path.split_at_mut(ix);
+                        (
+                            &head[previous_ix..previous_ix + disparities],
+                            &mut tail[..disparities],
+                        )
+                    } else {
+                        let (head, tail) = path.split_at_mut(previous_ix);
+                        (&tail[..disparities], &mut head[ix..ix + disparities])
+                    };
+                    let previous_min = *previous.iter().min().unwrap();
+                    for d in 0..disparities {
+                        let mut best = previous[d];
+                        if d > 0 {
+                            best = best.min(previous[d - 1] + self.small_penalty);
+                        }
+                        if d + 1 < disparities {
+                            best = best.min(previous[d + 1] + self.small_penalty);
+                        }
+                        best = best.min(previous_min + self.large_penalty);
+                        // Subtracting the minimum keeps the aggregated costs bounded along long paths.
+                        current[d] = costs[ix + d] as u32 + best - previous_min;
+                    }
+                }
+                for d in 0..disparities {
+                    aggregated[ix + d] += path[ix + d];
+                }
+            }
+        }
+    }
+}
+
+impl Default for Sgm {
+    fn default() -> Self {
+        Self {
+            min_disparity: 0,
+            num_disparities: 64,
+            census_radius: 3,
+            small_penalty: 8,
+            large_penalty: 96,
+            max_lr_difference: 1.0,
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Visual odometry on background threads.
+//!
+//! Feature extraction takes most of the time spent on a frame and needs nothing but the image, so
+//! [`ThreadedOdometry`] extracts features on a pool of threads. The remaining work is split between three threads
+//! which share the [`VisualOdometry`] behind a lock and hand frames to each other through channels:
+//!
+//! - The tracking thread registers the frames in the order the images were fed, deciding which become keyframes and
+//!   bundle adjusting the reconstruction when one is added, since a failed adjustment rejects the new view.
+//! - The local mapping thread runs the periodic map maintenance on the reconstruction a frame was tracked in.
+//! - The loop closure thread tries to close a loop with every keyframe after local mapping has seen it.
+//!
+//! Each thread only holds the lock for one step, so a frame is tracked while the previous keyframes are still being
+//! mapped, although tracking waits for a loop closure or maintenance step which is already running. The caller is
+//! never blocked by mapping and receives every [`OdometryUpdate`] through channels, which can be polled from a robot
+//! loop or drained by a task of an async runtime.
+
+use crate::{
+    extract_masked_features, DynamicMasker, OdometryUpdate, ReconstructionKey, ViewKey,
+    VisualOdometry,
+};
+use cv_core::{
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, TriangulatorObservations, WorldToCamera,
+};
+use image::DynamicImage;
+use log::*;
+use rand::Rng;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// The images waiting for feature extraction with their sequence number, timestamp and motion prior.
+type ImageSender = SyncSender<(u64, DynamicImage, f64, Option<CameraToCamera>)>;
+
+/// The odometry shared by the tracking, local mapping and loop closure threads.
+type SharedOdometry<C1, C2, PE, EE, T, R> = Arc<Mutex<VisualOdometry<C1, C2, PE, EE, T, R>>>;
+
+/// A tracked frame which is left for local mapping and loop closure.
+struct MappingJob {
+    /// The reconstruction to maintain, if maintenance is due
+    maintain: Option<ReconstructionKey>,
+    /// The view of the frame, if it became a keyframe
+    keyframe: Option<(ReconstructionKey, ViewKey)>,
+}
+
+/// Runs [`VisualOdometry`] on background threads.
+pub struct ThreadedOdometry<C1, C2, PE, EE, T, R> {
+    /// Sends images to the extraction threads
+    images: ImageSender,
+    /// The sequence number of the next image
+    next: u64,
+    /// Receivers of every update
+    subscribers: Arc<Mutex<Vec<Sender<OdometryUpdate>>>>,
+    /// The most recent update
+    latest: Arc<Mutex<Option<OdometryUpdate>>>,
+    /// The odometry with its map
+    odometry: SharedOdometry<C1, C2, PE, EE, T, R>,
+    /// The feature extraction threads
+    extractors: Vec<JoinHandle<()>>,
+    /// The tracking thread
+    tracker: JoinHandle<()>,
+    /// The local mapping thread
+    mapper: JoinHandle<()>,
+    /// The loop closure thread
+    loop_closer: JoinHandle<()>,
+}
+
+impl<C1, C2, PE, EE, T, R> ThreadedOdometry<C1, C2, PE, EE, T, R>
+where
+    C1: Consensus<PE, FeatureWorldMatch> + Send + 'static,
+    C2: Consensus<EE, FeatureMatch> + Send + 'static,
+    PE: Estimator<FeatureWorldMatch, Model = WorldToCamera> + Send + 'static,
+    EE: Estimator<FeatureMatch, Model = CameraToCamera> + Send + 'static,
+    T: TriangulatorObservations + Clone + Send + 'static,
+    R: Rng + Send + 'static,
+{
+    /// Starts `extraction_threads` feature extraction threads and the tracking, local mapping and loop closure threads
+    /// which run `odometry`.
+    ///
+    /// At most `queue` images wait for extraction at a time.
+    pub fn spawn(
+        odometry: VisualOdometry<C1, C2, PE, EE, T, R>,
+        extraction_threads: usize,
+        queue: usize,
+    ) -> Self {
+        let akaze_threshold = odometry.vslam.settings.akaze_threshold;
+        let intrinsics = odometry.vslam.data.feed(odometry.feed_key()).intrinsics;
+        let dynamic_masker = odometry.vslam.dynamic_masker.clone();
+
+        let (images, image_receiver) = mpsc::sync_channel(queue);
+        let image_receiver = Arc::new(Mutex::new(image_receiver));
+        let (feature_sender, feature_receiver) = mpsc::channel();
+        let extractors = (0..extraction_threads.max(1))
+            .map(|_| {
+                let images = image_receiver.clone();
+                let features = feature_sender.clone();
+                let dynamic_masker = dynamic_masker.clone();
+                thread::spawn(move || loop {
+                    // The lock is only held while waiting for the next image.
+                    let next = images.lock().unwrap().recv();
+                    let (sequence, image, timestamp, motion) = match next {
+                        Ok(next) => next,
+                        Err(_) => return,
+                    };
+                    let mask = dynamic_masker
+                        .as_ref()
+                        .and_then(|masker| masker.dynamic_mask(&image));
+                    let extracted = extract_masked_features(
+                        akaze_threshold,
+                        &intrinsics,
+                        &image,
+                        mask.as_ref(),
+                    );
+                    if features
+                        .send((sequence, extracted, timestamp, motion))
+                        .is_err()
+                    {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        drop(feature_sender);
+
+        let odometry = Arc::new(Mutex::new(odometry));
+        let (loop_sender, loop_receiver) = mpsc::channel::<(ReconstructionKey, ViewKey)>();
+        let loop_closer = {
+            let odometry = odometry.clone();
+            thread::spawn(move || {
+                for (reconstruction, view) in loop_receiver {
+                    // The view is skipped if local mapping or a failed bundle adjustment removed it in the meantime.
+                    odometry
+                        .lock()
+                        .unwrap()
+                        .vslam
+                        .close_view_loop(reconstruction, view);
+                }
+                info!("odometry loop closure thread finished");
+            })
+        };
+
+        let (mapping_sender, mapping_receiver) = mpsc::channel::<MappingJob>();
+        let mapper = {
+            let odometry = odometry.clone();
+            thread::spawn(move || {
+                for job in mapping_receiver {
+                    if let Some(reconstruction) = job.maintain {
+                        let mut odometry = odometry.lock().unwrap();
+                        // Tracking may have removed the reconstruction since the job was sent.
+                        if odometry
+                            .vslam
+                            .data
+                            .reconstructions
+                            .contains_key(reconstruction)
+                        {
+                            odometry.vslam.maintain_reconstruction(reconstruction);
+                        }
+                    }
+                    if let Some(keyframe) = job.keyframe {
+                        if loop_sender.send(keyframe).is_err() {
+                            return;
+                        }
+                    }
+                }
+                info!("odometry local mapping thread finished");
+            })
+        };
+
+        let subscribers: Arc<Mutex<Vec<Sender<OdometryUpdate>>>> = Default::default();
+        let latest: Arc<Mutex<Option<OdometryUpdate>>> = Default::default();
+        let tracker = {
+            let odometry = odometry.clone();
+            let subscribers = subscribers.clone();
+            let latest = latest.clone();
+            thread::spawn(move || {
+                let mut pending = BTreeMap::new();
+                let mut next = 0;
+                for (sequence, features, timestamp, motion) in feature_receiver {
+                    pending.insert(sequence, (features, timestamp, motion));
+                    // The images are extracted out of order, but they are tracked in the order they were fed.
+                    while let Some((features, timestamp, motion)) = pending.remove(&next) {
+                        next += 1;
+                        let (update, job) = {
+                            let mut odometry = odometry.lock().unwrap();
+                            let update = odometry.track_features(features, timestamp, motion);
+                            let job = MappingJob {
+                                maintain: odometry.vslam.maintenance_due(update.frame),
+                                keyframe: odometry.vslam.data.frame(update.frame).view,
+                            };
+                            (update, job)
+                        };
+                        *latest.lock().unwrap() = Some(update);
+                        subscribers
+                            .lock()
+                            .unwrap()
+                            .retain(|subscriber| subscriber.send(update).is_ok());
+                        if (job.maintain.is_some() || job.keyframe.is_some())
+                            && mapping_sender.send(job).is_err()
+                        {
+                            return;
+                        }
+                    }
+                }
+                info!("odometry tracking thread finished after {} frames", next);
+            })
+        };
+
+        Self {
+            images,
+            next: 0,
+            subscribers,
+            latest,
+            odometry,
+            extractors,
+            tracker,
+            mapper,
+            loop_closer,
+        }
+    }
+
+    /// Feeds the next image, waiting if the extraction queue is full.
+    ///
+    /// Returns `false` if the threads have stopped.
+    pub fn feed(&mut self, image: DynamicImage, timestamp: f64) -> bool {
+        self.send(image, timestamp, None)
+    }
+
+    /// Feeds the next image with the motion of the camera since the previous image (see
+    /// [`VisualOdometry::feed_with_motion`]), waiting if the extraction queue is full.
+    ///
+    /// Returns `false` if the threads have stopped.
+    pub fn feed_with_motion(
+        &mut self,
+        image: DynamicImage,
+        timestamp: f64,
+        motion: CameraToCamera,
+    ) -> bool {
+        self.send(image, timestamp, Some(motion))
+    }
+
+    /// Sends the next image to the extraction threads, waiting if the queue is full.
+    fn send(
+        &mut self,
+        image: DynamicImage,
+        timestamp: f64,
+        motion: Option<CameraToCamera>,
+    ) -> bool {
+        let sent = self
+            .images
+            .send((self.next, image, timestamp, motion))
+            .is_ok();
+        if sent {
+            self.next += 1;
+        }
+        sent
+    }
+
+    /// Feeds the next image unless the extraction queue is full, in which case the image is dropped.
+    ///
+    /// Dropping images keeps the latency bounded when the camera produces images faster than they can be processed.
+    /// Returns `false` if the image was dropped or the threads have stopped.
+    pub fn try_feed(&mut self, image: DynamicImage, timestamp: f64) -> bool {
+        match self.images.try_send((self.next, image, timestamp, None)) {
+            Ok(()) => {
+                self.next += 1;
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                info!("odometry extraction queue is full; dropping image");
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    /// Subscribes to the updates of every image processed from now on.
+    ///
+    /// The subscription ends when the [`Receiver`] is dropped.
+    pub fn subscribe(&self) -> Receiver<OdometryUpdate> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// The update of the most recently processed image.
+    pub fn latest(&self) -> Option<OdometryUpdate> {
+        *self.latest.lock().unwrap()
+    }
+
+    /// Waits for every image which was fed to be tracked, mapped and checked for loop closures and returns the
+    /// odometry with its map.
+    pub fn finish(self) -> VisualOdometry<C1, C2, PE, EE, T, R> {
+        // Each thread finishes once the thread before it has finished and dropped its sender.
+        drop(self.images);
+        for extractor in self.extractors {
+            extractor
+                .join()
+                .expect("odometry extraction thread panicked");
+        }
+        self.tracker
+            .join()
+            .expect("odometry tracking thread panicked");
+        self.mapper
+            .join()
+            .expect("odometry local mapping thread panicked");
+        self.loop_closer
+            .join()
+            .expect("odometry loop closure thread panicked");
+        Arc::try_unwrap(self.odometry)
+            .ok()
+            .expect("odometry is still shared after its threads finished")
+            .into_inner()
+            .unwrap()
+    }
+}
@@ -0,0 +1,206 @@
+//! Building multi-view feature tracks from pairwise matches.
+
+use std::{collections::HashMap, hash::Hash};
+
+/// A feature observed in an image, identified by the image key and the feature index in that image.
+pub type Observation<K> = (K, usize);
+
+/// A set of features across several images which are all believed to observe the same 3d point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Track<K> {
+    /// The observations of the track sorted by image key, then by feature index.
+    pub observations: Vec<Observation<K>>,
+}
+
+impl<K: Copy + Eq> Track<K> {
+    /// The number of observations in the track.
+    pub fn len(&self) -> usize {
+        self.observations.len()
+    }
+
+    /// Returns `true` if the track has no observations.
+    pub fn is_empty(&self) -> bool {
+        self.observations.is_empty()
+    }
+
+    /// Returns `true` if more than one feature from a single image ended up in this track.
+    ///
+    /// This happens when a chain of matches through other images leads back to a different feature in the same image,
+    /// which means at least one of the matches was wrong.
+    pub fn is_conflicting(&self) -> bool {
+        // Observations are sorted by image, so duplicates of an image are adjacent.
+        self.observations.windows(2).any(|w| w[0].0 == w[1].0)
+    }
+
+    /// Iterates over the images this track is observed in, once per observation.
+    pub fn images(&self) -> impl Iterator<Item = K> + '_ {
+        self.observations.iter().map(|&(image, _)| image)
+    }
+}
+
+/// The output of [`TrackBuilder::build`].
+#[derive(Clone, Debug)]
+pub struct Tracks<K> {
+    /// Tracks which observe at most one feature per image.
+    pub tracks: Vec<Track<K>>,
+    /// Tracks which received two or more features from the same image.
+    ///
+    /// These are kept separate so they can be discarded, or split by the caller using geometric information.
+    pub conflicting: Vec<Track<K>>,
+}
+
+/// Ingests pairwise matches between images and produces consistent multi-view feature tracks.
+///
+/// Matches are merged with a union-find (disjoint set) structure, so the order matches are added in
+/// does not affect the resulting tracks. `K` is the image key, which can be an index or a [`crate::FrameKey`].
+///
+/// ```
+/// use cv_sfm::{Track, TrackBuilder};
+///
+/// let mut builder = TrackBuilder::new();
+/// builder.add_matches(0, 1, &[[0, 0], [2, 3]]);
+/// builder.add_matches(1, 2, &[[0, 0]]);
+/// // This match leads back to a different feature in image 0, so one of the matches is wrong.
+/// builder.add_matches(2, 0, &[[0, 1]]);
+/// let tracks = builder.build(2);
+/// assert_eq!(
+///     tracks.tracks,
+///     vec![Track {
+///         observations: vec![(0, 2), (1, 3)]
+///     }]
+/// );
+/// assert_eq!(
+///     tracks.conflicting,
+///     vec![Track {
+///         observations: vec![(0, 0), (0, 1), (1, 0), (2, 0)]
+///     }]
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct TrackBuilder<K> {
+    /// Maps an observation to its node in the disjoint set forest.
+    nodes: HashMap<Observation<K>, usize>,
+    /// The observation of each node.
+    observations: Vec<Observation<K>>,
+    /// The parent of each node in the disjoint set forest.
+    parents: Vec<usize>,
+    /// The rank of each node, used for union by rank.
+    ranks: Vec<u8>,
+}
+
+impl<K> Default for TrackBuilder<K> {
+    fn default() -> Self {
+        Self {
+            nodes: HashMap::new(),
+            observations: vec![],
+            parents: vec![],
+            ranks: vec![],
+        }
+    }
+}
+
+impl<K> TrackBuilder<K>
+where
+    K: Copy + Eq + Hash + Ord,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the matches between two images.
+    ///
+    /// Each match is a pair of feature indices `[feature_a, feature_b]`, as produced by
+    /// [`crate::matching::symmetric_matching`].
+    pub fn add_matches(&mut self, image_a: K, image_b: K, matches: &[[usize; 2]]) {
+        for &[feature_a, feature_b] in matches {
+            self.add_match((image_a, feature_a), (image_b, feature_b));
+        }
+    }
+
+    /// Adds a single match between two observations.
+    pub fn add_match(&mut self, a: Observation<K>, b: Observation<K>) {
+        let a = self.node(a);
+        let b = self.node(b);
+        self.union(a, b);
+    }
+
+    /// The number of distinct observations which have been added.
+    pub fn num_observations(&self) -> usize {
+        self.observations.len()
+    }
+
+    /// Produces the tracks from all the matches added so far.
+    ///
+    /// Tracks with fewer than `min_length` observations are omitted.
+    pub fn build(&mut self, min_length: usize) -> Tracks<K> {
+        let mut root_tracks: HashMap<usize, Vec<Observation<K>>> = HashMap::new();
+        for node in 0..self.observations.len() {
+            let root = self.find(node);
+            root_tracks
+                .entry(root)
+                .or_default()
+                .push(self.observations[node]);
+        }
+        let mut tracks = vec![];
+        let mut conflicting = vec![];
+        for (_, mut observations) in root_tracks {
+            if observations.len() < min_length {
+                continue;
+            }
+            observations.sort_unstable();
+            let track = Track { observations };
+            if track.is_conflicting() {
+                conflicting.push(track);
+            } else {
+                tracks.push(track);
+            }
+        }
+        // Sort so the output is deterministic regardless of hash map ordering.
+        tracks.sort_unstable_by(|a, b| a.observations.cmp(&b.observations));
+        conflicting.sort_unstable_by(|a, b| a.observations.cmp(&b.observations));
+        Tracks {
+            tracks,
+            conflicting,
+        }
+    }
+
+    /// Gets the node for an observation, creating it if it doesn't exist yet.
+    fn node(&mut self, observation: Observation<K>) -> usize {
+        let observations = &mut self.observations;
+        let parents = &mut self.parents;
+        let ranks = &mut self.ranks;
+        *self.nodes.entry(observation).or_insert_with(|| {
+            let node = observations.len();
+            observations.push(observation);
+            parents.push(node);
+            ranks.push(0);
+            node
+        })
+    }
+
+    /// Finds the root of a node, compressing the path along the way.
+    fn find(&mut self, mut node: usize) -> usize {
+        while self.parents[node] != node {
+            // Path halving
+            self.parents[node] = self.parents[self.parents[node]];
+            node = self.parents[node];
+        }
+        node
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let a = self.find(a);
+        let b = self.find(b);
+        if a == b {
+            return;
+        }
+        match self.ranks[a].cmp(&self.ranks[b]) {
+            std::cmp::Ordering::Less => self.parents[a] = b,
+            std::cmp::Ordering::Greater => self.parents[b] = a,
+            std::cmp::Ordering::Equal => {
+                self.parents[b] = a;
+                self.ranks[a] += 1;
+            }
+        }
+    }
+}
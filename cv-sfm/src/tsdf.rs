@@ -0,0 +1,454 @@
+//! Volumetric fusion of depth maps and mesh extraction.
+//!
+//! A [`TsdfVolume`] stores the truncated signed distance to the nearest surface in a regular grid of voxels. Every
+//! depth map integrated into the volume updates a running weighted average of the distance in front of and just behind
+//! the observed surface, so the noise of individual depth maps, whether from multi-view stereo, stereo or an RGB-D
+//! camera, averages out. The surface is the zero crossing of the distance, which is extracted as a triangle [`Mesh`]
+//! with marching cubes.
+//!
+//! The triangles of each cube are found by tracing the zero crossings around the faces of the cube instead of with a
+//! lookup table. Faces with two diagonally opposite corners inside the surface are ambiguous, and are resolved by the
+//! mean of the four corners, which only depends on the face, so neighboring cubes always agree and the mesh has no
+//! cracks between cubes.
+
+use crate::{DepthMap, PointCloudEncoding};
+use cv_core::{
+    nalgebra::{Point3, Vector3},
+    Pose, WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsics;
+use image::RgbImage;
+use ply_rs::{
+    ply::{
+        Addable, DefaultElement, ElementDef, Encoding, Ply, Property, PropertyDef, PropertyType,
+        ScalarType,
+    },
+    writer::Writer,
+};
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// The corners of a cube as offsets from its first corner.
+const CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [0, 1, 0],
+    [1, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [0, 1, 1],
+    [1, 1, 1],
+];
+
+/// The faces of a cube as corners in a cycle around the face.
+const FACES: [[usize; 4]; 6] = [
+    [0, 1, 3, 2],
+    [4, 5, 7, 6],
+    [0, 1, 5, 4],
+    [2, 3, 7, 6],
+    [0, 2, 6, 4],
+    [1, 3, 7, 5],
+];
+
+/// A triangle mesh with colored vertices.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<Point3<f64>>,
+    pub colors: Vec<[u8; 3]>,
+    /// The indices of the vertices of each triangle, counter-clockwise when seen from the outside.
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl Mesh {
+    /// Writes the mesh as a PLY file.
+    pub fn write_ply(
+        &self,
+        mut writer: impl Write,
+        encoding: PointCloudEncoding,
+    ) -> io::Result<()> {
+        let mut ply = Ply::<DefaultElement>::new();
+        ply.header.encoding = match encoding {
+            PointCloudEncoding::Ascii => Encoding::Ascii,
+            PointCloudEncoding::Binary => Encoding::BinaryLittleEndian,
+        };
+        ply.header
+            .comments
+            .push("Exported from rust-cv/cv-sfm".to_string());
+
+        let mut vertex_element = ElementDef::new("vertex".to_string());
+        for name in ["x", "y", "z"] {
+            vertex_element.properties.add(PropertyDef::new(
+                name.to_string(),
+                PropertyType::Scalar(ScalarType::Double),
+            ));
+        }
+        for name in ["red", "green", "blue"] {
+            vertex_element.properties.add(PropertyDef::new(
+                name.to_string(),
+                PropertyType::Scalar(ScalarType::UChar),
+            ));
+        }
+        ply.header.elements.add(vertex_element);
+        let mut face_element = ElementDef::new("face".to_string());
+        face_element.properties.add(PropertyDef::new(
+            "vertex_index".to_string(),
+            PropertyType::List(ScalarType::UChar, ScalarType::Int),
+        ));
+        ply.header.elements.add(face_element);
+
+        let vertices = self
+            .vertices
+            .iter()
+            .zip(&self.colors)
+            .map(|(vertex, &[r, g, b])| {
+                let mut element = DefaultElement::new();
+                element.insert("x".to_string(), Property::Double(vertex.x));
+                element.insert("y".to_string(), Property::Double(vertex.y));
+                element.insert("z".to_string(), Property::Double(vertex.z));
+                element.insert("red".to_string(), Property::UChar(r));
+                element.insert("green".to_string(), Property::UChar(g));
+                element.insert("blue".to_string(), Property::UChar(b));
+                element
+            })
+            .collect();
+        let faces = self
+            .triangles
+            .iter()
+            .map(|triangle| {
+                let mut element = DefaultElement::new();
+                element.insert(
+                    "vertex_index".to_string(),
+                    Property::ListInt(triangle.iter().map(|&ix| ix as i32).collect()),
+                );
+                element
+            })
+            .collect();
+        ply.payload.insert("vertex".to_string(), vertices);
+        ply.payload.insert("face".to_string(), faces);
+
+        Writer::new().write_ply(&mut writer, &mut ply)?;
+        Ok(())
+    }
+
+    /// Writes the mesh as a Wavefront OBJ file, with the vertex colors after the positions.
+    pub fn write_obj(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "# Exported from rust-cv/cv-sfm")?;
+        for (vertex, &[r, g, b]) in self.vertices.iter().zip(&self.colors) {
+            writeln!(
+                writer,
+                "v {} {} {} {} {} {}",
+                vertex.x,
+                vertex.y,
+                vertex.z,
+                r as f64 / 255.0,
+                g as f64 / 255.0,
+                b as f64 / 255.0
+            )?;
+        }
+        for [a, b, c] in &self.triangles {
+            // OBJ indices start at one.
+            writeln!(writer, "f {} {} {}", a + 1, b + 1, c + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the mesh to a PLY file.
+    pub fn save_ply(&self, path: impl AsRef<Path>, encoding: PointCloudEncoding) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_ply(&mut writer, encoding)?;
+        writer.flush()
+    }
+
+    /// Writes the mesh to an OBJ file.
+    pub fn save_obj(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_obj(&mut writer)?;
+        writer.flush()
+    }
+}
+
+/// A voxel grid of truncated signed distances which depth maps are fused into.
+///
+/// The distance is positive in front of surfaces and negative behind them, and is stored as a fraction of the
+/// truncation distance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TsdfVolume {
+    /// The position of the center of the first voxel.
+    pub origin: Point3<f64>,
+    /// The number of voxels along each axis.
+    pub dimensions: [usize; 3],
+    pub voxel_size: f64,
+    truncation: f64,
+    max_weight: f32,
+    distances: Vec<f32>,
+    weights: Vec<f32>,
+    colors: Vec<[f32; 3]>,
+}
+
+impl TsdfVolume {
+    /// Creates an empty volume with `dimensions` voxels of `voxel_size` starting at `origin`.
+    ///
+    /// The truncation distance defaults to three voxels.
+    pub fn new(origin: Point3<f64>, dimensions: [usize; 3], voxel_size: f64) -> Self {
+        let len = dimensions.iter().product();
+        Self {
+            origin,
+            dimensions,
+            voxel_size,
+            truncation: 3.0 * voxel_size,
+            max_weight: 128.0,
+            distances: vec![1.0; len],
+            weights: vec![0.0; len],
+            colors: vec![[0.0; 3]; len],
+        }
+    }
+
+    /// Creates an empty volume which covers the box between `min` and `max`.
+    pub fn from_bounds(min: Point3<f64>, max: Point3<f64>, voxel_size: f64) -> Self {
+        let extent = max - min;
+        let dimensions =
+            [0, 1, 2].map(|axis| (extent[axis] / voxel_size).ceil().max(1.0) as usize + 1);
+        Self::new(min, dimensions, voxel_size)
+    }
+
+    /// Set the distance from the surface beyond which distances are truncated.
+    ///
+    /// Default is three times the voxel size.
+    #[must_use]
+    pub fn truncation(self, truncation: f64) -> Self {
+        Self { truncation, ..self }
+    }
+
+    /// Set the maximum weight of a voxel, which keeps the volume able to adapt to new depth maps.
+    ///
+    /// Default is `128.0`.
+    #[must_use]
+    pub fn max_weight(self, max_weight: f32) -> Self {
+        Self { max_weight, ..self }
+    }
+
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.dimensions[1] + y) * self.dimensions[0] + x
+    }
+
+    fn position(&self, [x, y, z]: [usize; 3]) -> Point3<f64> {
+        self.origin + Vector3::new(x as f64, y as f64, z as f64) * self.voxel_size
+    }
+
+    /// The signed distance at a voxel, if any depth map has observed it.
+    pub fn distance(&self, voxel: [usize; 3]) -> Option<f64> {
+        let ix = self.index(voxel);
+        (self.weights[ix] > 0.0).then(|| self.distances[ix] as f64 * self.truncation)
+    }
+
+    /// Fuses a depth map of a camera at `pose` into the volume.
+    ///
+    /// The voxels are colored from `image` if it is given.
+    pub fn integrate(
+        &mut self,
+        depth_map: &DepthMap,
+        intrinsics: &CameraIntrinsics,
+        pose: WorldToCamera,
+        image: Option<&RgbImage>,
+    ) {
+        let matrix = intrinsics.matrix();
+        let isometry = pose.isometry();
+        let [width, height] = [self.dimensions[0], self.dimensions[1]];
+        let (origin, voxel_size, truncation, max_weight) = (
+            self.origin,
+            self.voxel_size,
+            self.truncation,
+            self.max_weight,
+        );
+        self.distances
+            .par_iter_mut()
+            .zip(self.weights.par_iter_mut())
+            .zip(self.colors.par_iter_mut())
+            .enumerate()
+            .for_each(|(ix, ((distance, weight), color))| {
+                let voxel = [ix % width, ix / width % height, ix / (width * height)];
+                let position = origin
+                    + Vector3::new(voxel[0] as f64, voxel[1] as f64, voxel[2] as f64) * voxel_size;
+                let camera = isometry * position;
+                if camera.z <= 0.0 {
+                    return;
+                }
+                let pixel = matrix * camera.coords;
+                let (u, v) = ((pixel.x / pixel.z).round(), (pixel.y / pixel.z).round());
+                if u < 0.0 || v < 0.0 || u >= depth_map.width as f64 || v >= depth_map.height as f64
+                {
+                    return;
+                }
+                let (u, v) = (u as u32, v as u32);
+                let depth = match depth_map.depth(u, v) {
+                    Some(depth) => depth as f64,
+                    None => return,
+                };
+                let signed = depth - camera.z;
+                // Voxels far behind the surface are occluded and are not updated.
+                if signed < -truncation {
+                    return;
+                }
+                let observed = (signed / truncation).min(1.0) as f32;
+                let total = *weight + 1.0;
+                *distance = (*distance * *weight + observed) / total;
+                if let Some(image) = image {
+                    let pixel = image.get_pixel(u, v).0;
+                    for channel in 0..3 {
+                        color[channel] = (color[channel] * *weight + pixel[channel] as f32) / total;
+                    }
+                }
+                *weight = total.min(max_weight);
+            });
+    }
+
+    /// Extracts the surface of the volume as a mesh with marching cubes.
+    ///
+    /// Only cubes whose eight voxels were all observed are used. Vertices are shared between neighboring cubes.
+    pub fn extract_mesh(&self) -> Mesh {
+        let mut mesh = Mesh::default();
+        // Vertices are identified by the voxel at the start of their edge and the axis of the edge.
+        let mut edge_vertices: HashMap<(usize, usize), usize> = HashMap::new();
+        let [dx, dy, dz] = self.dimensions;
+        for z in 0..dz.saturating_sub(1) {
+            for y in 0..dy.saturating_sub(1) {
+                for x in 0..dx.saturating_sub(1) {
+                    self.march_cube(&mut mesh, &mut edge_vertices, [x, y, z]);
+                }
+            }
+        }
+        mesh
+    }
+
+    fn march_cube(
+        &self,
+        mesh: &mut Mesh,
+        edge_vertices: &mut HashMap<(usize, usize), usize>,
+        base: [usize; 3],
+    ) {
+        let voxels = CORNERS.map(|[x, y, z]| [base[0] + x, base[1] + y, base[2] + z]);
+        let mut values = [0.0; 8];
+        for (corner, &voxel) in voxels.iter().enumerate() {
+            values[corner] = match self.distance(voxel) {
+                Some(distance) => distance,
+                None => return,
+            };
+        }
+        let inside = values.map(|value| value < 0.0);
+        if inside.iter().all(|&inside| inside) || inside.iter().all(|&inside| !inside) {
+            return;
+        }
+
+        // Connect the crossings on each face into segments between corner pairs which identify the crossed edges.
+        let mut segments: Vec<[(usize, usize); 2]> = vec![];
+        for face in FACES {
+            let edges: Vec<(usize, usize)> = (0..4)
+                .map(|ix| (face[ix], face[(ix + 1) % 4]))
+                .filter(|&(a, b)| inside[a] != inside[b])
+                .collect();
+            match edges.len() {
+                2 => segments.push([edges[0], edges[1]]),
+                4 => {
+                    // The edges start at each corner of the face in order. If the mean is inside, the inside corners
+                    // are connected across the face, so the segments cut off the outside corners, and otherwise the
+                    // inside corners.
+                    let mean = face.iter().map(|&corner| values[corner]).sum::<f64>() / 4.0;
+                    let cut_inside = mean >= 0.0;
+                    let first = if inside[face[0]] == cut_inside { 0 } else { 1 };
+                    // The corner `face[first]` is cut off by the edges before and after it.
+                    segments.push([edges[(first + 3) % 4], edges[first]]);
+                    segments.push([edges[(first + 1) % 4], edges[(first + 2) % 4]]);
+                }
+                _ => {}
+            }
+        }
+
+        // Every crossed edge is shared by two faces, so the segments form closed loops.
+        let key = |(a, b): (usize, usize)| (a.min(b), a.max(b));
+        let mut remaining: Vec<[(usize, usize); 2]> = segments
+            .into_iter()
+            .map(|[a, b]| [key(a), key(b)])
+            .collect();
+        let gradient = self.cube_gradient(&values);
+        while let Some([start, mut next]) = remaining.pop() {
+            let mut loop_edges = vec![start];
+            while next != start {
+                loop_edges.push(next);
+                let position = match remaining.iter().position(|segment| segment.contains(&next)) {
+                    Some(position) => position,
+                    None => break,
+                };
+                let segment = remaining.swap_remove(position);
+                next = if segment[0] == next {
+                    segment[1]
+                } else {
+                    segment[0]
+                };
+            }
+            let loop_vertices: Vec<usize> = loop_edges
+                .iter()
+                .map(|&(a, b)| self.edge_vertex(mesh, edge_vertices, &voxels, &values, a, b))
+                .collect();
+            for ix in 1..loop_vertices.len().saturating_sub(1) {
+                let mut triangle = [loop_vertices[0], loop_vertices[ix], loop_vertices[ix + 1]];
+                let [a, b, c] = triangle.map(|vertex| mesh.vertices[vertex]);
+                // Triangles face outwards, which is the direction the distance grows in.
+                if (b - a).cross(&(c - a)).dot(&gradient) < 0.0 {
+                    triangle.swap(1, 2);
+                }
+                mesh.triangles.push(triangle);
+            }
+        }
+    }
+
+    /// The gradient of the distance over a cube from the differences across each axis.
+    fn cube_gradient(&self, values: &[f64; 8]) -> Vector3<f64> {
+        let mut gradient = Vector3::zeros();
+        for (corner, offset) in CORNERS.iter().enumerate() {
+            for axis in 0..3 {
+                let sign = if offset[axis] == 1 { 1.0 } else { -1.0 };
+                gradient[axis] += sign * values[corner];
+            }
+        }
+        gradient
+    }
+
+    /// Finds or creates the vertex at the zero crossing of the edge between two corners of a cube.
+    fn edge_vertex(
+        &self,
+        mesh: &mut Mesh,
+        edge_vertices: &mut HashMap<(usize, usize), usize>,
+        voxels: &[[usize; 3]; 8],
+        values: &[f64; 8],
+        a: usize,
+        b: usize,
+    ) -> usize {
+        let axis = (0..3)
+            .find(|&axis| voxels[a][axis] != voxels[b][axis])
+            .unwrap();
+        let key = (self.index(voxels[a]), axis);
+        if let Some(&vertex) = edge_vertices.get(&key) {
+            return vertex;
+        }
+        let t = values[a] / (values[a] - values[b]);
+        let position =
+            self.position(voxels[a]) + (self.position(voxels[b]) - self.position(voxels[a])) * t;
+        let [color_a, color_b] = [a, b].map(|corner| self.colors[self.index(voxels[corner])]);
+        let color = [0, 1, 2].map(|channel| {
+            (color_a[channel] as f64 * (1.0 - t) + color_b[channel] as f64 * t)
+                .round()
+                .max(0.0)
+                .min(255.0) as u8
+        });
+        let vertex = mesh.vertices.len();
+        mesh.vertices.push(position);
+        mesh.colors.push(color);
+        edge_vertices.insert(key, vertex);
+        vertex
+    }
+}
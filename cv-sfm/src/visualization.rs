@@ -0,0 +1,375 @@
+//! Live visualization of reconstructions in the [rerun](https://www.rerun.io/) viewer.
+//!
+//! A [`RerunSink`] streams what a pipeline does to a rerun viewer while it runs: the keypoints of every frame, the
+//! matches to the previous keyframe, the camera of every view drawn as a frustum, the triangulated landmarks and the
+//! trajectory of the camera. Feed it every [`OdometryUpdate`] with [`RerunSink::log_update`], or log a whole
+//! reconstruction at once with [`RerunSink::log_reconstruction`] or [`RerunSink::log_sparse_reconstruction`].
+//!
+//! Everything is logged under the `world` entity in the right-down-forward coordinates of the cameras. The frames
+//! are placed on a `frame` sequence timeline and a `time` timeline with the timestamps of the updates.
+
+use crate::{FrameKey, OdometryUpdate, ReconstructionKey, SparseReconstruction, VSlam, VSlamData};
+use cv_core::{
+    nalgebra::{IsometryMatrix3, UnitQuaternion},
+    sample_consensus::{Consensus, Estimator},
+    CameraModel, CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Projective,
+    TriangulatorObservations, WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsicsK1Distortion;
+use rand::Rng;
+use rerun::{
+    Color, LineStrips2D, LineStrips3D, Pinhole, Points2D, Points3D, Quaternion, RecordingStream,
+    RecordingStreamBuilder, RecordingStreamResult, Transform3D, ViewCoordinates,
+};
+
+/// The color of keypoints which observe a landmark seen by at least one other view.
+const TRACKED_COLOR: Color = Color::from_rgb(64, 224, 96);
+/// The color of all other keypoints.
+const UNTRACKED_COLOR: Color = Color::from_rgb(160, 160, 160);
+/// The color of matches and trajectories.
+const LINE_COLOR: Color = Color::from_rgb(255, 160, 32);
+
+/// Streams keypoints, matches, cameras, landmarks and trajectories to a rerun viewer.
+pub struct RerunSink {
+    recording: RecordingStream,
+    /// The reconstruction the trajectory is in
+    reconstruction: Option<ReconstructionKey>,
+    /// The optical centers of every tracked or predicted pose in the reconstruction
+    trajectory: Vec<[f32; 3]>,
+    /// The last keyframe of the reconstruction
+    last_keyframe: Option<FrameKey>,
+}
+
+impl RerunSink {
+    /// Creates a sink which logs to an existing recording.
+    pub fn new(recording: RecordingStream) -> Self {
+        recording.log_timeless("world", &ViewCoordinates::RDF).ok();
+        Self {
+            recording,
+            reconstruction: None,
+            trajectory: vec![],
+            last_keyframe: None,
+        }
+    }
+
+    /// Spawns a rerun viewer and creates a sink which logs to it.
+    pub fn spawn(application_id: &str) -> RecordingStreamResult<Self> {
+        Ok(Self::new(
+            RecordingStreamBuilder::new(application_id).spawn()?,
+        ))
+    }
+
+    /// Creates a sink which logs to a rerun viewer which is already running on this machine.
+    pub fn connect(application_id: &str) -> RecordingStreamResult<Self> {
+        Ok(Self::new(
+            RecordingStreamBuilder::new(application_id).connect()?,
+        ))
+    }
+
+    /// The recording which is logged to, which can be used to log anything else alongside the reconstruction.
+    pub fn recording(&self) -> &RecordingStream {
+        &self.recording
+    }
+
+    /// Logs the outcome of feeding one image to [`VisualOdometry`](crate::VisualOdometry).
+    ///
+    /// This logs the keypoints of the frame and, if it is tracked, the camera and the trajectory so far. When the
+    /// frame becomes a keyframe, the matches to the previous keyframe and the landmarks of the reconstruction are
+    /// logged too. The trajectory restarts whenever tracking continues in a new reconstruction.
+    pub fn log_update<C1, C2, PE, EE, T, R>(
+        &mut self,
+        vslam: &VSlam<C1, C2, PE, EE, T, R>,
+        update: &OdometryUpdate,
+    ) -> RecordingStreamResult<()>
+    where
+        C1: Consensus<PE, FeatureWorldMatch>,
+        C2: Consensus<EE, FeatureMatch>,
+        PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+        EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+        T: TriangulatorObservations + Clone,
+        R: Rng,
+    {
+        let frame = vslam.data.frame(update.frame);
+        self.recording
+            .set_time_sequence("frame", frame.feed_frame as i64);
+        self.recording.set_time_seconds("time", update.timestamp);
+
+        if update.reconstruction != self.reconstruction {
+            self.reconstruction = update.reconstruction;
+            self.trajectory.clear();
+            self.last_keyframe = None;
+        }
+
+        self.log_keypoints(&vslam.data, update.frame, "world/camera/image/keypoints")?;
+        if let Some(pose) = update.pose {
+            let intrinsics = &vslam.data.feed(frame.feed).intrinsics;
+            self.log_camera("world/camera", pose, intrinsics)?;
+            self.trajectory.push(optical_center(pose));
+            self.recording.log(
+                "world/trajectory",
+                &LineStrips3D::new([self.trajectory.iter().copied()]).with_colors([LINE_COLOR]),
+            )?;
+        }
+
+        if let (true, Some(reconstruction)) = (update.keyframe, update.reconstruction) {
+            if let Some(previous) = self.last_keyframe {
+                self.log_matches(
+                    &vslam.data,
+                    previous,
+                    update.frame,
+                    "world/camera/image/matches",
+                )?;
+            }
+            self.last_keyframe = Some(update.frame);
+            self.log_landmarks(vslam, reconstruction, "world/landmarks")?;
+        }
+        Ok(())
+    }
+
+    /// Logs every view of a reconstruction as a camera under `world/views`, its trajectory in the order of the
+    /// frames, and its landmarks.
+    pub fn log_reconstruction<C1, C2, PE, EE, T, R>(
+        &self,
+        vslam: &VSlam<C1, C2, PE, EE, T, R>,
+        reconstruction: ReconstructionKey,
+    ) -> RecordingStreamResult<()>
+    where
+        C1: Consensus<PE, FeatureWorldMatch>,
+        C2: Consensus<EE, FeatureMatch>,
+        PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+        EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+        T: TriangulatorObservations + Clone,
+        R: Rng,
+    {
+        let mut views: Vec<_> = vslam
+            .data
+            .reconstruction(reconstruction)
+            .views
+            .values()
+            .map(|view| (vslam.data.frame(view.frame), view))
+            .collect();
+        views.sort_by_key(|(frame, _)| (frame.feed, frame.feed_frame));
+        for (ix, (frame, view)) in views.iter().enumerate() {
+            let intrinsics = &vslam.data.feed(frame.feed).intrinsics;
+            self.log_camera(&format!("world/views/{}", ix), view.pose, intrinsics)?;
+        }
+        self.recording.log(
+            "world/trajectory",
+            &LineStrips3D::new([views.iter().map(|(_, view)| optical_center(view.pose))])
+                .with_colors([LINE_COLOR]),
+        )?;
+        self.log_landmarks(vslam, reconstruction, "world/landmarks")
+    }
+
+    /// Logs the output of [`IncrementalSfm`](crate::IncrementalSfm): the pose of every registered image under
+    /// `world/views`, the trajectory in the order of the images, and the points.
+    ///
+    /// The images have no intrinsics, so the poses are logged as transforms without frusta.
+    pub fn log_sparse_reconstruction(
+        &self,
+        reconstruction: &SparseReconstruction,
+    ) -> RecordingStreamResult<()> {
+        for (image, pose) in reconstruction.registered() {
+            self.recording.log(
+                format!("world/views/{}", image),
+                &transform(pose.inverse().isometry()),
+            )?;
+        }
+        self.recording.log(
+            "world/trajectory",
+            &LineStrips3D::new([reconstruction
+                .registered()
+                .map(|(_, pose)| optical_center(pose))])
+            .with_colors([LINE_COLOR]),
+        )?;
+        let (positions, colors): (Vec<_>, Vec<_>) = reconstruction
+            .points
+            .iter()
+            .filter_map(|point| {
+                let position = point.point.point()?;
+                let [r, g, b] = point.color;
+                Some((
+                    [position.x as f32, position.y as f32, position.z as f32],
+                    Color::from_rgb(r, g, b),
+                ))
+            })
+            .unzip();
+        self.recording.log(
+            "world/landmarks",
+            &Points3D::new(positions).with_colors(colors),
+        )
+    }
+
+    /// Logs the keypoints of a frame in pixels. Keypoints which observe a landmark seen by another view are colored
+    /// differently from the rest.
+    pub fn log_keypoints(
+        &self,
+        data: &VSlamData,
+        frame: FrameKey,
+        entity: &str,
+    ) -> RecordingStreamResult<()> {
+        let frame_data = data.frame(frame);
+        let intrinsics = &data.feed(frame_data.feed).intrinsics;
+        let (positions, colors): (Vec<_>, Vec<_>) = (0..frame_data.descriptor_features.len())
+            .filter_map(|feature| {
+                let position = pixel(intrinsics, data, frame, feature)?;
+                let tracked = frame_data.view.map_or(false, |(reconstruction, view)| {
+                    let landmark = data.observation_landmark(reconstruction, view, feature);
+                    data.landmark(reconstruction, landmark).observations.len() >= 2
+                });
+                Some((
+                    position,
+                    if tracked {
+                        TRACKED_COLOR
+                    } else {
+                        UNTRACKED_COLOR
+                    },
+                ))
+            })
+            .unzip();
+        self.recording
+            .log(entity, &Points2D::new(positions).with_colors(colors))
+    }
+
+    /// Logs the matches between two views of the same reconstruction in the image of the second, as lines from the
+    /// keypoints of the second frame to where the same landmarks were seen in the first.
+    ///
+    /// Nothing is logged unless both frames are views of the same reconstruction.
+    pub fn log_matches(
+        &self,
+        data: &VSlamData,
+        first: FrameKey,
+        second: FrameKey,
+        entity: &str,
+    ) -> RecordingStreamResult<()> {
+        let (reconstruction, first_view, second_view) =
+            match (data.frame(first).view, data.frame(second).view) {
+                (Some((a, first_view)), Some((b, second_view))) if a == b => {
+                    (a, first_view, second_view)
+                }
+                _ => return Ok(()),
+            };
+        let first_intrinsics = &data.feed(data.frame(first).feed).intrinsics;
+        let second_intrinsics = &data.feed(data.frame(second).feed).intrinsics;
+        let segments: Vec<[[f32; 2]; 2]> = data
+            .view(reconstruction, second_view)
+            .landmarks
+            .iter()
+            .enumerate()
+            .filter_map(|(feature, &landmark)| {
+                let &first_feature = data
+                    .landmark(reconstruction, landmark)
+                    .observations
+                    .get(&first_view)?;
+                Some([
+                    pixel(second_intrinsics, data, second, feature)?,
+                    pixel(first_intrinsics, data, first, first_feature)?,
+                ])
+            })
+            .collect();
+        self.recording.log(
+            entity,
+            &LineStrips2D::new(segments).with_colors([LINE_COLOR]),
+        )
+    }
+
+    /// Logs a camera with the given pose as a transform at `entity` and a pinhole frustum at `entity/image`, under
+    /// which keypoints and matches can be logged in pixels.
+    ///
+    /// The resolution of the frustum is taken to be twice the principal point.
+    pub fn log_camera(
+        &self,
+        entity: &str,
+        pose: WorldToCamera,
+        intrinsics: &CameraIntrinsicsK1Distortion,
+    ) -> RecordingStreamResult<()> {
+        self.recording
+            .log(entity, &transform(pose.inverse().isometry()))?;
+        let simple = &intrinsics.simple_intrinsics;
+        self.recording.log(
+            format!("{}/image", entity),
+            &Pinhole::from_focal_length_and_resolution(
+                [simple.focals.x as f32, simple.focals.y as f32],
+                [
+                    2.0 * simple.principal_point.x as f32,
+                    2.0 * simple.principal_point.y as f32,
+                ],
+            ),
+        )
+    }
+
+    /// Logs the landmarks of a reconstruction which can be triangulated robustly, with the colors of their first
+    /// observations.
+    pub fn log_landmarks<C1, C2, PE, EE, T, R>(
+        &self,
+        vslam: &VSlam<C1, C2, PE, EE, T, R>,
+        reconstruction: ReconstructionKey,
+        entity: &str,
+    ) -> RecordingStreamResult<()>
+    where
+        C1: Consensus<PE, FeatureWorldMatch>,
+        C2: Consensus<EE, FeatureMatch>,
+        PE: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+        EE: Estimator<FeatureMatch, Model = CameraToCamera>,
+        T: TriangulatorObservations + Clone,
+        R: Rng,
+    {
+        let (positions, colors): (Vec<_>, Vec<_>) = vslam
+            .data
+            .reconstruction(reconstruction)
+            .landmarks
+            .iter()
+            .filter_map(|(landmark, landmark_data)| {
+                let position = vslam
+                    .triangulate_landmark_robust(reconstruction, landmark)?
+                    .point()?;
+                let (&view, &feature) = landmark_data.observations.iter().next()?;
+                let [r, g, b] = vslam.data.observation_color(reconstruction, view, feature);
+                Some((
+                    [position.x as f32, position.y as f32, position.z as f32],
+                    Color::from_rgb(r, g, b),
+                ))
+            })
+            .unzip();
+        self.recording
+            .log(entity, &Points3D::new(positions).with_colors(colors))
+    }
+}
+
+/// The pixel a feature of a frame was observed at.
+fn pixel(
+    intrinsics: &CameraIntrinsicsK1Distortion,
+    data: &VSlamData,
+    frame: FrameKey,
+    feature: usize,
+) -> Option<[f32; 2]> {
+    let point = intrinsics
+        .uncalibrate(data.frame(frame).bearing(feature))?
+        .0;
+    Some([point.x as f32, point.y as f32])
+}
+
+/// The position of the camera in the world.
+fn optical_center(pose: WorldToCamera) -> [f32; 3] {
+    let center = pose.inverse().isometry().translation.vector;
+    [center.x as f32, center.y as f32, center.z as f32]
+}
+
+/// The transform from a child entity to its parent.
+fn transform(isometry: IsometryMatrix3<f64>) -> Transform3D {
+    let translation = isometry.translation.vector;
+    let rotation = UnitQuaternion::from_rotation_matrix(&isometry.rotation);
+    Transform3D::from_translation_rotation(
+        [
+            translation.x as f32,
+            translation.y as f32,
+            translation.z as f32,
+        ],
+        Quaternion::from_xyzw([
+            rotation.i as f32,
+            rotation.j as f32,
+            rotation.k as f32,
+            rotation.w as f32,
+        ]),
+    )
+}
@@ -0,0 +1,253 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point2, Point3, Rotation3, UnitVector3, Vector2, Vector3},
+    Pose, Projective, WorldPoint, WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsics;
+use cv_sfm::{
+    ColmapCamera, ColmapCameraModel, ColmapImage, ColmapModel, ColmapPoint2D, ColmapPoint3D,
+    SfmImage, SparsePoint, SparseReconstruction,
+};
+use std::path::{Path, PathBuf};
+
+const EPSILON: f64 = 1e-9;
+
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/colmap")
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("cv-sfm-colmap-{}", std::process::id()))
+        .join(name)
+}
+
+fn pose(roll: f64, pitch: f64, yaw: f64, translation: Vector3<f64>) -> WorldToCamera {
+    WorldToCamera(IsometryMatrix3::from_parts(
+        translation.into(),
+        Rotation3::from_euler_angles(roll, pitch, yaw),
+    ))
+}
+
+fn assert_same_pose(a: WorldToCamera, b: WorldToCamera) {
+    let (a, b) = (a.isometry(), b.isometry());
+    assert!((a.translation.vector - b.translation.vector).norm() < EPSILON);
+    assert!(a.rotation.rotation_to(&b.rotation).angle() < EPSILON);
+}
+
+fn assert_same_bearing(a: UnitVector3<f64>, b: Vector3<f64>) {
+    assert!(
+        (a.into_inner() - b.normalize()).norm() < EPSILON,
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+/// Compares models which only differ by the rounding of the poses through quaternions.
+fn assert_same_model(a: &ColmapModel, b: &ColmapModel) {
+    assert_eq!(a.cameras, b.cameras);
+    assert_eq!(a.points, b.points);
+    assert_eq!(a.images.len(), b.images.len());
+    for (a, b) in a.images.iter().zip(&b.images) {
+        assert_eq!(a.id, b.id);
+        assert_eq!(a.camera, b.camera);
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.points, b.points);
+        assert_same_pose(a.pose, b.pose);
+    }
+}
+
+fn model() -> ColmapModel {
+    let keypoint = |x, y, point3d| ColmapPoint2D {
+        position: Point2::new(x, y),
+        point3d,
+    };
+    ColmapModel {
+        cameras: vec![
+            ColmapCamera {
+                id: 1,
+                model: ColmapCameraModel::OpenCv,
+                width: 752,
+                height: 480,
+                params: vec![
+                    458.654, 457.296, 367.215, 248.375, -0.28, 0.07, 0.0002, 1.7e-5,
+                ],
+            },
+            ColmapCamera {
+                id: 3,
+                model: ColmapCameraModel::SimplePinhole,
+                width: 640,
+                height: 480,
+                params: vec![525.0, 320.0, 240.0],
+            },
+        ],
+        images: vec![
+            ColmapImage {
+                id: 1,
+                pose: pose(0.1, -0.2, 0.3, Vector3::new(0.5, -1.0, 2.0)),
+                camera: 1,
+                name: "left/0001.png".to_owned(),
+                points: vec![keypoint(100.25, 200.75, Some(7)), keypoint(0.5, 0.5, None)],
+            },
+            ColmapImage {
+                id: 4,
+                pose: pose(-1.2, 0.4, 2.9, Vector3::new(0.0, 0.1, -0.3)),
+                camera: 3,
+                name: "with space.jpg".to_owned(),
+                points: vec![],
+            },
+            ColmapImage {
+                id: 5,
+                pose: pose(3.0, -1.4, -2.0, Vector3::zeros()),
+                camera: 1,
+                name: "left/0003.png".to_owned(),
+                points: vec![keypoint(1.0 / 3.0, 479.5, Some(7))],
+            },
+        ],
+        points: vec![
+            ColmapPoint3D {
+                id: 7,
+                position: Point3::new(0.1, -2.5, 1e-3),
+                color: [255, 0, 17],
+                error: 0.123456789,
+                track: vec![(1, 0), (5, 0)],
+            },
+            ColmapPoint3D {
+                id: 8,
+                position: Point3::new(-4.0, 1.0 / 7.0, 12.0),
+                color: [1, 2, 3],
+                error: 0.0,
+                track: vec![],
+            },
+        ],
+    }
+}
+
+#[test]
+fn text_round_trip() {
+    let model = model();
+    let dir = temp_dir("text");
+    model.save_text(&dir).unwrap();
+    assert!(!dir.join("cameras.bin").exists());
+    assert_same_model(&ColmapModel::load(&dir).unwrap(), &model);
+}
+
+#[test]
+fn binary_round_trip() {
+    let model = model();
+    let dir = temp_dir("binary");
+    model.save_binary(&dir).unwrap();
+    assert_same_model(&ColmapModel::load_binary(&dir).unwrap(), &model);
+    assert_same_model(&ColmapModel::load(&dir).unwrap(), &model);
+}
+
+#[test]
+fn reconstruction_round_trip() {
+    let intrinsics = CameraIntrinsics {
+        focals: Vector2::new(500.0, 510.0),
+        principal_point: Point2::new(320.0, 240.0),
+        skew: 0.0,
+    };
+    let bearing = |x, y| UnitVector3::new_normalize(Vector3::new(x, y, 1.0));
+    let images = vec![
+        SfmImage::new(vec![bearing(0.0, 0.0), bearing(0.1, -0.2)], vec![(); 2]),
+        SfmImage::new(vec![bearing(0.3, 0.0)], vec![()]),
+        SfmImage::new(vec![bearing(-0.1, 0.1), bearing(0.2, 0.2)], vec![(); 2]),
+    ];
+    let poses = vec![
+        Some(pose(0.0, 0.0, 0.0, Vector3::zeros())),
+        None,
+        Some(pose(0.1, 0.2, -0.1, Vector3::new(-1.0, 0.0, 0.0))),
+    ];
+    let reconstruction = SparseReconstruction {
+        poses: poses.clone(),
+        points: vec![SparsePoint {
+            point: WorldPoint::from_point(Point3::new(0.0, 0.0, 3.0)),
+            color: [9, 8, 7],
+            observations: vec![(0, 0), (2, 1)],
+        }],
+    };
+    let model = reconstruction.colmap_model(&images, &intrinsics, 640, 480, |image| {
+        format!("{:04}.png", image)
+    });
+    assert_eq!(model.cameras.len(), 1);
+    assert_eq!(model.images.len(), 2);
+    assert_eq!(model.images[1].name, "0002.png");
+    assert_eq!(model.points[0].track, vec![(1, 0), (3, 1)]);
+
+    let dir = temp_dir("reconstruction");
+    model.save_text(&dir).unwrap();
+    let (loaded, bearings) = ColmapModel::load(&dir)
+        .unwrap()
+        .sparse_reconstruction()
+        .unwrap();
+    // The unregistered image is not written, so the images after it move down.
+    assert_eq!(loaded.poses.len(), 2);
+    assert_same_pose(loaded.poses[0].unwrap(), poses[0].unwrap());
+    assert_same_pose(loaded.poses[1].unwrap(), poses[2].unwrap());
+    for (loaded, original) in bearings.iter().zip([&images[0], &images[2]]) {
+        assert_eq!(loaded.len(), original.bearings.len());
+        for (&loaded, &original) in loaded.iter().zip(&original.bearings) {
+            assert_same_bearing(loaded, original.into_inner());
+        }
+    }
+    assert_eq!(loaded.points.len(), 1);
+    let point = &loaded.points[0];
+    assert!((point.point.point().unwrap() - Point3::new(0.0, 0.0, 3.0)).norm() < EPSILON);
+    assert_eq!(point.color, [9, 8, 7]);
+    assert_eq!(point.observations, vec![(0, 0), (1, 1)]);
+}
+
+#[test]
+fn parse_fixture() {
+    let model = ColmapModel::load(fixture()).unwrap();
+
+    assert_eq!(model.cameras.len(), 2);
+    assert_eq!(model.cameras[0].model, ColmapCameraModel::Pinhole);
+    assert_eq!(model.cameras[0].params, vec![500.0, 510.0, 320.5, 240.5]);
+    assert_eq!(model.cameras[1].model, ColmapCameraModel::SimpleRadial);
+    assert_eq!(
+        (model.cameras[1].width, model.cameras[1].height),
+        (1024, 768)
+    );
+
+    let ids: Vec<u32> = model.images.iter().map(|image| image.id).collect();
+    assert_eq!(ids, vec![1, 3, 2]);
+    let first = &model.images[0];
+    assert_eq!(first.name, "frame 0001.png");
+    assert_eq!(first.camera, 1);
+    assert_eq!(
+        first
+            .points
+            .iter()
+            .map(|point| point.point3d)
+            .collect::<Vec<_>>(),
+        vec![Some(1), None, Some(2)]
+    );
+    assert!(model.images[1].points.is_empty());
+    let second = &model.images[2];
+    assert_eq!(second.camera, 2);
+    assert_eq!(second.points[0].position, Point2::new(512.0, 384.0));
+    // The second image looks at the first point from the side.
+    let camera_point = second.pose.isometry() * Point3::new(0.0, 0.0, 2.0);
+    assert!((camera_point - Point3::new(0.0, 0.0, 2.0)).norm() < EPSILON);
+
+    assert_eq!(model.points.len(), 2);
+    assert_eq!(model.points[0].color, [255, 128, 0]);
+    assert_eq!(model.points[0].error, 0.25);
+    assert_eq!(model.points[0].track, vec![(1, 0), (2, 0)]);
+    assert_eq!(model.points[1].track, vec![(1, 2)]);
+
+    // Every keypoint observing a point looks along the ray to it.
+    let bearings = model.bearings(first).unwrap();
+    assert_same_bearing(bearings[0], Vector3::z());
+    assert_same_bearing(bearings[1], Vector3::new(0.5, 0.0, 1.0));
+    assert_same_bearing(bearings[2], Vector3::new(0.0, 0.1, 1.0));
+    assert_same_bearing(model.bearings(second).unwrap()[0], Vector3::z());
+
+    let (reconstruction, bearings) = model.sparse_reconstruction().unwrap();
+    assert_eq!(reconstruction.num_registered(), 3);
+    assert_eq!(bearings[1].len(), 0);
+    assert_eq!(reconstruction.points[0].observations, vec![(0, 0), (2, 0)]);
+    assert_eq!(reconstruction.points[1].observations, vec![(0, 2)]);
+}
@@ -0,0 +1,306 @@
+use cv_core::{nalgebra::Vector3, Pose};
+use cv_sfm::Dataset;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const EPSILON: f64 = 1e-9;
+
+/// Writes the files of a fixture to a fresh directory under the temporary directory.
+fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir()
+        .join(format!("cv-sfm-dataset-{}", std::process::id()))
+        .join(name);
+    if root.exists() {
+        fs::remove_dir_all(&root).unwrap();
+    }
+    for (path, contents) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    root
+}
+
+/// Replaces one file of a fixture.
+fn overwrite(root: &Path, path: &str, contents: &str) {
+    fs::write(root.join(path), contents).unwrap();
+}
+
+fn assert_invalid<T: std::fmt::Debug>(result: io::Result<T>) {
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+const KITTI_CALIBRATION: &str = "\
+P0: 718.856 0.0 607.1928 0.0 0.0 718.856 185.2157 0.0 0.0 0.0 1.0 0.0
+P1: 718.856 0.0 607.1928 -386.1448 0.0 718.856 185.2157 0.0 0.0 0.0 1.0 0.0
+";
+
+const KITTI_POSES: &str = "\
+1 0 0 0 0 1 0 0 0 0 1 0
+1 0 0 0.5 0 1 0 -0.1 0 0 1 1.5
+";
+
+fn kitti(name: &str) -> PathBuf {
+    fixture(
+        name,
+        &[
+            ("sequences/00/calib.txt", KITTI_CALIBRATION),
+            ("sequences/00/times.txt", "0.000000e+00\n1.036602e-01\n\n"),
+            ("poses/00.txt", KITTI_POSES),
+        ],
+    )
+}
+
+#[test]
+fn kitti_sequence() {
+    let root = kitti("kitti_sequence");
+    let dataset = Dataset::kitti(&root, "00").unwrap();
+    assert_eq!(dataset.intrinsics.simple_intrinsics.focals.x, 718.856);
+    assert_eq!(
+        dataset.intrinsics.simple_intrinsics.principal_point.y,
+        185.2157
+    );
+    assert_eq!(dataset.depth_scale, None);
+    assert_eq!(dataset.len(), 2);
+    let frame = &dataset.frames[1];
+    assert!((frame.timestamp - 0.1036602).abs() < EPSILON);
+    assert_eq!(
+        frame.image,
+        root.join("sequences/00/image_0").join("000001.png")
+    );
+    let pose = frame.ground_truth.unwrap().isometry();
+    assert!((pose.translation.vector - Vector3::new(0.5, -0.1, 1.5)).norm() < EPSILON);
+    assert_eq!(dataset.ground_truth().len(), 2);
+
+    // The test sequences have no ground truth.
+    fs::remove_file(root.join("poses/00.txt")).unwrap();
+    let dataset = Dataset::kitti(&root, "00").unwrap();
+    assert!(dataset.ground_truth().is_empty());
+}
+
+#[test]
+fn kitti_rejects_malformed_lines() {
+    let root = kitti("kitti_malformed");
+    overwrite(&root, "poses/00.txt", "1 0 0 0 0 1 0 0 0 0 1\n");
+    assert_invalid(Dataset::kitti(&root, "00"));
+
+    let root = kitti("kitti_malformed");
+    overwrite(&root, "sequences/00/times.txt", "0.0\nNaN\n");
+    assert_invalid(Dataset::kitti(&root, "00"));
+
+    let root = kitti("kitti_malformed");
+    overwrite(
+        &root,
+        "sequences/00/calib.txt",
+        "P1: 718.856 0.0 607.1928\n",
+    );
+    assert_invalid(Dataset::kitti(&root, "00"));
+}
+
+const TUM_RGB: &str = "\
+# color images
+# file: 'rgbd_dataset_freiburg1_xyz.bag'
+# timestamp filename
+1.000000 rgb/1.000000.png
+1.100000 rgb/1.100000.png
+";
+
+const TUM_DEPTH: &str = "\
+# depth maps
+1.010000 depth/1.010000.png
+";
+
+const TUM_GROUND_TRUTH: &str = "\
+# ground truth trajectory
+# timestamp tx ty tz qx qy qz qw
+1.005000 1.0 2.0 3.0 0.0 0.0 0.0 1.0
+1.300000 1.0 2.0 4.0 0.0 0.0 0.0 1.0
+";
+
+const TUM_ACCELEROMETER: &str = "\
+# accelerometer data
+# timestamp ax ay az
+0.950000 0.0 -9.8 0.1
+1.050000 0.0 -9.8 0.2
+1.200000 0.0 -9.8 0.3
+";
+
+fn tum(name: &str) -> PathBuf {
+    // The name of the directory tells which camera recorded the sequence.
+    fixture(
+        &format!("rgbd_dataset_freiburg1_{}", name),
+        &[
+            ("rgb.txt", TUM_RGB),
+            ("depth.txt", TUM_DEPTH),
+            ("groundtruth.txt", TUM_GROUND_TRUTH),
+            ("accelerometer.txt", TUM_ACCELEROMETER),
+        ],
+    )
+}
+
+#[test]
+fn tum_rgbd_sequence() {
+    let root = tum("sequence");
+    let dataset = Dataset::tum_rgbd(&root).unwrap();
+    assert_eq!(dataset.intrinsics.simple_intrinsics.focals.x, 517.3);
+    assert_eq!(dataset.intrinsics.k1, 0.2624);
+    assert_eq!(dataset.depth_scale, Some(1.0 / 5000.0));
+    assert_eq!(dataset.len(), 2);
+
+    // Only the first color image has a depth image and ground-truth pose within 20 milliseconds.
+    let first = &dataset.frames[0];
+    assert_eq!(first.image, root.join("rgb/1.000000.png"));
+    assert_eq!(first.depth, Some(root.join("depth/1.010000.png")));
+    let pose = first.ground_truth.unwrap().isometry();
+    assert!((pose.translation.vector - Vector3::new(1.0, 2.0, 3.0)).norm() < EPSILON);
+    let second = &dataset.frames[1];
+    assert_eq!(second.depth, None);
+    assert_eq!(second.ground_truth, None);
+
+    // The accelerometer samples after the last image belong to no frame.
+    assert_eq!(first.imu.len(), 1);
+    assert_eq!(first.imu[0].angular_velocity, None);
+    assert_eq!(
+        first.imu[0].linear_acceleration,
+        Vector3::new(0.0, -9.8, 0.1)
+    );
+    assert_eq!(second.imu.len(), 1);
+    assert_eq!(second.imu[0].timestamp, 1.05);
+}
+
+#[test]
+fn tum_rgbd_rejects_malformed_lines() {
+    let root = tum("malformed");
+    overwrite(&root, "groundtruth.txt", "1.005 1.0 2.0 3.0 0.0 0.0 1.0\n");
+    assert_invalid(Dataset::tum_rgbd(&root));
+
+    let root = tum("malformed");
+    overwrite(
+        &root,
+        "groundtruth.txt",
+        "1.005 1.0 NaN 3.0 0.0 0.0 0.0 1.0\n",
+    );
+    assert_invalid(Dataset::tum_rgbd(&root));
+
+    let root = tum("malformed");
+    overwrite(&root, "rgb.txt", "1.000000\n");
+    assert_invalid(Dataset::tum_rgbd(&root));
+
+    let root = tum("malformed");
+    overwrite(&root, "accelerometer.txt", "inf 0.0 -9.8 0.1\n");
+    assert_invalid(Dataset::tum_rgbd(&root));
+}
+
+const EUROC_SENSOR: &str = "\
+sensor_type: camera
+comment: VI-Sensor cam0 (MT9M034)
+
+T_BS:
+  cols: 4
+  rows: 4
+  data: [1.0, 0.0, 0.0, 0.1,
+         0.0, 1.0, 0.0, 0.0,
+         0.0, 0.0, 1.0, 0.0,
+         0.0, 0.0, 0.0, 1.0]
+
+rate_hz: 20
+resolution: [752, 480]
+camera_model: pinhole
+intrinsics: [458.654, 457.296, 367.215, 248.375]
+distortion_model: radial-tangential
+distortion_coefficients: [-0.28340811, 0.07395907, 0.00019359, 1.76187114e-05]
+";
+
+const EUROC_IMAGES: &str = "\
+#timestamp [ns],filename
+1000000000,1000000000.png
+2000000000,2000000000.png
+";
+
+const EUROC_GROUND_TRUTH: &str = "\
+#timestamp, p_RS_R_x [m], p_RS_R_y [m], p_RS_R_z [m], q_RS_w [], q_RS_x [], q_RS_y [], q_RS_z [], v_RS_R_x [m s^-1]
+1001000000,1.0,2.0,3.0,1.0,0.0,0.0,0.0,0.0
+";
+
+const EUROC_IMU: &str = "\
+#timestamp [ns],w_RS_S_x [rad s^-1],w_RS_S_y [rad s^-1],w_RS_S_z [rad s^-1],a_RS_S_x [m s^-2],a_RS_S_y [m s^-2],a_RS_S_z [m s^-2]
+1000000000,0.1,0.2,0.3,9.8,0.0,0.0
+1500000000,0.1,0.2,0.3,9.7,0.0,0.0
+2000000000,0.1,0.2,0.3,9.6,0.0,0.0
+";
+
+fn euroc(name: &str) -> PathBuf {
+    fixture(
+        name,
+        &[
+            ("mav0/cam0/sensor.yaml", EUROC_SENSOR),
+            ("mav0/cam0/data.csv", EUROC_IMAGES),
+            (
+                "mav0/state_groundtruth_estimate0/data.csv",
+                EUROC_GROUND_TRUTH,
+            ),
+            ("mav0/imu0/data.csv", EUROC_IMU),
+        ],
+    )
+}
+
+#[test]
+fn euroc_sequence() {
+    let root = euroc("euroc_sequence");
+    let dataset = Dataset::euroc(&root).unwrap();
+    assert_eq!(dataset.intrinsics.simple_intrinsics.focals.y, 457.296);
+    assert_eq!(dataset.intrinsics.k1, -0.28340811);
+    assert_eq!(dataset.len(), 2);
+
+    let first = &dataset.frames[0];
+    assert!((first.timestamp - 1.0).abs() < EPSILON);
+    assert_eq!(first.image, root.join("mav0/cam0/data/1000000000.png"));
+    // The ground truth is moved from the body to the camera by the extrinsics.
+    let pose = first.ground_truth.unwrap().isometry();
+    assert!((pose.translation.vector - Vector3::new(1.1, 2.0, 3.0)).norm() < EPSILON);
+    assert_eq!(dataset.frames[1].ground_truth, None);
+
+    assert_eq!(first.imu.len(), 1);
+    assert_eq!(
+        first.imu[0].angular_velocity,
+        Some(Vector3::new(0.1, 0.2, 0.3))
+    );
+    assert_eq!(dataset.frames[1].imu.len(), 2);
+    assert_eq!(
+        dataset.frames[1].imu[1].linear_acceleration,
+        Vector3::new(9.6, 0.0, 0.0)
+    );
+}
+
+#[test]
+fn euroc_rejects_malformed_lines() {
+    let root = euroc("euroc_malformed");
+    overwrite(
+        &root,
+        "mav0/imu0/data.csv",
+        "1000000000,0.1,0.2,0.3,9.8,0.0\n",
+    );
+    assert_invalid(Dataset::euroc(&root));
+
+    let root = euroc("euroc_malformed");
+    overwrite(
+        &root,
+        "mav0/imu0/data.csv",
+        "1000000000,0.1,NaN,0.3,9.8,0.0,0.0\n",
+    );
+    assert_invalid(Dataset::euroc(&root));
+
+    let root = euroc("euroc_malformed");
+    overwrite(&root, "mav0/cam0/data.csv", "1000000000\n");
+    assert_invalid(Dataset::euroc(&root));
+
+    let root = euroc("euroc_malformed");
+    overwrite(
+        &root,
+        "mav0/cam0/sensor.yaml",
+        &EUROC_SENSOR.replace("367.215, ", ""),
+    );
+    assert_invalid(Dataset::euroc(&root));
+}
@@ -0,0 +1,134 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Rotation3, Vector3},
+    CameraToWorld,
+};
+use cv_sfm::{associate, Alignment, Segment, TrajectoryEvaluator};
+
+const EPSILON: f64 = 1e-6;
+
+/// A ground-truth trajectory which moves along a curve while turning, so that it doesn't lie on a line.
+fn ground_truth() -> Vec<(f64, CameraToWorld)> {
+    (0..20)
+        .map(|ix| {
+            let t = ix as f64 * 0.1;
+            let pose = IsometryMatrix3::from_parts(
+                Vector3::new(t.cos(), t.sin(), 0.3 * t).into(),
+                Rotation3::from_euler_angles(0.1 * t, 0.2 * t, t),
+            );
+            (t, CameraToWorld(pose))
+        })
+        .collect()
+}
+
+/// Places a trajectory in another world which differs from the original by a similarity.
+fn transformed(
+    trajectory: &[(f64, CameraToWorld)],
+    scale: f64,
+    rotation: Rotation3<f64>,
+    translation: Vector3<f64>,
+) -> Vec<(f64, CameraToWorld)> {
+    trajectory
+        .iter()
+        .map(|&(timestamp, CameraToWorld(pose))| {
+            let pose = IsometryMatrix3::from_parts(
+                (scale * (rotation * pose.translation.vector) + translation).into(),
+                rotation * pose.rotation,
+            );
+            (timestamp, CameraToWorld(pose))
+        })
+        .collect()
+}
+
+#[test]
+fn sim3_alignment_removes_similarity() {
+    let truth = ground_truth();
+    let estimated = transformed(
+        &truth,
+        2.5,
+        Rotation3::from_euler_angles(0.3, -0.4, 1.2),
+        Vector3::new(1.0, -2.0, 3.0),
+    );
+
+    let ate = TrajectoryEvaluator::new()
+        .alignment(Alignment::Sim3)
+        .absolute_trajectory_error(&estimated, &truth)
+        .unwrap();
+    assert_eq!(ate.associations.len(), truth.len());
+    assert!((ate.alignment.scale() - 1.0 / 2.5).abs() < EPSILON);
+    assert!(
+        ate.translation.max < EPSILON,
+        "translation error {}",
+        ate.translation.max
+    );
+    assert!(
+        ate.rotation.max < EPSILON,
+        "rotation error {}",
+        ate.rotation.max
+    );
+
+    // A rigid alignment can't undo the change in scale.
+    let ate = TrajectoryEvaluator::new()
+        .alignment(Alignment::Se3)
+        .absolute_trajectory_error(&estimated, &truth)
+        .unwrap();
+    assert!(
+        ate.translation.rmse > 0.1,
+        "translation error {}",
+        ate.translation.rmse
+    );
+}
+
+#[test]
+fn relative_pose_error_between_frames() {
+    let frame = |ix: usize, x: f64, yaw: f64| {
+        let pose = IsometryMatrix3::from_parts(
+            Vector3::new(x, 0.0, 0.0).into(),
+            Rotation3::from_euler_angles(0.0, 0.0, yaw),
+        );
+        (ix as f64 * 0.1, CameraToWorld(pose))
+    };
+    // The ground truth moves one unit per frame, while the estimate moves 1.1 units and turns in the last frame.
+    let truth: Vec<_> = (0..4).map(|ix| frame(ix, ix as f64, 0.0)).collect();
+    let estimated = vec![
+        frame(0, 0.0, 0.0),
+        frame(1, 1.1, 0.0),
+        frame(2, 2.2, 0.0),
+        frame(3, 3.3, 0.1),
+    ];
+
+    let rpe = TrajectoryEvaluator::new()
+        .alignment(Alignment::None)
+        .relative_pose_error(&estimated, &truth, &[Segment::Frames(1)]);
+    assert_eq!(rpe.len(), 1);
+    let rpe = &rpe[0];
+    assert_eq!(rpe.segment, Segment::Frames(1));
+    assert_eq!(rpe.segments, 3);
+    assert!((rpe.translation.mean - 0.1).abs() < EPSILON);
+    assert!((rpe.translation.rmse - 0.1).abs() < EPSILON);
+    assert!((rpe.translation.max - 0.1).abs() < EPSILON);
+    let turn = 0.1f64.to_degrees();
+    assert!(rpe.rotation.min.abs() < EPSILON);
+    assert!((rpe.rotation.max - turn).abs() < EPSILON);
+    assert!((rpe.rotation.mean - turn / 3.0).abs() < EPSILON);
+    // Every segment travels one unit of the ground truth.
+    let drift = rpe.translation_drift.unwrap();
+    assert!((drift.mean - 0.1).abs() < EPSILON);
+}
+
+#[test]
+fn associate_uses_timestamps_once() {
+    // The second timestamp `0.004` is closest to `0.0`, so `0.01` is left without a match.
+    let first = [0.0, 0.01, 0.1, 0.2];
+    let second = [0.004, 0.3, 0.195];
+    assert_eq!(associate(&first, &second, 0.0, 0.02), vec![(0, 0), (3, 2)]);
+}
+
+#[test]
+fn associate_respects_max_difference() {
+    let first = [0.0, 1.0, 2.0];
+    let second = [0.05, 1.01, 2.5];
+    assert_eq!(associate(&first, &second, 0.0, 0.02), vec![(1, 1)]);
+    assert_eq!(associate(&first, &second, 0.0, 0.1), vec![(0, 0), (1, 1)]);
+    // The offset is added to the second timestamps.
+    assert_eq!(associate(&first, &second, -0.5, 0.02), vec![(2, 2)]);
+}
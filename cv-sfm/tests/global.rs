@@ -0,0 +1,182 @@
+use arrsac::Arrsac;
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point3, Rotation3, UnitVector3, Vector3},
+    sample_consensus::Estimator,
+    Pose, Projective, WorldToCamera,
+};
+use cv_geom::{
+    alignment::{PointMatch, Umeyama},
+    triangulation::LinearEigenTriangulator,
+};
+use cv_sfm::{
+    average_rotations, average_translations,
+    matching::{DescriptorMatch, Matcher},
+    GlobalSfm, RelativeDirection, RelativeRotation, SfmImage,
+};
+use eight_point::EightPoint;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+const POINTS: usize = 200;
+const IMAGES: usize = 5;
+
+/// Cameras on an arc around the points, each turned slightly differently.
+fn poses() -> Vec<WorldToCamera> {
+    (0..IMAGES)
+        .map(|ix| {
+            let ix = ix as f64;
+            let center = Vector3::new(0.5 * ix - 1.0, 0.1 * (ix * 1.3).sin(), 0.05 * ix * ix);
+            let rotation = Rotation3::from_euler_angles(0.02 * ix, 0.15 - 0.07 * ix, 0.03 * ix);
+            WorldToCamera(IsometryMatrix3::from_parts(
+                (-(rotation * center)).into(),
+                rotation,
+            ))
+        })
+        .collect()
+}
+
+fn center(pose: WorldToCamera) -> Vector3<f64> {
+    pose.inverse().isometry().translation.vector
+}
+
+#[test]
+fn rotation_averaging_rejects_an_outlier() {
+    let rotations: Vec<Rotation3<f64>> = poses()
+        .into_iter()
+        .map(|pose| pose.isometry().rotation)
+        .collect();
+    let mut relative = vec![];
+    for a in 0..IMAGES {
+        for b in a + 1..IMAGES {
+            relative.push(RelativeRotation {
+                a,
+                b,
+                rotation: rotations[b] * rotations[a].inverse(),
+                weight: 100.0,
+            });
+        }
+    }
+    // A wrong relative rotation with a low weight, so it is not part of the spanning tree.
+    relative.push(RelativeRotation {
+        a: 1,
+        b: 3,
+        rotation: Rotation3::from_euler_angles(0.5, -0.3, 0.2) * relative[5].rotation,
+        weight: 20.0,
+    });
+
+    let averaged = average_rotations(IMAGES, &relative, 2, 100);
+    for (image, rotation) in averaged.iter().enumerate() {
+        // The root is held at the identity, so the rotations are relative to it.
+        let truth = rotations[image] * rotations[2].inverse();
+        let error = rotation.unwrap().rotation_to(&truth).angle();
+        assert!(error < 1e-6, "image {} is off by {}", image, error);
+    }
+}
+
+#[test]
+fn translation_averaging_recovers_centers() {
+    let centers: Vec<Vector3<f64>> = poses().into_iter().map(center).collect();
+    let mut relative = vec![];
+    for a in 0..IMAGES {
+        for b in a + 1..IMAGES {
+            relative.push(RelativeDirection {
+                a,
+                b,
+                direction: (centers[b] - centers[a]).normalize(),
+                weight: 1.0,
+            });
+        }
+    }
+    // An image without any direction can't be placed.
+    let averaged = average_translations(IMAGES + 1, &relative, 0, 100);
+    assert_eq!(averaged[IMAGES], None);
+    assert_eq!(averaged[0], Some(Vector3::zeros()));
+
+    // The centers are only known up to scale.
+    let scale = averaged[1].unwrap().norm() / (centers[1] - centers[0]).norm();
+    for image in 0..IMAGES {
+        let expected = scale * (centers[image] - centers[0]);
+        let error = (averaged[image].unwrap() - expected).norm() / scale;
+        assert!(error < 1e-4, "image {} is off by {}", image, error);
+    }
+}
+
+/// Matches the features which observe the same point, whose index is the descriptor.
+struct PointMatcher;
+
+impl Matcher<usize> for PointMatcher {
+    fn match_descriptors(&self, query: &[usize], target: &[usize]) -> Vec<DescriptorMatch> {
+        query
+            .iter()
+            .enumerate()
+            .filter_map(|(query, point)| {
+                Some(DescriptorMatch {
+                    query,
+                    target: target.iter().position(|other| other == point)?,
+                    distance: 0.0,
+                })
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn reconstructs_synthetic_scene() {
+    let mut rng = Pcg64::from_seed([8; 32]);
+    let points: Vec<Point3<f64>> = (0..POINTS)
+        .map(|_| {
+            Point3::new(
+                rng.gen_range(-1.5..1.5),
+                rng.gen_range(-1.5..1.5),
+                rng.gen_range(4.0..7.0),
+            )
+        })
+        .collect();
+    let poses = poses();
+    let images: Vec<SfmImage<usize>> = poses
+        .iter()
+        .enumerate()
+        .map(|(image, pose)| {
+            let visible: Vec<usize> = (0..POINTS)
+                .filter(|point| (point + 2 * image) % 9 != 0)
+                .collect();
+            let bearings = visible
+                .iter()
+                .map(|&point| UnitVector3::new_normalize((pose.isometry() * points[point]).coords))
+                .collect();
+            SfmImage::new(bearings, visible)
+        })
+        .collect();
+
+    let reconstruction = GlobalSfm::new(
+        PointMatcher,
+        Arrsac::new(1e-6, Pcg64::from_seed([1; 32])),
+        EightPoint::new(),
+        LinearEigenTriangulator::new(),
+    )
+    .reconstruct(&images)
+    .expect("no pair was verified");
+    assert_eq!(reconstruction.num_registered(), IMAGES);
+    assert!(reconstruction.points.len() > POINTS * 9 / 10);
+
+    // The reconstruction is only known up to a similarity, so it is aligned by the camera centers.
+    let centers: Vec<PointMatch> = reconstruction
+        .registered()
+        .map(|(image, pose)| PointMatch(center(pose).into(), center(poses[image]).into()))
+        .collect();
+    let alignment = Umeyama::new().estimate(centers.into_iter()).unwrap();
+    for (image, pose) in reconstruction.registered() {
+        let error =
+            (alignment.0 * Point3::from(center(pose)) - Point3::from(center(poses[image]))).norm();
+        assert!(error < 1e-4, "image {} is off by {}", image, error);
+        let rotation = alignment.rotation() * pose.isometry().rotation.inverse();
+        let truth = poses[image].isometry().rotation.inverse();
+        assert!(rotation.rotation_to(&truth).angle() < 1e-4);
+    }
+    for point in &reconstruction.points {
+        let (image, feature) = point.observations[0];
+        let truth = points[images[image].descriptors[feature]];
+        let error = (alignment.0 * point.point.point().unwrap() - truth).norm();
+        assert!(error < 1e-3, "point is off by {}", error);
+    }
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "gpu")]
+
+use bitarray::{BitArray, Hamming};
+use cv_sfm::matching::{BruteForceMatcher, GpuHammingMatcher, Matcher};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+/// Random descriptors, of which the targets are noisy copies of the queries in a different order, so that most
+/// queries have a clear match and some have ties.
+fn descriptors<const B: usize>() -> (Vec<BitArray<B>>, Vec<BitArray<B>>) {
+    let mut rng = Pcg64::from_seed([5; 32]);
+    let query: Vec<BitArray<B>> = (0..300)
+        .map(|_| BitArray::new([(); B].map(|_| rng.gen())))
+        .collect();
+    let target = (0..400)
+        .map(|ix| {
+            let mut bytes = query[(ix * 7) % query.len()].bytes;
+            for _ in 0..ix % 40 {
+                bytes[rng.gen_range(0..B)] ^= 1 << rng.gen_range(0..8);
+            }
+            BitArray::new(bytes)
+        })
+        .collect();
+    (query, target)
+}
+
+fn matches_like_brute_force<const B: usize>(matcher: &mut GpuHammingMatcher) {
+    let (query, target) = descriptors::<B>();
+    for symmetric in [false, true] {
+        matcher.symmetric = symmetric;
+        let cpu = BruteForceMatcher::new(Hamming).symmetric(symmetric);
+        let expected = cpu.match_descriptors(&query, &target);
+        assert!(!expected.is_empty());
+        assert_eq!(matcher.match_descriptors(&query, &target), expected);
+    }
+    // Without at least two targets there are no matches.
+    assert!(matcher.match_descriptors(&query, &target[..1]).is_empty());
+    assert!(matcher.match_descriptors(&[], &target).is_empty());
+}
+
+#[test]
+fn gpu_matches_equal_cpu_matches() {
+    let mut matcher = match pollster::block_on(GpuHammingMatcher::new()) {
+        Some(matcher) => matcher,
+        None => {
+            eprintln!("skipping GPU matching test since no adapter is available");
+            return;
+        }
+    };
+    // AKAZE descriptors are 64 bytes, and descriptors which aren't a whole number of words are padded.
+    matches_like_brute_force::<64>(&mut matcher);
+    matches_like_brute_force::<61>(&mut matcher);
+}
@@ -0,0 +1,145 @@
+use arrsac::Arrsac;
+use cv_consensus::Ransac;
+use cv_core::{
+    nalgebra::{Point3, Rotation3, Unit, Vector3},
+    CameraToCamera, FeatureMatch, Pose,
+};
+use cv_pinhole::FourPoint;
+use cv_sfm::{TwoViewInitialization, TwoViewInitializer, TwoViewModel};
+use eight_point::EightPoint;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+const POINTS: usize = 100;
+
+fn matches(pose: CameraToCamera) -> Vec<FeatureMatch> {
+    let mut rng = Pcg64::from_seed([5; 32]);
+    (0..POINTS)
+        .map(|_| {
+            let point = Point3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(2.0..6.0),
+            );
+            let transformed = pose.isometry() * point;
+            FeatureMatch(
+                Unit::new_normalize(point.coords),
+                Unit::new_normalize(transformed.coords),
+            )
+        })
+        .collect()
+}
+
+/// Gets matches of points on the plane `transpose(normal) * X = distance` which cover a wide field of view, so that
+/// only one decomposition of the homography puts all of the points in front of both cameras.
+fn planar_matches(pose: CameraToCamera, normal: Vector3<f64>, distance: f64) -> Vec<FeatureMatch> {
+    let mut rng = Pcg64::from_seed([5; 32]);
+    (0..POINTS)
+        .map(|_| {
+            let x = rng.gen_range(-2.0..2.0);
+            let y = rng.gen_range(-2.0..2.0);
+            let point = Point3::new(x, y, (distance - normal.x * x - normal.y * y) / normal.z);
+            let transformed = pose.isometry() * point;
+            FeatureMatch(
+                Unit::new_normalize(point.coords),
+                Unit::new_normalize(transformed.coords),
+            )
+        })
+        .collect()
+}
+
+fn initialize(matches: &[FeatureMatch]) -> Option<TwoViewInitialization> {
+    TwoViewInitializer::new().initialize(
+        &mut Arrsac::new(0.001, Pcg64::from_seed([1; 32])),
+        &EightPoint::new(),
+        &mut Arrsac::new(0.001, Pcg64::from_seed([2; 32])),
+        &FourPoint::new(),
+        matches,
+    )
+}
+
+#[test]
+fn pure_rotation() {
+    let rotation = Rotation3::from_euler_angles(0.05, 0.1, -0.02);
+    let matches = matches(CameraToCamera::from_parts(Vector3::zeros(), rotation));
+    let initialization = initialize(&matches).expect("failed to initialize");
+    assert_eq!(initialization.model, TwoViewModel::Rotation);
+    assert_eq!(initialization.inliers.len(), POINTS);
+    let recovered = initialization.pose.isometry().rotation;
+    assert!(recovered.rotation_to(&rotation).angle() < 1e-6);
+}
+
+#[test]
+fn general_scene() {
+    let rotation = Rotation3::from_euler_angles(0.05, 0.1, -0.02);
+    let matches = matches(CameraToCamera::from_parts(
+        Vector3::new(0.5, 0.1, 0.0),
+        rotation,
+    ));
+    let initialization = initialize(&matches).expect("failed to initialize");
+    assert_eq!(initialization.model, TwoViewModel::Essential);
+    assert_eq!(initialization.inliers.len(), POINTS);
+    assert!(initialization.essential_gric < initialization.homography_gric);
+}
+
+#[test]
+fn planar_scene() {
+    let rotation = Rotation3::from_euler_angles(0.05, 0.1, -0.02);
+    let translation = Vector3::new(0.3, 0.0, 0.0);
+    let normal = Vector3::new(0.1, -0.2, 1.0).normalize();
+    let distance = 2.0;
+    let matches = planar_matches(
+        CameraToCamera::from_parts(translation, rotation),
+        normal,
+        distance,
+    );
+    let initialization = initialize(&matches).expect("failed to initialize");
+    assert_eq!(initialization.model, TwoViewModel::Planar);
+    assert_eq!(initialization.inliers.len(), POINTS);
+    let homography_gric = initialization.homography_gric.unwrap();
+    assert!(initialization
+        .essential_gric
+        .map_or(true, |essential_gric| homography_gric < essential_gric));
+    // The translation of a planar pose is scaled by the inverse of the distance to the plane.
+    let recovered = initialization.pose.isometry();
+    assert!(recovered.rotation.rotation_to(&rotation).angle() < 1e-6);
+    assert!((recovered.translation.vector - translation / distance).norm() < 1e-6);
+    assert!((initialization.plane_normal.unwrap() - normal).norm() < 1e-6);
+}
+
+#[test]
+fn reports_both_models() {
+    let rotation = Rotation3::from_euler_angles(0.05, 0.1, -0.02);
+    let mut matches = matches(CameraToCamera::from_parts(
+        Vector3::new(0.5, 0.1, 0.0),
+        rotation,
+    ));
+    // Every fifth match is to an unrelated bearing.
+    let mut rng = Pcg64::from_seed([6; 32]);
+    for m in matches.iter_mut().step_by(5) {
+        m.1 = Unit::new_normalize(Vector3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            1.0,
+        ));
+    }
+    let (initialization, reports) = TwoViewInitializer::new().initialize_report(
+        &mut Ransac::new(0.001, Pcg64::from_seed([1; 32])),
+        &EightPoint::new(),
+        &mut Ransac::new(0.001, Pcg64::from_seed([2; 32])),
+        &FourPoint::new(),
+        &matches,
+    );
+    let initialization = initialization.expect("failed to initialize");
+    assert_eq!(initialization.model, TwoViewModel::Essential);
+
+    let essential = reports.essential.expect("no essential matrix report");
+    assert_eq!(essential.inliers, initialization.inliers);
+    assert_eq!(essential.inlier_mask.len(), POINTS);
+    assert!(essential.iterations > 0);
+    assert!(essential.inliers.len() >= POINTS * 4 / 5);
+    // The homography can't explain a general scene.
+    let homography = reports.homography.expect("no homography report");
+    assert_eq!(homography.inlier_mask.len(), POINTS);
+    assert!(homography.inliers.len() < essential.inliers.len());
+}
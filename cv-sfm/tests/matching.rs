@@ -0,0 +1,107 @@
+use cv_core::nalgebra::Point2;
+use cv_sfm::matching::{
+    match_all_pairs, match_pairs, windowed_descriptor_matching, BruteForceMatcher, DescriptorMatch,
+    HnswMatcher, MatchTarget, Matcher, L2,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn windowed_matching_skips_non_finite_positions() {
+    let a: [[f32; 2]; 3] = [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0]];
+    let predicted = [
+        Point2::new(0.5, 0.5),
+        Point2::new(f64::NAN, 0.5),
+        Point2::new(0.5, 0.5),
+    ];
+    let b: [[f32; 2]; 3] = [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0]];
+    // Points which aren't finite would otherwise be put in the same cell as the first point.
+    let b_points = [
+        Point2::new(0.0, 0.0),
+        Point2::new(0.5, 1.0),
+        Point2::new(f64::NAN, f64::NAN),
+    ];
+    let matches = windowed_descriptor_matching(&L2, &a, &predicted, &b, &b_points, 4.0, 1.0);
+    assert_eq!(matches, vec![Some(0), None, Some(1)]);
+}
+
+/// The same five well separated descriptors in each image, in a different order and slightly moved.
+fn images() -> Vec<Vec<[f32; 2]>> {
+    (0..4)
+        .map(|image| {
+            (0..5)
+                .map(|feature| {
+                    let point = ((feature + image) % 5) as f32;
+                    [
+                        10.0 * point + 0.1 * image as f32,
+                        point * point - 0.2 * image as f32,
+                    ]
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A brute force matcher which counts the targets it prepared.
+struct CountingMatcher {
+    matcher: BruteForceMatcher<L2>,
+    prepared: AtomicUsize,
+}
+
+impl Matcher<[f32; 2]> for CountingMatcher {
+    fn match_descriptors(&self, query: &[[f32; 2]], target: &[[f32; 2]]) -> Vec<DescriptorMatch> {
+        self.matcher.match_descriptors(query, target)
+    }
+
+    fn prepare_target<'a>(&'a self, target: &'a [[f32; 2]]) -> Box<dyn MatchTarget<[f32; 2]> + 'a> {
+        self.prepared.fetch_add(1, Ordering::Relaxed);
+        self.matcher.prepare_target(target)
+    }
+}
+
+#[test]
+fn batch_matching_prepares_each_target_once() {
+    let images = images();
+    let images: Vec<&[[f32; 2]]> = images.iter().map(Vec::as_slice).collect();
+    let matcher = CountingMatcher {
+        matcher: BruteForceMatcher::new(L2),
+        prepared: AtomicUsize::new(0),
+    };
+
+    let matrix = match_all_pairs(&matcher, &images);
+    // The first image is never a target.
+    assert_eq!(matcher.prepared.load(Ordering::Relaxed), images.len() - 1);
+    assert_eq!(matrix.pairs.len(), 6);
+    for (&(a, b), matches) in &matrix.pairs {
+        assert!(a < b);
+        assert_eq!(matches.len(), 5);
+        assert_eq!(*matches, matcher.match_descriptors(images[a], images[b]));
+    }
+
+    // Pairs are matched once regardless of their order, and pairs of an image with itself are skipped.
+    matcher.prepared.store(0, Ordering::Relaxed);
+    let matrix = match_pairs(&matcher, &images, &[(2, 0), (0, 2), (1, 1), (3, 1)]);
+    assert_eq!(matcher.prepared.load(Ordering::Relaxed), 2);
+    let mut pairs: Vec<(usize, usize)> = matrix.pairs.keys().copied().collect();
+    pairs.sort_unstable();
+    assert_eq!(pairs, [(0, 2), (1, 3)]);
+    assert_eq!(matrix.get(2, 0).unwrap().len(), 5);
+}
+
+#[test]
+fn hnsw_batch_matching_finds_nearest_neighbors() {
+    let images = images();
+    let images: Vec<&[[f32; 2]]> = images.iter().map(Vec::as_slice).collect();
+    let matrix = match_all_pairs(&HnswMatcher::new(L2), &images);
+    // The index finds the exact neighbors of so few descriptors.
+    let exact = BruteForceMatcher::new(L2).symmetric(false);
+    assert_eq!(matrix.pairs.len(), 6);
+    for (&(a, b), matches) in &matrix.pairs {
+        assert_eq!(*matches, exact.match_descriptors(images[a], images[b]));
+        for m in matches {
+            assert_eq!(
+                images[a][m.query][0].round(),
+                images[b][m.target][0].round()
+            );
+        }
+    }
+}
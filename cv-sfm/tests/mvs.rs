@@ -0,0 +1,114 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point2, Rotation3, Vector2, Vector3},
+    WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsics;
+use cv_sfm::{MvsView, PatchMatch};
+use image::{GrayImage, Luma};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+/// The depth of the plane which all cameras look at.
+const PLANE_DEPTH: f64 = 4.0;
+
+fn intrinsics() -> CameraIntrinsics {
+    CameraIntrinsics {
+        focals: Vector2::new(60.0, 60.0),
+        principal_point: Point2::new(31.5, 23.5),
+        skew: 0.0,
+    }
+}
+
+/// A texture without repetitions in the range of disparities, so that every pixel has a unique match.
+fn texture(x: f64, y: f64) -> f64 {
+    0.5 + 0.2 * (11.0 * x + 2.0 * y).sin()
+        + 0.15 * (7.0 * y - 3.0 * x).cos()
+        + 0.1 * (5.0 * x + 13.0 * y).sin()
+}
+
+/// Renders the textured plane `z = PLANE_DEPTH` from a camera at `center` looking along the z axis.
+fn render(center: Vector3<f64>) -> GrayImage {
+    let inverse = intrinsics().matrix().try_inverse().unwrap();
+    GrayImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let ray = inverse * Vector3::new(x as f64, y as f64, 1.0);
+        let point = center + ray * ((PLANE_DEPTH - center.z) / ray.z);
+        Luma([(texture(point.x, point.y) * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8])
+    })
+}
+
+fn pose(center: Vector3<f64>) -> WorldToCamera {
+    WorldToCamera(IsometryMatrix3::from_parts(
+        (-center).into(),
+        Rotation3::identity(),
+    ))
+}
+
+#[test]
+fn estimates_depth_of_textured_plane() {
+    let centers = [
+        Vector3::new(-0.3, 0.0, 0.0),
+        Vector3::new(0.0, 0.05, 0.0),
+        Vector3::new(0.3, 0.0, 0.0),
+    ];
+    let images: Vec<GrayImage> = centers.iter().map(|&center| render(center)).collect();
+    let views: Vec<MvsView<'_>> = centers
+        .iter()
+        .zip(&images)
+        .enumerate()
+        .map(|(view, (&center, image))| MvsView {
+            image,
+            intrinsics: intrinsics(),
+            pose: pose(center),
+            neighbors: (0..centers.len()).filter(|&other| other != view).collect(),
+            depth_range: [3.0, 5.0],
+        })
+        .collect();
+
+    let maps = PatchMatch::new()
+        .window_radius(3)
+        .min_consistent_views(1)
+        .seed(3)
+        .estimate(&views);
+    assert_eq!(maps.len(), views.len());
+    for (view, map) in maps.iter().enumerate() {
+        assert_eq!((map.width, map.height), (WIDTH, HEIGHT));
+        let valid = map.num_valid();
+        assert!(
+            valid > (WIDTH * HEIGHT) as usize / 3,
+            "view {} only has {} pixels with a depth",
+            view,
+            valid
+        );
+        let mut accurate = 0;
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if let Some(depth) = map.depth(x, y) {
+                    let normal = map.normal(x, y).unwrap();
+                    // The plane faces the cameras, so its normal points back along the optical axis.
+                    if (depth as f64 - PLANE_DEPTH).abs() < 0.1 && normal.z < -0.9 {
+                        accurate += 1;
+                    }
+                }
+            }
+        }
+        assert!(
+            accurate * 20 >= valid * 19,
+            "view {} has {} of {} pixels on the plane",
+            view,
+            accurate,
+            valid
+        );
+    }
+
+    // The points of the depth maps are on the plane in the world.
+    let cloud = maps[2].point_cloud(&intrinsics(), views[2].pose, None);
+    assert_eq!(cloud.points.len(), maps[2].num_valid());
+    let on_plane = cloud
+        .points
+        .iter()
+        .filter(|point| (point.position.z - PLANE_DEPTH).abs() < 0.1)
+        .count();
+    assert!(on_plane * 20 >= cloud.points.len() * 19);
+}
@@ -0,0 +1,137 @@
+use arrsac::Arrsac;
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point3, Rotation3, UnitVector3, Vector3},
+    sample_consensus::Estimator,
+    Pose, Projective, WorldToCamera,
+};
+use cv_geom::{
+    alignment::{PointMatch, Umeyama},
+    triangulation::LinearEigenTriangulator,
+};
+use cv_sfm::{
+    matching::{DescriptorMatch, Matcher},
+    IncrementalSfm, SfmImage,
+};
+use eight_point::EightPoint;
+use lambda_twist::LambdaTwist;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+const POINTS: usize = 200;
+const IMAGES: usize = 5;
+
+/// Matches the features which observe the same point, whose index is the descriptor.
+struct PointMatcher;
+
+impl Matcher<usize> for PointMatcher {
+    fn match_descriptors(&self, query: &[usize], target: &[usize]) -> Vec<DescriptorMatch> {
+        query
+            .iter()
+            .enumerate()
+            .filter_map(|(query, point)| {
+                Some(DescriptorMatch {
+                    query,
+                    target: target.iter().position(|other| other == point)?,
+                    distance: 0.0,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Cameras which move sideways along a slight curve while looking at the points.
+fn poses() -> Vec<WorldToCamera> {
+    (0..IMAGES)
+        .map(|ix| {
+            let ix = ix as f64;
+            let center = Vector3::new(0.4 * ix - 0.8, 0.05 * ix, 0.1 * ix);
+            let rotation = Rotation3::from_euler_angles(0.01 * ix, -0.05 * ix + 0.1, 0.02);
+            WorldToCamera(IsometryMatrix3::from_parts(
+                (-(rotation * center)).into(),
+                rotation,
+            ))
+        })
+        .collect()
+}
+
+/// Every image sees most of the points, and the features of an image are in a different order than the points.
+fn images(points: &[Point3<f64>], poses: &[WorldToCamera]) -> Vec<SfmImage<usize>> {
+    poses
+        .iter()
+        .enumerate()
+        .map(|(image, pose)| {
+            let visible: Vec<usize> = (0..POINTS)
+                .rev()
+                .filter(|point| (point + image) % 7 != 0)
+                .collect();
+            let bearings = visible
+                .iter()
+                .map(|&point| UnitVector3::new_normalize((pose.isometry() * points[point]).coords))
+                .collect();
+            SfmImage::new(bearings, visible)
+        })
+        .collect()
+}
+
+#[test]
+fn reconstructs_synthetic_scene() {
+    let mut rng = Pcg64::from_seed([7; 32]);
+    let points: Vec<Point3<f64>> = (0..POINTS)
+        .map(|_| {
+            Point3::new(
+                rng.gen_range(-1.5..1.5),
+                rng.gen_range(-1.5..1.5),
+                rng.gen_range(4.0..7.0),
+            )
+        })
+        .collect();
+    let poses = poses();
+    let images = images(&points, &poses);
+
+    let reconstruction = IncrementalSfm::new(
+        PointMatcher,
+        Arrsac::new(1e-6, Pcg64::from_seed([1; 32])),
+        EightPoint::new(),
+        Arrsac::new(1e-6, Pcg64::from_seed([2; 32])),
+        LambdaTwist::new(),
+        LinearEigenTriangulator::new(),
+    )
+    .reconstruct(&images)
+    .expect("failed to initialize");
+    assert_eq!(reconstruction.num_registered(), IMAGES);
+    assert!(reconstruction.points.len() > POINTS * 9 / 10);
+
+    // The reconstruction is only known up to a similarity, so it is aligned by the camera centers.
+    let center = |pose: WorldToCamera| Point3::from(pose.inverse().isometry().translation.vector);
+    let centers: Vec<PointMatch> = reconstruction
+        .registered()
+        .map(|(image, pose)| PointMatch(center(pose), center(poses[image])))
+        .collect();
+    let alignment = Umeyama::new().estimate(centers.into_iter()).unwrap();
+    for (image, pose) in reconstruction.registered() {
+        let aligned = alignment.0 * center(pose);
+        assert!(
+            (aligned - center(poses[image])).norm() < 1e-4,
+            "image {} is off by {}",
+            image,
+            (aligned - center(poses[image])).norm()
+        );
+        let rotation = alignment.rotation() * pose.isometry().rotation.inverse();
+        let truth = poses[image].isometry().rotation.inverse();
+        assert!(rotation.rotation_to(&truth).angle() < 1e-4);
+    }
+    for point in &reconstruction.points {
+        let (image, feature) = point.observations[0];
+        let truth = points[images[image].descriptors[feature]];
+        assert!(point
+            .observations
+            .iter()
+            .all(|&(image, feature)| points[images[image].descriptors[feature]] == truth));
+        let aligned = alignment.0 * point.point.point().unwrap();
+        assert!(
+            (aligned - truth).norm() < 1e-3,
+            "point is off by {}",
+            (aligned - truth).norm()
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use cv_core::nalgebra::{Matrix3, Point3, Vector3};
+use cv_optimize::{Ellipsoid, Geodetic, LocalTangentPlane};
+use cv_sfm::{CloudPoint, PointCloud, PointCloudEncoding};
+use ply_rs::{
+    parser::Parser,
+    ply::{DefaultElement, Property},
+};
+use std::convert::TryInto;
+
+/// Points with colors, normals and covariances which are not representable exactly in single precision.
+fn cloud() -> PointCloud {
+    PointCloud::new(
+        (0..5)
+            .map(|ix| {
+                let ix = ix as f64;
+                let covariance = Matrix3::new(
+                    0.1 + ix,
+                    0.01 * ix,
+                    -0.02,
+                    0.01 * ix,
+                    0.3,
+                    0.001 * ix,
+                    -0.02,
+                    0.001 * ix,
+                    0.7 / (ix + 1.0),
+                );
+                CloudPoint {
+                    position: Point3::new(0.1 * ix - 0.3, 1.7 - 0.25 * ix, 4.0 + ix * ix / 3.0),
+                    color: [10 * ix as u8, 255 - 20 * ix as u8, 128],
+                    normal: Some(Vector3::new(ix, 1.0, -2.0).normalize()),
+                    covariance: Some(covariance),
+                }
+            })
+            .collect(),
+    )
+}
+
+/// The upper triangle of a covariance, in the order it is written.
+fn upper_triangle(c: Matrix3<f64>) -> [f64; 6] {
+    [
+        c[(0, 0)],
+        c[(0, 1)],
+        c[(0, 2)],
+        c[(1, 1)],
+        c[(1, 2)],
+        c[(2, 2)],
+    ]
+}
+
+fn double(vertex: &DefaultElement, name: &str) -> f64 {
+    match vertex[name] {
+        Property::Double(value) => value,
+        ref other => panic!("{} is {:?}", name, other),
+    }
+}
+
+fn uchar(vertex: &DefaultElement, name: &str) -> u8 {
+    match vertex[name] {
+        Property::UChar(value) => value,
+        ref other => panic!("{} is {:?}", name, other),
+    }
+}
+
+#[test]
+fn ply_round_trip() {
+    let cloud = cloud();
+    for encoding in [PointCloudEncoding::Ascii, PointCloudEncoding::Binary] {
+        let mut ply = vec![];
+        cloud.write_ply(&mut ply, encoding).unwrap();
+        let parsed = Parser::<DefaultElement>::new()
+            .read_ply(&mut ply.as_slice())
+            .unwrap();
+        let vertices = &parsed.payload["vertex"];
+        assert_eq!(vertices.len(), cloud.points.len());
+        for (vertex, point) in vertices.iter().zip(&cloud.points) {
+            // PLY stores doubles, so everything is read back exactly.
+            let position = Point3::new(
+                double(vertex, "x"),
+                double(vertex, "y"),
+                double(vertex, "z"),
+            );
+            assert_eq!(position, point.position);
+            let normal = Vector3::new(
+                double(vertex, "nx"),
+                double(vertex, "ny"),
+                double(vertex, "nz"),
+            );
+            assert_eq!(Some(normal), point.normal);
+            let covariance = ["cov_xx", "cov_xy", "cov_xz", "cov_yy", "cov_yz", "cov_zz"]
+                .map(|name| double(vertex, name));
+            assert_eq!(covariance, upper_triangle(point.covariance.unwrap()));
+            let color = ["red", "green", "blue"].map(|name| uchar(vertex, name));
+            assert_eq!(color, point.color);
+        }
+    }
+}
+
+#[test]
+fn ply_without_normals() {
+    let mut cloud = cloud();
+    // A single point without a normal or covariance drops the properties for the whole cloud.
+    cloud.points[2].normal = None;
+    cloud.points[3].covariance = None;
+    let mut ply = vec![];
+    cloud
+        .write_ply(&mut ply, PointCloudEncoding::Ascii)
+        .unwrap();
+    let parsed = Parser::<DefaultElement>::new()
+        .read_ply(&mut ply.as_slice())
+        .unwrap();
+    let names: Vec<&str> = parsed.header.elements["vertex"]
+        .properties
+        .keys()
+        .map(String::as_str)
+        .collect();
+    assert_eq!(names, ["x", "y", "z", "red", "green", "blue"]);
+}
+
+/// The fields of the header of a PCD file and its points, each with its values followed by the packed color.
+fn parse_pcd(pcd: &[u8]) -> (Vec<String>, Vec<(Vec<f32>, u32)>) {
+    let mut start = 0;
+    let mut header = vec![];
+    while !header
+        .last()
+        .map_or(false, |line: &String| line.starts_with("DATA"))
+    {
+        let end = start + pcd[start..].iter().position(|&byte| byte == b'\n').unwrap();
+        header.push(String::from_utf8(pcd[start..end].to_vec()).unwrap());
+        start = end + 1;
+    }
+    let field = |key: &str| -> Vec<String> {
+        let line = header
+            .iter()
+            .find(|line| line.split(' ').next() == Some(key))
+            .unwrap();
+        line.split(' ').skip(1).map(str::to_string).collect()
+    };
+    let values: usize = field("COUNT")
+        .iter()
+        .map(|count| count.parse::<usize>().unwrap())
+        .sum::<usize>()
+        - 1;
+    let points: usize = field("POINTS")[0].parse().unwrap();
+    let data = &pcd[start..];
+    let parsed = match field("DATA")[0].as_str() {
+        "ascii" => std::str::from_utf8(data)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                assert_eq!(words.len(), values + 1);
+                (
+                    words[..values]
+                        .iter()
+                        .map(|word| word.parse().unwrap())
+                        .collect(),
+                    words[values].parse().unwrap(),
+                )
+            })
+            .collect(),
+        "binary" => {
+            assert_eq!(data.len(), points * (values + 1) * 4);
+            data.chunks(4 * (values + 1))
+                .map(|chunk| {
+                    let words: Vec<[u8; 4]> = chunk
+                        .chunks(4)
+                        .map(|word| word.try_into().unwrap())
+                        .collect();
+                    (
+                        words[..values]
+                            .iter()
+                            .map(|&word| f32::from_le_bytes(word))
+                            .collect(),
+                        u32::from_le_bytes(words[values]),
+                    )
+                })
+                .collect()
+        }
+        other => panic!("unknown encoding {}", other),
+    };
+    (header, parsed)
+}
+
+#[test]
+fn pcd_round_trip() {
+    let cloud = cloud();
+    for encoding in [PointCloudEncoding::Ascii, PointCloudEncoding::Binary] {
+        let mut pcd = vec![];
+        cloud.write_pcd(&mut pcd, encoding).unwrap();
+        let (header, points) = parse_pcd(&pcd);
+        assert!(
+            header.contains(&"FIELDS x y z normal_x normal_y normal_z covariance rgb".to_string())
+        );
+        assert!(header.contains(&"COUNT 1 1 1 1 1 1 6 1".to_string()));
+        assert_eq!(points.len(), cloud.points.len());
+        for ((values, rgb), point) in points.iter().zip(&cloud.points) {
+            // PCD stores single precision floats.
+            let mut expected: Vec<f64> = point.position.coords.iter().copied().collect();
+            expected.extend(point.normal.unwrap().iter());
+            expected.extend(upper_triangle(point.covariance.unwrap()));
+            assert_eq!(values.len(), expected.len());
+            for (&value, &expected) in values.iter().zip(&expected) {
+                assert_eq!(value, expected as f32);
+            }
+            let [_, r, g, b] = rgb.to_be_bytes();
+            assert_eq!([r, g, b], point.color);
+        }
+    }
+}
+
+/// The scale and offset of the coordinates of a LAS file, the number of variable length records, and the points with
+/// their colors.
+fn parse_las(las: &[u8]) -> (f64, u32, Vec<(Vector3<f64>, [u16; 3])>) {
+    let u16_at = |offset: usize| u16::from_le_bytes(las[offset..offset + 2].try_into().unwrap());
+    let u32_at = |offset: usize| u32::from_le_bytes(las[offset..offset + 4].try_into().unwrap());
+    let i32_at = |offset: usize| i32::from_le_bytes(las[offset..offset + 4].try_into().unwrap());
+    let f64_at = |offset: usize| f64::from_le_bytes(las[offset..offset + 8].try_into().unwrap());
+
+    assert_eq!(&las[..4], b"LASF");
+    assert_eq!(las[24..26], [1, 2]);
+    assert_eq!(u16_at(94), 227);
+    let data = u32_at(96) as usize;
+    let records = u32_at(100);
+    assert_eq!(las[104], 2);
+    let length = u16_at(105) as usize;
+    assert_eq!(length, 26);
+    let count = u32_at(107) as usize;
+    assert_eq!(u32_at(111), count as u32);
+    assert_eq!(las.len(), data + count * length);
+
+    let scale = Vector3::new(f64_at(131), f64_at(139), f64_at(147));
+    let offset = Vector3::new(f64_at(155), f64_at(163), f64_at(171));
+    assert_eq!(scale, Vector3::repeat(scale.x));
+    let points = (0..count)
+        .map(|point| {
+            let record = data + point * length;
+            let position = Vector3::new(i32_at(record), i32_at(record + 4), i32_at(record + 8))
+                .cast::<f64>()
+                .component_mul(&scale)
+                + offset;
+            let color = [
+                u16_at(record + 20),
+                u16_at(record + 22),
+                u16_at(record + 24),
+            ];
+            (position, color)
+        })
+        .collect();
+    (scale.x, records, points)
+}
+
+#[test]
+fn las_round_trip() {
+    let cloud = cloud();
+    let mut las = vec![];
+    cloud.write_las(&mut las).unwrap();
+    let (scale, records, points) = parse_las(&las);
+    assert_eq!(records, 0);
+    assert!(scale <= 1e-8);
+    assert_eq!(points.len(), cloud.points.len());
+    for ((position, color), point) in points.iter().zip(&cloud.points) {
+        assert!((position - point.position.coords).norm() < scale);
+        assert_eq!(*color, point.color.map(|channel| channel as u16 * 257));
+    }
+}
+
+#[test]
+fn georeferenced_point_cloud() {
+    let mut cloud = cloud();
+    cloud.datum = Some(LocalTangentPlane::new(
+        Geodetic::new(48.8584, 2.2945, 35.0),
+        Ellipsoid::WGS84,
+    ));
+
+    // PLY and PCD keep the local coordinates and record the datum in a comment.
+    let mut ply = vec![];
+    cloud
+        .write_ply(&mut ply, PointCloudEncoding::Binary)
+        .unwrap();
+    let parsed = Parser::<DefaultElement>::new()
+        .read_ply(&mut ply.as_slice())
+        .unwrap();
+    assert!(parsed
+        .header
+        .comments
+        .contains(&"datum 48.8584 2.2945 35".to_string()));
+    assert_eq!(
+        double(&parsed.payload["vertex"][1], "x"),
+        cloud.points[1].position.x
+    );
+    let mut pcd = vec![];
+    cloud
+        .write_pcd(&mut pcd, PointCloudEncoding::Ascii)
+        .unwrap();
+    let (header, _) = parse_pcd(&pcd);
+    assert!(header.contains(&"# datum 48.8584 2.2945 35".to_string()));
+
+    // LAS stores earth-centered, earth-fixed coordinates to the millimeter with the key of their coordinate system.
+    let mut las = vec![];
+    cloud.write_las(&mut las).unwrap();
+    let (scale, records, points) = parse_las(&las);
+    assert_eq!(records, 1);
+    assert!(scale <= 1e-3);
+    assert_eq!(&las[227 + 2..227 + 2 + 15], b"LASF_Projection");
+    assert_eq!(u16::from_le_bytes([las[227 + 18], las[227 + 19]]), 34735);
+    for ((position, _), point) in points.iter().zip(&cloud.points) {
+        let ecef = cloud.ecef(point).unwrap();
+        assert!(ecef.norm() > 6.3e6);
+        assert!((position - ecef).norm() < 1e-3);
+    }
+}
@@ -0,0 +1,102 @@
+use cv_core::{
+    nalgebra::{Point2, Vector2},
+    Projective,
+};
+use cv_pinhole::{CameraIntrinsics, StereoRig};
+use cv_sfm::Sgm;
+use image::{GrayImage, Luma};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+const WIDTH: u32 = 96;
+const HEIGHT: u32 = 64;
+const BACKGROUND: u32 = 7;
+const FOREGROUND: u32 = 12;
+/// The square in the left image which is closer to the cameras than the background.
+const SQUARE: [u32; 4] = [40, 20, 70, 44];
+
+fn in_square(x: u32, y: u32, margin: u32) -> bool {
+    let [left, top, right, bottom] = SQUARE;
+    x + margin >= left && x < right + margin && y + margin >= top && y < bottom + margin
+}
+
+/// The true disparity of a pixel of the left image.
+fn disparity(x: u32, y: u32) -> u32 {
+    if in_square(x, y, 0) {
+        FOREGROUND
+    } else {
+        BACKGROUND
+    }
+}
+
+/// A rectified pair of a random texture, where the square occludes the background in the right image.
+fn stereo_pair() -> (GrayImage, GrayImage) {
+    let mut rng = Pcg64::from_seed([3; 32]);
+    let left = GrayImage::from_fn(WIDTH, HEIGHT, |_, _| Luma([rng.gen()]));
+    let mut right = GrayImage::from_fn(WIDTH, HEIGHT, |_, _| Luma([rng.gen()]));
+    for foreground in [false, true] {
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let d = disparity(x, y);
+                if in_square(x, y, 0) == foreground && x >= d {
+                    right.put_pixel(x - d, y, *left.get_pixel(x, y));
+                }
+            }
+        }
+    }
+    (left, right)
+}
+
+#[test]
+fn recovers_known_disparities() {
+    let (left, right) = stereo_pair();
+    let map = Sgm::new().num_disparities(32).compute(&left, &right);
+    assert_eq!((map.width, map.height), (WIDTH, HEIGHT));
+
+    // Pixels near the borders of the image and of the square have no true match or mix both disparities, and the
+    // background is occluded in the right image up to the difference of the disparities left of the square.
+    let margin = FOREGROUND - BACKGROUND + 2;
+    let (mut checked, mut correct) = (0, 0);
+    for y in margin..HEIGHT - margin {
+        for x in FOREGROUND + margin..WIDTH - margin {
+            if in_square(x, y, margin) && !in_square(x, y, 0) {
+                continue;
+            }
+            if in_square(x, y, 0) && !in_square(x.saturating_sub(margin), y, 0) {
+                continue;
+            }
+            checked += 1;
+            if let Some(found) = map.disparity(x, y) {
+                if (found - disparity(x, y) as f32).abs() < 0.5 {
+                    correct += 1;
+                }
+            }
+        }
+    }
+    assert!(
+        correct * 20 >= checked * 19,
+        "{} of {} pixels have the right disparity",
+        correct,
+        checked
+    );
+
+    // The background is at the depth of its disparity.
+    let rig = StereoRig::new(
+        CameraIntrinsics {
+            focals: Vector2::new(100.0, 100.0),
+            principal_point: Point2::new(47.5, 31.5),
+            skew: 0.0,
+        },
+        0.1,
+    );
+    let depth_map = map.depth_map(&rig);
+    let expected = 100.0 * 0.1 / BACKGROUND as f32;
+    let depth = depth_map.depth(20, 10).unwrap();
+    assert!(
+        (depth - expected).abs() < 0.05 * expected,
+        "depth {}",
+        depth
+    );
+    let point = map.point(20, 10, &rig).unwrap().point().unwrap();
+    assert!((point.z - expected as f64).abs() < 0.05 * expected as f64);
+}
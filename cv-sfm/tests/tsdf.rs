@@ -0,0 +1,78 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point2, Point3, Rotation3, Vector2, Vector3},
+    WorldToCamera,
+};
+use cv_pinhole::CameraIntrinsics;
+use cv_sfm::{DepthMap, TsdfVolume};
+use image::{Rgb, RgbImage};
+
+const WIDTH: u32 = 200;
+const HEIGHT: u32 = 150;
+/// The depth of the plane, which is between two layers of voxels.
+const PLANE_DEPTH: f64 = 1.05;
+
+fn intrinsics() -> CameraIntrinsics {
+    CameraIntrinsics {
+        focals: Vector2::new(100.0, 100.0),
+        principal_point: Point2::new(99.5, 74.5),
+        skew: 0.0,
+    }
+}
+
+/// A camera at `center` looking along the z axis, which sees the plane at the same depth at every pixel.
+fn pose(center: Vector3<f64>) -> WorldToCamera {
+    WorldToCamera(IsometryMatrix3::from_parts(
+        (-center).into(),
+        Rotation3::identity(),
+    ))
+}
+
+#[test]
+fn fuses_a_plane() {
+    let mut volume = TsdfVolume::new(Point3::new(-0.3, -0.2, 0.8), [31, 21, 26], 0.02);
+    let image = RgbImage::from_pixel(WIDTH, HEIGHT, Rgb([200, 100, 50]));
+    for center in [Vector3::new(-0.05, 0.0, 0.0), Vector3::new(0.05, 0.02, 0.0)] {
+        let depth_map = DepthMap::from_depths(
+            WIDTH,
+            HEIGHT,
+            vec![(PLANE_DEPTH - center.z) as f32; (WIDTH * HEIGHT) as usize],
+        );
+        volume.integrate(&depth_map, &intrinsics(), pose(center), Some(&image));
+    }
+
+    // The distance is positive in front of the plane, negative just behind it, and unknown far behind it.
+    let truncation = 0.06;
+    assert!((volume.distance([15, 10, 0]).unwrap() - truncation).abs() < 1e-6);
+    assert!((volume.distance([15, 10, 12]).unwrap() - 0.01).abs() < 1e-6);
+    assert!((volume.distance([15, 10, 13]).unwrap() + 0.01).abs() < 1e-6);
+    assert_eq!(volume.distance([15, 10, 20]), None);
+
+    let mesh = volume.extract_mesh();
+    assert!(!mesh.triangles.is_empty());
+    assert_eq!(mesh.vertices.len(), mesh.colors.len());
+    for (vertex, color) in mesh.vertices.iter().zip(&mesh.colors) {
+        assert!((vertex.z - PLANE_DEPTH).abs() < 1e-4, "vertex {:?}", vertex);
+        assert_eq!(*color, [200, 100, 50]);
+    }
+    // The triangles face the cameras and cover the cross section of the volume.
+    let mut area = 0.0;
+    for &[a, b, c] in &mesh.triangles {
+        let [a, b, c] = [a, b, c].map(|vertex| mesh.vertices[vertex]);
+        let normal = (b - a).cross(&(c - a));
+        assert!(normal.z < 0.0);
+        area += normal.norm() / 2.0;
+    }
+    assert!((area - 0.6 * 0.4).abs() < 1e-3, "area {}", area);
+
+    let mut obj = vec![];
+    mesh.write_obj(&mut obj).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    assert_eq!(
+        obj.lines().filter(|line| line.starts_with("v ")).count(),
+        mesh.vertices.len()
+    );
+    assert_eq!(
+        obj.lines().filter(|line| line.starts_with("f ")).count(),
+        mesh.triangles.len()
+    );
+}
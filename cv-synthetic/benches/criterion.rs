@@ -0,0 +1,29 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use cv_core::sample_consensus::Estimator;
+use cv_synthetic::Synthetic;
+use eight_point::EightPoint;
+use epnp::EPnP;
+use lambda_twist::LambdaTwist;
+use rand::{rngs::SmallRng, SeedableRng};
+
+fn relative(c: &mut Criterion) {
+    let problem = Synthetic::new(SmallRng::seed_from_u64(0)).relative(8);
+    c.bench_function("eight-point", |b| {
+        b.iter(|| EightPoint::new().estimate(problem.matches.iter().copied()))
+    });
+}
+
+fn absolute(c: &mut Criterion) {
+    let mut synthetic = Synthetic::new(SmallRng::seed_from_u64(0));
+    let minimal = synthetic.absolute(3);
+    let large = synthetic.absolute(64);
+    c.bench_function("lambda-twist", |b| {
+        b.iter(|| LambdaTwist::new().estimate(minimal.matches.iter().copied()))
+    });
+    c.bench_function("epnp", |b| {
+        b.iter(|| EPnP::new().estimate(large.matches.iter().copied()))
+    });
+}
+
+criterion_group!(benches, relative, absolute);
+criterion_main!(benches);
@@ -0,0 +1,113 @@
+use core::fmt;
+
+/// Summary statistics of the errors of an estimator.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ErrorStatistics {
+    pub mean: f64,
+    pub median: f64,
+    /// The error which 90% of the errors are below.
+    pub percentile_90: f64,
+    pub max: f64,
+}
+
+impl ErrorStatistics {
+    pub fn new(errors: &[f64]) -> Self {
+        if errors.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = errors.to_vec();
+        sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+        Self {
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            median: percentile(0.5),
+            percentile_90: percentile(0.9),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+impl fmt::Display for ErrorStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mean {:.3e}, median {:.3e}, 90% {:.3e}, max {:.3e}",
+            self.mean, self.median, self.percentile_90, self.max
+        )
+    }
+}
+
+/// The accuracy of an estimator over many synthetic problems.
+///
+/// Only rounds that didn't fail contribute errors, so that a few failures don't hide the accuracy of an estimator
+/// and vice versa.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    /// The number of problems the estimator was run on.
+    pub rounds: usize,
+    /// The number of problems with no model or a rotation error above the failure threshold.
+    pub failures: usize,
+    /// The rotation error in radians of each successful round.
+    pub rotation_errors: Vec<f64>,
+    /// The translation error of each successful round.
+    pub translation_errors: Vec<f64>,
+    /// The rotation error in radians above which an estimate is counted as a failure.
+    pub failure_threshold: f64,
+}
+
+impl Evaluation {
+    pub fn new(failure_threshold: f64) -> Self {
+        Self {
+            rounds: 0,
+            failures: 0,
+            rotation_errors: vec![],
+            translation_errors: vec![],
+            failure_threshold,
+        }
+    }
+
+    /// Adds the rotation and translation error of the best model from a round, or `None` if there was no model.
+    pub fn add(&mut self, errors: Option<(f64, f64)>) {
+        self.rounds += 1;
+        match errors {
+            Some((rotation_error, translation_error))
+                if rotation_error <= self.failure_threshold =>
+            {
+                self.rotation_errors.push(rotation_error);
+                self.translation_errors.push(translation_error);
+            }
+            _ => self.failures += 1,
+        }
+    }
+
+    /// The fraction of rounds which failed.
+    pub fn failure_rate(&self) -> f64 {
+        if self.rounds == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.rounds as f64
+        }
+    }
+
+    pub fn rotation_statistics(&self) -> ErrorStatistics {
+        ErrorStatistics::new(&self.rotation_errors)
+    }
+
+    pub fn translation_statistics(&self) -> ErrorStatistics {
+        ErrorStatistics::new(&self.translation_errors)
+    }
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "failures: {} of {} ({:.2}%)",
+            self.failures,
+            self.rounds,
+            100.0 * self.failure_rate()
+        )?;
+        writeln!(f, "rotation error: {}", self.rotation_statistics())?;
+        write!(f, "translation error: {}", self.translation_statistics())
+    }
+}
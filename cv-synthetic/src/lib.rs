@@ -0,0 +1,354 @@
+//! # `cv-synthetic`
+//!
+//! Synthetic data with known ground truth for validating estimators.
+//!
+//! [`Synthetic`] generates random poses along with (optionally noisy and contaminated) correspondences between them.
+//! It can also run any [`Estimator`] over many random problems to produce an [`Evaluation`] of the distribution of
+//! its rotation and translation errors and how often it fails, which is useful to validate new solvers and to
+//! catch numerical regressions in existing ones. Estimators which don't take bearings or don't produce poses, such
+//! as fundamental matrix or camera rig solvers, are evaluated with [`Synthetic::evaluate`] by converting the
+//! generated problem to their input and their models back to poses.
+
+mod evaluation;
+
+pub use evaluation::{ErrorStatistics, Evaluation};
+
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point3, Rotation3, UnitVector3, Vector3},
+    sample_consensus::Estimator,
+    CameraPoint, CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Projective, WorldPoint,
+    WorldToCamera,
+};
+use float_ord::FloatOrd;
+use rand::Rng;
+
+/// A randomly generated relative pose problem.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RelativeProblem {
+    /// The ground truth pose from the first camera to the second camera.
+    pub pose: CameraToCamera,
+    /// The matches between the two cameras.
+    pub matches: Vec<FeatureMatch>,
+    /// Whether each match is an inlier (as opposed to a randomly generated outlier).
+    pub inliers: Vec<bool>,
+}
+
+/// A randomly generated absolute pose problem.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbsoluteProblem {
+    /// The ground truth pose of the camera.
+    pub pose: WorldToCamera,
+    /// The matches between bearings in the camera and world points.
+    pub matches: Vec<FeatureWorldMatch>,
+    /// Whether each match is an inlier (as opposed to a randomly generated outlier).
+    pub inliers: Vec<bool>,
+}
+
+/// Generates synthetic problems and evaluates estimators on them.
+///
+/// The defaults match the randomized tests of the solver crates: points are generated in a box in front of the
+/// camera and poses rotate by up to a fifth of a turn.
+#[derive(Clone, Debug)]
+pub struct Synthetic<R> {
+    /// The rotation of a random pose is a random axis-angle vector with components up to this many turns.
+    pub rotation_magnitude: f64,
+    /// The size of the box in front of the camera that points are generated in.
+    pub point_box_size: f64,
+    /// The distance from the camera to the near side of the box that points are generated in.
+    pub point_distance: f64,
+    /// Each bearing is perturbed by a random rotation of up to this many radians.
+    pub noise: f64,
+    /// The fraction of the matches which are replaced by outliers.
+    pub outlier_ratio: f64,
+    /// The rotation error in radians above which an estimate is counted as a failure.
+    pub failure_threshold: f64,
+    /// The random number generator used to generate problems.
+    pub rng: R,
+}
+
+impl<R> Synthetic<R>
+where
+    R: Rng,
+{
+    pub fn new(rng: R) -> Self {
+        Self {
+            rotation_magnitude: 0.2,
+            point_box_size: 2.0,
+            point_distance: 3.0,
+            noise: 0.0,
+            outlier_ratio: 0.0,
+            failure_threshold: 1e-2,
+            rng,
+        }
+    }
+
+    #[must_use]
+    pub fn rotation_magnitude(self, rotation_magnitude: f64) -> Self {
+        Self {
+            rotation_magnitude,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn point_box_size(self, point_box_size: f64) -> Self {
+        Self {
+            point_box_size,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn point_distance(self, point_distance: f64) -> Self {
+        Self {
+            point_distance,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn noise(self, noise: f64) -> Self {
+        Self { noise, ..self }
+    }
+
+    #[must_use]
+    pub fn outlier_ratio(self, outlier_ratio: f64) -> Self {
+        Self {
+            outlier_ratio,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn failure_threshold(self, failure_threshold: f64) -> Self {
+        Self {
+            failure_threshold,
+            ..self
+        }
+    }
+
+    fn random_vector(&mut self) -> Vector3<f64> {
+        Vector3::from_fn(|_, _| self.rng.gen())
+    }
+
+    fn random_bearing(&mut self) -> UnitVector3<f64> {
+        UnitVector3::new_normalize(self.random_vector() - Vector3::new(0.5, 0.5, -1.0))
+    }
+
+    /// Generates a random pose with a translation in the unit cube.
+    pub fn pose(&mut self) -> IsometryMatrix3<f64> {
+        let translation = self.random_vector();
+        let rotation = self.random_vector() * core::f64::consts::PI * 2.0 * self.rotation_magnitude;
+        IsometryMatrix3::from_parts(translation.into(), Rotation3::new(rotation))
+    }
+
+    /// Generates a random motion along a circular arc in the plane of the x and z axes, like that of a car with a
+    /// forward facing camera.
+    ///
+    /// The camera yaws around the y axis by up to `rotation_magnitude` turns in either direction and moves between
+    /// `0.5` and `1.5` forwards in the direction of half of the yaw.
+    pub fn circular_motion(&mut self) -> CameraToCamera {
+        let yaw = (2.0 * self.rng.gen::<f64>() - 1.0)
+            * core::f64::consts::PI
+            * 2.0
+            * self.rotation_magnitude;
+        let distance = 0.5 + self.rng.gen::<f64>();
+        let center = Vector3::new((0.5 * yaw).sin(), 0.0, (0.5 * yaw).cos()) * distance;
+        // Points are transformed into the second camera, which is the inverse of the motion of the camera.
+        let rotation = Rotation3::from_axis_angle(&Vector3::y_axis(), yaw).inverse();
+        CameraToCamera(IsometryMatrix3::from_parts(
+            (-(rotation * center)).into(),
+            rotation,
+        ))
+    }
+
+    /// Generates a random point in front of the camera.
+    pub fn camera_point(&mut self) -> CameraPoint {
+        let mut point = Point3::from(self.random_vector() * self.point_box_size);
+        point.x -= 0.5 * self.point_box_size;
+        point.y -= 0.5 * self.point_box_size;
+        point.z += self.point_distance;
+        CameraPoint::from_point(point)
+    }
+
+    /// Perturbs a bearing by a random rotation of up to `noise` radians.
+    pub fn perturb(&mut self, bearing: UnitVector3<f64>) -> UnitVector3<f64> {
+        if self.noise == 0.0 {
+            return bearing;
+        }
+        let axis = UnitVector3::new_normalize(self.random_vector() - Vector3::repeat(0.5));
+        let angle = self.noise * self.rng.gen::<f64>();
+        Rotation3::from_axis_angle(&axis, angle) * bearing
+    }
+
+    fn is_outlier(&mut self) -> bool {
+        self.outlier_ratio > 0.0 && self.rng.gen::<f64>() < self.outlier_ratio
+    }
+
+    /// Generates a random relative pose problem with `count` matches.
+    pub fn relative(&mut self, count: usize) -> RelativeProblem {
+        let pose = CameraToCamera(self.pose());
+        self.relative_with_pose(pose, count)
+    }
+
+    /// Generates a relative pose problem with `count` matches for a given pose.
+    pub fn relative_with_pose(&mut self, pose: CameraToCamera, count: usize) -> RelativeProblem {
+        let (matches, inliers) = (0..count)
+            .map(|_| {
+                if self.is_outlier() {
+                    (
+                        FeatureMatch(self.random_bearing(), self.random_bearing()),
+                        false,
+                    )
+                } else {
+                    let a = self.camera_point();
+                    let b = pose.transform(a);
+                    (
+                        FeatureMatch(self.perturb(a.bearing()), self.perturb(b.bearing())),
+                        true,
+                    )
+                }
+            })
+            .unzip();
+        RelativeProblem {
+            pose,
+            matches,
+            inliers,
+        }
+    }
+
+    /// Generates a random absolute pose problem with `count` matches.
+    pub fn absolute(&mut self, count: usize) -> AbsoluteProblem {
+        let pose = WorldToCamera(self.pose());
+        self.absolute_with_pose(pose, count)
+    }
+
+    /// Generates an absolute pose problem with `count` matches for a given pose.
+    pub fn absolute_with_pose(&mut self, pose: WorldToCamera, count: usize) -> AbsoluteProblem {
+        let (matches, inliers) = (0..count)
+            .map(|_| {
+                let camera = self.camera_point();
+                let world: WorldPoint = pose.inverse().transform(camera);
+                if self.is_outlier() {
+                    (FeatureWorldMatch(self.random_bearing(), world), false)
+                } else {
+                    (
+                        FeatureWorldMatch(self.perturb(camera.bearing()), world),
+                        true,
+                    )
+                }
+            })
+            .unzip();
+        AbsoluteProblem {
+            pose,
+            matches,
+            inliers,
+        }
+    }
+
+    /// Evaluates an estimator over `rounds` rounds.
+    ///
+    /// Each round generates a problem, runs the estimator on it and returns the rotation and translation errors of
+    /// its best model, or `None` if it produced no model. [`relative_errors`] and [`absolute_errors`] pick the best
+    /// model out of several poses.
+    pub fn evaluate<F>(&mut self, rounds: usize, mut round: F) -> Evaluation
+    where
+        F: FnMut(&mut Self) -> Option<(f64, f64)>,
+    {
+        let mut evaluation = Evaluation::new(self.failure_threshold);
+        for _ in 0..rounds {
+            let errors = round(self);
+            evaluation.add(errors);
+        }
+        evaluation
+    }
+
+    /// Evaluates a relative pose estimator over `rounds` random problems with `samples` matches each.
+    ///
+    /// See [`relative_errors`] for how the models are compared to the ground truth.
+    pub fn evaluate_relative<E>(
+        &mut self,
+        estimator: &E,
+        rounds: usize,
+        samples: usize,
+    ) -> Evaluation
+    where
+        E: Estimator<FeatureMatch, Model = CameraToCamera>,
+    {
+        self.evaluate(rounds, |synthetic| {
+            let problem = synthetic.relative(samples);
+            relative_errors(
+                &problem.pose,
+                estimator.estimate(problem.matches.iter().copied()),
+            )
+        })
+    }
+
+    /// Evaluates an absolute pose estimator over `rounds` random problems with `samples` matches each.
+    ///
+    /// See [`absolute_errors`] for how the models are compared to the ground truth.
+    pub fn evaluate_absolute<E>(
+        &mut self,
+        estimator: &E,
+        rounds: usize,
+        samples: usize,
+    ) -> Evaluation
+    where
+        E: Estimator<FeatureWorldMatch, Model = WorldToCamera>,
+    {
+        self.evaluate(rounds, |synthetic| {
+            let problem = synthetic.absolute(samples);
+            absolute_errors(
+                &problem.pose,
+                estimator.estimate(problem.matches.iter().copied()),
+            )
+        })
+    }
+}
+
+/// Computes the rotation and translation errors of the relative pose closest to the ground truth `pose`.
+///
+/// Poses are compared by their rotation error, and then by their translation error so that a pose whose translation
+/// only differs in sign, like those decomposed from an essential matrix, isn't picked over the correct one. Since
+/// the scale of a relative pose is unknown, the translation error is the angle between the estimated and true
+/// translations.
+pub fn relative_errors(
+    pose: &CameraToCamera,
+    estimates: impl IntoIterator<Item = CameraToCamera>,
+) -> Option<(f64, f64)> {
+    estimates
+        .into_iter()
+        .map(|estimate| {
+            let rotation_error = estimate.0.rotation.rotation_to(&pose.0.rotation).angle();
+            let translation_error = estimate
+                .0
+                .translation
+                .vector
+                .angle(&pose.0.translation.vector);
+            (rotation_error, translation_error)
+        })
+        .min_by_key(|&(rotation_error, translation_error)| {
+            (FloatOrd(rotation_error), FloatOrd(translation_error))
+        })
+}
+
+/// Computes the rotation and translation errors of the absolute pose closest to the ground truth `pose`.
+///
+/// Poses are compared by their rotation error, and then by their translation error. The translation error is the
+/// distance between the estimated and true translations.
+pub fn absolute_errors(
+    pose: &WorldToCamera,
+    estimates: impl IntoIterator<Item = WorldToCamera>,
+) -> Option<(f64, f64)> {
+    estimates
+        .into_iter()
+        .map(|estimate| {
+            let rotation_error = estimate.0.rotation.rotation_to(&pose.0.rotation).angle();
+            let translation_error =
+                (estimate.0.translation.vector - pose.0.translation.vector).norm();
+            (rotation_error, translation_error)
+        })
+        .min_by_key(|&(rotation_error, translation_error)| {
+            (FloatOrd(rotation_error), FloatOrd(translation_error))
+        })
+}
@@ -0,0 +1,303 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point2, Rotation3, UnitVector3, Vector2, Vector3},
+    sample_consensus::Estimator,
+    CameraModel, CameraPoint, CameraRig, CameraToCamera, FeatureMatch, KeyPointMatch, Pose,
+    Projective, RigBearing, RigFeatureMatch,
+};
+use cv_pinhole::{
+    CameraIntrinsics, CameraIntrinsicsK1Distortion, EssentialMatrix, FourPoint, TwoPointRotation,
+};
+use cv_synthetic::{absolute_errors, relative_errors, Synthetic};
+use eight_point::EightPoint;
+use epnp::EPnP;
+use lambda_twist::LambdaTwist;
+use nine_point::NinePoint;
+use nister_stewenius::NisterStewenius;
+use one_point::OnePoint;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use seven_point::SevenPoint;
+use seventeen_point::SeventeenPoint;
+use upright::{UprightAbsolute, UprightRelative};
+
+const ROUNDS: usize = 1000;
+
+const FOCAL: f64 = 800.0;
+const LAMBDA_EPSILON: f64 = 1e-5;
+
+fn synthetic() -> Synthetic<SmallRng> {
+    Synthetic::new(SmallRng::seed_from_u64(0))
+}
+
+/// Converts the matches to pixel coordinates with a different camera for each image.
+fn seven_point(synthetic: &mut Synthetic<SmallRng>) -> Option<(f64, f64)> {
+    let intrinsics_a = CameraIntrinsics::identity()
+        .focals(Vector2::new(800.0, 780.0))
+        .principal_point(Point2::new(320.0, 240.0));
+    let intrinsics_b = CameraIntrinsics::identity()
+        .focal(1200.0)
+        .principal_point(Point2::new(640.0, 360.0));
+    let problem = synthetic.relative(7);
+    let matches = problem.matches.iter().filter_map(|&FeatureMatch(a, b)| {
+        Some(KeyPointMatch(
+            intrinsics_a.uncalibrate(a)?,
+            intrinsics_b.uncalibrate(b)?,
+        ))
+    });
+    let poses = SevenPoint::new()
+        .estimate(matches)
+        .into_iter()
+        .filter_map(|fundamental| {
+            fundamental
+                .essential(&intrinsics_a, &intrinsics_b)
+                .possible_unscaled_poses(1e-12, 1000)
+        })
+        .flatten();
+    relative_errors(&problem.pose, poses)
+}
+
+/// Puts the points on a tilted plane by moving them along their bearings and decomposes the homography.
+fn four_point(synthetic: &mut Synthetic<SmallRng>) -> Option<(f64, f64)> {
+    let pose = CameraToCamera(synthetic.pose());
+    let normal = Vector3::new(0.1, -0.2, 1.0).normalize();
+    let distance = synthetic.point_distance + 0.5 * synthetic.point_box_size;
+    let matches: Vec<FeatureMatch> = (0..4)
+        .map(|_| {
+            let point = synthetic.camera_point().point().unwrap();
+            let a = CameraPoint::from_point(point * (distance / normal.dot(&point.coords)));
+            let b = pose.transform(a);
+            FeatureMatch(
+                synthetic.perturb(a.bearing()),
+                synthetic.perturb(b.bearing()),
+            )
+        })
+        .collect();
+    // The translation of the decompositions is scaled by the inverse of the distance to the plane.
+    let poses = FourPoint::new()
+        .estimate(matches.iter().copied())
+        .and_then(|homography| homography.possible_unscaled_poses(1e-12, 1000))
+        .into_iter()
+        .flatten()
+        .map(|planar| planar.pose);
+    relative_errors(&pose, poses)
+}
+
+/// Estimates the homography of a camera which only rotated and projects it onto a rotation.
+fn pure_rotation(synthetic: &mut Synthetic<SmallRng>) -> Option<(f64, f64)> {
+    let rotation = synthetic.pose().rotation;
+    let problem =
+        synthetic.relative_with_pose(CameraToCamera::from_parts(Vector3::zeros(), rotation), 4);
+    // There is no translation to get wrong.
+    FourPoint::new()
+        .estimate(problem.matches.iter().copied())
+        .and_then(|homography| homography.rotation(1e-12, 1000))
+        .map(|estimate| (estimate.rotation_to(&rotation).angle(), 0.0))
+}
+
+/// Estimates a rotation from a minimal sample of two matches.
+fn two_point_rotation(
+    synthetic: &mut Synthetic<SmallRng>,
+    rotation: Rotation3<f64>,
+) -> Option<(f64, f64)> {
+    let problem =
+        synthetic.relative_with_pose(CameraToCamera::from_parts(Vector3::zeros(), rotation), 2);
+    TwoPointRotation::new()
+        .estimate(problem.matches.iter().copied())
+        .map(|estimate| (estimate.0.rotation_to(&rotation).angle(), 0.0))
+}
+
+/// Rotations within a hundredth of a radian of half a turn around a random axis.
+fn near_half_turn(synthetic: &mut Synthetic<SmallRng>) -> Option<(f64, f64)> {
+    let axis = UnitVector3::new_normalize(
+        Vector3::from_fn(|_, _| synthetic.rng.gen::<f64>()) - Vector3::repeat(0.5),
+    );
+    let angle = std::f64::consts::PI - 1e-2 * synthetic.rng.gen::<f64>();
+    two_point_rotation(synthetic, Rotation3::from_axis_angle(&axis, angle))
+}
+
+/// Distorts the matches with a random distortion coefficient, which must also be recovered.
+fn nine_point(synthetic: &mut Synthetic<SmallRng>) -> Option<(f64, f64)> {
+    let center = Point2::new(320.0, 240.0);
+    let k1 = -0.1 - 0.3 * synthetic.rng.gen::<f64>();
+    let intrinsics = CameraIntrinsicsK1Distortion::new(
+        CameraIntrinsics::identity()
+            .focal(FOCAL)
+            .principal_point(center),
+        k1,
+    );
+    let problem = synthetic.relative(9);
+    let matches = problem.matches.iter().filter_map(|&FeatureMatch(a, b)| {
+        Some(KeyPointMatch(
+            intrinsics.uncalibrate(a)?,
+            intrinsics.uncalibrate(b)?,
+        ))
+    });
+    // The fundamental matrix relates undistorted normalized coordinates, so it is an essential matrix.
+    let poses = NinePoint::new(center, FOCAL)
+        .estimate(matches)
+        .into_iter()
+        .filter(|estimate| (estimate.lambda - k1).abs() < LAMBDA_EPSILON)
+        .filter_map(|estimate| {
+            EssentialMatrix(estimate.fundamental.0).possible_unscaled_poses(1e-12, 1000)
+        })
+        .flatten();
+    relative_errors(&problem.pose, poses)
+}
+
+/// A rig of four cameras facing in every horizontal direction around a vehicle.
+fn surround_rig() -> CameraRig<4> {
+    let camera = |yaw: f64, offset: Vector3<f64>| {
+        let camera_to_rig = IsometryMatrix3::from_parts(
+            offset.into(),
+            Rotation3::from_axis_angle(&Vector3::y_axis(), yaw),
+        );
+        camera_to_rig.inverse()
+    };
+    let half_pi = std::f64::consts::FRAC_PI_2;
+    CameraRig::new([
+        camera(0.0, Vector3::new(0.0, 0.0, 1.0)),
+        camera(half_pi, Vector3::new(0.5, 0.0, 0.0)),
+        camera(2.0 * half_pi, Vector3::new(0.0, 0.0, -1.0)),
+        camera(3.0 * half_pi, Vector3::new(-0.5, 0.0, 0.0)),
+    ])
+}
+
+/// Observes each point in front of a camera of the rig with whichever camera sees it most directly after the
+/// motion. Since the rig has a known scale, the translation error is the distance to the true translation.
+fn seventeen_point(synthetic: &mut Synthetic<SmallRng>) -> Option<(f64, f64)> {
+    let rig = surround_rig();
+    let motion = CameraToCamera(synthetic.pose());
+    let matches: Vec<RigFeatureMatch> = (0..32)
+        .map(|ix| {
+            let camera_a = ix % 4;
+            let point = synthetic.camera_point().point().unwrap();
+            let moved = motion.0 * (rig.extrinsics[camera_a].inverse() * point);
+            let directness = |camera: usize| {
+                let p = rig.extrinsics[camera] * moved;
+                p.z / p.coords.norm()
+            };
+            let camera_b = (0..4)
+                .max_by(|&x, &y| directness(x).partial_cmp(&directness(y)).unwrap())
+                .unwrap();
+            RigFeatureMatch(
+                RigBearing {
+                    camera: camera_a,
+                    bearing: synthetic.perturb(CameraPoint::from_point(point).bearing()),
+                },
+                RigBearing {
+                    camera: camera_b,
+                    bearing: synthetic.perturb(
+                        CameraPoint::from_point(rig.extrinsics[camera_b] * moved).bearing(),
+                    ),
+                },
+            )
+        })
+        .collect();
+    SeventeenPoint::new(rig)
+        .from_matches(matches.iter().copied())
+        .map(|estimate| {
+            let rotation_error = estimate
+                .motion
+                .0
+                .rotation
+                .rotation_to(&motion.0.rotation)
+                .angle();
+            let translation_error =
+                (estimate.motion.0.translation.vector - motion.0.translation.vector).norm();
+            (rotation_error, translation_error)
+        })
+}
+
+fn one_point(synthetic: &mut Synthetic<SmallRng>) -> Option<(f64, f64)> {
+    let pose = synthetic.circular_motion();
+    let problem = synthetic.relative_with_pose(pose, 1);
+    relative_errors(
+        &problem.pose,
+        OnePoint::new().estimate(problem.matches.iter().copied()),
+    )
+}
+
+/// Measures the gravity in the first camera as a random direction close to its y axis.
+fn upright_relative(synthetic: &mut Synthetic<SmallRng>) -> Option<(f64, f64)> {
+    let gravity_a = UnitVector3::new_normalize(
+        Vector3::y() + Vector3::from_fn(|_, _| synthetic.rng.gen::<f64>()) * 0.5,
+    );
+    let problem = synthetic.relative(3);
+    let gravity_b = problem.pose.0.rotation * gravity_a;
+    relative_errors(
+        &problem.pose,
+        UprightRelative::new(gravity_a, gravity_b).estimate(problem.matches.iter().copied()),
+    )
+}
+
+fn upright_absolute(synthetic: &mut Synthetic<SmallRng>) -> Option<(f64, f64)> {
+    let gravity_world = UnitVector3::new_normalize(-Vector3::z());
+    let problem = synthetic.absolute(2);
+    let gravity_camera = problem.pose.0.rotation * gravity_world;
+    absolute_errors(
+        &problem.pose,
+        UprightAbsolute::new(gravity_camera, gravity_world)
+            .estimate(problem.matches.iter().copied()),
+    )
+}
+
+#[test]
+fn exact() {
+    let evaluations = [
+        synthetic().evaluate_relative(&EightPoint::new(), ROUNDS, 16),
+        synthetic().evaluate_relative(&NisterStewenius::new(), ROUNDS, 5),
+        synthetic().evaluate(ROUNDS, four_point),
+        synthetic().evaluate(ROUNDS, pure_rotation),
+        synthetic().evaluate(ROUNDS, |synthetic| {
+            let rotation = synthetic.pose().rotation;
+            two_point_rotation(synthetic, rotation)
+        }),
+        synthetic().evaluate(ROUNDS, near_half_turn),
+        synthetic().evaluate(ROUNDS, seven_point),
+        synthetic().evaluate(ROUNDS, nine_point),
+        synthetic().evaluate(ROUNDS, seventeen_point),
+        synthetic()
+            .rotation_magnitude(0.05)
+            .point_box_size(4.0)
+            .point_distance(5.0)
+            .evaluate(ROUNDS, one_point),
+        synthetic().evaluate(ROUNDS, upright_relative),
+        synthetic().evaluate_absolute(&LambdaTwist::new(), ROUNDS, 3),
+        synthetic().evaluate_absolute(&EPnP::new(), ROUNDS, 16),
+        synthetic().evaluate(ROUNDS, upright_absolute),
+    ];
+    for evaluation in &evaluations {
+        eprintln!("{}", evaluation);
+        assert!(evaluation.failure_rate() < 0.05);
+        assert!(evaluation.rotation_statistics().median < 1e-6);
+        assert!(evaluation.translation_statistics().median < 1e-6);
+    }
+}
+
+#[test]
+fn noisy() {
+    let evaluations = [
+        synthetic()
+            .noise(1e-3)
+            .evaluate_relative(&EightPoint::new(), ROUNDS, 64),
+        // A wider field of view separates the bearings of the two matches more.
+        synthetic()
+            .noise(1e-3)
+            .point_box_size(6.0)
+            .evaluate(ROUNDS, |synthetic| {
+                let rotation = synthetic.pose().rotation;
+                two_point_rotation(synthetic, rotation)
+            }),
+        synthetic()
+            .noise(1e-3)
+            .point_box_size(6.0)
+            .evaluate(ROUNDS, near_half_turn),
+        synthetic()
+            .noise(1e-3)
+            .evaluate_absolute(&EPnP::new(), ROUNDS, 64),
+    ];
+    for evaluation in &evaluations {
+        eprintln!("{}", evaluation);
+        assert!(evaluation.failure_rate() < 0.1);
+        assert!(evaluation.rotation_statistics().median < 5e-3);
+    }
+}
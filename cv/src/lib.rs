@@ -41,6 +41,8 @@ pub mod camera {
 pub mod consensus {
     #[cfg(feature = "arrsac")]
     pub use arrsac::Arrsac;
+    #[cfg(feature = "cv-consensus")]
+    pub use cv_consensus::{LocalOptimization, PreemptiveRansac, Ransac, Sampling, Scoring};
 }
 
 /// Computational geometry algorithms
@@ -53,10 +55,22 @@ pub mod geom {
 pub mod estimate {
     #[cfg(feature = "eight-point")]
     pub use eight_point::EightPoint;
+    #[cfg(feature = "epnp")]
+    pub use epnp::EPnP;
     #[cfg(feature = "lambda-twist")]
     pub use lambda_twist::LambdaTwist;
+    #[cfg(feature = "nine-point")]
+    pub use nine_point::NinePoint;
     #[cfg(feature = "nister-stewenius")]
     pub use nister_stewenius::NisterStewenius;
+    #[cfg(feature = "one-point")]
+    pub use one_point::{OnePoint, PlanarFallback};
+    #[cfg(feature = "seven-point")]
+    pub use seven_point::SevenPoint;
+    #[cfg(feature = "seventeen-point")]
+    pub use seventeen_point::SeventeenPoint;
+    #[cfg(feature = "upright")]
+    pub use upright::{UprightAbsolute, UprightRelative};
 }
 
 /// Feature detection and description algorithms
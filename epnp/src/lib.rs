@@ -0,0 +1,282 @@
+#![no_std]
+
+use cv_core::{
+    nalgebra::{
+        IsometryMatrix3, Matrix3, Point3, Rotation3, SMatrix, SVector, Translation3, Vector3,
+        Vector4,
+    },
+    sample_consensus::Estimator,
+    FeatureWorldMatch, Projective, WorldToCamera,
+};
+use float_ord::FloatOrd;
+use num_traits::Float;
+
+/// The indices of the control point pairs used for the distance constraints.
+///
+/// The first three pairs only involve the first three control points, which are the only ones used for planar points.
+const CONTROL_PAIRS: [(usize, usize); 6] = [(0, 1), (0, 2), (1, 2), (0, 3), (1, 3), (2, 3)];
+
+/// Performs [EPnP](https://www.tugraz.at/fileadmin/user_upload/Institute/ICG/Images/team_lepetit/publications/lepetit_ijcv08.pdf)
+/// by Vincent Lepetit, Francesc Moreno-Noguer, and Pascal Fua.
+///
+/// EPnP expresses every world point as a weighted sum of four virtual control points and solves for the
+/// control points in camera space, which reduces the problem to finding the null space of a 12x12 matrix regardless
+/// of the number of correspondences. This makes it suitable for solving for a [`WorldToCamera`] from all of the
+/// inliers after consensus, and as an initialization for nonlinear optimization.
+///
+/// The constraints are formulated directly on the bearings, so observations are not required to lie in front of
+/// the image plane.
+///
+/// When the world points are coplanar, they don't constrain a fourth control point, so as in the paper only
+/// three control points in the plane are used and the null space is found in a 9x9 matrix instead.
+#[derive(Copy, Clone, Debug)]
+pub struct EPnP {
+    pub epsilon: f64,
+    pub iterations: usize,
+    /// The number of Gauss-Newton iterations used to refine the null space coefficients.
+    pub refinement_iterations: usize,
+    /// The world points are treated as coplanar when the ratio of the smallest to the largest variance along the
+    /// principal axes is below this.
+    pub planar_threshold: f64,
+}
+
+impl EPnP {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Estimates the pose from all of the matches.
+    ///
+    /// At least 4 matches with world points not at infinity are required, and the world points must not be collinear.
+    pub fn from_matches<I>(&self, data: I) -> Option<WorldToCamera>
+    where
+        I: Iterator<Item = FeatureWorldMatch> + Clone,
+    {
+        let points = data
+            .clone()
+            .filter_map(|FeatureWorldMatch(bearing, world)| Some((bearing, world.point()?)));
+
+        // Choose the control points from the centroid and principal axes of the world points.
+        let (count, sum) = points
+            .clone()
+            .fold((0usize, Vector3::zeros()), |(count, sum), (_, p)| {
+                (count + 1, sum + p.coords)
+            });
+        if count < 4 {
+            return None;
+        }
+        let centroid = sum / count as f64;
+        let covariance = points.clone().fold(Matrix3::zeros(), |cov, (_, p)| {
+            let d = p.coords - centroid;
+            cov + d * d.transpose()
+        }) / count as f64;
+        let eigens = covariance.try_symmetric_eigen(self.epsilon, self.iterations)?;
+        // Sort the principal axes by decreasing variance.
+        let mut axes = [0, 1, 2];
+        axes.sort_unstable_by_key(|&ix| FloatOrd(-eigens.eigenvalues[ix]));
+        let largest = eigens.eigenvalues[axes[0]];
+        if largest <= 0.0 || eigens.eigenvalues[axes[1]] <= self.planar_threshold * largest {
+            return None;
+        }
+        let planar = eigens.eigenvalues[axes[2]] <= self.planar_threshold * largest;
+        let controls = if planar { 3 } else { 4 };
+        let mut control_world = [centroid; 4];
+        for (control, &axis) in control_world[1..controls].iter_mut().zip(axes.iter()) {
+            *control += eigens.eigenvectors.column(axis) * eigens.eigenvalues[axis].sqrt();
+        }
+
+        // The principal axes are orthogonal, so the barycentric coordinates of world points in terms of control
+        // points are their projections onto the axes. Planar points don't use the fourth control point.
+        let alphas = |p: Point3<f64>| {
+            let d = p.coords - centroid;
+            let mut alpha = Vector4::zeros();
+            for (a, control) in alpha
+                .iter_mut()
+                .zip(control_world.iter())
+                .take(controls)
+                .skip(1)
+            {
+                let axis = control - centroid;
+                *a = axis.dot(&d) / axis.norm_squared();
+            }
+            alpha[0] = 1.0 - alpha.sum();
+            alpha
+        };
+
+        // Each bearing b must be parallel to its camera point, so b x (sum_j alpha_j c_j) = 0.
+        let mut mtm = SMatrix::<f64, 12, 12>::zeros();
+        for (bearing, world) in points.clone() {
+            let alpha = alphas(world);
+            let cross = bearing.cross_matrix();
+            let mut rows = SMatrix::<f64, 3, 12>::zeros();
+            for (j, &a) in alpha.iter().enumerate() {
+                rows.fixed_columns_mut::<3>(3 * j).copy_from(&(cross * a));
+            }
+            mtm += rows.transpose() * rows;
+        }
+
+        // The solution is a combination of the eigenvectors with the smallest eigenvalues.
+        let mut null_space = [SVector::<f64, 12>::zeros(); 4];
+        let control_camera_vector = if planar {
+            // The fourth control point is unconstrained, so only the block of the first three is used.
+            let eigens = mtm
+                .fixed_slice::<9, 9>(0, 0)
+                .into_owned()
+                .try_symmetric_eigen(self.epsilon, self.iterations)?;
+            let mut order = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+            order.sort_unstable_by_key(|&ix| FloatOrd(eigens.eigenvalues[ix]));
+            for (vector, &ix) in null_space.iter_mut().zip(order.iter()) {
+                vector
+                    .fixed_rows_mut::<9>(0)
+                    .copy_from(&eigens.eigenvectors.column(ix));
+            }
+            let null_space = [null_space[0], null_space[1], null_space[2]];
+            let betas = self.solve_betas(&null_space, &control_world, &CONTROL_PAIRS[..3])?;
+            combine(&null_space, betas.as_slice())
+        } else {
+            let eigens = mtm.try_symmetric_eigen(self.epsilon, self.iterations)?;
+            let mut order = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+            order.sort_unstable_by_key(|&ix| FloatOrd(eigens.eigenvalues[ix]));
+            for (vector, &ix) in null_space.iter_mut().zip(order.iter()) {
+                vector.copy_from(&eigens.eigenvectors.column(ix));
+            }
+            let betas = self.solve_betas(&null_space, &control_world, &CONTROL_PAIRS)?;
+            combine(&null_space, betas.as_slice())
+        };
+        let mut control_camera = [Vector3::zeros(); 4];
+        for (j, control) in control_camera.iter_mut().enumerate() {
+            *control = control_camera_vector.fixed_rows::<3>(3 * j).into_owned();
+        }
+        let camera_point = |alpha: Vector4<f64>| {
+            control_camera
+                .iter()
+                .zip(alpha.iter())
+                .fold(Vector3::zeros(), |sum, (c, &a)| sum + c * a)
+        };
+
+        // The null space vectors have an arbitrary sign, so make the points face the same way as the bearings.
+        let facing: f64 = points
+            .clone()
+            .map(|(bearing, world)| bearing.dot(&camera_point(alphas(world))).signum())
+            .sum();
+        let sign = if facing < 0.0 { -1.0 } else { 1.0 };
+
+        // Find the rigid transformation which maps the world points onto the camera points.
+        let world_centroid = centroid;
+        let camera_centroid = points.clone().fold(Vector3::zeros(), |sum, (_, world)| {
+            sum + camera_point(alphas(world)) * sign
+        }) / count as f64;
+        let cross_covariance = points.fold(Matrix3::zeros(), |h, (_, world)| {
+            let camera = camera_point(alphas(world)) * sign;
+            h + (world.coords - world_centroid) * (camera - camera_centroid).transpose()
+        });
+        let svd = cross_covariance.try_svd(true, true, self.epsilon, self.iterations)?;
+        let u = svd.u?;
+        let v_t = svd.v_t?;
+        let mut correction = Matrix3::identity();
+        correction[(2, 2)] = (v_t.transpose() * u.transpose()).determinant().signum();
+        let rotation = v_t.transpose() * correction * u.transpose();
+        let translation = camera_centroid - rotation * world_centroid;
+        Some(WorldToCamera(IsometryMatrix3::from_parts(
+            Translation3::from(translation),
+            Rotation3::from_matrix_unchecked(rotation),
+        )))
+    }
+
+    /// Finds the coefficients of the null space vectors which preserve the distances between the control points.
+    ///
+    /// A single null space vector is solved for in closed form first, and then all `N` coefficients are refined
+    /// with Gauss-Newton. Only the distances between the control point `pairs` are constrained.
+    fn solve_betas<const N: usize>(
+        &self,
+        null_space: &[SVector<f64, 12>; N],
+        control_world: &[Vector3<f64>; 4],
+        pairs: &[(usize, usize)],
+    ) -> Option<SVector<f64, N>> {
+        let difference = |v: &SVector<f64, 12>, (i, j): (usize, usize)| -> Vector3<f64> {
+            v.fixed_rows::<3>(3 * i) - v.fixed_rows::<3>(3 * j)
+        };
+        let mut world_distances_squared = [0.0; 6];
+        for (distance, &(i, j)) in world_distances_squared.iter_mut().zip(pairs.iter()) {
+            *distance = (control_world[i] - control_world[j]).norm_squared();
+        }
+
+        // N = 1: the scale of the first vector that best matches the world distances.
+        let (numerator, denominator) = pairs.iter().zip(world_distances_squared.iter()).fold(
+            (0.0, 0.0),
+            |(numerator, denominator), (&pair, &world)| {
+                let camera = difference(&null_space[0], pair).norm();
+                (
+                    numerator + camera * world.sqrt(),
+                    denominator + camera * camera,
+                )
+            },
+        );
+        if denominator == 0.0 {
+            return None;
+        }
+        let mut betas = SVector::<f64, N>::zeros();
+        betas[0] = numerator / denominator;
+
+        // Refine all of the coefficients with Gauss-Newton on the squared distance residuals.
+        for _ in 0..self.refinement_iterations {
+            let mut jacobian = SMatrix::<f64, 6, N>::zeros();
+            let mut residuals = SVector::<f64, 6>::zeros();
+            for (row, (&pair, &world)) in
+                pairs.iter().zip(world_distances_squared.iter()).enumerate()
+            {
+                let combined = null_space
+                    .iter()
+                    .zip(betas.iter())
+                    .fold(Vector3::zeros(), |sum, (v, &beta)| {
+                        sum + difference(v, pair) * beta
+                    });
+                residuals[row] = combined.norm_squared() - world;
+                for (k, v) in null_space.iter().enumerate() {
+                    jacobian[(row, k)] = 2.0 * combined.dot(&difference(v, pair));
+                }
+            }
+            let jtj: SMatrix<f64, N, N> = jacobian.transpose() * jacobian;
+            let step = match jtj.cholesky() {
+                Some(cholesky) => cholesky.solve(&(jacobian.transpose() * residuals)),
+                None => break,
+            };
+            betas -= step;
+        }
+        Some(betas)
+    }
+}
+
+impl Default for EPnP {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-12,
+            iterations: 1000,
+            refinement_iterations: 5,
+            planar_threshold: 1e-8,
+        }
+    }
+}
+
+/// Combines the null space vectors with the coefficients `betas`.
+fn combine(null_space: &[SVector<f64, 12>], betas: &[f64]) -> SVector<f64, 12> {
+    null_space
+        .iter()
+        .zip(betas.iter())
+        .fold(SVector::<f64, 12>::zeros(), |sum, (v, &beta)| {
+            sum + v * beta
+        })
+}
+
+impl Estimator<FeatureWorldMatch> for EPnP {
+    type Model = WorldToCamera;
+    type ModelIter = Option<WorldToCamera>;
+    const MIN_SAMPLES: usize = 4;
+
+    fn estimate<I>(&self, data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = FeatureWorldMatch> + Clone,
+    {
+        self.from_matches(data)
+    }
+}
@@ -0,0 +1,102 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3},
+    CameraPoint, FeatureWorldMatch, Pose, Projective, WorldPoint, WorldToCamera,
+};
+use epnp::EPnP;
+
+const SAMPLE_POINTS: usize = 32;
+const EPSILON: f64 = 1e-6;
+
+const ROT_MAGNITUDE: f64 = 0.2;
+const POINT_BOX_SIZE: f64 = 2.0;
+const POINT_DISTANCE: f64 = 3.0;
+
+#[test]
+fn randomized() {
+    let successes = (0..1000).filter(|_| run_round()).count();
+    eprintln!("successes: {}", successes);
+    assert!(successes > 950);
+}
+
+#[test]
+fn minimal() {
+    let (pose, matches) = some_test_data(4);
+    let estimate = EPnP::new()
+        .from_matches(matches.iter().copied())
+        .expect("didn't get a pose");
+    assert!(close(pose, estimate, 1e-4));
+}
+
+#[test]
+fn planar() {
+    let successes = (0..1000).filter(|_| run_planar_round()).count();
+    eprintln!("successes: {}", successes);
+    assert!(successes > 950);
+}
+
+fn run_round() -> bool {
+    let (pose, matches) = some_test_data(SAMPLE_POINTS);
+    let estimate = EPnP::new()
+        .from_matches(matches.iter().copied())
+        .expect("didn't get a pose");
+    close(pose, estimate, EPSILON)
+}
+
+fn run_planar_round() -> bool {
+    let (pose, matches) = some_planar_test_data(SAMPLE_POINTS);
+    let estimate = EPnP::new()
+        .from_matches(matches.iter().copied())
+        .expect("didn't get a pose");
+    close(pose, estimate, EPSILON)
+}
+
+fn close(a: WorldToCamera, b: WorldToCamera, epsilon: f64) -> bool {
+    let rotation_error = a.0.rotation.rotation_to(&b.0.rotation).angle();
+    let translation_error = (a.0.translation.vector - b.0.translation.vector).norm();
+    if rotation_error > epsilon || translation_error > epsilon {
+        eprintln!(
+            "rotation error: {}, translation error: {}",
+            rotation_error, translation_error
+        );
+        false
+    } else {
+        true
+    }
+}
+
+/// Gets a random pose and matches of random points in front of the camera.
+fn some_test_data(points: usize) -> (WorldToCamera, Vec<FeatureWorldMatch>) {
+    let pose = WorldToCamera(IsometryMatrix3::from_parts(
+        Vector3::new_random().into(),
+        Rotation3::new(Vector3::new_random() * std::f64::consts::PI * 2.0 * ROT_MAGNITUDE),
+    ));
+    let matches = (0..points)
+        .map(|_| {
+            let mut camera = Point3::from(Vector3::new_random() * POINT_BOX_SIZE);
+            camera.x -= 0.5 * POINT_BOX_SIZE;
+            camera.y -= 0.5 * POINT_BOX_SIZE;
+            camera.z += POINT_DISTANCE;
+            let camera = CameraPoint::from_point(camera);
+            let world: WorldPoint = pose.inverse().transform(camera);
+            FeatureWorldMatch(camera.bearing(), world)
+        })
+        .collect();
+    (pose, matches)
+}
+
+/// Gets a random pose and matches of random points on a tilted plane in front of the camera.
+fn some_planar_test_data(points: usize) -> (WorldToCamera, Vec<FeatureWorldMatch>) {
+    let (pose, matches) = some_test_data(points);
+    let tilt = Vector3::new_random() - Vector3::repeat(0.5);
+    let matches = matches
+        .into_iter()
+        .map(|FeatureWorldMatch(_, world)| {
+            let mut camera = pose.transform(world).point().unwrap();
+            camera.z = POINT_DISTANCE + tilt.x * camera.x + tilt.y * camera.y;
+            let camera = CameraPoint::from_point(camera);
+            let world: WorldPoint = pose.inverse().transform(camera);
+            FeatureWorldMatch(camera.bearing(), world)
+        })
+        .collect();
+    (pose, matches)
+}
@@ -1,7 +1,14 @@
 use akaze::{Akaze, KeyPoint};
-use image::{DynamicImage, Rgba};
+use image::{imageops, DynamicImage, Rgb, RgbImage, Rgba};
 use imageproc::drawing;
 
+/// The color used for keypoints and matches which have no inlier information.
+pub const NEUTRAL_COLOR: Rgb<u8> = Rgb([0, 255, 255]);
+/// The color used for matches which were classified as inliers.
+pub const INLIER_COLOR: Rgb<u8> = Rgb([0, 255, 0]);
+/// The color used for matches which were classified as outliers.
+pub const OUTLIER_COLOR: Rgb<u8> = Rgb([255, 0, 0]);
+
 pub fn render_akaze_keypoints(image: &DynamicImage, threshold: f64) -> DynamicImage {
     let akaze = Akaze::new(threshold);
     let (kps, _) = akaze.extract(image);
@@ -11,3 +18,71 @@ pub fn render_akaze_keypoints(image: &DynamicImage, threshold: f64) -> DynamicIm
     }
     DynamicImage::ImageRgba8(image.0)
 }
+
+/// Draws a single keypoint as a circle of its size with a line from the center indicating its orientation.
+fn draw_keypoint_mut(
+    canvas: &mut RgbImage,
+    keypoint: &KeyPoint,
+    offset: (f32, f32),
+    color: Rgb<u8>,
+) {
+    let (x, y) = (keypoint.point.0 + offset.0, keypoint.point.1 + offset.1);
+    let radius = keypoint.size.max(1.0);
+    drawing::draw_hollow_circle_mut(canvas, (x as i32, y as i32), radius as i32, color);
+    let tip = (
+        x + radius * keypoint.angle.cos(),
+        y + radius * keypoint.angle.sin(),
+    );
+    drawing::draw_line_segment_mut(canvas, (x, y), tip, color);
+}
+
+/// Renders keypoints onto a copy of `image`, showing the scale and orientation of each keypoint.
+pub fn draw_keypoints(image: &DynamicImage, keypoints: &[KeyPoint]) -> RgbImage {
+    let mut canvas = image.to_rgb8();
+    for keypoint in keypoints {
+        draw_keypoint_mut(&mut canvas, keypoint, (0.0, 0.0), NEUTRAL_COLOR);
+    }
+    canvas
+}
+
+/// Renders two images side-by-side with a line drawn between the keypoints of every match.
+///
+/// `matches` contains pairs of indices into `a_keypoints` and `b_keypoints`. If `inliers` is provided,
+/// it must be the same length as `matches`, and each match is colored with [`INLIER_COLOR`] or [`OUTLIER_COLOR`]
+/// accordingly. Otherwise all matches are drawn with [`NEUTRAL_COLOR`].
+pub fn draw_matches(
+    a: &DynamicImage,
+    a_keypoints: &[KeyPoint],
+    b: &DynamicImage,
+    b_keypoints: &[KeyPoint],
+    matches: &[[usize; 2]],
+    inliers: Option<&[bool]>,
+) -> RgbImage {
+    let a = a.to_rgb8();
+    let b = b.to_rgb8();
+    let mut canvas = RgbImage::new(a.width() + b.width(), a.height().max(b.height()));
+    imageops::replace(&mut canvas, &a, 0, 0);
+    imageops::replace(&mut canvas, &b, a.width(), 0);
+    let b_offset = (a.width() as f32, 0.0);
+    for (ix, &[a_ix, b_ix]) in matches.iter().enumerate() {
+        let color = match inliers {
+            Some(inliers) if inliers[ix] => INLIER_COLOR,
+            Some(_) => OUTLIER_COLOR,
+            None => NEUTRAL_COLOR,
+        };
+        let a_keypoint = &a_keypoints[a_ix];
+        let b_keypoint = &b_keypoints[b_ix];
+        draw_keypoint_mut(&mut canvas, a_keypoint, (0.0, 0.0), color);
+        draw_keypoint_mut(&mut canvas, b_keypoint, b_offset, color);
+        drawing::draw_line_segment_mut(
+            &mut canvas,
+            a_keypoint.point,
+            (
+                b_keypoint.point.0 + b_offset.0,
+                b_keypoint.point.1 + b_offset.1,
+            ),
+            color,
+        );
+    }
+    canvas
+}
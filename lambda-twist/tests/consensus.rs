@@ -1,6 +1,7 @@
 use approx::assert_relative_eq;
 use arrayvec::ArrayVec;
 use arrsac::Arrsac;
+use cv_consensus::Ransac;
 use cv_core::{
     nalgebra::{IsometryMatrix3, Point2, Point3, Rotation3, Translation, UnitVector3, Vector3},
     sample_consensus::Consensus,
@@ -15,10 +16,8 @@ fn map<T, U, F: Fn(T) -> U, const N: usize>(f: F, array: ArrayVec<T, N>) -> Arra
     array.into_iter().map(f).collect()
 }
 
-#[test]
-fn arrsac_manual() {
-    let mut arrsac = Arrsac::new(0.01, SmallRng::seed_from_u64(0));
-
+/// Gets the ground truth rotation, translation, and the matches of a few manually specified points.
+fn manual_samples() -> (Rotation3<f64>, Translation<f64, 3>, Vec<FeatureWorldMatch>) {
     // Define some points in camera coordinates (with z > 0).
     let camera_depth_points: ArrayVec<Point3<f64>, 5> = map(
         Point3::from,
@@ -54,6 +53,14 @@ fn arrsac_manual() {
         })
         .collect();
 
+    (rot, trans, samples)
+}
+
+#[test]
+fn arrsac_manual() {
+    let mut arrsac = Arrsac::new(0.01, SmallRng::seed_from_u64(0));
+    let (rot, trans, samples) = manual_samples();
+
     // Estimate potential poses with P3P.
     // Arrsac should use the fourth point to filter and find only one model from the 4 generated.
     let pose = arrsac
@@ -65,6 +72,22 @@ fn arrsac_manual() {
     assert_relative_eq!(trans, pose.0.translation, epsilon = EPSILON_APPROX);
 }
 
+#[test]
+fn ransac_manual() {
+    let mut ransac = Ransac::new(0.01, SmallRng::seed_from_u64(0));
+    let (rot, trans, samples) = manual_samples();
+
+    // The minimal 3 point sample generates up to 4 models, and the remaining points select the correct one.
+    let (pose, inliers) = ransac
+        .model_inliers(&LambdaTwist::new(), samples.iter().cloned())
+        .unwrap();
+
+    // Compare the pose to ground truth.
+    assert_relative_eq!(rot, pose.0.rotation, epsilon = EPSILON_APPROX);
+    assert_relative_eq!(trans, pose.0.translation, epsilon = EPSILON_APPROX);
+    assert_eq!(inliers.len(), samples.len());
+}
+
 #[test]
 fn endless_loop_case() {
     let mut arrsac = Arrsac::new(0.01, SmallRng::seed_from_u64(0));
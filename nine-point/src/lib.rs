@@ -0,0 +1,163 @@
+#![no_std]
+
+use arrayvec::ArrayVec;
+use cv_core::{
+    nalgebra::{Matrix3, Point2, SMatrix, SVector, Vector3},
+    sample_consensus::Estimator,
+    KeyPointMatch,
+};
+use cv_pinhole::{FundamentalMatrix, RadialFundamentalMatrix};
+use float_ord::FloatOrd;
+use num_traits::Float;
+
+type Matrix9 = SMatrix<f64, 9, 9>;
+
+/// Performs the nine-point algorithm by Andrew Fitzgibbon from "Simultaneous linear estimation of multiple view
+/// geometry and lens distortion" (the F + λ problem).
+///
+/// This estimates a [`RadialFundamentalMatrix`], which is a fundamental matrix between two uncalibrated images
+/// along with the parameter of a division model of radial distortion shared by both images, so wide-angle images
+/// don't need to be undistorted before estimation. The undistorted coordinate `(p.x, p.y, 1 + λ * |p|²)` is linear
+/// in `λ`, so the epipolar constraint becomes the quadratic eigenvalue problem `(D1 + λ * D2 + λ² * D3) * f = 0`.
+/// Nine matches is the minimal case, and more matches are solved in the least-squares sense.
+///
+/// The distortion is centered at `center`, which should be the principal point (or the center of the image if
+/// it is unknown), and the keypoints are divided by `scale` to keep the problem well conditioned. A good `scale`
+/// is the focal length, or half of the image diagonal if it is unknown.
+#[derive(Copy, Clone, Debug)]
+pub struct NinePoint {
+    pub center: Point2<f64>,
+    pub scale: f64,
+    pub epsilon: f64,
+    pub iterations: usize,
+}
+
+impl NinePoint {
+    pub fn new(center: Point2<f64>, scale: f64) -> Self {
+        Self {
+            center,
+            scale,
+            epsilon: 1e-12,
+            iterations: 1000,
+        }
+    }
+
+    #[must_use]
+    pub fn epsilon(self, epsilon: f64) -> Self {
+        Self { epsilon, ..self }
+    }
+
+    #[must_use]
+    pub fn iterations(self, iterations: usize) -> Self {
+        Self { iterations, ..self }
+    }
+
+    pub fn from_matches<I>(&self, data: I) -> ArrayVec<RadialFundamentalMatrix, 18>
+    where
+        I: Iterator<Item = KeyPointMatch> + Clone,
+    {
+        self.solve(data).unwrap_or_default()
+    }
+
+    fn solve<I>(&self, data: I) -> Option<ArrayVec<RadialFundamentalMatrix, 18>>
+    where
+        I: Iterator<Item = KeyPointMatch> + Clone,
+    {
+        // Each match contributes a row to `D1`, `D2`, and `D3` with `F` stored in column-major order.
+        // Both sides are multiplied by `transpose(D1)` to get a square problem for any number of matches.
+        let normalize = |point: Point2<f64>| (point - self.center) / self.scale;
+        let (mut a1, mut a2, mut a3) = (Matrix9::zeros(), Matrix9::zeros(), Matrix9::zeros());
+        for KeyPointMatch(a, b) in data {
+            let (a, b) = (normalize(a.0), normalize(b.0));
+            let (xa, xb) = (Vector3::new(a.x, a.y, 1.0), Vector3::new(b.x, b.y, 1.0));
+            let (ea, eb) = (
+                Vector3::new(0.0, 0.0, a.norm_squared()),
+                Vector3::new(0.0, 0.0, b.norm_squared()),
+            );
+            let mut d1 = SVector::<f64, 9>::zeros();
+            let mut d2 = SVector::<f64, 9>::zeros();
+            let mut d3 = SVector::<f64, 9>::zeros();
+            for j in 0..3 {
+                for i in 0..3 {
+                    d1[3 * j + i] = xb[i] * xa[j];
+                    d2[3 * j + i] = xb[i] * ea[j] + eb[i] * xa[j];
+                    d3[3 * j + i] = eb[i] * ea[j];
+                }
+            }
+            a1 += d1 * d1.transpose();
+            a2 += d1 * d2.transpose();
+            a3 += d1 * d3.transpose();
+        }
+
+        // `D3` is rank one, so most of the eigenvalues are at infinity. Solving for `μ = 1 / λ` instead moves them to
+        // zero, and the problem `(μ² * A1 + μ * A2 + A3) * f = 0` can be linearized with a companion matrix.
+        let a1_inverse = a1.try_inverse()?;
+        let mut companion = SMatrix::<f64, 18, 18>::zeros();
+        companion
+            .fixed_slice_mut::<9, 9>(0, 9)
+            .copy_from(&Matrix9::identity());
+        companion
+            .fixed_slice_mut::<9, 9>(9, 0)
+            .copy_from(&(-a1_inverse * a3));
+        companion
+            .fixed_slice_mut::<9, 9>(9, 9)
+            .copy_from(&(-a1_inverse * a2));
+        let eigenvalues = companion
+            .try_schur(self.epsilon, self.iterations)?
+            .complex_eigenvalues();
+
+        Some(
+            eigenvalues
+                .iter()
+                .filter(|mu| {
+                    Float::abs(mu.im) <= 1e-6 * Float::abs(mu.re) && Float::abs(mu.re) > 1e-9
+                })
+                .filter_map(|mu| {
+                    let lambda = 1.0 / mu.re;
+                    let fundamental =
+                        self.fundamental(a1 + a2 * lambda + a3 * (lambda * lambda))?;
+                    Some(RadialFundamentalMatrix {
+                        fundamental,
+                        lambda,
+                        center: self.center,
+                        scale: self.scale,
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Finds the rank two fundamental matrix closest to the null space of the problem at a specific `λ`.
+    fn fundamental(&self, problem: Matrix9) -> Option<FundamentalMatrix> {
+        let eigens =
+            (problem.transpose() * problem).try_symmetric_eigen(self.epsilon, self.iterations)?;
+        let (smallest, _) = eigens
+            .eigenvalues
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &n)| FloatOrd(n))?;
+        let fundamental =
+            Matrix3::from_iterator(eigens.eigenvectors.column(smallest).iter().copied());
+        let mut svd = fundamental.try_svd(true, true, self.epsilon, self.iterations)?;
+        let (smallest, _) = svd
+            .singular_values
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &n)| FloatOrd(n))?;
+        svd.singular_values[smallest] = 0.0;
+        Some(FundamentalMatrix(svd.recompose().ok()?).normalize())
+    }
+}
+
+impl Estimator<KeyPointMatch> for NinePoint {
+    type Model = RadialFundamentalMatrix;
+    type ModelIter = ArrayVec<RadialFundamentalMatrix, 18>;
+    const MIN_SAMPLES: usize = 9;
+
+    fn estimate<I>(&self, data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = KeyPointMatch> + Clone,
+    {
+        self.from_matches(data)
+    }
+}
@@ -0,0 +1,68 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point2, Point3, Rotation3, Vector3},
+    sample_consensus::{Estimator, Model},
+    CameraModel, CameraPoint, CameraToCamera, KeyPointMatch, Pose, Projective,
+};
+use cv_pinhole::{CameraIntrinsics, CameraIntrinsicsK1Distortion};
+use nine_point::NinePoint;
+
+const SAMPLE_POINTS: usize = 32;
+/// The maximum Sampson distance in pixels.
+const RESIDUAL_THRESHOLD: f64 = 1e-3;
+const LAMBDA_EPSILON: f64 = 1e-5;
+
+const FOCAL: f64 = 800.0;
+const ROT_MAGNITUDE: f64 = 0.2;
+const POINT_BOX_SIZE: f64 = 2.0;
+const POINT_DISTANCE: f64 = 3.0;
+
+#[test]
+fn randomized() {
+    let successes = (0..1000).filter(|_| run_round()).count();
+    eprintln!("successes: {}", successes);
+    assert!(successes > 950);
+}
+
+fn run_round() -> bool {
+    let (k1, matches) = some_test_data();
+    let center = Point2::new(320.0, 240.0);
+    NinePoint::new(center, FOCAL)
+        .estimate(matches.iter().take(9).copied())
+        .into_iter()
+        .any(|estimate| {
+            (estimate.lambda - k1).abs() < LAMBDA_EPSILON
+                && matches
+                    .iter()
+                    .all(|m| estimate.residual(m) < RESIDUAL_THRESHOLD)
+        })
+}
+
+/// Gets a random distortion coefficient and distorted matches in pixel coordinates.
+fn some_test_data() -> (f64, Vec<KeyPointMatch>) {
+    let relative_pose = CameraToCamera(IsometryMatrix3::from_parts(
+        Vector3::new_random().into(),
+        Rotation3::new(Vector3::new_random() * std::f64::consts::PI * 2.0 * ROT_MAGNITUDE),
+    ));
+    let k1 = -0.1 - 0.3 * rand::random::<f64>();
+    let intrinsics = CameraIntrinsicsK1Distortion::new(
+        CameraIntrinsics::identity()
+            .focal(FOCAL)
+            .principal_point(Point2::new(320.0, 240.0)),
+        k1,
+    );
+    let matches = (0..SAMPLE_POINTS)
+        .filter_map(|_| {
+            let mut a = Point3::from(Vector3::new_random() * POINT_BOX_SIZE);
+            a.x -= 0.5 * POINT_BOX_SIZE;
+            a.y -= 0.5 * POINT_BOX_SIZE;
+            a.z += POINT_DISTANCE;
+            let a = CameraPoint::from_point(a);
+            let b = relative_pose.transform(a);
+            Some(KeyPointMatch(
+                intrinsics.uncalibrate(a.bearing())?,
+                intrinsics.uncalibrate(b.bearing())?,
+            ))
+        })
+        .collect();
+    (k1, matches)
+}
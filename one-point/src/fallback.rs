@@ -0,0 +1,85 @@
+use crate::OnePoint;
+use alloc::vec::Vec;
+use cv_core::{
+    sample_consensus::{Consensus, Estimator},
+    CameraToCamera, FeatureMatch,
+};
+
+/// A consensus which uses [`OnePoint`] and falls back to a general relative pose estimator when too few matches
+/// agree with the planar motion model.
+///
+/// When the vehicle moves as expected, the cheap one-point consensus finds nearly all of the inliers. When the
+/// planar motion constraint is violated, only a fraction of the true inliers fit the model, so the inlier ratio drops
+/// below `minimum_inlier_ratio` and the `general` consensus is run with the `general_estimator`
+/// (such as the eight-point or five-point algorithm) instead.
+#[derive(Clone, Debug)]
+pub struct PlanarFallback<C1, C2, E> {
+    /// The consensus used with [`OnePoint`].
+    pub planar: C1,
+    /// The consensus used with the general estimator.
+    pub general: C2,
+    /// The general relative pose estimator used when the planar motion constraint is violated.
+    pub general_estimator: E,
+    /// The minimum ratio of inliers to matches for the planar motion model to be accepted.
+    pub minimum_inlier_ratio: f64,
+    /// Set if the last estimation fell back to the general estimator.
+    pub fell_back: bool,
+}
+
+impl<C1, C2, E> PlanarFallback<C1, C2, E> {
+    pub fn new(planar: C1, general: C2, general_estimator: E) -> Self {
+        Self {
+            planar,
+            general,
+            general_estimator,
+            minimum_inlier_ratio: 0.5,
+            fell_back: false,
+        }
+    }
+
+    #[must_use]
+    pub fn minimum_inlier_ratio(self, minimum_inlier_ratio: f64) -> Self {
+        Self {
+            minimum_inlier_ratio,
+            ..self
+        }
+    }
+}
+
+impl<C1, C2, E> Consensus<OnePoint, FeatureMatch> for PlanarFallback<C1, C2, E>
+where
+    C1: Consensus<OnePoint, FeatureMatch>,
+    C2: Consensus<E, FeatureMatch>,
+    E: Estimator<FeatureMatch, Model = CameraToCamera>,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &OnePoint, data: I) -> Option<CameraToCamera>
+    where
+        I: Iterator<Item = FeatureMatch> + Clone,
+    {
+        self.model_inliers(estimator, data).map(|(model, _)| model)
+    }
+
+    fn model_inliers<I>(
+        &mut self,
+        estimator: &OnePoint,
+        data: I,
+    ) -> Option<(CameraToCamera, Self::Inliers)>
+    where
+        I: Iterator<Item = FeatureMatch> + Clone,
+    {
+        let len = data.clone().count();
+        if let Some((model, inliers)) = self.planar.model_inliers(estimator, data.clone()) {
+            let inliers: Vec<usize> = inliers.into_iter().collect();
+            if inliers.len() as f64 >= self.minimum_inlier_ratio * len as f64 {
+                self.fell_back = false;
+                return Some((model, inliers));
+            }
+        }
+        self.fell_back = true;
+        self.general
+            .model_inliers(&self.general_estimator, data)
+            .map(|(model, inliers)| (model, inliers.into_iter().collect()))
+    }
+}
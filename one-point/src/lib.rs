@@ -0,0 +1,85 @@
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+mod fallback;
+
+#[cfg(feature = "alloc")]
+pub use fallback::*;
+
+use arrayvec::ArrayVec;
+use cv_core::{
+    nalgebra::{Rotation3, Vector3},
+    sample_consensus::Estimator,
+    CameraToCamera, FeatureMatch, Pose,
+};
+use num_traits::Float;
+
+/// Performs the [1-point algorithm](https://rpg.ifi.uzh.ch/docs/IJCV11_scaramuzza.pdf) by Davide Scaramuzza.
+///
+/// A vehicle with Ackermann steering moves locally along a circular arc on the ground plane, so its motion between
+/// two frames is described by the yaw angle `θ` alone (up to the scale of translation): the vehicle rotates by `θ`
+/// about the vertical axis and moves in the direction `θ / 2` from straight ahead. This can be solved in closed form
+/// from a single match, which makes consensus extremely cheap and lets it be run with very high outlier ratios.
+///
+/// The camera coordinate system must be aligned with the vehicle, with the `y` axis vertical and the `z` axis
+/// pointing forwards, and the camera should be above the rear axle. When the motion violates these constraints
+/// (such as on uneven ground or when the vehicle skids), see [`PlanarFallback`] to fall back to a general relative
+/// pose estimator.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OnePoint;
+
+impl OnePoint {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Computes the yaw angle of the vehicle from a single match.
+    pub fn yaw(&self, FeatureMatch(a, b): FeatureMatch) -> Option<f64> {
+        // The epipolar constraint reduces to `cos(θ/2) * (ax*by - ay*bx) = sin(θ/2) * (ay*bz + az*by)`.
+        let numerator = a.x * b.y - a.y * b.x;
+        let denominator = a.y * b.z + a.z * b.y;
+        if numerator == 0.0 && denominator == 0.0 {
+            return None;
+        }
+        // Both `θ/2` and `θ/2 + π` solve this, but they produce the same rotation, so keep the yaw in `(-π, π]`.
+        Some(2.0 * Float::atan2(numerator * denominator.signum(), denominator.abs()))
+    }
+
+    /// Computes the relative pose for a given yaw angle of the vehicle.
+    ///
+    /// The translation is a unit vector pointing forwards along the motion.
+    pub fn pose_from_yaw(yaw: f64) -> CameraToCamera {
+        let rotation = Rotation3::from_axis_angle(&Vector3::y_axis(), yaw);
+        let (sin, cos) = Float::sin_cos(0.5 * yaw);
+        let center = Vector3::new(sin, 0.0, cos);
+        // The vehicle moves to `center` and rotates by `rotation`, so points move the opposite way.
+        let inverse = rotation.inverse();
+        CameraToCamera::from_parts(-(inverse * center), inverse)
+    }
+}
+
+impl Estimator<FeatureMatch> for OnePoint {
+    type Model = CameraToCamera;
+    type ModelIter = ArrayVec<CameraToCamera, 2>;
+    const MIN_SAMPLES: usize = 1;
+
+    fn estimate<I>(&self, mut data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = FeatureMatch> + Clone,
+    {
+        let mut poses = ArrayVec::new();
+        if let Some(yaw) = data.next().and_then(|m| self.yaw(m)) {
+            let pose = Self::pose_from_yaw(yaw);
+            // The direction of travel can't be determined from the epipolar constraint.
+            poses.push(pose);
+            poses.push(CameraToCamera::from_parts(
+                -pose.0.translation.vector,
+                pose.0.rotation,
+            ));
+        }
+        poses
+    }
+}
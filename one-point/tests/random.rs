@@ -0,0 +1,65 @@
+use cv_core::{
+    nalgebra::{Point3, Rotation3, Vector3},
+    sample_consensus::{Estimator, Model},
+    CameraPoint, FeatureMatch, Projective,
+};
+use one_point::OnePoint;
+
+const SAMPLE_POINTS: usize = 16;
+const RESIDUAL_THRESHOLD: f64 = 1e-6;
+
+const MAX_YAW: f64 = 0.3;
+const POINT_BOX_SIZE: f64 = 4.0;
+const POINT_DISTANCE: f64 = 5.0;
+
+#[test]
+fn randomized() {
+    let successes = (0..1000).filter(|_| run_round()).count();
+    eprintln!("successes: {}", successes);
+    assert!(successes > 950);
+}
+
+fn run_round() -> bool {
+    let (yaw, matches) = some_test_data();
+    let estimated_yaw = OnePoint::new().yaw(matches[0]).expect("didn't get a yaw");
+    if (estimated_yaw - yaw).abs() > RESIDUAL_THRESHOLD {
+        eprintln!("yaw was {}, but estimated {}", yaw, estimated_yaw);
+        return false;
+    }
+    // One of the poses must agree with all of the other matches.
+    OnePoint::new()
+        .estimate(matches.iter().copied())
+        .iter()
+        .any(|pose| {
+            matches
+                .iter()
+                .all(|m| pose.residual(m).abs() < RESIDUAL_THRESHOLD)
+        })
+}
+
+/// Gets a random yaw and matches of random points seen by a vehicle moving along a circular arc.
+fn some_test_data() -> (f64, Vec<FeatureMatch>) {
+    let yaw = (rand_unit() * 2.0 - 1.0) * MAX_YAW;
+    let rotation = Rotation3::from_axis_angle(&Vector3::y_axis(), yaw);
+    // The vehicle moves in the direction of half of the yaw angle.
+    let distance = 0.5 + rand_unit();
+    let center = Vector3::new((0.5 * yaw).sin(), 0.0, (0.5 * yaw).cos()) * distance;
+    let matches = (0..SAMPLE_POINTS)
+        .map(|_| {
+            let mut a = Point3::from(Vector3::new_random() * POINT_BOX_SIZE);
+            a.x -= 0.5 * POINT_BOX_SIZE;
+            a.y -= 0.5 * POINT_BOX_SIZE;
+            a.z += POINT_DISTANCE;
+            let b = rotation.inverse() * (a - center);
+            FeatureMatch(
+                CameraPoint::from_point(a).bearing(),
+                CameraPoint::from_point(b).bearing(),
+            )
+        })
+        .collect();
+    (yaw, matches)
+}
+
+fn rand_unit() -> f64 {
+    Vector3::<f64>::new_random().x
+}
@@ -0,0 +1,198 @@
+#![no_std]
+
+use arrayvec::ArrayVec;
+use cv_core::{
+    nalgebra::{Matrix3, Point2, SMatrix, SVector},
+    sample_consensus::Estimator,
+    KeyPointMatch,
+};
+use cv_pinhole::FundamentalMatrix;
+use float_ord::FloatOrd;
+use num_traits::Float;
+
+/// Computes the transformation which moves the centroid of the points to the origin and
+/// scales them to have an average distance of `sqrt(2)` from the origin.
+///
+/// This is the normalization recommended by Hartley, without which the linear system is very poorly conditioned
+/// in pixel coordinates.
+fn normalization(points: impl Iterator<Item = Point2<f64>> + Clone) -> Option<Matrix3<f64>> {
+    let count = points.clone().count() as f64;
+    let centroid = points
+        .clone()
+        .fold(Point2::origin(), |acc, p| acc + p.coords)
+        / count;
+    let mean_distance = points.map(|p| (p - centroid).norm()).sum::<f64>() / count;
+    if mean_distance == 0.0 {
+        return None;
+    }
+    let scale = core::f64::consts::SQRT_2 / mean_distance;
+    #[rustfmt::skip]
+    let transform = Matrix3::new(
+        scale, 0.0,   -scale * centroid.x,
+        0.0,   scale, -scale * centroid.y,
+        0.0,   0.0,   1.0,
+    );
+    Some(transform)
+}
+
+/// Finds the real roots of `c3 * x^3 + c2 * x^2 + c1 * x + c0`.
+fn cubic_roots(c3: f64, c2: f64, c1: f64, c0: f64) -> ArrayVec<f64, 3> {
+    let mut roots = ArrayVec::new();
+    let scale = Float::abs(c3)
+        .max(Float::abs(c2))
+        .max(Float::abs(c1))
+        .max(Float::abs(c0));
+    if scale == 0.0 {
+        return roots;
+    }
+    if Float::abs(c3) < 1e-12 * scale {
+        // The polynomial is (nearly) quadratic.
+        if Float::abs(c2) < 1e-12 * scale {
+            if c1 != 0.0 {
+                roots.push(-c0 / c1);
+            }
+            return roots;
+        }
+        let discriminant = c1 * c1 - 4.0 * c2 * c0;
+        if discriminant >= 0.0 {
+            let sqrt = Float::sqrt(discriminant);
+            roots.push((-c1 + sqrt) / (2.0 * c2));
+            roots.push((-c1 - sqrt) / (2.0 * c2));
+        }
+        return roots;
+    }
+    let (a, b, c) = (c2 / c3, c1 / c3, c0 / c3);
+    // Substitute `x = t - a / 3` to get the depressed cubic `t^3 + p * t + q`.
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+    let shift = -a / 3.0;
+    if discriminant > 0.0 {
+        let sqrt = Float::sqrt(discriminant);
+        roots.push(Float::cbrt(-q / 2.0 + sqrt) + Float::cbrt(-q / 2.0 - sqrt) + shift);
+    } else if p == 0.0 {
+        roots.push(Float::cbrt(-q) + shift);
+    } else {
+        let r = Float::sqrt(-p / 3.0);
+        let phi = Float::acos(
+            (3.0 * q / (2.0 * p) * Float::sqrt(-3.0 / p))
+                .max(-1.0)
+                .min(1.0),
+        );
+        for k in 0..3 {
+            let angle = phi / 3.0 - 2.0 * core::f64::consts::PI * k as f64 / 3.0;
+            roots.push(2.0 * r * Float::cos(angle) + shift);
+        }
+    }
+    // Polish the roots with Newton's method, since the closed form loses precision.
+    for root in &mut roots {
+        for _ in 0..2 {
+            let value = ((c3 * *root + c2) * *root + c1) * *root + c0;
+            let derivative = (3.0 * c3 * *root + 2.0 * c2) * *root + c1;
+            if derivative != 0.0 {
+                *root -= value / derivative;
+            }
+        }
+    }
+    roots
+}
+
+/// Performs the seven-point algorithm by Richard Hartley and Andrew Zisserman for estimating a
+/// [`FundamentalMatrix`] between two uncalibrated images.
+///
+/// Seven matches leave a two dimensional family of matrices `F = a * F1 + (1 - a) * F2` which satisfy the epipolar
+/// constraint. Requiring `det(F) = 0` gives a cubic in `a`, and each of its one or three real roots produces a
+/// fundamental matrix. Since seven is the true minimal sample, consensus on uncalibrated images needs far fewer
+/// iterations than with the eight-point algorithm.
+#[derive(Copy, Clone, Debug)]
+pub struct SevenPoint {
+    pub epsilon: f64,
+    pub iterations: usize,
+}
+
+impl SevenPoint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn from_matches<I>(&self, data: I) -> ArrayVec<FundamentalMatrix, 3>
+    where
+        I: Iterator<Item = KeyPointMatch> + Clone,
+    {
+        self.solve(data).unwrap_or_default()
+    }
+
+    fn solve<I>(&self, data: I) -> Option<ArrayVec<FundamentalMatrix, 3>>
+    where
+        I: Iterator<Item = KeyPointMatch> + Clone,
+    {
+        let a_transform = normalization(data.clone().map(|KeyPointMatch(a, _)| a.0))?;
+        let b_transform = normalization(data.clone().map(|KeyPointMatch(_, b)| b.0))?;
+
+        // Encode the epipolar constraint with `F` stored in column-major order.
+        let mut ata = SMatrix::<f64, 9, 9>::zeros();
+        for KeyPointMatch(a, b) in data {
+            let a = a_transform * a.0.to_homogeneous();
+            let b = b_transform * b.0.to_homogeneous();
+            let mut row = SVector::<f64, 9>::zeros();
+            for j in 0..3 {
+                for i in 0..3 {
+                    row[3 * j + i] = b[i] * a[j];
+                }
+            }
+            ata += row * row.transpose();
+        }
+        let eigens = ata.try_symmetric_eigen(self.epsilon, self.iterations)?;
+        let mut order = [0usize; 9];
+        for (ix, o) in order.iter_mut().enumerate() {
+            *o = ix;
+        }
+        order.sort_unstable_by_key(|&ix| FloatOrd(eigens.eigenvalues[ix]));
+        let matrix = |ix: usize| {
+            Matrix3::from_iterator(eigens.eigenvectors.column(order[ix]).iter().copied())
+        };
+        let (f1, f2) = (matrix(0), matrix(1));
+
+        // Interpolate the cubic `det(a * F1 + (1 - a) * F2)` from its value at four points.
+        let det = |a: f64| (f1 * a + f2 * (1.0 - a)).determinant();
+        let (d0, d1, dm1, d2) = (det(0.0), det(1.0), det(-1.0), det(2.0));
+        let c0 = d0;
+        let c2 = (d1 + dm1) / 2.0 - c0;
+        let odd = (d1 - dm1) / 2.0;
+        let c3 = (d2 - 4.0 * c2 - c0 - 2.0 * odd) / 6.0;
+        let c1 = odd - c3;
+
+        Some(
+            cubic_roots(c3, c2, c1, c0)
+                .into_iter()
+                .map(|a| {
+                    let normalized = f1 * a + f2 * (1.0 - a);
+                    FundamentalMatrix(b_transform.transpose() * normalized * a_transform)
+                        .normalize()
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Default for SevenPoint {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-12,
+            iterations: 1000,
+        }
+    }
+}
+
+impl Estimator<KeyPointMatch> for SevenPoint {
+    type Model = FundamentalMatrix;
+    type ModelIter = ArrayVec<FundamentalMatrix, 3>;
+    const MIN_SAMPLES: usize = 7;
+
+    fn estimate<I>(&self, data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = KeyPointMatch> + Clone,
+    {
+        self.from_matches(data)
+    }
+}
@@ -0,0 +1,62 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point2, Point3, Rotation3, Vector2, Vector3},
+    sample_consensus::{Estimator, Model},
+    CameraModel, CameraPoint, CameraToCamera, KeyPointMatch, Pose, Projective,
+};
+use cv_pinhole::CameraIntrinsics;
+use seven_point::SevenPoint;
+
+const SAMPLE_POINTS: usize = 32;
+/// The maximum Sampson distance in pixels.
+const RESIDUAL_THRESHOLD: f64 = 1e-4;
+
+const ROT_MAGNITUDE: f64 = 0.2;
+const POINT_BOX_SIZE: f64 = 2.0;
+const POINT_DISTANCE: f64 = 3.0;
+
+#[test]
+fn randomized() {
+    let successes = (0..1000).filter(|_| run_round()).count();
+    eprintln!("successes: {}", successes);
+    assert!(successes > 950);
+}
+
+fn run_round() -> bool {
+    let matches = some_test_data();
+    SevenPoint::new()
+        .estimate(matches.iter().take(7).copied())
+        .into_iter()
+        .any(|fundamental| {
+            matches
+                .iter()
+                .all(|m| fundamental.residual(m) < RESIDUAL_THRESHOLD)
+        })
+}
+
+/// Gets matches in pixel coordinates between two cameras with different intrinsics.
+fn some_test_data() -> Vec<KeyPointMatch> {
+    let relative_pose = CameraToCamera(IsometryMatrix3::from_parts(
+        Vector3::new_random().into(),
+        Rotation3::new(Vector3::new_random() * std::f64::consts::PI * 2.0 * ROT_MAGNITUDE),
+    ));
+    let intrinsics_a = CameraIntrinsics::identity()
+        .focals(Vector2::new(800.0, 780.0))
+        .principal_point(Point2::new(320.0, 240.0));
+    let intrinsics_b = CameraIntrinsics::identity()
+        .focal(1200.0)
+        .principal_point(Point2::new(640.0, 360.0));
+    (0..SAMPLE_POINTS)
+        .filter_map(|_| {
+            let mut a = Point3::from(Vector3::new_random() * POINT_BOX_SIZE);
+            a.x -= 0.5 * POINT_BOX_SIZE;
+            a.y -= 0.5 * POINT_BOX_SIZE;
+            a.z += POINT_DISTANCE;
+            let a = CameraPoint::from_point(a);
+            let b = relative_pose.transform(a);
+            Some(KeyPointMatch(
+                intrinsics_a.uncalibrate(a.bearing())?,
+                intrinsics_b.uncalibrate(b.bearing())?,
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,208 @@
+#![no_std]
+
+use cv_core::{
+    nalgebra::{Matrix3, Rotation3, SMatrix, SVector, Vector3},
+    sample_consensus::Estimator,
+    CameraRig, CameraToCamera, Pose, RigFeatureMatch, RigMotion,
+};
+use cv_pinhole::EssentialMatrix;
+use float_ord::FloatOrd;
+use num_traits::Float;
+
+/// A ray from a camera in a rig as a Plücker line in rig coordinates.
+struct Line {
+    direction: Vector3<f64>,
+    moment: Vector3<f64>,
+}
+
+impl Line {
+    fn new<const N: usize>(rig: &CameraRig<N>, camera: usize, bearing: Vector3<f64>) -> Self {
+        let direction = rig.extrinsics[camera].rotation.inverse() * bearing;
+        let moment = rig.camera_center(camera).cross(&direction);
+        Self { direction, moment }
+    }
+}
+
+/// Performs the linear 17-point algorithm for generalized cameras by Robert Pless.
+///
+/// This estimates the [`RigMotion`] of a [`CameraRig`] with known extrinsics from matches which may be observed by
+/// different cameras in the rig, such as a surround-view setup where features move from one camera to the next.
+/// Every observation is treated as a ray (Plücker line) in the coordinate system of the rig, and the rays of a match
+/// before and after the motion must intersect. This generalized epipolar constraint is linear in the essential matrix
+/// and the rotation, so it can be solved from 17 or more matches.
+///
+/// Since the cameras don't share an optical center, the translation has a metric scale. The common case where
+/// every match is observed by the same camera before and after the motion has a spurious solution, which is handled
+/// as described by Li, Hartley, and Kim in "A Linear Approach to Motion Estimation using Generalized Camera Models".
+/// The rig is fully degenerate when all of the cameras share an optical center, in which case a single-camera solver
+/// should be used instead.
+#[derive(Copy, Clone, Debug)]
+pub struct SeventeenPoint<const N: usize> {
+    pub rig: CameraRig<N>,
+    pub epsilon: f64,
+    pub iterations: usize,
+}
+
+impl<const N: usize> SeventeenPoint<N> {
+    pub fn new(rig: CameraRig<N>) -> Self {
+        Self {
+            rig,
+            epsilon: 1e-12,
+            iterations: 1000,
+        }
+    }
+
+    #[must_use]
+    pub fn epsilon(self, epsilon: f64) -> Self {
+        Self { epsilon, ..self }
+    }
+
+    #[must_use]
+    pub fn iterations(self, iterations: usize) -> Self {
+        Self { iterations, ..self }
+    }
+
+    fn lines(&self, RigFeatureMatch(a, b): RigFeatureMatch) -> (Line, Line) {
+        (
+            Line::new(&self.rig, a.camera, a.bearing.into_inner()),
+            Line::new(&self.rig, b.camera, b.bearing.into_inner()),
+        )
+    }
+
+    pub fn from_matches<I>(&self, data: I) -> Option<RigMotion<N>>
+    where
+        I: Iterator<Item = RigFeatureMatch> + Clone,
+    {
+        // Encode `transpose(db) * E * da + transpose(mb) * R * da + transpose(db) * R * ma = 0` with the unknowns
+        // `E` and `R` stacked in column-major order.
+        let mut ata = SMatrix::<f64, 18, 18>::zeros();
+        let mut count = 0;
+        for m in data.clone() {
+            let (a, b) = self.lines(m);
+            let mut row = SVector::<f64, 18>::zeros();
+            for j in 0..3 {
+                for i in 0..3 {
+                    row[3 * j + i] = b.direction[i] * a.direction[j];
+                    row[9 + 3 * j + i] =
+                        b.moment[i] * a.direction[j] + b.direction[i] * a.moment[j];
+                }
+            }
+            ata += row * row.transpose();
+            count += 1;
+        }
+        if count < 17 {
+            return None;
+        }
+        let eigens = ata.try_symmetric_eigen(self.epsilon, self.iterations)?;
+        let mut order = [0usize; 18];
+        for (ix, o) in order.iter_mut().enumerate() {
+            *o = ix;
+        }
+        order.sort_unstable_by_key(|&ix| FloatOrd(eigens.eigenvalues[ix]));
+        let smallest = eigens.eigenvectors.column(order[0]).into_owned();
+        let second = eigens.eigenvectors.column(order[1]).into_owned();
+
+        // When every match is observed by the same camera before and after the motion, `E = 0` and `R = I` is a
+        // spurious solution, so the null space is two dimensional. The combination of the two smallest eigenvectors
+        // which has no component along the spurious solution still has the correct essential matrix block.
+        let spurious = SVector::<f64, 18>::from_iterator(
+            [0.0; 9]
+                .iter()
+                .copied()
+                .chain(Matrix3::<f64>::identity().iter().copied()),
+        );
+        let projected = smallest * spurious.dot(&second) - second * spurious.dot(&smallest);
+
+        let essential_rotations = |solution: &SVector<f64, 18>| {
+            EssentialMatrix(Matrix3::from_iterator(solution.iter().take(9).copied()))
+                .possible_rotations(self.epsilon, self.iterations)
+        };
+        let [a, b] = essential_rotations(&smallest).unwrap_or([Rotation3::identity(); 2]);
+        let [c, d] = essential_rotations(&projected).unwrap_or([Rotation3::identity(); 2]);
+        let candidates = [
+            self.rotation_block(&smallest),
+            Some(a),
+            Some(b),
+            Some(c),
+            Some(d),
+        ];
+
+        // Pick the rotation which best satisfies the constraint after solving for the translation.
+        let (rotation, translation, _) = candidates
+            .iter()
+            .flatten()
+            .filter_map(|&rotation| {
+                let (translation, cost) = self.translation(data.clone(), rotation)?;
+                Some((rotation, translation, cost))
+            })
+            .min_by_key(|&(_, _, cost)| FloatOrd(cost))?;
+        Some(RigMotion {
+            rig: self.rig,
+            motion: CameraToCamera::from_parts(translation, rotation),
+        })
+    }
+
+    /// Recovers the rotation from the rotation block of a solution, which has an arbitrary scale.
+    fn rotation_block(&self, solution: &SVector<f64, 18>) -> Option<Rotation3<f64>> {
+        let rotation = Matrix3::from_iterator(solution.iter().skip(9).copied());
+        let determinant = rotation.determinant();
+        if determinant == 0.0 {
+            return None;
+        }
+        let rotation = rotation / Float::cbrt(determinant);
+        let svd = rotation.try_svd(true, true, self.epsilon, self.iterations)?;
+        let (u, v_t) = (svd.u?, svd.v_t?);
+        let mut correction = Matrix3::identity();
+        correction[(2, 2)] = (u * v_t).determinant().signum();
+        Some(Rotation3::from_matrix_unchecked(u * correction * v_t))
+    }
+
+    /// Solves for the translation given the rotation, returning the translation and the sum of squared residuals.
+    ///
+    /// With the rotation known, the constraint is linear in the translation:
+    /// `dot(t, cross(R * da, db)) = -(transpose(mb) * R * da + transpose(db) * R * ma)`.
+    fn translation<I>(&self, data: I, rotation: Rotation3<f64>) -> Option<(Vector3<f64>, f64)>
+    where
+        I: Iterator<Item = RigFeatureMatch> + Clone,
+    {
+        let constraint = |m| {
+            let (a, b) = self.lines(m);
+            let rotated_direction = rotation * a.direction;
+            let coefficients = rotated_direction.cross(&b.direction);
+            let constant =
+                b.moment.dot(&rotated_direction) + b.direction.dot(&(rotation * a.moment));
+            (coefficients, constant)
+        };
+        let (normal, rhs) = data.clone().map(constraint).fold(
+            (Matrix3::zeros(), Vector3::zeros()),
+            |(normal, rhs): (Matrix3<f64>, Vector3<f64>), (coefficients, constant)| {
+                (
+                    normal + coefficients * coefficients.transpose(),
+                    rhs - coefficients * constant,
+                )
+            },
+        );
+        let translation = normal.cholesky()?.solve(&rhs);
+        let cost = data
+            .map(constraint)
+            .map(|(coefficients, constant)| {
+                let residual = coefficients.dot(&translation) + constant;
+                residual * residual
+            })
+            .sum();
+        Some((translation, cost))
+    }
+}
+
+impl<const N: usize> Estimator<RigFeatureMatch> for SeventeenPoint<N> {
+    type Model = RigMotion<N>;
+    type ModelIter = Option<RigMotion<N>>;
+    const MIN_SAMPLES: usize = 17;
+
+    fn estimate<I>(&self, data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = RigFeatureMatch> + Clone,
+    {
+        self.from_matches(data)
+    }
+}
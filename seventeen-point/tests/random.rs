@@ -0,0 +1,97 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point3, Rotation3, Vector3},
+    sample_consensus::Model,
+    CameraPoint, CameraRig, CameraToCamera, Projective, RigBearing, RigFeatureMatch,
+};
+use seventeen_point::SeventeenPoint;
+
+const SAMPLE_POINTS: usize = 32;
+const EPSILON: f64 = 1e-6;
+
+const ROT_MAGNITUDE: f64 = 0.2;
+const POINT_BOX_SIZE: f64 = 2.0;
+const POINT_DISTANCE: f64 = 3.0;
+
+#[test]
+fn randomized() {
+    let successes = (0..1000).filter(|_| run_round()).count();
+    eprintln!("successes: {}", successes);
+    assert!(successes > 950);
+}
+
+fn run_round() -> bool {
+    let (rig, motion, matches) = some_test_data();
+    let estimate = match SeventeenPoint::new(rig).from_matches(matches.iter().copied()) {
+        Some(estimate) => estimate,
+        None => return false,
+    };
+    let rotation_error = estimate
+        .motion
+        .0
+        .rotation
+        .rotation_to(&motion.0.rotation)
+        .angle();
+    let translation_error =
+        (estimate.motion.0.translation.vector - motion.0.translation.vector).norm();
+    let residuals_ok = matches.iter().all(|m| estimate.residual(m) < EPSILON);
+    rotation_error < EPSILON && translation_error < EPSILON && residuals_ok
+}
+
+/// A rig of four cameras facing in every horizontal direction around a vehicle.
+fn surround_rig() -> CameraRig<4> {
+    let camera = |yaw: f64, offset: Vector3<f64>| {
+        let camera_to_rig = IsometryMatrix3::from_parts(
+            offset.into(),
+            Rotation3::from_axis_angle(&Vector3::y_axis(), yaw),
+        );
+        camera_to_rig.inverse()
+    };
+    let half_pi = std::f64::consts::FRAC_PI_2;
+    CameraRig::new([
+        camera(0.0, Vector3::new(0.0, 0.0, 1.0)),
+        camera(half_pi, Vector3::new(0.5, 0.0, 0.0)),
+        camera(2.0 * half_pi, Vector3::new(0.0, 0.0, -1.0)),
+        camera(3.0 * half_pi, Vector3::new(-0.5, 0.0, 0.0)),
+    ])
+}
+
+/// Gets a rig, a random motion of the rig, and matches of random points between cameras of the rig.
+fn some_test_data() -> (CameraRig<4>, CameraToCamera, Vec<RigFeatureMatch>) {
+    let rig = surround_rig();
+    let motion = CameraToCamera(IsometryMatrix3::from_parts(
+        Vector3::new_random().into(),
+        Rotation3::new(Vector3::new_random() * std::f64::consts::PI * 2.0 * ROT_MAGNITUDE),
+    ));
+    let matches = (0..SAMPLE_POINTS)
+        .map(|ix| {
+            let camera_a = ix % 4;
+            let mut point = Point3::from(Vector3::new_random() * POINT_BOX_SIZE);
+            point.x -= 0.5 * POINT_BOX_SIZE;
+            point.y -= 0.5 * POINT_BOX_SIZE;
+            point.z += POINT_DISTANCE;
+            let rig_point = rig.extrinsics[camera_a].inverse() * point;
+            let moved = motion.0 * rig_point;
+            // Observe the point with whichever camera sees it most directly after the motion.
+            let camera_b = (0..4)
+                .max_by(|&x, &y| {
+                    let z = |camera: usize| {
+                        let p = rig.extrinsics[camera] * moved;
+                        p.z / p.coords.norm()
+                    };
+                    z(x).partial_cmp(&z(y)).unwrap()
+                })
+                .unwrap();
+            RigFeatureMatch(
+                RigBearing {
+                    camera: camera_a,
+                    bearing: CameraPoint::from_point(point).bearing(),
+                },
+                RigBearing {
+                    camera: camera_b,
+                    bearing: CameraPoint::from_point(rig.extrinsics[camera_b] * moved).bearing(),
+                },
+            )
+        })
+        .collect();
+    (rig, motion, matches)
+}
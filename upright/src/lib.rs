@@ -0,0 +1,403 @@
+#![no_std]
+
+use arrayvec::ArrayVec;
+use cv_core::{
+    nalgebra::{Rotation3, SMatrix, SVector, UnitVector3, Vector3},
+    sample_consensus::Estimator,
+    CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Projective, WorldToCamera,
+};
+use num_traits::Float;
+
+/// The number of Newton steps used to polish each root of the determinant polynomial.
+const POLISH_STEPS: usize = 4;
+
+/// Finds the rotation which takes the gravity direction to the `y` axis (down in camera coordinates).
+fn gravity_alignment(gravity: UnitVector3<f64>) -> Rotation3<f64> {
+    Rotation3::rotation_between(&gravity.into_inner(), &Vector3::y()).unwrap_or_else(|| {
+        // The gravity points exactly up, so any half turn perpendicular to it works.
+        Rotation3::from_axis_angle(&Vector3::x_axis(), core::f64::consts::PI)
+    })
+}
+
+/// Rotates `v` about the `y` axis by `yaw`.
+fn yaw_rotate(yaw: f64, v: Vector3<f64>) -> Vector3<f64> {
+    let (s, c) = Float::sin_cos(yaw);
+    Vector3::new(c * v.x + s * v.z, v.y, -s * v.x + c * v.z)
+}
+
+/// Computes the normal of the epipolar plane of an aligned match when camera A is rotated by `yaw`.
+fn normal(yaw: f64, (a, b): (Vector3<f64>, Vector3<f64>)) -> Vector3<f64> {
+    yaw_rotate(yaw, a).cross(&b)
+}
+
+/// A polynomial with its coefficients in increasing order of degree.
+type Polynomial = [f64; 7];
+
+/// Multiplies a polynomial by a polynomial of degree two, assuming the product has a degree of six or less.
+fn multiply(polynomial: Polynomial, factor: [f64; 3]) -> Polynomial {
+    let mut product = [0.0; 7];
+    for (i, &p) in polynomial.iter().enumerate() {
+        for (j, &f) in factor.iter().enumerate() {
+            if i + j < product.len() {
+                product[i + j] += p * f;
+            }
+        }
+    }
+    product
+}
+
+/// Evaluates a polynomial and its derivative at `t`.
+fn evaluate(polynomial: &Polynomial, t: f64) -> (f64, f64) {
+    polynomial
+        .iter()
+        .rev()
+        .fold((0.0, 0.0), |(value, derivative), &c| {
+            (value * t + c, derivative * t + value)
+        })
+}
+
+/// Computes the determinant of the epipolar plane normals of three aligned matches as a polynomial in
+/// `t = tan(yaw / 2)`.
+///
+/// Each normal is `cos(yaw) * u + sin(yaw) * v + w`. Substituting `cos(yaw) = (1 - t²) / (1 + t²)` and
+/// `sin(yaw) = 2t / (1 + t²)` and multiplying by `(1 + t²)³` clears the denominators, leaving a polynomial of degree
+/// six whose leading coefficient is the determinant at a yaw of half a turn.
+fn determinant_polynomial(aligned: &[(Vector3<f64>, Vector3<f64>); 3]) -> Polynomial {
+    let terms = |(a, b): (Vector3<f64>, Vector3<f64>)| {
+        [
+            Vector3::new(a.x, 0.0, a.z).cross(&b),
+            Vector3::new(a.z, 0.0, -a.x).cross(&b),
+            Vector3::new(0.0, a.y, 0.0).cross(&b),
+        ]
+    };
+    let normals = [terms(aligned[0]), terms(aligned[1]), terms(aligned[2])];
+    // The numerators of the cosine, sine, and constant terms.
+    let numerators = [[1.0, 0.0, -1.0], [0.0, 2.0, 0.0], [1.0, 0.0, 1.0]];
+    let mut polynomial = [0.0; 7];
+    // The determinant is linear in each normal, so it is the sum of the determinants of every choice of terms.
+    for (i, u) in normals[0].iter().enumerate() {
+        for (j, v) in normals[1].iter().enumerate() {
+            for (k, w) in normals[2].iter().enumerate() {
+                let mut term = [0.0; 7];
+                term[0] = u.dot(&v.cross(w));
+                for &choice in &[i, j, k] {
+                    term = multiply(term, numerators[choice]);
+                }
+                for (p, t) in polynomial.iter_mut().zip(term.iter()) {
+                    *p += t;
+                }
+            }
+        }
+    }
+    polynomial
+}
+
+/// Estimates the relative pose between two cameras when the gravity direction is known in both cameras,
+/// such as from an IMU.
+///
+/// The gravity direction fixes the roll and pitch of both cameras, so only the yaw and the direction of
+/// translation remain unknown. These three degrees of freedom are solved from three matches, rather than from the
+/// five needed by general relative pose solvers. Drawing fewer matches per sample means far fewer samples are
+/// needed to find an all-inlier sample during consensus.
+///
+/// This takes three matches, not two. Knowing the gravity direction leaves three degrees of freedom and each match
+/// constrains only one of them, so two matches leave a one-parameter family of yaw angles which satisfy the
+/// epipolar constraint. Two-point solvers additionally assume planar motion with the translation perpendicular to
+/// gravity, which doesn't hold for drones or handheld phones.
+///
+/// The gravity directions are the direction "down" expressed in the coordinates of each camera, so a new
+/// estimator is created for every pair of views.
+#[derive(Copy, Clone, Debug)]
+pub struct UprightRelative {
+    /// The rotation that aligns the gravity in camera A with the `y` axis.
+    alignment_a: Rotation3<f64>,
+    /// The rotation that aligns the gravity in camera B with the `y` axis.
+    alignment_b: Rotation3<f64>,
+    pub epsilon: f64,
+    pub iterations: usize,
+}
+
+impl UprightRelative {
+    pub fn new(gravity_a: UnitVector3<f64>, gravity_b: UnitVector3<f64>) -> Self {
+        Self {
+            alignment_a: gravity_alignment(gravity_a),
+            alignment_b: gravity_alignment(gravity_b),
+            epsilon: 1e-12,
+            iterations: 1000,
+        }
+    }
+
+    /// Computes all of the poses consistent with three matches.
+    ///
+    /// The yaw makes the epipolar plane normals of the three matches coplanar. Their determinant is a polynomial of
+    /// degree six in the tangent of half the yaw, and its roots are found as the eigenvalues of its companion matrix,
+    /// so that double roots and roots close to each other aren't missed.
+    ///
+    /// Since the sign of the translation can't be determined from the epipolar constraint,
+    /// each solution is returned with both signs of translation.
+    pub fn from_matches(&self, matches: [FeatureMatch; 3]) -> ArrayVec<CameraToCamera, 16> {
+        // Align both cameras so that gravity points along the `y` axis. The remaining rotation is about the `y` axis.
+        let align = |FeatureMatch(a, b): FeatureMatch| {
+            (
+                self.alignment_a * a.into_inner(),
+                self.alignment_b * b.into_inner(),
+            )
+        };
+        let aligned = [align(matches[0]), align(matches[1]), align(matches[2])];
+        let mut poses = ArrayVec::new();
+        // The translation must be perpendicular to the epipolar plane normal of every match.
+        for yaw in self.yaws(&aligned) {
+            let normals = [
+                normal(yaw, aligned[0]),
+                normal(yaw, aligned[1]),
+                normal(yaw, aligned[2]),
+            ];
+            // Use the pair of normals which are furthest from parallel for the translation.
+            let candidates = [
+                normals[0].cross(&normals[1]),
+                normals[0].cross(&normals[2]),
+                normals[1].cross(&normals[2]),
+            ];
+            let translation = candidates.iter().copied().fold(
+                Vector3::zeros(),
+                |best: Vector3<f64>, candidate| {
+                    if candidate.norm_squared() > best.norm_squared() {
+                        candidate
+                    } else {
+                        best
+                    }
+                },
+            );
+            if translation.norm_squared() == 0.0 {
+                continue;
+            }
+            let translation = translation.normalize();
+            // Undo the gravity alignment.
+            let rotation = self.alignment_b.inverse()
+                * Rotation3::from_axis_angle(&Vector3::y_axis(), yaw)
+                * self.alignment_a;
+            let translation = self.alignment_b.inverse() * translation;
+            for &sign in &[1.0, -1.0] {
+                if poses
+                    .try_push(CameraToCamera::from_parts(translation * sign, rotation))
+                    .is_err()
+                {
+                    return poses;
+                }
+            }
+        }
+        poses
+    }
+
+    /// Finds the yaws at which the epipolar plane normals of three aligned matches lie in a plane.
+    fn yaws(&self, aligned: &[(Vector3<f64>, Vector3<f64>); 3]) -> ArrayVec<f64, 6> {
+        let mut yaws = ArrayVec::new();
+        let pi = core::f64::consts::PI;
+        let determinant = |yaw: f64| {
+            normal(yaw, aligned[0]).dot(&normal(yaw, aligned[1]).cross(&normal(yaw, aligned[2])))
+        };
+        // The leading coefficient of the polynomial is the determinant half a turn from the yaw that `t = 0` maps
+        // to. Offsetting the yaw so that this is as far from zero as possible keeps the companion matrix well defined.
+        let far = [-pi, -0.5 * pi, 0.0, 0.5 * pi]
+            .iter()
+            .copied()
+            .fold(0.0, |best: f64, yaw| {
+                if Float::abs(determinant(yaw)) > Float::abs(determinant(best)) {
+                    yaw
+                } else {
+                    best
+                }
+            });
+        if determinant(far) == 0.0 {
+            return yaws;
+        }
+        let offset = far - pi;
+        let offset_aligned = [
+            (yaw_rotate(offset, aligned[0].0), aligned[0].1),
+            (yaw_rotate(offset, aligned[1].0), aligned[1].1),
+            (yaw_rotate(offset, aligned[2].0), aligned[2].1),
+        ];
+        let polynomial = determinant_polynomial(&offset_aligned);
+        let mut companion = SMatrix::<f64, 6, 6>::zeros();
+        for (i, &coefficient) in polynomial[..6].iter().enumerate() {
+            companion[(i, 5)] = -coefficient / polynomial[6];
+            if i > 0 {
+                companion[(i, i - 1)] = 1.0;
+            }
+        }
+        let eigenvalues = match companion.try_schur(self.epsilon, self.iterations) {
+            Some(schur) => schur.complex_eigenvalues(),
+            None => return yaws,
+        };
+        for t in eigenvalues.iter() {
+            if Float::abs(t.im) > 1e-6 * (1.0 + Float::abs(t.re)) {
+                continue;
+            }
+            // Polish the root with Newton's method as long as it gets closer to zero.
+            let mut t = t.re;
+            let (mut value, mut derivative) = evaluate(&polynomial, t);
+            for _ in 0..POLISH_STEPS {
+                if derivative == 0.0 {
+                    break;
+                }
+                let next = t - value / derivative;
+                let (next_value, next_derivative) = evaluate(&polynomial, next);
+                if Float::abs(next_value) >= Float::abs(value) {
+                    break;
+                }
+                t = next;
+                value = next_value;
+                derivative = next_derivative;
+            }
+            yaws.push(offset + 2.0 * Float::atan(t));
+        }
+        yaws
+    }
+}
+
+impl Estimator<FeatureMatch> for UprightRelative {
+    type Model = CameraToCamera;
+    type ModelIter = ArrayVec<CameraToCamera, 16>;
+    const MIN_SAMPLES: usize = 3;
+
+    fn estimate<I>(&self, mut data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = FeatureMatch> + Clone,
+    {
+        let mut next = || data.next();
+        match (next(), next(), next()) {
+            (Some(a), Some(b), Some(c)) => self.from_matches([a, b, c]),
+            _ => ArrayVec::new(),
+        }
+    }
+}
+
+/// Estimates the absolute pose of a camera from two world point matches when the gravity direction is known
+/// both in the camera and in the world, such as from an IMU.
+///
+/// The gravity direction fixes the roll and pitch of the camera, which leaves only the yaw and the translation
+/// unknown. Each match provides two constraints, so two matches are sufficient, compared to three for general
+/// absolute pose solvers like P3P. This is useful for fast relocalization on devices with an IMU.
+///
+/// The gravity directions are the direction "down" expressed in the coordinates of the camera and the world.
+/// The gravity in the world is typically constant, but the gravity in the camera changes with every frame.
+#[derive(Copy, Clone, Debug)]
+pub struct UprightAbsolute {
+    /// The rotation that aligns the gravity in the camera with the `y` axis.
+    alignment_camera: Rotation3<f64>,
+    /// The rotation that aligns the gravity in the world with the `y` axis.
+    alignment_world: Rotation3<f64>,
+    pub epsilon: f64,
+    pub iterations: usize,
+}
+
+impl UprightAbsolute {
+    pub fn new(gravity_camera: UnitVector3<f64>, gravity_world: UnitVector3<f64>) -> Self {
+        Self {
+            alignment_camera: gravity_alignment(gravity_camera),
+            alignment_world: gravity_alignment(gravity_world),
+            epsilon: 1e-12,
+            iterations: 1000,
+        }
+    }
+
+    /// Computes all of the poses consistent with two matches that place both points in front of the camera.
+    pub fn from_matches(&self, matches: [FeatureWorldMatch; 2]) -> ArrayVec<WorldToCamera, 2> {
+        let mut poses = ArrayVec::new();
+        // Align the camera and world so that gravity points along the `y` axis in both.
+        let mut aligned = [(Vector3::zeros(), Vector3::zeros()); 2];
+        for (aligned, &FeatureWorldMatch(bearing, world)) in aligned.iter_mut().zip(matches.iter())
+        {
+            let world = match world.point() {
+                Some(world) => world,
+                None => return poses,
+            };
+            *aligned = (
+                self.alignment_camera * bearing.into_inner(),
+                self.alignment_world * world.coords,
+            );
+        }
+
+        // The aligned camera point `R_y(θ) * X + t` must be parallel to the bearing `b`. With `R_y(θ) * X`
+        // expressed as `cos(θ) * u + sin(θ) * v + w`, `cross(b, R_y(θ) * X + t) = 0` is linear in
+        // `[t, cos(θ), sin(θ)]`, with an additional constraint that `cos(θ)² + sin(θ)² = 1`.
+        let mut system = SMatrix::<f64, 6, 5>::zeros();
+        let mut rhs = SVector::<f64, 6>::zeros();
+        for (ix, &(bearing, world)) in aligned.iter().enumerate() {
+            let u = Vector3::new(world.x, 0.0, world.z);
+            let v = Vector3::new(world.z, 0.0, -world.x);
+            let w = Vector3::new(0.0, world.y, 0.0);
+            let cross = bearing.cross_matrix();
+            let mut rows = system.fixed_rows_mut::<3>(3 * ix);
+            rows.fixed_columns_mut::<3>(0).copy_from(&cross);
+            rows.column_mut(3).copy_from(&(cross * u));
+            rows.column_mut(4).copy_from(&(cross * v));
+            rhs.fixed_rows_mut::<3>(3 * ix).copy_from(&-(cross * w));
+        }
+
+        // Each cross product only has rank 2, so the solutions form a line `particular + λ * null`.
+        let svd = match system.try_svd(true, true, self.epsilon, self.iterations) {
+            Some(svd) => svd,
+            None => return poses,
+        };
+        let (u, v_t) = match (svd.u, svd.v_t) {
+            (Some(u), Some(v_t)) => (u, v_t),
+            _ => return poses,
+        };
+        let null_ix = svd.singular_values.imin();
+        let mut particular = SVector::<f64, 5>::zeros();
+        for (ix, &singular) in svd.singular_values.iter().enumerate() {
+            if ix != null_ix && singular > 0.0 {
+                particular += v_t.row(ix).transpose() * (u.column(ix).dot(&rhs) / singular);
+            }
+        }
+        let null = v_t.row(null_ix).transpose();
+
+        // Solve `(c₀ + λ * c₁)² + (s₀ + λ * s₁)² = 1` for λ.
+        let a = null[3] * null[3] + null[4] * null[4];
+        let b = 2.0 * (particular[3] * null[3] + particular[4] * null[4]);
+        let c = particular[3] * particular[3] + particular[4] * particular[4] - 1.0;
+        let discriminant = b * b - 4.0 * a * c;
+        if a == 0.0 || discriminant < 0.0 {
+            return poses;
+        }
+        let root = discriminant.sqrt();
+        for &lambda in &[(-b + root) / (2.0 * a), (-b - root) / (2.0 * a)] {
+            let solution = particular + null * lambda;
+            let yaw = Float::atan2(solution[4], solution[3]);
+            let translation = Vector3::new(solution[0], solution[1], solution[2]);
+            let in_front = aligned.iter().all(|&(bearing, world)| {
+                (yaw_rotate(yaw, world) + translation).dot(&bearing) > 0.0
+            });
+            if in_front {
+                // Undo the gravity alignment.
+                let camera_inverse = self.alignment_camera.inverse();
+                let rotation = camera_inverse
+                    * Rotation3::from_axis_angle(&Vector3::y_axis(), yaw)
+                    * self.alignment_world;
+                poses.push(WorldToCamera::from_parts(
+                    camera_inverse * translation,
+                    rotation,
+                ));
+            }
+        }
+        poses
+    }
+}
+
+impl Estimator<FeatureWorldMatch> for UprightAbsolute {
+    type Model = WorldToCamera;
+    type ModelIter = ArrayVec<WorldToCamera, 2>;
+    const MIN_SAMPLES: usize = 2;
+
+    fn estimate<I>(&self, mut data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = FeatureWorldMatch> + Clone,
+    {
+        let mut next = || data.next();
+        match (next(), next()) {
+            (Some(a), Some(b)) => self.from_matches([a, b]),
+            _ => ArrayVec::new(),
+        }
+    }
+}
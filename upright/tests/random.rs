@@ -0,0 +1,98 @@
+use cv_core::{
+    nalgebra::{IsometryMatrix3, Point3, Rotation3, UnitVector3, Vector3},
+    sample_consensus::Estimator,
+    CameraPoint, CameraToCamera, FeatureMatch, FeatureWorldMatch, Pose, Projective, WorldPoint,
+    WorldToCamera,
+};
+use upright::{UprightAbsolute, UprightRelative};
+
+const EPSILON: f64 = 1e-6;
+
+const ROT_MAGNITUDE: f64 = 0.2;
+const POINT_BOX_SIZE: f64 = 2.0;
+const POINT_DISTANCE: f64 = 3.0;
+
+#[test]
+fn randomized() {
+    let successes = (0..1000).filter(|_| run_round()).count();
+    eprintln!("successes: {}", successes);
+    assert!(successes > 950);
+}
+
+#[test]
+fn absolute_randomized() {
+    let successes = (0..1000).filter(|_| run_absolute_round()).count();
+    eprintln!("successes: {}", successes);
+    assert!(successes > 950);
+}
+
+fn run_absolute_round() -> bool {
+    let pose = WorldToCamera(IsometryMatrix3::from_parts(
+        Vector3::new_random().into(),
+        Rotation3::new(Vector3::new_random() * std::f64::consts::PI * 2.0 * ROT_MAGNITUDE),
+    ));
+    let gravity_world = UnitVector3::new_normalize(-Vector3::z());
+    let gravity_camera = pose.0.rotation * gravity_world;
+    let sample = || {
+        let camera = CameraPoint::from_point(random_camera_point());
+        let world: WorldPoint = pose.inverse().transform(camera);
+        FeatureWorldMatch(camera.bearing(), world)
+    };
+    let estimator = UprightAbsolute::new(gravity_camera, gravity_world);
+    estimator
+        .estimate([sample(), sample()].iter().copied())
+        .into_iter()
+        .any(|estimate| {
+            let rotation_error = estimate.0.rotation.rotation_to(&pose.0.rotation).angle();
+            let translation_error =
+                (estimate.0.translation.vector - pose.0.translation.vector).norm();
+            rotation_error < EPSILON && translation_error < EPSILON
+        })
+}
+
+fn random_camera_point() -> Point3<f64> {
+    let mut a = Point3::from(Vector3::new_random() * POINT_BOX_SIZE);
+    a.x -= 0.5 * POINT_BOX_SIZE;
+    a.y -= 0.5 * POINT_BOX_SIZE;
+    a.z += POINT_DISTANCE;
+    a
+}
+
+fn run_round() -> bool {
+    let (pose, gravity_a, gravity_b, matches) = some_test_data();
+    let estimator = UprightRelative::new(gravity_a, gravity_b);
+    estimator
+        .estimate(matches.iter().copied())
+        .into_iter()
+        .any(|estimate| {
+            let rotation_error = estimate.0.rotation.rotation_to(&pose.0.rotation).angle();
+            let translation_error = 1.0
+                - estimate
+                    .0
+                    .translation
+                    .vector
+                    .dot(&pose.0.translation.vector.normalize());
+            rotation_error < EPSILON && translation_error < EPSILON
+        })
+}
+
+/// Gets a random relative pose, the gravity in both cameras, and three matches.
+fn some_test_data() -> (
+    CameraToCamera,
+    UnitVector3<f64>,
+    UnitVector3<f64>,
+    [FeatureMatch; 3],
+) {
+    let pose = CameraToCamera(IsometryMatrix3::from_parts(
+        Vector3::new_random().into(),
+        Rotation3::new(Vector3::new_random() * std::f64::consts::PI * 2.0 * ROT_MAGNITUDE),
+    ));
+    let gravity_a = UnitVector3::new_normalize(Vector3::y() + Vector3::new_random() * 0.5);
+    let gravity_b = pose.0.rotation * gravity_a;
+    let sample = || {
+        let a = CameraPoint::from_point(random_camera_point());
+        let b = pose.transform(a);
+        FeatureMatch(a.bearing(), b.bearing())
+    };
+    (pose, gravity_a, gravity_b, [sample(), sample(), sample()])
+}
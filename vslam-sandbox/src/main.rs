@@ -4,7 +4,7 @@ use cv::{
     estimate::{EightPoint, LambdaTwist},
     geom::triangulation::LinearEigenTriangulator,
     nalgebra::{Point2, Vector2},
-    sfm::{VSlam, VSlamSettings},
+    sfm::{QualityReport, VSlam, VSlamSettings},
 };
 use log::*;
 use rand::SeedableRng;
@@ -53,6 +53,15 @@ struct Opt {
     /// Output directory for reconstruction PLY files
     #[structopt(short, long)]
     output: Option<PathBuf>,
+    /// A JSON file where the quality report of every reconstruction is written when the program finishes
+    #[structopt(long)]
+    report: Option<PathBuf>,
+    /// The reprojection error in pixels below which an observation is an inlier in the quality report
+    #[structopt(long, default_value = "2.0")]
+    report_inlier_threshold: f64,
+    /// The seed of the random number generators, which makes runs with the same seed reproducible
+    #[structopt(long, default_value = "0")]
+    seed: u64,
     /// List of image files
     ///
     /// Default vales are for "The Zurich Urban Micro Aerial Vehicle Dataset"
@@ -101,18 +110,18 @@ fn main() {
         settings,
         Arrsac::new(
             settings.single_view_consensus_threshold,
-            Xoshiro256PlusPlus::seed_from_u64(0),
+            Xoshiro256PlusPlus::seed_from_u64(opt.seed),
         )
         .max_candidate_hypotheses(1024),
         Arrsac::new(
             settings.two_view_consensus_threshold,
-            Xoshiro256PlusPlus::seed_from_u64(0),
+            Xoshiro256PlusPlus::seed_from_u64(opt.seed),
         )
         .max_candidate_hypotheses(8192),
         LambdaTwist::new(),
         EightPoint::new(),
         LinearEigenTriangulator::new(),
-        Xoshiro256PlusPlus::seed_from_u64(0),
+        Xoshiro256PlusPlus::seed_from_u64(opt.seed),
     );
 
     // Add the feed.
@@ -157,9 +166,25 @@ fn main() {
         }
     }
 
+    if let Some(path) = &opt.report {
+        let reports: Vec<QualityReport> = vslam
+            .data
+            .reconstructions()
+            .map(|reconstruction| vslam.quality_report(reconstruction, opt.report_inlier_threshold))
+            .collect();
+        match std::fs::File::create(path) {
+            Ok(file) => {
+                if let Err(e) = serde_json::to_writer_pretty(file, &reports) {
+                    error!("unable to write quality report: {}", e);
+                }
+            }
+            Err(e) => error!("unable to create quality report file: {}", e),
+        }
+    }
+
     if !opt.images.is_empty() {
         info!("saving the reconstruction data");
-        if let Ok(file) = std::fs::File::create(opt.data) {
+        if let Ok(file) = std::fs::File::create(&opt.data) {
             if let Err(e) = bincode::serialize_into(file, &vslam.data) {
                 error!("unable to save reconstruction data: {}", e);
             }
@@ -0,0 +1,185 @@
+//! # `wasm-demo`
+//!
+//! Feature extraction and two-view pose estimation compiled to WebAssembly.
+//!
+//! The images come from the page as RGBA pixels, such as the data of a canvas, so nothing touches the file system.
+//! With the `threads` feature, `initThreadPool` must be awaited from JavaScript before extracting features.
+
+use akaze::{Akaze, KeyPoint};
+use arrsac::Arrsac;
+use bitarray::{BitArray, Hamming};
+use cv_core::{
+    nalgebra::{Point2, Vector2},
+    sample_consensus::Consensus,
+    CameraModel, FeatureMatch,
+};
+use cv_pinhole::CameraIntrinsics;
+use image::{DynamicImage, ImageBuffer};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use space::Knn;
+use wasm_bindgen::prelude::*;
+
+#[cfg(all(target_arch = "wasm32", feature = "threads"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
+/// The ratio between the distances to the best and second best match below which a match is kept.
+const LOWES_RATIO: f32 = 0.7;
+
+/// The residual of the essential matrix below which a match is an inlier.
+const INLIER_THRESHOLD: f64 = 1e-3;
+
+/// The features extracted from one image.
+#[wasm_bindgen]
+pub struct Features {
+    keypoints: Vec<KeyPoint>,
+    descriptors: Vec<BitArray<64>>,
+}
+
+#[wasm_bindgen]
+impl Features {
+    /// The number of features.
+    pub fn len(&self) -> usize {
+        self.keypoints.len()
+    }
+
+    /// Whether no features were found.
+    pub fn is_empty(&self) -> bool {
+        self.keypoints.is_empty()
+    }
+
+    /// The positions of the features in pixels as interleaved `x` and `y` coordinates.
+    pub fn positions(&self) -> Vec<f32> {
+        self.keypoints
+            .iter()
+            .flat_map(|keypoint| vec![keypoint.point.0, keypoint.point.1])
+            .collect()
+    }
+
+    /// The sizes of the features in pixels.
+    pub fn sizes(&self) -> Vec<f32> {
+        self.keypoints
+            .iter()
+            .map(|keypoint| keypoint.size)
+            .collect()
+    }
+}
+
+/// Extracts AKAZE features from an RGBA image with the given detector threshold, such as `0.001`.
+///
+/// Returns `undefined` if the buffer doesn't hold `width * height` RGBA pixels.
+#[wasm_bindgen]
+pub fn extract(rgba: &[u8], width: u32, height: u32, threshold: f64) -> Option<Features> {
+    let image = ImageBuffer::from_raw(width, height, rgba.to_vec())?;
+    let (keypoints, descriptors) = Akaze::new(threshold).extract(&DynamicImage::ImageRgba8(image));
+    Some(Features {
+        keypoints,
+        descriptors,
+    })
+}
+
+/// The relative pose of the second camera, whose translation is only known up to scale.
+#[wasm_bindgen]
+pub struct TwoViewPose {
+    rotation: [f64; 9],
+    translation: [f64; 3],
+    matches: Vec<u32>,
+    inliers: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl TwoViewPose {
+    /// The rotation matrix row by row.
+    pub fn rotation(&self) -> Vec<f64> {
+        self.rotation.to_vec()
+    }
+
+    /// The unit translation.
+    pub fn translation(&self) -> Vec<f64> {
+        self.translation.to_vec()
+    }
+
+    /// The matches as interleaved indices of the features in the first and second image.
+    pub fn matches(&self) -> Vec<u32> {
+        self.matches.clone()
+    }
+
+    /// The indices of the matches which are inliers of the pose.
+    pub fn inliers(&self) -> Vec<u32> {
+        self.inliers.clone()
+    }
+}
+
+/// Matches the features of two images taken by the same pinhole camera and estimates their relative pose.
+///
+/// Returns `undefined` if there aren't enough matches to estimate the pose.
+#[wasm_bindgen]
+pub fn estimate_pose(
+    first: &Features,
+    second: &Features,
+    focal: f64,
+    principal_x: f64,
+    principal_y: f64,
+) -> Option<TwoViewPose> {
+    let intrinsics = CameraIntrinsics {
+        focals: Vector2::new(focal, focal),
+        principal_point: Point2::new(principal_x, principal_y),
+        skew: 0.0,
+    };
+    let pairs = match_descriptors(&first.descriptors, &second.descriptors);
+    let matches: Vec<FeatureMatch> = pairs
+        .iter()
+        .map(|&(a, b)| {
+            FeatureMatch(
+                intrinsics.calibrate(first.keypoints[a]),
+                intrinsics.calibrate(second.keypoints[b]),
+            )
+        })
+        .collect();
+    let mut arrsac = Arrsac::new(INLIER_THRESHOLD, Pcg64::from_seed([1; 32]));
+    let (pose, inliers) =
+        arrsac.model_inliers(&eight_point::EightPoint::new(), matches.iter().copied())?;
+    let rotation = pose.0.rotation.matrix();
+    let translation = pose.0.translation.vector.normalize();
+    Some(TwoViewPose {
+        rotation: [
+            rotation.m11,
+            rotation.m12,
+            rotation.m13,
+            rotation.m21,
+            rotation.m22,
+            rotation.m23,
+            rotation.m31,
+            rotation.m32,
+            rotation.m33,
+        ],
+        translation: [translation.x, translation.y, translation.z],
+        matches: pairs
+            .iter()
+            .flat_map(|&(a, b)| vec![a as u32, b as u32])
+            .collect(),
+        inliers: inliers.into_iter().map(|ix| ix as u32).collect(),
+    })
+}
+
+/// Matches every descriptor of the first image to its nearest neighbor in the second if it passes Lowe's ratio
+/// test.
+fn match_descriptors(first: &[BitArray<64>], second: &[BitArray<64>]) -> Vec<(usize, usize)> {
+    first
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, descriptor)| {
+            let neighbors = space::LinearKnn {
+                metric: Hamming,
+                iter: second.iter(),
+            }
+            .knn(descriptor, 2);
+            match neighbors.as_slice() {
+                [best, next] if (best.distance as f32) < next.distance as f32 * LOWES_RATIO => {
+                    Some((ix, best.index))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,83 @@
+import init, * as cv from "../pkg/wasm_demo.js";
+
+const output = document.getElementById("output");
+const run = document.getElementById("run");
+
+// Draws an image file onto a canvas and returns its RGBA pixels.
+async function loadImage(input, canvas) {
+    const bitmap = await createImageBitmap(input.files[0]);
+    canvas.width = bitmap.width;
+    canvas.height = bitmap.height;
+    const context = canvas.getContext("2d");
+    context.drawImage(bitmap, 0, 0);
+    return context.getImageData(0, 0, bitmap.width, bitmap.height);
+}
+
+function drawFeatures(canvas, features, inlierFeatures) {
+    const context = canvas.getContext("2d");
+    const positions = features.positions();
+    for (let ix = 0; ix < features.len(); ix++) {
+        context.strokeStyle = inlierFeatures.has(ix) ? "lime" : "red";
+        context.beginPath();
+        context.arc(positions[2 * ix], positions[2 * ix + 1], 3, 0, 2 * Math.PI);
+        context.stroke();
+    }
+}
+
+async function estimate() {
+    const threshold = Number(document.getElementById("threshold").value);
+    const focal = Number(document.getElementById("focal").value);
+    const firstCanvas = document.getElementById("first-canvas");
+    const secondCanvas = document.getElementById("second-canvas");
+    const first = await loadImage(document.getElementById("first"), firstCanvas);
+    const second = await loadImage(document.getElementById("second"), secondCanvas);
+
+    const start = performance.now();
+    const pixels = (image) => new Uint8Array(image.data.buffer);
+    const firstFeatures = cv.extract(pixels(first), first.width, first.height, threshold);
+    const secondFeatures = cv.extract(pixels(second), second.width, second.height, threshold);
+    const pose = cv.estimate_pose(
+        firstFeatures,
+        secondFeatures,
+        focal,
+        first.width / 2 - 0.5,
+        first.height / 2 - 0.5,
+    );
+    const elapsed = performance.now() - start;
+
+    let text = `${firstFeatures.len()} and ${secondFeatures.len()} features in ${elapsed.toFixed(0)} ms\n`;
+    const firstInliers = new Set();
+    const secondInliers = new Set();
+    if (pose === undefined) {
+        text += "not enough matches to estimate the pose";
+    } else {
+        const matches = pose.matches();
+        for (const ix of pose.inliers()) {
+            firstInliers.add(matches[2 * ix]);
+            secondInliers.add(matches[2 * ix + 1]);
+        }
+        const format = (values) => Array.from(values, (value) => value.toFixed(4)).join(" ");
+        text += `${pose.inliers().length} inliers of ${matches.length / 2} matches\n`;
+        text += `rotation: ${format(pose.rotation())}\n`;
+        text += `translation: ${format(pose.translation())}`;
+    }
+    output.textContent = text;
+    drawFeatures(firstCanvas, firstFeatures, firstInliers);
+    drawFeatures(secondCanvas, secondFeatures, secondInliers);
+}
+
+async function main() {
+    await init();
+    // The thread pool only exists when the package was built with the `threads` feature.
+    if (cv.initThreadPool !== undefined) {
+        await cv.initThreadPool(navigator.hardwareConcurrency);
+    }
+    run.disabled = false;
+    run.addEventListener("click", () => {
+        estimate().catch((error) => {
+            output.textContent = error;
+        });
+    });
+}
+
+main();